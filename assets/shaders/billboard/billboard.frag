@@ -0,0 +1,18 @@
+#version 450
+
+layout (location = 0) out vec4 out_color;
+
+in VS_OUT {
+    vec2 uv;
+    flat vec4 color;
+    flat vec4 background;
+    flat float fill;
+} vs_in;
+
+uniform sampler2D billboard_texture;
+
+void main() {
+    vec4 color = vs_in.uv.x <= vs_in.fill ? vs_in.color : vs_in.background;
+
+    out_color = texture(billboard_texture, vs_in.uv) * color;
+}
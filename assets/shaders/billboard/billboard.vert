@@ -0,0 +1,38 @@
+#version 450
+
+layout (location = 0) in vec2 corner;
+
+// Per instance
+in vec3 billboard_position;
+in vec2 billboard_size;
+in vec4 billboard_color;
+in vec4 billboard_background;
+in float billboard_fill;
+in uint billboard_on_top;
+
+out VS_OUT {
+    vec2 uv;
+    flat vec4 color;
+    flat vec4 background;
+    flat float fill;
+} vs_out;
+
+uniform mat4 vp;
+// World space axes of the screen, so every billboard faces the camera
+uniform vec3 camera_right;
+uniform vec3 camera_up;
+
+void main() {
+    vec3 offset = camera_right * corner.x * billboard_size.x + camera_up * corner.y * billboard_size.y;
+
+    vs_out.uv = corner + 0.5;
+    vs_out.color = billboard_color;
+    vs_out.background = billboard_background;
+    vs_out.fill = billboard_fill;
+
+    gl_Position = vp * vec4(billboard_position + offset, 1.0);
+    // Just past the near plane, so it passes the depth test against anything drawn
+    if (billboard_on_top != 0u) {
+        gl_Position.z = -gl_Position.w * 0.9999;
+    }
+}
@@ -0,0 +1,33 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 tex_coord;
+layout(location = 3) in vec3 world_position;
+
+layout(location = 0) out vec4 out_color;
+
+// 0 for albedo, 1 for normals, 2 for depth and 3 for overdraw, as in DebugView::shader_view
+uniform int view;
+uniform float near;
+uniform float far;
+
+uniform vec4 albedo_factor;
+uniform sampler2D albedo_texture;
+
+void main() {
+    if (view == 0) {
+        out_color = vec4(texture(albedo_texture, tex_coord).rgb * albedo_factor.rgb, 1.0);
+    } else if (view == 1) {
+        // world space, mapped from -1..1 to colors
+        out_color = vec4(normalize(normal) * 0.5 + 0.5, 1.0);
+    } else if (view == 2) {
+        // linear distance, white up close fading to black at the far plane
+        float ndc = gl_FragCoord.z * 2.0 - 1.0;
+        float linear_depth = 2.0 * near * far / (far + near - ndc * (far - near));
+        out_color = vec4(vec3(1.0 - linear_depth / far), 1.0);
+    } else {
+        // added up for every fragment, so red turns orange then white the more is drawn over it
+        out_color = vec4(0.1, 0.04, 0.015, 1.0);
+    }
+}
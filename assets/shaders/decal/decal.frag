@@ -0,0 +1,32 @@
+#version 450
+
+layout (location = 0) out vec4 out_color;
+
+in VS_OUT {
+    vec2 corner;
+    float opacity;
+    flat uint shape;
+} vs_in;
+
+const uint BULLET_HOLE = 0u;
+const uint SCORCH = 1u;
+
+void main() {
+    // 0 at the centre, 1 at the edge of the quad's inscribed circle
+    float radius = length(vs_in.corner) * 2.0;
+    vec4 color;
+
+    if (vs_in.shape == BULLET_HOLE) {
+        // A black hole inside a ring of scuffed, lighter surface
+        float hole = 1.0 - smoothstep(0.3, 0.4, radius);
+        float ring = (1.0 - smoothstep(0.6, 1.0, radius)) * 0.5;
+        color = mix(vec4(0.35, 0.33, 0.3, ring), vec4(0.02, 0.02, 0.02, 1.0), hole);
+    } else {
+        // Soot that's darkest in the middle and ragged towards the edge
+        float angle = atan(vs_in.corner.y, vs_in.corner.x);
+        float edge = 0.8 + 0.1 * sin(angle * 7.0) + 0.05 * sin(angle * 13.0);
+        color = vec4(0.03, 0.025, 0.02, 0.9 * (1.0 - smoothstep(0.1, edge, radius)));
+    }
+
+    out_color = vec4(color.rgb, color.a * vs_in.opacity);
+}
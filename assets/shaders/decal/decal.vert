@@ -0,0 +1,24 @@
+#version 450
+
+layout (location = 0) in vec2 corner;
+
+// Per instance
+in mat4 decal_matrix;
+in float decal_opacity;
+in uint decal_shape;
+
+out VS_OUT {
+    vec2 corner;
+    float opacity;
+    flat uint shape;
+} vs_out;
+
+uniform mat4 vp;
+
+void main() {
+    vs_out.corner = corner;
+    vs_out.opacity = decal_opacity;
+    vs_out.shape = decal_shape;
+
+    gl_Position = vp * decal_matrix * vec4(corner, 0.0, 1.0);
+}
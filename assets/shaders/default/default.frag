@@ -1,53 +1,49 @@
 #version 450
 
+#include "scene_lighting.glsl"
+
 layout(location = 0) in vec3 position;
-//layout(location = 1) in vec3 normal;
+layout(location = 1) in vec3 normal;
 //layout(location = 2) in vec2 tex_coord;
-//layout(location = 3) in vec3 camera_position;
+layout(location = 3) in vec3 world_position;
 
 layout(location = 0) out vec4 out_color;
 
-struct Light {
-    vec4 position;
-    vec4 color;
-    float intensity;
-};
+// Points towards the sun
+uniform vec3 sun_direction;
+uniform vec3 sun_color;
+uniform vec3 ambient_color;
+uniform vec3 camera_position;
+
+uniform vec3 fog_color;
+uniform float fog_density;
+// Shades everything white, to see the lighting on its own
+uniform bool lighting_only;
 
-//layout(binding = 2) uniform LightsUniform {
-//    Light lights[10];
-//} lights_uniform;
-//
-//layout(binding = 3) uniform sampler2D texture_sampler;
+// Only the opacity is used, as models are colored by their position
+uniform vec4 albedo_factor;
 
 void main() {
-//    Light light = lights_uniform.lights[0];
-//
-//    vec3 light_color = light.color.rgb;
-//
-//    // ambient
-//    float ambient_strength = 0.1;
-//    vec3 ambient = ambient_strength * light_color;
-//
-//    vec4 color = texture(texture_sampler, tex_coord);
-//
-//    // diffuse
-//    // how close is the angle of incidence to the normal?
-//    vec3 light_direction = normalize(light.position.xyz - position);
-//
-//    float incidence_angle = max(dot(normal, light_direction), 0.0);
-//    vec3 diffuse = incidence_angle * light_color;
-//
-//    // specular
-//    // how close is the direction of reflected light to the direction from the fragment to the eye?
-//    float specular_strength = 0.5;
-//    vec3 view_direction = normalize(camera_position - position);
-//    vec3 reflection_direction = reflect(-light_direction, normal);
-//
-//    float shininess = 64;
-//    float specularity = pow(max(dot(view_direction, reflection_direction), 0.0), shininess);
-//    vec3 specular = specular_strength * specularity * light_color;
-//
-//    out_color = vec4(ambient + diffuse + specular, 1.0) * color;
-
-    out_color = vec4(position, 1.0);
+    // wet surfaces are darker and glossier
+    vec3 albedo = lighting_only ? vec3(1.0) : position * mix(1.0, 0.6, wetness);
+
+    vec3 surface_normal = normalize(normal);
+    vec3 light_direction = normalize(sun_direction);
+    vec3 view_direction = normalize(camera_position - world_position);
+
+    float incidence_angle = max(dot(surface_normal, light_direction), 0.0);
+    vec3 lighting = ambient_color + incidence_angle * sun_color;
+
+    vec3 halfway_direction = normalize(light_direction + view_direction);
+    float shininess = mix(8.0, 128.0, wetness);
+    float specularity = pow(max(dot(surface_normal, halfway_direction), 0.0), shininess);
+    vec3 specular = mix(0.05, 0.8, wetness) * specularity * sun_color;
+
+    vec3 color = lighting * albedo + specular
+        + scene_lighting(albedo, world_position, surface_normal, view_direction);
+
+    float view_distance = length(camera_position - world_position);
+    float fog = 1.0 - exp(-pow(fog_density * view_distance, 2.0));
+
+    out_color = vec4(mix(color, fog_color, fog), albedo_factor.a);
 }
\ No newline at end of file
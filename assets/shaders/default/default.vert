@@ -4,12 +4,16 @@ layout (location = 0) in vec3 position;
 layout (location = 1) in vec3 normal;
 layout (location = 2) in vec2 tex_coord;
 layout (location = 3) in mat4 transform;
-layout (location = 4) in mat4 transform_normal;
+layout (location = 7) in mat4 transform_normal;
+layout (location = 11) in vec4 tangent;
+layout (location = 12) in vec4 joints;
+layout (location = 13) in vec4 weights;
 
 layout (location = 0) out vec3 out_position;
-//layout(location = 1) out vec3 out_normal;
-//layout(location = 2) out vec2 out_tex_coord;
-//layout(location = 3) out vec3 out_camera_position;
+layout (location = 1) out vec3 out_normal;
+layout (location = 2) out vec2 out_tex_coord;
+layout (location = 3) out vec3 out_world_position;
+layout (location = 4) out vec4 out_tangent;
 
 // TODO if anything bad happens listen to this guy https://stackoverflow.com/questions/38172696/should-i-ever-use-a-vec3-inside-of-a-uniform-buffer-or-shader-storage-buffer-o
 
@@ -17,12 +21,31 @@ layout (location = 0) out vec3 out_position;
 uniform mat4 vp;
 uniform vec3 camera_position;
 
+#define MAX_BONES 128
+
+// per skinned instance
+layout(std140) uniform Bones {
+    mat4 bones[MAX_BONES];
+};
+
 void main() {
+    // vertices that aren't skinned have no weights
+    mat4 skin = mat4(1.0);
+    if (dot(weights, vec4(1.0)) > 0.0) {
+        skin = weights.x * bones[int(joints.x)]
+            + weights.y * bones[int(joints.y)]
+            + weights.z * bones[int(joints.z)]
+            + weights.w * bones[int(joints.w)];
+    }
+
     out_position = position;
-    //    // Fix non-uniform scalings
-    //    out_normal = vec3(model_uniform.normal * vec4(normalize(normal), 1.0));
-    //    out_tex_coord = tex_coord;
-    //    out_camera_position = camera_uniform.camera_position;
-    //
-    gl_Position = vp * transform * vec4(position, 1.0);
-}
\ No newline at end of file
+    // Fix non-uniform scalings
+    out_normal = normalize(mat3(transform_normal) * mat3(skin) * normal);
+    out_tex_coord = tex_coord;
+    out_tangent = vec4(mat3(transform) * mat3(skin) * tangent.xyz, tangent.w);
+
+    vec4 world_position = transform * skin * vec4(position, 1.0);
+    out_world_position = world_position.xyz;
+
+    gl_Position = vp * world_position;
+}
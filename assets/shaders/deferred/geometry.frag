@@ -0,0 +1,19 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+//layout(location = 2) in vec2 tex_coord;
+layout(location = 3) in vec3 world_position;
+
+layout(location = 0) out vec4 out_albedo;
+layout(location = 1) out vec4 out_normal;
+layout(location = 2) out vec4 out_position;
+
+uniform float wetness;
+
+void main() {
+    // wet surfaces are darker
+    out_albedo = vec4(position * mix(1.0, 0.6, wetness), 1.0);
+    out_normal = vec4(normalize(normal), 0.0);
+    out_position = vec4(world_position, 1.0);
+}
@@ -0,0 +1,58 @@
+#version 450
+
+#include "scene_lighting.glsl"
+
+layout(location = 0) out vec4 out_color;
+
+uniform sampler2D albedo_texture;
+uniform sampler2D normal_texture;
+uniform sampler2D position_texture;
+uniform sampler2D depth_texture;
+uniform vec2 screen_size;
+
+// Points towards the sun
+uniform vec3 sun_direction;
+uniform vec3 sun_color;
+uniform vec3 ambient_color;
+uniform vec3 sky_color;
+uniform vec3 camera_position;
+
+uniform vec3 fog_color;
+uniform float fog_density;
+
+void main() {
+    vec2 tex_coord = gl_FragCoord.xy / screen_size;
+
+    // keep the scene's depth so lines and portals drawn afterwards are hidden behind it
+    gl_FragDepth = texture(depth_texture, tex_coord).r;
+
+    vec4 albedo = texture(albedo_texture, tex_coord);
+
+    // nothing was drawn here
+    if (albedo.a == 0.0) {
+        out_color = vec4(sky_color, 1.0);
+        return;
+    }
+
+    vec3 surface_normal = normalize(texture(normal_texture, tex_coord).xyz);
+    vec3 world_position = texture(position_texture, tex_coord).xyz;
+
+    vec3 light_direction = normalize(sun_direction);
+    vec3 view_direction = normalize(camera_position - world_position);
+
+    float incidence_angle = max(dot(surface_normal, light_direction), 0.0);
+    vec3 lighting = ambient_color + incidence_angle * sun_color;
+
+    vec3 halfway_direction = normalize(light_direction + view_direction);
+    float shininess = mix(8.0, 128.0, wetness);
+    float specularity = pow(max(dot(surface_normal, halfway_direction), 0.0), shininess);
+    vec3 specular = mix(0.05, 0.8, wetness) * specularity * sun_color;
+
+    vec3 color = lighting * albedo.rgb + specular
+        + scene_lighting(albedo.rgb, world_position, surface_normal, view_direction);
+
+    float view_distance = length(camera_position - world_position);
+    float fog = 1.0 - exp(-pow(fog_density * view_distance, 2.0));
+
+    out_color = vec4(mix(color, fog_color, fog), 1.0);
+}
@@ -0,0 +1,8 @@
+#version 450
+
+// Covers the whole screen, fragments look up the G-buffer with gl_FragCoord
+layout (location = 0) in vec2 position;
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+}
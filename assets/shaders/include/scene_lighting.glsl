@@ -0,0 +1,81 @@
+// The scene's lights and how they fall on a surface, shared by every shader that lights models
+
+#define MAX_LIGHTS 64
+
+struct Light {
+    // w is the kind, 0 for directional, 1 for point and 2 for spot
+    vec4 position;
+    // direction the light travels in, w is the radius
+    vec4 direction;
+    // already multiplied by the intensity
+    vec4 color;
+    // cosines of the inner and outer angles of spot lights
+    vec4 cone;
+};
+
+layout(std140) uniform Lights {
+    Light lights[MAX_LIGHTS];
+    int light_count;
+};
+
+uniform float wetness;
+
+// How much of a light reaches world_position, setting light_direction to point towards it
+float light_attenuation(Light light, vec3 world_position, out vec3 light_direction) {
+    int kind = int(light.position.w);
+
+    light_direction = -normalize(light.direction.xyz);
+
+    if (kind == 0) {
+        return 1.0;
+    }
+
+    vec3 to_light = light.position.xyz - world_position;
+    float light_distance = length(to_light);
+    float light_radius = light.direction.w;
+
+    if (light_distance >= light_radius) {
+        return 0.0;
+    }
+
+    // inverse square falloff, windowed to reach zero at the radius
+    float window = clamp(1.0 - pow(light_distance / light_radius, 4.0), 0.0, 1.0);
+    float attenuation = window * window / (light_distance * light_distance + 1.0);
+
+    // spot lights fade out between the inner and outer angle of their cone
+    if (kind == 2) {
+        float cosine = dot(normalize(light.direction.xyz), -to_light / light_distance);
+        attenuation *= smoothstep(light.cone.y, light.cone.x, cosine);
+    }
+
+    light_direction = to_light / light_distance;
+
+    return attenuation;
+}
+
+// Diffuse and specular light reaching a surface from the scene's lights
+vec3 scene_lighting(vec3 albedo, vec3 world_position, vec3 surface_normal, vec3 view_direction) {
+    float shininess = mix(8.0, 128.0, wetness);
+    float specular_strength = mix(0.05, 0.8, wetness);
+
+    vec3 total = vec3(0.0);
+
+    for (int i = 0; i < light_count; i++) {
+        vec3 light_direction;
+        float attenuation = light_attenuation(lights[i], world_position, light_direction);
+
+        if (attenuation == 0.0) {
+            continue;
+        }
+
+        float incidence_angle = max(dot(surface_normal, light_direction), 0.0);
+
+        vec3 halfway_direction = normalize(light_direction + view_direction);
+        float specularity = pow(max(dot(surface_normal, halfway_direction), 0.0), shininess);
+
+        vec3 color = incidence_angle * albedo + specular_strength * specularity;
+        total += color * lights[i].color.rgb * attenuation;
+    }
+
+    return total;
+}
@@ -0,0 +1,8 @@
+#version 450
+
+layout (location = 0) out vec4 out_color;
+
+// Only depth tested, with color writes masked off
+void main() {
+    out_color = vec4(1.0);
+}
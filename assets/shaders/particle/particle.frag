@@ -0,0 +1,15 @@
+#version 450
+
+layout (location = 0) out vec4 out_color;
+
+in VS_OUT {
+    vec2 corner;
+    vec4 color;
+} vs_in;
+
+void main() {
+    // A soft round spot rather than a square, fading to nothing at the edge
+    float falloff = 1.0 - smoothstep(0.0, 0.5, length(vs_in.corner));
+
+    out_color = vec4(vs_in.color.rgb, vs_in.color.a * falloff);
+}
@@ -0,0 +1,27 @@
+#version 450
+
+layout (location = 0) in vec2 corner;
+
+// Per instance
+in vec3 particle_position;
+in float particle_size;
+in vec4 particle_color;
+
+out VS_OUT {
+    vec2 corner;
+    vec4 color;
+} vs_out;
+
+uniform mat4 vp;
+// World space axes of the screen, so every particle faces the camera
+uniform vec3 camera_right;
+uniform vec3 camera_up;
+
+void main() {
+    vec3 offset = (camera_right * corner.x + camera_up * corner.y) * particle_size;
+
+    vs_out.corner = corner;
+    vs_out.color = particle_color;
+
+    gl_Position = vp * vec4(particle_position + offset, 1.0);
+}
@@ -0,0 +1,252 @@
+#version 450
+
+#include "scene_lighting.glsl"
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 tex_coord;
+layout(location = 3) in vec3 world_position;
+layout(location = 4) in vec4 tangent;
+
+layout(location = 0) out vec4 out_color;
+
+#define MAX_REFLECTION_PROBES 4
+#define PI 3.14159265359
+
+struct ReflectionProbe {
+    // w is the highest mipmap level
+    vec4 position;
+    vec4 box_min;
+    vec4 box_max;
+};
+
+layout(std140) uniform ReflectionProbes {
+    ReflectionProbe probes[MAX_REFLECTION_PROBES];
+    int probe_count;
+};
+
+// the cubemaps of each probe in the block, whose mipmaps are increasingly blurry
+uniform samplerCube reflection_probe_0;
+uniform samplerCube reflection_probe_1;
+uniform samplerCube reflection_probe_2;
+uniform samplerCube reflection_probe_3;
+
+// the scene drawn mirrored in a plane, shown on surfaces lying on it
+uniform sampler2D planar_reflection;
+uniform bool planar_reflection_active;
+// xyz is the normal and w the distance
+uniform vec4 planar_reflection_plane;
+uniform vec2 planar_reflection_screen_size;
+
+// Points towards the sun
+uniform vec3 sun_direction;
+uniform vec3 sun_color;
+uniform vec3 ambient_color;
+uniform vec3 camera_position;
+
+// the skybox, whose mipmaps are increasingly blurry. An intensity of 0 uses ambient_color instead.
+uniform samplerCube environment_map;
+uniform float environment_intensity;
+uniform float environment_max_mipmap_level;
+
+uniform vec3 fog_color;
+uniform float fog_density;
+// Shades everything white without emission, to see the lighting on its own
+uniform bool lighting_only;
+
+// per primitive
+uniform vec4 albedo_factor;
+uniform float metallic_factor;
+uniform float roughness_factor;
+uniform float normal_scale;
+uniform float occlusion_strength;
+uniform vec3 emissive_factor;
+
+uniform sampler2D albedo_texture;
+uniform sampler2D normal_texture;
+uniform sampler2D metallic_roughness_texture;
+uniform sampler2D occlusion_texture;
+uniform sampler2D emissive_texture;
+
+vec3 mapped_normal() {
+    vec3 geometry_normal = normalize(normal);
+
+    // models without tangents can't be normal mapped
+    if (dot(tangent.xyz, tangent.xyz) == 0.0) {
+        return geometry_normal;
+    }
+
+    vec3 surface_tangent = normalize(tangent.xyz - geometry_normal * dot(geometry_normal, tangent.xyz));
+    vec3 bitangent = cross(geometry_normal, surface_tangent) * tangent.w;
+
+    vec3 tangent_normal = texture(normal_texture, tex_coord).xyz * 2.0 - 1.0;
+    tangent_normal.xy *= normal_scale;
+
+    return normalize(mat3(surface_tangent, bitangent, geometry_normal) * tangent_normal);
+}
+
+// Cook-Torrance with a GGX distribution, Schlick-GGX geometry and Schlick's Fresnel approximation.
+// Light colors are multiplied by pi so lights look as bright as they do with Blinn-Phong shading.
+vec3 brdf(vec3 surface_normal, vec3 view_direction, vec3 light_direction, vec3 albedo, float metallic, float roughness) {
+    vec3 halfway_direction = normalize(light_direction + view_direction);
+
+    float n_dot_l = max(dot(surface_normal, light_direction), 0.0);
+    float n_dot_v = max(dot(surface_normal, view_direction), 0.0001);
+    float n_dot_h = max(dot(surface_normal, halfway_direction), 0.0);
+
+    float alpha = roughness * roughness;
+    float alpha_squared = alpha * alpha;
+    float denominator = n_dot_h * n_dot_h * (alpha_squared - 1.0) + 1.0;
+    float distribution = alpha_squared / (PI * denominator * denominator);
+
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float geometry = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+    vec3 fresnel = f0 + (1.0 - f0) * pow(1.0 - max(dot(halfway_direction, view_direction), 0.0), 5.0);
+
+    vec3 specular = distribution * geometry * fresnel / max(4.0 * n_dot_v * n_dot_l, 0.0001);
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo / PI;
+
+    return (diffuse + specular) * n_dot_l * PI;
+}
+
+vec3 sample_reflection_probe(int index, vec3 direction, float level) {
+    switch (index) {
+        case 0: return textureLod(reflection_probe_0, direction, level).rgb;
+        case 1: return textureLod(reflection_probe_1, direction, level).rgb;
+        case 2: return textureLod(reflection_probe_2, direction, level).rgb;
+        default: return textureLod(reflection_probe_3, direction, level).rgb;
+    }
+}
+
+// Light reflected from the smallest probe box around the surface. The reflection is traced to
+// where it leaves the box and the probe sampled towards that point, so it lines up with walls the
+// box is fitted to. False if no box is around the surface.
+bool probe_reflection(vec3 reflection, float roughness, out vec3 radiance) {
+    int smallest = -1;
+    float smallest_volume = 0.0;
+
+    for (int i = 0; i < probe_count; i++) {
+        vec3 box_min = probes[i].box_min.xyz;
+        vec3 box_max = probes[i].box_max.xyz;
+        if (any(lessThan(world_position, box_min)) || any(greaterThan(world_position, box_max))) {
+            continue;
+        }
+
+        vec3 size = box_max - box_min;
+        float volume = size.x * size.y * size.z;
+        if (smallest < 0 || volume < smallest_volume) {
+            smallest = i;
+            smallest_volume = volume;
+        }
+    }
+
+    if (smallest < 0) {
+        return false;
+    }
+
+    ReflectionProbe probe = probes[smallest];
+    vec3 furthest = max((probe.box_max.xyz - world_position) / reflection, (probe.box_min.xyz - world_position) / reflection);
+    float distance = min(min(furthest.x, furthest.y), furthest.z);
+    vec3 direction = world_position + reflection * distance - probe.position.xyz;
+
+    radiance = sample_reflection_probe(smallest, direction, roughness * probe.position.w);
+    return true;
+}
+
+// The scene mirrored in the planar reflection's plane, if the surface lies on it
+bool planar_reflection_light(out vec3 radiance) {
+    if (!planar_reflection_active) {
+        return false;
+    }
+
+    vec3 plane_normal = planar_reflection_plane.xyz;
+    float plane_distance = dot(plane_normal, world_position) + planar_reflection_plane.w;
+    if (abs(plane_distance) > 0.02 || dot(normalize(normal), plane_normal) < 0.99) {
+        return false;
+    }
+
+    radiance = texture(planar_reflection, gl_FragCoord.xy / planar_reflection_screen_size).rgb;
+    return true;
+}
+
+// Light reaching the surface from its surroundings, approximating image based lighting by
+// sampling blurrier mipmaps of the environment map for rougher surfaces. Reflections come from a
+// probe around the surface before the environment map, and sharp planar reflections are blurred
+// into them the rougher the surface is.
+vec3 ambient_light(vec3 surface_normal, vec3 view_direction, vec3 albedo, float metallic, float roughness) {
+    vec3 reflection = reflect(-view_direction, surface_normal);
+    vec3 radiance = vec3(0.0);
+    bool reflected = probe_reflection(reflection, roughness, radiance);
+
+    if (!reflected && environment_intensity > 0.0) {
+        radiance = textureLod(environment_map, reflection, roughness * environment_max_mipmap_level).rgb
+            * environment_intensity;
+        reflected = true;
+    }
+
+    vec3 planar_radiance;
+    if (planar_reflection_light(planar_radiance)) {
+        radiance = reflected ? mix(planar_radiance, radiance, roughness) : planar_radiance;
+        reflected = true;
+    }
+
+    if (!reflected) {
+        return ambient_color * albedo;
+    }
+
+    float n_dot_v = max(dot(surface_normal, view_direction), 0.0);
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+    vec3 fresnel = f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+
+    vec3 irradiance = environment_intensity > 0.0
+        ? textureLod(environment_map, surface_normal, environment_max_mipmap_level).rgb * environment_intensity
+        : ambient_color;
+
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo * irradiance;
+    vec3 specular = fresnel * radiance;
+
+    return diffuse + specular;
+}
+
+void main() {
+    vec4 albedo = texture(albedo_texture, tex_coord) * albedo_factor;
+    vec4 metallic_roughness = texture(metallic_roughness_texture, tex_coord);
+
+    // wet surfaces are darker and glossier
+    vec3 base_color = albedo.rgb * mix(1.0, 0.6, wetness);
+    float metallic = clamp(metallic_roughness.b * metallic_factor, 0.0, 1.0);
+    float roughness = clamp(metallic_roughness.g * roughness_factor * mix(1.0, 0.3, wetness), 0.04, 1.0);
+
+    float occlusion = mix(1.0, texture(occlusion_texture, tex_coord).r, occlusion_strength);
+    vec3 emissive = texture(emissive_texture, tex_coord).rgb * emissive_factor;
+    if (lighting_only) {
+        base_color = vec3(1.0);
+        emissive = vec3(0.0);
+    }
+
+    vec3 surface_normal = mapped_normal();
+    vec3 view_direction = normalize(camera_position - world_position);
+
+    vec3 color = ambient_light(surface_normal, view_direction, base_color, metallic, roughness) * occlusion + emissive
+        + brdf(surface_normal, view_direction, normalize(sun_direction), base_color, metallic, roughness) * sun_color;
+
+    for (int i = 0; i < light_count; i++) {
+        vec3 light_direction;
+        float attenuation = light_attenuation(lights[i], world_position, light_direction);
+
+        if (attenuation == 0.0) {
+            continue;
+        }
+
+        color += brdf(surface_normal, view_direction, light_direction, base_color, metallic, roughness)
+            * lights[i].color.rgb * attenuation;
+    }
+
+    float view_distance = length(camera_position - world_position);
+    float fog = 1.0 - exp(-pow(fog_density * view_distance, 2.0));
+
+    // Only blended materials are drawn with blending, so the alpha is ignored for the rest
+    out_color = vec4(mix(color, fog_color, fog), albedo.a);
+}
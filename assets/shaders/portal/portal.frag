@@ -0,0 +1,11 @@
+#version 450
+
+layout (location = 0) out vec4 out_color;
+
+// The view through the portal, rendered at screen resolution
+uniform sampler2D portal_texture;
+uniform vec2 screen_size;
+
+void main() {
+    out_color = vec4(texture(portal_texture, gl_FragCoord.xy / screen_size).rgb, 1.0);
+}
@@ -0,0 +1,9 @@
+#version 450
+
+layout (location = 0) in vec3 position;
+
+uniform mat4 vp;
+
+void main() {
+    gl_Position = vp * vec4(position, 1.0);
+}
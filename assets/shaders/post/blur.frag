@@ -0,0 +1,24 @@
+#version 450
+
+layout (location = 0) out vec4 out_color;
+
+in vec2 uv;
+
+uniform sampler2D source;
+// One texel along the axis being blurred
+uniform vec2 direction;
+
+// Gaussian weights, sampled between texels so linear filtering adds two taps in one
+const float weights[3] = float[](0.2270270270, 0.3162162162, 0.0702702703);
+const float offsets[3] = float[](0.0, 1.3846153846, 3.2307692308);
+
+void main() {
+    vec3 color = texture(source, uv).rgb * weights[0];
+
+    for (int i = 1; i < 3; i++) {
+        color += texture(source, uv + direction * offsets[i]).rgb * weights[i];
+        color += texture(source, uv - direction * offsets[i]).rgb * weights[i];
+    }
+
+    out_color = vec4(color, 1.0);
+}
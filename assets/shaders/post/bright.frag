@@ -0,0 +1,19 @@
+#version 450
+
+layout (location = 0) out vec4 out_color;
+
+in vec2 uv;
+
+uniform sampler2D hdr_texture;
+// Brightness above which pixels start to glow
+uniform float threshold;
+
+void main() {
+    vec3 color = texture(hdr_texture, uv).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+
+    // Keeps the hue of what's glowing while only letting through how far above the threshold it is
+    float contribution = max(brightness - threshold, 0.0) / max(brightness, 0.0001);
+
+    out_color = vec4(color * contribution, 1.0);
+}
@@ -0,0 +1,36 @@
+#version 450
+
+layout (location = 0) out vec4 out_color;
+
+in vec2 uv;
+
+uniform sampler2D hdr_texture;
+uniform sampler2D bloom_texture;
+uniform float bloom_intensity;
+// 0 clamps, 1 is Reinhard and 2 is ACES
+uniform int tonemapping;
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 color) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+}
+
+void main() {
+    vec3 color = texture(hdr_texture, uv).rgb;
+    color += texture(bloom_texture, uv).rgb * bloom_intensity;
+
+    if (tonemapping == 1) {
+        color = color / (color + 1.0);
+    } else if (tonemapping == 2) {
+        color = aces(color);
+    }
+
+    // Still linear, converted to sRGB when written to the window
+    out_color = vec4(clamp(color, 0.0, 1.0), 1.0);
+}
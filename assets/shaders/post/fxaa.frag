@@ -0,0 +1,54 @@
+#version 450
+
+layout (location = 0) out vec4 out_color;
+
+in vec2 uv;
+
+uniform sampler2D source;
+// Size of a texel in texture coordinates
+uniform vec2 texel_size;
+
+const float SPAN_MAX = 8.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+const float REDUCE_MIN = 1.0 / 128.0;
+
+// Edges are found by perceived brightness, so it's taken from roughly gamma encoded colors
+float luma(vec3 color) {
+    return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+void main() {
+    vec3 center = texture(source, uv).rgb;
+
+    float luma_nw = luma(texture(source, uv + vec2(-1.0, -1.0) * texel_size).rgb);
+    float luma_ne = luma(texture(source, uv + vec2(1.0, -1.0) * texel_size).rgb);
+    float luma_sw = luma(texture(source, uv + vec2(-1.0, 1.0) * texel_size).rgb);
+    float luma_se = luma(texture(source, uv + vec2(1.0, 1.0) * texel_size).rgb);
+    float luma_m = luma(center);
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // Blurs along the edge, perpendicular to the direction brightness changes in
+    vec2 direction = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+
+    float reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, -SPAN_MAX, SPAN_MAX) * texel_size;
+
+    vec3 near = 0.5 * (
+        texture(source, uv + direction * (1.0 / 3.0 - 0.5)).rgb +
+        texture(source, uv + direction * (2.0 / 3.0 - 0.5)).rgb
+    );
+    vec3 far = near * 0.5 + 0.25 * (
+        texture(source, uv - direction * 0.5).rgb +
+        texture(source, uv + direction * 0.5).rgb
+    );
+
+    // The wider blur is only used if it didn't reach past the edge into something else
+    float luma_far = luma(far);
+    out_color = vec4(luma_far < luma_min || luma_far > luma_max ? near : far, 1.0);
+}
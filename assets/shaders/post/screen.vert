@@ -0,0 +1,11 @@
+#version 450
+
+// Covers the whole target, with texture coordinates running from 0 to 1 across it
+layout (location = 0) in vec2 position;
+
+out vec2 uv;
+
+void main() {
+    uv = position * 0.5 + 0.5;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
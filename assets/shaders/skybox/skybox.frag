@@ -0,0 +1,12 @@
+#version 450
+
+layout (location = 0) in vec3 direction;
+
+layout (location = 0) out vec4 out_color;
+
+uniform samplerCube skybox;
+uniform float intensity;
+
+void main() {
+    out_color = vec4(textureLod(skybox, direction, 0.0).rgb * intensity, 1.0);
+}
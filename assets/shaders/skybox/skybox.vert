@@ -0,0 +1,15 @@
+#version 450
+
+layout (location = 0) in vec3 position;
+
+layout (location = 0) out vec3 direction;
+
+// projection and the camera's rotation, without its translation
+uniform mat4 vp;
+
+void main() {
+    direction = position;
+
+    // depth is always 1 so the skybox is behind everything
+    gl_Position = (vp * vec4(position, 1.0)).xyww;
+}
@@ -0,0 +1,54 @@
+#version 450
+
+layout(location = 0) in vec3 world_position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec4 splat;
+
+layout(location = 0) out vec4 out_color;
+
+// Points towards the sun
+uniform vec3 sun_direction;
+uniform vec3 sun_color;
+uniform vec3 ambient_color;
+uniform vec3 camera_position;
+
+uniform vec3 fog_color;
+uniform float fog_density;
+uniform float wetness;
+
+// sand, grass, rock and snow
+uniform sampler2D layer0_texture;
+uniform sampler2D layer1_texture;
+uniform sampler2D layer2_texture;
+uniform sampler2D layer3_texture;
+// each column is a layer's color
+uniform mat4 layer_colors;
+// repeats of each layer's texture per world unit
+uniform vec4 layer_tiling;
+// Shades the ground white, to see the lighting on its own
+uniform bool lighting_only;
+
+void main() {
+    // projected from above, so steep slopes stretch
+    vec2 ground = world_position.xz;
+
+    vec3 albedo = splat.x * texture(layer0_texture, ground * layer_tiling.x).rgb * layer_colors[0].rgb
+        + splat.y * texture(layer1_texture, ground * layer_tiling.y).rgb * layer_colors[1].rgb
+        + splat.z * texture(layer2_texture, ground * layer_tiling.z).rgb * layer_colors[2].rgb
+        + splat.w * texture(layer3_texture, ground * layer_tiling.w).rgb * layer_colors[3].rgb;
+
+    // wet ground is darker
+    albedo *= mix(1.0, 0.6, wetness);
+    if (lighting_only) {
+        albedo = vec3(1.0);
+    }
+
+    vec3 surface_normal = normalize(normal);
+    float incidence_angle = max(dot(surface_normal, normalize(sun_direction)), 0.0);
+    vec3 color = (ambient_color + incidence_angle * sun_color) * albedo;
+
+    float view_distance = length(camera_position - world_position);
+    float fog = 1.0 - exp(-pow(fog_density * view_distance, 2.0));
+
+    out_color = vec4(mix(color, fog_color, fog), 1.0);
+}
@@ -0,0 +1,20 @@
+#version 450
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec3 normal;
+layout (location = 2) in vec4 splat;
+
+layout (location = 0) out vec3 out_world_position;
+layout (location = 1) out vec3 out_normal;
+layout (location = 2) out vec4 out_splat;
+
+uniform mat4 vp;
+
+void main() {
+    // terrain is built in world space
+    out_world_position = position;
+    out_normal = normal;
+    out_splat = splat;
+
+    gl_Position = vp * vec4(position, 1.0);
+}
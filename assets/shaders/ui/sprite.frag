@@ -0,0 +1,11 @@
+#version 450
+
+layout (location = 0) out vec4 out_color;
+
+in VS_OUT {
+    vec4 color;
+} vs_in;
+
+void main() {
+    out_color = vs_in.color;
+}
@@ -0,0 +1,25 @@
+#version 450
+
+layout (location = 0) in vec2 corner;
+
+// Per instance, in pixels
+in vec2 center;
+in vec2 size;
+in float rotation;
+in vec4 color;
+
+out VS_OUT {
+    vec4 color;
+} vs_out;
+
+uniform mat4 projection;
+
+void main() {
+    vec2 scaled = corner * size;
+    // Clockwise on screen, as y points down
+    mat2 rotate = mat2(cos(rotation), sin(rotation), -sin(rotation), cos(rotation));
+
+    vs_out.color = color;
+
+    gl_Position = projection * vec4(center + rotate * scaled, 0.0, 1.0);
+}
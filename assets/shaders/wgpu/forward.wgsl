@@ -0,0 +1,200 @@
+// Forward shading for the wgpu backend, lit like default.frag and scene_lighting.glsl but
+// colored by each material's albedo
+
+struct Frame {
+    view_projection: mat4x4<f32>,
+    camera_position: vec4<f32>,
+    // Points towards the sun
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+    ambient_color: vec4<f32>,
+    // w is the fog density
+    fog: vec4<f32>,
+    // x is the wetness, y is 1 to shade everything white and see the lighting on its own
+    surface: vec4<f32>,
+};
+
+struct Light {
+    // w is the kind, 0 for directional, 1 for point and 2 for spot
+    position: vec4<f32>,
+    // direction the light travels in, w is the radius
+    direction: vec4<f32>,
+    // already multiplied by the intensity
+    color: vec4<f32>,
+    // cosines of the inner and outer angles of spot lights
+    cone: vec4<f32>,
+};
+
+// MAX_LIGHTS in light.rs
+struct Lights {
+    lights: array<Light, 64>,
+    light_count: i32,
+};
+
+struct Material {
+    albedo_factor: vec4<f32>,
+};
+
+// MAX_BONES in animation.rs
+struct Bones {
+    bones: array<mat4x4<f32>, 128>,
+};
+
+@group(0) @binding(0) var<uniform> frame: Frame;
+@group(0) @binding(1) var<uniform> lights: Lights;
+
+@group(1) @binding(0) var<uniform> material: Material;
+@group(1) @binding(1) var albedo_texture: texture_2d<f32>;
+@group(1) @binding(2) var albedo_sampler: sampler;
+
+// per skinned instance, identities for everything else
+@group(2) @binding(0) var<uniform> bones: Bones;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tex_coord: vec2<f32>,
+    @location(3) tangent: vec4<f32>,
+    @location(4) joints: vec4<f32>,
+    @location(5) weights: vec4<f32>,
+};
+
+// The columns of each matrix
+struct InstanceInput {
+    @location(6) transform_0: vec4<f32>,
+    @location(7) transform_1: vec4<f32>,
+    @location(8) transform_2: vec4<f32>,
+    @location(9) transform_3: vec4<f32>,
+    @location(10) transform_normal_0: vec4<f32>,
+    @location(11) transform_normal_1: vec4<f32>,
+    @location(12) transform_normal_2: vec4<f32>,
+    @location(13) transform_normal_3: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tex_coord: vec2<f32>,
+};
+
+fn upper_left(matrix: mat4x4<f32>) -> mat3x3<f32> {
+    return mat3x3<f32>(matrix[0].xyz, matrix[1].xyz, matrix[2].xyz);
+}
+
+@vertex
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let transform = mat4x4<f32>(
+        instance.transform_0,
+        instance.transform_1,
+        instance.transform_2,
+        instance.transform_3,
+    );
+    let transform_normal = mat4x4<f32>(
+        instance.transform_normal_0,
+        instance.transform_normal_1,
+        instance.transform_normal_2,
+        instance.transform_normal_3,
+    );
+
+    // vertices that aren't skinned have no weights
+    var skin = mat4x4<f32>(
+        vec4<f32>(1.0, 0.0, 0.0, 0.0),
+        vec4<f32>(0.0, 1.0, 0.0, 0.0),
+        vec4<f32>(0.0, 0.0, 1.0, 0.0),
+        vec4<f32>(0.0, 0.0, 0.0, 1.0),
+    );
+    if dot(vertex.weights, vec4<f32>(1.0)) > 0.0 {
+        skin = vertex.weights.x * bones.bones[u32(vertex.joints.x)]
+            + vertex.weights.y * bones.bones[u32(vertex.joints.y)]
+            + vertex.weights.z * bones.bones[u32(vertex.joints.z)]
+            + vertex.weights.w * bones.bones[u32(vertex.joints.w)];
+    }
+
+    let world_position = transform * skin * vec4<f32>(vertex.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = frame.view_projection * world_position;
+    out.world_position = world_position.xyz;
+    // Fix non-uniform scalings
+    out.normal = normalize(upper_left(transform_normal) * upper_left(skin) * vertex.normal);
+    out.tex_coord = vertex.tex_coord;
+
+    return out;
+}
+
+// Direction towards a light in xyz and how much of it reaches world_position in w
+fn light_falloff(light: Light, world_position: vec3<f32>) -> vec4<f32> {
+    let kind = i32(light.position.w);
+
+    if kind == 0 {
+        return vec4<f32>(-normalize(light.direction.xyz), 1.0);
+    }
+
+    let to_light = light.position.xyz - world_position;
+    let light_distance = length(to_light);
+    let light_radius = light.direction.w;
+
+    if light_distance >= light_radius {
+        return vec4<f32>(0.0);
+    }
+
+    // inverse square falloff, windowed to reach zero at the radius
+    let window = clamp(1.0 - pow(light_distance / light_radius, 4.0), 0.0, 1.0);
+    var attenuation = window * window / (light_distance * light_distance + 1.0);
+
+    // spot lights fade out between the inner and outer angle of their cone
+    if kind == 2 {
+        let cosine = dot(normalize(light.direction.xyz), -to_light / light_distance);
+        attenuation *= smoothstep(light.cone.y, light.cone.x, cosine);
+    }
+
+    return vec4<f32>(to_light / light_distance, attenuation);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let wetness = frame.surface.x;
+    let albedo_color = material.albedo_factor
+        * textureSample(albedo_texture, albedo_sampler, in.tex_coord);
+
+    // wet surfaces are darker and glossier
+    var albedo = albedo_color.rgb * mix(1.0, 0.6, wetness);
+    if frame.surface.y > 0.5 {
+        albedo = vec3<f32>(1.0);
+    }
+
+    let surface_normal = normalize(in.normal);
+    let sun_direction = normalize(frame.sun_direction.xyz);
+    let view_direction = normalize(frame.camera_position.xyz - in.world_position);
+    let shininess = mix(8.0, 128.0, wetness);
+    let specular_strength = mix(0.05, 0.8, wetness);
+
+    let sun_incidence = max(dot(surface_normal, sun_direction), 0.0);
+    let sun_halfway = normalize(sun_direction + view_direction);
+    let sun_specularity = pow(max(dot(surface_normal, sun_halfway), 0.0), shininess);
+
+    var color = (frame.ambient_color.rgb + sun_incidence * frame.sun_color.rgb) * albedo
+        + specular_strength * sun_specularity * frame.sun_color.rgb;
+
+    for (var i = 0; i < lights.light_count; i++) {
+        let light = lights.lights[i];
+        let falloff = light_falloff(light, in.world_position);
+
+        if falloff.w == 0.0 {
+            continue;
+        }
+
+        let incidence_angle = max(dot(surface_normal, falloff.xyz), 0.0);
+        let halfway_direction = normalize(falloff.xyz + view_direction);
+        let specularity = pow(max(dot(surface_normal, halfway_direction), 0.0), shininess);
+
+        let lit = incidence_angle * albedo + specular_strength * specularity;
+        color += lit * light.color.rgb * falloff.w;
+    }
+
+    let view_distance = length(frame.camera_position.xyz - in.world_position);
+    let fog = 1.0 - exp(-pow(frame.fog.w * view_distance, 2.0));
+
+    return vec4<f32>(mix(color, frame.fog.rgb, fog), albedo_color.a);
+}
@@ -0,0 +1,106 @@
+//! Compares drawing a large grid of teapots with one draw call per model against one draw call per
+//! instance. Run with `cargo bench --bench instancing`.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use cgmath::{Point3, Vector3};
+use winit::event_loop::EventLoop;
+
+use common::assets::Assets;
+use common::backend::Backend;
+use common::camera::Camera;
+use common::config::AppConfig;
+use common::context::OpenGLContext;
+use common::model::Transform;
+use common::profiling;
+use common::scene::Scene;
+use common::settings::GraphicsSettings;
+
+const GRID_SIZE: usize = 120;
+const FRAMES: u32 = 100;
+
+fn main() {
+    let event_loop = EventLoop::new().expect("Failed to create event loop");
+    // Vsync would cap both methods to the refresh rate
+    let settings = GraphicsSettings {
+        vsync: false,
+        ..GraphicsSettings::default()
+    };
+    let config = AppConfig {
+        title: "Instancing benchmark".to_owned(),
+        maximized: false,
+        ..AppConfig::default()
+    };
+    let mut opengl_context = OpenGLContext::new(&config, &settings, &event_loop);
+
+    // Inside the far plane of the middle of the grid, so most of it is in view
+    let camera = Camera::new_orbital(
+        Point3::new(0.0, 50.0, 60.0),
+        Point3::new(0.0, 0.0, 0.0),
+        1.0,
+    );
+    let mut scene = Scene::new("Instancing benchmark", camera, &mut Assets::new());
+    // Every teapot is drawn in full detail so only the draw calls differ
+    scene.level_of_detail = false;
+
+    let model = scene
+        .load_model(Path::new("assets/models/teapot.glb"))
+        .unwrap();
+
+    let offset = GRID_SIZE as f32 / 2.0;
+    for x in 0..GRID_SIZE {
+        for z in 0..GRID_SIZE {
+            scene.spawn_model(
+                model.clone(),
+                Transform {
+                    translation: Vector3::new(x as f32 - offset, 0.0, z as f32 - offset) * 2.0,
+                    ..Transform::default()
+                },
+            );
+        }
+    }
+
+    // Culling finds instances through the bounds a simulation step would refit
+    scene.simulation.update_bounds();
+
+    println!(
+        "{} instances, {} frames each",
+        scene.simulation.world.len(),
+        FRAMES
+    );
+
+    for instanced_rendering in [true, false] {
+        scene.instanced_rendering = instanced_rendering;
+
+        let frame_time = time_frames(&mut scene, &mut opengl_context);
+
+        println!(
+            "instanced_rendering = {}: {:.2}ms per frame, {} instances drawn",
+            instanced_rendering,
+            frame_time.as_secs_f64() * 1000.0,
+            scene.culling_statistics.drawn
+        );
+    }
+}
+
+fn time_frames(scene: &mut Scene, opengl_context: &mut OpenGLContext) -> Duration {
+    // Warm up so buffer allocation isn't counted
+    render_frame(scene, opengl_context);
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        render_frame(scene, opengl_context);
+    }
+    opengl_context.display.finish();
+
+    start.elapsed() / FRAMES
+}
+
+fn render_frame(scene: &mut Scene, opengl_context: &mut OpenGLContext) {
+    let mut target = opengl_context.begin_frame().unwrap();
+    scene.render(opengl_context, &mut target);
+    opengl_context.finish_frame(target).unwrap();
+    scene.gpu_timer.end_frame();
+    profiling::end_frame(&scene.gpu_timer.timings);
+}
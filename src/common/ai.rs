@@ -0,0 +1,301 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Quaternion, Rad, Rotation3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::collision::CollisionLayers;
+use crate::gameplay::Dead;
+use crate::maths::Ray;
+use crate::model::Transform;
+use crate::scene::Scene;
+use crate::uuid::UUID;
+use crate::weapons;
+
+/// How close an enemy has to get to a point on its path to move on to the next one
+const ARRIVAL_DISTANCE: f32 = 0.3;
+
+/// Seconds between finding a new path to a moving target
+const REPATH_INTERVAL: f32 = 0.5;
+
+/// What an enemy is doing
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AiState {
+    /// Walking between its patrol points, watching for the player
+    Patrol,
+    /// Heading for where the player is, or was last seen
+    Chase,
+    /// Standing still and shooting at the player
+    Attack,
+}
+
+/// Component for entities that hunt the player. Enemies walk between their patrol points until
+/// the player comes into view, then chase them around obstacles using the simulation's
+/// navigation grid, shooting once close enough. Losing sight of the player sends them to where
+/// they were last seen before going back to patrolling.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Enemy {
+    pub state: AiState,
+    /// Visited in turn while patrolling, standing still if empty
+    pub patrol: Vec<Point3<f32>>,
+    /// Units per second
+    pub speed: f32,
+    /// Furthest away the player can be seen
+    pub sight_range: f32,
+    /// Degrees either side of straight ahead the player can be seen while patrolling. Once
+    /// alerted, enemies notice the player in any direction.
+    pub field_of_view: f32,
+    pub attack_range: f32,
+    /// Damage dealt by each attack
+    pub damage: f32,
+    /// Attacks per second
+    pub attack_rate: f32,
+    /// Height of the eyes above the entity's origin, which sight is checked from
+    pub eye_height: f32,
+    next_waypoint: usize,
+    /// Left to walk through to reach the current target, nearest first
+    path: Vec<Point3<f32>>,
+    /// Where the path leads, so it's only found again once the target moves
+    path_target: Option<Point3<f32>>,
+    repath_timer: f32,
+    last_seen: Option<Point3<f32>>,
+    cooldown: f32,
+}
+
+impl Enemy {
+    pub fn new(patrol: Vec<Point3<f32>>) -> Self {
+        Self {
+            state: AiState::Patrol,
+            patrol,
+            speed: 3.0,
+            sight_range: 30.0,
+            field_of_view: 60.0,
+            attack_range: 12.0,
+            damage: 5.0,
+            attack_rate: 1.0,
+            eye_height: 1.6,
+            next_waypoint: 0,
+            path: vec![],
+            path_target: None,
+            repath_timer: 0.0,
+            last_seen: None,
+            cooldown: 0.0,
+        }
+    }
+
+    /// Where the enemy is walking through next, for debugging
+    pub fn path(&self) -> &[Point3<f32>] {
+        &self.path
+    }
+}
+
+/// An enemy shooting at the player, sent through the scene's events
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EnemyAttack {
+    pub enemy: UUID,
+    /// The enemy's eyes
+    pub origin: Point3<f32>,
+    pub target: Point3<f32>,
+    pub damage: f32,
+}
+
+/// Advances every living enemy by a fixed step, hunting the player at `player`, which is where
+/// the eyes of whoever is playing are, or `None` while they're dead
+pub fn update(scene: &mut Scene, player: Option<Point3<f32>>, deltatime: f32) {
+    let enemies = scene
+        .simulation
+        .query::<Enemy>()
+        .map(|(entity, _)| entity)
+        .filter(|&entity| !scene.simulation.world.has::<Dead>(entity))
+        .collect::<Vec<_>>();
+
+    for entity in enemies {
+        let Some(transform) = scene.simulation.world.get::<Transform>(entity) else {
+            continue;
+        };
+        let position = Point3::from_vec(transform.translation);
+        let facing = transform.rotation * -Vector3::unit_z();
+        let mut enemy = scene.simulation.world.get::<Enemy>(entity).unwrap().clone();
+
+        let eye = position + Vector3::unit_y() * enemy.eye_height;
+        let seen = player.filter(|&player| can_see(scene, entity, &enemy, eye, facing, player));
+        if seen.is_some() {
+            enemy.last_seen = seen;
+        }
+
+        enemy.state = match (enemy.state, seen) {
+            (_, Some(player)) if eye.distance(player) <= enemy.attack_range => AiState::Attack,
+            (_, Some(_)) => AiState::Chase,
+            (AiState::Patrol, None) => AiState::Patrol,
+            // Carries on to where the player was last seen, then gives up
+            (_, None) => match enemy.last_seen {
+                Some(last_seen) if flat_distance(position, last_seen) > ARRIVAL_DISTANCE => {
+                    AiState::Chase
+                }
+                _ => {
+                    enemy.last_seen = None;
+                    AiState::Patrol
+                }
+            },
+        };
+
+        enemy.cooldown = (enemy.cooldown - deltatime).max(0.0);
+        enemy.repath_timer = (enemy.repath_timer - deltatime).max(0.0);
+
+        let target = match enemy.state {
+            AiState::Patrol => patrol_target(&mut enemy, position),
+            AiState::Chase => enemy.last_seen,
+            AiState::Attack => {
+                let player = seen.unwrap();
+                if enemy.cooldown == 0.0 {
+                    enemy.cooldown = 1.0 / enemy.attack_rate;
+                    scene.events.send(EnemyAttack {
+                        enemy: entity,
+                        origin: eye,
+                        target: player,
+                        damage: enemy.damage,
+                    });
+                }
+                None
+            }
+        };
+
+        let heading = match target {
+            Some(target) => steer(scene, &mut enemy, position, target, deltatime),
+            None => {
+                enemy.path.clear();
+                enemy.path_target = None;
+                // Turns to face whoever it's shooting
+                seen.filter(|_| enemy.state == AiState::Attack)
+                    .map(|player| player - position)
+            }
+        };
+
+        if let Some(step) = heading {
+            let transform = scene.simulation.world.get_mut::<Transform>(entity).unwrap();
+            transform.translation += movement(&enemy, step, deltatime);
+            if let Some(rotation) = facing_rotation(step) {
+                transform.rotation = rotation;
+            }
+        }
+
+        *scene.simulation.world.get_mut::<Enemy>(entity).unwrap() = enemy;
+    }
+}
+
+/// Whether the player is in range, in view and not hidden behind anything
+fn can_see(
+    scene: &Scene,
+    entity: UUID,
+    enemy: &Enemy,
+    eye: Point3<f32>,
+    facing: Vector3<f32>,
+    player: Point3<f32>,
+) -> bool {
+    let offset = player - eye;
+    let distance = offset.magnitude();
+    if distance > enemy.sight_range || distance == 0.0 {
+        return false;
+    }
+
+    let direction = offset / distance;
+    if enemy.state == AiState::Patrol
+        && facing.dot(direction) < enemy.field_of_view.to_radians().cos()
+    {
+        return false;
+    }
+
+    weapons::first_hit(
+        &scene.simulation,
+        &Ray::new(eye, direction),
+        distance,
+        CollisionLayers::PROJECTILE,
+        |other| other != entity,
+    )
+    .is_none()
+}
+
+/// The patrol point being walked to, moving on to the next once it's reached
+fn patrol_target(enemy: &mut Enemy, position: Point3<f32>) -> Option<Point3<f32>> {
+    if enemy.patrol.is_empty() {
+        return None;
+    }
+
+    enemy.next_waypoint %= enemy.patrol.len();
+    if flat_distance(position, enemy.patrol[enemy.next_waypoint]) <= ARRIVAL_DISTANCE {
+        enemy.next_waypoint = (enemy.next_waypoint + 1) % enemy.patrol.len();
+    }
+
+    Some(enemy.patrol[enemy.next_waypoint])
+}
+
+/// Direction to walk in this step to follow a path to `target`, finding a new path if the target
+/// has moved. Heads straight for it when there's no navigation grid or no path.
+fn steer(
+    scene: &Scene,
+    enemy: &mut Enemy,
+    position: Point3<f32>,
+    target: Point3<f32>,
+    deltatime: f32,
+) -> Option<Vector3<f32>> {
+    let target_moved = enemy
+        .path_target
+        .map_or(true, |old| flat_distance(old, target) > ARRIVAL_DISTANCE);
+
+    if target_moved && enemy.repath_timer == 0.0 {
+        enemy.path = scene
+            .simulation
+            .navigation
+            .as_ref()
+            .and_then(|navigation| navigation.find_path(position, target))
+            .unwrap_or_else(|| vec![target]);
+        enemy.path_target = Some(target);
+        enemy.repath_timer = REPATH_INTERVAL;
+    }
+
+    // Skips every point reached, or about to be overshot, this step
+    let reach = (enemy.speed * deltatime).max(ARRIVAL_DISTANCE);
+    while enemy
+        .path
+        .first()
+        .is_some_and(|&next| flat_distance(position, next) <= reach && enemy.path.len() > 1)
+    {
+        enemy.path.remove(0);
+    }
+
+    let next = *enemy.path.first()?;
+    if flat_distance(position, next) <= ARRIVAL_DISTANCE {
+        enemy.path.clear();
+        return None;
+    }
+
+    Some(next - position)
+}
+
+/// How far an enemy moves along the ground this step, never past the point it's heading for
+fn movement(enemy: &Enemy, heading: Vector3<f32>, deltatime: f32) -> Vector3<f32> {
+    if enemy.state == AiState::Attack {
+        return Vector3::new(0.0, 0.0, 0.0);
+    }
+
+    let flat = Vector3::new(heading.x, 0.0, heading.z);
+    let distance = flat.magnitude();
+    if distance == 0.0 {
+        return flat;
+    }
+
+    flat * ((enemy.speed * deltatime).min(distance) / distance)
+}
+
+/// Turns about the Y axis so -Z, the way models face, points along `direction`
+fn facing_rotation(direction: Vector3<f32>) -> Option<Quaternion<f32>> {
+    if direction.x == 0.0 && direction.z == 0.0 {
+        return None;
+    }
+
+    Some(Quaternion::from_angle_y(Rad(
+        (-direction.x).atan2(-direction.z)
+    )))
+}
+
+/// Distance apart ignoring height, as enemies walk along the ground
+fn flat_distance(a: Point3<f32>, b: Point3<f32>) -> f32 {
+    Vector3::new(a.x - b.x, 0.0, a.z - b.z).magnitude()
+}
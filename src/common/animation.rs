@@ -0,0 +1,374 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{
+    EuclideanSpace, Matrix4, Point3, Quaternion, SquareMatrix, Transform as _, Vector3, VectorSpace,
+};
+use glium::implement_uniform_block;
+use gltf::animation::util::ReadOutputs;
+use gltf::animation::Interpolation;
+use gltf::buffer::Data;
+use itertools::Itertools;
+use log::warn;
+
+use crate::model::{Model, Transform};
+
+/// The most joints a skin can have, matching `MAX_BONES` in the vertex shader
+pub const MAX_BONES: usize = 128;
+
+pub struct SkeletonNode {
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    /// Transform relative to the parent when not animated
+    pub rest: Transform,
+}
+
+/// The node hierarchy of a model and the joints of its skin. Only a model's first skin is used.
+pub struct Skeleton {
+    /// Every node in the document, indexed the same way as in the glTF
+    pub nodes: Vec<SkeletonNode>,
+    /// Node of each joint, in the order vertices refer to them
+    pub joints: Vec<usize>,
+    /// Takes vertices from model space to the space of each joint
+    pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+    /// Nodes with parents before their children
+    order: Vec<usize>,
+}
+
+impl Skeleton {
+    pub(crate) fn from_gltf(document: &gltf::Document, file_buffers: &[Data]) -> Option<Self> {
+        let skin = document.skins().next()?;
+
+        let mut parents = vec![None; document.nodes().len()];
+        let mut children = vec![vec![]; document.nodes().len()];
+        for node in document.nodes() {
+            for child in node.children() {
+                parents[child.index()] = Some(node.index());
+                children[node.index()].push(child.index());
+            }
+        }
+
+        let nodes = document
+            .nodes()
+            .map(|node| {
+                let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+
+                SkeletonNode {
+                    name: node.name().map(str::to_owned),
+                    parent: parents[node.index()],
+                    rest: Transform {
+                        translation: Vector3::from(translation),
+                        rotation: Quaternion::new(w, x, y, z),
+                        scale: Vector3::from(scale),
+                    },
+                }
+            })
+            .collect_vec();
+
+        let mut order = nodes
+            .iter()
+            .positions(|node| node.parent.is_none())
+            .collect_vec();
+        let mut next = 0;
+        while next < order.len() {
+            order.extend_from_slice(&children[order[next]]);
+            next += 1;
+        }
+
+        let mut joints = skin.joints().map(|joint| joint.index()).collect_vec();
+        if joints.len() > MAX_BONES {
+            warn!(
+                "Skin has {} joints but only {} are supported",
+                joints.len(),
+                MAX_BONES
+            );
+            joints.truncate(MAX_BONES);
+        }
+
+        let reader = skin.reader(|buffer| Some(&file_buffers[buffer.index()]));
+        let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+            Some(matrices) => matrices.map(Matrix4::from).take(joints.len()).collect(),
+            None => vec![Matrix4::identity(); joints.len()],
+        };
+
+        Some(Self {
+            nodes,
+            joints,
+            inverse_bind_matrices,
+            order,
+        })
+    }
+
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.nodes.iter().map(|node| node.rest.clone()).collect()
+    }
+
+    /// Skinning matrix of each joint for a pose of every node. Vertices are loaded with their Y
+    /// axis flipped, so the matrices are too.
+    pub fn bone_matrices(&self, pose: &[Transform]) -> Vec<Matrix4<f32>> {
+        let globals = self.global_matrices(pose);
+        let flip = Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0);
+
+        self.joints
+            .iter()
+            .zip(self.inverse_bind_matrices.iter())
+            .map(|(&joint, inverse_bind_matrix)| flip * globals[joint] * inverse_bind_matrix * flip)
+            .collect()
+    }
+
+    /// Where each joint is in model space for a pose of every node, with Y flipped to match the
+    /// vertices
+    pub fn joint_positions(&self, pose: &[Transform]) -> Vec<Point3<f32>> {
+        let globals = self.global_matrices(pose);
+        let flip = Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0);
+
+        self.joints
+            .iter()
+            .map(|&joint| (flip * globals[joint]).transform_point(Point3::origin()))
+            .collect()
+    }
+
+    /// Index of the closest joint above each joint, skipping nodes that aren't joints
+    pub fn joint_parents(&self) -> Vec<Option<usize>> {
+        self.joints
+            .iter()
+            .map(|&joint| {
+                let mut node = self.nodes[joint].parent;
+                while let Some(parent) = node {
+                    if let Some(index) = self.joints.iter().position(|&other| other == parent) {
+                        return Some(index);
+                    }
+                    node = self.nodes[parent].parent;
+                }
+                None
+            })
+            .collect()
+    }
+
+    /// Transform of every node relative to the model
+    fn global_matrices(&self, pose: &[Transform]) -> Vec<Matrix4<f32>> {
+        let mut globals = vec![Matrix4::identity(); self.nodes.len()];
+
+        for &node in self.order.iter() {
+            let local = Matrix4::from(pose[node].clone());
+
+            globals[node] = match self.nodes[node].parent {
+                Some(parent) => globals[parent] * local,
+                None => local,
+            };
+        }
+
+        globals
+    }
+}
+
+enum Keyframes {
+    Translations(Vec<Vector3<f32>>),
+    Rotations(Vec<Quaternion<f32>>),
+    Scales(Vec<Vector3<f32>>),
+}
+
+/// Keyframes animating one property of one node
+struct Channel {
+    node: usize,
+    interpolation: Interpolation,
+    times: Vec<f32>,
+    keyframes: Keyframes,
+}
+
+impl Channel {
+    /// The keyframes either side of `time` and how far between them it is
+    fn keyframes_around(&self, time: f32) -> (usize, usize, f32) {
+        let next = self
+            .times
+            .partition_point(|&keyframe_time| keyframe_time <= time);
+
+        if next == 0 {
+            return (0, 0, 0.0);
+        }
+        if next == self.times.len() {
+            return (next - 1, next - 1, 0.0);
+        }
+
+        let previous = next - 1;
+        let amount = match self.interpolation {
+            Interpolation::Step => 0.0,
+            _ => (time - self.times[previous]) / (self.times[next] - self.times[previous]),
+        };
+
+        (previous, next, amount)
+    }
+
+    fn apply(&self, time: f32, transform: &mut Transform) {
+        let (previous, next, amount) = self.keyframes_around(time);
+
+        // Cubic spline keyframes are stored as an in tangent, value and out tangent. The tangents
+        // are ignored and the values blended linearly.
+        let value = |keyframe: usize| match self.interpolation {
+            Interpolation::CubicSpline => keyframe * 3 + 1,
+            _ => keyframe,
+        };
+        let (previous, next) = (value(previous), value(next));
+
+        match &self.keyframes {
+            Keyframes::Translations(translations) => {
+                transform.translation = translations[previous].lerp(translations[next], amount)
+            }
+            Keyframes::Rotations(rotations) => {
+                transform.rotation = rotations[previous].nlerp(
+                    Transform::same_hemisphere(rotations[previous], rotations[next]),
+                    amount,
+                )
+            }
+            Keyframes::Scales(scales) => {
+                transform.scale = scales[previous].lerp(scales[next], amount)
+            }
+        }
+    }
+}
+
+pub struct AnimationClip {
+    pub name: Option<String>,
+    /// Seconds
+    pub duration: f32,
+    channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    pub(crate) fn from_gltf(animation: gltf::Animation, file_buffers: &[Data]) -> Self {
+        let channels = animation
+            .channels()
+            .filter_map(|channel| {
+                let reader = channel.reader(|buffer| Some(&file_buffers[buffer.index()]));
+
+                let times = reader.read_inputs()?.collect_vec();
+                let keyframes = match reader.read_outputs()? {
+                    ReadOutputs::Translations(translations) => {
+                        Keyframes::Translations(translations.map(Vector3::from).collect())
+                    }
+                    ReadOutputs::Rotations(rotations) => Keyframes::Rotations(
+                        rotations
+                            .into_f32()
+                            .map(|[x, y, z, w]| Quaternion::new(w, x, y, z))
+                            .collect(),
+                    ),
+                    ReadOutputs::Scales(scales) => {
+                        Keyframes::Scales(scales.map(Vector3::from).collect())
+                    }
+                    ReadOutputs::MorphTargetWeights(_) => {
+                        warn!("Morph target animation is not supported");
+                        return None;
+                    }
+                };
+
+                Some(Channel {
+                    node: channel.target().node().index(),
+                    interpolation: channel.sampler().interpolation(),
+                    times,
+                    keyframes,
+                })
+            })
+            .filter(|channel| !channel.times.is_empty())
+            .collect_vec();
+
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last().copied())
+            .fold(0.0, f32::max);
+
+        Self {
+            name: animation.name().map(str::to_owned),
+            duration,
+            channels,
+        }
+    }
+
+    /// Poses every node of a skeleton at `time`, leaving nodes the clip doesn't animate at rest
+    pub fn sample(&self, skeleton: &Skeleton, time: f32) -> Vec<Transform> {
+        let mut pose = skeleton.rest_pose();
+
+        for channel in self.channels.iter() {
+            if let Some(transform) = pose.get_mut(channel.node) {
+                channel.apply(time, transform);
+            }
+        }
+
+        pose
+    }
+}
+
+/// Component that plays one of the animations of its entity's model
+#[derive(Clone, Debug)]
+pub struct AnimationPlayer {
+    /// Index into the model's animations
+    pub clip: usize,
+    /// Seconds into the clip
+    pub time: f32,
+    pub speed: f32,
+    pub looping: bool,
+    pub playing: bool,
+}
+
+impl AnimationPlayer {
+    pub fn new(clip: usize) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            looping: true,
+            playing: true,
+        }
+    }
+
+    pub fn update(&mut self, deltatime: f32, model: &Model) {
+        let Some(clip) = model.animations.get(self.clip) else {
+            return;
+        };
+
+        if !self.playing || clip.duration <= 0.0 {
+            return;
+        }
+
+        self.time += deltatime * self.speed;
+
+        if self.looping {
+            self.time = self.time.rem_euclid(clip.duration);
+        } else if !(0.0..=clip.duration).contains(&self.time) {
+            self.time = self.time.clamp(0.0, clip.duration);
+            self.playing = false;
+        }
+    }
+
+    /// The pose of the model's skeleton at the current time, or its rest pose if the clip doesn't
+    /// exist. `None` if the model has no skeleton.
+    pub fn pose(&self, model: &Model) -> Option<Vec<Transform>> {
+        let skeleton = model.skeleton.as_ref()?;
+
+        Some(match model.animations.get(self.clip) {
+            Some(clip) => clip.sample(skeleton, self.time),
+            None => skeleton.rest_pose(),
+        })
+    }
+}
+
+/// Skinning matrices of one skinned instance as laid out in the `Bones` uniform block
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct BonesBlock {
+    bones: [[[f32; 4]; 4]; MAX_BONES],
+}
+implement_uniform_block!(BonesBlock, bones);
+
+// Only made of floats, so without padding and valid for any bytes
+unsafe impl Zeroable for BonesBlock {}
+unsafe impl Pod for BonesBlock {}
+
+impl BonesBlock {
+    pub(crate) fn new(bone_matrices: &[Matrix4<f32>]) -> Self {
+        let mut bones = [<[[f32; 4]; 4]>::from(Matrix4::identity()); MAX_BONES];
+
+        for (bone, matrix) in bones.iter_mut().zip(bone_matrices) {
+            *bone = <[[f32; 4]; 4]>::from(*matrix);
+        }
+
+        Self { bones }
+    }
+}
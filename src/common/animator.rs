@@ -0,0 +1,457 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::animation::Skeleton;
+use crate::model::{Model, Transform};
+
+/// Set from how fast an entity moves along the ground in units per second
+pub const SPEED_PARAMETER: &str = "speed";
+
+/// Set when a character fires its weapon
+pub const SHOOT_TRIGGER: &str = "shoot";
+
+/// Speed the run clip of `AnimatorController::character` plays at full weight, matching how fast
+/// enemies walk
+const RUN_SPEED: f32 = 3.0;
+
+/// What a state plays
+#[derive(Clone, Debug, PartialEq)]
+pub enum Motion {
+    /// Leaves the pose of the layers below alone, or the rest pose on the first layer
+    Empty,
+    Clip {
+        /// Index into the model's animations
+        clip: usize,
+        looping: bool,
+    },
+    /// Blends between looping clips by a parameter, each placed at a value of it, e.g. idling at
+    /// a speed of 0 and running at 3. Clips are played in step with each other, so feet stay in
+    /// phase as the blend changes.
+    BlendTree {
+        parameter: String,
+        /// Sorted by their values
+        clips: Vec<(f32, usize)>,
+    },
+}
+
+/// What a transition waits for
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    Greater {
+        parameter: String,
+        value: f32,
+    },
+    Less {
+        parameter: String,
+        value: f32,
+    },
+    /// The trigger has been set since a transition last used it
+    Trigger(String),
+    /// The current state has played through at least once
+    Finished,
+}
+
+/// A way out of a state, taken as soon as all of its conditions hold
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transition {
+    /// State the transition leaves, or `None` to leave any other state
+    pub from: Option<usize>,
+    pub to: usize,
+    pub conditions: Vec<Condition>,
+    /// Seconds to crossfade from the old state to the new one over
+    pub duration: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimatorState {
+    pub name: String,
+    pub motion: Motion,
+    pub speed: f32,
+}
+
+impl AnimatorState {
+    pub fn new(name: &str, motion: Motion) -> Self {
+        Self {
+            name: name.to_owned(),
+            motion,
+            speed: 1.0,
+        }
+    }
+}
+
+/// How far through a state a layer is
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Playback {
+    state: usize,
+    /// Times the state's motion has played through, so 0.5 is halfway through the first time
+    phase: f32,
+}
+
+/// A state machine animating some or all of a skeleton on top of the layers below it
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimatorLayer {
+    pub name: String,
+    pub states: Vec<AnimatorState>,
+    pub transitions: Vec<Transition>,
+    /// Whether the layer animates each node of the skeleton, every node if `None`
+    pub mask: Option<Vec<bool>>,
+    /// How much the layer overrides the ones below, from 0 to 1
+    pub weight: f32,
+    current: Playback,
+    /// The state being faded out of, with seconds into the fade and how long it lasts
+    fading: Option<(Playback, f32, f32)>,
+}
+
+impl AnimatorLayer {
+    /// Starts in the first state
+    pub fn new(name: &str, states: Vec<AnimatorState>, transitions: Vec<Transition>) -> Self {
+        Self {
+            name: name.to_owned(),
+            states,
+            transitions,
+            mask: None,
+            weight: 1.0,
+            current: Playback {
+                state: 0,
+                phase: 0.0,
+            },
+            fading: None,
+        }
+    }
+
+    pub fn current_state(&self) -> Option<&AnimatorState> {
+        self.states.get(self.current.state)
+    }
+
+    fn update(
+        &mut self,
+        deltatime: f32,
+        model: &Model,
+        parameters: &HashMap<String, f32>,
+        triggers: &mut HashSet<String>,
+    ) {
+        self.current = self.advance(self.current, deltatime, model, parameters);
+        self.fading = self.fading.and_then(|(playback, elapsed, duration)| {
+            let elapsed = elapsed + deltatime;
+            (elapsed < duration).then(|| {
+                let playback = self.advance(playback, deltatime, model, parameters);
+                (playback, elapsed, duration)
+            })
+        });
+
+        let current = self.current;
+        let transition = self.transitions.iter().find(|transition| {
+            let leaves = match transition.from {
+                Some(from) => from == current.state,
+                None => transition.to != current.state,
+            };
+
+            leaves
+                && transition.to < self.states.len()
+                && transition
+                    .conditions
+                    .iter()
+                    .all(|condition| match condition {
+                        Condition::Greater { parameter, value } => {
+                            parameters.get(parameter).copied().unwrap_or(0.0) > *value
+                        }
+                        Condition::Less { parameter, value } => {
+                            parameters.get(parameter).copied().unwrap_or(0.0) < *value
+                        }
+                        Condition::Trigger(trigger) => triggers.contains(trigger),
+                        Condition::Finished => current.phase >= 1.0,
+                    })
+        });
+
+        if let Some(transition) = transition {
+            for condition in transition.conditions.iter() {
+                if let Condition::Trigger(trigger) = condition {
+                    triggers.remove(trigger);
+                }
+            }
+
+            self.fading =
+                (transition.duration > 0.0).then_some((self.current, 0.0, transition.duration));
+            self.current = Playback {
+                state: transition.to,
+                phase: 0.0,
+            };
+        }
+    }
+
+    fn advance(
+        &self,
+        playback: Playback,
+        deltatime: f32,
+        model: &Model,
+        parameters: &HashMap<String, f32>,
+    ) -> Playback {
+        let Some(state) = self.states.get(playback.state) else {
+            return playback;
+        };
+        let duration = motion_duration(&state.motion, model, parameters);
+        let phase = playback.phase + deltatime * state.speed / duration.max(f32::EPSILON);
+
+        Playback {
+            phase: match state.motion {
+                Motion::Clip { looping: false, .. } => phase.min(1.0),
+                _ => phase,
+            },
+            ..playback
+        }
+    }
+
+    /// The layer's pose before masking and weighting, crossfading from the state being left.
+    /// Empty states hold `below`.
+    fn sample(
+        &self,
+        model: &Model,
+        skeleton: &Skeleton,
+        parameters: &HashMap<String, f32>,
+        below: &[Transform],
+    ) -> Vec<Transform> {
+        let sample = |playback: Playback| {
+            self.states
+                .get(playback.state)
+                .and_then(|state| {
+                    sample_motion(&state.motion, playback.phase, model, skeleton, parameters)
+                })
+                .unwrap_or_else(|| below.to_vec())
+        };
+
+        let current = sample(self.current);
+        match self.fading {
+            Some((previous, elapsed, duration)) => {
+                blend_poses(&sample(previous), &current, elapsed / duration, None)
+            }
+            None => current,
+        }
+    }
+}
+
+/// Component animating its entity's skeleton with layers of state machines, driven by parameters
+/// set by gameplay. Takes the place of an `AnimationPlayer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimatorController {
+    /// Applied in order, each on top of the ones before
+    pub layers: Vec<AnimatorLayer>,
+    parameters: HashMap<String, f32>,
+    triggers: HashSet<String>,
+}
+
+impl AnimatorController {
+    pub fn new(layers: Vec<AnimatorLayer>) -> Self {
+        Self {
+            layers,
+            parameters: HashMap::new(),
+            triggers: HashSet::new(),
+        }
+    }
+
+    /// Idling and running blended by speed, with shooting played over the upper body, using the
+    /// model's clips with "idle", "run" and "shoot" in their names. `None` unless the model has
+    /// both an idle and a run clip.
+    pub fn character(model: &Model) -> Option<Self> {
+        let find = |name: &str| {
+            model.animations.iter().position(|clip| {
+                clip.name
+                    .as_ref()
+                    .is_some_and(|clip_name| clip_name.to_lowercase().contains(name))
+            })
+        };
+
+        let locomotion = AnimatorLayer::new(
+            "Locomotion",
+            vec![AnimatorState::new(
+                "Locomotion",
+                Motion::BlendTree {
+                    parameter: SPEED_PARAMETER.to_owned(),
+                    clips: vec![(0.0, find("idle")?), (RUN_SPEED, find("run")?)],
+                },
+            )],
+            vec![],
+        );
+        let mut layers = vec![locomotion];
+
+        if let Some(shoot) = find("shoot") {
+            // Everything from the spine up, or the whole skeleton if there isn't one
+            let mask = model.skeleton.as_ref().and_then(|skeleton| {
+                let spine = skeleton.nodes.iter().position(|node| {
+                    node.name
+                        .as_ref()
+                        .is_some_and(|name| name.to_lowercase().contains("spine"))
+                })?;
+                Some(mask_below(skeleton, spine))
+            });
+
+            layers.push(AnimatorLayer {
+                mask,
+                ..AnimatorLayer::new(
+                    "Upper body",
+                    vec![
+                        AnimatorState::new("Empty", Motion::Empty),
+                        AnimatorState::new(
+                            "Shoot",
+                            Motion::Clip {
+                                clip: shoot,
+                                looping: false,
+                            },
+                        ),
+                    ],
+                    vec![
+                        Transition {
+                            from: Some(0),
+                            to: 1,
+                            conditions: vec![Condition::Trigger(SHOOT_TRIGGER.to_owned())],
+                            duration: 0.1,
+                        },
+                        Transition {
+                            from: Some(1),
+                            to: 0,
+                            conditions: vec![Condition::Finished],
+                            duration: 0.2,
+                        },
+                    ],
+                )
+            });
+        }
+
+        Some(Self::new(layers))
+    }
+
+    pub fn set_float(&mut self, parameter: &str, value: f32) {
+        self.parameters.insert(parameter.to_owned(), value);
+    }
+
+    /// 0 for parameters that haven't been set
+    pub fn float(&self, parameter: &str) -> f32 {
+        self.parameters.get(parameter).copied().unwrap_or(0.0)
+    }
+
+    /// Sets a trigger, which stays set until a transition waiting for it is taken
+    pub fn set_trigger(&mut self, trigger: &str) {
+        self.triggers.insert(trigger.to_owned());
+    }
+
+    /// Advances every layer, taking any transitions whose conditions now hold
+    pub fn update(&mut self, deltatime: f32, model: &Model) {
+        for layer in self.layers.iter_mut() {
+            layer.update(deltatime, model, &self.parameters, &mut self.triggers);
+        }
+    }
+
+    /// The pose of the model's skeleton with every layer applied over its rest pose. `None` if
+    /// the model has no skeleton.
+    pub fn pose(&self, model: &Model) -> Option<Vec<Transform>> {
+        let skeleton = model.skeleton.as_ref()?;
+        let mut pose = skeleton.rest_pose();
+
+        for layer in self.layers.iter() {
+            let layer_pose = layer.sample(model, skeleton, &self.parameters, &pose);
+            pose = blend_poses(&pose, &layer_pose, layer.weight, layer.mask.as_deref());
+        }
+
+        Some(pose)
+    }
+}
+
+/// Mask of the nodes at and under `root`, e.g. the spine to animate the upper body on its own
+pub fn mask_below(skeleton: &Skeleton, root: usize) -> Vec<bool> {
+    (0..skeleton.nodes.len())
+        .map(|node| {
+            std::iter::successors(Some(node), |&node| skeleton.nodes[node].parent)
+                .any(|ancestor| ancestor == root)
+        })
+        .collect()
+}
+
+/// Seconds one play through of a motion takes. Blend trees take as long as their clips blended
+/// by the same weights.
+fn motion_duration(motion: &Motion, model: &Model, parameters: &HashMap<String, f32>) -> f32 {
+    let clip_duration = |clip: usize| model.animations.get(clip).map_or(0.0, |clip| clip.duration);
+
+    match motion {
+        Motion::Empty => 1.0,
+        Motion::Clip { clip, .. } => clip_duration(*clip),
+        Motion::BlendTree { parameter, clips } => {
+            let value = parameters.get(parameter).copied().unwrap_or(0.0);
+            match blend_weights(clips, value) {
+                Some((a, b, amount)) => {
+                    let (a, b) = (clip_duration(clips[a].1), clip_duration(clips[b].1));
+                    a + (b - a) * amount
+                }
+                None => 1.0,
+            }
+        }
+    }
+}
+
+fn sample_motion(
+    motion: &Motion,
+    phase: f32,
+    model: &Model,
+    skeleton: &Skeleton,
+    parameters: &HashMap<String, f32>,
+) -> Option<Vec<Transform>> {
+    let sample = |clip: usize, looping: bool| {
+        let clip = model.animations.get(clip)?;
+        let phase = if looping {
+            phase.fract()
+        } else {
+            phase.min(1.0)
+        };
+
+        Some(clip.sample(skeleton, phase * clip.duration))
+    };
+
+    match motion {
+        Motion::Empty => None,
+        Motion::Clip { clip, looping } => sample(*clip, *looping),
+        Motion::BlendTree { parameter, clips } => {
+            let value = parameters.get(parameter).copied().unwrap_or(0.0);
+            let (a, b, amount) = blend_weights(clips, value)?;
+
+            Some(blend_poses(
+                &sample(clips[a].1, true)?,
+                &sample(clips[b].1, true)?,
+                amount,
+                None,
+            ))
+        }
+    }
+}
+
+/// The clips either side of `value` and how far between them it is, holding the first or last
+/// clip past either end
+fn blend_weights(clips: &[(f32, usize)], value: f32) -> Option<(usize, usize, f32)> {
+    let last = clips.len().checked_sub(1)?;
+    let next = clips.partition_point(|&(threshold, _)| threshold <= value);
+
+    Some(match next {
+        0 => (0, 0, 0.0),
+        _ if next > last => (last, last, 0.0),
+        _ => {
+            let (from, to) = (clips[next - 1].0, clips[next].0);
+            (next - 1, next, (value - from) / (to - from))
+        }
+    })
+}
+
+/// Blends every node `mask` allows from pose `a` to `b`
+fn blend_poses(
+    a: &[Transform],
+    b: &[Transform],
+    amount: f32,
+    mask: Option<&[bool]>,
+) -> Vec<Transform> {
+    a.iter()
+        .zip(b.iter())
+        .enumerate()
+        .map(|(node, (a, b))| {
+            if mask.is_some_and(|mask| !mask.get(node).copied().unwrap_or(false)) {
+                a.clone()
+            } else {
+                a.lerp(b, amount)
+            }
+        })
+        .collect()
+}
@@ -2,7 +2,10 @@ use winit::event_loop::EventLoop;
 
 pub trait Application {
     fn run(self, event_loop: EventLoop<()>);
+    /// Called once per frame
     fn update(&mut self);
+    /// Advances the simulation by a fixed `deltatime`, called zero or more times per frame
+    fn fixed_update(&mut self, deltatime: f64);
     fn render(&mut self);
     fn render_gui(&mut self);
 }
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use color_eyre::Result;
+use glium::glutin::surface::WindowSurface;
+use glium::{Display, Program};
+use log::{error, info};
+
+use crate::context;
+use crate::model::Model;
+use crate::texture::{DecodedTexture, Texture};
+
+/// Logical names for assets, so scenes can refer to them without their paths
+pub const ASSET_NAMES_PATH: &str = "assets/config/assets.toml";
+
+/// Typed reference to an asset loaded into `Assets`
+pub struct Handle<T> {
+    index: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    fn new(index: usize) -> Self {
+        Self {
+            index,
+            marker: PhantomData,
+        }
+    }
+}
+
+// Derives would require `T` to implement these too
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state)
+    }
+}
+
+impl<T> Debug for Handle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle({})", self.index)
+    }
+}
+
+/// Loaded assets of one type along with what they were loaded from
+struct Storage<K, T> {
+    /// `None` where an asset was unloaded, so the indices of the rest stay the same
+    assets: Vec<Option<T>>,
+    indices: HashMap<K, usize>,
+}
+
+impl<K, T> Default for Storage<K, T> {
+    fn default() -> Self {
+        Self {
+            assets: vec![],
+            indices: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash, T> Storage<K, T> {
+    /// Index of the asset loaded from `key`, only calling `load` the first time
+    fn get_or_load(&mut self, key: K, load: impl FnOnce() -> Result<T>) -> Result<usize> {
+        if let Some(&index) = self.indices.get(&key) {
+            return Ok(index);
+        }
+
+        self.assets.push(Some(load()?));
+        self.indices.insert(key, self.assets.len() - 1);
+
+        Ok(self.assets.len() - 1)
+    }
+
+    /// Index of an asset loaded elsewhere, e.g. by `AssetLoader`, replacing any loaded from `key`
+    fn insert(&mut self, key: K, asset: T) -> usize {
+        if let Some(&index) = self.indices.get(&key) {
+            self.assets[index] = Some(asset);
+            return index;
+        }
+
+        self.assets.push(Some(asset));
+        self.indices.insert(key, self.assets.len() - 1);
+
+        self.assets.len() - 1
+    }
+
+    /// Drops the asset loaded from `key`, so loading it again reads it afresh
+    fn remove(&mut self, key: &K) -> bool {
+        let Some(index) = self.indices.remove(key) else {
+            return false;
+        };
+        self.assets[index] = None;
+
+        true
+    }
+
+    fn get(&self, index: usize) -> &T {
+        self.assets[index]
+            .as_ref()
+            .expect("Handle refers to an unloaded asset")
+    }
+}
+
+/// Caches models, textures and shader programs by path so each is only read once. Models are kept
+/// on the CPU for whichever backend draws them, textures and programs are OpenGL's.
+#[derive(Default)]
+pub struct Assets {
+    models: Storage<PathBuf, Arc<Model>>,
+    textures: Storage<PathBuf, Texture>,
+    /// Keyed by vertex then fragment shader path
+    programs: Storage<(PathBuf, PathBuf), Program>,
+    names: HashMap<String, PathBuf>,
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads logical names from a TOML file of `name = "path"` pairs, adding them to any already
+    /// known
+    pub fn load_names(&mut self, path: &Path) -> Result<()> {
+        let names = toml::from_str::<HashMap<String, PathBuf>>(&fs::read_to_string(path)?)?;
+        self.names.extend(names);
+
+        Ok(())
+    }
+
+    pub fn set_name(&mut self, name: &str, path: &Path) {
+        self.names.insert(name.to_owned(), path.to_owned());
+    }
+
+    /// The path a logical name refers to, or `name` itself if it isn't a known name
+    pub fn resolve(&self, name: &Path) -> PathBuf {
+        name.to_str()
+            .and_then(|name| self.names.get(name))
+            .cloned()
+            .unwrap_or_else(|| name.to_owned())
+    }
+
+    /// The logical name of the asset at `path`, if it has one
+    pub fn name_of(&self, path: &Path) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(_, named_path)| named_path.as_path() == path)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Loads a model by path or logical name
+    pub fn load_model(&mut self, name: &Path) -> Result<Handle<Model>> {
+        let path = self.resolve(name);
+
+        let index = self
+            .models
+            .get_or_load(path.clone(), || Model::load(&path))?;
+
+        Ok(Handle::new(index))
+    }
+
+    /// Loads a model from GLB data held in memory, keyed by `name`
+    pub fn load_embedded_model(&mut self, name: &str, bytes: &[u8]) -> Result<Handle<Model>> {
+        let path = PathBuf::from(name);
+
+        let index = self
+            .models
+            .get_or_load(path.clone(), || Model::load_from_bytes(&path, bytes))?;
+
+        Ok(Handle::new(index))
+    }
+
+    /// Adds a model that was decoded elsewhere, e.g. by `AssetLoader`, so loading `path` finds it
+    pub fn insert_model(&mut self, path: &Path, model: Arc<Model>) -> Handle<Model> {
+        Handle::new(self.models.insert(path.to_owned(), model))
+    }
+
+    /// Loads an image by path or logical name as an sRGB texture, see `Texture::upload`
+    pub fn load_texture(
+        &mut self,
+        name: &Path,
+        display: &Display<WindowSurface>,
+    ) -> Result<Handle<Texture>> {
+        let path = self.resolve(name);
+
+        let index = self.textures.get_or_load(path.clone(), || {
+            upload_texture(decode_texture(&path)?, display)
+        })?;
+
+        Ok(Handle::new(index))
+    }
+
+    /// Adds a texture that was decoded and uploaded elsewhere, e.g. by `AssetLoader`
+    pub fn insert_texture(&mut self, path: &Path, texture: Texture) -> Handle<Texture> {
+        Handle::new(self.textures.insert(path.to_owned(), texture))
+    }
+
+    pub fn load_program(
+        &mut self,
+        vertex_source_path: &str,
+        fragment_source_path: &str,
+        display: &Display<WindowSurface>,
+    ) -> Result<Handle<Program>> {
+        let key = (
+            PathBuf::from(vertex_source_path),
+            PathBuf::from(fragment_source_path),
+        );
+
+        let index = self.programs.get_or_load(key, || {
+            context::new_program(vertex_source_path, fragment_source_path, None, display)
+        })?;
+
+        Ok(Handle::new(index))
+    }
+
+    /// Recompiles the programs that use any of the `changed` shaders, given as canonical paths.
+    /// A program that fails to compile keeps its previous version.
+    pub fn reload_programs(&mut self, changed: &[PathBuf], display: &Display<WindowSurface>) {
+        let Storage { assets, indices } = &mut self.programs;
+
+        let was_changed =
+            |path: &PathBuf| fs::canonicalize(path).is_ok_and(|path| changed.contains(&path));
+        // Any program could use an include, so all of them are rebuilt when one changes
+        let include_changed = fs::canonicalize(context::SHADER_INCLUDE_DIRECTORY)
+            .is_ok_and(|directory| changed.iter().any(|path| path.starts_with(&directory)));
+
+        for ((vertex_source_path, fragment_source_path), &index) in indices.iter() {
+            if !include_changed
+                && !was_changed(vertex_source_path)
+                && !was_changed(fragment_source_path)
+            {
+                continue;
+            }
+
+            match context::new_program(
+                &vertex_source_path.to_string_lossy(),
+                &fragment_source_path.to_string_lossy(),
+                None,
+                display,
+            ) {
+                Ok(program) => {
+                    info!(
+                        "Reloaded shaders {:?} and {:?}",
+                        vertex_source_path, fragment_source_path
+                    );
+                    assets[index] = Some(program);
+                }
+                Err(error) => error!(
+                    "Failed to reload shaders {:?} and {:?}, keeping the old program: {}",
+                    vertex_source_path, fragment_source_path, error
+                ),
+            }
+        }
+    }
+
+    pub fn model(&self, handle: Handle<Model>) -> &Arc<Model> {
+        self.models.get(handle.index)
+    }
+
+    pub fn texture(&self, handle: Handle<Texture>) -> &Texture {
+        self.textures.get(handle.index)
+    }
+
+    pub fn program(&self, handle: Handle<Program>) -> &Program {
+        self.programs.get(handle.index)
+    }
+
+    /// Drops the cached model at a path or logical name, so loading it again reads it afresh.
+    /// Refuses, returning false, while anything else still holds the model, such as an instance
+    /// of it, as whatever is using it may still have a handle to it.
+    pub fn unload_model(&mut self, name: &Path) -> bool {
+        let path = self.resolve(name);
+        let Some(&index) = self.models.indices.get(&path) else {
+            return false;
+        };
+        if Arc::strong_count(self.models.get(index)) > 1 {
+            return false;
+        }
+
+        self.models.remove(&path)
+    }
+
+    /// Whether `model` is the one cached for its path, rather than one since unloaded or replaced
+    pub fn is_cached(&self, model: &Arc<Model>) -> bool {
+        self.models
+            .indices
+            .get(&model.path)
+            .is_some_and(|&index| Arc::ptr_eq(self.models.get(index), model))
+    }
+
+    pub fn model_is_loaded(&self, path: &Path) -> bool {
+        self.models.indices.contains_key(&self.resolve(path))
+    }
+
+    pub fn texture_is_loaded(&self, path: &Path) -> bool {
+        self.textures.indices.contains_key(&self.resolve(path))
+    }
+}
+
+/// Reads an image into memory without touching the GPU, so it can be done on a loading thread
+pub fn decode_texture(path: &Path) -> Result<DecodedTexture> {
+    DecodedTexture::read(path)
+}
+
+pub fn upload_texture(
+    decoded: DecodedTexture,
+    display: &Display<WindowSurface>,
+) -> Result<Texture> {
+    Texture::upload(decoded, display)
+}
+
+/// `path` relative to the directory the game runs from, which holds the assets directory, so
+/// scenes saved with it open on other machines. Paths outside of it are kept as they are.
+pub fn relative_path(path: &Path) -> PathBuf {
+    std::env::current_dir()
+        .and_then(fs::canonicalize)
+        .ok()
+        .zip(fs::canonicalize(path).ok())
+        .and_then(|(root, path)| Some(path.strip_prefix(root).ok()?.to_owned()))
+        .unwrap_or_else(|| path.to_owned())
+}
@@ -0,0 +1,338 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use color_eyre::Result;
+use log::debug;
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source, SpatialSink};
+
+use crate::camera::Camera;
+use crate::entity::World;
+use crate::model::Transform;
+use crate::uuid::UUID;
+
+/// Half the distance between the listener's ears
+const EAR_OFFSET: f32 = 0.1;
+
+/// Encoded audio, decoded again each time it's played
+#[derive(Clone)]
+pub struct Sound {
+    bytes: Arc<[u8]>,
+}
+
+impl Sound {
+    /// Reads a WAV, Vorbis, FLAC or MP3 file
+    pub fn load(path: &Path) -> Result<Self> {
+        debug!("Loading sound \"{:?}\"...", path);
+
+        let bytes = Arc::<[u8]>::from(std::fs::read(path)?);
+
+        // Decode once up front so bad files fail here rather than when played
+        Decoder::new(Cursor::new(bytes.clone()))?;
+
+        Ok(Self { bytes })
+    }
+
+    fn decoder(&self) -> Decoder<Cursor<Arc<[u8]>>> {
+        Decoder::new(Cursor::new(self.bytes.clone())).unwrap()
+    }
+}
+
+/// Mixer groups, each with its own volume under the master volume
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Effects,
+    Music,
+}
+
+/// Where a positional sound is heard from
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Emitter {
+    Position(Point3<f32>),
+    /// Follows the entity's `Transform`, staying where it last was if the entity is despawned
+    Entity(UUID),
+}
+
+enum Voice {
+    Flat(Sink),
+    Spatial {
+        sink: SpatialSink,
+        emitter: Emitter,
+        /// Where it was last heard from, kept once its entity is gone
+        position: Point3<f32>,
+    },
+}
+
+struct Playing {
+    voice: Voice,
+    channel: Channel,
+    volume: f32,
+}
+
+impl Playing {
+    fn finished(&self) -> bool {
+        match &self.voice {
+            Voice::Flat(sink) => sink.empty(),
+            Voice::Spatial { sink, .. } => sink.empty(),
+        }
+    }
+
+    fn stop(&self) {
+        match &self.voice {
+            Voice::Flat(sink) => sink.stop(),
+            Voice::Spatial { sink, .. } => sink.stop(),
+        }
+    }
+
+    fn set_volume(&self, volume: f32) {
+        match &self.voice {
+            Voice::Flat(sink) => sink.set_volume(volume),
+            Voice::Spatial { sink, .. } => sink.set_volume(volume),
+        }
+    }
+}
+
+/// Plays sounds through the default output device, panning and attenuating positional ones by
+/// where they are relative to the camera
+pub struct Audio {
+    /// Scales every channel
+    pub master_volume: f32,
+    pub effects_volume: f32,
+    pub music_volume: f32,
+    /// Dropping the stream stops all sound, so it's kept even though it isn't used
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+    sounds: HashMap<PathBuf, Sound>,
+    playing: Vec<Playing>,
+    /// Ambient sounds looping until stopped, by path
+    loops: HashMap<PathBuf, Playing>,
+    /// Loops that couldn't be played, which aren't tried again
+    failed_loops: HashSet<PathBuf>,
+    /// Ear positions from the last update, which new positional sounds start with
+    ears: [Point3<f32>; 2],
+}
+
+impl Audio {
+    /// Fails if there's no output device
+    pub fn new() -> Result<Self> {
+        let (stream, handle) = OutputStream::try_default()?;
+
+        Ok(Self {
+            master_volume: 1.0,
+            effects_volume: 1.0,
+            music_volume: 0.5,
+            _stream: stream,
+            handle,
+            sounds: HashMap::new(),
+            playing: vec![],
+            loops: HashMap::new(),
+            failed_loops: HashSet::new(),
+            ears: [Point3::origin(); 2],
+        })
+    }
+
+    /// Loads a sound by path, only reading it the first time
+    pub fn load(&mut self, path: &Path) -> Result<Sound> {
+        if let Some(sound) = self.sounds.get(path) {
+            return Ok(sound.clone());
+        }
+
+        let sound = Sound::load(path)?;
+        self.sounds.insert(path.to_owned(), sound.clone());
+
+        Ok(sound)
+    }
+
+    /// Plays an effect once at the same volume in both ears, e.g. for the player's own gunshots
+    pub fn play(&mut self, sound: &Sound, volume: f32) -> Result<()> {
+        let sink = Sink::try_new(&self.handle)?;
+        sink.append(sound.decoder());
+
+        self.start(Voice::Flat(sink), Channel::Effects, volume);
+
+        Ok(())
+    }
+
+    /// Plays an effect once from a point or entity in the world, quieter the further it is
+    pub fn play_at(
+        &mut self,
+        sound: &Sound,
+        emitter: Emitter,
+        volume: f32,
+        world: &World,
+    ) -> Result<()> {
+        let [left_ear, right_ear] = self.ears;
+        let position =
+            Self::emitter_position(emitter, world).unwrap_or(left_ear.midpoint(right_ear));
+        let sink = SpatialSink::try_new(
+            &self.handle,
+            position.into(),
+            left_ear.into(),
+            right_ear.into(),
+        )?;
+        sink.append(sound.decoder());
+
+        self.start(
+            Voice::Spatial {
+                sink,
+                emitter,
+                position,
+            },
+            Channel::Effects,
+            volume,
+        );
+
+        Ok(())
+    }
+
+    /// Loops a sound as music, replacing whatever music was playing
+    pub fn play_music(&mut self, sound: &Sound, volume: f32) -> Result<()> {
+        self.stop_music();
+
+        let sink = Sink::try_new(&self.handle)?;
+        sink.append(sound.decoder().repeat_infinite());
+
+        self.start(Voice::Flat(sink), Channel::Music, volume);
+
+        Ok(())
+    }
+
+    pub fn stop_music(&mut self) {
+        self.playing.retain(|playing| {
+            if playing.channel == Channel::Music {
+                playing.stop();
+            }
+
+            playing.channel != Channel::Music
+        });
+    }
+
+    /// Loops the sound at `path` as an effect in both ears, starting it if it isn't already
+    /// playing or changing its volume if it is. A sound that fails only reports it the first time.
+    pub fn set_loop(&mut self, path: &Path, volume: f32) -> Result<()> {
+        if let Some(playing) = self.loops.get_mut(path) {
+            playing.volume = volume;
+            return Ok(());
+        }
+        if self.failed_loops.contains(path) {
+            return Ok(());
+        }
+
+        let sink = self.load(path).and_then(|sound| {
+            let sink = Sink::try_new(&self.handle)?;
+            sink.append(sound.decoder().repeat_infinite());
+
+            Ok(sink)
+        });
+        let sink = match sink {
+            Ok(sink) => sink,
+            Err(error) => {
+                self.failed_loops.insert(path.to_owned());
+                return Err(error);
+            }
+        };
+
+        let playing = Playing {
+            voice: Voice::Flat(sink),
+            channel: Channel::Effects,
+            volume,
+        };
+        playing.set_volume(self.channel_volume(Channel::Effects) * volume);
+        self.loops.insert(path.to_owned(), playing);
+
+        Ok(())
+    }
+
+    pub fn stop_loop(&mut self, path: &Path) {
+        if let Some(playing) = self.loops.remove(path) {
+            playing.stop();
+        }
+    }
+
+    /// Moves the listener to the camera and positional sounds to their emitters, applies the
+    /// mixer's volumes and forgets sounds that have finished
+    pub fn update(&mut self, camera: &Camera, world: &World) {
+        let right = camera
+            .forward_direction
+            .cross(Vector3::unit_y())
+            .normalize()
+            * EAR_OFFSET;
+        self.ears = [camera.position - right, camera.position + right];
+        let [left_ear, right_ear] = self.ears;
+
+        self.playing.retain(|playing| !playing.finished());
+
+        for playing in self.playing.iter_mut() {
+            if let Voice::Spatial {
+                sink,
+                emitter,
+                position,
+            } = &mut playing.voice
+            {
+                sink.set_left_ear_position(left_ear.into());
+                sink.set_right_ear_position(right_ear.into());
+
+                if let Some(emitter_position) = Self::emitter_position(*emitter, world) {
+                    *position = emitter_position;
+                    sink.set_emitter_position(emitter_position.into());
+                }
+            }
+
+            playing.set_volume(self.channel_volume(playing.channel) * playing.volume);
+        }
+
+        for playing in self.loops.values() {
+            playing.set_volume(self.channel_volume(playing.channel) * playing.volume);
+        }
+    }
+
+    /// Leaves sounds following a despawned entity where it last was, so they don't jump to
+    /// whatever reuses its ID
+    pub fn forget_emitter(&mut self, entity: UUID) {
+        for playing in self.playing.iter_mut() {
+            if let Voice::Spatial {
+                emitter, position, ..
+            } = &mut playing.voice
+            {
+                if *emitter == Emitter::Entity(entity) {
+                    *emitter = Emitter::Position(*position);
+                }
+            }
+        }
+    }
+
+    /// Sounds still playing, including music and loops
+    pub fn playing_count(&self) -> usize {
+        self.playing.len() + self.loops.len()
+    }
+
+    fn start(&mut self, voice: Voice, channel: Channel, volume: f32) {
+        let playing = Playing {
+            voice,
+            channel,
+            volume,
+        };
+        playing.set_volume(self.channel_volume(channel) * volume);
+
+        self.playing.push(playing);
+    }
+
+    fn channel_volume(&self, channel: Channel) -> f32 {
+        self.master_volume
+            * match channel {
+                Channel::Effects => self.effects_volume,
+                Channel::Music => self.music_volume,
+            }
+    }
+
+    fn emitter_position(emitter: Emitter, world: &World) -> Option<Point3<f32>> {
+        match emitter {
+            Emitter::Position(position) => Some(position),
+            Emitter::Entity(entity) => world
+                .get::<Transform>(entity)
+                .map(|transform| Point3::from_vec(transform.translation)),
+        }
+    }
+}
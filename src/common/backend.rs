@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use color_eyre::Result;
+use glium::index::PrimitiveType;
+use glium::{Frame, IndexBuffer, Surface, VertexBuffer};
+use serde::{Deserialize, Serialize};
+use winit::dpi::PhysicalSize;
+
+use crate::camera::Camera;
+use crate::context::OpenGLContext;
+use crate::material::MaterialTextures;
+use crate::model::Model;
+use crate::scene::Scene;
+use crate::vertex::Vertex;
+use crate::viewport::Viewport;
+
+/// Graphics APIs the game can draw with, picked at startup by `AppConfig::backend`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+pub enum BackendKind {
+    #[default]
+    OpenGl,
+    /// Metal, DX12 or Vulkan, whichever the platform has, through wgpu. Draws less than OpenGL
+    /// does, as listed on `WgpuContext`.
+    Wgpu,
+}
+
+/// A graphics API the window is drawn through. Each backend uploads the models it draws itself,
+/// as they're kept on the CPU until then.
+pub trait Backend {
+    /// What each frame is drawn onto before it's shown
+    type Frame;
+    /// A model's meshes and materials uploaded to the GPU
+    type Model;
+
+    fn kind(&self) -> BackendKind;
+
+    /// The GPU and driver drawn with, for crash reports
+    fn description(&self) -> String;
+
+    /// Starts drawing a frame onto the window
+    fn begin_frame(&mut self) -> Result<Self::Frame>;
+
+    /// Shows a frame started with `begin_frame`
+    fn finish_frame(&mut self, frame: Self::Frame) -> Result<()>;
+
+    /// Fits the frames to the window once it's been resized
+    fn resize(&mut self, size: PhysicalSize<u32>);
+
+    /// Uploads every level of detail of a model along with its materials' textures
+    fn upload_model(&self, model: &Model) -> Result<Self::Model>;
+}
+
+/// A backend that can draw scenes onto `T`, which `Scene::render` and `Scene::render_views` draw
+/// through. Draw calls and culling statistics are added to the scene's.
+pub trait DrawScene<T: ?Sized>: Backend {
+    /// Draws the scene from its camera
+    fn draw_scene(&mut self, scene: &mut Scene, target: &mut T);
+
+    /// Draws the scene from each camera into its viewport, as `Scene::render_views` describes
+    fn draw_views(&mut self, scene: &mut Scene, target: &mut T, views: &[(Camera, Viewport)]);
+}
+
+/// Models a backend has uploaded, kept until nothing else holds them
+pub struct ModelCache<M> {
+    /// `None` for models that failed to upload, which aren't tried again
+    models: HashMap<Arc<Model>, Option<M>>,
+}
+
+impl<M> ModelCache<M> {
+    pub fn new() -> Self {
+        Self {
+            models: HashMap::new(),
+        }
+    }
+
+    /// Uploads a model through `backend` unless it already has been, returning the error the
+    /// first time it fails
+    pub fn upload<B: Backend<Model = M>>(&mut self, backend: &B, model: &Arc<Model>) -> Result<()> {
+        if self.models.contains_key(model) {
+            return Ok(());
+        }
+
+        match backend.upload_model(model) {
+            Ok(upload) => {
+                self.models.insert(model.clone(), Some(upload));
+                Ok(())
+            }
+            Err(error) => {
+                self.models.insert(model.clone(), None);
+                Err(error)
+            }
+        }
+    }
+
+    pub fn get(&self, model: &Model) -> Option<&M> {
+        self.models.get(model).and_then(Option::as_ref)
+    }
+
+    /// Drops the uploads of models only the cache still holds, such as those unloaded
+    pub fn retain_used(&mut self) {
+        self.models.retain(|model, _| Arc::strong_count(model) > 1);
+    }
+}
+
+impl<M> Default for ModelCache<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A model uploaded for OpenGL
+pub struct GlModel {
+    /// Buffers of every primitive at each level of detail in the order of `Model::lod_meshes`,
+    /// starting with the model's own meshes
+    levels: Vec<Vec<GlPrimitive>>,
+    /// One per material of the model
+    pub materials: Vec<MaterialTextures>,
+}
+
+pub struct GlPrimitive {
+    pub vertex_buffer: VertexBuffer<Vertex>,
+    pub index_buffer: IndexBuffer<u16>,
+}
+
+impl GlModel {
+    /// Buffers of the primitives of `Model::lod_meshes(level)`, falling back on the model's own
+    /// meshes just as it does
+    pub fn level(&self, level: usize) -> &[GlPrimitive] {
+        self.levels.get(level).unwrap_or(&self.levels[0])
+    }
+}
+
+impl Backend for OpenGLContext {
+    type Frame = Frame;
+    type Model = GlModel;
+
+    fn kind(&self) -> BackendKind {
+        BackendKind::OpenGl
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "{} by {}, OpenGL {}",
+            self.display.get_opengl_renderer_string(),
+            self.display.get_opengl_vendor_string(),
+            self.display.get_opengl_version_string()
+        )
+    }
+
+    fn begin_frame(&mut self) -> Result<Frame> {
+        Ok(self.display.draw())
+    }
+
+    fn finish_frame(&mut self, frame: Frame) -> Result<()> {
+        Ok(frame.finish()?)
+    }
+
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.display.resize((size.width, size.height));
+    }
+
+    fn upload_model(&self, model: &Model) -> Result<GlModel> {
+        let levels = (0..=model.lods.len())
+            .map(|level| {
+                model
+                    .lod_meshes(level)
+                    .iter()
+                    .flat_map(|mesh| mesh.primitives.iter())
+                    .map(|primitive| {
+                        Ok(GlPrimitive {
+                            vertex_buffer: VertexBuffer::new(&self.display, &primitive.vertices)?,
+                            index_buffer: IndexBuffer::new(
+                                &self.display,
+                                PrimitiveType::TrianglesList,
+                                &primitive.indices,
+                            )?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let materials = model
+            .materials
+            .iter()
+            .map(|material| MaterialTextures::upload(&material.borrow(), &self.display))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(GlModel { levels, materials })
+    }
+}
+
+impl<S: Surface> DrawScene<S> for OpenGLContext {
+    fn draw_scene(&mut self, scene: &mut Scene, target: &mut S) {
+        scene.render_gl(self, target);
+    }
+
+    fn draw_views(&mut self, scene: &mut Scene, target: &mut S, views: &[(Camera, Viewport)]) {
+        scene.render_views_gl(self, target, views);
+    }
+}
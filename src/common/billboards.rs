@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector2, Vector3};
+use color_eyre::Result;
+use glium::glutin::surface::WindowSurface;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::{
+    implement_vertex, uniform, Blend, Depth, DepthTest, Display, DrawParameters, Program, Surface,
+    VertexBuffer,
+};
+use itertools::Itertools;
+use palette::Srgba;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::{Assets, Handle};
+use crate::camera::Camera;
+use crate::entity::World;
+use crate::hierarchy;
+use crate::maths;
+use crate::texture::Texture;
+
+/// Drawn for billboards without a texture of their own, so they're flat colours
+const WHITE_TEXTURE_PATH: &str = "assets/textures/white.jpg";
+
+/// Component drawing a quad that always faces the camera at its entity's position, such as a
+/// health bar above an enemy, an icon over a pickup or a waypoint marker
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Billboard {
+    /// Drawn tinted by `color`, or just `color` when `None`. Handles only mean something to the
+    /// assets they came from, so it isn't saved.
+    #[serde(skip)]
+    pub texture: Option<Handle<Texture>>,
+    /// Width and height in world units
+    pub size: Vector2<f32>,
+    /// From the entity's position in world space, so it isn't scaled or turned with the entity
+    pub offset: Vector3<f32>,
+    pub color: Srgba,
+    /// Fraction of the width drawn in `color` from the left, the rest drawn in `background`, e.g.
+    /// how much health is left
+    pub fill: f32,
+    pub background: Srgba,
+    /// Drawn over everything rather than hidden behind what's in front of it
+    pub always_on_top: bool,
+    pub visible: bool,
+}
+
+impl Default for Billboard {
+    fn default() -> Self {
+        Self {
+            texture: None,
+            size: Vector2::new(0.5, 0.5),
+            offset: Vector3::new(0.0, 0.0, 0.0),
+            color: Srgba::new(1.0, 1.0, 1.0, 1.0),
+            fill: 1.0,
+            background: Srgba::new(0.0, 0.0, 0.0, 0.0),
+            always_on_top: false,
+            visible: true,
+        }
+    }
+}
+
+impl Billboard {
+    pub fn new(texture: Handle<Texture>, size: f32) -> Self {
+        Self {
+            texture: Some(texture),
+            size: Vector2::new(size, size),
+            ..Self::default()
+        }
+    }
+
+    /// A wide, thin bar `height` above its entity, green over dark red, filled from `fill`
+    pub fn bar(height: f32) -> Self {
+        Self {
+            size: Vector2::new(0.8, 0.08),
+            offset: Vector3::new(0.0, height, 0.0),
+            color: Srgba::new(0.2, 0.9, 0.2, 1.0),
+            background: Srgba::new(0.4, 0.0, 0.0, 0.8),
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Corner {
+    corner: [f32; 2],
+}
+implement_vertex!(Corner, corner);
+
+#[derive(Copy, Clone)]
+struct BillboardInstance {
+    billboard_position: [f32; 3],
+    billboard_size: [f32; 2],
+    billboard_color: [f32; 4],
+    billboard_background: [f32; 4],
+    billboard_fill: f32,
+    billboard_on_top: u32,
+}
+implement_vertex!(
+    BillboardInstance,
+    billboard_position,
+    billboard_size,
+    billboard_color,
+    billboard_background,
+    billboard_fill,
+    billboard_on_top
+);
+
+/// Draws every `Billboard` in the world, in one instanced draw call per texture. Those always on
+/// top are moved onto the near plane in the vertex shader rather than drawn separately.
+pub struct BillboardRenderer {
+    program: Handle<Program>,
+    white_texture: Handle<Texture>,
+    quad: VertexBuffer<Corner>,
+    /// Grows to fit the most billboards drawn at once
+    instances: Option<VertexBuffer<BillboardInstance>>,
+}
+
+impl BillboardRenderer {
+    pub fn new(assets: &mut Assets, display: &Display<WindowSurface>) -> Result<Self> {
+        let program = assets.load_program(
+            "assets/shaders/billboard/billboard.vert",
+            "assets/shaders/billboard/billboard.frag",
+            display,
+        )?;
+        let white_texture = assets.load_texture(WHITE_TEXTURE_PATH.as_ref(), display)?;
+
+        let quad = VertexBuffer::new(
+            display,
+            &[[-0.5, -0.5], [0.5, -0.5], [-0.5, 0.5], [0.5, 0.5]].map(|corner| Corner { corner }),
+        )?;
+
+        Ok(Self {
+            program,
+            white_texture,
+            quad,
+            instances: None,
+        })
+    }
+
+    /// Draws billboards furthest first with alpha blending, those always on top after the rest.
+    /// Returns the number of draw calls made.
+    pub fn render<S: Surface>(
+        &mut self,
+        display: &Display<WindowSurface>,
+        world: &World,
+        target: &mut S,
+        assets: &Assets,
+        camera: &Camera,
+    ) -> Result<usize> {
+        let mut batches = HashMap::<Handle<Texture>, Vec<(bool, f32, BillboardInstance)>>::new();
+        for (entity, billboard) in world.query::<Billboard>() {
+            if !billboard.visible {
+                continue;
+            }
+            let Some(matrix) = hierarchy::global_matrix(world, entity) else {
+                continue;
+            };
+            let position = Point3::from_vec(matrix.w.truncate()) + billboard.offset;
+
+            batches
+                .entry(billboard.texture.unwrap_or(self.white_texture))
+                .or_default()
+                .push((
+                    billboard.always_on_top,
+                    (position - camera.position).magnitude2(),
+                    BillboardInstance {
+                        billboard_position: position.into(),
+                        billboard_size: billboard.size.into(),
+                        billboard_color: color_array(billboard.color),
+                        billboard_background: color_array(billboard.background),
+                        billboard_fill: billboard.fill.clamp(0.0, 1.0),
+                        billboard_on_top: billboard.always_on_top as u32,
+                    },
+                ));
+        }
+
+        let count = batches.values().map(Vec::len).max().unwrap_or(0);
+        if count == 0 {
+            return Ok(0);
+        }
+        if self
+            .instances
+            .as_ref()
+            .map_or(true, |instances| instances.len() < count)
+        {
+            self.instances = Some(VertexBuffer::empty_dynamic(
+                display,
+                count.next_power_of_two(),
+            )?);
+        }
+
+        let right = camera
+            .forward_direction
+            .cross(camera.up_direction)
+            .normalize();
+        let up = right.cross(camera.forward_direction).normalize();
+
+        for (texture, batch) in batches.iter_mut() {
+            // Blending needs them drawn back to front, and those on top over all the rest
+            batch.sort_by(|(a_on_top, a, _), (b_on_top, b, _)| {
+                a_on_top.cmp(b_on_top).then(b.total_cmp(a))
+            });
+            let instances = batch.iter().map(|(_, _, instance)| *instance).collect_vec();
+
+            let slice = self
+                .instances
+                .as_ref()
+                .unwrap()
+                .slice(0..instances.len())
+                .unwrap();
+            slice.write(&instances);
+
+            target.draw(
+                (&self.quad, slice.per_instance().unwrap()),
+                &NoIndices(PrimitiveType::TriangleStrip),
+                assets.program(self.program),
+                &uniform! {
+                    vp: maths::raw_matrix(camera.view_projection),
+                    camera_right: <[f32; 3]>::from(right),
+                    camera_up: <[f32; 3]>::from(up),
+                    billboard_texture: assets.texture(*texture),
+                },
+                &DrawParameters {
+                    depth: Depth {
+                        test: DepthTest::IfLess,
+                        write: false,
+                        ..Depth::default()
+                    },
+                    blend: Blend::alpha_blending(),
+                    ..DrawParameters::default()
+                },
+            )?;
+        }
+
+        Ok(batches.len())
+    }
+}
+
+fn color_array(color: Srgba) -> [f32; 4] {
+    [color.red, color.green, color.blue, color.alpha]
+}
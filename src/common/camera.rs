@@ -1,10 +1,13 @@
 use cgmath::num_traits::Pow;
-use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, Vector3, Zero};
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Transform, Vector2, Vector3,
+    Zero,
+};
 use log::info;
 use serde::{Deserialize, Serialize};
-use winit::keyboard::KeyCode;
 
-use crate::input::Input;
+use crate::input::{Input, Stick};
+use crate::maths::Ray;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum ViewMode {
@@ -24,15 +27,37 @@ pub struct Camera {
     pub view_mode: ViewMode,
     pub yaw: f32,
     pub pitch: f32,
+    /// How far the camera sits from its target when orbiting
+    #[serde(default = "Camera::default_orbit_distance")]
+    pub distance: f32,
+    /// Vertical field of view in radians, set with `set_fov`
+    #[serde(default = "Camera::default_fov")]
+    fov: f32,
+    #[serde(default = "Camera::default_aspect_ratio")]
+    aspect_ratio: f32,
 }
 
 impl Camera {
+    const DEFAULT_ORBIT_DISTANCE: f32 = 5.0;
+    const MIN_ORBIT_DISTANCE: f32 = 0.1;
+    /// Fraction of the distance to the target covered by one line of scrolling
+    const ZOOM_SPEED: f32 = 0.1;
+    const PAN_SPEED: f32 = 0.5;
+    /// Units moved per second
+    const MOVE_SPEED: f32 = 6.0;
+    /// Radians turned per second with the stick pushed all the way
+    const STICK_LOOK_SPEED: f32 = 2.4;
+    pub const DEFAULT_FOV: f32 = std::f32::consts::FRAC_PI_2;
+    /// Distances to the near and far clipping planes
+    pub const NEAR: f32 = 0.01;
+    pub const FAR: f32 = 100.0;
+
     pub fn new_fps(
         position: Point3<f32>,
         forward_direction: Vector3<f32>,
         aspect_ratio: f32,
     ) -> Self {
-        let projection = Self::create_perspective_matrix(aspect_ratio);
+        let projection = Self::create_perspective_matrix(Self::DEFAULT_FOV, aspect_ratio);
         let view = Self::create_view_matrix(position, forward_direction);
 
         let yaw = forward_direction.z.atan2(forward_direction.x);
@@ -52,25 +77,111 @@ impl Camera {
             view_mode: ViewMode::FPS,
             yaw,
             pitch,
+            distance: Self::DEFAULT_ORBIT_DISTANCE,
+            fov: Self::DEFAULT_FOV,
+            aspect_ratio,
         }
     }
 
-    pub fn new_orbital(position: Point3<f32>, target: Point3<f32>) -> Self {
-        unimplemented!()
+    pub fn new_orbital(position: Point3<f32>, target: Point3<f32>, aspect_ratio: f32) -> Self {
+        let offset = target - position;
+
+        let mut camera = Self::new_fps(position, offset.normalize(), aspect_ratio);
+        camera.view_mode = ViewMode::Orbit;
+        camera.target = target;
+        camera.distance = offset.magnitude().max(Self::MIN_ORBIT_DISTANCE);
+
+        camera
     }
 
-    pub fn update(&mut self, input: &Input) {
+    /// Moves the camera from input over a frame lasting `deltatime` seconds
+    pub fn update(&mut self, input: &Input, deltatime: f32) {
         match self.view_mode {
-            ViewMode::Orbit => unimplemented!(),
-            ViewMode::FPS => self.update_fps(input),
+            ViewMode::Orbit => self.update_orbit(input),
+            ViewMode::FPS => self.update_fps(input, deltatime),
         }
 
         self.view = Self::create_view_matrix(self.position, self.forward_direction);
         self.view_projection = self.projection * self.view;
     }
 
+    /// Moves the camera to `position` facing along `forward_direction`
+    pub fn look_at(&mut self, position: Point3<f32>, forward_direction: Vector3<f32>) {
+        self.position = position;
+        self.forward_direction = forward_direction;
+        self.target = position + forward_direction;
+
+        self.yaw = forward_direction.z.atan2(forward_direction.x);
+        self.pitch = forward_direction.y.asin();
+
+        let right_direction = forward_direction.cross(Vector3::unit_y()).normalize();
+        self.up_direction = forward_direction.cross(right_direction);
+
+        self.view = Self::create_view_matrix(self.position, self.forward_direction);
+        self.view_projection = self.projection * self.view;
+    }
+
+    /// Switches between view modes without changing where the camera is looking
+    pub fn set_view_mode(&mut self, view_mode: ViewMode) {
+        if view_mode == ViewMode::Orbit && self.view_mode != ViewMode::Orbit {
+            // Orbit whatever is in front of the camera so the view doesn't jump
+            self.target = self.position + self.forward_direction * self.distance;
+        }
+
+        self.view_mode = view_mode;
+    }
+
+    pub fn toggle_view_mode(&mut self) {
+        match self.view_mode {
+            ViewMode::FPS => self.set_view_mode(ViewMode::Orbit),
+            ViewMode::Orbit => self.set_view_mode(ViewMode::FPS),
+        }
+    }
+
     pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
-        self.projection = Self::create_perspective_matrix(aspect_ratio);
+        self.aspect_ratio = aspect_ratio;
+        self.projection = Self::create_perspective_matrix(self.fov, aspect_ratio);
+        self.view_projection = self.projection * self.view;
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.aspect_ratio
+    }
+
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    /// Changes the vertical field of view, in radians, e.g. to zoom in
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = fov.clamp(0.01, std::f32::consts::PI - 0.01);
+        self.projection = Self::create_perspective_matrix(self.fov, self.aspect_ratio);
+        self.view_projection = self.projection * self.view;
+    }
+
+    /// Turns the camera by radians of yaw and pitch on top of whatever input turned it, e.g. for
+    /// recoil. Orbit cameras turn around their target.
+    pub fn turn(&mut self, yaw: f32, pitch: f32) {
+        self.rotate(Vector2::new(yaw, -pitch));
+        if self.view_mode == ViewMode::Orbit {
+            self.position = self.target - self.forward_direction * self.distance;
+        }
+
+        self.view = Self::create_view_matrix(self.position, self.forward_direction);
+        self.view_projection = self.projection * self.view;
+    }
+
+    /// The world space ray through a point on the screen, given in pixels from the top left of a
+    /// window of `window_size`. It starts on the near plane and its direction is normalized.
+    pub fn screen_ray(&self, cursor_position: Vector2<f32>, window_size: Vector2<f32>) -> Ray {
+        let x = 2.0 * cursor_position.x / window_size.x - 1.0;
+        let y = 1.0 - 2.0 * cursor_position.y / window_size.y;
+
+        let inverse = self.view_projection.invert().unwrap_or(Matrix4::identity());
+        let near = inverse.transform_point(Point3::new(x, y, -1.0));
+        let far = inverse.transform_point(Point3::new(x, y, 1.0));
+
+        Ray::new(near, (far - near).normalize())
     }
 
     fn create_view_matrix(position: Point3<f32>, forward_direction: Vector3<f32>) -> Matrix4<f32> {
@@ -81,15 +192,25 @@ impl Camera {
         )
     }
 
-    fn create_perspective_matrix(aspect_ratio: f32) -> Matrix4<f32> {
-        cgmath::perspective(Rad(std::f32::consts::FRAC_PI_2), aspect_ratio, 0.01, 100.0)
+    fn create_perspective_matrix(fov: f32, aspect_ratio: f32) -> Matrix4<f32> {
+        cgmath::perspective(Rad(fov), aspect_ratio, Self::NEAR, Self::FAR)
     }
 
-    fn update_fps(&mut self, input: &Input) {
-        let speed = 0.1;
+    fn default_orbit_distance() -> f32 {
+        Self::DEFAULT_ORBIT_DISTANCE
+    }
 
-        let offset = input.device_offset();
+    fn default_fov() -> f32 {
+        Self::DEFAULT_FOV
+    }
+
+    /// Scenes saved before the aspect ratio was kept have it set from the window once loaded
+    fn default_aspect_ratio() -> f32 {
+        16.0 / 9.0
+    }
 
+    /// Turns the camera by a mouse offset, returning the new right direction
+    fn rotate(&mut self, offset: Vector2<f32>) -> Vector3<f32> {
         self.yaw += offset.x % (2.0 * std::f32::consts::PI);
         self.pitch -= offset.y;
 
@@ -110,22 +231,58 @@ impl Camera {
         let right_direction = self.forward_direction.cross(Vector3::unit_y()).normalize();
         self.up_direction = self.forward_direction.cross(right_direction);
 
-        if input.key_down(KeyCode::KeyW) {
+        right_direction
+    }
+
+    /// Mouse or right stick to look around, the movement actions or left stick to move
+    fn update_fps(&mut self, input: &Input, deltatime: f32) {
+        let speed = Self::MOVE_SPEED * deltatime;
+
+        let look = input.stick(Stick::Right) * Self::STICK_LOOK_SPEED * deltatime;
+        let right_direction = self.rotate(input.device_offset() + Vector2::new(look.x, -look.y));
+
+        // Analog movement scales with how far the stick is pushed
+        let movement = input.stick(Stick::Left);
+        self.position +=
+            speed * (movement.y * self.forward_direction + movement.x * right_direction);
+
+        if input.action_down("move_forward") {
             self.position += speed * self.forward_direction;
         }
 
-        if input.key_down(KeyCode::KeyS) {
+        if input.action_down("move_backward") {
             self.position -= speed * self.forward_direction;
         }
 
-        if input.key_down(KeyCode::KeyA) {
+        if input.action_down("move_left") {
             self.position -= speed * right_direction;
         }
 
-        if input.key_down(KeyCode::KeyD) {
+        if input.action_down("move_right") {
             self.position += speed * right_direction;
         }
     }
+
+    /// Drag to turn around the target, drag while holding pan to pan and scroll to zoom
+    fn update_orbit(&mut self, input: &Input) {
+        let offset = input.device_offset();
+
+        if input.action_down("pan") {
+            let right_direction = self.forward_direction.cross(Vector3::unit_y()).normalize();
+            let up_direction = right_direction.cross(self.forward_direction);
+
+            self.target += (up_direction * offset.y - right_direction * offset.x)
+                * self.distance
+                * Self::PAN_SPEED;
+        } else {
+            self.rotate(offset);
+        }
+
+        self.distance = (self.distance * (1.0 - Self::ZOOM_SPEED).powf(input.scroll_delta()))
+            .max(Self::MIN_ORBIT_DISTANCE);
+
+        self.position = self.target - self.forward_direction * self.distance;
+    }
 }
 
 impl Default for Camera {
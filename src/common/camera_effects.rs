@@ -0,0 +1,178 @@
+use cgmath::{InnerSpace, Vector2};
+
+use crate::camera::Camera;
+
+/// How quickly the field of view closes in on where it's headed, per second
+const FOV_SPEED: f32 = 12.0;
+
+/// Trauma lost per second, so a full shake dies down in about a second
+const TRAUMA_DECAY: f32 = 1.2;
+
+/// Radians of yaw and pitch at full trauma
+const MAX_SHAKE_ANGLE: f32 = 0.06;
+
+/// World units the view moves at full trauma
+const MAX_SHAKE_OFFSET: f32 = 0.08;
+
+/// How fast the shake wobbles
+const SHAKE_FREQUENCY: f32 = 18.0;
+
+/// Fraction of what's left of a recoil kick recovered per second
+const RECOIL_RECOVERY: f32 = 8.0;
+
+/// Feedback layered on top of a camera: easing its field of view to zoom in, shaking the view
+/// from trauma that builds up with explosions and hits, and kicking it up with recoil that
+/// recovers over time. Zoom and recoil change the camera itself, shake only the copy it's drawn
+/// from, so none of it gets in the way of where the player is aiming.
+pub struct CameraEffects {
+    /// Field of view in degrees when not zoomed in
+    pub fov: f32,
+    /// Fraction of `fov` seen while aiming down sights
+    pub zoom: f32,
+    /// Scales how much the view shakes, zero turning it off
+    pub shake: f32,
+    aiming: bool,
+    /// Radians the camera is currently seeing, easing towards the target
+    current_fov: f32,
+    /// From zero to one, shaking the view by its square so small knocks barely register
+    trauma: f32,
+    time: f32,
+    /// Yaw and pitch still to be recovered from recoil
+    recoil: Vector2<f32>,
+    /// Where effects last left the camera, so turning it any other way is known to be the player
+    last_rotation: Option<Vector2<f32>>,
+}
+
+impl Default for CameraEffects {
+    fn default() -> Self {
+        Self {
+            fov: Camera::DEFAULT_FOV.to_degrees(),
+            zoom: 0.6,
+            shake: 1.0,
+            aiming: false,
+            current_fov: Camera::DEFAULT_FOV,
+            trauma: 0.0,
+            time: 0.0,
+            recoil: Vector2::new(0.0, 0.0),
+            last_rotation: None,
+        }
+    }
+}
+
+impl CameraEffects {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Zooms in while `aiming`, back out when not
+    pub fn aim(&mut self, aiming: bool) {
+        self.aiming = aiming;
+    }
+
+    pub fn aiming(&self) -> bool {
+        self.aiming
+    }
+
+    /// Shakes the view harder, e.g. `0.2` for taking a hit and `1.0` for an explosion up close
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Kicks the camera by radians of pitch, and yaw to the right, at once, recovering over time
+    pub fn kick(&mut self, camera: &mut Camera, pitch: f32, yaw: f32) {
+        self.track_player(camera);
+
+        let before = Vector2::new(camera.yaw, camera.pitch);
+        camera.turn(yaw, pitch);
+        // Pitch stops at straight up, and so should what's recovered
+        self.recoil += Vector2::new(camera.yaw, camera.pitch) - before;
+        self.last_rotation = Some(Vector2::new(camera.yaw, camera.pitch));
+    }
+
+    /// Eases the field of view, lets trauma die down and recovers recoil
+    pub fn update(&mut self, camera: &mut Camera, deltatime: f32) {
+        let target_fov = self.fov.to_radians() * if self.aiming { self.zoom } else { 1.0 };
+        self.current_fov += (target_fov - self.current_fov) * approach(FOV_SPEED, deltatime);
+        if (camera.fov() - self.current_fov).abs() > f32::EPSILON {
+            camera.set_fov(self.current_fov);
+        }
+
+        self.trauma = (self.trauma - TRAUMA_DECAY * deltatime).max(0.0);
+        self.time += deltatime * SHAKE_FREQUENCY;
+
+        self.track_player(camera);
+        if self.recoil.magnitude2() > 0.0 {
+            let recovered = self.recoil * approach(RECOIL_RECOVERY, deltatime);
+            camera.turn(-recovered.x, -recovered.y);
+            self.recoil -= recovered;
+        }
+        self.last_rotation = Some(Vector2::new(camera.yaw, camera.pitch));
+    }
+
+    /// A copy of `camera` shaken by the current trauma, to draw the scene from
+    pub fn shaken(&self, camera: &Camera) -> Camera {
+        let mut shaken = camera.clone();
+        let shake = self.trauma * self.trauma * self.shake;
+        if shake <= 0.0 {
+            return shaken;
+        }
+
+        shaken.turn(
+            MAX_SHAKE_ANGLE * shake * noise(self.time, 0.0),
+            MAX_SHAKE_ANGLE * shake * noise(self.time, 1.0),
+        );
+
+        let right = shaken
+            .forward_direction
+            .cross(shaken.up_direction)
+            .normalize();
+        let offset = right * noise(self.time, 2.0) + shaken.up_direction * noise(self.time, 3.0);
+        let position = shaken.position + offset * MAX_SHAKE_OFFSET * shake;
+        let forward_direction = shaken.forward_direction;
+        shaken.look_at(position, forward_direction);
+
+        shaken
+    }
+
+    /// Forgets whatever recoil the player has already pulled against, so recovering never drags
+    /// the aim past where they put it
+    fn track_player(&mut self, camera: &Camera) {
+        let Some(last_rotation) = self.last_rotation else {
+            return;
+        };
+
+        let turned = Vector2::new(camera.yaw, camera.pitch) - last_rotation;
+        self.recoil.x = cancel(self.recoil.x, turned.x);
+        self.recoil.y = cancel(self.recoil.y, turned.y);
+    }
+}
+
+/// Fraction of the way to close in `deltatime` when closing `rate` of the gap per second,
+/// independent of frame rate
+fn approach(rate: f32, deltatime: f32) -> f32 {
+    1.0 - (-rate * deltatime).exp()
+}
+
+/// Takes a turn against `recoil` off it, never past zero. Turns the same way are left alone.
+fn cancel(recoil: f32, turned: f32) -> f32 {
+    if recoil * turned >= 0.0 {
+        return recoil;
+    }
+
+    let left = recoil.abs() - turned.abs();
+    left.max(0.0).copysign(recoil)
+}
+
+/// Smooth noise between -1 and 1, different for each `seed`
+fn noise(time: f32, seed: f32) -> f32 {
+    let offset = seed * 17.31;
+
+    ((time + offset).sin()
+        + 0.5 * (2.3 * time + 1.7 * offset).sin()
+        + 0.25 * (4.1 * time + 2.9 * offset).sin())
+        / 1.75
+}
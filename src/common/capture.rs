@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+
+use color_eyre::Result;
+use glium::glutin::surface::WindowSurface;
+use glium::texture::pixel_buffer::PixelBuffer;
+use glium::texture::RawImage2d;
+use glium::uniforms::MagnifySamplerFilter;
+use glium::{Display, Frame, Surface, Texture2d};
+use image::RgbaImage;
+use log::{info, warn};
+
+/// Where screenshots and recordings are saved
+pub const CAPTURE_DIRECTORY: &str = "captures";
+
+/// Frames a readback is left in flight before its pixels are read, by which point the GPU has
+/// almost always finished copying them so reading doesn't stall
+const READBACK_DELAY: usize = 2;
+
+/// A frame being copied into a pixel buffer
+struct Readback {
+    pixels: PixelBuffer<(u8, u8, u8, u8)>,
+    dimensions: (u32, u32),
+    path: PathBuf,
+    age: usize,
+}
+
+/// Saves the default framebuffer as PNGs, either once for a screenshot or every frame while
+/// recording. Frames are copied into pixel buffers and read a few frames later, then encoded on
+/// another thread, so capturing doesn't hold up rendering.
+pub struct Capture {
+    screenshot_requested: bool,
+    /// Folder frames are saved to while recording
+    recording: Option<PathBuf>,
+    recorded_frames: usize,
+    /// Copy of the back buffer the pixel buffers are filled from, resized with the window
+    texture: Option<Texture2d>,
+    pending: VecDeque<Readback>,
+    saver: Sender<(PathBuf, RgbaImage)>,
+}
+
+impl Capture {
+    pub fn new() -> Self {
+        let (saver, images) = mpsc::channel::<(PathBuf, RgbaImage)>();
+
+        std::thread::spawn(move || {
+            for (path, image) in images {
+                if let Err(error) = image.save(&path) {
+                    warn!("Failed to save {:?}: {}", path, error);
+                }
+            }
+        });
+
+        Self {
+            screenshot_requested: false,
+            recording: None,
+            recorded_frames: 0,
+            texture: None,
+            pending: VecDeque::new(),
+            saver,
+        }
+    }
+
+    /// Saves the next frame as a timestamped PNG
+    pub fn take_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Starts saving every frame to a new folder, or stops if already recording
+    pub fn toggle_recording(&mut self) {
+        match self.recording.take() {
+            Some(directory) => {
+                info!(
+                    "Recorded {} frames to {:?}",
+                    self.recorded_frames, directory
+                );
+            }
+            None => {
+                self.recording =
+                    Some(Path::new(CAPTURE_DIRECTORY).join(format!("recording_{}", timestamp())));
+                self.recorded_frames = 0;
+            }
+        }
+    }
+
+    /// Copies the frame if it's being captured and saves those copied earlier that are ready.
+    /// Called once everything has been drawn, before the frame is finished.
+    pub fn capture(&mut self, display: &Display<WindowSurface>, target: &Frame) -> Result<()> {
+        for readback in self.pending.iter_mut() {
+            readback.age += 1;
+        }
+
+        while self
+            .pending
+            .front()
+            .is_some_and(|readback| readback.age >= READBACK_DELAY)
+        {
+            let readback = self.pending.pop_front().unwrap();
+            self.save(readback)?;
+        }
+
+        let path = if let Some(directory) = &self.recording {
+            if self.recorded_frames == 0 {
+                std::fs::create_dir_all(directory)?;
+            }
+            self.recorded_frames += 1;
+            directory.join(format!("frame_{:05}.png", self.recorded_frames))
+        } else if self.screenshot_requested {
+            std::fs::create_dir_all(CAPTURE_DIRECTORY)?;
+            let path = Path::new(CAPTURE_DIRECTORY).join(format!("screenshot_{}.png", timestamp()));
+            info!("Saving screenshot to {:?}", path);
+            path
+        } else {
+            return Ok(());
+        };
+        self.screenshot_requested = false;
+
+        let dimensions = target.get_dimensions();
+        if self
+            .texture
+            .as_ref()
+            .map_or(true, |texture| texture.dimensions() != dimensions)
+        {
+            self.texture = Some(Texture2d::empty(display, dimensions.0, dimensions.1)?);
+        }
+
+        let texture = self.texture.as_ref().unwrap();
+        target.fill(&texture.as_surface(), MagnifySamplerFilter::Nearest);
+
+        self.pending.push_back(Readback {
+            pixels: texture.read_to_pixel_buffer(),
+            dimensions,
+            path,
+            age: 0,
+        });
+
+        Ok(())
+    }
+
+    fn save(&self, readback: Readback) -> Result<()> {
+        let raw: RawImage2d<u8> = readback.pixels.read_as_texture_2d()?;
+        let Some(image) = RgbaImage::from_raw(
+            readback.dimensions.0,
+            readback.dimensions.1,
+            raw.data.into_owned(),
+        ) else {
+            warn!("Readback for {:?} was the wrong size", readback.path);
+            return Ok(());
+        };
+
+        // OpenGL's rows start at the bottom
+        let image = image::imageops::flip_vertical(&image);
+
+        self.saver.send((readback.path, image))?;
+
+        Ok(())
+    }
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Local time to the millisecond, so captures sort by when they were taken
+fn timestamp() -> String {
+    chrono::offset::Local::now()
+        .format("%Y-%m-%d_%H-%M-%S%.3f")
+        .to_string()
+}
@@ -0,0 +1,194 @@
+use std::collections::HashSet;
+
+use cgmath::{EuclideanSpace, Point3};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::entity::World;
+use crate::maths::Aabb;
+use crate::model::Transform;
+use crate::scene::Scene;
+use crate::uuid::UUID;
+
+pub const LAYER_DEFAULT: u32 = 1;
+pub const LAYER_PLAYER: u32 = 1 << 1;
+pub const LAYER_ENEMY: u32 = 1 << 2;
+pub const LAYER_PROJECTILE: u32 = 1 << 3;
+pub const LAYER_PICKUP: u32 = 1 << 4;
+/// Bones of ragdolls, which pass through each other
+pub const LAYER_RAGDOLL: u32 = 1 << 5;
+pub const LAYER_ALL: u32 = u32::MAX;
+
+/// Component choosing what its entity is hit by and triggers, with a bit per layer. Two things
+/// interact when each is in a layer the other's filter lets through, as in rapier. Entities
+/// without one are in the default layer and interact with everything.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollisionLayers {
+    /// Layers the entity is in
+    pub memberships: u32,
+    /// Layers the entity interacts with
+    pub filter: u32,
+}
+
+impl Default for CollisionLayers {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl CollisionLayers {
+    pub const DEFAULT: Self = Self::new(LAYER_DEFAULT, LAYER_ALL);
+    pub const PLAYER: Self = Self::new(LAYER_PLAYER, LAYER_ALL);
+    pub const ENEMY: Self = Self::new(LAYER_ENEMY, LAYER_ALL);
+    /// Shots and sight lines, which pass through pickups and each other
+    pub const PROJECTILE: Self = Self::new(
+        LAYER_PROJECTILE,
+        LAYER_ALL & !LAYER_PICKUP & !LAYER_PROJECTILE,
+    );
+    /// Only noticed by players, so shots pass through them
+    pub const PICKUP: Self = Self::new(LAYER_PICKUP, LAYER_PLAYER);
+
+    pub const fn new(memberships: u32, filter: u32) -> Self {
+        Self {
+            memberships,
+            filter,
+        }
+    }
+
+    /// The layers of a ragdoll's bones, which collide with what the entity would except other
+    /// bones, so a ragdoll's limbs don't catch on each other
+    pub const fn ragdoll(self) -> Self {
+        Self::new(LAYER_RAGDOLL, self.filter & !LAYER_RAGDOLL)
+    }
+
+    pub fn interacts_with(&self, other: &CollisionLayers) -> bool {
+        self.memberships & other.filter != 0 && other.memberships & self.filter != 0
+    }
+
+    /// An entity's layers, the default if it has none
+    pub fn of(world: &World, entity: UUID) -> Self {
+        world
+            .get::<CollisionLayers>(entity)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Component for volumes noticing when things go in and out of them, sending `TriggerEntered`
+/// and `TriggerExited` through the scene's events. Model instances are inside once their bounds
+/// overlap it, other entities with `CollisionLayers` once their origin is in it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trigger {
+    /// In the entity's space, so it moves along with it
+    pub bounds: Aabb,
+    /// What it notices, by the same rules things collide by
+    pub layers: CollisionLayers,
+    /// What is inside, as of the last update. `None` for the player. Saves keep it apart, as
+    /// each occupant has to be in the world it's put back into.
+    #[serde(skip)]
+    occupants: HashSet<Option<UUID>>,
+}
+
+impl Trigger {
+    pub fn new(bounds: Aabb) -> Self {
+        Self {
+            bounds,
+            layers: CollisionLayers::DEFAULT,
+            occupants: HashSet::new(),
+        }
+    }
+
+    /// What is inside, as of the last update. `None` for the player.
+    pub fn occupants(&self) -> impl Iterator<Item = Option<UUID>> + '_ {
+        self.occupants.iter().copied()
+    }
+
+    /// Replaces what is inside without sending events, e.g. when loading a save
+    pub fn set_occupants(&mut self, occupants: impl IntoIterator<Item = Option<UUID>>) {
+        self.occupants = occupants.into_iter().collect();
+    }
+}
+
+/// Something going into a trigger, sent through the scene's events
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TriggerEntered {
+    pub trigger: UUID,
+    /// `None` for the player
+    pub entity: Option<UUID>,
+}
+
+/// Something leaving a trigger or being despawned inside it, sent through the scene's events
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TriggerExited {
+    pub trigger: UUID,
+    /// `None` for the player
+    pub entity: Option<UUID>,
+}
+
+/// Finds what is inside each trigger, sending events for whatever went in or came out since the
+/// last update. `player` is where whoever is playing is, or `None` while they're dead. Called
+/// each fixed step after things have moved and the scene's bounds have been updated.
+pub fn update_triggers(scene: &mut Scene, player: Option<Point3<f32>>) {
+    let simulation = &scene.simulation;
+    let world = &simulation.world;
+
+    // Entities that aren't model instances are only tested by their origin
+    let points = world
+        .query2::<CollisionLayers, Transform>()
+        .map(|(entity, layers, transform)| {
+            (entity, *layers, Point3::from_vec(transform.translation))
+        })
+        .collect_vec();
+
+    let triggers = world
+        .query::<Trigger>()
+        .filter_map(|(trigger, trigger_info)| {
+            let bounds = trigger_info
+                .bounds
+                .transformed(simulation.global_matrix(trigger)?);
+            let layers = trigger_info.layers;
+
+            let mut inside = simulation
+                .overlapping(&bounds)
+                .into_iter()
+                .filter(|&entity| entity != trigger)
+                .filter(|&entity| layers.interacts_with(&CollisionLayers::of(world, entity)))
+                .map(Some)
+                .collect::<HashSet<_>>();
+            inside.extend(
+                points
+                    .iter()
+                    .filter(|&&(entity, other, point)| {
+                        entity != trigger && layers.interacts_with(&other) && bounds.contains(point)
+                    })
+                    .map(|&(entity, _, _)| Some(entity)),
+            );
+            if player.is_some_and(|player| {
+                layers.interacts_with(&CollisionLayers::PLAYER) && bounds.contains(player)
+            }) {
+                inside.insert(None);
+            }
+
+            Some((trigger, inside))
+        })
+        .collect_vec();
+
+    for (trigger, inside) in triggers {
+        let occupants = &mut scene
+            .simulation
+            .world
+            .get_mut::<Trigger>(trigger)
+            .unwrap()
+            .occupants;
+        let entered = inside.difference(occupants).copied().collect_vec();
+        let exited = occupants.difference(&inside).copied().collect_vec();
+        *occupants = inside;
+
+        for entity in entered {
+            scene.events.send(TriggerEntered { trigger, entity });
+        }
+        for entity in exited {
+            scene.events.send(TriggerExited { trigger, entity });
+        }
+    }
+}
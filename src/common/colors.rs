@@ -3,6 +3,14 @@ use palette::{FromColor, IntoColor, Lch, ShiftHue, Srgb};
 
 type Color = Lch;
 
+pub const WHITE: Srgb = Srgb::new(1.0, 1.0, 1.0);
+pub const RED: Srgb = Srgb::new(1.0, 0.0, 0.0);
+pub const GREEN: Srgb = Srgb::new(0.0, 1.0, 0.0);
+pub const BLUE: Srgb = Srgb::new(0.0, 0.0, 1.0);
+pub const YELLOW: Srgb = Srgb::new(1.0, 1.0, 0.0);
+pub const CYAN: Srgb = Srgb::new(0.0, 1.0, 1.0);
+pub const MAGENTA: Srgb = Srgb::new(1.0, 0.0, 1.0);
+
 pub fn shift_hue(color: &Lch, time: f32) -> Color {
     let shift = time % 360.0;
     color.shift_hue(shift)
@@ -17,3 +25,34 @@ pub fn to_vector4(color: Color) -> Vector4<f32> {
 
     Vector4::new(rgb.red as f32, rgb.green as f32, rgb.blue as f32, 1.0)
 }
+
+/// Approximates the colour of a black body at a temperature in Kelvin, from 1000K to 40000K
+pub fn from_temperature(kelvin: f32) -> Srgb {
+    let temperature = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+    let red = if temperature <= 66.0 {
+        255.0
+    } else {
+        329.69873 * (temperature - 60.0).powf(-0.13320476)
+    };
+
+    let green = if temperature <= 66.0 {
+        99.4708 * temperature.ln() - 161.11957
+    } else {
+        288.12216 * (temperature - 60.0).powf(-0.07551485)
+    };
+
+    let blue = if temperature >= 66.0 {
+        255.0
+    } else if temperature <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (temperature - 10.0).ln() - 305.0448
+    };
+
+    Srgb::new(
+        red.clamp(0.0, 255.0) / 255.0,
+        green.clamp(0.0, 255.0) / 255.0,
+        blue.clamp(0.0, 255.0) / 255.0,
+    )
+}
@@ -0,0 +1,194 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::ASSET_NAMES_PATH;
+use crate::backend::BackendKind;
+use crate::context::SHADER_DIRECTORY;
+use crate::debug::LogConfig;
+use crate::input::Input;
+use crate::settings::GRAPHICS_SETTINGS_PATH;
+
+/// Startup options, falling back to the defaults if missing
+pub const CONFIG_PATH: &str = "config.toml";
+
+/// Key bindings, falling back to the defaults if missing
+pub const BINDINGS_PATH: &str = "assets/config/bindings.toml";
+
+/// How the window is shown
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    /// Covering a monitor without changing its video mode
+    Borderless,
+    /// Taking over a monitor at a chosen resolution
+    Exclusive,
+}
+
+impl DisplayMode {
+    pub const ALL: [DisplayMode; 3] = [Self::Windowed, Self::Borderless, Self::Exclusive];
+}
+
+/// How the app starts up, read from `config.toml` with command-line options applied on top
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub title: String,
+    /// Size of the window in logical pixels when it isn't maximised
+    pub width: u32,
+    pub height: u32,
+    pub maximized: bool,
+    pub display_mode: DisplayMode,
+    /// Name of the monitor to go fullscreen on, the primary one if left out or not connected
+    pub monitor: Option<String>,
+    /// Width and height exclusive fullscreen switches the monitor to, its largest if left out
+    pub resolution: Option<(u32, u32)>,
+    /// Graphics API the game draws with. The editor always draws with OpenGL.
+    pub backend: BackendKind,
+    /// Loaded at startup instead of an empty scene
+    pub scene: Option<PathBuf>,
+    /// Watched for changes so shaders can be edited while running
+    pub shader_directory: PathBuf,
+    /// Logical names for assets, see `Assets::load_names`
+    pub asset_names: PathBuf,
+    pub bindings: PathBuf,
+    pub graphics_settings: PathBuf,
+    /// Radians turned per pixel the mouse moves
+    pub mouse_sensitivity: f64,
+    /// Which messages are logged, by module
+    pub log: LogConfig,
+    /// Where the session's input is saved on exit, only set from the command line
+    #[serde(skip)]
+    pub record: Option<PathBuf>,
+    /// Input played back instead of reading the devices, only set from the command line
+    #[serde(skip)]
+    pub replay: Option<PathBuf>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            title: "Shooter Game".to_owned(),
+            width: 1280,
+            height: 720,
+            maximized: true,
+            display_mode: DisplayMode::default(),
+            monitor: None,
+            resolution: None,
+            backend: BackendKind::default(),
+            scene: None,
+            shader_directory: PathBuf::from(SHADER_DIRECTORY),
+            asset_names: PathBuf::from(ASSET_NAMES_PATH),
+            bindings: PathBuf::from(BINDINGS_PATH),
+            graphics_settings: PathBuf::from(GRAPHICS_SETTINGS_PATH),
+            mouse_sensitivity: Input::DEFAULT_CURSOR_SENSITIVITY,
+            log: LogConfig::default(),
+            record: None,
+            replay: None,
+        }
+    }
+}
+
+/// Command-line options overriding the config file, flattened into each binary's arguments
+#[derive(Clone, Debug, Default, clap::Args)]
+pub struct ConfigArgs {
+    /// Config file to read instead of config.toml
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Window width in logical pixels, stops the window starting maximised
+    #[arg(long)]
+    pub width: Option<u32>,
+    /// Window height in logical pixels, stops the window starting maximised
+    #[arg(long)]
+    pub height: Option<u32>,
+    /// Shorthand for `--display-mode borderless`
+    #[arg(long, conflicts_with = "display_mode")]
+    pub fullscreen: bool,
+    #[arg(long, value_enum)]
+    pub display_mode: Option<DisplayMode>,
+    /// Name of the monitor to go fullscreen on
+    #[arg(long, value_name = "NAME")]
+    pub monitor: Option<String>,
+    #[arg(long, value_enum)]
+    pub backend: Option<BackendKind>,
+    /// Scene to load at startup
+    #[arg(long, value_name = "PATH")]
+    pub scene: Option<PathBuf>,
+    /// Records input until exit and saves it to a file, starting once the scene has loaded
+    #[arg(long, value_name = "PATH", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// Plays back recorded input, loading the scene it was recorded in
+    #[arg(long, value_name = "PATH")]
+    pub replay: Option<PathBuf>,
+}
+
+impl ConfigArgs {
+    /// Where the config is read from, and saved to when changed while running
+    pub fn path(&self) -> &Path {
+        self.config.as_deref().unwrap_or(Path::new(CONFIG_PATH))
+    }
+}
+
+impl AppConfig {
+    /// Reads a config from a TOML file, keeping the defaults of any options left out
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// Reads the config file chosen by `args`, falling back to the defaults if it can't be read,
+    /// then applies the rest of the options
+    pub fn from_args(args: &ConfigArgs) -> Self {
+        let path = args.path();
+
+        let mut config = Self::load(path).unwrap_or_else(|error| {
+            // A missing default config is expected, one asked for should be there
+            if args.config.is_some() || path.exists() {
+                warn!(
+                    "Using the default config, could not load {:?}: {}",
+                    path, error
+                );
+            }
+            Self::default()
+        });
+        config.apply(args);
+
+        config
+    }
+
+    pub fn apply(&mut self, args: &ConfigArgs) {
+        if let Some(width) = args.width {
+            self.width = width;
+            self.maximized = false;
+        }
+        if let Some(height) = args.height {
+            self.height = height;
+            self.maximized = false;
+        }
+        if args.fullscreen {
+            self.display_mode = DisplayMode::Borderless;
+        }
+        if let Some(display_mode) = args.display_mode {
+            self.display_mode = display_mode;
+        }
+        if let Some(monitor) = &args.monitor {
+            self.monitor = Some(monitor.clone());
+        }
+        if let Some(backend) = args.backend {
+            self.backend = backend;
+        }
+        if let Some(scene) = &args.scene {
+            self.scene = Some(scene.clone());
+        }
+        self.record.clone_from(&args.record);
+        self.replay.clone_from(&args.replay);
+    }
+}
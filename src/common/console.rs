@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::str::FromStr;
+
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::Result;
+use itertools::Itertools;
+
+/// Lines of output kept, oldest dropped first
+const MAX_OUTPUT: usize = 256;
+
+/// Lines of input kept to go back through
+const MAX_HISTORY: usize = 64;
+
+/// Built into every console, ahead of whatever is registered
+const BUILTINS: [(&str, &str); 3] = [
+    (
+        "help",
+        "Lists commands and variables, or describes the one named",
+    ),
+    ("clear", "Clears the output"),
+    ("history", "Lists what was entered, oldest first"),
+];
+
+type CommandFn<C> = Box<dyn Fn(&mut C, &[&str]) -> Result<String>>;
+
+/// Reads a variable's value when given `None`, or parses and sets it when given text, either way
+/// returning what it is afterwards
+type CvarFn<C> = Box<dyn Fn(&mut C, Option<&str>) -> Result<String>>;
+
+struct Command<C> {
+    help: String,
+    run: CommandFn<C>,
+}
+
+struct Cvar<C> {
+    help: String,
+    access: CvarFn<C>,
+}
+
+/// Values a console variable can hold, shown and parsed the way they'd be typed
+pub trait CvarValue: Sized + 'static {
+    fn parse(text: &str) -> Result<Self>;
+    fn show(&self) -> String;
+}
+
+impl CvarValue for bool {
+    fn parse(text: &str) -> Result<Self> {
+        match text.to_ascii_lowercase().as_str() {
+            "1" | "true" | "on" | "yes" => Ok(true),
+            "0" | "false" | "off" | "no" => Ok(false),
+            _ => bail!("Expected true or false, got {:?}", text),
+        }
+    }
+
+    fn show(&self) -> String {
+        self.to_string()
+    }
+}
+
+macro_rules! impl_cvar_value {
+    ($($value:ty),*) => {$(
+        impl CvarValue for $value {
+            fn parse(text: &str) -> Result<Self> {
+                <$value>::from_str(text).map_err(|error| eyre!("{:?}: {}", text, error))
+            }
+
+            fn show(&self) -> String {
+                self.to_string()
+            }
+        }
+    )*};
+}
+
+impl_cvar_value!(f32, f64, i32, u32, u64, usize, String);
+
+/// Parses the argument at `index` given to a command, as a console variable would be
+pub fn argument<T: CvarValue>(arguments: &[&str], index: usize) -> Result<T> {
+    let Some(argument) = arguments.get(index) else {
+        bail!("Expected at least {} arguments", index + 1);
+    };
+
+    T::parse(argument)
+}
+
+/// Drop-down console running commands and tweaking variables on `C`, e.g. the app. Commands and
+/// variables are registered by whichever modules own what they change, and the console is taken
+/// out of `C` while it runs them.
+pub struct Console<C> {
+    pub open: bool,
+    /// What's being typed, run on `submit`
+    pub input: String,
+    output: VecDeque<String>,
+    /// Oldest first, without repeats in a row
+    history: Vec<String>,
+    /// Position in `history` while going back through it
+    browsing: Option<usize>,
+    commands: BTreeMap<String, Command<C>>,
+    cvars: BTreeMap<String, Cvar<C>>,
+}
+
+impl<C> Default for Console<C> {
+    fn default() -> Self {
+        Self {
+            open: false,
+            input: String::new(),
+            output: VecDeque::new(),
+            history: vec![],
+            browsing: None,
+            commands: BTreeMap::new(),
+            cvars: BTreeMap::new(),
+        }
+    }
+}
+
+impl<C> Console<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    /// Adds a command run with the words typed after its name, whose output is printed
+    pub fn register_command(
+        &mut self,
+        name: &str,
+        help: &str,
+        run: impl Fn(&mut C, &[&str]) -> Result<String> + 'static,
+    ) {
+        self.commands.insert(
+            name.to_owned(),
+            Command {
+                help: help.to_owned(),
+                run: Box::new(run),
+            },
+        );
+    }
+
+    /// Adds a variable that's shown by typing its name and set by typing a value after it.
+    /// `field` finds it in `C` each time, so it's never out of date.
+    pub fn register_cvar<T: CvarValue>(
+        &mut self,
+        name: &str,
+        help: &str,
+        field: impl Fn(&mut C) -> &mut T + 'static,
+    ) {
+        let access = move |context: &mut C, text: Option<&str>| {
+            let value = field(context);
+            if let Some(text) = text {
+                *value = T::parse(text)?;
+            }
+
+            Ok(value.show())
+        };
+
+        self.cvars.insert(
+            name.to_owned(),
+            Cvar {
+                help: help.to_owned(),
+                access: Box::new(access),
+            },
+        );
+    }
+
+    pub fn print(&mut self, line: impl Into<String>) {
+        if self.output.len() >= MAX_OUTPUT {
+            self.output.pop_front();
+        }
+        self.output.push_back(line.into());
+    }
+
+    /// Oldest first
+    pub fn output(&self) -> impl Iterator<Item = &str> {
+        self.output.iter().map(String::as_str)
+    }
+
+    /// Runs what's been typed, remembering it in the history
+    pub fn submit(&mut self, context: &mut C) {
+        let line = std::mem::take(&mut self.input).trim().to_owned();
+        self.browsing = None;
+        if line.is_empty() {
+            return;
+        }
+
+        if self.history.last() != Some(&line) {
+            if self.history.len() >= MAX_HISTORY {
+                self.history.remove(0);
+            }
+            self.history.push(line.clone());
+        }
+
+        self.execute(context, &line);
+    }
+
+    /// Runs a line as though it were typed, printing it and whatever it outputs
+    pub fn execute(&mut self, context: &mut C, line: &str) {
+        self.print(format!("> {}", line));
+
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return;
+        };
+        let arguments = words.collect_vec();
+
+        let result = match name {
+            "help" => Ok(self.help(arguments.first().copied())),
+            "clear" => {
+                self.output.clear();
+                Ok(String::new())
+            }
+            "history" => Ok(self.history.iter().join("\n")),
+            _ => match (self.commands.get(name), self.cvars.get(name)) {
+                (Some(command), _) => (command.run)(context, &arguments),
+                (None, Some(cvar)) => {
+                    let text = (!arguments.is_empty()).then(|| arguments.join(" "));
+                    (cvar.access)(context, text.as_deref())
+                        .map(|value| format!("{} = {}", name, value))
+                }
+                (None, None) => Err(eyre!("Unknown command or variable {:?}", name)),
+            },
+        };
+
+        match result {
+            Ok(output) => {
+                for line in output.lines() {
+                    self.print(line);
+                }
+            }
+            Err(error) => self.print(format!("Error: {}", error)),
+        }
+    }
+
+    /// Completes the name being typed as far as every match agrees, printing the matches if
+    /// there's more than one
+    pub fn complete(&mut self) {
+        if self.input.contains(char::is_whitespace) {
+            return;
+        }
+
+        let matches = self
+            .names()
+            .filter(|name| name.starts_with(self.input.as_str()))
+            .map(str::to_owned)
+            .collect_vec();
+
+        match matches.as_slice() {
+            [] => (),
+            [only] => self.input = format!("{} ", only),
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.as_str(), |common, name| {
+                    let length = common
+                        .bytes()
+                        .zip(name.bytes())
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    &common[..length]
+                });
+                self.input = common.to_owned();
+                self.print(matches.join("  "));
+            }
+        }
+    }
+
+    /// Replaces the input with the line entered before the one shown, starting from the last
+    pub fn history_previous(&mut self) {
+        let index = match self.browsing {
+            Some(index) => index.saturating_sub(1),
+            None if self.history.is_empty() => return,
+            None => self.history.len() - 1,
+        };
+
+        self.browsing = Some(index);
+        self.input = self.history[index].clone();
+    }
+
+    /// Replaces the input with the line entered after the one shown, back to empty past the last
+    pub fn history_next(&mut self) {
+        let Some(index) = self.browsing else {
+            return;
+        };
+
+        if index + 1 < self.history.len() {
+            self.browsing = Some(index + 1);
+            self.input = self.history[index + 1].clone();
+        } else {
+            self.browsing = None;
+            self.input.clear();
+        }
+    }
+
+    /// Every command and variable, builtins first and then in order
+    fn names(&self) -> impl Iterator<Item = &str> {
+        BUILTINS
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(self.commands.keys().map(String::as_str))
+            .chain(self.cvars.keys().map(String::as_str))
+    }
+
+    fn help(&self, name: Option<&str>) -> String {
+        let builtins = BUILTINS
+            .iter()
+            .map(|(name, help)| (name.to_string(), help.to_string()));
+        let commands = self
+            .commands
+            .iter()
+            .map(|(name, command)| (name.clone(), command.help.clone()));
+        let cvars = self
+            .cvars
+            .iter()
+            .map(|(name, cvar)| (format!("{} <value>", name), cvar.help.clone()));
+
+        let entries = builtins.chain(commands).chain(cvars);
+        match name {
+            Some(name) => entries
+                .filter(|(entry, _)| entry.split_whitespace().next() == Some(name))
+                .map(|(entry, help)| format!("{}: {}", entry, help))
+                .next()
+                .unwrap_or_else(|| format!("Unknown command or variable {:?}", name)),
+            None => entries
+                .map(|(entry, help)| format!("{}: {}", entry, help))
+                .join("\n"),
+        }
+    }
+}
@@ -1,45 +1,220 @@
 use std::fs;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
 
+use color_eyre::eyre::eyre;
 use color_eyre::Result;
-use glium::backend::glutin::SimpleWindowBuilder;
-use glium::glutin::surface::WindowSurface;
-use glium::{Display, Program};
-use winit::dpi::LogicalPosition;
+use glium::framebuffer::MultiOutputFrameBuffer;
+use glium::glutin::config::{ConfigTemplateBuilder, GlConfig};
+use glium::glutin::context::{ContextAttributesBuilder, NotCurrentGlContext};
+use glium::glutin::display::{GetGlDisplay, GlDisplay};
+use glium::glutin::surface::{GlSurface, SurfaceAttributesBuilder, SwapInterval, WindowSurface};
+use glium::texture::{DepthFormat, DepthTexture2d, MipmapsOption, UncompressedFloatFormat};
+use glium::{Display, Program, Texture2d};
+use glutin_winit::DisplayBuilder;
+use itertools::Itertools;
+use log::warn;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use raw_window_handle::HasRawWindowHandle;
+use winit::dpi::{LogicalPosition, LogicalSize, PhysicalSize};
 use winit::event_loop::EventLoop;
+use winit::monitor::{MonitorHandle, VideoMode};
 use winit::window::{CursorGrabMode, Fullscreen, Window, WindowBuilder};
 
+use crate::backend::Backend;
+use crate::config::{AppConfig, DisplayMode};
+use crate::crash;
+use crate::profiling;
+use crate::settings::GraphicsSettings;
+
+/// Where shaders are watched for changes unless configured otherwise
+pub const SHADER_DIRECTORY: &str = "assets/shaders";
+
+/// Where the files named by `#include "..."` lines in shaders are
+pub const SHADER_INCLUDE_DIRECTORY: &str = "assets/shaders/include";
+
 #[derive(Debug)]
 pub struct OpenGLContext {
-    pub window: Window,
+    /// Shared with the panic hook, which releases the cursor and hides it
+    pub window: Arc<Window>,
     pub display: Display<WindowSurface>,
+    /// `None` if the shader directory couldn't be watched
+    pub shader_watcher: Option<FileWatcher>,
+    cursor_captured: bool,
 }
 
 impl OpenGLContext {
-    pub fn new(title: &str, fullscreen: bool, event_loop: &EventLoop<()>) -> Self {
-        let mut window_builder = WindowBuilder::new().with_title(title);
+    pub fn new(
+        config: &AppConfig,
+        settings: &GraphicsSettings,
+        event_loop: &EventLoop<()>,
+    ) -> Self {
+        let window_builder = window_builder(config, event_loop);
+        let (window, display) = Self::build_display(window_builder, settings, event_loop);
+        let window = Arc::new(window);
+        crash::watch_window(&window);
+
+        let shader_watcher = FileWatcher::new(&config.shader_directory)
+            .map_err(|error| warn!("Shaders won't be hot reloaded: {}", error))
+            .ok();
+
+        let context = Self {
+            window,
+            display,
+            shader_watcher,
+            cursor_captured: false,
+        };
+        crash::set_gpu(context.description());
+
+        context
+    }
+
+    /// Does what glium's `SimpleWindowBuilder` does, but picks the framebuffer config with the
+    /// most samples up to `settings.msaa_samples` and sets the swap interval
+    fn build_display(
+        window_builder: WindowBuilder,
+        settings: &GraphicsSettings,
+        event_loop: &EventLoop<()>,
+    ) -> (Window, Display<WindowSurface>) {
+        let (window, config) = DisplayBuilder::new()
+            .with_window_builder(Some(window_builder))
+            .build(event_loop, ConfigTemplateBuilder::new(), |configs| {
+                configs
+                    .filter(|config| config.num_samples() <= settings.msaa_samples)
+                    .max_by_key(|config| config.num_samples())
+                    .expect("No framebuffer config without multisampling")
+            })
+            .unwrap();
+        let window = window.unwrap();
+
+        if config.num_samples() != settings.msaa_samples {
+            warn!(
+                "Using {} samples per pixel as {} aren't supported",
+                config.num_samples(),
+                settings.msaa_samples
+            );
+        }
 
-        if fullscreen {
-            window_builder = window_builder.with_fullscreen(Some(Fullscreen::Borderless(None)));
+        let (width, height): (u32, u32) = window.inner_size().into();
+        let surface_attributes = SurfaceAttributesBuilder::<WindowSurface>::new().build(
+            window.raw_window_handle(),
+            NonZeroU32::new(width).unwrap_or(NonZeroU32::MIN),
+            NonZeroU32::new(height).unwrap_or(NonZeroU32::MIN),
+        );
+        let surface = unsafe {
+            config
+                .display()
+                .create_window_surface(&config, &surface_attributes)
+                .unwrap()
+        };
+
+        let context_attributes =
+            ContextAttributesBuilder::new().build(Some(window.raw_window_handle()));
+        let context = unsafe {
+            config
+                .display()
+                .create_context(&config, &context_attributes)
+                .unwrap()
+        }
+        .make_current(&surface)
+        .unwrap();
+        profiling::load_gl(|symbol| config.display().get_proc_address(symbol));
+
+        let swap_interval = if settings.vsync {
+            SwapInterval::Wait(NonZeroU32::MIN)
         } else {
-            window_builder = window_builder.with_maximized(true);
+            SwapInterval::DontWait
+        };
+        if let Err(error) = surface.set_swap_interval(&context, swap_interval) {
+            warn!("Could not set vsync to {}: {}", settings.vsync, error);
         }
 
-        let (window, display) = SimpleWindowBuilder::new()
-            .set_window_builder(window_builder)
-            .build(event_loop);
+        let display = Display::from_context_surface(context, surface).unwrap();
+
+        (window, display)
+    }
+
+    /// Switches to the display mode, monitor and resolution in `config`
+    pub fn set_display_mode(&mut self, config: &AppConfig) {
+        let fullscreen = Self::fullscreen(config, self.monitors(), self.window.primary_monitor());
+        self.window.set_fullscreen(fullscreen);
+    }
 
-        Self { window, display }
+    pub fn monitors(&self) -> Vec<MonitorHandle> {
+        self.window.available_monitors().collect()
     }
 
+    /// What the window should be made fullscreen with for `config`, `None` for windowed
+    pub(crate) fn fullscreen(
+        config: &AppConfig,
+        monitors: Vec<MonitorHandle>,
+        primary: Option<MonitorHandle>,
+    ) -> Option<Fullscreen> {
+        let chosen = config.monitor.as_ref().and_then(|name| {
+            let monitor = monitors
+                .iter()
+                .find(|monitor| monitor.name().as_ref() == Some(name))
+                .cloned();
+            if monitor.is_none() {
+                warn!("Monitor {:?} isn't connected, using the primary one", name);
+            }
+            monitor
+        });
+        let monitor = chosen.or(primary).or_else(|| monitors.first().cloned());
+
+        match config.display_mode {
+            DisplayMode::Windowed => None,
+            DisplayMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+            DisplayMode::Exclusive => {
+                let Some(video_mode) = monitor
+                    .as_ref()
+                    .and_then(|monitor| video_mode(monitor, config.resolution))
+                else {
+                    warn!("No video modes to go fullscreen with, using borderless");
+                    return Some(Fullscreen::Borderless(monitor));
+                };
+
+                Some(Fullscreen::Exclusive(video_mode))
+            }
+        }
+    }
+
+    /// Canonical paths of the shaders changed since the last call
+    pub fn changed_shaders(&self) -> Vec<PathBuf> {
+        self.shader_watcher
+            .as_ref()
+            .map_or_else(Vec::new, FileWatcher::changed_files)
+    }
+
+    /// Grabs and hides the cursor so it can be used to look around
     pub fn capture_cursor(&mut self) {
+        if self.cursor_captured {
+            return;
+        }
+
         self.window
             .set_cursor_grab(CursorGrabMode::Confined)
             .or_else(|_| self.window.set_cursor_grab(CursorGrabMode::Locked))
             .unwrap();
+        self.window.set_cursor_visible(false);
+        self.cursor_captured = true;
     }
 
     pub fn release_cursor(&mut self) {
+        if !self.cursor_captured {
+            return;
+        }
+
         self.window.set_cursor_grab(CursorGrabMode::None).unwrap();
+        self.window.set_cursor_visible(true);
+        self.cursor_captured = false;
+    }
+
+    pub fn cursor_captured(&self) -> bool {
+        self.cursor_captured
     }
 
     pub fn center_cursor(&mut self) {
@@ -50,20 +225,190 @@ impl OpenGLContext {
     }
 }
 
-    pub fn new_program(
-        vertex_source_path: &str,
-        fragment_source_path: &str,
-        geometry_source_path: Option<&str>,
-        display: &Display<WindowSurface>,
-    ) -> Result<Program> {
-        let vertex_source = fs::read_to_string(vertex_source_path)?;
-        let fragment_source = fs::read_to_string(fragment_source_path)?;
-        let geometry_source = geometry_source_path.map(|path| fs::read_to_string(path).unwrap());
+/// The window `config` asks for, shared by every backend
+pub(crate) fn window_builder(config: &AppConfig, event_loop: &EventLoop<()>) -> WindowBuilder {
+    let window_builder = WindowBuilder::new()
+        .with_title(&config.title)
+        .with_inner_size(LogicalSize::new(config.width, config.height));
+
+    match OpenGLContext::fullscreen(
+        config,
+        event_loop.available_monitors().collect(),
+        event_loop.primary_monitor(),
+    ) {
+        Some(fullscreen) => window_builder.with_fullscreen(Some(fullscreen)),
+        None => window_builder.with_maximized(config.maximized),
+    }
+}
 
-        Ok(Program::from_source(
+/// Widths and heights a monitor can be switched to, largest first
+pub fn resolutions(monitor: &MonitorHandle) -> Vec<(u32, u32)> {
+    monitor
+        .video_modes()
+        .map(|mode| (mode.size().width, mode.size().height))
+        .sorted_by_key(|(width, height)| std::cmp::Reverse(width * height))
+        .dedup()
+        .collect()
+}
+
+/// The video mode at `resolution` with the highest refresh rate, or the largest one if there's no
+/// mode at that resolution
+fn video_mode(monitor: &MonitorHandle, resolution: Option<(u32, u32)>) -> Option<VideoMode> {
+    let quality = |mode: &VideoMode| {
+        (
+            mode.size().width * mode.size().height,
+            mode.bit_depth(),
+            mode.refresh_rate_millihertz(),
+        )
+    };
+
+    if let Some((width, height)) = resolution {
+        let size = PhysicalSize::new(width, height);
+        if let Some(mode) = monitor
+            .video_modes()
+            .filter(|mode| mode.size() == size)
+            .max_by_key(quality)
+        {
+            return Some(mode);
+        }
+
+        warn!(
+            "{}x{} isn't supported, using the largest resolution",
+            width, height
+        );
+    }
+
+    monitor.video_modes().max_by_key(quality)
+}
+
+/// Watches a directory, such as the shaders, for files being written
+#[derive(Debug)]
+pub struct FileWatcher {
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+}
+
+impl FileWatcher {
+    pub fn new(directory: &Path) -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(directory, RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Canonical paths of the files created or modified since the last call
+    pub fn changed_files(&self) -> Vec<PathBuf> {
+        self.events
+            .try_iter()
+            .filter_map(|event| {
+                event
+                    .map_err(|error| warn!("Error watching files: {}", error))
+                    .ok()
+            })
+            .filter(|event| matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)))
+            .flat_map(|event| event.paths)
+            .filter_map(|path| fs::canonicalize(path).ok())
+            .unique()
+            .collect()
+    }
+}
+
+pub fn new_program(
+    vertex_source_path: &str,
+    fragment_source_path: &str,
+    geometry_source_path: Option<&str>,
+    display: &Display<WindowSurface>,
+) -> Result<Program> {
+    let vertex_source = read_shader(vertex_source_path)?;
+    let fragment_source = read_shader(fragment_source_path)?;
+    let geometry_source = geometry_source_path.map(read_shader).transpose()?;
+
+    Ok(Program::from_source(
+        display,
+        vertex_source.as_str(),
+        fragment_source.as_str(),
+        geometry_source.as_deref(),
+    )?)
+}
+
+/// Reads a shader's source, replacing each `#include "name"` line with the file of that name in
+/// `SHADER_INCLUDE_DIRECTORY`, which GLSL has no way to do itself
+fn read_shader(path: &str) -> Result<String> {
+    let source = fs::read_to_string(path)?;
+
+    source
+        .lines()
+        .map(|line| {
+            let Some(name) = line
+                .trim()
+                .strip_prefix("#include \"")
+                .and_then(|name| name.strip_suffix('"'))
+            else {
+                return Ok(line.to_owned());
+            };
+
+            let include_path = Path::new(SHADER_INCLUDE_DIRECTORY).join(name);
+            fs::read_to_string(&include_path)
+                .map_err(|error| eyre!("{:?} included by {:?}: {}", include_path, path, error))
+        })
+        .collect::<Result<Vec<String>>>()
+        .map(|lines| lines.join("\n"))
+}
+
+/// Render targets for the geometry pass of deferred shading, read back by the lighting pass
+pub struct GBuffer {
+    /// Surface color, with an alpha of zero where nothing was drawn
+    pub albedo: Texture2d,
+    pub normal: Texture2d,
+    /// World space position
+    pub position: Texture2d,
+    pub depth: DepthTexture2d,
+}
+
+impl GBuffer {
+    pub fn new(display: &Display<WindowSurface>, (width, height): (u32, u32)) -> Result<Self> {
+        let color_texture = |format| {
+            Texture2d::empty_with_format(display, format, MipmapsOption::NoMipmap, width, height)
+        };
+
+        Ok(Self {
+            albedo: color_texture(UncompressedFloatFormat::U8U8U8U8)?,
+            normal: color_texture(UncompressedFloatFormat::F16F16F16F16)?,
+            position: color_texture(UncompressedFloatFormat::F32F32F32F32)?,
+            depth: DepthTexture2d::empty_with_format(
+                display,
+                DepthFormat::F32,
+                MipmapsOption::NoMipmap,
+                width,
+                height,
+            )?,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.albedo.dimensions()
+    }
+
+    /// Framebuffer writing the `out_albedo`, `out_normal` and `out_position` shader outputs into
+    /// their textures
+    pub fn framebuffer<'a>(
+        &'a self,
+        display: &Display<WindowSurface>,
+    ) -> Result<MultiOutputFrameBuffer<'a>> {
+        Ok(MultiOutputFrameBuffer::with_depth_buffer(
             display,
-            vertex_source.as_str(),
-            fragment_source.as_str(),
-            geometry_source.as_deref(),
+            [
+                ("out_albedo", &self.albedo),
+                ("out_normal", &self.normal),
+                ("out_position", &self.position),
+            ],
+            &self.depth,
         )?)
     }
+}
@@ -0,0 +1,200 @@
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError, Weak};
+use std::thread::ThreadId;
+
+use color_eyre::Result;
+use rfd::{MessageButtons, MessageDialog, MessageLevel};
+use winit::window::{CursorGrabMode, Window};
+
+use crate::debug;
+
+/// Where crash reports are saved
+pub const CRASH_DIRECTORY: &str = "crashes";
+
+/// Most recent log lines written into a crash report
+const CRASH_LOG_LINES: usize = 200;
+
+/// What a crash report says about the game beyond the panic itself, kept up to date as it runs
+struct CrashContext {
+    scene: String,
+    gpu: String,
+    /// Window released and hidden on a panic, if there is one still open
+    window: Option<Weak<Window>>,
+    /// Thread the window and GL context belong to, the only one that tears them down
+    window_thread: Option<ThreadId>,
+}
+
+static CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+    scene: String::new(),
+    gpu: String::new(),
+    window: None,
+    window_thread: None,
+});
+
+/// The crash context, even after a panic while it was locked, or `None` if this thread already
+/// has it locked
+fn context() -> Option<MutexGuard<'static, CrashContext>> {
+    match CONTEXT.try_lock() {
+        Ok(context) => Some(context),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        // Only a panic on this thread while it's locked, as nothing holds it for long
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// Saves a crash report to `CRASH_DIRECTORY` on any panic, after the panic hook already installed
+/// by `color_eyre` prints it. Panics on the window's thread then release the cursor, finish
+/// whatever the GPU is doing so the GL context closes cleanly as it's dropped, and show an error
+/// dialog where there's a desktop to show it on.
+pub fn install() {
+    let previous = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        let report = report(info);
+        let path = save_report(&report)
+            .map_err(|error| eprintln!("Failed to save crash report: {}", error))
+            .ok();
+
+        previous(info);
+
+        let Some(window) = release_window() else {
+            return;
+        };
+
+        let location = path.map_or_else(
+            || "The crash report couldn't be saved.".to_owned(),
+            |path| format!("A crash report was saved to {}.", path.display()),
+        );
+        MessageDialog::new()
+            .set_level(MessageLevel::Error)
+            .set_title(window.title())
+            .set_description(format!("{}\n\n{}", panic_message(info), location))
+            .set_buttons(MessageButtons::Ok)
+            .show();
+    }));
+}
+
+/// Says which scene is open in crash reports
+pub fn set_scene(title: &str) {
+    if let Some(mut context) = context() {
+        if context.scene != title {
+            context.scene = title.to_owned();
+        }
+    }
+}
+
+/// Says which GPU and driver the game is drawing with in crash reports
+pub fn set_gpu(description: String) {
+    if let Some(mut context) = context() {
+        context.gpu = description;
+    }
+}
+
+/// Releases and hides `window` on a panic on the calling thread, which should be the one it and
+/// its GL context belong to. Until a window is watched, panics show no error dialog.
+pub fn watch_window(window: &Arc<Window>) {
+    if let Some(mut context) = context() {
+        context.window = Some(Arc::downgrade(window));
+        context.window_thread = Some(std::thread::current().id());
+    }
+}
+
+/// Everything known about a panic, as written to its crash report
+fn report(info: &PanicHookInfo) -> String {
+    let thread = std::thread::current();
+    let (scene, gpu) = context().map_or_else(
+        || ("unknown".to_owned(), "unknown".to_owned()),
+        |context| (context.scene.clone(), context.gpu.clone()),
+    );
+
+    let mut report = format!(
+        "{name} {version} crashed at {time}\n\
+         Platform: {os} {arch}\n\
+         Thread: {thread}\n\
+         Scene: {scene}\n\
+         GPU: {gpu}\n\n\
+         {message}\n\n\
+         Backtrace:\n{backtrace}\n\
+         Last log lines:\n",
+        name = env!("CARGO_PKG_NAME"),
+        version = env!("CARGO_PKG_VERSION"),
+        time = chrono::offset::Local::now().to_rfc3339(),
+        os = std::env::consts::OS,
+        arch = std::env::consts::ARCH,
+        thread = thread.name().unwrap_or("unnamed"),
+        scene = or_unknown(&scene),
+        gpu = or_unknown(&gpu),
+        message = panic_message(info),
+        backtrace = Backtrace::force_capture(),
+    );
+    for line in debug::recent_log_lines(CRASH_LOG_LINES) {
+        let _ = writeln!(
+            report,
+            "[{} {} {}] {}",
+            line.time, line.level, line.target, line.message
+        );
+    }
+
+    report
+}
+
+/// What panicked and where, e.g. `Panicked at src/editor/editor.rs:12:5: no map`
+fn panic_message(info: &PanicHookInfo) -> String {
+    let payload = info
+        .payload()
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| info.payload().downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+
+    match info.location() {
+        Some(location) => format!("Panicked at {}: {}", location, payload),
+        None => format!("Panicked: {}", payload),
+    }
+}
+
+fn or_unknown(value: &str) -> &str {
+    if value.is_empty() {
+        "unknown"
+    } else {
+        value
+    }
+}
+
+/// Writes `report` to a new file named after the time, returning where
+fn save_report(report: &str) -> Result<PathBuf> {
+    std::fs::create_dir_all(CRASH_DIRECTORY)?;
+    let timestamp = chrono::offset::Local::now().format("%Y-%m-%d_%H-%M-%S%.3f");
+    let path = Path::new(CRASH_DIRECTORY).join(format!("crash_{}.txt", timestamp));
+    std::fs::write(&path, report)?;
+
+    Ok(path)
+}
+
+/// Gives the cursor back, leaves fullscreen and hides the watched window so the dialog isn't
+/// stuck behind it, and waits for the GPU to finish. `None` if there's no window open or it
+/// belongs to another thread, which carries on without it.
+fn release_window() -> Option<Arc<Window>> {
+    let window = {
+        let context = context()?;
+        if context.window_thread != Some(std::thread::current().id()) {
+            return None;
+        }
+        context.window.as_ref()?.upgrade()?
+    };
+
+    let _ = window.set_cursor_grab(CursorGrabMode::None);
+    window.set_cursor_visible(true);
+    window.set_fullscreen(None);
+    window.set_visible(false);
+
+    // The context is current on this thread, and is destroyed as the panic unwinds past it
+    if gl::Finish::is_loaded() {
+        unsafe { gl::Finish() };
+    }
+
+    Some(window)
+}
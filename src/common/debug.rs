@@ -1,6 +1,112 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::str::FromStr;
+use std::sync::{Mutex, RwLock, TryLockError};
+
 use fern::colors::{Color, ColoredLevelConfig};
+use log::{Level, LevelFilter, Metadata};
+use serde::{Deserialize, Serialize};
+
+/// Environment variable with filters applied on top of the config's, e.g.
+/// `warn,common::physics=debug`
+pub const LOG_ENVIRONMENT_VARIABLE: &str = "RUST_LOG";
+
+/// Most lines kept for the in-game log view, oldest dropped first
+const MAX_LOG_LINES: usize = 1024;
+
+/// Which messages are logged, read from the app config
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Level for modules without one of their own: off, error, warn, info, debug or trace
+    pub level: String,
+    /// Levels by module path, e.g. `"common::physics" = "debug"`, the longest path a message's
+    /// module starts with winning
+    pub modules: BTreeMap<String, String>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        // Dependencies only when something's wrong, the game's own crates a little more
+        let modules = ["common", "editor", "game", "server"]
+            .map(|module| (module.to_owned(), "info".to_owned()))
+            .into();
+
+        Self {
+            level: "warn".to_owned(),
+            modules,
+        }
+    }
+}
+
+impl LogConfig {
+    /// Applies comma separated filters, each either a level for every module or `module=level`
+    pub fn apply_directives(&mut self, directives: &str) {
+        for directive in directives.split(',').map(str::trim) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    self.modules
+                        .insert(module.trim().to_owned(), level.trim().to_owned());
+                }
+                None if LevelFilter::from_str(directive).is_ok() => {
+                    self.level = directive.to_owned();
+                }
+                // A module on its own logs everything
+                None if !directive.is_empty() => {
+                    self.modules
+                        .insert(directive.to_owned(), "trace".to_owned());
+                }
+                None => (),
+            }
+        }
+    }
+}
+
+/// A message kept for the in-game log view
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogLine {
+    pub time: String,
+    pub level: Level,
+    /// Module path it was logged from
+    pub target: String,
+    pub message: String,
+}
+
+/// Levels parsed from a `LogConfig`, checked for every message
+struct LogFilters {
+    level: LevelFilter,
+    /// Longest first, so the most specific match is found first
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilters {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let target = metadata.target();
+        let level = self
+            .modules
+            .iter()
+            .find(|(module, _)| {
+                target
+                    .strip_prefix(module.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .map_or(self.level, |(_, level)| *level);
+
+        metadata.level() <= level
+    }
+}
+
+static FILTERS: RwLock<LogFilters> = RwLock::new(LogFilters {
+    level: LevelFilter::Warn,
+    modules: vec![],
+});
+
+static LOG_LINES: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
 
+/// Logs to stdout and keeps recent lines for `for_each_log_line`, filtered by the default
+/// `LogConfig` until `configure_logging` is given another
 pub fn set_up_logging() {
+    configure_logging(&LogConfig::default());
+
     // configure colors for the whole line
     let colors_line = ColoredLevelConfig::new()
         .error(Color::Red)
@@ -12,7 +118,7 @@ pub fn set_up_logging() {
     // configure colors for the severity
     let colors_level = colors_line.info(Color::Green).debug(Color::Blue);
 
-    fern::Dispatch::new()
+    let stdout = fern::Dispatch::new()
         .format(move |out, message, record| {
             out.finish(format_args!(
                 "[{time} {color_line}{level} {white}{target}] {color_line}{message}\x1B[0m",
@@ -27,11 +133,85 @@ pub fn set_up_logging() {
                 message = message,
             ));
         })
-        // Sets log level across entire crate to remove verbose dependency information
-        .level(log::LevelFilter::Warn)
-        // TODO change name
-        .level_for("vulkano_teapot", log::LevelFilter::Trace)
-        .chain(std::io::stdout())
+        .chain(std::io::stdout());
+
+    let log_lines = fern::Output::call(|record| {
+        let mut lines = LOG_LINES.lock().unwrap();
+        if lines.len() >= MAX_LOG_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(LogLine {
+            time: chrono::offset::Local::now().format("%H:%M:%S").to_string(),
+            level: record.level(),
+            target: record.target().to_owned(),
+            message: record.args().to_string(),
+        });
+    });
+
+    // Everything passes the dispatch's own level so the filters can change while running
+    fern::Dispatch::new()
+        .level(LevelFilter::Trace)
+        .filter(|metadata| FILTERS.read().unwrap().enabled(metadata))
+        .chain(stdout)
+        .chain(log_lines)
         .apply()
         .unwrap();
 }
+
+/// Changes which messages are logged, with any filters in `LOG_ENVIRONMENT_VARIABLE` applied on
+/// top. Levels that don't parse are warned about and left out.
+pub fn configure_logging(config: &LogConfig) {
+    let mut config = config.clone();
+    if let Ok(directives) = std::env::var(LOG_ENVIRONMENT_VARIABLE) {
+        config.apply_directives(&directives);
+    }
+
+    let mut invalid = vec![];
+    let mut parse = |name: &str, level: &str| {
+        LevelFilter::from_str(level)
+            .map_err(|_| invalid.push(format!("{} for {}", level, name)))
+            .ok()
+    };
+
+    let level = parse("every module", &config.level).unwrap_or(LevelFilter::Warn);
+    let mut modules = config
+        .modules
+        .iter()
+        .filter_map(|(module, level)| Some((module.clone(), parse(module, level)?)))
+        .collect::<Vec<_>>();
+    modules.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+
+    *FILTERS.write().unwrap() = LogFilters { level, modules };
+
+    for invalid in invalid {
+        log::warn!("Ignoring invalid log level {}", invalid);
+    }
+}
+
+/// Calls `f` with each of the most recent lines logged, oldest first
+pub fn for_each_log_line(mut f: impl FnMut(&LogLine)) {
+    for line in LOG_LINES.lock().unwrap().iter() {
+        f(line);
+    }
+}
+
+/// Up to `count` of the most recent lines logged, oldest first. Doesn't wait for the lines, so a
+/// panic while logging gets none rather than deadlocking.
+pub fn recent_log_lines(count: usize) -> Vec<LogLine> {
+    let lines = match LOG_LINES.try_lock() {
+        Ok(lines) => lines,
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+        Err(TryLockError::WouldBlock) => return vec![],
+    };
+
+    lines
+        .iter()
+        .skip(lines.len().saturating_sub(count))
+        .cloned()
+        .collect()
+}
+
+/// Forgets the lines kept for the log view
+pub fn clear_log_lines() {
+    LOG_LINES.lock().unwrap().clear();
+}
@@ -0,0 +1,202 @@
+use std::f32::consts::TAU;
+
+use cgmath::{Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use color_eyre::Result;
+use glium::glutin::surface::WindowSurface;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::{
+    uniform, Blend, Depth, DepthTest, Display, DrawParameters, Program, Surface, VertexBuffer,
+};
+use palette::Srgb;
+
+use crate::assets::{Assets, Handle};
+use crate::camera::Camera;
+use crate::line::LinePoint;
+use crate::maths;
+use crate::maths::{Aabb, Ray};
+
+/// How visible lines are through whatever is in front of them
+const HIDDEN_OPACITY: f32 = 0.25;
+
+const LINE_WIDTH: f32 = 2.0;
+
+/// Segments each of a sphere's circles is drawn with
+const CIRCLE_SEGMENTS: usize = 24;
+
+/// Immediate mode lines for seeing what the code is doing, e.g. rays, bounds and lights. Shapes
+/// are submitted from anywhere during a frame and drawn once at the end of it, then forgotten.
+pub struct DebugDraw {
+    /// Whether submitted shapes are kept and drawn, so calls can be left in place
+    pub enabled: bool,
+    vertices: Vec<LinePoint>,
+    /// Loaded by the first render
+    program: Option<Handle<Program>>,
+    /// Grows to fit the most lines drawn in a frame
+    vertex_buffer: Option<VertexBuffer<LinePoint>>,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            vertices: vec![],
+            program: None,
+            vertex_buffer: None,
+        }
+    }
+
+    pub fn draw_line(&mut self, start: Point3<f32>, end: Point3<f32>, color: Srgb) {
+        if !self.enabled {
+            return;
+        }
+
+        let color = [color.red, color.green, color.blue];
+        self.vertices.extend([
+            LinePoint {
+                position: start.into(),
+                color,
+            },
+            LinePoint {
+                position: end.into(),
+                color,
+            },
+        ]);
+    }
+
+    /// A ray from its origin to `length` multiples of its direction along it
+    pub fn draw_ray(&mut self, ray: &Ray, length: f32, color: Srgb) {
+        self.draw_line(ray.origin, ray.at(length), color);
+    }
+
+    pub fn draw_aabb(&mut self, aabb: &Aabb, color: Srgb) {
+        self.draw_box(aabb, Matrix4::identity(), color);
+    }
+
+    /// A box transformed by a matrix, e.g. a model's bounds placed by an instance's transform
+    pub fn draw_box(&mut self, aabb: &Aabb, transform: Matrix4<f32>, color: Srgb) {
+        let corners = aabb
+            .corners()
+            .map(|corner| transform.transform_point(corner));
+
+        // Corners differ in one axis along each edge, going by `Aabb::corners`' order
+        for (a, b) in [
+            (0, 1),
+            (2, 3),
+            (4, 5),
+            (6, 7),
+            (0, 2),
+            (1, 3),
+            (4, 6),
+            (5, 7),
+            (0, 4),
+            (1, 5),
+            (2, 6),
+            (3, 7),
+        ] {
+            self.draw_line(corners[a], corners[b], color);
+        }
+    }
+
+    /// A circle around each axis
+    pub fn draw_sphere(&mut self, center: Point3<f32>, radius: f32, color: Srgb) {
+        for (a, b) in [
+            (Vector3::unit_x(), Vector3::unit_y()),
+            (Vector3::unit_y(), Vector3::unit_z()),
+            (Vector3::unit_z(), Vector3::unit_x()),
+        ] {
+            let point = |segment: usize| {
+                let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+                center + (a * angle.cos() + b * angle.sin()) * radius
+            };
+
+            for segment in 0..CIRCLE_SEGMENTS {
+                self.draw_line(point(segment), point(segment + 1), color);
+            }
+        }
+    }
+
+    /// A small cross marking a position
+    pub fn draw_point(&mut self, position: Point3<f32>, size: f32, color: Srgb) {
+        for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+            let offset = axis * size / 2.0;
+            self.draw_line(position - offset, position + offset, color);
+        }
+    }
+
+    /// Forgets everything submitted this frame without drawing it, for backends that can't
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    /// Draws everything submitted this frame, faintly where it's hidden, then clears it. Returns
+    /// the number of draw calls made.
+    pub fn render<S: Surface>(
+        &mut self,
+        display: &Display<WindowSurface>,
+        target: &mut S,
+        assets: &mut Assets,
+        camera: &Camera,
+    ) -> Result<usize> {
+        let count = self.vertices.len();
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let program = match self.program {
+            Some(program) => program,
+            None => *self.program.insert(assets.load_program(
+                "assets/shaders/debug/debug.vert",
+                "assets/shaders/debug/debug.frag",
+                display,
+            )?),
+        };
+
+        if self
+            .vertex_buffer
+            .as_ref()
+            .map_or(true, |buffer| buffer.len() < count)
+        {
+            self.vertex_buffer = Some(VertexBuffer::empty_dynamic(
+                display,
+                count.next_power_of_two(),
+            )?);
+        }
+
+        let vertex_buffer = self.vertex_buffer.as_ref().unwrap();
+        vertex_buffer.slice(0..count).unwrap().write(&self.vertices);
+        self.vertices.clear();
+
+        for (test, opacity) in [
+            (DepthTest::IfMore, HIDDEN_OPACITY),
+            (DepthTest::IfLessOrEqual, 1.0),
+        ] {
+            target.draw(
+                vertex_buffer.slice(0..count).unwrap(),
+                &NoIndices(PrimitiveType::LinesList),
+                assets.program(program),
+                &uniform! {
+                    vp: maths::raw_matrix(camera.view_projection),
+                    opacity: opacity,
+                },
+                &DrawParameters {
+                    depth: Depth {
+                        test,
+                        write: false,
+                        ..Depth::default()
+                    },
+                    blend: Blend::alpha_blending(),
+                    line_width: Some(LINE_WIDTH),
+                    ..DrawParameters::default()
+                },
+            )?;
+        }
+
+        Ok(2)
+    }
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self::new()
+    }
+}
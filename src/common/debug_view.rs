@@ -0,0 +1,83 @@
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+
+use crate::console::CvarValue;
+
+/// What the scene is drawn as, either lit as usual or showing one thing about it to find what's
+/// wrong with its geometry or lighting
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DebugView {
+    #[default]
+    Lit,
+    /// Models as their triangles' edges, still lit
+    Wireframe,
+    /// Materials' base colors without any lighting
+    Albedo,
+    /// World space normals as colors
+    Normals,
+    /// Distance from the camera, white up close
+    Depth,
+    /// How many times each pixel is drawn over, brighter the more
+    Overdraw,
+    /// Lighting on white surfaces, forward rendered
+    Lighting,
+}
+
+impl DebugView {
+    pub const ALL: [Self; 7] = [
+        Self::Lit,
+        Self::Wireframe,
+        Self::Albedo,
+        Self::Normals,
+        Self::Depth,
+        Self::Overdraw,
+        Self::Lighting,
+    ];
+
+    /// The one after this, back to `Lit` after the last
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|view| *view == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lit => "lit",
+            Self::Wireframe => "wireframe",
+            Self::Albedo => "albedo",
+            Self::Normals => "normals",
+            Self::Depth => "depth",
+            Self::Overdraw => "overdraw",
+            Self::Lighting => "lighting",
+        }
+    }
+
+    /// Which view the debug view shader draws, for the views that replace the scene's shaders
+    pub fn shader_view(self) -> Option<i32> {
+        match self {
+            Self::Albedo => Some(0),
+            Self::Normals => Some(1),
+            Self::Depth => Some(2),
+            Self::Overdraw => Some(3),
+            Self::Lit | Self::Wireframe | Self::Lighting => None,
+        }
+    }
+}
+
+impl CvarValue for DebugView {
+    fn parse(text: &str) -> Result<Self> {
+        let text = text.to_ascii_lowercase();
+        match Self::ALL.into_iter().find(|view| view.name() == text) {
+            Some(view) => Ok(view),
+            None => bail!(
+                "Expected one of {}, got {:?}",
+                Self::ALL.map(Self::name).join(", "),
+                text
+            ),
+        }
+    }
+
+    fn show(&self) -> String {
+        self.name().to_owned()
+    }
+}
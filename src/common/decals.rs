@@ -0,0 +1,293 @@
+use std::collections::VecDeque;
+
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4};
+use color_eyre::Result;
+use glium::draw_parameters::PolygonOffset;
+use glium::glutin::surface::WindowSurface;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::{
+    implement_vertex, uniform, Blend, Depth, DepthTest, Display, DrawParameters, Program, Surface,
+    VertexBuffer,
+};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::{Assets, Handle};
+use crate::camera::Camera;
+use crate::entity::World;
+use crate::hierarchy;
+use crate::maths;
+use crate::uuid::UUID;
+
+/// The oldest decal is recycled to make room past this many
+const MAX_DECALS: usize = 256;
+
+/// How far decals are lifted off the surface along its normal, on top of their depth bias, so
+/// they don't flicker on surfaces seen side-on
+const SURFACE_OFFSET: f32 = 0.002;
+
+/// What a decal looks like
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecalKind {
+    /// A small dark hole with a lighter ring, left by bullets
+    #[default]
+    BulletHole,
+    /// A wide soft burn, left by explosions
+    Scorch,
+}
+
+impl DecalKind {
+    /// Width in world units
+    fn size(self) -> f32 {
+        match self {
+            Self::BulletHole => 0.08,
+            Self::Scorch => 1.2,
+        }
+    }
+
+    /// Seconds before it starts fading, and how long fading takes
+    fn lifetime(self) -> (f32, f32) {
+        match self {
+            Self::BulletHole => (20.0, 5.0),
+            Self::Scorch => (30.0, 10.0),
+        }
+    }
+
+    /// Picks the shape drawn in the fragment shader
+    fn shape(self) -> u32 {
+        match self {
+            Self::BulletHole => 0,
+            Self::Scorch => 1,
+        }
+    }
+}
+
+struct Decal {
+    kind: DecalKind,
+    /// Places a unit quad on the surface, relative to `entity` if it's attached to one
+    matrix: Matrix4<f32>,
+    /// Followed as it moves, and the decal removed once it's despawned
+    entity: Option<UUID>,
+    age: f32,
+}
+
+#[derive(Copy, Clone)]
+struct Corner {
+    corner: [f32; 2],
+}
+implement_vertex!(Corner, corner);
+
+#[derive(Copy, Clone)]
+struct DecalInstance {
+    decal_matrix: [[f32; 4]; 4],
+    decal_opacity: f32,
+    decal_shape: u32,
+}
+implement_vertex!(DecalInstance, decal_matrix, decal_opacity, decal_shape);
+
+/// Marks left on surfaces, such as bullet holes and scorch marks, drawn as quads lying on them
+/// in one instanced draw call. They fade out over time, and once there are too many the oldest is
+/// recycled for the newest.
+pub struct DecalSystem {
+    /// Oldest first
+    decals: VecDeque<Decal>,
+    /// Made by the first render
+    resources: Option<DecalResources>,
+}
+
+struct DecalResources {
+    program: Handle<Program>,
+    quad: VertexBuffer<Corner>,
+    /// Holds as many decals as there can be at once
+    instances: VertexBuffer<DecalInstance>,
+}
+
+impl DecalResources {
+    fn new(assets: &mut Assets, display: &Display<WindowSurface>) -> Result<Self> {
+        let program = assets.load_program(
+            "assets/shaders/decal/decal.vert",
+            "assets/shaders/decal/decal.frag",
+            display,
+        )?;
+
+        let quad = VertexBuffer::new(
+            display,
+            &[[-0.5, -0.5], [0.5, -0.5], [-0.5, 0.5], [0.5, 0.5]].map(|corner| Corner { corner }),
+        )?;
+
+        Ok(Self {
+            program,
+            quad,
+            instances: VertexBuffer::empty_dynamic(display, MAX_DECALS)?,
+        })
+    }
+}
+
+impl DecalSystem {
+    pub fn new() -> Self {
+        Self {
+            decals: VecDeque::with_capacity(MAX_DECALS),
+            resources: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.decals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decals.is_empty()
+    }
+
+    /// Leaves a decal on the surface at `point` facing along `normal`, such as where a raycast
+    /// hit. Attached to `entity` it moves along with it and goes once it's despawned.
+    pub fn spawn(
+        &mut self,
+        world: &World,
+        kind: DecalKind,
+        point: Point3<f32>,
+        normal: Vector3<f32>,
+        entity: Option<UUID>,
+    ) {
+        if normal.magnitude2() == 0.0 {
+            return;
+        }
+        let normal = normal.normalize();
+
+        // Any axis not along the normal will do, and a random spin stops repeats lining up
+        let helper = if normal.y.abs() < 0.99 {
+            Vector3::unit_y()
+        } else {
+            Vector3::unit_x()
+        };
+        let (sin, cos) = (fastrand::f32() * std::f32::consts::TAU).sin_cos();
+        let tangent = normal.cross(helper).normalize();
+        let bitangent = normal.cross(tangent);
+        let (tangent, bitangent) = (
+            tangent * cos + bitangent * sin,
+            bitangent * cos - tangent * sin,
+        );
+
+        let size = kind.size();
+        let position = point + normal * SURFACE_OFFSET;
+        let world_matrix = Matrix4::from_cols(
+            (tangent * size).extend(0.0),
+            (bitangent * size).extend(0.0),
+            normal.extend(0.0),
+            Vector4::new(position.x, position.y, position.z, 1.0),
+        );
+
+        let inverse = entity
+            .and_then(|entity| hierarchy::global_matrix(world, entity))
+            .and_then(|matrix| matrix.invert());
+        let (matrix, entity) = match inverse {
+            Some(inverse) => (inverse * world_matrix, entity),
+            None => (world_matrix, None),
+        };
+
+        if self.decals.len() >= MAX_DECALS {
+            self.decals.pop_front();
+        }
+        self.decals.push_back(Decal {
+            kind,
+            matrix,
+            entity,
+            age: 0.0,
+        });
+    }
+
+    /// Ages decals, removing those that have faded away or whose entity is gone
+    pub fn update(&mut self, world: &World, deltatime: f32) {
+        for decal in self.decals.iter_mut() {
+            decal.age += deltatime;
+        }
+
+        self.decals.retain(|decal| {
+            let (lifetime, fade) = decal.kind.lifetime();
+            decal.age < lifetime + fade
+                && decal.entity.map_or(true, |entity| world.contains(entity))
+        });
+    }
+
+    /// Removes the decals attached to an entity, for when it's despawned or recycled
+    pub fn detach(&mut self, entity: UUID) {
+        self.decals.retain(|decal| decal.entity != Some(entity));
+    }
+
+    /// Removes every decal, e.g. when the scene is changed
+    pub fn clear(&mut self) {
+        self.decals.clear();
+    }
+
+    /// Draws every decal over what's been drawn, biased towards the camera so they win against
+    /// the surfaces they're on. Returns the number of draw calls made.
+    pub fn render<S: Surface>(
+        &mut self,
+        display: &Display<WindowSurface>,
+        world: &World,
+        target: &mut S,
+        assets: &mut Assets,
+        camera: &Camera,
+    ) -> Result<usize> {
+        let instances = self
+            .decals
+            .iter()
+            .filter_map(|decal| {
+                let matrix = match decal.entity {
+                    Some(entity) => hierarchy::global_matrix(world, entity)? * decal.matrix,
+                    None => decal.matrix,
+                };
+                let (lifetime, fade) = decal.kind.lifetime();
+
+                Some(DecalInstance {
+                    decal_matrix: maths::raw_matrix(matrix),
+                    decal_opacity: 1.0 - ((decal.age - lifetime) / fade).clamp(0.0, 1.0),
+                    decal_shape: decal.kind.shape(),
+                })
+            })
+            .collect_vec();
+        if instances.is_empty() {
+            return Ok(0);
+        }
+
+        if self.resources.is_none() {
+            self.resources = Some(DecalResources::new(assets, display)?);
+        }
+        let resources = self.resources.as_ref().unwrap();
+
+        let slice = resources.instances.slice(0..instances.len()).unwrap();
+        slice.write(&instances);
+
+        target.draw(
+            (&resources.quad, slice.per_instance().unwrap()),
+            &NoIndices(PrimitiveType::TriangleStrip),
+            assets.program(resources.program),
+            &uniform! {
+                vp: maths::raw_matrix(camera.view_projection),
+            },
+            &DrawParameters {
+                depth: Depth {
+                    test: DepthTest::IfLessOrEqual,
+                    write: false,
+                    ..Depth::default()
+                },
+                polygon_offset: PolygonOffset {
+                    factor: -1.0,
+                    units: -4.0,
+                    fill: true,
+                    ..PolygonOffset::default()
+                },
+                blend: Blend::alpha_blending(),
+                ..DrawParameters::default()
+            },
+        )?;
+
+        Ok(1)
+    }
+}
+
+impl Default for DecalSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
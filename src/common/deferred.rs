@@ -0,0 +1,152 @@
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use glium::framebuffer::MultiOutputFrameBuffer;
+use glium::glutin::surface::WindowSurface;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::uniforms::UniformBuffer;
+use glium::{
+    implement_vertex, uniform, Depth, DepthTest, Display, DrawParameters, Program, Surface,
+    VertexBuffer,
+};
+
+use crate::assets::{Assets, Handle};
+use crate::camera::Camera;
+use crate::context::GBuffer;
+use crate::light::LightsBlock;
+use crate::scene::Environment;
+
+/// How a scene is shaded
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Shades each model as it's drawn, including parts that end up hidden behind others
+    #[default]
+    Forward,
+    /// Draws models once into a G-buffer, then shades only the visible pixels
+    Deferred,
+}
+
+#[derive(Copy, Clone)]
+struct ScreenVertex {
+    position: [f32; 2],
+}
+implement_vertex!(ScreenVertex, position);
+
+/// Lighting passes for deferred shading. The geometry pass is drawn by the scene into
+/// `geometry_framebuffer`, then `render_lighting` shades it onto the target.
+pub struct DeferredRenderer {
+    pub geometry_program: Handle<Program>,
+    lighting_program: Handle<Program>,
+    screen_quad: VertexBuffer<ScreenVertex>,
+    gbuffer: Option<GBuffer>,
+}
+
+impl DeferredRenderer {
+    pub fn new(assets: &mut Assets, display: &Display<WindowSurface>) -> Result<Self> {
+        let geometry_program = assets.load_program(
+            "assets/shaders/default/default.vert",
+            "assets/shaders/deferred/geometry.frag",
+            display,
+        )?;
+
+        let lighting_program = assets.load_program(
+            "assets/shaders/deferred/screen.vert",
+            "assets/shaders/deferred/lighting.frag",
+            display,
+        )?;
+
+        let screen_quad = VertexBuffer::new(
+            display,
+            &[[-1.0, -1.0], [1.0, -1.0], [-1.0, 1.0], [1.0, 1.0]]
+                .map(|position| ScreenVertex { position }),
+        )?;
+
+        Ok(Self {
+            geometry_program,
+            lighting_program,
+            screen_quad,
+            gbuffer: None,
+        })
+    }
+
+    /// Recreates the G-buffer if the target has been resized
+    pub fn resize(&mut self, display: &Display<WindowSurface>, size: (u32, u32)) -> Result<()> {
+        if self.gbuffer.as_ref().map(GBuffer::size) != Some(size) {
+            self.gbuffer = Some(GBuffer::new(display, size)?);
+        }
+
+        Ok(())
+    }
+
+    /// Cleared framebuffer for the geometry pass to draw into with `geometry_program`
+    pub fn geometry_framebuffer(
+        &self,
+        display: &Display<WindowSurface>,
+    ) -> Result<MultiOutputFrameBuffer> {
+        let gbuffer = self
+            .gbuffer
+            .as_ref()
+            .ok_or_else(|| eyre!("G-buffer has not been created yet"))?;
+
+        let mut framebuffer = gbuffer.framebuffer(display)?;
+        framebuffer.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+
+        Ok(framebuffer)
+    }
+
+    /// Shades the G-buffer with the environment's sun, ambient light and fog along with the
+    /// scene's lights. The G-buffer's depth is copied to the target so anything drawn afterwards
+    /// is hidden behind the scene.
+    pub fn render_lighting<S: Surface>(
+        &self,
+        target: &mut S,
+        assets: &Assets,
+        camera: &Camera,
+        environment: &Environment,
+        lights: &UniformBuffer<LightsBlock>,
+    ) -> Result<()> {
+        let Some(gbuffer) = self.gbuffer.as_ref() else {
+            return Ok(());
+        };
+
+        let (width, height) = gbuffer.size();
+
+        let sun_color = environment.sun_color * environment.sun_intensity;
+        let ambient_color = environment.ambient_color * environment.ambient_intensity;
+        let sky_color = environment.sky_color;
+        let fog_color = environment.fog_color;
+
+        let uniforms = uniform! {
+            albedo_texture: &gbuffer.albedo,
+            normal_texture: &gbuffer.normal,
+            position_texture: &gbuffer.position,
+            depth_texture: &gbuffer.depth,
+            screen_size: [width as f32, height as f32],
+            camera_position: <[f32; 3]>::from(camera.position),
+            sun_direction: <[f32; 3]>::from(environment.sun_direction),
+            sun_color: [sun_color.red, sun_color.green, sun_color.blue],
+            ambient_color: [ambient_color.red, ambient_color.green, ambient_color.blue],
+            sky_color: [sky_color.red, sky_color.green, sky_color.blue],
+            fog_color: [fog_color.red, fog_color.green, fog_color.blue],
+            fog_density: environment.fog_density,
+            wetness: environment.wetness,
+            Lights: lights,
+        };
+
+        target.draw(
+            &self.screen_quad,
+            NoIndices(PrimitiveType::TriangleStrip),
+            assets.program(self.lighting_program),
+            &uniforms,
+            &DrawParameters {
+                depth: Depth {
+                    test: DepthTest::Overwrite,
+                    write: true,
+                    ..Depth::default()
+                },
+                ..DrawParameters::default()
+            },
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, HashSet};
+
+use crate::uuid::UUID;
+
+/// Components of one type, packed together for fast iteration. `indices` maps each entity to its
+/// component so lookups don't have to search.
+struct Storage<T> {
+    components: Vec<T>,
+    entities: Vec<UUID>,
+    indices: HashMap<UUID, usize>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Self {
+            components: vec![],
+            entities: vec![],
+            indices: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Storage<T> {
+    fn insert(&mut self, entity: UUID, component: T) -> Option<T> {
+        if let Some(&index) = self.indices.get(&entity) {
+            return Some(std::mem::replace(&mut self.components[index], component));
+        }
+
+        self.indices.insert(entity, self.components.len());
+        self.components.push(component);
+        self.entities.push(entity);
+
+        None
+    }
+
+    fn remove(&mut self, entity: UUID) -> Option<T> {
+        let index = self.indices.remove(&entity)?;
+
+        self.entities.swap_remove(index);
+        if let Some(moved) = self.entities.get(index) {
+            self.indices.insert(*moved, index);
+        }
+
+        Some(self.components.swap_remove(index))
+    }
+
+    fn get(&self, entity: UUID) -> Option<&T> {
+        self.indices
+            .get(&entity)
+            .map(|&index| &self.components[index])
+    }
+
+    fn get_mut(&mut self, entity: UUID) -> Option<&mut T> {
+        self.indices
+            .get(&entity)
+            .map(|&index| &mut self.components[index])
+    }
+}
+
+/// Lets storages of different component types be kept together
+trait AnyStorage {
+    fn remove_entity(&mut self, entity: UUID);
+    fn move_entity(&mut self, entity: UUID, to: &mut World);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+    fn remove_entity(&mut self, entity: UUID) {
+        self.remove(entity);
+    }
+
+    fn move_entity(&mut self, entity: UUID, to: &mut World) {
+        if let Some(component) = self.remove(entity) {
+            to.insert(entity, component);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Entities addressed by UUID, each with at most one component of any type. Components are plain
+/// structs, e.g. a `Transform` and a `ModelInstance` make an entity that gets drawn.
+#[derive(Default)]
+pub struct World {
+    entities: HashSet<UUID>,
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an entity without any components
+    pub fn spawn(&mut self) -> UUID {
+        self.spawn_with_uuid(UUID::new())
+    }
+
+    /// Creates an entity with a known ID, e.g. when loading a saved scene
+    pub fn spawn_with_uuid(&mut self, entity: UUID) -> UUID {
+        self.entities.insert(entity);
+        entity
+    }
+
+    /// Removes an entity and all of its components, returning whether it existed
+    pub fn despawn(&mut self, entity: UUID) -> bool {
+        if !self.entities.remove(&entity) {
+            return false;
+        }
+
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(entity);
+        }
+
+        true
+    }
+
+    /// Moves an entity and all of its components into another world as they are, returning
+    /// whether it existed
+    pub fn move_entity(&mut self, entity: UUID, to: &mut World) -> bool {
+        if !self.entities.remove(&entity) {
+            return false;
+        }
+
+        to.entities.insert(entity);
+        for storage in self.storages.values_mut() {
+            storage.move_entity(entity, to);
+        }
+
+        true
+    }
+
+    pub fn contains(&self, entity: UUID) -> bool {
+        self.entities.contains(&entity)
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = UUID> + '_ {
+        self.entities.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+        self.storages.clear();
+    }
+
+    /// Attaches a component to an entity, spawning it if needed. Returns the component of the
+    /// same type the entity already had.
+    pub fn insert<T: 'static>(&mut self, entity: UUID, component: T) -> Option<T> {
+        self.entities.insert(entity);
+
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::<Storage<T>>::default())
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()
+            .unwrap()
+            .insert(entity, component)
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: UUID) -> Option<T> {
+        self.storage_mut::<T>()?.remove(entity)
+    }
+
+    pub fn get<T: 'static>(&self, entity: UUID) -> Option<&T> {
+        self.storage::<T>()?.get(entity)
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: UUID) -> Option<&mut T> {
+        self.storage_mut::<T>()?.get_mut(entity)
+    }
+
+    pub fn has<T: 'static>(&self, entity: UUID) -> bool {
+        self.get::<T>(entity).is_some()
+    }
+
+    /// Every entity with a `T`, in the order the components were added
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = (UUID, &T)> {
+        self.storage::<T>().into_iter().flat_map(|storage| {
+            storage
+                .entities
+                .iter()
+                .copied()
+                .zip(storage.components.iter())
+        })
+    }
+
+    pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = (UUID, &mut T)> {
+        self.storage_mut::<T>().into_iter().flat_map(|storage| {
+            storage
+                .entities
+                .iter()
+                .copied()
+                .zip(storage.components.iter_mut())
+        })
+    }
+
+    /// Every entity with both an `A` and a `B`
+    pub fn query2<A: 'static, B: 'static>(&self) -> impl Iterator<Item = (UUID, &A, &B)> {
+        self.query::<A>()
+            .filter_map(|(entity, a)| Some((entity, a, self.get::<B>(entity)?)))
+    }
+
+    /// Calls `f` with every entity that has both an `A` and a `B`, with the `A` mutable
+    pub fn for_each2_mut<A: 'static, B: 'static>(&mut self, mut f: impl FnMut(UUID, &mut A, &B)) {
+        assert_ne!(
+            TypeId::of::<A>(),
+            TypeId::of::<B>(),
+            "Cannot borrow a component mutably and immutably"
+        );
+
+        // Take B's storage out so that A's can be borrowed mutably alongside it
+        let Some(storage_b) = self.storages.remove(&TypeId::of::<B>()) else {
+            return;
+        };
+
+        if let Some(storage_a) = self.storage_mut::<A>() {
+            let storage_b = storage_b.as_any().downcast_ref::<Storage<B>>().unwrap();
+
+            for (entity, a) in storage_a
+                .entities
+                .iter()
+                .copied()
+                .zip(storage_a.components.iter_mut())
+            {
+                if let Some(b) = storage_b.get(entity) {
+                    f(entity, a, b);
+                }
+            }
+        }
+
+        self.storages.insert(TypeId::of::<B>(), storage_b);
+    }
+
+    fn storage<T: 'static>(&self) -> Option<&Storage<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .map(|storage| storage.as_any().downcast_ref::<Storage<T>>().unwrap())
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> Option<&mut Storage<T>> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())
+            .map(|storage| storage.as_any_mut().downcast_mut::<Storage<T>>().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct Position(i32);
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct Velocity(i32);
+
+    #[test]
+    fn inserting_replaces_and_returns_the_old_component() {
+        let mut world = World::new();
+        let entity = UUID::new();
+
+        assert_eq!(world.insert(entity, Position(1)), None);
+        assert!(world.contains(entity));
+        assert_eq!(world.insert(entity, Position(2)), Some(Position(1)));
+        assert_eq!(world.get::<Position>(entity), Some(&Position(2)));
+        assert!(!world.has::<Velocity>(entity));
+        assert_eq!(world.query::<Position>().count(), 1);
+    }
+
+    #[test]
+    fn removals_keep_the_rest_findable() {
+        let mut rng = fastrand::Rng::with_seed(11);
+        let mut world = World::new();
+        let mut expected = HashMap::new();
+        let entities = (0..100).map(|_| world.spawn()).collect::<Vec<_>>();
+
+        for step in 0..2000 {
+            let entity = entities[rng.usize(..entities.len())];
+            if rng.bool() {
+                world.insert(entity, Position(step));
+                expected.insert(entity, Position(step));
+            } else {
+                assert_eq!(world.remove::<Position>(entity), expected.remove(&entity));
+            }
+        }
+
+        for &entity in entities.iter() {
+            assert_eq!(world.get::<Position>(entity), expected.get(&entity));
+        }
+        let queried = world
+            .query::<Position>()
+            .map(|(entity, position)| (entity, *position))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(queried, expected);
+    }
+
+    #[test]
+    fn despawning_removes_every_component() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        let other = world.spawn();
+        world.insert(entity, Position(1));
+        world.insert(entity, Velocity(2));
+        world.insert(other, Position(3));
+
+        assert!(world.despawn(entity));
+        assert!(!world.despawn(entity));
+        assert!(!world.contains(entity));
+        assert!(!world.has::<Position>(entity));
+        assert!(!world.has::<Velocity>(entity));
+        assert_eq!(world.get::<Position>(other), Some(&Position(3)));
+        assert_eq!(world.len(), 1);
+    }
+
+    #[test]
+    fn queries_visit_components_in_the_order_they_were_added() {
+        let mut world = World::new();
+        let entities = (0..5).map(|_| world.spawn()).collect::<Vec<_>>();
+        for (index, &entity) in entities.iter().enumerate() {
+            world.insert(entity, Position(index as i32));
+        }
+
+        let queried = world
+            .query::<Position>()
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>();
+        assert_eq!(queried, entities);
+    }
+
+    #[test]
+    fn paired_queries_only_visit_entities_with_both() {
+        let mut world = World::new();
+        let both = world.spawn();
+        let position_only = world.spawn();
+        let velocity_only = world.spawn();
+        world.insert(both, Position(1));
+        world.insert(both, Velocity(2));
+        world.insert(position_only, Position(3));
+        world.insert(velocity_only, Velocity(4));
+
+        let pairs = world
+            .query2::<Position, Velocity>()
+            .map(|(entity, position, velocity)| (entity, *position, *velocity))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, [(both, Position(1), Velocity(2))]);
+
+        world.for_each2_mut::<Position, Velocity>(|_, position, velocity| {
+            position.0 += velocity.0;
+        });
+        assert_eq!(world.get::<Position>(both), Some(&Position(3)));
+        assert_eq!(world.get::<Position>(position_only), Some(&Position(3)));
+        // Taken out and put back while iterating
+        assert_eq!(world.get::<Velocity>(velocity_only), Some(&Velocity(4)));
+    }
+
+    #[test]
+    fn moving_an_entity_takes_its_components_along() {
+        let mut world = World::new();
+        let mut other = World::new();
+        let entity = world.spawn();
+        let stays = world.spawn();
+        world.insert(entity, Position(1));
+        world.insert(entity, Velocity(2));
+        world.insert(stays, Position(3));
+
+        assert!(world.move_entity(entity, &mut other));
+        assert!(!world.move_entity(entity, &mut other));
+
+        assert!(!world.contains(entity));
+        assert!(!world.has::<Position>(entity));
+        assert_eq!(world.get::<Position>(stays), Some(&Position(3)));
+        assert!(other.contains(entity));
+        assert_eq!(other.get::<Position>(entity), Some(&Position(1)));
+        assert_eq!(other.get::<Velocity>(entity), Some(&Velocity(2)));
+    }
+}
@@ -0,0 +1,119 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use winit::dpi::PhysicalSize;
+
+/// The window's new size in physical pixels
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WindowResized(pub PhysicalSize<u32>);
+
+/// An action's bindings being pressed or let go, sent on the frame it happens with the action's
+/// name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ActionEvent {
+    Pressed(String),
+    Released(String),
+}
+
+/// Events of one type waiting to be handled, oldest first
+struct Events<T> {
+    events: Vec<T>,
+    /// How many of the oldest events were already waiting at the last `update`
+    stale: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            events: vec![],
+            stale: 0,
+        }
+    }
+}
+
+/// Lets queues of different event types be kept together
+trait AnyEvents {
+    fn update(&mut self);
+    fn clear(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyEvents for Events<T> {
+    fn update(&mut self) {
+        self.events.drain(..self.stale);
+        self.stale = self.events.len();
+    }
+
+    fn clear(&mut self) {
+        self.events.clear();
+        self.stale = 0;
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Queues of events of any type, so systems can react to each other, e.g. to damage being dealt,
+/// without knowing about each other. Events are plain structs, sent from anywhere and drained by
+/// whatever handles them. Events nobody handles are dropped by the second `update` after they were
+/// sent, so every system running each fixed step gets a chance to see them.
+#[derive(Default)]
+pub struct EventBus {
+    queues: HashMap<TypeId, Box<dyn AnyEvents>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send<T: 'static>(&mut self, event: T) {
+        self.queue_mut::<T>().events.push(event);
+    }
+
+    /// Takes every waiting event of a type, oldest first
+    pub fn drain<T: 'static>(&mut self) -> Vec<T> {
+        let queue = self.queue_mut::<T>();
+        queue.stale = 0;
+
+        std::mem::take(&mut queue.events)
+    }
+
+    /// Waiting events of a type, oldest first, leaving them for others to handle
+    pub fn read<T: 'static>(&self) -> impl Iterator<Item = &T> {
+        self.queues
+            .get(&TypeId::of::<T>())
+            .map(|queue| queue.as_any().downcast_ref::<Events<T>>().unwrap())
+            .into_iter()
+            .flat_map(|queue| queue.events.iter())
+    }
+
+    /// Drops events left unhandled since the last update, called after each fixed step
+    pub fn update(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.update();
+        }
+    }
+
+    /// Drops every waiting event, e.g. when the scene is changed
+    pub fn clear(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.clear();
+        }
+    }
+
+    fn queue_mut<T: 'static>(&mut self) -> &mut Events<T> {
+        self.queues
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Events::<T>::default()))
+            .as_any_mut()
+            .downcast_mut::<Events<T>>()
+            .unwrap()
+    }
+}
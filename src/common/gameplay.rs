@@ -0,0 +1,533 @@
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix4, MetricSpace, Point3, SquareMatrix, Transform as _, Vector3,
+};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::billboards::Billboard;
+use crate::collision::TriggerEntered;
+use crate::entity::World;
+use crate::levelgen::PickupKind;
+use crate::model::{ModelInstance, PreviousTransform, Transform};
+use crate::ragdoll::{Ragdoll, RagdollBone};
+use crate::scene::Scene;
+use crate::simulation::Simulation;
+use crate::uuid::UUID;
+use crate::weapons::Hit;
+
+/// Seconds a ragdoll lies around before its entity is despawned, unless it respawns first
+const CORPSE_LIFETIME: f32 = 10.0;
+
+/// Thickness of every ragdoll limb
+const CORPSE_BONE_RADIUS: f32 = 0.1;
+
+/// Speed a ragdoll's nearest joint is knocked at per point of damage dealt to it
+const CORPSE_KNOCKBACK: f32 = 0.2;
+
+/// Seconds a revived ragdoll takes to ease back into its animation
+const RAGDOLL_BLEND_TIME: f32 = 0.4;
+
+/// What happens to an entity once its health runs out
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeathBehavior {
+    #[default]
+    Despawn,
+    /// Goes limp as a `Ragdoll`, falling under physics before being despawned. Only skinned models
+    /// can, others are despawned straight away.
+    Ragdoll,
+}
+
+/// Component for entities that can be damaged
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+    pub on_death: DeathBehavior,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            on_death: DeathBehavior::default(),
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+/// Component bringing its entity back at one of the simulation's spawn points `delay` seconds
+/// after it dies, instead of despawning it. With no spawn points it comes back where it died.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Respawn {
+    pub delay: f32,
+    /// How far above the spawn point the entity's origin is put, as spawn points are on the floor
+    pub height: f32,
+}
+
+/// Health being taken off an entity, sent through the scene's events by whatever hurt it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Damage {
+    pub entity: UUID,
+    pub amount: f32,
+    pub point: Point3<f32>,
+    /// Direction the damage was dealt in, which ragdolls are knocked along
+    pub direction: Vector3<f32>,
+    /// Who dealt it, `None` for the player
+    pub source: Option<UUID>,
+}
+
+impl From<Hit> for Damage {
+    fn from(hit: Hit) -> Self {
+        Self {
+            entity: hit.entity,
+            amount: hit.damage,
+            point: hit.point,
+            direction: hit.direction,
+            source: hit.source,
+        }
+    }
+}
+
+/// An entity's health running out, sent through the scene's events
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Death {
+    pub entity: UUID,
+    pub position: Point3<f32>,
+    pub source: Option<UUID>,
+}
+
+/// A dead entity coming back, sent through the scene's events
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Respawned {
+    pub entity: UUID,
+    pub position: Point3<f32>,
+}
+
+/// Component for things taken by walking into them. Its entity also needs a `Trigger` to notice
+/// that, usually with `CollisionLayers::PICKUP` so only players can.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pickup {
+    pub kind: PickupKind,
+    /// Health given back, or how much of whatever else it gives
+    pub amount: f32,
+}
+
+/// A pickup being taken, sent through the scene's events once it's been despawned
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PickedUp {
+    pub pickup: UUID,
+    pub kind: PickupKind,
+    pub amount: f32,
+    /// Who took it, `None` for the player
+    pub entity: Option<UUID>,
+}
+
+/// Component keeping its entity's `Billboard` filled to how much `Health` it has left, hidden
+/// while it's dead
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HealthBar;
+
+/// Component on entities that have died and are waiting to respawn or be despawned, which other
+/// systems such as AI leave alone
+pub struct Dead {
+    /// Seconds until the entity respawns or is despawned
+    pub remaining: f32,
+    respawn: bool,
+    /// Taken off the entity to hide it until it respawns, unless it's lying around as a ragdoll
+    model: Option<ModelInstance>,
+}
+
+impl Dead {
+    /// The model hidden until the entity respawns
+    pub fn model(&self) -> Option<&ModelInstance> {
+        self.model.as_ref()
+    }
+}
+
+/// Component making a dead skinned entity fall limp, drawn with its skeleton following the
+/// entity's `Ragdoll` rather than its animation
+pub struct Corpse {
+    /// Joint of the skin each ragdoll bone was made from
+    joints: Vec<usize>,
+    /// Skinning matrices of the pose the entity died in
+    death_bones: Vec<Matrix4<f32>>,
+    /// The entity's transform when it died, which the ragdoll is simulated in world space from
+    model_matrix: Matrix4<f32>,
+}
+
+impl Corpse {
+    /// Skinning matrix of each joint, moving the pose the entity died in along with its ragdoll
+    pub fn bone_matrices(&self, ragdoll: &Ragdoll) -> Vec<Matrix4<f32>> {
+        let inverse = self.model_matrix.invert().unwrap_or(Matrix4::identity());
+        let mut matrices = self.death_bones.clone();
+
+        for (delta, &joint) in ragdoll.bone_matrices().iter().zip(self.joints.iter()) {
+            matrices[joint] = inverse * delta * self.model_matrix * self.death_bones[joint];
+        }
+
+        matrices
+    }
+}
+
+/// Component easing a revived entity's skeleton from where its ragdoll lay back into its
+/// animation, removed once it's done
+pub struct RagdollBlend {
+    /// Skinning matrices the ragdoll was left in
+    from: Vec<Matrix4<f32>>,
+    elapsed: f32,
+}
+
+impl RagdollBlend {
+    /// The animation's skinning matrices mixed with the ragdoll's by how far through it is.
+    /// Matrices are mixed component by component, which only holds up as it's brief.
+    pub fn bone_matrices(&self, animated: &[Matrix4<f32>]) -> Vec<Matrix4<f32>> {
+        let t = (self.elapsed / RAGDOLL_BLEND_TIME).clamp(0.0, 1.0);
+
+        animated
+            .iter()
+            .zip(self.from.iter())
+            .map(|(animated, from)| *from * (1.0 - t) + *animated * t)
+            .collect()
+    }
+}
+
+/// Advances by a fixed step, taking the damage sent this step off each entity's `Health`,
+/// handling deaths and pickups, moving respawn timers and ragdoll blends along and updating health
+/// bars. Ragdolls themselves are moved by physics.
+pub fn update(scene: &mut Scene, deltatime: f32) {
+    for damage in scene.events.drain::<Damage>() {
+        apply_damage(scene, &damage, deltatime);
+    }
+
+    let entered = scene.events.read::<TriggerEntered>().copied().collect_vec();
+    for TriggerEntered { trigger, entity } in entered {
+        take_pickup(scene, trigger, entity);
+    }
+
+    let blended = scene
+        .simulation
+        .world
+        .query_mut::<RagdollBlend>()
+        .filter_map(|(entity, blend)| {
+            blend.elapsed += deltatime;
+            (blend.elapsed >= RAGDOLL_BLEND_TIME).then_some(entity)
+        })
+        .collect_vec();
+    for entity in blended {
+        scene.simulation.world.remove::<RagdollBlend>(entity);
+    }
+
+    let finished = scene
+        .simulation
+        .world
+        .query_mut::<Dead>()
+        .filter_map(|(entity, dead)| {
+            dead.remaining -= deltatime;
+            (dead.remaining <= 0.0).then_some(entity)
+        })
+        .collect_vec();
+
+    for entity in finished {
+        let dead = scene.simulation.world.remove::<Dead>(entity).unwrap();
+        if dead.respawn {
+            respawn(scene, entity, dead);
+        } else {
+            scene.simulation.despawn(entity);
+        }
+    }
+
+    update_health_bars(&mut scene.simulation.world);
+}
+
+/// Brings a dead entity back where it is, without a `Respawned` event, e.g. when loading a save
+/// from before it died. Ragdolls ease back into their animation.
+pub fn revive(simulation: &mut Simulation, entity: UUID) {
+    let world = &mut simulation.world;
+    let Some(dead) = world.remove::<Dead>(entity) else {
+        return;
+    };
+
+    remove_corpse(world, entity, true);
+    if let Some(model) = dead.model {
+        world.insert(entity, model);
+    }
+}
+
+/// Leaves an entity dead for `remaining` seconds without a `Death` event, e.g. when loading a save
+/// from after it died. Those that don't respawn are despawned, as their corpse isn't kept.
+pub fn kill(simulation: &mut Simulation, entity: UUID, remaining: f32) {
+    let world = &mut simulation.world;
+    if let Some(dead) = world.get_mut::<Dead>(entity) {
+        dead.remaining = remaining;
+        return;
+    }
+    if !world.has::<Respawn>(entity) {
+        simulation.despawn(entity);
+        return;
+    }
+
+    let model = world.remove::<ModelInstance>(entity);
+    world.insert(
+        entity,
+        Dead {
+            remaining,
+            respawn: true,
+            model,
+        },
+    );
+}
+
+/// One of the simulation's spawn points, as far as possible from whatever is at `avoid`, or a
+/// random one if there's nothing to avoid
+pub fn spawn_point(simulation: &Simulation, avoid: &[Point3<f32>]) -> Option<Point3<f32>> {
+    if avoid.is_empty() {
+        return fastrand::choice(simulation.spawn_points.iter()).copied();
+    }
+
+    let nearest = |point: Point3<f32>| {
+        avoid
+            .iter()
+            .map(|other| point.distance2(*other))
+            .fold(f32::INFINITY, f32::min)
+    };
+
+    simulation
+        .spawn_points
+        .iter()
+        .copied()
+        .max_by(|&a, &b| nearest(a).total_cmp(&nearest(b)))
+}
+
+fn apply_damage(scene: &mut Scene, damage: &Damage, deltatime: f32) {
+    let world = &mut scene.simulation.world;
+
+    if let Some(ragdoll) = world.get_mut::<Ragdoll>(damage.entity) {
+        knock(ragdoll, damage);
+    }
+    if world.has::<Dead>(damage.entity) {
+        return;
+    }
+
+    let Some(health) = world.get_mut::<Health>(damage.entity) else {
+        return;
+    };
+    health.current -= damage.amount;
+    if !health.is_dead() {
+        return;
+    }
+
+    let on_death = health.on_death;
+    let position = world
+        .get::<Transform>(damage.entity)
+        .map_or(damage.point, |transform| {
+            Point3::from_vec(transform.translation)
+        });
+    scene.events.send(Death {
+        entity: damage.entity,
+        position,
+        source: damage.source,
+    });
+
+    let respawn = scene
+        .simulation
+        .world
+        .get::<Respawn>(damage.entity)
+        .copied();
+    let corpse = match on_death {
+        DeathBehavior::Ragdoll => ragdoll(&scene.simulation, damage, deltatime),
+        DeathBehavior::Despawn => None,
+    };
+
+    let world = &mut scene.simulation.world;
+    let model = match corpse {
+        Some((corpse, ragdoll)) => {
+            world.insert(damage.entity, corpse);
+            world.insert(damage.entity, ragdoll);
+            None
+        }
+        None if respawn.is_some() => world.remove::<ModelInstance>(damage.entity),
+        None => {
+            scene.simulation.despawn(damage.entity);
+            return;
+        }
+    };
+
+    world.insert(
+        damage.entity,
+        Dead {
+            remaining: respawn.map_or(CORPSE_LIFETIME, |respawn| respawn.delay),
+            respawn: respawn.is_some(),
+            model,
+        },
+    );
+}
+
+/// Fills the billboards of entities with `HealthBar` with how much health they have left, hiding
+/// those of the dead
+fn update_health_bars(world: &mut World) {
+    let bars = world
+        .query2::<HealthBar, Health>()
+        .map(|(entity, _, health)| {
+            (
+                entity,
+                health.current / health.max,
+                !world.has::<Dead>(entity),
+            )
+        })
+        .collect_vec();
+
+    for (entity, fill, visible) in bars {
+        if let Some(billboard) = world.get_mut::<Billboard>(entity) {
+            billboard.fill = fill;
+            billboard.visible = visible;
+        }
+    }
+}
+
+/// Despawns a pickup that was walked into, healing whoever took it if it gives health and they
+/// have any
+fn take_pickup(scene: &mut Scene, pickup: UUID, entity: Option<UUID>) {
+    let world = &mut scene.simulation.world;
+    let Some(&Pickup { kind, amount }) = world.get::<Pickup>(pickup) else {
+        return;
+    };
+    let dead = entity.is_some_and(|entity| world.has::<Dead>(entity));
+    if dead {
+        return;
+    }
+
+    if let (PickupKind::Health, Some(health)) = (
+        kind,
+        entity.and_then(|entity| world.get_mut::<Health>(entity)),
+    ) {
+        health.current = (health.current + amount).min(health.max);
+    }
+
+    scene.simulation.despawn(pickup);
+    scene.events.send(PickedUp {
+        pickup,
+        kind,
+        amount,
+        entity,
+    });
+}
+
+/// Builds a ragdoll from the pose a skinned entity died in, carrying on with the momentum it had
+/// and knocked by what killed it
+fn ragdoll(simulation: &Simulation, damage: &Damage, deltatime: f32) -> Option<(Corpse, Ragdoll)> {
+    let world = &simulation.world;
+    let model = &world.get::<ModelInstance>(damage.entity)?.model;
+    let skeleton = model.skeleton.as_ref()?;
+    let model_matrix = simulation.global_matrix(damage.entity)?;
+
+    let pose = simulation.pose(damage.entity)?;
+    let positions = skeleton.joint_positions(&pose);
+    let parents = skeleton.joint_parents();
+
+    // Ragdolls need parents before their children
+    let depth =
+        |joint: usize| std::iter::successors(parents[joint], |&parent| parents[parent]).count();
+    let joints = (0..parents.len())
+        .sorted_by_key(|&joint| depth(joint))
+        .collect_vec();
+    let bone_of = |joint: usize| joints.iter().position(|&other| other == joint);
+
+    let bones = joints
+        .iter()
+        .map(|&joint| RagdollBone {
+            name: skeleton.nodes[skeleton.joints[joint]]
+                .name
+                .clone()
+                .unwrap_or_default(),
+            position: model_matrix.transform_point(positions[joint]),
+            parent: parents[joint].and_then(bone_of),
+            radius: CORPSE_BONE_RADIUS,
+        })
+        .collect_vec();
+
+    let velocity = match (
+        world.get::<Transform>(damage.entity),
+        world.get::<PreviousTransform>(damage.entity),
+    ) {
+        (Some(transform), Some(PreviousTransform(previous))) if deltatime > 0.0 => {
+            (transform.translation - previous.translation) / deltatime
+        }
+        _ => Vector3::new(0.0, 0.0, 0.0),
+    };
+
+    let mut ragdoll = Ragdoll::new(bones, velocity);
+    knock(&mut ragdoll, damage);
+
+    let corpse = Corpse {
+        joints,
+        death_bones: skeleton.bone_matrices(&pose),
+        model_matrix,
+    };
+
+    Some((corpse, ragdoll))
+}
+
+/// Pushes the bone of a ragdoll nearest to where it was damaged
+fn knock(ragdoll: &mut Ragdoll, damage: &Damage) {
+    let nearest = ragdoll.nearest_bone(damage.point);
+
+    if let (Some(bone), true) = (nearest, damage.direction.magnitude2() > 0.0) {
+        let velocity = damage.direction.normalize() * damage.amount * CORPSE_KNOCKBACK;
+        ragdoll.apply_impulse(bone, velocity);
+    }
+}
+
+/// Takes a dead entity's ragdoll away, handing its skeleton back to its animation. With `blend`
+/// it eases back from wherever the ragdoll lay rather than snapping.
+fn remove_corpse(world: &mut World, entity: UUID, blend: bool) {
+    let corpse = world.remove::<Corpse>(entity);
+    let ragdoll = world.remove::<Ragdoll>(entity);
+
+    if let (true, Some(corpse), Some(ragdoll)) = (blend, corpse, ragdoll) {
+        world.insert(
+            entity,
+            RagdollBlend {
+                from: corpse.bone_matrices(&ragdoll),
+                elapsed: 0.0,
+            },
+        );
+    }
+}
+
+/// Brings a dead entity back at full health at a spawn point, or getting up where it lay if there
+/// are none
+fn respawn(scene: &mut Scene, entity: UUID, dead: Dead) {
+    let height = scene
+        .simulation
+        .world
+        .get::<Respawn>(entity)
+        .map_or(0.0, |respawn| respawn.height);
+    let position =
+        spawn_point(&scene.simulation, &[]).map(|point| point + Vector3::unit_y() * height);
+
+    let world = &mut scene.simulation.world;
+    remove_corpse(world, entity, position.is_none());
+    if let Some(model) = dead.model {
+        world.insert(entity, model);
+    }
+    if let Some(health) = world.get_mut::<Health>(entity) {
+        health.current = health.max;
+    }
+
+    let Some(transform) = world.get_mut::<Transform>(entity) else {
+        return;
+    };
+    if let Some(position) = position {
+        transform.translation = position.to_vec();
+        // Jumping straight there rather than being interpolated across the level
+        let transform = transform.clone();
+        world.insert(entity, PreviousTransform(transform));
+    }
+
+    let position = Point3::from_vec(world.get::<Transform>(entity).unwrap().translation);
+    scene.events.send(Respawned { entity, position });
+}
@@ -0,0 +1,152 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{Matrix4, SquareMatrix};
+use itertools::Itertools;
+
+use crate::entity::World;
+use crate::model::{ModelInstance, Transform};
+use crate::uuid::UUID;
+
+/// Component making its entity's `Transform` relative to another entity, e.g. a weapon held in a
+/// hand or a turret on a vehicle. Physics moves bodies in world space, so entities with a
+/// `RigidBody` shouldn't have one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Parent {
+    pub entity: UUID,
+    /// Index into the parent's model nodes to follow instead of the parent itself, in its rest
+    /// pose
+    pub node: Option<usize>,
+}
+
+/// Component holding the world matrix of an entity with a `Parent`, kept up to date by
+/// `Hierarchy::update`. Entities without a parent are placed by their `Transform` alone.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlobalTransform(pub Matrix4<f32>);
+
+/// Works out the world matrices of parented entities, only recomputing those whose own, parent's
+/// or ancestor's transform has changed since the last update
+#[derive(Default)]
+pub struct Hierarchy {
+    /// Parent and local transform each parented entity's world matrix was last made from
+    cache: HashMap<UUID, (Parent, Transform)>,
+    /// Transform of each entity at the top of a hierarchy at the last update
+    roots: HashMap<UUID, Option<Transform>>,
+}
+
+impl Hierarchy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the `GlobalTransform` of every entity with a `Parent`, parents before children
+    pub fn update(&mut self, world: &mut World) {
+        let parented = world
+            .query::<Parent>()
+            .map(|(entity, parent)| (entity, *parent))
+            .sorted_by_key(|&(entity, _)| depth(world, entity))
+            .collect_vec();
+
+        self.cache.retain(|&entity, _| world.has::<Parent>(entity));
+
+        // Entities whose world matrix changed this update, dirtying everything below them
+        let mut moved = HashSet::new();
+        let roots: HashSet<UUID> = parented
+            .iter()
+            .map(|(_, parent)| parent.entity)
+            .filter(|&entity| !world.has::<Parent>(entity))
+            .collect();
+        self.roots.retain(|entity, _| roots.contains(entity));
+        for root in roots {
+            let transform = world.get::<Transform>(root).cloned();
+            if self.roots.get(&root) != Some(&transform) {
+                moved.insert(root);
+                self.roots.insert(root, transform);
+            }
+        }
+
+        for (entity, parent) in parented {
+            let Some(transform) = world.get::<Transform>(entity) else {
+                continue;
+            };
+
+            let dirty = moved.contains(&parent.entity)
+                || !world.has::<GlobalTransform>(entity)
+                || self.cache.get(&entity) != Some(&(parent, transform.clone()));
+            if !dirty {
+                continue;
+            }
+
+            let transform = transform.clone();
+            let global = parent_matrix(world, parent) * Matrix4::from(transform.clone());
+            world.insert(entity, GlobalTransform(global));
+            self.cache.insert(entity, (parent, transform));
+            moved.insert(entity);
+        }
+    }
+}
+
+/// An entity's world matrix, from its `GlobalTransform` if it has a parent or its `Transform`
+/// otherwise
+pub fn global_matrix(world: &World, entity: UUID) -> Option<Matrix4<f32>> {
+    if world.has::<Parent>(entity) {
+        if let Some(GlobalTransform(global)) = world.get::<GlobalTransform>(entity) {
+            return Some(*global);
+        }
+    }
+
+    world
+        .get::<Transform>(entity)
+        .map(|transform| Matrix4::from(transform.clone()))
+}
+
+/// Entities whose parent is `entity`
+pub fn children(world: &World, entity: UUID) -> impl Iterator<Item = UUID> + '_ {
+    world
+        .query::<Parent>()
+        .filter(move |(_, parent)| parent.entity == entity)
+        .map(|(child, _)| child)
+}
+
+/// Whether `ancestor` is `entity` or one of its parents, however far up
+pub fn is_ancestor(world: &World, ancestor: UUID, entity: UUID) -> bool {
+    let mut current = Some(entity);
+
+    while let Some(entity) = current {
+        if entity == ancestor {
+            return true;
+        }
+
+        current = world.get::<Parent>(entity).map(|parent| parent.entity);
+    }
+
+    false
+}
+
+/// The world matrix a child's `Transform` is relative to
+pub fn parent_matrix(world: &World, parent: Parent) -> Matrix4<f32> {
+    global_matrix(world, parent.entity).unwrap_or(Matrix4::identity()) * node_matrix(world, parent)
+}
+
+/// Where the node a child follows is within its parent, or identity if it follows the parent
+pub fn node_matrix(world: &World, parent: Parent) -> Matrix4<f32> {
+    parent
+        .node
+        .and_then(|node| {
+            let model_instance = world.get::<ModelInstance>(parent.entity)?;
+            Some(model_instance.model.nodes.get(node)?.global)
+        })
+        .unwrap_or(Matrix4::identity())
+}
+
+/// Number of parents above an entity
+fn depth(world: &World, entity: UUID) -> usize {
+    let mut depth = 0;
+    let mut current = entity;
+
+    while let Some(parent) = world.get::<Parent>(current) {
+        depth += 1;
+        current = parent.entity;
+    }
+
+    depth
+}
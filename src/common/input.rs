@@ -1,25 +1,200 @@
-use cgmath::{Vector2, Zero};
-use log::warn;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use cgmath::{InnerSpace, Vector2, Zero};
+use color_eyre::Result;
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use itertools::Itertools;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
 use winit::dpi::PhysicalPosition;
-use winit::event::{DeviceEvent, Event, MouseButton, WindowEvent};
+use winit::event::{DeviceEvent, Event, MouseButton, MouseScrollDelta, WindowEvent};
 use winit::window::WindowId;
 use winit::{
     event::{ElementState, KeyEvent},
     keyboard::{KeyCode, NativeKeyCode, PhysicalKey},
 };
 
+use crate::events::ActionEvent;
+
 const NUM_KEYS: usize = 194;
 const NUM_MOUSE_BUTTONS: usize = 6;
 
+/// Something that can trigger an action
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// A button on any connected gamepad
+    Gamepad(Button),
+}
+
+/// Maps named actions such as `"move_forward"` to the keys and buttons that trigger them, so
+/// controls can be rebound without touching the code that reads them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ActionMap {
+    bindings: HashMap<String, Vec<Binding>>,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        let mut action_map = Self {
+            bindings: HashMap::new(),
+        };
+
+        action_map.bind("move_forward", Binding::Key(KeyCode::KeyW));
+        action_map.bind("move_backward", Binding::Key(KeyCode::KeyS));
+        action_map.bind("move_left", Binding::Key(KeyCode::KeyA));
+        action_map.bind("move_right", Binding::Key(KeyCode::KeyD));
+        action_map.bind("fire", Binding::Mouse(MouseButton::Left));
+        action_map.bind("fire", Binding::Gamepad(Button::RightTrigger2));
+        action_map.bind("aim", Binding::Mouse(MouseButton::Right));
+        action_map.bind("aim", Binding::Gamepad(Button::LeftTrigger2));
+        action_map.bind("pick", Binding::Mouse(MouseButton::Left));
+        action_map.bind("pan", Binding::Mouse(MouseButton::Middle));
+        action_map.bind("grab_viewport", Binding::Mouse(MouseButton::Middle));
+        action_map.bind("grab_viewport", Binding::Key(KeyCode::Space));
+        action_map.bind("toggle_view_mode", Binding::Key(KeyCode::KeyV));
+        action_map.bind("toggle_cursor_capture", Binding::Key(KeyCode::Tab));
+        action_map.bind("toggle_debug_overlay", Binding::Key(KeyCode::F3));
+        action_map.bind("toggle_editor_panel", Binding::Key(KeyCode::F1));
+        action_map.bind("toggle_log", Binding::Key(KeyCode::F2));
+        action_map.bind("cycle_debug_view", Binding::Key(KeyCode::F4));
+        action_map.bind("toggle_console", Binding::Key(KeyCode::Backquote));
+        // Along with either Alt key
+        action_map.bind("toggle_fullscreen", Binding::Key(KeyCode::Enter));
+        action_map.bind("screenshot", Binding::Key(KeyCode::F12));
+        action_map.bind("toggle_recording", Binding::Key(KeyCode::F11));
+        action_map.bind("toggle_pause", Binding::Key(KeyCode::KeyP));
+        action_map.bind("step_frame", Binding::Key(KeyCode::Period));
+        action_map.bind("toggle_slow_motion", Binding::Key(KeyCode::Comma));
+        action_map.bind("quick_save", Binding::Key(KeyCode::F5));
+        action_map.bind("quick_load", Binding::Key(KeyCode::F9));
+        action_map.bind("quit", Binding::Key(KeyCode::Escape));
+
+        action_map
+    }
+}
+
+impl ActionMap {
+    /// Reads bindings from a TOML file where each action is a list of bindings, e.g.
+    /// `move_forward = [{ Key = "KeyW" }, { Gamepad = "DPadUp" }]`. Actions missing from the file
+    /// keep their default bindings.
+    pub fn load(path: &Path) -> Result<Self> {
+        let loaded = toml::from_str::<Self>(&std::fs::read_to_string(path)?)?;
+
+        let mut action_map = Self::default();
+        action_map.bindings.extend(loaded.bindings);
+
+        Ok(action_map)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// Adds a binding to an action, keeping any it already has
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let bindings = self.bindings.entry(action.to_owned()).or_default();
+
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Replaces every binding of an action
+    pub fn rebind(&mut self, action: &str, bindings: Vec<Binding>) {
+        self.bindings.insert(action.to_owned(), bindings);
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.bindings.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Names of every action with bindings
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        self.bindings.keys().map(String::as_str)
+    }
+}
+
 pub struct Input {
+    pub action_map: ActionMap,
     key_states: [KeyState; NUM_KEYS],
     mouse_button_states: [KeyState; NUM_MOUSE_BUTTONS],
     last_cursor_position: Option<PhysicalPosition<f64>>,
     window_offset: Vector2<f32>,
     device_offset: Vector2<f32>,
+    scroll_delta: f32,
+    /// Radians turned per pixel the mouse moves
+    pub cursor_sensitivity: f64,
+    /// Mouse motion is only read from the device while the cursor is captured
+    cursor_captured: bool,
+    /// `None` if gamepads aren't supported on this platform
+    gilrs: Option<Gilrs>,
+    /// By the index of their `GamepadId`, so replayed gamepads can be stored as well
+    gamepads: HashMap<usize, GamepadState>,
+}
+
+/// The devices a local player's input comes from, so several can play on one machine
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputDevices {
+    pub keyboard_mouse: bool,
+    /// By the index of their `GamepadId`
+    pub gamepads: Vec<usize>,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+impl InputDevices {
+    pub fn gamepad(gamepad: GamepadId) -> Self {
+        Self {
+            keyboard_mouse: false,
+            gamepads: vec![gamepad.into()],
+        }
+    }
+}
+
+/// Analog sticks found on most controllers
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+impl Stick {
+    fn axes(self) -> (Axis, Axis) {
+        match self {
+            Stick::Left => (Axis::LeftStickX, Axis::LeftStickY),
+            Stick::Right => (Axis::RightStickX, Axis::RightStickY),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct GamepadState {
+    button_states: HashMap<Button, KeyState>,
+    axis_values: HashMap<Axis, f32>,
+}
+
+impl GamepadState {
+    fn button_state(&self, button: Button) -> KeyState {
+        self.button_states
+            .get(&button)
+            .copied()
+            .unwrap_or(KeyState::Released)
+    }
+
+    /// Without the deadzone
+    fn stick(&self, stick: Stick) -> Vector2<f32> {
+        let (x_axis, y_axis) = stick.axes();
+        let axis = |axis| self.axis_values.get(&axis).copied().unwrap_or(0.0);
+
+        Vector2::new(axis(x_axis), axis(y_axis))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
 enum KeyState {
     Released,
     Pressed,
@@ -35,15 +210,80 @@ impl Default for Input {
 
 impl Input {
     pub fn new() -> Self {
-        Self {
+        let mut input = Self {
+            action_map: ActionMap::default(),
             key_states: [KeyState::Released; NUM_KEYS],
             mouse_button_states: [KeyState::Released; NUM_MOUSE_BUTTONS],
             last_cursor_position: None,
             window_offset: Vector2::zero(),
             device_offset: Vector2::zero(),
+            scroll_delta: 0.0,
+            cursor_sensitivity: Self::DEFAULT_CURSOR_SENSITIVITY,
+            cursor_captured: false,
+            gilrs: None,
+            gamepads: HashMap::new(),
+        };
+
+        match Gilrs::new() {
+            Ok(gilrs) => {
+                for (id, _) in gilrs.gamepads() {
+                    input.gamepads.insert(id.into(), GamepadState::default());
+                }
+
+                input.gilrs = Some(gilrs);
+            }
+            Err(error) => warn!("Gamepads are not supported: {}", error),
+        }
+
+        input
+    }
+
+    pub fn with_action_map(action_map: ActionMap) -> Self {
+        Self {
+            action_map,
+            ..Self::new()
         }
     }
 
+    /// Whether any binding of the action was pressed this frame
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.action_map
+            .bindings(action)
+            .iter()
+            .any(|binding| self.binding_state(*binding) == KeyState::Pressed)
+    }
+
+    pub fn action_down(&self, action: &str) -> bool {
+        self.action_map.bindings(action).iter().any(|binding| {
+            let state = self.binding_state(*binding);
+            state == KeyState::Pressed || state == KeyState::Repeat
+        })
+    }
+
+    pub fn action_just_released(&self, action: &str) -> bool {
+        self.action_map
+            .bindings(action)
+            .iter()
+            .any(|binding| self.binding_state(*binding) == KeyState::JustReleased)
+    }
+
+    /// Every action pressed or released this frame, to be sent through an `EventBus`
+    pub fn action_events(&self) -> Vec<ActionEvent> {
+        self.action_map
+            .actions()
+            .flat_map(|action| {
+                let pressed = self
+                    .action_pressed(action)
+                    .then(|| ActionEvent::Pressed(action.to_owned()));
+                let released = self
+                    .action_just_released(action)
+                    .then(|| ActionEvent::Released(action.to_owned()));
+
+                pressed.into_iter().chain(released)
+            })
+            .collect()
+    }
+
     pub fn key_pressed(&self, key_code: KeyCode) -> bool {
         self.key_states[key_code as usize] == KeyState::Pressed
     }
@@ -87,15 +327,256 @@ impl Input {
         self.device_offset
     }
 
-    pub fn reset_internal_state(&mut self) {
-        for key_state in self.key_states.iter_mut() {
-            if *key_state == KeyState::JustReleased {
-                *key_state = KeyState::Released;
+    /// Pixels from the top left of the window, once the cursor has moved over it
+    pub fn cursor_position(&self) -> Option<Vector2<f32>> {
+        self.last_cursor_position
+            .map(|position| Vector2::new(position.x as f32, position.y as f32))
+    }
+
+    /// Should match whether the window has captured the cursor
+    pub fn set_cursor_captured(&mut self, cursor_captured: bool) {
+        self.cursor_captured = cursor_captured;
+    }
+
+    /// Lines scrolled since the last reset, positive when scrolling up / away from the user
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    /// Connected gamepads, in no particular order
+    pub fn gamepads(&self) -> impl Iterator<Item = GamepadId> + '_ {
+        self.gilrs
+            .iter()
+            .flat_map(|gilrs| gilrs.gamepads().map(|(id, _)| id))
+    }
+
+    pub fn gamepad_button_pressed(&self, gamepad: GamepadId, button: Button) -> bool {
+        self.gamepad_button_state(gamepad, button) == KeyState::Pressed
+    }
+
+    pub fn gamepad_button_down(&self, gamepad: GamepadId, button: Button) -> bool {
+        let state = self.gamepad_button_state(gamepad, button);
+        state == KeyState::Pressed || state == KeyState::Repeat
+    }
+
+    pub fn gamepad_button_just_released(&self, gamepad: GamepadId, button: Button) -> bool {
+        self.gamepad_button_state(gamepad, button) == KeyState::JustReleased
+    }
+
+    /// Raw axis value from -1 to 1, without a deadzone
+    pub fn gamepad_axis(&self, gamepad: GamepadId, axis: Axis) -> f32 {
+        self.gamepads
+            .get(&usize::from(gamepad))
+            .and_then(|state| state.axis_values.get(&axis))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Position of a stick on one gamepad with the deadzone removed, positive Y being up
+    pub fn gamepad_stick(&self, gamepad: GamepadId, stick: Stick) -> Vector2<f32> {
+        self.gamepads
+            .get(&usize::from(gamepad))
+            .map_or(Vector2::zero(), |state| {
+                Self::apply_deadzone(state.stick(stick))
+            })
+    }
+
+    /// The furthest pushed of a stick across every connected gamepad
+    pub fn stick(&self, stick: Stick) -> Vector2<f32> {
+        self.gamepads
+            .values()
+            .map(|state| Self::apply_deadzone(state.stick(stick)))
+            .max_by(|a, b| a.magnitude2().total_cmp(&b.magnitude2()))
+            .unwrap_or(Vector2::zero())
+    }
+
+    /// Hands out connected devices to `players` local players. The first player gets the
+    /// keyboard and mouse, and gamepads go to each player in turn, starting with the first player
+    /// if there's a gamepad for everyone or the second if not.
+    pub fn assign_devices(&self, players: usize) -> Vec<InputDevices> {
+        let mut devices = vec![InputDevices::default(); players];
+        let Some(first) = devices.first_mut() else {
+            return devices;
+        };
+        first.keyboard_mouse = true;
+
+        let gamepads = self.gamepads.keys().copied().sorted();
+        let skip = usize::from(gamepads.len() < players);
+        for (player, gamepad) in devices.iter_mut().skip(skip).zip(gamepads) {
+            player.gamepads.push(gamepad);
+        }
+
+        devices
+    }
+
+    /// A copy of the input only seeing `devices`, so one player's controls can be read on their
+    /// own by anything that reads an `Input`, e.g. to move their camera. Bindings are kept, and
+    /// the copy can't read devices itself.
+    pub fn routed(&self, devices: &InputDevices) -> Input {
+        let mut input = Self {
+            action_map: self.action_map.clone(),
+            key_states: [KeyState::Released; NUM_KEYS],
+            mouse_button_states: [KeyState::Released; NUM_MOUSE_BUTTONS],
+            last_cursor_position: None,
+            window_offset: Vector2::zero(),
+            device_offset: Vector2::zero(),
+            scroll_delta: 0.0,
+            cursor_sensitivity: self.cursor_sensitivity,
+            cursor_captured: self.cursor_captured,
+            gilrs: None,
+            gamepads: self
+                .gamepads
+                .iter()
+                .filter(|(index, _)| devices.gamepads.contains(index))
+                .map(|(index, state)| (*index, state.clone()))
+                .collect(),
+        };
+
+        if devices.keyboard_mouse {
+            input.key_states = self.key_states;
+            input.mouse_button_states = self.mouse_button_states;
+            input.last_cursor_position = self.last_cursor_position;
+            input.window_offset = self.window_offset;
+            input.device_offset = self.device_offset;
+            input.scroll_delta = self.scroll_delta;
+        }
+
+        input
+    }
+
+    /// Everything read from the keyboard, mouse and gamepads this frame
+    pub fn frame(&self, deltatime: f64, frame_time: f64) -> InputFrame {
+        let held = |states: &[KeyState]| {
+            states
+                .iter()
+                .enumerate()
+                .filter(|(_, state)| **state != KeyState::Released)
+                .map(|(index, state)| (index, *state))
+                .collect()
+        };
+
+        InputFrame {
+            deltatime,
+            frame_time,
+            keys: held(&self.key_states),
+            mouse_buttons: held(&self.mouse_button_states),
+            cursor_position: self
+                .last_cursor_position
+                .map(|position| (position.x, position.y)),
+            window_offset: self.window_offset,
+            device_offset: self.device_offset,
+            scroll_delta: self.scroll_delta,
+            gamepads: self
+                .gamepads
+                .iter()
+                .map(|(index, state)| RecordedGamepad {
+                    index: *index,
+                    buttons: state.button_states.clone().into_iter().collect(),
+                    axes: state.axis_values.clone().into_iter().collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Replaces what was read from the devices with a recorded frame, keeping the bindings
+    pub fn restore(&mut self, frame: &InputFrame) {
+        self.key_states = [KeyState::Released; NUM_KEYS];
+        for &(index, state) in &frame.keys {
+            self.key_states[index] = state;
+        }
+        self.mouse_button_states = [KeyState::Released; NUM_MOUSE_BUTTONS];
+        for &(index, state) in &frame.mouse_buttons {
+            self.mouse_button_states[index] = state;
+        }
+
+        self.last_cursor_position = frame
+            .cursor_position
+            .map(|(x, y)| PhysicalPosition::new(x, y));
+        self.window_offset = frame.window_offset;
+        self.device_offset = frame.device_offset;
+        self.scroll_delta = frame.scroll_delta;
+
+        self.gamepads = frame
+            .gamepads
+            .iter()
+            .map(|gamepad| {
+                let state = GamepadState {
+                    button_states: gamepad.buttons.iter().copied().collect(),
+                    axis_values: gamepad.axes.iter().copied().collect(),
+                };
+                (gamepad.index, state)
+            })
+            .collect();
+    }
+
+    /// Reads events from connected gamepads, should be called once per frame before querying them
+    pub fn update_gamepads(&mut self) {
+        let Some(gilrs) = self.gilrs.as_mut() else {
+            return;
+        };
+
+        while let Some(gilrs::Event { id, event, .. }) = gilrs.next_event() {
+            let index = usize::from(id);
+
+            match event {
+                EventType::Connected => {
+                    info!("Gamepad {:?} connected", id);
+                    self.gamepads.insert(index, GamepadState::default());
+                }
+                EventType::Disconnected => {
+                    info!("Gamepad {:?} disconnected", id);
+                    self.gamepads.remove(&index);
+                }
+                EventType::ButtonPressed(button, _) | EventType::ButtonRepeated(button, _) => {
+                    Self::update_button_state(
+                        &mut self.gamepads,
+                        index,
+                        button,
+                        ElementState::Pressed,
+                    )
+                }
+                EventType::ButtonReleased(button, _) => Self::update_button_state(
+                    &mut self.gamepads,
+                    index,
+                    button,
+                    ElementState::Released,
+                ),
+                EventType::AxisChanged(axis, value, _) => {
+                    self.gamepads
+                        .entry(index)
+                        .or_default()
+                        .axis_values
+                        .insert(axis, value);
+                }
+                _ => (),
             }
         }
+    }
+
+    /// Should be called at the end of each frame. Presses and releases only count for the frame
+    /// they happened in, after which buttons are held or released until their next event.
+    pub fn reset_internal_state(&mut self) {
+        for key_state in self
+            .key_states
+            .iter_mut()
+            .chain(self.mouse_button_states.iter_mut())
+            .chain(
+                self.gamepads
+                    .values_mut()
+                    .flat_map(|gamepad| gamepad.button_states.values_mut()),
+            )
+        {
+            *key_state = match *key_state {
+                // Mouse and gamepad buttons don't repeat, so they would stay pressed otherwise
+                KeyState::Pressed => KeyState::Repeat,
+                KeyState::JustReleased => KeyState::Released,
+                state => state,
+            };
+        }
 
         self.window_offset = Vector2::zero();
         self.device_offset = Vector2::zero();
+        self.scroll_delta = 0.0;
     }
 
     pub fn process_event(&mut self, window_id: WindowId, event: &Event<()>) {
@@ -114,6 +595,9 @@ impl Input {
                     WindowEvent::MouseInput { state, button, .. } => {
                         self.process_mouse_button_event(*button, *state);
                     }
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        self.process_mouse_wheel_event(*delta);
+                    }
                     _ => (),
                 };
             }
@@ -151,7 +635,6 @@ impl Input {
                 "Unidentified mouse button event received with code {}",
                 code
             ),
-            // Offsets into the key_states member
             _ => Self::update_key_state(
                 &mut self.mouse_button_states,
                 Self::mouse_button_to_index(button),
@@ -160,7 +643,20 @@ impl Input {
         };
     }
 
-    const CURSOR_SENSITIVITY: f64 = 0.002;
+    pub const DEFAULT_CURSOR_SENSITIVITY: f64 = 0.002;
+    /// Sticks pushed less than this far are treated as centred, as they rarely rest at exactly 0
+    const STICK_DEADZONE: f32 = 0.15;
+    /// Touchpads report scrolling in pixels rather than lines
+    const PIXELS_PER_SCROLL_LINE: f64 = 20.0;
+
+    fn process_mouse_wheel_event(&mut self, delta: MouseScrollDelta) {
+        self.scroll_delta += match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(position) => {
+                (position.y / Self::PIXELS_PER_SCROLL_LINE) as f32
+            }
+        };
+    }
 
     fn process_cursor_moved_window_event(&mut self, position: PhysicalPosition<f64>) {
         if self.last_cursor_position.is_none() {
@@ -169,19 +665,21 @@ impl Input {
         }
 
         self.window_offset = Vector2::new(
-            ((position.x - self.last_cursor_position.unwrap().x) * Self::CURSOR_SENSITIVITY) as f32,
-            ((position.y - self.last_cursor_position.unwrap().y) * Self::CURSOR_SENSITIVITY) as f32,
+            ((position.x - self.last_cursor_position.unwrap().x) * self.cursor_sensitivity) as f32,
+            ((position.y - self.last_cursor_position.unwrap().y) * self.cursor_sensitivity) as f32,
         );
 
         self.last_cursor_position = Some(position);
     }
 
     fn process_cursor_moved_device_event(&mut self, offset: (f64, f64)) {
-        // println!("{:?}", offset);
+        if !self.cursor_captured {
+            return;
+        }
 
         self.device_offset = Vector2::new(
-            (offset.0 * Self::CURSOR_SENSITIVITY) as f32,
-            (offset.1 * Self::CURSOR_SENSITIVITY) as f32,
+            (offset.0 * self.cursor_sensitivity) as f32,
+            (offset.1 * self.cursor_sensitivity) as f32,
         );
     }
 
@@ -208,6 +706,56 @@ impl Input {
         key_states[index] = new_state;
     }
 
+    fn update_button_state(
+        gamepads: &mut HashMap<usize, GamepadState>,
+        gamepad: usize,
+        button: Button,
+        state: ElementState,
+    ) {
+        let button_states = &mut gamepads.entry(gamepad).or_default().button_states;
+        let mut key_states = [*button_states.get(&button).unwrap_or(&KeyState::Released)];
+
+        Self::update_key_state(&mut key_states, 0, state);
+        button_states.insert(button, key_states[0]);
+    }
+
+    /// The state of a binding. Gamepad buttons take their state from the first gamepad holding them.
+    fn binding_state(&self, binding: Binding) -> KeyState {
+        match binding {
+            Binding::Key(key_code) => self.key_states[key_code as usize],
+            Binding::Mouse(MouseButton::Other(_)) => KeyState::Released,
+            Binding::Mouse(mouse_button) => {
+                self.mouse_button_states[Self::mouse_button_to_index(mouse_button)]
+            }
+            Binding::Gamepad(button) => self
+                .gamepads
+                .values()
+                .map(|state| state.button_state(button))
+                .find(|state| *state != KeyState::Released)
+                .unwrap_or(KeyState::Released),
+        }
+    }
+
+    fn gamepad_button_state(&self, gamepad: GamepadId, button: Button) -> KeyState {
+        self.gamepads
+            .get(&usize::from(gamepad))
+            .map_or(KeyState::Released, |state| state.button_state(button))
+    }
+
+    /// Radial deadzone, rescaled so the stick still reaches full speed at its edge
+    fn apply_deadzone(stick: Vector2<f32>) -> Vector2<f32> {
+        let magnitude = stick.magnitude();
+
+        if magnitude < Self::STICK_DEADZONE {
+            return Vector2::zero();
+        }
+
+        let scaled_magnitude =
+            ((magnitude - Self::STICK_DEADZONE) / (1.0 - Self::STICK_DEADZONE)).min(1.0);
+
+        stick * (scaled_magnitude / magnitude)
+    }
+
     fn mouse_button_to_index(button: MouseButton) -> usize {
         match button {
             MouseButton::Left => 0,
@@ -222,3 +770,125 @@ impl Input {
         }
     }
 }
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RecordedGamepad {
+    index: usize,
+    buttons: Vec<(Button, KeyState)>,
+    axes: Vec<(Axis, f32)>,
+}
+
+/// The input state of one frame, see `Input::frame`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InputFrame {
+    /// Seconds the frame took, which per-frame updates such as the camera move by
+    pub deltatime: f64,
+    /// Seconds the fixed timestep was advanced by
+    pub frame_time: f64,
+    /// Indices and states of keys and mouse buttons that weren't released
+    keys: Vec<(usize, KeyState)>,
+    mouse_buttons: Vec<(usize, KeyState)>,
+    cursor_position: Option<(f64, f64)>,
+    window_offset: Vector2<f32>,
+    device_offset: Vector2<f32>,
+    scroll_delta: f32,
+    gamepads: Vec<RecordedGamepad>,
+}
+
+/// A session's input frame by frame, with what's needed to start it again the same way
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InputRecording {
+    /// Random numbers are seeded with this when the session starts, so the same entities are
+    /// spawned with the same IDs
+    pub seed: u64,
+    /// Loaded before the session starts, the editor's starting scene if `None`
+    pub scene: Option<PathBuf>,
+    pub frames: Vec<InputFrame>,
+}
+
+impl InputRecording {
+    /// Seeds random numbers with the recording's seed, to be called as the session starts. How
+    /// many numbers are drawn while loading depends on how long it takes, so seeding any earlier
+    /// would leave a replay drawing different ones.
+    pub fn seed_random(&self) {
+        fastrand::seed(self.seed);
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(bincode::deserialize(&std::fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, bincode::serialize(self)?)?;
+
+        Ok(())
+    }
+
+    /// Seconds from the start of the session to the end of its last frame
+    pub fn duration(&self) -> f64 {
+        self.frames.iter().map(|frame| frame.frame_time).sum()
+    }
+}
+
+/// Records input each frame, to be saved and played back with `InputReplay`
+pub struct InputRecorder {
+    recording: InputRecording,
+}
+
+impl InputRecorder {
+    /// Picks the seed random numbers are seeded with once the session starts
+    pub fn start(scene: Option<PathBuf>) -> Self {
+        let seed = fastrand::u64(..);
+
+        Self {
+            recording: InputRecording {
+                seed,
+                scene,
+                frames: vec![],
+            },
+        }
+    }
+
+    pub fn record(&mut self, input: &Input, deltatime: f64, frame_time: f64) {
+        self.recording
+            .frames
+            .push(input.frame(deltatime, frame_time));
+    }
+
+    pub fn recording(&self) -> &InputRecording {
+        &self.recording
+    }
+}
+
+/// Plays back recorded input a frame at a time
+pub struct InputReplay {
+    recording: InputRecording,
+    next: usize,
+}
+
+impl InputReplay {
+    pub fn start(recording: InputRecording) -> Self {
+        Self { recording, next: 0 }
+    }
+
+    pub fn recording(&self) -> &InputRecording {
+        &self.recording
+    }
+
+    /// `None` once every frame has been played
+    pub fn next_frame(&mut self) -> Option<&InputFrame> {
+        let frame = self.recording.frames.get(self.next)?;
+        self.next += 1;
+
+        Some(frame)
+    }
+
+    /// Fraction of the frames played so far
+    pub fn progress(&self) -> f32 {
+        if self.recording.frames.is_empty() {
+            return 1.0;
+        }
+
+        self.next as f32 / self.recording.frames.len() as f32
+    }
+}
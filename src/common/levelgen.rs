@@ -0,0 +1,346 @@
+use std::ops::Range;
+use std::path::Path;
+
+use cgmath::{Point3, Vector3};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::Assets;
+use crate::camera::Camera;
+use crate::maths::Aabb;
+use crate::model::Transform;
+use crate::navigation::NavGrid;
+use crate::scene::Scene;
+
+/// Model every tile is built from, loaded by `Level::build_scene`
+pub const CUBE_MODEL_PATH: &str = "assets/models/cube.glb";
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Tile {
+    Solid,
+    Floor,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PickupKind {
+    Health,
+    Ammo,
+    Armour,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Pickup {
+    pub kind: PickupKind,
+    pub position: Point3<f32>,
+}
+
+/// A rectangle of floor tiles
+#[derive(Copy, Clone, Debug)]
+pub struct Room {
+    pub x: usize,
+    pub z: usize,
+    pub width: usize,
+    pub depth: usize,
+}
+
+impl Room {
+    pub fn center(&self) -> (usize, usize) {
+        (self.x + self.width / 2, self.z + self.depth / 2)
+    }
+
+    /// Whether the rooms overlap or are closer than `padding` tiles
+    fn intersects(&self, other: &Room, padding: usize) -> bool {
+        self.x < other.x + other.width + padding
+            && other.x < self.x + self.width + padding
+            && self.z < other.z + other.depth + padding
+            && other.z < self.z + self.depth + padding
+    }
+}
+
+/// Generates rooms joined by corridors on a tile grid. The same seed always produces the same
+/// level, so horde mode can advance through levels by incrementing it.
+#[derive(Clone, Debug)]
+pub struct LevelGenerator {
+    pub seed: u64,
+    /// Size of the level in tiles
+    pub width: usize,
+    pub depth: usize,
+    pub max_rooms: usize,
+    /// How many attempts are made to place each room before giving up
+    pub placement_attempts: usize,
+    pub room_size: Range<usize>,
+    pub pickups_per_room: usize,
+    /// Size of a tile in world units
+    pub tile_size: f32,
+    pub wall_height: f32,
+}
+
+impl Default for LevelGenerator {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            width: 48,
+            depth: 48,
+            max_rooms: 10,
+            placement_attempts: 20,
+            room_size: 4..10,
+            pickups_per_room: 1,
+            tile_size: 2.0,
+            wall_height: 3.0,
+        }
+    }
+}
+
+impl LevelGenerator {
+    pub fn generate(&self) -> Level {
+        let mut rng = fastrand::Rng::with_seed(self.seed);
+
+        let mut level = Level {
+            width: self.width,
+            depth: self.depth,
+            tile_size: self.tile_size,
+            wall_height: self.wall_height,
+            tiles: vec![Tile::Solid; self.width * self.depth],
+            rooms: vec![],
+            colliders: vec![],
+            navigation: NavGrid::new(
+                self.width,
+                self.depth,
+                self.tile_size,
+                Point3::new(0.0, 0.0, 0.0),
+            ),
+            spawn_points: vec![],
+            pickups: vec![],
+        };
+
+        self.place_rooms(&mut rng, &mut level);
+        self.carve_corridors(&mut rng, &mut level);
+
+        level.build_colliders();
+        level.build_navigation();
+
+        self.place_spawn_points(&mut level);
+        self.place_pickups(&mut rng, &mut level);
+
+        level
+    }
+
+    fn place_rooms(&self, rng: &mut fastrand::Rng, level: &mut Level) {
+        for _ in 0..self.max_rooms * self.placement_attempts {
+            if level.rooms.len() >= self.max_rooms {
+                break;
+            }
+
+            let width = rng.usize(self.room_size.clone());
+            let depth = rng.usize(self.room_size.clone());
+
+            // Keep a border of solid tiles around the level
+            if width + 2 >= self.width || depth + 2 >= self.depth {
+                continue;
+            }
+
+            let room = Room {
+                x: rng.usize(1..self.width - width - 1),
+                z: rng.usize(1..self.depth - depth - 1),
+                width,
+                depth,
+            };
+
+            if level.rooms.iter().any(|other| room.intersects(other, 1)) {
+                continue;
+            }
+
+            for z in room.z..room.z + room.depth {
+                for x in room.x..room.x + room.width {
+                    level.set_tile(x, z, Tile::Floor);
+                }
+            }
+
+            level.rooms.push(room);
+        }
+    }
+
+    /// Joins each room to the previous one with an L shaped corridor
+    fn carve_corridors(&self, rng: &mut fastrand::Rng, level: &mut Level) {
+        for index in 1..level.rooms.len() {
+            let (x1, z1) = level.rooms[index - 1].center();
+            let (x2, z2) = level.rooms[index].center();
+
+            if rng.bool() {
+                level.carve_horizontal(x1, x2, z1);
+                level.carve_vertical(z1, z2, x2);
+            } else {
+                level.carve_vertical(z1, z2, x1);
+                level.carve_horizontal(x1, x2, z2);
+            }
+        }
+    }
+
+    fn place_spawn_points(&self, level: &mut Level) {
+        level.spawn_points = level
+            .rooms
+            .iter()
+            .map(|room| {
+                let (x, z) = room.center();
+                level.tile_center(x, z)
+            })
+            .collect();
+    }
+
+    fn place_pickups(&self, rng: &mut fastrand::Rng, level: &mut Level) {
+        let kinds = [PickupKind::Health, PickupKind::Ammo, PickupKind::Armour];
+
+        for room in level.rooms.clone() {
+            for _ in 0..self.pickups_per_room {
+                let x = rng.usize(room.x..room.x + room.width);
+                let z = rng.usize(room.z..room.z + room.depth);
+
+                level.pickups.push(Pickup {
+                    kind: kinds[rng.usize(0..kinds.len())],
+                    position: level.tile_center(x, z) + Vector3::new(0.0, 0.5, 0.0),
+                });
+            }
+        }
+    }
+}
+
+/// The output of a `LevelGenerator`
+pub struct Level {
+    pub width: usize,
+    pub depth: usize,
+    pub tile_size: f32,
+    pub wall_height: f32,
+    pub tiles: Vec<Tile>,
+    pub rooms: Vec<Room>,
+    /// One box per wall tile bordering a floor, plus the floor itself
+    pub colliders: Vec<Aabb>,
+    pub navigation: NavGrid,
+    pub spawn_points: Vec<Point3<f32>>,
+    pub pickups: Vec<Pickup>,
+}
+
+impl Level {
+    /// Tiles outside of the level are solid
+    pub fn tile(&self, x: usize, z: usize) -> Tile {
+        if x < self.width && z < self.depth {
+            self.tiles[z * self.width + x]
+        } else {
+            Tile::Solid
+        }
+    }
+
+    /// World space position of the centre of a tile at floor height
+    pub fn tile_center(&self, x: usize, z: usize) -> Point3<f32> {
+        self.navigation.cell_center(x, z)
+    }
+
+    /// Builds a scene out of cube instances, one per floor tile and one per visible wall tile
+    pub fn build_scene(&self, title: &str, camera: Camera, assets: &mut Assets) -> Result<Scene> {
+        Scene::build(title, camera, assets, |scene| {
+            let cube = scene.load_model(Path::new(CUBE_MODEL_PATH))?;
+
+            // The cube model spans -1 to 1 on each axis
+            let half_tile = self.tile_size / 2.0;
+
+            for z in 0..self.depth {
+                for x in 0..self.width {
+                    let center = self.tile_center(x, z);
+
+                    let transform = match self.tile(x, z) {
+                        Tile::Floor => Transform {
+                            translation: Vector3::new(center.x, -0.05, center.z),
+                            scale: Vector3::new(half_tile, 0.05, half_tile),
+                            ..Transform::default()
+                        },
+                        Tile::Solid if self.borders_floor(x, z) => Transform {
+                            translation: Vector3::new(center.x, self.wall_height / 2.0, center.z),
+                            scale: Vector3::new(half_tile, self.wall_height / 2.0, half_tile),
+                            ..Transform::default()
+                        },
+                        Tile::Solid => continue,
+                    };
+
+                    scene.spawn_model(cube.clone(), transform);
+                }
+            }
+
+            scene
+                .simulation
+                .add_colliders(self.colliders.iter().copied());
+            scene.simulation.navigation = Some(self.navigation.clone());
+            scene.simulation.spawn_points = self.spawn_points.clone();
+
+            if let Some(spawn_point) = self.spawn_points.first() {
+                scene.camera.position = *spawn_point + Vector3::new(0.0, 1.7, 0.0);
+            }
+
+            Ok(())
+        })
+    }
+
+    fn set_tile(&mut self, x: usize, z: usize, tile: Tile) {
+        if x < self.width && z < self.depth {
+            self.tiles[z * self.width + x] = tile;
+        }
+    }
+
+    fn carve_horizontal(&mut self, x1: usize, x2: usize, z: usize) {
+        for x in x1.min(x2)..=x1.max(x2) {
+            self.set_tile(x, z, Tile::Floor);
+        }
+    }
+
+    fn carve_vertical(&mut self, z1: usize, z2: usize, x: usize) {
+        for z in z1.min(z2)..=z1.max(z2) {
+            self.set_tile(x, z, Tile::Floor);
+        }
+    }
+
+    fn borders_floor(&self, x: usize, z: usize) -> bool {
+        (-1_isize..=1).any(|dz| {
+            (-1_isize..=1).any(|dx| {
+                let (Some(nx), Some(nz)) = (x.checked_add_signed(dx), z.checked_add_signed(dz))
+                else {
+                    return false;
+                };
+
+                self.tile(nx, nz) == Tile::Floor
+            })
+        })
+    }
+
+    fn build_colliders(&mut self) {
+        let half_tile = self.tile_size / 2.0;
+
+        self.colliders = vec![Aabb::new(
+            Point3::new(0.0, -0.1, 0.0),
+            Point3::new(
+                self.width as f32 * self.tile_size,
+                0.0,
+                self.depth as f32 * self.tile_size,
+            ),
+        )];
+
+        for z in 0..self.depth {
+            for x in 0..self.width {
+                if self.tile(x, z) == Tile::Solid && self.borders_floor(x, z) {
+                    let center = self.tile_center(x, z);
+
+                    self.colliders.push(Aabb::new(
+                        Point3::new(center.x - half_tile, 0.0, center.z - half_tile),
+                        Point3::new(center.x + half_tile, self.wall_height, center.z + half_tile),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn build_navigation(&mut self) {
+        for z in 0..self.depth {
+            for x in 0..self.width {
+                let walkable = self.tile(x, z) == Tile::Floor;
+                self.navigation.set_walkable(x, z, walkable);
+            }
+        }
+    }
+}
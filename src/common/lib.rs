@@ -1,12 +1,70 @@
+pub mod ai;
+pub mod animation;
+pub mod animator;
 pub mod app;
+pub mod assets;
+pub mod audio;
+pub mod backend;
+pub mod billboards;
 pub mod camera;
+pub mod camera_effects;
+pub mod capture;
+pub mod collision;
 pub mod colors;
+pub mod config;
+pub mod console;
 pub mod context;
+pub mod crash;
 pub mod debug;
+pub mod debug_draw;
+pub mod debug_view;
+pub mod decals;
+pub mod deferred;
+pub mod entity;
+pub mod events;
+pub mod gameplay;
+pub mod hierarchy;
 pub mod input;
+pub mod levelgen;
+pub mod light;
 pub mod line;
+pub mod loading;
+pub mod lod;
+pub mod map;
+pub mod material;
 pub mod maths;
 pub mod model;
+pub mod navigation;
+pub mod net;
+pub mod obj;
+pub mod occlusion;
+pub mod particles;
+pub mod physics;
+pub mod pool;
+pub mod portal;
+pub mod postprocess;
+pub mod profiling;
+pub mod ragdoll;
+pub mod reflection;
+pub mod render_graph;
+pub mod replay;
+pub mod savegame;
 pub mod scene;
+pub mod scripting;
+pub mod settings;
+pub mod simulation;
+pub mod skybox;
+pub mod spatial;
+pub mod streaming;
+pub mod terrain;
+pub mod texture;
+pub mod time_of_day;
+pub mod timestep;
+pub mod tween;
+pub mod ui;
 pub mod uuid;
 pub mod vertex;
+pub mod viewport;
+pub mod weapons;
+pub mod weather;
+pub mod wgpu_backend;
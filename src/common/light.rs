@@ -0,0 +1,157 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Deg, InnerSpace, Rotation, Vector3};
+use glium::implement_uniform_block;
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+
+use crate::entity::World;
+use crate::model::Transform;
+
+/// The most lights the shaders handle at once, matching `MAX_LIGHTS` in the shaders
+pub const MAX_LIGHTS: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LightKind {
+    /// Shines along the entity's forward direction from infinitely far away, like the sun
+    Directional,
+    /// Shines in every direction from the entity's position, fading out completely at `radius`
+    Point { radius: f32 },
+    /// A point light limited to a cone around the entity's forward direction, fading out between
+    /// the inner and outer angles
+    Spot {
+        radius: f32,
+        inner_angle: Deg<f32>,
+        outer_angle: Deg<f32>,
+    },
+}
+
+/// Component that lights the scene from its entity's `Transform`. Entities face along -Z before
+/// they are rotated.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Srgb,
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn directional(color: Srgb, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color,
+            intensity,
+        }
+    }
+
+    pub fn point(color: Srgb, intensity: f32, radius: f32) -> Self {
+        Self {
+            kind: LightKind::Point { radius },
+            color,
+            intensity,
+        }
+    }
+
+    pub fn spot(
+        color: Srgb,
+        intensity: f32,
+        radius: f32,
+        inner_angle: Deg<f32>,
+        outer_angle: Deg<f32>,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                radius,
+                inner_angle,
+                outer_angle,
+            },
+            color,
+            intensity,
+        }
+    }
+}
+
+/// A light as laid out in the `Lights` uniform block
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct GpuLight {
+    /// W is the kind, 0 for directional, 1 for point and 2 for spot
+    position: [f32; 4],
+    /// Direction the light travels in, W is the radius
+    direction: [f32; 4],
+    color: [f32; 4],
+    /// Cosines of the inner and outer angles of spot lights
+    cone: [f32; 4],
+}
+implement_uniform_block!(GpuLight, position, direction, color, cone);
+
+// Only made of floats, so without padding and valid for any bytes
+unsafe impl Zeroable for GpuLight {}
+unsafe impl Pod for GpuLight {}
+
+impl GpuLight {
+    fn new(light: &Light, transform: &Transform) -> Self {
+        let direction = transform
+            .rotation
+            .rotate_vector(-Vector3::unit_z())
+            .normalize();
+        let color = light.color * light.intensity;
+
+        let (kind, radius, cone) = match light.kind {
+            LightKind::Directional => (0.0, 0.0, [1.0, 1.0]),
+            LightKind::Point { radius } => (1.0, radius, [1.0, 1.0]),
+            LightKind::Spot {
+                radius,
+                inner_angle,
+                outer_angle,
+            } => (
+                2.0,
+                radius,
+                [
+                    inner_angle.0.to_radians().cos(),
+                    outer_angle.0.to_radians().cos(),
+                ],
+            ),
+        };
+
+        Self {
+            position: transform.translation.extend(kind).into(),
+            direction: direction.extend(radius).into(),
+            color: [color.red, color.green, color.blue, 1.0],
+            cone: [cone[0], cone[1], 0.0, 0.0],
+        }
+    }
+}
+
+/// Every light in a scene, uploaded to a uniform buffer once per frame
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct LightsBlock {
+    lights: [GpuLight; MAX_LIGHTS],
+    light_count: i32,
+}
+implement_uniform_block!(LightsBlock, lights, light_count);
+
+// Lights and an integer, all four bytes wide, so without padding and valid for any bytes
+unsafe impl Zeroable for LightsBlock {}
+unsafe impl Pod for LightsBlock {}
+
+impl LightsBlock {
+    /// Gathers the entities with both a `Light` and a `Transform`, ignoring any past `MAX_LIGHTS`
+    pub(crate) fn new(world: &World) -> Self {
+        let mut block = Self {
+            lights: [GpuLight::default(); MAX_LIGHTS],
+            light_count: 0,
+        };
+
+        for (gpu_light, (_, light, transform)) in block
+            .lights
+            .iter_mut()
+            .zip(world.query2::<Light, Transform>())
+        {
+            *gpu_light = GpuLight::new(light, transform);
+            block.light_count += 1;
+        }
+
+        block
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use color_eyre::Result;
+use glium::glutin::surface::WindowSurface;
+use glium::Display;
+use log::warn;
+
+use crate::assets;
+use crate::assets::Assets;
+use crate::model::{Model, ModelData};
+use crate::texture::DecodedTexture;
+
+/// Most threads decoding assets at once, whatever the number of cores
+const MAX_LOADING_THREADS: usize = 4;
+
+/// Time spent uploading decoded assets each frame, so a burst of them finishing together doesn't
+/// freeze the window. At least one is always uploaded.
+const UPLOAD_BUDGET: Duration = Duration::from_millis(8);
+
+/// Identifies a load queued with `AssetLoader`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LoadId(usize);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadState {
+    /// Being read and decoded, or waiting to be uploaded
+    Loading,
+    /// In `Assets`, so loading it from there is instant
+    Loaded,
+    Failed(String),
+}
+
+enum Request {
+    Model(PathBuf),
+    /// GLB data held in memory, e.g. embedded in a map, keyed by the path
+    EmbeddedModel(PathBuf, Vec<u8>),
+    Texture(PathBuf),
+}
+
+enum Decoded {
+    Model(ModelData),
+    Texture(DecodedTexture),
+}
+
+/// Loads models and textures in the background. Files are read and decoded on a pool of loading
+/// threads, then `update` adds them to `Assets` on the main thread, uploading textures as it owns
+/// the display.
+pub struct AssetLoader {
+    requests: Sender<(LoadId, Request)>,
+    decoded: Receiver<(LoadId, Result<Decoded>)>,
+    states: HashMap<LoadId, LoadState>,
+    /// What each load in progress is for, so a path queued twice is only loaded once
+    in_flight: HashMap<LoadId, PathBuf>,
+    next_id: usize,
+    /// Loads queued and finished since the loader was last idle, for showing progress
+    queued: usize,
+    finished: usize,
+}
+
+impl AssetLoader {
+    pub fn new() -> Self {
+        let (requests, requests_receiver) = mpsc::channel::<(LoadId, Request)>();
+        let (decoded_sender, decoded) = mpsc::channel();
+
+        // One core is left for the main thread
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |cores| cores.get().saturating_sub(1))
+            .clamp(1, MAX_LOADING_THREADS);
+        let requests_receiver = Arc::new(Mutex::new(requests_receiver));
+
+        for _ in 0..threads {
+            let requests_receiver = requests_receiver.clone();
+            let decoded_sender = decoded_sender.clone();
+
+            std::thread::spawn(move || loop {
+                // Stops once the loader is dropped
+                let Ok((id, request)) = requests_receiver.lock().unwrap().recv() else {
+                    break;
+                };
+
+                let result = match request {
+                    Request::Model(path) => Model::decode(&path).map(Decoded::Model),
+                    Request::EmbeddedModel(path, bytes) => {
+                        Model::decode_bytes(&path, &bytes).map(Decoded::Model)
+                    }
+                    Request::Texture(path) => assets::decode_texture(&path).map(Decoded::Texture),
+                };
+
+                if decoded_sender.send((id, result)).is_err() {
+                    break;
+                }
+            });
+        }
+
+        Self {
+            requests,
+            decoded,
+            states: HashMap::new(),
+            in_flight: HashMap::new(),
+            next_id: 0,
+            queued: 0,
+            finished: 0,
+        }
+    }
+
+    /// Starts loading a model by path or logical name, unless it's already loaded or loading
+    pub fn load_model(&mut self, assets: &Assets, name: &Path) -> LoadId {
+        let path = assets.resolve(name);
+        let loaded = assets.model_is_loaded(&path);
+
+        self.queue(path.clone(), loaded, Request::Model(path))
+    }
+
+    /// Starts loading a model from GLB data held in memory, keyed by `name`
+    pub fn load_embedded_model(&mut self, assets: &Assets, name: &str, bytes: Vec<u8>) -> LoadId {
+        let path = PathBuf::from(name);
+        let loaded = assets.model_is_loaded(&path);
+
+        self.queue(path.clone(), loaded, Request::EmbeddedModel(path, bytes))
+    }
+
+    /// Starts loading an image by path or logical name as an sRGB texture
+    pub fn load_texture(&mut self, assets: &Assets, name: &Path) -> LoadId {
+        let path = assets.resolve(name);
+        let loaded = assets.texture_is_loaded(&path);
+
+        self.queue(path.clone(), loaded, Request::Texture(path))
+    }
+
+    fn queue(&mut self, path: PathBuf, loaded: bool, request: Request) -> LoadId {
+        if let Some((&id, _)) = self.in_flight.iter().find(|(_, queued)| **queued == path) {
+            return id;
+        }
+
+        let id = LoadId(self.next_id);
+        self.next_id += 1;
+
+        if loaded {
+            self.states.insert(id, LoadState::Loaded);
+            return id;
+        }
+
+        self.states.insert(id, LoadState::Loading);
+        self.in_flight.insert(id, path);
+        self.queued += 1;
+        self.requests.send((id, request)).unwrap();
+
+        id
+    }
+
+    /// Adds assets that have finished decoding to `assets`, uploading textures, called once per
+    /// frame
+    pub fn update(&mut self, assets: &mut Assets, display: &Display<WindowSurface>) {
+        let start = Instant::now();
+
+        while start.elapsed() < UPLOAD_BUDGET {
+            let Ok((id, result)) = self.decoded.try_recv() else {
+                break;
+            };
+            let Some(path) = self.in_flight.remove(&id) else {
+                continue;
+            };
+
+            let uploaded = result.and_then(|decoded| match decoded {
+                Decoded::Model(data) => {
+                    assets.insert_model(&path, data.into_model(&path));
+                    Ok(())
+                }
+                Decoded::Texture(decoded) => {
+                    let texture = assets::upload_texture(decoded, display)?;
+                    assets.insert_texture(&path, texture);
+                    Ok(())
+                }
+            });
+
+            let state = match uploaded {
+                Ok(()) => LoadState::Loaded,
+                Err(error) => {
+                    warn!("Failed to load {:?}: {}", path, error);
+                    LoadState::Failed(error.to_string())
+                }
+            };
+            self.states.insert(id, state);
+            self.finished += 1;
+        }
+
+        if self.in_flight.is_empty() {
+            self.queued = 0;
+            self.finished = 0;
+        }
+    }
+
+    /// `None` for IDs from another loader
+    pub fn state(&self, id: LoadId) -> Option<&LoadState> {
+        self.states.get(&id)
+    }
+
+    /// Whether anything queued hasn't finished yet
+    pub fn is_loading(&self) -> bool {
+        !self.in_flight.is_empty()
+    }
+
+    /// Fraction of the loads queued since the loader was last idle that have finished
+    pub fn progress(&self) -> f32 {
+        if self.queued == 0 {
+            return 1.0;
+        }
+
+        self.finished as f32 / self.queued as f32
+    }
+}
+
+impl Default for AssetLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3, Zero};
+use itertools::Itertools;
+use log::debug;
+
+use crate::maths::Aabb;
+use crate::model::{Mesh, MeshData, PrimitiveData};
+use crate::vertex::Vertex;
+
+/// How far past a switch distance, as a fraction of it, an instance has to move before its level
+/// changes, so instances sitting near one don't keep popping between levels
+pub const LOD_HYSTERESIS: f32 = 0.1;
+
+/// Models with fewer triangles than this are always drawn in full detail
+const MIN_TRIANGLES: usize = 1000;
+
+/// Grid cells across the largest side of the model for each generated level, and the distance it
+/// starts being drawn from
+const GENERATED_LEVELS: [(f32, f32); 3] = [(48.0, 15.0), (24.0, 30.0), (12.0, 60.0)];
+
+/// Simpler meshes drawn in place of a model's own from `distance` away
+pub struct LodLevel {
+    pub distance: f32,
+    pub meshes: Vec<Mesh>,
+}
+
+/// A generated level of detail, waiting for its primitives' bounds to be found
+pub struct LodData {
+    pub distance: f32,
+    pub meshes: Vec<MeshData>,
+}
+
+impl LodData {
+    pub fn into_level(self) -> LodLevel {
+        LodLevel {
+            distance: self.distance,
+            meshes: self.meshes.into_iter().map(MeshData::into_mesh).collect(),
+        }
+    }
+}
+
+/// Simplifies a model's meshes into levels of detail by merging the vertices that fall in the same
+/// cell of a grid, which gets coarser with each level. Models that are already simple get none.
+/// Works on the vertices an importer built, so it can be done on a loading thread.
+pub fn generate(meshes: &[MeshData]) -> Vec<LodData> {
+    let triangles: usize = meshes
+        .iter()
+        .flat_map(|mesh| mesh.primitives.iter())
+        .map(|primitive| primitive.indices.len() / 3)
+        .sum();
+    if triangles < MIN_TRIANGLES {
+        return vec![];
+    }
+
+    let Some(bounds) = Aabb::from_points(
+        meshes
+            .iter()
+            .flat_map(|mesh| mesh.primitives.iter())
+            .flat_map(|primitive| primitive.vertices.iter())
+            .map(|vertex| Point3::from(vertex.position)),
+    ) else {
+        return vec![];
+    };
+
+    let half_extents = bounds.half_extents();
+    let largest_side = half_extents.x.max(half_extents.y).max(half_extents.z) * 2.0;
+    let mut levels = vec![];
+    let mut previous_triangles = triangles;
+
+    for (cells, distance) in GENERATED_LEVELS {
+        let cell_size = largest_side / cells;
+        let mut level_triangles = 0;
+        let mut level_meshes = vec![];
+
+        for mesh in meshes {
+            let mut primitives = vec![];
+
+            for primitive in mesh.primitives.iter() {
+                let (vertices, indices) = simplify(
+                    &primitive.vertices,
+                    &primitive.indices,
+                    bounds.min,
+                    cell_size,
+                );
+                if indices.is_empty() {
+                    continue;
+                }
+
+                level_triangles += indices.len() / 3;
+                primitives.push(PrimitiveData {
+                    vertices,
+                    indices,
+                    material: primitive.material,
+                });
+            }
+
+            level_meshes.push(MeshData {
+                name: mesh.name.clone(),
+                primitives,
+            });
+        }
+
+        // Not worth a level if merging barely removed anything
+        if level_triangles * 4 > previous_triangles * 3 {
+            continue;
+        }
+
+        debug!("Generated a level of detail with {level_triangles} of {triangles} triangles");
+
+        previous_triangles = level_triangles;
+        levels.push(LodData {
+            distance,
+            meshes: level_meshes,
+        });
+    }
+
+    levels
+}
+
+/// Picks the level to draw an instance `distance` away at, where 0 is full detail, moving on from
+/// the level it was at only once it's clearly past a switch distance
+pub fn select_level(levels: &[LodLevel], distance: f32, current: usize) -> usize {
+    let mut level = current.min(levels.len());
+
+    while level < levels.len() && distance > levels[level].distance * (1.0 + LOD_HYSTERESIS) {
+        level += 1;
+    }
+    while level > 0 && distance < levels[level - 1].distance * (1.0 - LOD_HYSTERESIS) {
+        level -= 1;
+    }
+
+    level
+}
+
+/// Merges the vertices in each grid cell into one at their average position, dropping triangles
+/// that collapse. Everything but the position and normal is taken from the first vertex in a cell.
+fn simplify(
+    vertices: &[Vertex],
+    indices: &[u16],
+    origin: Point3<f32>,
+    cell_size: f32,
+) -> (Vec<Vertex>, Vec<u16>) {
+    let mut cells = HashMap::<[i32; 3], u16>::new();
+    let mut merged = vec![];
+    let mut sums = vec![];
+
+    let remap = indices
+        .iter()
+        .map(|&index| {
+            let vertex = vertices[index as usize];
+            let position = Point3::from(vertex.position);
+            let cell = ((position - origin) / cell_size).map(|value| value.floor() as i32);
+
+            *cells.entry(cell.into()).or_insert_with(|| {
+                merged.push(vertex);
+                sums.push((Vector3::zero(), Vector3::zero(), 0.0));
+                (merged.len() - 1) as u16
+            })
+        })
+        .collect_vec();
+
+    for (&index, &new_index) in indices
+        .iter()
+        .zip(remap.iter())
+        .unique_by(|(index, _)| **index)
+    {
+        let vertex = vertices[index as usize];
+        let (position, normal, count) = &mut sums[new_index as usize];
+
+        *position += Vector3::from(vertex.position);
+        *normal += Vector3::from(vertex.normal);
+        *count += 1.0;
+    }
+
+    for (vertex, (position, normal, count)) in merged.iter_mut().zip(sums) {
+        vertex.position = Point3::from_vec(position / count).into();
+        if !normal.is_zero() {
+            vertex.normal = normal.normalize().into();
+        }
+    }
+
+    let indices = remap
+        .into_iter()
+        .tuples()
+        .filter(|(a, b, c)| a != b && b != c && a != c)
+        .flat_map(|(a, b, c)| [a, b, c])
+        .collect();
+
+    (merged, indices)
+}
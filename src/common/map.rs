@@ -0,0 +1,221 @@
+use std::path::{Path, PathBuf};
+
+use cgmath::{Point3, Vector3};
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::Result;
+use itertools::Itertools;
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+use crate::maths::Aabb;
+use crate::model::{ModelInstance, Transform};
+use crate::navigation::NavGrid;
+use crate::portal::PortalPair;
+use crate::scene::{Environment, Scene};
+use crate::uuid::UUID;
+
+/// Bumped whenever the layout of `Map` changes. Older maps are upgraded by `migrate` on load.
+pub const MAP_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum AssetSource {
+    /// Path to a model on disk, relative to the working directory
+    Referenced(PathBuf),
+    /// GLB data stored in the map itself so it can be shared as a single file
+    Embedded(Vec<u8>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MapAsset {
+    pub name: String,
+    pub source: AssetSource,
+}
+
+/// An instance of one of the map's assets
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MapEntity {
+    #[serde(default)]
+    pub uuid: UUID,
+    pub name: Option<String>,
+    /// Index into the map's assets
+    pub asset: usize,
+    pub transform: Transform,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MapLighting {
+    pub sun_direction: Vector3<f32>,
+    pub sun_color: [f32; 3],
+    pub sun_intensity: f32,
+    pub ambient_color: [f32; 3],
+    pub ambient_intensity: f32,
+    pub sky_color: [f32; 3],
+    pub fog_color: [f32; 3],
+    pub fog_density: f32,
+}
+
+impl From<&Environment> for MapLighting {
+    fn from(environment: &Environment) -> Self {
+        let raw = |color: Srgb| [color.red, color.green, color.blue];
+
+        Self {
+            sun_direction: environment.sun_direction,
+            sun_color: raw(environment.sun_color),
+            sun_intensity: environment.sun_intensity,
+            ambient_color: raw(environment.ambient_color),
+            ambient_intensity: environment.ambient_intensity,
+            sky_color: raw(environment.sky_color),
+            fog_color: raw(environment.fog_color),
+            fog_density: environment.fog_density,
+        }
+    }
+}
+
+impl MapLighting {
+    pub fn apply(&self, environment: &mut Environment) {
+        let color = |[red, green, blue]: [f32; 3]| Srgb::new(red, green, blue);
+
+        environment.sun_direction = self.sun_direction;
+        environment.sun_color = color(self.sun_color);
+        environment.sun_intensity = self.sun_intensity;
+        environment.ambient_color = color(self.ambient_color);
+        environment.ambient_intensity = self.ambient_intensity;
+        environment.sky_color = color(self.sky_color);
+        environment.fog_color = color(self.fog_color);
+        environment.fog_density = self.fog_density;
+    }
+}
+
+/// Component on the cubes drawn for a map's brushes, so they're exported as the brush they came
+/// from rather than as entities
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Brush(pub Aabb);
+
+/// A self contained level that can be shared as a single file
+#[derive(Serialize, Deserialize)]
+pub struct Map {
+    pub version: u32,
+    pub title: String,
+    pub camera: Camera,
+    pub assets: Vec<MapAsset>,
+    pub entities: Vec<MapEntity>,
+    /// Solid boxes that are both drawn and collided with
+    pub brushes: Vec<Aabb>,
+    /// Invisible collision on top of the brushes
+    pub colliders: Vec<Aabb>,
+    pub navigation: Option<NavGrid>,
+    pub spawn_points: Vec<Point3<f32>>,
+    pub lighting: MapLighting,
+    pub portals: Vec<PortalPair>,
+}
+
+impl Map {
+    /// Converts a scene into a map. With `embed_assets` GLB model files are read into the map,
+    /// otherwise they are referenced by path. Models that were embedded in the map the scene came
+    /// from are always embedded, as there's no file to refer to.
+    pub fn from_scene(scene: &Scene, embed_assets: bool) -> Result<Self> {
+        let world = &scene.simulation.world;
+        let mut assets: Vec<MapAsset> = vec![];
+        let mut entities = vec![];
+
+        let brushes = world
+            .query::<Brush>()
+            .map(|(_, brush)| brush.0)
+            .collect_vec();
+        // Brushes are collided with as well as drawn, so their collision goes with them
+        let colliders = scene
+            .simulation
+            .colliders
+            .iter()
+            .filter(|collider| !brushes.contains(collider))
+            .copied()
+            .collect_vec();
+
+        for (uuid, model_instance, transform) in world.query2::<ModelInstance, Transform>() {
+            if world.has::<Brush>(uuid) {
+                continue;
+            }
+
+            let path = &model_instance.model.path;
+            let name = path.to_string_lossy().into_owned();
+
+            let asset = match assets.iter().position(|asset| asset.name == name) {
+                Some(asset) => asset,
+                None => {
+                    // Only GLB files are self contained, other formats refer to files beside them
+                    let is_glb = path
+                        .extension()
+                        .is_some_and(|extension| extension.eq_ignore_ascii_case("glb"));
+
+                    let source = match &model_instance.model.embedded {
+                        Some(bytes) => AssetSource::Embedded(bytes.clone()),
+                        None if embed_assets && is_glb => {
+                            AssetSource::Embedded(std::fs::read(path)?)
+                        }
+                        None => AssetSource::Referenced(path.clone()),
+                    };
+
+                    assets.push(MapAsset { name, source });
+                    assets.len() - 1
+                }
+            };
+
+            entities.push(MapEntity {
+                uuid,
+                name: None,
+                asset,
+                transform: transform.clone(),
+            });
+        }
+
+        Ok(Self {
+            version: MAP_VERSION,
+            title: scene.title.clone(),
+            camera: scene.camera.clone(),
+            assets,
+            entities,
+            brushes,
+            colliders,
+            navigation: scene.simulation.navigation.clone(),
+            spawn_points: scene.simulation.spawn_points.clone(),
+            lighting: MapLighting::from(&scene.environment),
+            portals: scene.simulation.portals.clone(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let serialized = std::fs::read_to_string(path)?;
+        let value = serde_json::from_str::<serde_json::Value>(&serialized)?;
+
+        let version = value
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| eyre!("Map {:?} has no version", path))? as u32;
+
+        if version > MAP_VERSION {
+            bail!(
+                "Map {:?} is version {} but only up to version {} is supported",
+                path,
+                version,
+                MAP_VERSION
+            );
+        }
+
+        Ok(serde_json::from_value(migrate(value, version)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)?;
+
+        Ok(())
+    }
+}
+
+/// Upgrades a map saved by an older version of the format, one version at a time
+fn migrate(map: serde_json::Value, version: u32) -> Result<serde_json::Value> {
+    match version {
+        MAP_VERSION => Ok(map),
+        _ => bail!("No migration from map version {}", version),
+    }
+}
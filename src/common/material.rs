@@ -0,0 +1,329 @@
+use std::path::Path;
+
+use color_eyre::Result;
+use glium::glutin::surface::WindowSurface;
+use glium::texture::{MipmapsOption, RawImage2d, SrgbTexture2d};
+use glium::uniforms::{
+    MinifySamplerFilter, SamplerBehavior, SamplerWrapFunction, UniformValue, Uniforms,
+};
+use glium::{Display, Texture2d};
+use gltf::image::Format;
+use gltf::material::AlphaMode;
+use image::RgbaImage;
+use log::warn;
+
+use crate::texture::{self, DecodedTexture, Texture};
+
+/// Which fragment shader forward rendering shades models with
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ShadingModel {
+    /// Colors models by their position, ignoring materials
+    #[default]
+    BlinnPhong,
+    /// Physically based shading from each primitive's material
+    Pbr,
+}
+
+/// How a material's primitives are combined with what's behind them
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Hides what's behind, ignoring the albedo's alpha
+    #[default]
+    Opaque,
+    /// Mixed with what's behind by the albedo's alpha. Drawn after everything opaque, furthest
+    /// first, without writing depth.
+    Blend,
+}
+
+/// Surface properties of a primitive, following glTF's metallic-roughness model. Each factor is
+/// multiplied with its texture. The textures are kept as they were read, for each backend to
+/// upload the first time the material is drawn, so a material can be made off the main thread.
+pub struct Material {
+    pub name: Option<String>,
+    pub albedo_factor: [f32; 4],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    pub blend_mode: BlendMode,
+    /// Kept compressed if it was stored compressed
+    pub albedo_texture: Option<DecodedTexture>,
+    /// Tangent space normals
+    pub normal_texture: Option<RgbaImage>,
+    /// Roughness in the green channel and metalness in the blue channel
+    pub metallic_roughness_texture: Option<RgbaImage>,
+    /// Occlusion in the red channel
+    pub occlusion_texture: Option<RgbaImage>,
+    pub emissive_texture: Option<DecodedTexture>,
+}
+
+const WHITE: [u8; 4] = [255, 255, 255, 255];
+/// A normal pointing straight out of the surface
+const FLAT_NORMAL: [u8; 4] = [128, 128, 255, 255];
+
+impl Default for Material {
+    /// A plain white, fully rough material for primitives that don't specify one
+    fn default() -> Self {
+        Self {
+            name: None,
+            albedo_factor: [1.0, 1.0, 1.0, 1.0],
+            metallic_factor: 0.0,
+            roughness_factor: 1.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            emissive_factor: [0.0, 0.0, 0.0],
+            blend_mode: BlendMode::Opaque,
+            albedo_texture: None,
+            normal_texture: None,
+            metallic_roughness_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+impl Material {
+    /// Reads a glTF material, taking its textures from the images loaded alongside the document
+    pub(crate) fn from_gltf(material: gltf::Material, images: &[gltf::image::Data]) -> Self {
+        let image =
+            |texture: gltf::Texture| images.get(texture.source().index()).and_then(rgba_image);
+        let pbr = material.pbr_metallic_roughness();
+
+        Self {
+            name: material.name().map(str::to_owned),
+            albedo_factor: pbr.base_color_factor(),
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            normal_scale: material
+                .normal_texture()
+                .map_or(1.0, |normal| normal.scale()),
+            occlusion_strength: material
+                .occlusion_texture()
+                .map_or(1.0, |occlusion| occlusion.strength()),
+            emissive_factor: material.emissive_factor(),
+            // Masked materials are drawn opaque, as nothing discards their cut out parts yet
+            blend_mode: match material.alpha_mode() {
+                AlphaMode::Blend => BlendMode::Blend,
+                AlphaMode::Opaque | AlphaMode::Mask => BlendMode::Opaque,
+            },
+            albedo_texture: pbr
+                .base_color_texture()
+                .and_then(|info| image(info.texture()))
+                .map(DecodedTexture::Raw),
+            normal_texture: material
+                .normal_texture()
+                .and_then(|normal| image(normal.texture())),
+            metallic_roughness_texture: pbr
+                .metallic_roughness_texture()
+                .and_then(|info| image(info.texture())),
+            occlusion_texture: material
+                .occlusion_texture()
+                .and_then(|occlusion| image(occlusion.texture())),
+            emissive_texture: material
+                .emissive_texture()
+                .and_then(|info| image(info.texture()))
+                .map(DecodedTexture::Raw),
+        }
+    }
+
+    /// Converts an MTL material to metallic-roughness, reading its textures from the `directory`
+    /// the MTL file is in
+    pub(crate) fn from_mtl(material: &tobj::Material, directory: &Path) -> Self {
+        let image = |texture: &Option<String>| {
+            texture
+                .as_ref()
+                .and_then(|texture| load_image(&directory.join(texture)))
+        };
+        let [red, green, blue] = material.diffuse.unwrap_or([1.0, 1.0, 1.0]);
+        let dissolve = material.dissolve.unwrap_or(1.0);
+
+        Self {
+            name: Some(material.name.clone()),
+            albedo_factor: [red, green, blue, dissolve],
+            // Gives highlights about the same size as the Blinn-Phong exponent would
+            roughness_factor: material
+                .shininess
+                .map_or(1.0, |shininess| (2.0 / (shininess + 2.0)).sqrt()),
+            blend_mode: if dissolve < 1.0 {
+                BlendMode::Blend
+            } else {
+                BlendMode::Opaque
+            },
+            albedo_texture: image(&material.diffuse_texture),
+            normal_texture: image(&material.normal_texture).and_then(decompressed),
+            ..Self::default()
+        }
+    }
+}
+
+/// A material's textures uploaded for OpenGL. Those the material doesn't have are replaced with
+/// 1x1 textures that leave its factors unchanged, so every material binds the same uniforms.
+pub struct MaterialTextures {
+    pub albedo: Texture,
+    pub normal: Texture2d,
+    pub metallic_roughness: Texture2d,
+    pub occlusion: Texture2d,
+    pub emissive: Texture,
+}
+
+impl MaterialTextures {
+    pub fn upload(material: &Material, display: &Display<WindowSurface>) -> Result<Self> {
+        Ok(Self {
+            albedo: srgb_texture(material.albedo_texture.clone(), display)?,
+            normal: linear_texture(material.normal_texture.clone(), FLAT_NORMAL, display)?,
+            metallic_roughness: linear_texture(
+                material.metallic_roughness_texture.clone(),
+                WHITE,
+                display,
+            )?,
+            occlusion: linear_texture(material.occlusion_texture.clone(), WHITE, display)?,
+            emissive: srgb_texture(material.emissive_texture.clone(), display)?,
+        })
+    }
+}
+
+/// Per frame uniforms with a material's uniforms added on, for drawing one primitive
+pub(crate) struct MaterialUniforms<'a, U: Uniforms> {
+    pub uniforms: &'a U,
+    pub material: &'a Material,
+    pub textures: &'a MaterialTextures,
+    pub max_anisotropy: u16,
+}
+
+impl<U: Uniforms> Uniforms for MaterialUniforms<'_, U> {
+    /// Samples the material's textures with up to `max_anisotropy`
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut output: F) {
+        self.uniforms.visit_values(&mut output);
+
+        let material = self.material;
+        let textures = self.textures;
+        let sampler = Some(SamplerBehavior {
+            wrap_function: (
+                SamplerWrapFunction::Repeat,
+                SamplerWrapFunction::Repeat,
+                SamplerWrapFunction::Repeat,
+            ),
+            minify_filter: MinifySamplerFilter::LinearMipmapLinear,
+            max_anisotropy: self.max_anisotropy,
+            ..SamplerBehavior::default()
+        });
+
+        output("albedo_factor", UniformValue::Vec4(material.albedo_factor));
+        output(
+            "metallic_factor",
+            UniformValue::Float(material.metallic_factor),
+        );
+        output(
+            "roughness_factor",
+            UniformValue::Float(material.roughness_factor),
+        );
+        output("normal_scale", UniformValue::Float(material.normal_scale));
+        output(
+            "occlusion_strength",
+            UniformValue::Float(material.occlusion_strength),
+        );
+        output(
+            "emissive_factor",
+            UniformValue::Vec3(material.emissive_factor),
+        );
+
+        output("albedo_texture", textures.albedo.uniform_value(sampler));
+        output(
+            "normal_texture",
+            UniformValue::Texture2d(&textures.normal, sampler),
+        );
+        output(
+            "metallic_roughness_texture",
+            UniformValue::Texture2d(&textures.metallic_roughness, sampler),
+        );
+        output(
+            "occlusion_texture",
+            UniformValue::Texture2d(&textures.occlusion, sampler),
+        );
+        output("emissive_texture", textures.emissive.uniform_value(sampler));
+    }
+}
+
+/// Uploads an sRGB texture, compressed if it was stored compressed, or white if there's none
+fn srgb_texture(
+    image: Option<DecodedTexture>,
+    display: &Display<WindowSurface>,
+) -> Result<Texture> {
+    match image {
+        Some(DecodedTexture::Raw(image)) => Ok(Texture::Srgb(SrgbTexture2d::with_mipmaps(
+            display,
+            raw_image(image),
+            MipmapsOption::AutoGeneratedMipmaps,
+        )?)),
+        Some(compressed) => Texture::upload(compressed, display),
+        None => srgb_texture(Some(DecodedTexture::Raw(pixel(WHITE))), display),
+    }
+}
+
+fn linear_texture(
+    image: Option<RgbaImage>,
+    fallback: [u8; 4],
+    display: &Display<WindowSurface>,
+) -> Result<Texture2d> {
+    Ok(Texture2d::with_mipmaps(
+        display,
+        raw_image(image.unwrap_or_else(|| pixel(fallback))),
+        MipmapsOption::AutoGeneratedMipmaps,
+    )?)
+}
+
+/// Reads an image file as `Assets::load_texture` does, keeping DDS and KTX2 files compressed,
+/// warning if it can't be read
+fn load_image(path: &Path) -> Option<DecodedTexture> {
+    DecodedTexture::read(path)
+        .map_err(|error| warn!("Failed to load texture {:?}: {}", path, error))
+        .ok()
+}
+
+/// An image read from a file as plain RGBA, for textures holding linear data, which compressed
+/// textures are only uploaded as if they're sRGB
+fn decompressed(image: DecodedTexture) -> Option<RgbaImage> {
+    match image {
+        DecodedTexture::Raw(image) => Some(image),
+        DecodedTexture::Compressed(image) => texture::decompress(&image)
+            .map_err(|error| warn!("Failed to decompress a texture: {}", error))
+            .ok(),
+    }
+}
+
+fn pixel(color: [u8; 4]) -> RgbaImage {
+    RgbaImage::from_pixel(1, 1, image::Rgba(color))
+}
+
+/// Uploaded with its first row first, as glTF's texture coordinates start at the top
+fn raw_image(image: RgbaImage) -> RawImage2d<'static, u8> {
+    let dimensions = image.dimensions();
+    RawImage2d::from_raw_rgba(image.into_raw(), dimensions)
+}
+
+/// Converts a glTF image to RGBA, warning and leaving it out if its format isn't supported
+fn rgba_image(image: &gltf::image::Data) -> Option<RgbaImage> {
+    let pixels = &image.pixels;
+    let rgba = match image.format {
+        Format::R8G8B8A8 => pixels.clone(),
+        Format::R8G8B8 => pixels
+            .chunks_exact(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+            .collect(),
+        Format::R8G8 => pixels
+            .chunks_exact(2)
+            .flat_map(|pixel| [pixel[0], pixel[1], 0, 255])
+            .collect(),
+        Format::R8 => pixels
+            .iter()
+            .flat_map(|&value| [value, value, value, 255])
+            .collect(),
+        format => {
+            warn!("Unsupported texture format {format:?}, using a placeholder");
+            return None;
+        }
+    };
+
+    RgbaImage::from_raw(image.width, image.height, rgba)
+}
@@ -1,4 +1,10 @@
-use cgmath::Matrix4;
+use std::f32::consts::PI;
+
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, SquareMatrix, Transform, Vector3, Vector4,
+    Zero,
+};
+use serde::{Deserialize, Serialize};
 
 pub fn linear_map(
     x: f32,
@@ -10,6 +16,311 @@ pub fn linear_map(
     ((x - original_min) * (target_max - target_min) / (original_max - original_min)) + target_min
 }
 
+/// Hermite interpolation between 0 and 1 as `x` moves from `edge0` to `edge1`
+pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// A random direction up to `angle` radians away from the normalized `axis`, spread evenly over
+/// the cone
+pub fn random_in_cone(axis: Vector3<f32>, angle: f32) -> Vector3<f32> {
+    if angle <= 0.0 {
+        return axis;
+    }
+
+    let up = if axis.y.abs() < 0.99 {
+        Vector3::unit_y()
+    } else {
+        Vector3::unit_x()
+    };
+    let right = axis.cross(up).normalize();
+    let up = right.cross(axis);
+
+    // Square root so directions aren't bunched in the middle
+    let angle = angle * fastrand::f32().sqrt();
+    let around = 2.0 * PI * fastrand::f32();
+
+    (axis * angle.cos() + (right * around.cos() + up * around.sin()) * angle.sin()).normalize()
+}
+
 pub fn raw_matrix(matrix: Matrix4<f32>) -> [[f32; 4]; 4] {
     <[[f32; 4]; 4]>::from(matrix)
 }
+
+/// Replaces the near plane of a perspective projection with an arbitrary view space clip plane,
+/// given as `(normal, distance)`, so that geometry behind the plane is clipped. See Lengyel,
+/// "Oblique View Frustum Depth Projection and Clipping".
+pub fn oblique_projection(projection: Matrix4<f32>, clip_plane: Vector4<f32>) -> Matrix4<f32> {
+    let mut projection = projection;
+
+    let corner = Vector4::new(
+        (clip_plane.x.signum() + projection.z.x) / projection.x.x,
+        (clip_plane.y.signum() + projection.z.y) / projection.y.y,
+        -1.0,
+        (1.0 + projection.z.z) / projection.w.z,
+    );
+
+    let scaled_plane = clip_plane * (2.0 / clip_plane.dot(corner));
+
+    projection.x.z = scaled_plane.x;
+    projection.y.z = scaled_plane.y;
+    projection.z.z = scaled_plane.z + 1.0;
+    projection.w.z = scaled_plane.w;
+
+    projection
+}
+
+/// Moves a plane given as `(normal, distance)` by a transform
+pub fn transform_plane(transform: Matrix4<f32>, plane: Vector4<f32>) -> Vector4<f32> {
+    transform.invert().unwrap().transpose() * plane
+}
+
+/// Axis aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_center(center: Point3<f32>, half_extents: Vector3<f32>) -> Self {
+        Self {
+            min: center - half_extents,
+            max: center + half_extents,
+        }
+    }
+
+    /// The smallest box around every point, or `None` if there are no points
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+
+        Some(points.fold(Self::new(first, first), |aabb, point| {
+            aabb.union(&Self::new(point, point))
+        }))
+    }
+
+    /// The smallest box around both boxes
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (min, max) = (self.min, self.max);
+
+        [
+            Point3::new(min.x, min.y, min.z),
+            Point3::new(max.x, min.y, min.z),
+            Point3::new(min.x, max.y, min.z),
+            Point3::new(max.x, max.y, min.z),
+            Point3::new(min.x, min.y, max.z),
+            Point3::new(max.x, min.y, max.z),
+            Point3::new(min.x, max.y, max.z),
+            Point3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// The box around this one after it has been transformed, e.g. from model to world space
+    pub fn transformed(&self, transform: Matrix4<f32>) -> Self {
+        Self::from_points(
+            self.corners()
+                .map(|corner| transform.transform_point(corner)),
+        )
+        .unwrap()
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) / 2.0
+    }
+
+    pub fn contains(&self, point: Point3<f32>) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x)
+            && (self.min.y..=self.max.y).contains(&point.y)
+            && (self.min.z..=self.max.z).contains(&point.z)
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+            && self.min.z <= other.max.z
+            && self.max.z >= other.min.z
+    }
+
+    /// Where a ray first enters the box as `(t, normal)`, with the normal of the face it enters
+    /// through. A ray starting inside the box hits it at `t = 0` facing back along the ray.
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<(f32, Vector3<f32>)> {
+        let mut near = f32::NEG_INFINITY;
+        let mut far = f32::INFINITY;
+        let mut normal = Vector3::zero();
+
+        // Slab method, the ray is inside the box where it is between every pair of faces
+        for axis in 0..3 {
+            let origin = ray.origin[axis];
+            let direction = ray.direction[axis];
+
+            if direction == 0.0 {
+                if origin < self.min[axis] || origin > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+
+            let t_min = (self.min[axis] - origin) / direction;
+            let t_max = (self.max[axis] - origin) / direction;
+            let (entry, exit) = if t_min < t_max {
+                (t_min, t_max)
+            } else {
+                (t_max, t_min)
+            };
+
+            if entry > near {
+                near = entry;
+                normal = Vector3::zero();
+                normal[axis] = -direction.signum();
+            }
+            far = far.min(exit);
+        }
+
+        if near > far || far < 0.0 {
+            return None;
+        }
+
+        Some(if near < 0.0 {
+            (0.0, -ray.direction.normalize())
+        } else {
+            (near, normal)
+        })
+    }
+}
+
+/// Half line from `origin` along `direction`, which needn't be normalized. Distances along a ray
+/// are in multiples of its direction.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> Point3<f32> {
+        self.origin + self.direction * t
+    }
+
+    /// The same ray in another space, e.g. world to model space with an inverse model matrix.
+    /// The direction isn't renormalized, so distances along both rays match.
+    pub fn transformed(&self, transform: Matrix4<f32>) -> Self {
+        Self {
+            origin: transform.transform_point(self.origin),
+            direction: transform.transform_vector(self.direction),
+        }
+    }
+
+    /// Where the ray hits a triangle as `(t, normal)`, from either side. The normal follows the
+    /// triangle's counter-clockwise winding. See Möller and Trumbore, "Fast, Minimum Storage
+    /// Ray/Triangle Intersection".
+    pub fn intersect_triangle(&self, triangle: [Point3<f32>; 3]) -> Option<(f32, Vector3<f32>)> {
+        let [a, b, c] = triangle;
+        let edge1 = b - a;
+        let edge2 = c - a;
+
+        let p = self.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let inverse_determinant = 1.0 / determinant;
+        let offset = self.origin - a;
+
+        let u = offset.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = offset.cross(edge1);
+        let v = self.direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(q) * inverse_determinant;
+        (t >= 0.0).then(|| (t, edge1.cross(edge2).normalize()))
+    }
+}
+
+/// The volume a camera can see, bounded by six planes given as `(normal, distance)` with the
+/// normals pointing inwards
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view projection matrix. See Gribb and Hartmann, "Fast
+    /// Extraction of Viewing Frustum Planes from the World-View-Projection Matrix".
+    pub fn from_matrix(view_projection: Matrix4<f32>) -> Self {
+        let (x, y, z, w) = (
+            view_projection.row(0),
+            view_projection.row(1),
+            view_projection.row(2),
+            view_projection.row(3),
+        );
+
+        Self {
+            planes: [w + x, w - x, w + y, w - y, w + z, w - z],
+        }
+    }
+
+    /// Whether any part of a box may be visible. Boxes near the corners of the frustum can be
+    /// reported as visible when they aren't, which only costs drawing them.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal
+            let corner = Vector3::new(
+                if plane.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if plane.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if plane.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            );
+
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::fmt::Debug;
 use std::hash::{Hash, Hasher};
 use std::mem::offset_of;
@@ -5,11 +6,12 @@ use std::path::{Path, PathBuf};
 use std::ptr;
 use std::sync::Arc;
 
-use cgmath::{Matrix4, Quaternion, Vector3, Zero};
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, One, Point3, Quaternion, SquareMatrix,
+    Transform as _, Vector2, Vector3, VectorSpace, Zero,
+};
+use color_eyre::eyre::eyre;
 use color_eyre::Result;
-use glium::glutin::surface::WindowSurface;
-use glium::index::PrimitiveType;
-use glium::{Display, IndexBuffer, VertexBuffer};
 use gltf::buffer::Data;
 use gltf::json::accessor::ComponentType;
 use gltf::{Accessor, Semantic};
@@ -19,10 +21,16 @@ use serde::{Deserialize, Serialize};
 
 use vertex::Vertex;
 
+use crate::animation::{AnimationClip, Skeleton};
+use crate::lod;
+use crate::lod::{LodData, LodLevel};
+use crate::material::{BlendMode, Material};
+use crate::maths::Aabb;
+use crate::obj::ObjImporter;
 use crate::uuid::UUID;
 use crate::{maths, vertex};
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Transform {
     pub translation: Vector3<f32>,
     pub rotation: Quaternion<f32>,
@@ -37,33 +45,90 @@ impl From<Transform> for Matrix4<f32> {
     }
 }
 
+impl Transform {
+    /// Splits a matrix back into a transform, losing any shear
+    pub fn from_matrix(matrix: Matrix4<f32>) -> Self {
+        let [x, y, z] = [matrix.x, matrix.y, matrix.z].map(|column| column.truncate());
+        let mut scale = Vector3::new(x.magnitude(), y.magnitude(), z.magnitude());
+
+        // A mirrored matrix has one flipped axis, which is put in the scale
+        if Matrix3::from_cols(x, y, z).determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+
+        // A collapsed axis has no direction left to take the rotation from
+        let rotation = if scale.x == 0.0 || scale.y == 0.0 || scale.z == 0.0 {
+            Quaternion::one()
+        } else {
+            Quaternion::from(Matrix3::from_cols(x / scale.x, y / scale.y, z / scale.z))
+        };
+
+        Self {
+            translation: matrix.w.truncate(),
+            rotation,
+            scale,
+        }
+    }
+
+    /// Blends between two transforms, where an `amount` of 0 is `self` and 1 is `other`
+    pub fn lerp(&self, other: &Transform, amount: f32) -> Transform {
+        Transform {
+            translation: self.translation.lerp(other.translation, amount),
+            rotation: self
+                .rotation
+                .nlerp(Self::same_hemisphere(self.rotation, other.rotation), amount),
+            scale: self.scale.lerp(other.scale, amount),
+        }
+    }
+
+    /// `to`, negated if needed so blending from `from` takes the shortest way around
+    pub(crate) fn same_hemisphere(from: Quaternion<f32>, to: Quaternion<f32>) -> Quaternion<f32> {
+        if from.dot(to) < 0.0 {
+            -to
+        } else {
+            to
+        }
+    }
+}
+
 impl Default for Transform {
     fn default() -> Self {
         Self {
             translation: Vector3::zero(),
-            rotation: Quaternion::zero(),
+            rotation: Quaternion::one(),
             scale: Vector3::new(1.0, 1.0, 1.0),
         }
     }
 }
 
+/// Component holding an entity's `Transform` from the previous simulation step, so that rendering
+/// can blend between steps
+#[derive(Clone)]
+pub struct PreviousTransform(pub Transform);
+
+/// Component that draws a model at its entity's `Transform`
+#[derive(Clone)]
 pub struct ModelInstance {
     pub model: Arc<Model>,
-    pub transform: Transform,
 }
 
 impl From<Arc<Model>> for ModelInstance {
     fn from(model: Arc<Model>) -> Self {
-        Self {
-            model,
-            transform: Transform::default(),
-        }
+        Self { model }
     }
 }
 
+/// Vertices and indices kept on the CPU, which each backend copies to the GPU the first time the
+/// primitive is drawn
 pub struct Primitive {
-    pub vertex_buffer: VertexBuffer<Vertex>,
-    pub index_buffer: IndexBuffer<u16>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+    /// Model space bounds of the vertices
+    pub bounds: Aabb,
+    /// Index into the model's materials
+    pub material: usize,
+    /// Copies of the vertex positions, for raycasts and colliders
+    pub positions: Vec<Point3<f32>>,
 }
 
 // TODO could move all vertices / indices into one buffer and then have an offset into this for each primitive
@@ -72,35 +137,353 @@ pub struct Mesh {
     pub primitives: Vec<Primitive>,
 }
 
+/// A node of a model's glTF hierarchy, e.g. one prop in an arrangement or an attachment point
+pub struct Node {
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// Relative to the parent, as it is in the file
+    pub transform: Transform,
+    /// Relative to the model, with the Y axis flipped like the vertices are
+    pub global: Matrix4<f32>,
+    /// Index into the model's meshes of the mesh drawn at this node
+    pub mesh: Option<usize>,
+}
+
 pub struct Model {
     pub uuid: UUID,
+    /// One per node with a mesh, with the node's transform already applied to the vertices
     pub meshes: Vec<Mesh>,
+    /// Every node in the document, indexed the same way as in the glTF
+    pub nodes: Vec<Node>,
     pub path: PathBuf,
+    /// Model space bounds of every mesh, for culling
+    pub bounds: Aabb,
+    /// Materials of the model's primitives, ending with a default for those without one. Editable
+    /// in place, which changes every instance of the model.
+    pub materials: Vec<RefCell<Material>>,
+    /// Present for skinned models, which are posed by their joints
+    pub skeleton: Option<Skeleton>,
+    pub animations: Vec<AnimationClip>,
+    /// Simpler versions of the meshes for drawing far away, nearest first. Skinned models have
+    /// none.
+    pub lods: Vec<LodLevel>,
+    /// GLB data the model was read from when it isn't a file, e.g. one embedded in a map, so it
+    /// can be embedded again
+    pub embedded: Option<Vec<u8>>,
 }
 
-impl Model {
-    pub fn load(path: &Path, display: &Display<WindowSurface>) -> Result<Arc<Self>> {
-        debug!("Loading model \"{:?}\"...", path);
+/// Reads one model file format into the same meshes, vertices and materials as every other
+pub trait ModelImporter {
+    /// Lowercase extensions of the files this reads
+    fn extensions(&self) -> &[&str];
+
+    /// Reads the file, which can be done on a loading thread
+    fn decode(&self, path: &Path) -> Result<ModelData>;
+}
+
+/// A primitive's vertices and indices as an importer built them, before their bounds are found.
+/// The vertices have already had their Y axis flipped.
+pub struct PrimitiveData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+    /// Index into the model's materials
+    pub material: usize,
+}
+
+pub struct MeshData {
+    pub name: Option<String>,
+    pub primitives: Vec<PrimitiveData>,
+}
+
+impl MeshData {
+    pub fn into_mesh(self) -> Mesh {
+        Mesh {
+            name: self.name,
+            primitives: self
+                .primitives
+                .into_iter()
+                .map(PrimitiveData::into_primitive)
+                .collect(),
+        }
+    }
+}
+
+/// A model file read into memory with its vertices built, images decoded and levels of detail
+/// generated, waiting to be put together into a `Model`
+pub struct ModelData {
+    pub meshes: Vec<MeshData>,
+    pub nodes: Vec<Node>,
+    /// Ending with a default for primitives without one
+    pub materials: Vec<Material>,
+    pub skeleton: Option<Skeleton>,
+    pub animations: Vec<AnimationClip>,
+    pub lods: Vec<LodData>,
+    /// The GLB data itself, if it was read from memory rather than a file
+    pub embedded: Option<Vec<u8>>,
+}
 
-        // TODO parse materials
-        let (document, file_buffers, _images) = gltf::import(path)?;
+impl ModelData {
+    /// Puts together what an importer has read, generating levels of detail
+    pub(crate) fn new(
+        meshes: Vec<MeshData>,
+        nodes: Vec<Node>,
+        materials: Vec<Material>,
+        skeleton: Option<Skeleton>,
+        animations: Vec<AnimationClip>,
+    ) -> Self {
+        // Merging vertices would mix up which joints they follow
+        let lods = match skeleton {
+            Some(_) => vec![],
+            None => lod::generate(&meshes),
+        };
 
-        Ok(Arc::new(Model {
+        Self {
+            meshes,
+            nodes,
+            materials,
+            skeleton,
+            animations,
+            lods,
+            embedded: None,
+        }
+    }
+
+    /// Finds the model's bounds and gives it an ID. The `path` is only used to identify the
+    /// model.
+    pub fn into_model(self, path: &Path) -> Arc<Model> {
+        let materials = self.materials.into_iter().map(RefCell::new).collect();
+        let meshes = self
+            .meshes
+            .into_iter()
+            .map(MeshData::into_mesh)
+            .collect::<Vec<Mesh>>();
+        let lods = self.lods.into_iter().map(LodData::into_level).collect();
+
+        let bounds = meshes
+            .iter()
+            .flat_map(|mesh| mesh.primitives.iter())
+            .map(|primitive| primitive.bounds)
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or(Aabb::new(Point3::origin(), Point3::origin()));
+
+        Arc::new(Model {
             uuid: UUID::new(),
             path: path.to_owned(),
-            meshes: document
-                .meshes()
-                .map(|mesh| Mesh {
-                    name: mesh.name().map(str::to_owned),
-                    primitives: mesh
-                        .primitives()
-                        .map(|primitive| {
-                            Primitive::from(primitive, &file_buffers, display).unwrap()
-                        })
-                        .collect::<Vec<Primitive>>(),
-                })
-                .collect::<Vec<Mesh>>(),
-        }))
+            meshes,
+            nodes: self.nodes,
+            bounds,
+            materials,
+            skeleton: self.skeleton,
+            animations: self.animations,
+            lods,
+            embedded: self.embedded,
+        })
+    }
+
+    /// Builds the vertices and reads the materials of a glTF document, whose buffers and images
+    /// are already loaded
+    fn from_gltf(
+        document: &gltf::Document,
+        file_buffers: &[Data],
+        images: &[gltf::image::Data],
+    ) -> Self {
+        let mut materials = document
+            .materials()
+            .map(|material| Material::from_gltf(material, images))
+            .collect_vec();
+        let default_material = materials.len();
+        materials.push(Material::default());
+
+        let mut nodes = Node::from_gltf(document);
+        let mut meshes = vec![];
+
+        for (gltf_node, node) in document.nodes().zip(nodes.iter_mut()) {
+            let Some(mesh) = gltf_node.mesh() else {
+                continue;
+            };
+
+            // Skinned meshes are placed by their joints instead, as glTF specifies
+            let transform = match gltf_node.skin() {
+                Some(_) => Matrix4::identity(),
+                None => node.global,
+            };
+
+            node.mesh = Some(meshes.len());
+            meshes.push(MeshData {
+                name: node.name.clone().or(mesh.name().map(str::to_owned)),
+                primitives: mesh
+                    .primitives()
+                    .map(|primitive| {
+                        PrimitiveData::from_gltf(
+                            primitive,
+                            file_buffers,
+                            default_material,
+                            transform,
+                        )
+                    })
+                    .collect(),
+            });
+        }
+
+        let skeleton = Skeleton::from_gltf(document, file_buffers);
+        let animations = document
+            .animations()
+            .map(|animation| AnimationClip::from_gltf(animation, file_buffers))
+            .collect();
+
+        Self::new(meshes, nodes, materials, skeleton, animations)
+    }
+}
+
+/// Reads glTF, in either its binary or JSON form
+pub struct GltfImporter;
+
+impl ModelImporter for GltfImporter {
+    fn extensions(&self) -> &[&str] {
+        &["glb", "gltf"]
+    }
+
+    fn decode(&self, path: &Path) -> Result<ModelData> {
+        let (document, buffers, images) = gltf::import(path)?;
+
+        Ok(ModelData::from_gltf(&document, &buffers, &images))
+    }
+}
+
+/// Every format `Model::load` can read, tried in order by file extension
+const IMPORTERS: &[&dyn ModelImporter] = &[&GltfImporter, &ObjImporter];
+
+impl Model {
+    /// Loads a model with the importer for its file extension
+    pub fn load(path: &Path) -> Result<Arc<Self>> {
+        Ok(Self::decode(path)?.into_model(path))
+    }
+
+    /// Reads a model with the importer for its file extension, leaving it to be put together
+    /// later. Can be called from any thread.
+    pub fn decode(path: &Path) -> Result<ModelData> {
+        debug!("Loading model \"{:?}\"...", path);
+
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let importer = IMPORTERS
+            .iter()
+            .find(|importer| importer.extensions().contains(&extension.as_str()))
+            .ok_or_else(|| eyre!("No importer for models with extension \"{}\"", extension))?;
+
+        importer.decode(path)
+    }
+
+    /// Extensions of every model file that can be loaded, e.g. for file dialogs
+    pub fn extensions() -> Vec<&'static str> {
+        IMPORTERS
+            .iter()
+            .flat_map(|importer| importer.extensions().iter().copied())
+            .collect()
+    }
+
+    /// Loads a model from GLB data held in memory, e.g. embedded in a map. The `path` is only used
+    /// to identify the model.
+    pub fn load_from_bytes(path: &Path, bytes: &[u8]) -> Result<Arc<Self>> {
+        Ok(Self::decode_bytes(path, bytes)?.into_model(path))
+    }
+
+    /// Reads GLB data held in memory, leaving it to be put together later
+    pub fn decode_bytes(path: &Path, bytes: &[u8]) -> Result<ModelData> {
+        debug!("Loading embedded model \"{:?}\"...", path);
+
+        let (document, buffers, images) = gltf::import_slice(bytes)?;
+
+        Ok(ModelData {
+            embedded: Some(bytes.to_vec()),
+            ..ModelData::from_gltf(&document, &buffers, &images)
+        })
+    }
+
+    /// Whether any primitive is blended with what's behind it, so must be drawn after everything
+    /// opaque
+    pub fn has_blended_materials(&self) -> bool {
+        self.materials
+            .iter()
+            .any(|material| material.borrow().blend_mode == BlendMode::Blend)
+    }
+
+    /// Meshes to draw at a level of detail from `lod::select_level`, where 0 is full detail
+    pub fn lod_meshes(&self, level: usize) -> &[Mesh] {
+        match level.checked_sub(1).and_then(|index| self.lods.get(index)) {
+            Some(lod) => &lod.meshes,
+            None => &self.meshes,
+        }
+    }
+}
+
+impl Model {
+    /// The first node with the given name
+    pub fn node(&self, name: &str) -> Option<&Node> {
+        self.nodes
+            .iter()
+            .find(|node| node.name.as_deref() == Some(name))
+    }
+}
+
+impl Node {
+    fn from_gltf(document: &gltf::Document) -> Vec<Self> {
+        let mut parents = vec![None; document.nodes().len()];
+        for node in document.nodes() {
+            for child in node.children() {
+                parents[child.index()] = Some(node.index());
+            }
+        }
+
+        let mut nodes = document
+            .nodes()
+            .map(|node| {
+                let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+
+                Node {
+                    name: node.name().map(str::to_owned),
+                    parent: parents[node.index()],
+                    children: node.children().map(|child| child.index()).collect(),
+                    transform: Transform {
+                        translation: Vector3::from(translation),
+                        rotation: Quaternion::new(w, x, y, z),
+                        scale: Vector3::from(scale),
+                    },
+                    global: Matrix4::identity(),
+                    mesh: None,
+                }
+            })
+            .collect_vec();
+
+        // Parents before their children, so each parent's global transform is ready first
+        let mut order = nodes
+            .iter()
+            .positions(|node| node.parent.is_none())
+            .collect_vec();
+        let mut next = 0;
+        while next < order.len() {
+            order.extend_from_slice(&nodes[order[next]].children);
+            next += 1;
+        }
+
+        let flip = Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0);
+        let mut globals = vec![Matrix4::identity(); nodes.len()];
+
+        for node in order {
+            let local = Matrix4::from(nodes[node].transform.clone());
+
+            globals[node] = match nodes[node].parent {
+                Some(parent) => globals[parent] * local,
+                None => local,
+            };
+            nodes[node].global = flip * globals[node] * flip;
+        }
+
+        nodes
     }
 }
 
@@ -118,12 +501,17 @@ impl Hash for Model {
     }
 }
 
-impl Primitive {
-    fn from(
+impl PrimitiveData {
+    pub fn into_primitive(self) -> Primitive {
+        Primitive::new(self.vertices, self.indices, self.material)
+    }
+
+    fn from_gltf(
         primitive: gltf::Primitive,
         file_buffers: &[Data],
-        display: &Display<WindowSurface>,
-    ) -> Result<Self> {
+        default_material: usize,
+        transform: Matrix4<f32>,
+    ) -> Self {
         let available_attributes = primitive
             .attributes()
             .map(|(semantic, _)| semantic)
@@ -145,14 +533,23 @@ impl Primitive {
             generate_tex_coords(&mut vertices);
         }
 
-        let vertex_buffer = VertexBuffer::new(display, &vertices)?;
+        if !available_attributes.contains(&Semantic::Tangents) {
+            generate_tangents(&mut vertices, &indices);
+        }
 
-        let index_buffer = IndexBuffer::new(display, PrimitiveType::TrianglesList, &indices)?;
+        for vertex in vertices.iter_mut() {
+            vertex.position[1] *= -1.0;
+        }
 
-        Ok(Primitive {
-            vertex_buffer,
-            index_buffer,
-        })
+        if transform != Matrix4::identity() {
+            transform_vertices(&mut vertices, transform);
+        }
+
+        Self {
+            vertices,
+            indices,
+            material: primitive.material().index().unwrap_or(default_material),
+        }
     }
 
     fn extract_indices(primitive: &gltf::Primitive, file_buffers: &[Data]) -> Vec<u16> {
@@ -201,18 +598,70 @@ impl Primitive {
                         file_buffers,
                     );
                 }
-                _ => unimplemented!("{semantic:?}"),
+                Semantic::Tangents => {
+                    map_accessor_data_to_buffer(
+                        &mut vertices,
+                        offset_of!(Vertex, tangent),
+                        &accessor,
+                        file_buffers,
+                    );
+                }
+                // Joints and weights come in several formats, so they're read below instead
+                Semantic::Joints(0) | Semantic::Weights(0) => {}
+                _ => warn!("Skipping unsupported vertex attribute {:?}", semantic),
             }
         }
 
-        for vertex in vertices.iter_mut() {
-            vertex.position[1] *= -1.0;
+        let reader = primitive.reader(|buffer| Some(&file_buffers[buffer.index()]));
+
+        if let Some(joints) = reader.read_joints(0) {
+            for (vertex, joints) in vertices.iter_mut().zip(joints.into_u16()) {
+                vertex.joints = joints.map(f32::from);
+            }
+        }
+
+        if let Some(weights) = reader.read_weights(0) {
+            for (vertex, weights) in vertices.iter_mut().zip(weights.into_f32()) {
+                vertex.weights = weights;
+            }
         }
 
         vertices
     }
 }
 
+impl Primitive {
+    /// Takes vertices that have already had their Y axis flipped, finding their bounds
+    pub(crate) fn new(vertices: Vec<Vertex>, indices: Vec<u16>, material: usize) -> Self {
+        let positions = vertices
+            .iter()
+            .map(|vertex| Point3::from(vertex.position))
+            .collect_vec();
+
+        let bounds = Aabb::from_points(positions.iter().copied())
+            .unwrap_or(Aabb::new(Point3::origin(), Point3::origin()));
+
+        Primitive {
+            vertices,
+            indices,
+            bounds,
+            material,
+            positions,
+        }
+    }
+
+    /// Model space corners of each triangle
+    pub fn triangles(&self) -> impl Iterator<Item = [Point3<f32>; 3]> + '_ {
+        self.indices.iter().tuples().map(|(&a, &b, &c)| {
+            [
+                self.positions[a as usize],
+                self.positions[b as usize],
+                self.positions[c as usize],
+            ]
+        })
+    }
+}
+
 /// Fills the member, specified by the `byte_offset`, of each element of a given buffer from an `Accessor`
 fn map_accessor_data_to_buffer<T: Debug>(
     destination_buffer: &mut [T],
@@ -256,7 +705,7 @@ fn map_accessor_data_to_buffer<T: Debug>(
     }
 }
 
-fn generate_tex_coords(vertices: &mut [Vertex]) {
+pub(crate) fn generate_tex_coords(vertices: &mut [Vertex]) {
     let mut x_min = f32::MAX;
     let mut x_max = f32::MIN;
     let mut z_min = f32::MAX;
@@ -279,6 +728,82 @@ fn generate_tex_coords(vertices: &mut [Vertex]) {
     }
 }
 
+/// Tangents for normal mapping, averaged from the texture coordinates of the triangles around each
+/// vertex, for models that don't come with them. Vertices must not have had their Y axis flipped
+/// yet, so the tangents are in the same space as the normals. Vertices whose triangles have no
+/// usable texture coordinates are left without, which turns off normal mapping for them.
+pub(crate) fn generate_tangents(vertices: &mut [Vertex], indices: &[u16]) {
+    let mut tangents = vec![Vector3::zero(); vertices.len()];
+    let mut bitangents = vec![Vector3::zero(); vertices.len()];
+
+    for (&a, &b, &c) in indices.iter().tuples() {
+        let corners = [a, b, c].map(usize::from);
+        let position = |corner: usize| Vector3::from(vertices[corners[corner]].position);
+        let tex_coord = |corner: usize| Vector2::from(vertices[corners[corner]].tex_coord);
+
+        let (edge_1, edge_2) = (position(1) - position(0), position(2) - position(0));
+        let (uv_1, uv_2) = (tex_coord(1) - tex_coord(0), tex_coord(2) - tex_coord(0));
+
+        let determinant = uv_1.x * uv_2.y - uv_2.x * uv_1.y;
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+
+        // Larger triangles count for more, as the sums aren't normalized
+        let tangent = (edge_1 * uv_2.y - edge_2 * uv_1.y) / determinant;
+        let bitangent = (edge_2 * uv_1.x - edge_1 * uv_2.x) / determinant;
+        for corner in corners {
+            tangents[corner] += tangent;
+            bitangents[corner] += bitangent;
+        }
+    }
+
+    for ((vertex, tangent), bitangent) in vertices.iter_mut().zip(tangents).zip(bitangents) {
+        let normal = Vector3::from(vertex.normal);
+        let tangent = tangent - normal * normal.dot(tangent);
+        if tangent.magnitude2() < f32::EPSILON {
+            continue;
+        }
+
+        let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        vertex.tangent = tangent.normalize().extend(handedness).into();
+    }
+}
+
+/// Moves vertices by a node's transform, keeping their normals and tangents perpendicular to and
+/// along the surface
+fn transform_vertices(vertices: &mut [Vertex], transform: Matrix4<f32>) {
+    let linear = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    );
+    let normal_matrix = linear.invert().unwrap_or(linear).transpose();
+    // Mirroring transforms swap the handedness of the bitangent
+    let handedness = linear.determinant().signum();
+
+    for vertex in vertices.iter_mut() {
+        vertex.position = transform
+            .transform_point(Point3::from(vertex.position))
+            .into();
+
+        let normal = normal_matrix * Vector3::from(vertex.normal);
+        if !normal.is_zero() {
+            vertex.normal = normal.normalize().into();
+        }
+
+        let [x, y, z, w] = vertex.tangent;
+        let tangent = linear * Vector3::new(x, y, z);
+        if !tangent.is_zero() {
+            vertex.tangent = tangent.normalize().extend(w * handedness).into();
+        }
+    }
+}
+
 fn calculate_bit_stride(accessor: &Accessor) -> usize {
     let component_size = match accessor.data_type() {
         ComponentType::U8 | ComponentType::I8 => 8,
@@ -0,0 +1,226 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+/// A grid of walkable cells laid over the XZ plane, starting at `origin`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NavGrid {
+    pub width: usize,
+    pub depth: usize,
+    pub cell_size: f32,
+    pub origin: Point3<f32>,
+    walkable: Vec<bool>,
+}
+
+impl NavGrid {
+    /// Creates a grid where every cell is blocked
+    pub fn new(width: usize, depth: usize, cell_size: f32, origin: Point3<f32>) -> Self {
+        Self {
+            width,
+            depth,
+            cell_size,
+            origin,
+            walkable: vec![false; width * depth],
+        }
+    }
+
+    pub fn set_walkable(&mut self, x: usize, z: usize, walkable: bool) {
+        if x < self.width && z < self.depth {
+            self.walkable[z * self.width + x] = walkable;
+        }
+    }
+
+    /// Cells outside of the grid are never walkable
+    pub fn is_walkable(&self, x: usize, z: usize) -> bool {
+        x < self.width && z < self.depth && self.walkable[z * self.width + x]
+    }
+
+    /// Finds the cell containing a world space position
+    pub fn cell_at(&self, position: Point3<f32>) -> Option<(usize, usize)> {
+        let local = position - self.origin;
+
+        let x = (local.x / self.cell_size).floor();
+        let z = (local.z / self.cell_size).floor();
+
+        if x < 0.0 || z < 0.0 || x as usize >= self.width || z as usize >= self.depth {
+            return None;
+        }
+
+        Some((x as usize, z as usize))
+    }
+
+    /// World space position of the centre of a cell, on the grid's plane
+    pub fn cell_center(&self, x: usize, z: usize) -> Point3<f32> {
+        self.origin
+            + Vector3::new(
+                (x as f32 + 0.5) * self.cell_size,
+                0.0,
+                (z as f32 + 0.5) * self.cell_size,
+            )
+    }
+
+    /// Shortest walkable route from one position to another with A*, as the positions to walk
+    /// through after `start`, ending at `goal`. Moves can be diagonal, but not past the corners of
+    /// blocked cells, and corners are cut wherever the straight line between them is walkable.
+    /// `None` if either end isn't on a walkable cell or there's no way between them.
+    pub fn find_path(&self, start: Point3<f32>, goal: Point3<f32>) -> Option<Vec<Point3<f32>>> {
+        let start_cell = self.cell_at(start)?;
+        let goal_cell = self.cell_at(goal)?;
+        if !self.is_walkable(start_cell.0, start_cell.1)
+            || !self.is_walkable(goal_cell.0, goal_cell.1)
+        {
+            return None;
+        }
+
+        let mut open = BinaryHeap::from([Candidate {
+            cost: heuristic(start_cell, goal_cell),
+            cell: start_cell,
+        }]);
+        let mut came_from = HashMap::new();
+        let mut costs = HashMap::from([(start_cell, 0.0)]);
+
+        while let Some(Candidate { cell, .. }) = open.pop() {
+            if cell == goal_cell {
+                let mut cells = vec![cell];
+                while let Some(&previous) = came_from.get(cells.last().unwrap()) {
+                    cells.push(previous);
+                }
+                cells.reverse();
+
+                return Some(self.smooth(start, &cells, goal));
+            }
+
+            let cost = costs[&cell];
+            for (neighbour, step) in self.neighbours(cell) {
+                let new_cost = cost + step;
+                if costs.get(&neighbour).is_some_and(|&old| old <= new_cost) {
+                    continue;
+                }
+
+                costs.insert(neighbour, new_cost);
+                came_from.insert(neighbour, cell);
+                open.push(Candidate {
+                    cost: new_cost + heuristic(neighbour, goal_cell),
+                    cell: neighbour,
+                });
+            }
+        }
+
+        None
+    }
+
+    /// Whether every cell a straight line crosses between two positions is walkable
+    pub fn is_line_walkable(&self, from: Point3<f32>, to: Point3<f32>) -> bool {
+        let mut offset = to - from;
+        offset.y = 0.0;
+
+        // Sampled more finely than the cells, so a line can't skip past one
+        let samples = (offset.magnitude() / (self.cell_size * 0.25))
+            .ceil()
+            .max(1.0) as usize;
+
+        (0..=samples).all(|sample| {
+            let position = from + offset * (sample as f32 / samples as f32);
+            self.cell_at(position)
+                .is_some_and(|(x, z)| self.is_walkable(x, z))
+        })
+    }
+
+    /// Walkable cells next to a cell and how far away they are, in cells
+    fn neighbours(
+        &self,
+        (x, z): (usize, usize),
+    ) -> impl Iterator<Item = ((usize, usize), f32)> + '_ {
+        let walkable = |dx: isize, dz: isize| {
+            let (x, z) = (x.checked_add_signed(dx)?, z.checked_add_signed(dz)?);
+            self.is_walkable(x, z).then_some((x, z))
+        };
+
+        [-1, 0, 1]
+            .into_iter()
+            .flat_map(|dx| [-1, 0, 1].into_iter().map(move |dz| (dx, dz)))
+            .filter(|&offset| offset != (0, 0))
+            .filter_map(move |(dx, dz)| {
+                let cell = walkable(dx, dz)?;
+                if dx != 0 && dz != 0 {
+                    // Diagonals need both cells beside them walkable
+                    walkable(dx, 0)?;
+                    walkable(0, dz)?;
+                    return Some((cell, std::f32::consts::SQRT_2));
+                }
+
+                Some((cell, 1.0))
+            })
+    }
+
+    /// Turns a chain of cells into the fewest positions that can be walked between in straight
+    /// lines
+    fn smooth(
+        &self,
+        start: Point3<f32>,
+        cells: &[(usize, usize)],
+        goal: Point3<f32>,
+    ) -> Vec<Point3<f32>> {
+        // Cell centres on the way, at the height the path starts at
+        let mut points = cells
+            .iter()
+            .skip(1)
+            .take(cells.len().saturating_sub(2))
+            .map(|&(x, z)| {
+                let mut center = self.cell_center(x, z);
+                center.y = start.y;
+                center
+            })
+            .collect::<Vec<_>>();
+        points.push(goal);
+
+        let mut path = vec![];
+        let mut from = start;
+        let mut index = 0;
+        while index < points.len() {
+            // The furthest point that can be walked to directly
+            let furthest = (index..points.len())
+                .rev()
+                .find(|&later| self.is_line_walkable(from, points[later]))
+                .unwrap_or(index);
+
+            from = points[furthest];
+            path.push(from);
+            index = furthest + 1;
+        }
+
+        path
+    }
+}
+
+/// Lower bound on the distance between cells, in cells, allowing diagonal moves
+fn heuristic(from: (usize, usize), to: (usize, usize)) -> f32 {
+    let dx = from.0.abs_diff(to.0) as f32;
+    let dz = from.1.abs_diff(to.1) as f32;
+
+    dx.max(dz) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dz)
+}
+
+/// A cell waiting to be explored, ordered so the heap pops the cheapest first
+#[derive(Copy, Clone, PartialEq)]
+struct Candidate {
+    /// Cost of getting here plus the heuristic to the goal
+    cost: f32,
+    cell: (usize, usize),
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
@@ -0,0 +1,679 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use cgmath::{InnerSpace, Quaternion, Rotation3, Vector2, Vector3, Zero};
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use itertools::Itertools;
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::entity::World;
+use crate::model::{ModelInstance, Transform};
+use crate::scene::Scene;
+use crate::uuid::UUID;
+
+pub const DEFAULT_PORT: u16 = 7777;
+
+/// Simulation steps per second on the server, each followed by a snapshot
+pub const TICK_RATE: f64 = 30.0;
+
+/// Clients and servers only talk to each other if their versions match
+const PROTOCOL_VERSION: u32 = 1;
+
+/// Kept under the usual internet MTU so packets aren't fragmented
+const MAX_PACKET_SIZE: usize = 1200;
+
+/// Entities per snapshot packet, so each packet stays under `MAX_PACKET_SIZE`
+const ENTITIES_PER_CHUNK: usize = 8;
+/// Most chunks a snapshot can be split into, so a bad packet can't make the client allocate
+/// without bound
+const MAX_SNAPSHOT_CHUNKS: u32 = 4096;
+
+/// Clients that haven't sent anything in this long are dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a client asks to join until the server answers
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How far behind the latest snapshot remote entities are shown, so there's usually a newer
+/// snapshot to blend towards even if one is late
+const INTERPOLATION_DELAY: Duration = Duration::from_millis(100);
+
+/// Units per second players move at
+const PLAYER_SPEED: f32 = 6.0;
+
+/// What a player wants to do, sent by their client every frame
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInput {
+    /// Counts up with each input sent
+    pub sequence: u32,
+    /// Right and forward from -1 to 1
+    pub movement: Vector2<f32>,
+    /// Radians, matching `Camera`
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fire: bool,
+}
+
+impl Default for PlayerInput {
+    fn default() -> Self {
+        Self {
+            sequence: 0,
+            movement: Vector2::zero(),
+            yaw: 0.0,
+            pitch: 0.0,
+            fire: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum ClientMessage {
+    Connect { version: u32 },
+    Input(PlayerInput),
+    Disconnect,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum ServerMessage {
+    /// Accepts a client, telling it which entity is its player
+    Welcome { player: UUID },
+    /// Part of the state of every replicated entity at `tick`
+    Snapshot {
+        tick: u64,
+        chunk: u32,
+        chunks: u32,
+        entities: Vec<EntitySnapshot>,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct EntitySnapshot {
+    entity: UUID,
+    transform: Transform,
+    model: Option<PathBuf>,
+}
+
+// Transforms aren't Debug
+impl std::fmt::Debug for EntitySnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EntitySnapshot")
+            .field("entity", &self.entity)
+            .field("model", &self.model)
+            .finish()
+    }
+}
+
+/// Component for entities the server sends to clients, which draw them with `model` if it has
+/// one. It can be a path or logical asset name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replicated {
+    pub model: Option<PathBuf>,
+}
+
+/// Component for an entity controlled by a client
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Player {
+    pub input: PlayerInput,
+}
+
+fn send<T: Serialize>(socket: &UdpSocket, address: SocketAddr, message: &T) {
+    let bytes = match bincode::serialize(message) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            warn!("Could not serialize message: {}", error);
+            return;
+        }
+    };
+
+    if bytes.len() > MAX_PACKET_SIZE {
+        warn!(
+            "Sending a {} byte packet, which may be fragmented",
+            bytes.len()
+        );
+    }
+
+    if let Err(error) = socket.send_to(&bytes, address) {
+        warn!("Could not send to {}: {}", address, error);
+    }
+}
+
+/// Every message waiting on a non-blocking socket, skipping any that can't be read
+fn receive<T: DeserializeOwned>(socket: &UdpSocket) -> Vec<(T, SocketAddr)> {
+    let mut buffer = [0; MAX_PACKET_SIZE * 2];
+    let mut messages = vec![];
+
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((length, address)) => match bincode::deserialize(&buffer[..length]) {
+                Ok(message) => messages.push((message, address)),
+                Err(error) => warn!("Ignoring bad packet from {}: {}", address, error),
+            },
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            // Sends to a closed port can be reported here on some platforms
+            Err(error) if error.kind() == ErrorKind::ConnectionReset => continue,
+            Err(error) => {
+                warn!("Could not receive: {}", error);
+                break;
+            }
+        }
+    }
+
+    messages
+}
+
+fn forward_direction(yaw: f32) -> Vector3<f32> {
+    Vector3::new(yaw.cos(), 0.0, yaw.sin())
+}
+
+struct Connection {
+    player: UUID,
+    last_heard: Instant,
+}
+
+/// Authoritative simulation that clients connect to. Players move by the latest input from their
+/// client and every `Replicated` entity is sent back each tick.
+pub struct Server {
+    socket: UdpSocket,
+    connections: HashMap<SocketAddr, Connection>,
+    /// Drawn for each player
+    pub player_model: Option<PathBuf>,
+    tick: u64,
+}
+
+impl Server {
+    pub fn bind(address: impl ToSocketAddrs) -> Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+
+        info!("Server listening on {}", socket.local_addr()?);
+
+        Ok(Self {
+            socket,
+            connections: HashMap::new(),
+            player_model: None,
+            tick: 0,
+        })
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Handles messages from clients, spawning and despawning their players, and drops clients
+    /// that have gone quiet
+    pub fn receive(&mut self, world: &mut World) {
+        for (message, address) in receive::<ClientMessage>(&self.socket) {
+            match message {
+                ClientMessage::Connect { version } => {
+                    if version != PROTOCOL_VERSION {
+                        warn!(
+                            "Ignoring {} with protocol version {}, expected {}",
+                            address, version, PROTOCOL_VERSION
+                        );
+                        continue;
+                    }
+
+                    // Connect is resent until answered, so it may arrive after joining
+                    let player = match self.connections.get(&address) {
+                        Some(connection) => connection.player,
+                        None => {
+                            let player = self.spawn_player(world);
+                            info!("{} joined as {}", address, player);
+                            player
+                        }
+                    };
+
+                    self.connections.insert(
+                        address,
+                        Connection {
+                            player,
+                            last_heard: Instant::now(),
+                        },
+                    );
+                    send(&self.socket, address, &ServerMessage::Welcome { player });
+                }
+                ClientMessage::Input(input) => {
+                    let Some(connection) = self.connections.get_mut(&address) else {
+                        continue;
+                    };
+                    connection.last_heard = Instant::now();
+
+                    // Inputs can arrive out of order, only the newest counts
+                    if let Some(player) = world.get_mut::<Player>(connection.player) {
+                        if input.sequence >= player.input.sequence {
+                            player.input = input;
+                        }
+                    }
+                }
+                ClientMessage::Disconnect => {
+                    if let Some(connection) = self.connections.remove(&address) {
+                        info!("{} left", address);
+                        world.despawn(connection.player);
+                    }
+                }
+            }
+        }
+
+        let timed_out = self
+            .connections
+            .iter()
+            .filter(|(_, connection)| connection.last_heard.elapsed() > CLIENT_TIMEOUT)
+            .map(|(&address, _)| address)
+            .collect_vec();
+
+        for address in timed_out {
+            let connection = self.connections.remove(&address).unwrap();
+            info!("{} timed out", address);
+            world.despawn(connection.player);
+        }
+    }
+
+    /// Moves every player by their input over a tick lasting `deltatime` seconds
+    pub fn update(&mut self, world: &mut World, deltatime: f32) {
+        world.for_each2_mut::<Transform, Player>(|_, transform, player| {
+            let forward = forward_direction(player.input.yaw);
+            let right = forward.cross(Vector3::unit_y());
+
+            let movement = player.input.movement;
+            let direction = forward * movement.y + right * movement.x;
+            if direction.magnitude2() > 1.0 {
+                transform.translation += direction.normalize() * PLAYER_SPEED * deltatime;
+            } else {
+                transform.translation += direction * PLAYER_SPEED * deltatime;
+            }
+
+            transform.rotation = Quaternion::from_arc(-Vector3::unit_z(), forward, None);
+        });
+
+        self.tick += 1;
+    }
+
+    /// Sends the state of every `Replicated` entity to every client
+    pub fn send_snapshots(&self, world: &World) {
+        let entities = world
+            .query2::<Replicated, Transform>()
+            .map(|(entity, replicated, transform)| EntitySnapshot {
+                entity,
+                transform: transform.clone(),
+                model: replicated.model.clone(),
+            })
+            .collect_vec();
+
+        // An empty world still needs a snapshot so clients despawn what they have
+        let chunks = entities.chunks(ENTITIES_PER_CHUNK).collect_vec();
+        let chunks = if chunks.is_empty() {
+            vec![&[][..]]
+        } else {
+            chunks
+        };
+
+        for (index, chunk) in chunks.iter().enumerate() {
+            let message = ServerMessage::Snapshot {
+                tick: self.tick,
+                chunk: index as u32,
+                chunks: chunks.len() as u32,
+                entities: chunk.to_vec(),
+            };
+
+            for &address in self.connections.keys() {
+                send(&self.socket, address, &message);
+            }
+        }
+    }
+
+    fn spawn_player(&self, world: &mut World) -> UUID {
+        let player = world.spawn();
+        world.insert(player, Transform::default());
+        world.insert(
+            player,
+            Player {
+                input: PlayerInput::default(),
+            },
+        );
+        world.insert(
+            player,
+            Replicated {
+                model: self.player_model.clone(),
+            },
+        );
+
+        player
+    }
+}
+
+/// A complete snapshot and when its last chunk arrived
+struct ReceivedSnapshot {
+    received: Instant,
+    transforms: HashMap<UUID, Transform>,
+}
+
+/// Chunks of the newest snapshot that haven't all arrived yet
+struct PartialSnapshot {
+    tick: u64,
+    chunks: u32,
+    /// A bit per chunk that has arrived, so a chunk received twice isn't counted twice
+    received: Vec<u64>,
+    entities: Vec<EntitySnapshot>,
+}
+
+impl PartialSnapshot {
+    fn new(tick: u64, chunks: u32) -> Self {
+        Self {
+            tick,
+            chunks,
+            received: vec![0; chunks.div_ceil(64) as usize],
+            entities: vec![],
+        }
+    }
+
+    /// Marks a chunk as arrived, returning false if it already had or isn't one of the snapshot's
+    fn receive(&mut self, chunk: u32) -> bool {
+        if chunk >= self.chunks {
+            return false;
+        }
+
+        let (word, bit) = ((chunk / 64) as usize, 1 << (chunk % 64));
+        let first = self.received[word] & bit == 0;
+        self.received[word] |= bit;
+
+        first
+    }
+
+    fn is_complete(&self) -> bool {
+        self.received
+            .iter()
+            .map(|word| word.count_ones())
+            .sum::<u32>()
+            == self.chunks
+    }
+}
+
+/// Connection to a server, showing the entities it sends in a scene. Remote entities are blended
+/// between snapshots, shown slightly in the past so movement stays smooth between packets.
+pub struct Client {
+    socket: UdpSocket,
+    server: SocketAddr,
+    /// This client's entity once the server has accepted it
+    player: Option<UUID>,
+    sequence: u32,
+    last_connect_attempt: Option<Instant>,
+    latest_tick: Option<u64>,
+    partial: Option<PartialSnapshot>,
+    snapshots: VecDeque<ReceivedSnapshot>,
+    /// Models of the remote entities spawned into the scene so far
+    models: HashMap<UUID, Option<PathBuf>>,
+}
+
+impl Client {
+    pub fn connect(server: impl ToSocketAddrs) -> Result<Self> {
+        let server = server
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| eyre!("Server address did not resolve"))?;
+
+        let socket = UdpSocket::bind(if server.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        })?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            server,
+            player: None,
+            sequence: 0,
+            last_connect_attempt: None,
+            latest_tick: None,
+            partial: None,
+            snapshots: VecDeque::new(),
+            models: HashMap::new(),
+        })
+    }
+
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Whether the server has accepted this client
+    pub fn connected(&self) -> bool {
+        self.player.is_some()
+    }
+
+    pub fn player(&self) -> Option<UUID> {
+        self.player
+    }
+
+    /// Sends this frame's input, filling in its sequence number
+    pub fn send_input(&mut self, input: PlayerInput) {
+        if !self.connected() {
+            return;
+        }
+
+        self.sequence += 1;
+        send(
+            &self.socket,
+            self.server,
+            &ClientMessage::Input(PlayerInput {
+                sequence: self.sequence,
+                ..input
+            }),
+        );
+    }
+
+    /// Handles messages from the server and moves remote entities in the scene to where they
+    /// were `INTERPOLATION_DELAY` ago. Entities the server stops sending are despawned.
+    pub fn update(&mut self, scene: &mut Scene) {
+        if !self.connected()
+            && self
+                .last_connect_attempt
+                .map_or(true, |attempt| attempt.elapsed() > CONNECT_RETRY_INTERVAL)
+        {
+            self.last_connect_attempt = Some(Instant::now());
+            send(
+                &self.socket,
+                self.server,
+                &ClientMessage::Connect {
+                    version: PROTOCOL_VERSION,
+                },
+            );
+        }
+
+        for (message, address) in receive::<ServerMessage>(&self.socket) {
+            if address != self.server {
+                continue;
+            }
+
+            match message {
+                ServerMessage::Welcome { player } => {
+                    if self.player.is_none() {
+                        info!("Joined {} as {}", self.server, player);
+                    }
+                    self.player = Some(player);
+                }
+                ServerMessage::Snapshot {
+                    tick,
+                    chunk,
+                    chunks,
+                    entities,
+                } => self.receive_chunk(tick, chunk, chunks, entities),
+            }
+        }
+
+        self.apply(scene);
+    }
+
+    /// Tells the server this client is leaving and despawns the remote entities
+    pub fn disconnect(self, scene: &mut Scene) {
+        send(&self.socket, self.server, &ClientMessage::Disconnect);
+
+        for entity in self.models.keys() {
+            scene.simulation.despawn(*entity);
+        }
+    }
+
+    fn receive_chunk(&mut self, tick: u64, chunk: u32, chunks: u32, entities: Vec<EntitySnapshot>) {
+        // Chunks of snapshots older than one already shown are too late to matter
+        if self.latest_tick.is_some_and(|latest| tick <= latest)
+            || chunk >= chunks
+            || chunks > MAX_SNAPSHOT_CHUNKS
+        {
+            return;
+        }
+
+        if self
+            .partial
+            .as_ref()
+            .map_or(true, |partial| partial.tick < tick)
+        {
+            self.partial = Some(PartialSnapshot::new(tick, chunks));
+        }
+
+        let partial = self.partial.as_mut().unwrap();
+        if partial.tick != tick || partial.chunks != chunks || !partial.receive(chunk) {
+            return;
+        }
+
+        partial.entities.extend(entities);
+
+        if !partial.is_complete() {
+            return;
+        }
+
+        let partial = self.partial.take().unwrap();
+        self.latest_tick = Some(tick);
+
+        let mut transforms = HashMap::new();
+        for snapshot in partial.entities {
+            self.models.entry(snapshot.entity).or_insert(snapshot.model);
+            transforms.insert(snapshot.entity, snapshot.transform);
+        }
+
+        self.snapshots.push_back(ReceivedSnapshot {
+            received: Instant::now(),
+            transforms,
+        });
+    }
+
+    fn apply(&mut self, scene: &mut Scene) {
+        let Some(render_time) = Instant::now().checked_sub(INTERPOLATION_DELAY) else {
+            return;
+        };
+
+        // Keep one snapshot from before the render time to blend from
+        while self.snapshots.len() > 2 && self.snapshots[1].received <= render_time {
+            self.snapshots.pop_front();
+        }
+
+        let Some(from) = self.snapshots.front() else {
+            return;
+        };
+        let to = self.snapshots.get(1).unwrap_or(from);
+
+        let amount = if to.received > from.received {
+            (render_time
+                .saturating_duration_since(from.received)
+                .as_secs_f32()
+                / (to.received - from.received).as_secs_f32())
+            .clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+
+        let despawned = self
+            .models
+            .keys()
+            .copied()
+            .filter(|entity| !to.transforms.contains_key(entity))
+            .collect_vec();
+        for entity in despawned {
+            self.models.remove(&entity);
+            scene.simulation.despawn(entity);
+        }
+
+        for (&entity, to_transform) in to.transforms.iter() {
+            // This client's own player is moved by its camera instead
+            if Some(entity) == self.player {
+                continue;
+            }
+
+            let transform = match from.transforms.get(&entity) {
+                Some(from_transform) => from_transform.lerp(to_transform, amount),
+                None => to_transform.clone(),
+            };
+
+            if scene.simulation.world.contains(entity) {
+                scene.simulation.world.insert(entity, transform);
+                continue;
+            }
+
+            scene.simulation.world.spawn_with_uuid(entity);
+            scene.simulation.world.insert(entity, transform);
+
+            if let Some(path) = self.models.get(&entity).cloned().flatten() {
+                match scene.load_model(Path::new(&path)) {
+                    Ok(model) => {
+                        scene
+                            .simulation
+                            .world
+                            .insert(entity, ModelInstance::from(model));
+                    }
+                    Err(error) => warn!("Could not load {:?} for {}: {}", path, entity, error),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_complete_once_every_chunk_arrives() {
+        for chunks in [1, 63, 64, 65, 130] {
+            let mut partial = PartialSnapshot::new(1, chunks);
+            for chunk in (0..chunks).rev() {
+                assert!(!partial.is_complete(), "{} of {}", chunk, chunks);
+                assert!(partial.receive(chunk));
+            }
+
+            assert!(partial.is_complete(), "{} chunks", chunks);
+        }
+    }
+
+    #[test]
+    fn duplicate_chunks_are_only_counted_once() {
+        let mut partial = PartialSnapshot::new(1, 3);
+
+        assert!(partial.receive(1));
+        assert!(!partial.receive(1));
+        assert!(partial.receive(0));
+        assert!(!partial.receive(0));
+        assert!(!partial.is_complete());
+
+        assert!(partial.receive(2));
+        assert!(partial.is_complete());
+    }
+
+    #[test]
+    fn out_of_range_chunks_are_ignored() {
+        // Past the last chunk but within the last word of the bitset, and past the bitset
+        let mut partial = PartialSnapshot::new(1, 2);
+        assert!(!partial.receive(2));
+        assert!(!partial.receive(63));
+        assert!(!partial.receive(64));
+        assert!(!partial.receive(u32::MAX));
+
+        assert!(partial.receive(0));
+        assert!(!partial.is_complete());
+        assert!(partial.receive(1));
+        assert!(partial.is_complete());
+    }
+}
@@ -0,0 +1,143 @@
+use std::path::Path;
+
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Zero};
+use color_eyre::eyre::ensure;
+use color_eyre::Result;
+use itertools::Itertools;
+use log::warn;
+
+use crate::material::Material;
+use crate::model::{self, MeshData, ModelData, ModelImporter, Node, PrimitiveData, Transform};
+use crate::vertex::Vertex;
+
+/// Reads Wavefront OBJ files along with the MTL files they refer to. Each object becomes a mesh
+/// with a node of its own, as OBJ has no hierarchy.
+pub struct ObjImporter;
+
+impl ModelImporter for ObjImporter {
+    fn extensions(&self) -> &[&str] {
+        &["obj"]
+    }
+
+    fn decode(&self, path: &Path) -> Result<ModelData> {
+        let (objects, materials) = tobj::load_obj(
+            path,
+            &tobj::LoadOptions {
+                single_index: true,
+                triangulate: true,
+                ..tobj::LoadOptions::default()
+            },
+        )?;
+
+        let mtl_materials = materials.unwrap_or_else(|error| {
+            warn!("Failed to load materials for {:?}: {}", path, error);
+            vec![]
+        });
+
+        // The textures the materials refer to are beside the MTL file
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let mut materials = mtl_materials
+            .iter()
+            .map(|material| Material::from_mtl(material, directory))
+            .collect_vec();
+        let default_material = materials.len();
+        materials.push(Material::default());
+
+        let mut meshes = vec![];
+        let mut nodes = vec![];
+
+        for object in objects {
+            let mesh = &object.mesh;
+            ensure!(
+                mesh.positions.len() / 3 <= u16::MAX as usize + 1,
+                "\"{}\" in {:?} has too many vertices",
+                object.name,
+                path
+            );
+
+            let indices = mesh.indices.iter().map(|&index| index as u16).collect_vec();
+            let vertices = vertices(mesh, &indices);
+            let material = mesh
+                .material_id
+                .filter(|&material| material < default_material)
+                .unwrap_or(default_material);
+
+            nodes.push(Node {
+                name: Some(object.name.clone()),
+                parent: None,
+                children: vec![],
+                transform: Transform::default(),
+                global: Matrix4::identity(),
+                mesh: Some(meshes.len()),
+            });
+            meshes.push(MeshData {
+                name: Some(object.name),
+                primitives: vec![PrimitiveData {
+                    vertices,
+                    indices,
+                    material,
+                }],
+            });
+        }
+
+        Ok(ModelData::new(meshes, nodes, materials, None, vec![]))
+    }
+}
+
+/// Vertices in the same form as those loaded from glTF, generating whatever the file leaves out
+fn vertices(mesh: &tobj::Mesh, indices: &[u16]) -> Vec<Vertex> {
+    let mut vertices = mesh
+        .positions
+        .chunks_exact(3)
+        .map(|position| Vertex {
+            position: [position[0], position[1], position[2]],
+            ..Vertex::default()
+        })
+        .collect_vec();
+
+    if mesh.normals.is_empty() {
+        generate_normals(&mut vertices, &mesh.indices);
+    } else {
+        for (vertex, normal) in vertices.iter_mut().zip(mesh.normals.chunks_exact(3)) {
+            vertex.normal = [normal[0], normal[1], normal[2]];
+        }
+    }
+
+    if mesh.texcoords.is_empty() {
+        model::generate_tex_coords(&mut vertices);
+    } else {
+        // OBJ texture coordinates start at the bottom of the image, glTF's at the top
+        for (vertex, tex_coord) in vertices.iter_mut().zip(mesh.texcoords.chunks_exact(2)) {
+            vertex.tex_coord = [tex_coord[0], 1.0 - tex_coord[1]];
+        }
+    }
+
+    model::generate_tangents(&mut vertices, indices);
+
+    for vertex in vertices.iter_mut() {
+        vertex.position[1] *= -1.0;
+    }
+
+    vertices
+}
+
+/// Smooth normals, averaging the faces around each vertex weighted by their area
+fn generate_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![Vector3::zero(); vertices.len()];
+
+    for (&a, &b, &c) in indices.iter().tuples() {
+        let [a, b, c] = [a, b, c].map(|index| index as usize);
+        let [pa, pb, pc] = [a, b, c].map(|index| Point3::from(vertices[index].position));
+        let normal = (pb - pa).cross(pc - pa);
+
+        for index in [a, b, c] {
+            normals[index] += normal;
+        }
+    }
+
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        if !normal.is_zero() {
+            vertex.normal = normal.normalize().into();
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{Array, EuclideanSpace, Matrix4, Vector3};
+use color_eyre::Result;
+use glium::draw_parameters::AnySamplesPassedQuery;
+use glium::glutin::surface::WindowSurface;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::{
+    implement_vertex, uniform, Depth, DepthTest, Display, DrawParameters, Program, Surface,
+    VertexBuffer,
+};
+
+use crate::assets::{Assets, Handle};
+use crate::camera::Camera;
+use crate::debug_draw::DebugDraw;
+use crate::maths;
+use crate::maths::Aabb;
+use crate::uuid::UUID;
+use crate::{colors, profiling};
+
+/// Bounds the camera is this close to are always drawn, as their faces nearest to it would be
+/// clipped by the near plane and pass no samples
+const CAMERA_MARGIN: f32 = 0.5;
+
+#[derive(Copy, Clone)]
+struct BoxVertex {
+    position: [f32; 3],
+}
+implement_vertex!(BoxVertex, position);
+
+/// Skips instances hidden behind others, going by whether any of their bounds passed the depth
+/// test when drawn after the opaque geometry. Queries are only read once the GPU has answered
+/// them, so nothing stalls, and results are a frame or more late: something coming out from
+/// behind a wall may be missing for a frame.
+pub struct OcclusionCuller {
+    /// Draws the bounds of occluded instances through `DebugDraw`
+    pub visualize: bool,
+    /// Last known answer for instances tested, cleared out for those no longer in view
+    occluded: HashSet<UUID>,
+    /// Queries the GPU hasn't answered yet, which aren't issued again until it has
+    pending: HashMap<UUID, AnySamplesPassedQuery>,
+    /// Bounds of instances in view this frame, tested once the opaque geometry is drawn
+    candidates: Vec<(UUID, Aabb)>,
+    /// The program and a unit cube drawn as triangles, scaled to each instance's bounds, made by
+    /// the first test
+    resources: Option<(Handle<Program>, VertexBuffer<BoxVertex>)>,
+}
+
+impl OcclusionCuller {
+    pub fn new() -> Self {
+        Self {
+            visualize: false,
+            occluded: HashSet::new(),
+            pending: HashMap::new(),
+            candidates: vec![],
+            resources: None,
+        }
+    }
+
+    /// Whether an instance was hidden when last tested
+    pub fn is_occluded(&self, entity: UUID) -> bool {
+        self.occluded.contains(&entity)
+    }
+
+    /// Queues an instance in view to be tested this frame, with its bounds in world space
+    pub fn add_candidate(&mut self, entity: UUID, bounds: Aabb) {
+        self.candidates.push((entity, bounds));
+    }
+
+    /// Reads the queries the GPU has answered, before instances are chosen for a frame
+    pub fn collect(&mut self) {
+        let _scope = profiling::scope("Occlusion queries");
+
+        let answered = self
+            .pending
+            .iter()
+            .filter(|(_, query)| query.is_ready())
+            .map(|(&entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in answered {
+            let visible = self.pending.remove(&entity).unwrap().get();
+            if visible {
+                self.occluded.remove(&entity);
+            } else {
+                self.occluded.insert(entity);
+            }
+        }
+    }
+
+    /// Tests the bounds of this frame's candidates against the depth drawn to `target` so far,
+    /// writing neither color nor depth. Returns the number of draw calls made.
+    pub fn test<S: Surface>(
+        &mut self,
+        display: &Display<WindowSurface>,
+        target: &mut S,
+        assets: &mut Assets,
+        camera: &Camera,
+        debug: &mut DebugDraw,
+    ) -> Result<usize> {
+        if self.resources.is_none() {
+            let program = assets.load_program(
+                "assets/shaders/occlusion/occlusion.vert",
+                "assets/shaders/occlusion/occlusion.frag",
+                display,
+            )?;
+            self.resources = Some((program, VertexBuffer::new(display, &cube_vertices())?));
+        }
+        let (program, cube) = self.resources.as_ref().unwrap();
+
+        let candidates = std::mem::take(&mut self.candidates);
+
+        // Anything out of view is tested afresh when it comes back
+        let in_view = candidates
+            .iter()
+            .map(|&(entity, _)| entity)
+            .collect::<HashSet<_>>();
+        self.occluded.retain(|entity| in_view.contains(entity));
+        self.pending.retain(|entity, _| in_view.contains(entity));
+
+        let margin = Vector3::from_value(CAMERA_MARGIN);
+        let mut draw_calls = 0;
+        for (entity, bounds) in candidates {
+            if self.visualize && self.occluded.contains(&entity) {
+                debug.draw_aabb(&bounds, colors::RED);
+            }
+
+            if self.pending.contains_key(&entity) {
+                continue;
+            }
+
+            let around_camera = Aabb::new(bounds.min - margin, bounds.max + margin);
+            if around_camera.contains(camera.position) {
+                self.occluded.remove(&entity);
+                continue;
+            }
+
+            let query = AnySamplesPassedQuery::new(display, true)?;
+            let size = bounds.max - bounds.min;
+            let model = Matrix4::from_translation(bounds.min.to_vec())
+                * Matrix4::from_nonuniform_scale(size.x, size.y, size.z);
+
+            target.draw(
+                cube,
+                &NoIndices(PrimitiveType::TrianglesList),
+                assets.program(*program),
+                &uniform! {
+                    mvp: maths::raw_matrix(camera.view_projection * model),
+                },
+                &DrawParameters {
+                    depth: Depth {
+                        test: DepthTest::IfLess,
+                        write: false,
+                        ..Depth::default()
+                    },
+                    color_mask: (false, false, false, false),
+                    samples_passed_query: Some((&query).into()),
+                    ..DrawParameters::default()
+                },
+            )?;
+            self.pending.insert(entity, query);
+            draw_calls += 1;
+        }
+
+        Ok(draw_calls)
+    }
+
+    /// Forgets every result, e.g. when turned off so nothing stays hidden when turned back on
+    pub fn clear(&mut self) {
+        self.occluded.clear();
+        self.pending.clear();
+        self.candidates.clear();
+    }
+}
+
+impl Default for OcclusionCuller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Two triangles per face of a unit cube, going by `Aabb::corners`' order. Faces are drawn both
+/// ways, so winding doesn't matter.
+fn cube_vertices() -> Vec<BoxVertex> {
+    let corners = Aabb::new([0.0; 3].into(), [1.0; 3].into()).corners();
+    let faces = [
+        [0, 1, 3, 2],
+        [4, 5, 7, 6],
+        [0, 1, 5, 4],
+        [2, 3, 7, 6],
+        [0, 2, 6, 4],
+        [1, 3, 7, 5],
+    ];
+
+    faces
+        .iter()
+        .flat_map(|&[a, b, c, d]| [a, b, c, a, c, d])
+        .map(|corner| BoxVertex {
+            position: corners[corner].into(),
+        })
+        .collect()
+}
@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Transform, Vector3, Vector4, VectorSpace};
+use color_eyre::Result;
+use glium::glutin::surface::WindowSurface;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::{
+    implement_vertex, uniform, Blend, BlendingFunction, Depth, DepthTest, Display, DrawParameters,
+    LinearBlendingFactor, Program, Surface, VertexBuffer,
+};
+use itertools::Itertools;
+use palette::Srgba;
+
+use crate::assets::{Assets, Handle};
+use crate::camera::Camera;
+use crate::entity::World;
+use crate::hierarchy;
+use crate::maths;
+use crate::uuid::UUID;
+
+/// Downwards acceleration of particles with a `gravity` of 1
+const GRAVITY: f32 = 9.81;
+
+/// Oldest particles are dropped to make room past this many
+const MAX_PARTICLES: usize = 10_000;
+
+/// Component spawning particles from its entity along the entity's forward direction, following
+/// it as it moves. Also describes the particles spawned by `ParticleSystem::burst`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParticleEmitter {
+    /// Particles spawned per second while `emitting`
+    pub rate: f32,
+    pub emitting: bool,
+    /// Seconds each particle lives for
+    pub lifetime: f32,
+    /// Units per second particles start moving at
+    pub speed: f32,
+    /// Radians particles can stray from the forward direction
+    pub spread: f32,
+    /// Multiple of normal gravity pulling particles down
+    pub gravity: f32,
+    /// Width of each particle when it spawns and when it dies
+    pub start_size: f32,
+    pub end_size: f32,
+    /// Colours particles fade between over their lifetime. Blending is additive, so black is
+    /// invisible.
+    pub start_color: Srgba,
+    pub end_color: Srgba,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            rate: 20.0,
+            emitting: true,
+            lifetime: 1.0,
+            speed: 1.0,
+            spread: 0.3,
+            gravity: 0.0,
+            start_size: 0.1,
+            end_size: 0.1,
+            start_color: Srgba::new(1.0, 1.0, 1.0, 1.0),
+            end_color: Srgba::new(1.0, 1.0, 1.0, 0.0),
+        }
+    }
+}
+
+impl ParticleEmitter {
+    /// A short, bright burst of flame from a gun's muzzle
+    pub fn muzzle_flash() -> Self {
+        Self {
+            rate: 0.0,
+            lifetime: 0.06,
+            speed: 4.0,
+            spread: 0.35,
+            start_size: 0.15,
+            end_size: 0.05,
+            start_color: Srgba::new(1.0, 0.8, 0.4, 1.0),
+            end_color: Srgba::new(1.0, 0.3, 0.0, 0.0),
+            ..Self::default()
+        }
+    }
+
+    /// Sparks bouncing off a surface that was hit
+    pub fn sparks() -> Self {
+        Self {
+            rate: 0.0,
+            lifetime: 0.4,
+            speed: 5.0,
+            spread: 1.2,
+            gravity: 1.0,
+            start_size: 0.04,
+            end_size: 0.01,
+            start_color: Srgba::new(1.0, 0.9, 0.5, 1.0),
+            end_color: Srgba::new(1.0, 0.2, 0.0, 0.0),
+            ..Self::default()
+        }
+    }
+
+    /// A glowing trail left behind by something moving, such as a projectile
+    pub fn trail() -> Self {
+        Self {
+            rate: 60.0,
+            lifetime: 0.3,
+            speed: 0.2,
+            spread: std::f32::consts::PI,
+            start_size: 0.08,
+            end_size: 0.0,
+            start_color: Srgba::new(1.0, 0.6, 0.2, 0.8),
+            end_color: Srgba::new(0.6, 0.1, 0.0, 0.0),
+            ..Self::default()
+        }
+    }
+}
+
+struct Particle {
+    position: Point3<f32>,
+    velocity: Vector3<f32>,
+    age: f32,
+    lifetime: f32,
+    gravity: f32,
+    start_size: f32,
+    end_size: f32,
+    start_color: Srgba,
+    end_color: Srgba,
+}
+
+#[derive(Copy, Clone)]
+struct Corner {
+    corner: [f32; 2],
+}
+implement_vertex!(Corner, corner);
+
+#[derive(Copy, Clone)]
+struct ParticleInstance {
+    particle_position: [f32; 3],
+    particle_size: f32,
+    particle_color: [f32; 4],
+}
+implement_vertex!(
+    ParticleInstance,
+    particle_position,
+    particle_size,
+    particle_color
+);
+
+/// Simulates particles on the CPU and draws them all in one instanced draw call as camera facing
+/// quads
+pub struct ParticleSystem {
+    particles: Vec<Particle>,
+    /// Fraction of a particle each emitter has left over from earlier updates
+    spawn_remainders: HashMap<UUID, f32>,
+    /// The program and the quad each particle is drawn as, made by the first render
+    resources: Option<(Handle<Program>, VertexBuffer<Corner>)>,
+    /// Grows to fit the most particles alive at once
+    instances: Option<VertexBuffer<ParticleInstance>>,
+}
+
+impl ParticleSystem {
+    pub fn new() -> Self {
+        Self {
+            particles: vec![],
+            spawn_remainders: HashMap::new(),
+            resources: None,
+            instances: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Spawns `count` particles at once, like `emitter` would, from `position` along `direction`
+    pub fn burst(
+        &mut self,
+        emitter: &ParticleEmitter,
+        position: Point3<f32>,
+        direction: Vector3<f32>,
+        count: usize,
+    ) {
+        let direction = direction.normalize();
+
+        for _ in 0..count {
+            let velocity = maths::random_in_cone(direction, emitter.spread) * emitter.speed;
+
+            self.particles.push(Particle {
+                position,
+                velocity,
+                age: 0.0,
+                lifetime: emitter.lifetime,
+                gravity: emitter.gravity,
+                start_size: emitter.start_size,
+                end_size: emitter.end_size,
+                start_color: emitter.start_color,
+                end_color: emitter.end_color,
+            });
+        }
+
+        if self.particles.len() > MAX_PARTICLES {
+            self.particles.drain(..self.particles.len() - MAX_PARTICLES);
+        }
+    }
+
+    /// Spawns particles from every `ParticleEmitter` in the world and moves those alive, called
+    /// once per frame
+    pub fn update(&mut self, world: &World, deltatime: f32) {
+        self.spawn_remainders
+            .retain(|&entity, _| world.has::<ParticleEmitter>(entity));
+
+        for (entity, emitter) in world.query::<ParticleEmitter>() {
+            let Some(matrix) = hierarchy::global_matrix(world, entity) else {
+                continue;
+            };
+            if !emitter.emitting {
+                continue;
+            }
+
+            let remainder = self.spawn_remainders.entry(entity).or_default();
+            let spawned = emitter.rate * deltatime + *remainder;
+            *remainder = spawned.fract();
+
+            self.burst(
+                emitter,
+                Point3::from_vec(matrix.w.truncate()),
+                matrix.transform_vector(-Vector3::unit_z()),
+                spawned as usize,
+            );
+        }
+
+        for particle in self.particles.iter_mut() {
+            particle.velocity.y -= GRAVITY * particle.gravity * deltatime;
+            particle.position += particle.velocity * deltatime;
+            particle.age += deltatime;
+        }
+
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+    }
+
+    /// Removes every particle, e.g. when the scene is changed
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Draws every particle with additive blending, so they don't need sorting. Returns the number
+    /// of draw calls made.
+    pub fn render<S: Surface>(
+        &mut self,
+        display: &Display<WindowSurface>,
+        target: &mut S,
+        assets: &mut Assets,
+        camera: &Camera,
+    ) -> Result<usize> {
+        let count = self.particles.len();
+        if count == 0 {
+            return Ok(0);
+        }
+
+        if self.resources.is_none() {
+            let program = assets.load_program(
+                "assets/shaders/particle/particle.vert",
+                "assets/shaders/particle/particle.frag",
+                display,
+            )?;
+            let quad = VertexBuffer::new(
+                display,
+                &[[-0.5, -0.5], [0.5, -0.5], [-0.5, 0.5], [0.5, 0.5]]
+                    .map(|corner| Corner { corner }),
+            )?;
+            self.resources = Some((program, quad));
+        }
+
+        if self
+            .instances
+            .as_ref()
+            .map_or(true, |instances| instances.len() < count)
+        {
+            self.instances = Some(VertexBuffer::empty_dynamic(
+                display,
+                count.next_power_of_two(),
+            )?);
+        }
+
+        let (program, quad) = self.resources.as_ref().unwrap();
+        let instances = self.instances.as_ref().unwrap().slice(0..count).unwrap();
+        instances.write(
+            &self
+                .particles
+                .iter()
+                .map(|particle| {
+                    let life = particle.age / particle.lifetime;
+                    let color = color_vector(particle.start_color)
+                        .lerp(color_vector(particle.end_color), life);
+
+                    ParticleInstance {
+                        particle_position: particle.position.into(),
+                        particle_size: particle.start_size
+                            + (particle.end_size - particle.start_size) * life,
+                        particle_color: color.into(),
+                    }
+                })
+                .collect_vec(),
+        );
+
+        let right = camera
+            .forward_direction
+            .cross(camera.up_direction)
+            .normalize();
+        let up = right.cross(camera.forward_direction).normalize();
+
+        let additive = BlendingFunction::Addition {
+            source: LinearBlendingFactor::SourceAlpha,
+            destination: LinearBlendingFactor::One,
+        };
+
+        target.draw(
+            (quad, instances.per_instance().unwrap()),
+            &NoIndices(PrimitiveType::TriangleStrip),
+            assets.program(*program),
+            &uniform! {
+                vp: maths::raw_matrix(camera.view_projection),
+                camera_right: <[f32; 3]>::from(right),
+                camera_up: <[f32; 3]>::from(up),
+            },
+            &DrawParameters {
+                // Hidden by the scene without hiding each other
+                depth: Depth {
+                    test: DepthTest::IfLess,
+                    write: false,
+                    ..Depth::default()
+                },
+                blend: Blend {
+                    color: additive,
+                    alpha: additive,
+                    constant_value: (0.0, 0.0, 0.0, 0.0),
+                },
+                ..DrawParameters::default()
+            },
+        )?;
+
+        Ok(1)
+    }
+}
+
+impl Default for ParticleSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn color_vector(color: Srgba) -> Vector4<f32> {
+    Vector4::new(color.red, color.green, color.blue, color.alpha)
+}
@@ -0,0 +1,664 @@
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Quaternion, Vector3};
+use itertools::Itertools;
+use rapier3d::na::{DMatrix, Isometry3, Translation3, UnitQuaternion};
+use rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::collision::CollisionLayers;
+use crate::entity::World;
+use crate::maths::Aabb;
+use crate::model::{Model, Transform};
+use crate::ragdoll::Ragdoll;
+use crate::simulation::RaycastHit;
+use crate::uuid::UUID;
+
+/// Radians each ragdoll joint can bend from the pose it died in, around each axis
+const RAGDOLL_JOINT_LIMIT: f32 = 1.2;
+
+/// Ragdolls slow down quicker than other bodies, so they settle rather than twitch
+const RAGDOLL_DAMPING: f32 = 0.5;
+
+/// How a rigid body is moved
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BodyKind {
+    /// Moved by forces, gravity and collisions
+    #[default]
+    Dynamic,
+    /// Never moves
+    Fixed,
+    /// Follows its entity's `Transform`, pushing dynamic bodies out of the way
+    Kinematic,
+}
+
+/// Component that hands its entity's `Transform` over to physics. It's read when the body is
+/// created, so changing it afterwards has no effect.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RigidBody {
+    pub kind: BodyKind,
+    pub linear_velocity: Vector3<f32>,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    /// Continuous collision detection, so fast bodies don't pass through thin ones
+    pub ccd: bool,
+}
+
+impl RigidBody {
+    pub fn new(kind: BodyKind) -> Self {
+        Self {
+            kind,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ColliderShape {
+    Cuboid {
+        half_extents: Vector3<f32>,
+    },
+    Ball {
+        radius: f32,
+    },
+    /// Upright, with `half_height` being half the length of the cylinder between the caps
+    Capsule {
+        half_height: f32,
+        radius: f32,
+    },
+    /// Only suitable for bodies that don't move, such as level geometry
+    TriMesh {
+        vertices: Vec<Point3<f32>>,
+        indices: Vec<[u32; 3]>,
+    },
+    /// Grid of heights from 0 to 1, row by row along Z, stretched to `scale` and centered on the
+    /// entity horizontally. For fixed ground such as terrain.
+    HeightField {
+        rows: usize,
+        columns: usize,
+        heights: Vec<f32>,
+        scale: Vector3<f32>,
+    },
+}
+
+/// Component giving its entity a shape to collide with. Entities with a collider but no
+/// `RigidBody` become fixed geometry.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Collider {
+    pub shape: ColliderShape,
+    /// Position of the shape relative to the entity, in its already scaled space
+    pub offset: Vector3<f32>,
+    pub friction: f32,
+    pub restitution: f32,
+    pub density: f32,
+}
+
+impl Collider {
+    pub fn new(shape: ColliderShape) -> Self {
+        Self {
+            shape,
+            offset: Vector3::new(0.0, 0.0, 0.0),
+            friction: 0.5,
+            restitution: 0.0,
+            density: 1.0,
+        }
+    }
+
+    /// A box covering `aabb` once it's been scaled by `scale`
+    pub fn from_aabb(aabb: &Aabb, scale: Vector3<f32>) -> Self {
+        let scaled = |vector: Vector3<f32>| {
+            Vector3::new(vector.x * scale.x, vector.y * scale.y, vector.z * scale.z)
+        };
+
+        Self {
+            offset: scaled(aabb.center().to_vec()),
+            ..Self::new(ColliderShape::Cuboid {
+                half_extents: scaled(aabb.half_extents()),
+            })
+        }
+    }
+
+    /// A box around the model, for an instance of it scaled by `scale`
+    pub fn from_model_bounds(model: &Model, scale: Vector3<f32>) -> Self {
+        Self::from_aabb(&model.bounds, scale)
+    }
+
+    /// The model's triangles for an instance of it scaled by `scale`
+    pub fn from_model_mesh(model: &Model, scale: Vector3<f32>) -> Self {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+
+        for primitive in model.meshes.iter().flat_map(|mesh| mesh.primitives.iter()) {
+            let first = vertices.len() as u32;
+
+            vertices.extend(primitive.positions.iter().map(|position| {
+                Point3::new(
+                    position.x * scale.x,
+                    position.y * scale.y,
+                    position.z * scale.z,
+                )
+            }));
+
+            indices.extend(
+                primitive
+                    .indices
+                    .iter()
+                    .map(|&index| first + index as u32)
+                    .tuples()
+                    .map(|(a, b, c)| [a, b, c]),
+            );
+        }
+
+        Self::new(ColliderShape::TriMesh { vertices, indices })
+    }
+
+    fn build(&self, entity: UUID, layers: CollisionLayers) -> rapier3d::geometry::Collider {
+        let builder = match &self.shape {
+            ColliderShape::Cuboid { half_extents } => {
+                ColliderBuilder::cuboid(half_extents.x, half_extents.y, half_extents.z)
+            }
+            ColliderShape::Ball { radius } => ColliderBuilder::ball(*radius),
+            ColliderShape::Capsule {
+                half_height,
+                radius,
+            } => ColliderBuilder::capsule_y(*half_height, *radius),
+            ColliderShape::TriMesh { vertices, indices } => ColliderBuilder::trimesh(
+                vertices
+                    .iter()
+                    .map(|vertex| point![vertex.x, vertex.y, vertex.z])
+                    .collect(),
+                indices.clone(),
+            ),
+            ColliderShape::HeightField {
+                rows,
+                columns,
+                heights,
+                scale,
+            } => ColliderBuilder::heightfield(
+                DMatrix::from_fn(*rows, *columns, |row, column| {
+                    heights[row * columns + column]
+                }),
+                vector![scale.x, scale.y, scale.z],
+            ),
+        };
+
+        builder
+            .translation(vector![self.offset.x, self.offset.y, self.offset.z])
+            .friction(self.friction)
+            .restitution(self.restitution)
+            .density(self.density)
+            .collision_groups(interaction_groups(layers))
+            .user_data(u128::from(entity))
+            .build()
+    }
+}
+
+/// Simulates every entity with a `RigidBody` or `Collider`, keeping rapier's copies of them in
+/// step with the `World`
+pub struct PhysicsWorld {
+    pub gravity: Vector3<f32>,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    /// Rigid body of each entity with a `RigidBody` component
+    entity_bodies: HashMap<UUID, RigidBodyHandle>,
+    /// Colliders of entities without a `RigidBody`, which are fixed in place
+    entity_colliders: HashMap<UUID, ColliderHandle>,
+    /// Body of each bone of entities with a `Ragdoll`, whose own colliders are disabled meanwhile
+    ragdolls: HashMap<UUID, Vec<RigidBodyHandle>>,
+    integration_parameters: IntegrationParameters,
+    pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+    query_pipeline: QueryPipeline,
+}
+
+impl Default for PhysicsWorld {
+    fn default() -> Self {
+        Self {
+            gravity: Vector3::new(0.0, -9.81, 0.0),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            entity_bodies: HashMap::new(),
+            entity_colliders: HashMap::new(),
+            ragdolls: HashMap::new(),
+            integration_parameters: IntegrationParameters::default(),
+            pipeline: PhysicsPipeline::new(),
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            impulse_joints: ImpulseJointSet::new(),
+            multibody_joints: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            query_pipeline: QueryPipeline::new(),
+        }
+    }
+}
+
+impl PhysicsWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds bodies and colliders for new entities and removes those of despawned ones, moves
+    /// kinematic bodies to their transforms, advances the simulation and then writes the
+    /// transforms of dynamic bodies and the bones of ragdolls back
+    pub fn step(&mut self, world: &mut World, deltatime: f32) {
+        self.remove_missing(world);
+        self.add_new(world);
+
+        for (entity, body, transform) in world.query2::<RigidBody, Transform>() {
+            if body.kind != BodyKind::Kinematic {
+                continue;
+            }
+
+            if let Some(&handle) = self.entity_bodies.get(&entity) {
+                self.bodies[handle].set_next_kinematic_position(isometry(transform));
+            }
+        }
+
+        for (entity, ragdoll) in world.query_mut::<Ragdoll>() {
+            let Some(handles) = self.ragdolls.get(&entity) else {
+                continue;
+            };
+
+            for (bone, velocity) in ragdoll.impulses.drain(..) {
+                if let Some(body) = handles.get(bone).map(|&handle| &mut self.bodies[handle]) {
+                    let impulse = velocity * body.mass();
+                    body.apply_impulse(vector![impulse.x, impulse.y, impulse.z], true);
+                }
+            }
+        }
+
+        self.integration_parameters.dt = deltatime;
+        self.pipeline.step(
+            &vector![self.gravity.x, self.gravity.y, self.gravity.z],
+            &self.integration_parameters,
+            &mut self.islands,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            &mut self.ccd_solver,
+            Some(&mut self.query_pipeline),
+            &(),
+            &(),
+        );
+
+        for (entity, &handle) in self.entity_bodies.iter() {
+            let body = &self.bodies[handle];
+            if !body.is_dynamic() {
+                continue;
+            }
+
+            if let Some(transform) = world.get_mut::<Transform>(*entity) {
+                let translation = body.translation();
+                let rotation = body.rotation();
+
+                transform.translation = Vector3::new(translation.x, translation.y, translation.z);
+                transform.rotation =
+                    Quaternion::new(rotation.w, rotation.i, rotation.j, rotation.k);
+            }
+        }
+
+        for (entity, ragdoll) in world.query_mut::<Ragdoll>() {
+            let Some(handles) = self.ragdolls.get(&entity) else {
+                continue;
+            };
+
+            // Bodies start at their joint unrotated, so how far they've moved is where they are
+            let origins = ragdoll
+                .bones()
+                .iter()
+                .map(|bone| bone.position.to_vec())
+                .collect_vec();
+            for ((offset, origin), &handle) in ragdoll.offsets.iter_mut().zip(origins).zip(handles)
+            {
+                let position = self.bodies[handle].position();
+                let translation = position.translation.vector;
+                let rotation = position.rotation;
+
+                *offset = Matrix4::from_translation(Vector3::new(
+                    translation.x,
+                    translation.y,
+                    translation.z,
+                )) * Matrix4::from(Quaternion::new(
+                    rotation.w, rotation.i, rotation.j, rotation.k,
+                )) * Matrix4::from_translation(-origin);
+            }
+        }
+    }
+
+    /// rapier's body for an entity, e.g. to apply impulses to it
+    pub fn body_mut(&mut self, entity: UUID) -> Option<&mut rapier3d::dynamics::RigidBody> {
+        let handle = *self.entity_bodies.get(&entity)?;
+        self.bodies.get_mut(handle)
+    }
+
+    /// Moves an entity's body straight to `transform` and stops it, e.g. when it's placed by hand
+    pub fn teleport(&mut self, entity: UUID, transform: &Transform) {
+        let Some(body) = self.body_mut(entity) else {
+            return;
+        };
+
+        body.set_position(isometry(transform), true);
+        body.set_linvel(vector![0.0, 0.0, 0.0], true);
+        body.set_angvel(vector![0.0, 0.0, 0.0], true);
+    }
+
+    /// Changes what an entity's colliders collide with, as they only read its `CollisionLayers`
+    /// when they're created
+    pub fn set_layers(&mut self, entity: UUID, layers: CollisionLayers) {
+        for handle in self.entity_collider_handles(entity) {
+            self.colliders[handle].set_collision_groups(interaction_groups(layers));
+        }
+    }
+
+    /// Where a ray in `layers` first hits a collider, out of those of entities `filter` returns
+    /// true for
+    pub fn cast_ray(
+        &self,
+        origin: Point3<f32>,
+        direction: Vector3<f32>,
+        max_distance: f32,
+        layers: CollisionLayers,
+        filter: impl Fn(UUID) -> bool,
+    ) -> Option<RaycastHit> {
+        let ray = Ray::new(
+            point![origin.x, origin.y, origin.z],
+            vector![direction.x, direction.y, direction.z],
+        );
+
+        let (handle, intersection) = self.query_pipeline.cast_ray_and_get_normal(
+            &self.bodies,
+            &self.colliders,
+            &ray,
+            max_distance,
+            true,
+            QueryFilter::default()
+                .groups(interaction_groups(layers))
+                .predicate(&|_, collider| filter(UUID::from(collider.user_data))),
+        )?;
+
+        let normal = intersection.normal;
+
+        Some(RaycastHit {
+            entity: UUID::from(self.colliders[handle].user_data),
+            distance: intersection.toi,
+            normal: Vector3::new(normal.x, normal.y, normal.z),
+        })
+    }
+
+    /// Removes every body and collider, for when the world they belong to is cleared
+    pub fn clear(&mut self) {
+        *self = Self {
+            gravity: self.gravity,
+            ..Self::default()
+        };
+    }
+
+    /// Colliders made from an entity's `Collider`, not counting those of its ragdoll
+    fn entity_collider_handles(&self, entity: UUID) -> Vec<ColliderHandle> {
+        match self.entity_bodies.get(&entity) {
+            Some(&body) => self.bodies[body].colliders().to_vec(),
+            None => self
+                .entity_colliders
+                .get(&entity)
+                .copied()
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Removes an entity's body, collider and ragdoll straight away rather than at the next step,
+    /// for when it's despawned
+    pub fn remove_entity(&mut self, entity: UUID) {
+        self.remove_ragdoll(entity);
+        self.remove_body(entity);
+        self.remove_collider(entity);
+    }
+
+    fn remove_missing(&mut self, world: &World) {
+        let removed_ragdolls = self
+            .ragdolls
+            .keys()
+            .copied()
+            .filter(|&entity| !world.has::<Ragdoll>(entity))
+            .collect_vec();
+
+        for entity in removed_ragdolls {
+            self.remove_ragdoll(entity);
+        }
+
+        let removed_bodies = self
+            .entity_bodies
+            .keys()
+            .copied()
+            .filter(|&entity| !world.has::<RigidBody>(entity))
+            .collect_vec();
+
+        for entity in removed_bodies {
+            self.remove_body(entity);
+        }
+
+        let removed_colliders = self
+            .entity_colliders
+            .keys()
+            .copied()
+            .filter(|&entity| !world.has::<Collider>(entity) || world.has::<RigidBody>(entity))
+            .collect_vec();
+
+        for entity in removed_colliders {
+            self.remove_collider(entity);
+        }
+    }
+
+    /// Puts the entity's own colliders back once its ragdoll is gone
+    fn remove_ragdoll(&mut self, entity: UUID) {
+        let Some(handles) = self.ragdolls.remove(&entity) else {
+            return;
+        };
+
+        for handle in handles {
+            self.bodies.remove(
+                handle,
+                &mut self.islands,
+                &mut self.colliders,
+                &mut self.impulse_joints,
+                &mut self.multibody_joints,
+                true,
+            );
+        }
+
+        for handle in self.entity_collider_handles(entity) {
+            self.colliders[handle].set_enabled(true);
+        }
+    }
+
+    fn remove_body(&mut self, entity: UUID) {
+        let Some(handle) = self.entity_bodies.remove(&entity) else {
+            return;
+        };
+
+        self.bodies.remove(
+            handle,
+            &mut self.islands,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            true,
+        );
+    }
+
+    fn remove_collider(&mut self, entity: UUID) {
+        let Some(handle) = self.entity_colliders.remove(&entity) else {
+            return;
+        };
+
+        self.colliders
+            .remove(handle, &mut self.islands, &mut self.bodies, false);
+    }
+
+    fn add_new(&mut self, world: &World) {
+        for (entity, body, transform) in world.query2::<RigidBody, Transform>() {
+            if self.entity_bodies.contains_key(&entity) {
+                continue;
+            }
+
+            let builder = match body.kind {
+                BodyKind::Dynamic => RigidBodyBuilder::dynamic(),
+                BodyKind::Fixed => RigidBodyBuilder::fixed(),
+                BodyKind::Kinematic => RigidBodyBuilder::kinematic_position_based(),
+            };
+
+            let velocity = body.linear_velocity;
+            let handle = self.bodies.insert(
+                builder
+                    .position(isometry(transform))
+                    .linvel(vector![velocity.x, velocity.y, velocity.z])
+                    .linear_damping(body.linear_damping)
+                    .angular_damping(body.angular_damping)
+                    .ccd_enabled(body.ccd)
+                    .user_data(u128::from(entity))
+                    .build(),
+            );
+
+            if let Some(collider) = world.get::<Collider>(entity) {
+                self.colliders.insert_with_parent(
+                    collider.build(entity, CollisionLayers::of(world, entity)),
+                    handle,
+                    &mut self.bodies,
+                );
+            }
+
+            self.entity_bodies.insert(entity, handle);
+        }
+
+        for (entity, collider, transform) in world.query2::<Collider, Transform>() {
+            if world.has::<RigidBody>(entity) || self.entity_colliders.contains_key(&entity) {
+                continue;
+            }
+
+            let mut fixed = collider.build(entity, CollisionLayers::of(world, entity));
+            fixed.set_position(isometry(transform) * *fixed.position());
+
+            self.entity_colliders
+                .insert(entity, self.colliders.insert(fixed));
+        }
+
+        for (entity, ragdoll) in world.query::<Ragdoll>() {
+            if !self.ragdolls.contains_key(&entity) {
+                let layers = CollisionLayers::of(world, entity).ragdoll();
+                let handles = self.add_ragdoll(entity, ragdoll, layers);
+                self.ragdolls.insert(entity, handles);
+            }
+        }
+    }
+
+    /// A body per bone, each jointed to its parent's at its joint. The entity's own colliders are
+    /// disabled so the ragdoll doesn't land on the body it came from.
+    fn add_ragdoll(
+        &mut self,
+        entity: UUID,
+        ragdoll: &Ragdoll,
+        layers: CollisionLayers,
+    ) -> Vec<RigidBodyHandle> {
+        let velocity = ragdoll.velocity;
+
+        let handles = ragdoll
+            .bones()
+            .iter()
+            .enumerate()
+            .map(|(index, bone)| {
+                let position = bone.position;
+                let handle = self.bodies.insert(
+                    RigidBodyBuilder::dynamic()
+                        .translation(vector![position.x, position.y, position.z])
+                        .linvel(vector![velocity.x, velocity.y, velocity.z])
+                        .linear_damping(RAGDOLL_DAMPING)
+                        .angular_damping(RAGDOLL_DAMPING)
+                        .user_data(u128::from(entity))
+                        .build(),
+                );
+
+                // Capsules are pulled in by their radius, so their caps end at the joints
+                let radius = bone.radius;
+                let builder = match ragdoll.end(index) {
+                    Some(end) => {
+                        let offset = end - position;
+                        let inset = offset.normalize() * radius;
+                        let (start, end) = (inset, offset - inset);
+
+                        ColliderBuilder::capsule_from_endpoints(
+                            point![start.x, start.y, start.z],
+                            point![end.x, end.y, end.z],
+                            radius,
+                        )
+                    }
+                    None => ColliderBuilder::ball(radius),
+                };
+                self.colliders.insert_with_parent(
+                    builder
+                        .collision_groups(interaction_groups(layers))
+                        .user_data(u128::from(entity))
+                        .build(),
+                    handle,
+                    &mut self.bodies,
+                );
+
+                handle
+            })
+            .collect_vec();
+
+        for (index, bone) in ragdoll.bones().iter().enumerate() {
+            let Some(parent) = bone.parent else {
+                continue;
+            };
+
+            let anchor = bone.position - ragdoll.bones()[parent].position;
+            let joint = SphericalJointBuilder::new()
+                .local_anchor1(point![anchor.x, anchor.y, anchor.z])
+                .local_anchor2(point![0.0, 0.0, 0.0])
+                .limits(JointAxis::AngX, [-RAGDOLL_JOINT_LIMIT, RAGDOLL_JOINT_LIMIT])
+                .limits(JointAxis::AngY, [-RAGDOLL_JOINT_LIMIT, RAGDOLL_JOINT_LIMIT])
+                .limits(JointAxis::AngZ, [-RAGDOLL_JOINT_LIMIT, RAGDOLL_JOINT_LIMIT])
+                .contacts_enabled(false);
+            self.impulse_joints
+                .insert(handles[parent], handles[index], joint, true);
+        }
+
+        for handle in self.entity_collider_handles(entity) {
+            self.colliders[handle].set_enabled(false);
+        }
+
+        handles
+    }
+}
+
+/// A transform without its scale, which colliders have built in instead
+fn isometry(transform: &Transform) -> Isometry3<f32> {
+    let translation = transform.translation;
+    let rotation = transform.rotation;
+
+    Isometry3::from_parts(
+        Translation3::new(translation.x, translation.y, translation.z),
+        UnitQuaternion::from_quaternion(rapier3d::na::Quaternion::new(
+            rotation.s,
+            rotation.v.x,
+            rotation.v.y,
+            rotation.v.z,
+        )),
+    )
+}
+
+fn interaction_groups(layers: CollisionLayers) -> InteractionGroups {
+    InteractionGroups::new(
+        Group::from_bits_truncate(layers.memberships),
+        Group::from_bits_truncate(layers.filter),
+    )
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use crate::entity::World;
+use crate::uuid::UUID;
+
+/// Most entities a pool keeps parked, past which those released are despawned as usual
+const MAX_PARKED: usize = 256;
+
+/// Component putting its entity in a pool, so `Simulation::recycle` parks it for reuse rather
+/// than despawning it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pooled {
+    pub pool: &'static str,
+}
+
+/// Component counting down the seconds until `Simulation::update_lifetimes` recycles its entity,
+/// for short-lived things like projectiles
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Lifetime {
+    pub remaining: f32,
+}
+
+impl Lifetime {
+    pub fn new(seconds: f32) -> Self {
+        Self { remaining: seconds }
+    }
+}
+
+/// Entities parked for reuse by pool, so things spawned all the time don't keep allocating new
+/// entities and components. Parked entities are kept with their components in a world of their
+/// own, where no system sees them.
+#[derive(Default)]
+pub struct EntityPools {
+    parked: World,
+    /// Parked entities of each pool, most recently parked last
+    free: HashMap<&'static str, Vec<UUID>>,
+}
+
+impl EntityPools {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An entity from `pool`, either a parked one moved back into `world` or a new one. Parked
+    /// entities keep the components they had, so every component it needs should be inserted
+    /// again.
+    pub fn acquire(&mut self, world: &mut World, pool: &'static str) -> UUID {
+        if let Some(entity) = self.free.get_mut(pool).and_then(Vec::pop) {
+            self.parked.move_entity(entity, world);
+            return entity;
+        }
+
+        let entity = world.spawn();
+        world.insert(entity, Pooled { pool });
+
+        entity
+    }
+
+    /// Parks an entity in its pool, returning false and leaving it in `world` if it isn't in one
+    /// or its pool is full
+    pub fn release(&mut self, world: &mut World, entity: UUID) -> bool {
+        let Some(Pooled { pool }) = world.get::<Pooled>(entity).copied() else {
+            return false;
+        };
+
+        let free = self.free.entry(pool).or_default();
+        if free.len() >= MAX_PARKED {
+            return false;
+        }
+
+        world.move_entity(entity, &mut self.parked);
+        free.push(entity);
+
+        true
+    }
+
+    /// Entities parked in `pool`
+    pub fn parked(&self, pool: &str) -> usize {
+        self.free.get(pool).map_or(0, Vec::len)
+    }
+
+    /// Despawns every parked entity, e.g. when the scene is changed
+    pub fn clear(&mut self) {
+        self.parked.clear();
+        self.free.clear();
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+
+use cgmath::{
+    Deg, EuclideanSpace, InnerSpace, Matrix3, Matrix4, Point3, Quaternion, SquareMatrix,
+    Transform as _, Vector3, Vector4,
+};
+use color_eyre::Result;
+use glium::framebuffer::{DepthRenderBuffer, SimpleFrameBuffer};
+use glium::glutin::surface::WindowSurface;
+use glium::index::PrimitiveType;
+use glium::texture::{DepthFormat, RawImage2d};
+use glium::{
+    implement_vertex, uniform, Depth, DepthTest, Display, DrawParameters, IndexBuffer, Program,
+    Surface, Texture2d, VertexBuffer,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+use crate::context::OpenGLContext;
+use crate::model::{PreviousTransform, Transform};
+use crate::scene::Scene;
+use crate::uuid::UUID;
+use crate::{context, maths};
+
+/// One side of a portal pair, a rectangle that is looked into from the side `normal` points to
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Portal {
+    pub center: Point3<f32>,
+    pub normal: Vector3<f32>,
+    pub up: Vector3<f32>,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Portal {
+    /// Maps portal space, where the portal lies on the XY plane facing +Z, to world space
+    pub fn matrix(&self) -> Matrix4<f32> {
+        let forward = self.normal.normalize();
+        let right = self.up.cross(forward).normalize();
+        let up = forward.cross(right);
+
+        Matrix4::from_cols(
+            right.extend(0.0),
+            up.extend(0.0),
+            forward.extend(0.0),
+            self.center.to_homogeneous(),
+        )
+    }
+
+    /// Whether something moving from `from` to `to` passes into the front of the portal
+    pub fn crossed(&self, from: Point3<f32>, to: Point3<f32>) -> bool {
+        let inverse = self.matrix().invert().unwrap();
+
+        let from = inverse.transform_point(from);
+        let to = inverse.transform_point(to);
+
+        if from.z < 0.0 || to.z >= 0.0 {
+            return false;
+        }
+
+        let hit = from + (to - from) * (from.z / (from.z - to.z));
+
+        hit.x.abs() <= self.width / 2.0 && hit.y.abs() <= self.height / 2.0
+    }
+
+    /// World space plane, as `(normal, distance)`, that the portal lies on
+    fn plane(&self) -> Vector4<f32> {
+        let normal = self.normal.normalize();
+        normal.extend(-normal.dot(self.center.to_vec()))
+    }
+
+    fn corners(&self) -> [Point3<f32>; 4] {
+        let matrix = self.matrix();
+        let (x, y) = (self.width / 2.0, self.height / 2.0);
+
+        [(-x, -y), (x, -y), (x, y), (-x, y)]
+            .map(|(x, y)| matrix.transform_point(Point3::new(x, y, 0.0)))
+    }
+}
+
+/// Two portals that lead into each other
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PortalPair {
+    pub a: Portal,
+    pub b: Portal,
+}
+
+impl PortalPair {
+    /// Takes anything that entered the front of `from` to the same place leaving the front of `to`
+    pub fn transform(from: &Portal, to: &Portal) -> Matrix4<f32> {
+        to.matrix() * Matrix4::from_angle_y(Deg(180.0)) * from.matrix().invert().unwrap()
+    }
+
+    /// The transform to apply to something that travelled from `from` to `to`, if it went through
+    /// either portal
+    pub fn crossing(&self, from: Point3<f32>, to: Point3<f32>) -> Option<Matrix4<f32>> {
+        if self.a.crossed(from, to) {
+            Some(Self::transform(&self.a, &self.b))
+        } else if self.b.crossed(from, to) {
+            Some(Self::transform(&self.b, &self.a))
+        } else {
+            None
+        }
+    }
+}
+
+/// Teleports the camera and any entity with a `Transform` that moves through one of a scene's
+/// portals
+#[derive(Default)]
+pub struct PortalTeleporter {
+    previous_camera_position: Option<Point3<f32>>,
+    previous_translations: HashMap<UUID, Vector3<f32>>,
+}
+
+impl PortalTeleporter {
+    pub fn update(&mut self, scene: &mut Scene) {
+        if let Some(previous_position) = self.previous_camera_position {
+            if let Some(transform) = Self::find_crossing(
+                &scene.simulation.portals,
+                previous_position,
+                scene.camera.position,
+            ) {
+                let position = transform.transform_point(scene.camera.position);
+                let forward_direction = transform
+                    .transform_vector(scene.camera.forward_direction)
+                    .normalize();
+
+                scene.camera.look_at(position, forward_direction);
+            }
+        }
+
+        let portals = &scene.simulation.portals;
+        let mut teleported = vec![];
+
+        for (entity, transform) in scene.simulation.world.query_mut::<Transform>() {
+            let Some(previous_translation) = self.previous_translations.get(&entity) else {
+                continue;
+            };
+
+            let Some(portal_transform) = Self::find_crossing(
+                portals,
+                Point3::from_vec(*previous_translation),
+                Point3::from_vec(transform.translation),
+            ) else {
+                continue;
+            };
+
+            let rotation = Quaternion::from(Matrix3::from_cols(
+                portal_transform.x.truncate(),
+                portal_transform.y.truncate(),
+                portal_transform.z.truncate(),
+            ));
+
+            transform.translation = portal_transform
+                .transform_point(Point3::from_vec(transform.translation))
+                .to_vec();
+            transform.rotation = rotation * transform.rotation;
+
+            teleported.push((entity, transform.clone()));
+        }
+
+        // Don't interpolate across a portal
+        for (entity, transform) in teleported {
+            if let Some(previous) = scene.simulation.world.get_mut::<PreviousTransform>(entity) {
+                previous.0 = transform;
+            }
+        }
+
+        self.previous_camera_position = Some(scene.camera.position);
+        self.previous_translations = scene
+            .simulation
+            .world
+            .query::<Transform>()
+            .map(|(entity, transform)| (entity, transform.translation))
+            .collect();
+    }
+
+    fn find_crossing(
+        portals: &[PortalPair],
+        from: Point3<f32>,
+        to: Point3<f32>,
+    ) -> Option<Matrix4<f32>> {
+        portals.iter().find_map(|pair| pair.crossing(from, to))
+    }
+}
+
+#[derive(Copy, Clone)]
+struct PortalVertex {
+    position: [f32; 3],
+}
+implement_vertex!(PortalVertex, position);
+
+struct RenderTarget {
+    color: Texture2d,
+    depth: DepthRenderBuffer,
+}
+
+/// Draws the view through each portal by rendering the scene from a virtual camera behind its
+/// partner. Portals visible through a portal are drawn recursively up to `recursion_depth` times.
+pub struct PortalRenderer {
+    pub recursion_depth: usize,
+    program: Program,
+    /// Shown in portals past the recursion depth
+    placeholder: Texture2d,
+    // Two targets are swapped between each level of recursion
+    targets: Vec<RenderTarget>,
+    target_size: (u32, u32),
+}
+
+impl PortalRenderer {
+    pub fn new(display: &Display<WindowSurface>) -> Result<Self> {
+        let program = context::new_program(
+            "assets/shaders/portal/portal.vert",
+            "assets/shaders/portal/portal.frag",
+            None,
+            display,
+        )?;
+
+        let placeholder = Texture2d::new(
+            display,
+            RawImage2d::from_raw_rgba(vec![40_u8, 0, 60, 255], (1, 1)),
+        )?;
+
+        Ok(Self {
+            recursion_depth: 3,
+            program,
+            placeholder,
+            targets: vec![],
+            target_size: (0, 0),
+        })
+    }
+
+    pub fn render<S: Surface>(
+        &mut self,
+        scene: &mut Scene,
+        context: &OpenGLContext,
+        target: &mut S,
+    ) -> Result<()> {
+        if scene.simulation.portals.is_empty() || self.recursion_depth == 0 {
+            return Ok(());
+        }
+        let display = &context.display;
+
+        self.resize_targets(display, target.get_dimensions())?;
+
+        let camera = scene.camera.clone();
+
+        for pair in scene.simulation.portals.clone() {
+            for (from, to) in [(&pair.a, &pair.b), (&pair.b, &pair.a)] {
+                let view = self.render_view(scene, context, &camera, from, to)?;
+                self.draw_portal(display, target, &camera, from, &self.targets[view].color)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Renders what can be seen through `from` into one of the targets, returning its index
+    fn render_view(
+        &self,
+        scene: &mut Scene,
+        context: &OpenGLContext,
+        camera: &Camera,
+        from: &Portal,
+        to: &Portal,
+    ) -> Result<usize> {
+        let display = &context.display;
+        let transform = PortalPair::transform(from, to);
+        let mut previous: Option<usize> = None;
+
+        // Render the deepest view first so that each level can show the one beyond it
+        for level in (1..=self.recursion_depth).rev() {
+            let index = level % 2;
+
+            let virtual_camera = Self::virtual_camera(camera, transform, level, to);
+
+            let render_target = &self.targets[index];
+            let mut framebuffer = SimpleFrameBuffer::with_depth_buffer(
+                display,
+                &render_target.color,
+                &render_target.depth,
+            )?;
+
+            scene.render_with_camera(context, &mut framebuffer, &virtual_camera);
+
+            let portal_texture =
+                previous.map_or(&self.placeholder, |previous| &self.targets[previous].color);
+            self.draw_portal(
+                display,
+                &mut framebuffer,
+                &virtual_camera,
+                from,
+                portal_texture,
+            )?;
+
+            previous = Some(index);
+        }
+
+        Ok(previous.unwrap())
+    }
+
+    /// The camera looking through `level` portals in a row, with its near plane moved onto the
+    /// exit portal so nothing behind the exit is drawn
+    fn virtual_camera(
+        camera: &Camera,
+        transform: Matrix4<f32>,
+        level: usize,
+        exit: &Portal,
+    ) -> Camera {
+        let mut exit_transform = Matrix4::identity();
+        for _ in 1..level {
+            exit_transform = transform * exit_transform;
+        }
+        let camera_transform = transform * exit_transform;
+
+        let mut virtual_camera = camera.clone();
+        virtual_camera.position = camera_transform.transform_point(camera.position);
+        virtual_camera.forward_direction = camera_transform
+            .transform_vector(camera.forward_direction)
+            .normalize();
+        virtual_camera.view = camera.view * camera_transform.invert().unwrap();
+
+        let exit_plane = maths::transform_plane(exit_transform, exit.plane());
+        let view_plane = maths::transform_plane(virtual_camera.view, exit_plane);
+
+        virtual_camera.view_projection =
+            maths::oblique_projection(camera.projection, view_plane) * virtual_camera.view;
+
+        virtual_camera
+    }
+
+    fn draw_portal<S: Surface>(
+        &self,
+        display: &Display<WindowSurface>,
+        target: &mut S,
+        camera: &Camera,
+        portal: &Portal,
+        portal_texture: &Texture2d,
+    ) -> Result<()> {
+        let vertices = portal.corners().map(|corner| PortalVertex {
+            position: <[f32; 3]>::from(corner),
+        });
+
+        let vertex_buffer = VertexBuffer::new(display, &vertices)?;
+        let index_buffer = IndexBuffer::new(
+            display,
+            PrimitiveType::TrianglesList,
+            &[0_u16, 1, 2, 0, 2, 3],
+        )?;
+
+        let (width, height) = target.get_dimensions();
+
+        let uniforms = uniform! {
+            vp: maths::raw_matrix(camera.view_projection),
+            screen_size: [width as f32, height as f32],
+            portal_texture: portal_texture,
+        };
+
+        target.draw(
+            &vertex_buffer,
+            &index_buffer,
+            &self.program,
+            &uniforms,
+            &DrawParameters {
+                depth: Depth {
+                    test: DepthTest::IfLess,
+                    write: true,
+                    ..Depth::default()
+                },
+                ..DrawParameters::default()
+            },
+        )?;
+
+        Ok(())
+    }
+
+    fn resize_targets(&mut self, display: &Display<WindowSurface>, size: (u32, u32)) -> Result<()> {
+        if self.target_size == size && !self.targets.is_empty() {
+            return Ok(());
+        }
+
+        self.targets = (0..2)
+            .map(|_| {
+                Ok(RenderTarget {
+                    color: Texture2d::empty(display, size.0, size.1)?,
+                    depth: DepthRenderBuffer::new(display, DepthFormat::I24, size.0, size.1)?,
+                })
+            })
+            .collect::<Result<Vec<RenderTarget>>>()?;
+        self.target_size = size;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+use color_eyre::Result;
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::glutin::surface::WindowSurface;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::texture::{MipmapsOption, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction};
+use glium::{implement_vertex, uniform, Display, Program, Surface, Texture2d, VertexBuffer};
+use serde::{Deserialize, Serialize};
+
+use crate::assets::{Assets, Handle};
+use crate::settings::GraphicsSettings;
+
+/// Brightness past which the scene starts to glow
+const BLOOM_THRESHOLD: f32 = 1.0;
+const BLOOM_INTENSITY: f32 = 0.3;
+
+/// Horizontal and vertical blurs applied to the bright parts, each widening the glow
+const BLUR_PASSES: usize = 2;
+
+/// How colours brighter than the screen can show are brought into range
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tonemapping {
+    /// Clamps, losing detail in anything too bright
+    Off,
+    Reinhard,
+    /// A filmic curve, with more contrast than Reinhard
+    #[default]
+    Aces,
+}
+
+impl Tonemapping {
+    pub const ALL: [Tonemapping; 3] = [Tonemapping::Off, Tonemapping::Reinhard, Tonemapping::Aces];
+
+    /// How the composite shader picks the curve
+    fn shader_index(self) -> i32 {
+        match self {
+            Tonemapping::Off => 0,
+            Tonemapping::Reinhard => 1,
+            Tonemapping::Aces => 2,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct ScreenVertex {
+    position: [f32; 2],
+}
+implement_vertex!(ScreenVertex, position);
+
+/// Off-screen textures, recreated whenever the scene's size changes
+struct PostTargets {
+    /// Size of the scene they were created for
+    size: (u32, u32),
+    /// Half resolution, blurred back and forth between each other
+    bloom: [Texture2d; 2],
+    /// Tonemapped scene waiting to be smoothed by FXAA
+    ldr: Texture2d,
+}
+
+impl PostTargets {
+    fn new(display: &Display<WindowSurface>, (width, height): (u32, u32)) -> Result<Self> {
+        let color_texture = |width, height| {
+            Texture2d::empty_with_format(
+                display,
+                UncompressedFloatFormat::F16F16F16F16,
+                MipmapsOption::NoMipmap,
+                width,
+                height,
+            )
+        };
+        let (bloom_width, bloom_height) = ((width / 2).max(1), (height / 2).max(1));
+
+        Ok(Self {
+            size: (width, height),
+            bloom: [
+                color_texture(bloom_width, bloom_height)?,
+                color_texture(bloom_width, bloom_height)?,
+            ],
+            ldr: color_texture(width, height)?,
+        })
+    }
+}
+
+/// Full-screen passes applied to the scene once it's drawn: bloom, tonemapping and FXAA. The
+/// scene is drawn into an HDR texture, which keeps colours past 1, then `render` draws the result
+/// onto the target.
+pub struct PostProcessor {
+    bright_program: Handle<Program>,
+    blur_program: Handle<Program>,
+    composite_program: Handle<Program>,
+    fxaa_program: Handle<Program>,
+    screen_quad: VertexBuffer<ScreenVertex>,
+    targets: Option<PostTargets>,
+}
+
+impl PostProcessor {
+    pub fn new(assets: &mut Assets, display: &Display<WindowSurface>) -> Result<Self> {
+        let mut load =
+            |fragment| assets.load_program("assets/shaders/post/screen.vert", fragment, display);
+
+        let bright_program = load("assets/shaders/post/bright.frag")?;
+        let blur_program = load("assets/shaders/post/blur.frag")?;
+        let composite_program = load("assets/shaders/post/composite.frag")?;
+        let fxaa_program = load("assets/shaders/post/fxaa.frag")?;
+
+        let screen_quad = VertexBuffer::new(
+            display,
+            &[[-1.0, -1.0], [1.0, -1.0], [-1.0, 1.0], [1.0, 1.0]]
+                .map(|position| ScreenVertex { position }),
+        )?;
+
+        Ok(Self {
+            bright_program,
+            blur_program,
+            composite_program,
+            fxaa_program,
+            screen_quad,
+            targets: None,
+        })
+    }
+
+    /// Applies the passes turned on in `settings` to the scene drawn into `hdr` and draws the
+    /// result onto the target, recreating the off-screen textures first if `hdr` has been resized.
+    /// It isn't multisampled, so FXAA is what smooths edges. Returns the number of draw calls made.
+    pub fn render<S: Surface>(
+        &mut self,
+        display: &Display<WindowSurface>,
+        target: &mut S,
+        hdr: &Texture2d,
+        assets: &Assets,
+        settings: &GraphicsSettings,
+    ) -> Result<usize> {
+        let size = hdr.dimensions();
+        if self.targets.as_ref().map(|targets| targets.size) != Some(size) {
+            self.targets = Some(PostTargets::new(display, size)?);
+        }
+        let targets = self.targets.as_ref().unwrap();
+
+        let mut draw_calls = 0;
+        let indices = NoIndices(PrimitiveType::TriangleStrip);
+
+        if settings.bloom {
+            let mut bright = SimpleFrameBuffer::new(display, &targets.bloom[0])?;
+            bright.draw(
+                &self.screen_quad,
+                indices,
+                assets.program(self.bright_program),
+                &uniform! {
+                    hdr_texture: linear(hdr),
+                    threshold: BLOOM_THRESHOLD,
+                },
+                &Default::default(),
+            )?;
+            draw_calls += 1;
+
+            let (width, height) = targets.bloom[0].dimensions();
+            for _ in 0..BLUR_PASSES {
+                for (source, destination, direction) in [
+                    (0, 1, [1.0 / width as f32, 0.0]),
+                    (1, 0, [0.0, 1.0 / height as f32]),
+                ] {
+                    let mut framebuffer =
+                        SimpleFrameBuffer::new(display, &targets.bloom[destination])?;
+                    framebuffer.draw(
+                        &self.screen_quad,
+                        indices,
+                        assets.program(self.blur_program),
+                        &uniform! {
+                            source: linear(&targets.bloom[source]),
+                            direction: direction,
+                        },
+                        &Default::default(),
+                    )?;
+                    draw_calls += 1;
+                }
+            }
+        }
+
+        let bloom_intensity = if settings.bloom { BLOOM_INTENSITY } else { 0.0 };
+        let composite_uniforms = uniform! {
+            hdr_texture: linear(hdr),
+            bloom_texture: linear(&targets.bloom[0]),
+            bloom_intensity: bloom_intensity,
+            tonemapping: settings.tonemapping.shader_index(),
+        };
+        let composite_program = assets.program(self.composite_program);
+
+        if !settings.fxaa {
+            target.draw(
+                &self.screen_quad,
+                indices,
+                composite_program,
+                &composite_uniforms,
+                &Default::default(),
+            )?;
+            return Ok(draw_calls + 1);
+        }
+
+        let mut ldr = SimpleFrameBuffer::new(display, &targets.ldr)?;
+        ldr.draw(
+            &self.screen_quad,
+            indices,
+            composite_program,
+            &composite_uniforms,
+            &Default::default(),
+        )?;
+
+        let (width, height) = size;
+        target.draw(
+            &self.screen_quad,
+            indices,
+            assets.program(self.fxaa_program),
+            &uniform! {
+                source: linear(&targets.ldr),
+                texel_size: [1.0 / width as f32, 1.0 / height as f32],
+            },
+            &Default::default(),
+        )?;
+
+        Ok(draw_calls + 2)
+    }
+}
+
+/// Bilinear sampling clamped to the edges, as the textures have no mipmaps
+fn linear(texture: &Texture2d) -> Sampler<'_, Texture2d> {
+    texture
+        .sampled()
+        .magnify_filter(MagnifySamplerFilter::Linear)
+        .minify_filter(MinifySamplerFilter::Linear)
+        .wrap_function(SamplerWrapFunction::Clamp)
+}
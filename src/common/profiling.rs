@@ -0,0 +1,283 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::{c_void, CStr, CString};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use color_eyre::Result;
+use gl::types::{GLint, GLuint, GLuint64};
+use log::warn;
+use serde::Serialize;
+
+/// Frames of GPU queries kept waiting for results before the oldest is waited on
+const MAX_FRAMES_IN_FLIGHT: usize = 4;
+
+thread_local! {
+    static PROFILER: RefCell<Profiler> = RefCell::new(Profiler::default());
+}
+
+/// How long a scope took during a frame
+#[derive(Clone, Debug)]
+pub struct ScopeTiming {
+    pub name: &'static str,
+    /// Number of scopes it was inside
+    pub depth: usize,
+    /// Since the frame started
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+/// Times the rest of the enclosing block on the CPU, recording it when dropped
+#[must_use = "the scope is timed until it's dropped"]
+pub struct CpuScope {
+    name: &'static str,
+    depth: usize,
+    start: Instant,
+}
+
+impl Drop for CpuScope {
+    fn drop(&mut self) {
+        let end = Instant::now();
+
+        PROFILER.with(|profiler| {
+            let mut profiler = profiler.borrow_mut();
+            let frame_start = *profiler.frame_start.get_or_insert(self.start);
+
+            profiler.depth -= 1;
+            profiler.current.push(ScopeTiming {
+                name: self.name,
+                depth: self.depth,
+                start: self.start.saturating_duration_since(frame_start),
+                duration: end - self.start,
+            });
+        });
+    }
+}
+
+/// Starts timing a scope, e.g. `let _scope = profiling::scope("Physics");`
+pub fn scope(name: &'static str) -> CpuScope {
+    let start = Instant::now();
+
+    let depth = PROFILER.with(|profiler| {
+        let mut profiler = profiler.borrow_mut();
+        profiler.frame_start.get_or_insert(start);
+        profiler.depth += 1;
+        profiler.depth - 1
+    });
+
+    CpuScope { name, depth, start }
+}
+
+/// Finishes the frame's CPU timings, adding them and the GPU's to the trace if one is being
+/// recorded
+pub fn end_frame(gpu_timings: &[ScopeTiming]) {
+    PROFILER.with(|profiler| {
+        let mut profiler = profiler.borrow_mut();
+        let frame_start = profiler.frame_start.take();
+
+        let mut timings = std::mem::take(&mut profiler.current);
+        timings.sort_by_key(|timing| timing.start);
+
+        if let (Some(trace), Some(frame_start)) = (profiler.trace.as_mut(), frame_start) {
+            trace.add(frame_start, &timings, CPU_THREAD);
+            trace.add(frame_start, gpu_timings, GPU_THREAD);
+        }
+
+        profiler.last_frame = timings;
+    });
+}
+
+/// CPU timings of the last finished frame, in the order their scopes started
+pub fn last_frame() -> Vec<ScopeTiming> {
+    PROFILER.with(|profiler| profiler.borrow().last_frame.clone())
+}
+
+/// Starts collecting every frame's timings for `save_trace`
+pub fn start_trace() {
+    PROFILER.with(|profiler| {
+        profiler.borrow_mut().trace = Some(Trace {
+            start: Instant::now(),
+            events: vec![],
+        })
+    });
+}
+
+pub fn is_tracing() -> bool {
+    PROFILER.with(|profiler| profiler.borrow().trace.is_some())
+}
+
+/// Stops tracing and writes what was collected in the Chrome trace format, which can be opened
+/// in chrome://tracing or Perfetto
+pub fn save_trace(path: &Path) -> Result<()> {
+    let Some(trace) = PROFILER.with(|profiler| profiler.borrow_mut().trace.take()) else {
+        return Ok(());
+    };
+
+    std::fs::write(
+        path,
+        serde_json::to_string(&TraceFile {
+            trace_events: trace.events,
+        })?,
+    )?;
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct Profiler {
+    frame_start: Option<Instant>,
+    depth: usize,
+    current: Vec<ScopeTiming>,
+    last_frame: Vec<ScopeTiming>,
+    trace: Option<Trace>,
+}
+
+/// Rows of the trace the timings are shown on
+const CPU_THREAD: u32 = 0;
+const GPU_THREAD: u32 = 1;
+
+struct Trace {
+    start: Instant,
+    events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    fn add(&mut self, frame_start: Instant, timings: &[ScopeTiming], thread: u32) {
+        let frame_offset = frame_start.saturating_duration_since(self.start);
+
+        self.events.extend(timings.iter().map(|timing| TraceEvent {
+            name: timing.name,
+            phase: "X",
+            timestamp: (frame_offset + timing.start).as_secs_f64() * 1e6,
+            duration: timing.duration.as_secs_f64() * 1e6,
+            process: 0,
+            thread,
+        }));
+    }
+}
+
+#[derive(Serialize)]
+struct TraceFile {
+    #[serde(rename = "traceEvents")]
+    trace_events: Vec<TraceEvent>,
+}
+
+/// A complete event, with times in microseconds
+#[derive(Serialize)]
+struct TraceEvent {
+    name: &'static str,
+    #[serde(rename = "ph")]
+    phase: &'static str,
+    #[serde(rename = "ts")]
+    timestamp: f64,
+    #[serde(rename = "dur")]
+    duration: f64,
+    #[serde(rename = "pid")]
+    process: u32,
+    #[serde(rename = "tid")]
+    thread: u32,
+}
+
+/// Loads the OpenGL functions `GpuTimer` calls directly, as glium doesn't wrap timer queries
+/// around arbitrary passes. Called once the context is current.
+pub fn load_gl(get_proc_address: impl Fn(&CStr) -> *const c_void) {
+    gl::load_with(|symbol| get_proc_address(&CString::new(symbol).unwrap()));
+}
+
+/// Times render passes on the GPU with timer queries. Results arrive a few frames late, so
+/// `timings` is always for an earlier frame. Passes begun inside another pass are counted as part
+/// of it, as timer queries can't be nested.
+#[derive(Default)]
+pub struct GpuTimer {
+    pub enabled: bool,
+    /// GPU time of each pass of the latest frame with results, laid end to end
+    pub timings: Vec<ScopeTiming>,
+    depth: usize,
+    current: Vec<(&'static str, GLuint)>,
+    in_flight: VecDeque<Vec<(&'static str, GLuint)>>,
+    free: Vec<GLuint>,
+}
+
+impl GpuTimer {
+    pub fn new() -> Self {
+        let supported = gl::GenQueries::is_loaded();
+        if !supported {
+            warn!("Timer queries aren't available, so GPU times won't be shown");
+        }
+
+        Self {
+            enabled: supported,
+            ..Self::default()
+        }
+    }
+
+    /// Starts timing a pass, which must be ended with `end` before the frame is finished
+    pub fn begin(&mut self, name: &'static str) {
+        self.depth += 1;
+        if !self.enabled || self.depth > 1 {
+            return;
+        }
+
+        let query = self.free.pop().unwrap_or_else(|| {
+            let mut query = 0;
+            unsafe { gl::GenQueries(1, &mut query) };
+            query
+        });
+
+        unsafe { gl::BeginQuery(gl::TIME_ELAPSED, query) };
+        self.current.push((name, query));
+    }
+
+    pub fn end(&mut self) {
+        self.depth -= 1;
+        if !self.enabled || self.depth > 0 || self.current.is_empty() {
+            return;
+        }
+
+        unsafe { gl::EndQuery(gl::TIME_ELAPSED) };
+    }
+
+    /// Queues this frame's queries and collects the results of earlier frames that are ready
+    pub fn end_frame(&mut self) {
+        if !self.current.is_empty() {
+            self.in_flight.push_back(std::mem::take(&mut self.current));
+        }
+
+        while let Some(frame) = self.in_flight.front() {
+            let ready = self.in_flight.len() > MAX_FRAMES_IN_FLIGHT
+                || frame.iter().all(|&(_, query)| {
+                    let mut available: GLint = 0;
+                    unsafe {
+                        gl::GetQueryObjectiv(query, gl::QUERY_RESULT_AVAILABLE, &mut available)
+                    };
+                    available != 0
+                });
+            if !ready {
+                break;
+            }
+
+            let frame = self.in_flight.pop_front().unwrap();
+            let mut start = Duration::ZERO;
+
+            self.timings = frame
+                .iter()
+                .map(|&(name, query)| {
+                    let mut nanoseconds: GLuint64 = 0;
+                    unsafe { gl::GetQueryObjectui64v(query, gl::QUERY_RESULT, &mut nanoseconds) };
+
+                    let duration = Duration::from_nanos(nanoseconds);
+                    start += duration;
+
+                    ScopeTiming {
+                        name,
+                        depth: 0,
+                        start: start - duration,
+                        duration,
+                    }
+                })
+                .collect();
+
+            self.free.extend(frame.into_iter().map(|(_, query)| query));
+        }
+    }
+}
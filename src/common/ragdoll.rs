@@ -0,0 +1,94 @@
+use cgmath::{EuclideanSpace, Matrix4, MetricSpace, Point3, SquareMatrix, Transform as _, Vector3};
+use itertools::Itertools;
+
+/// A joint of the skeleton a ragdoll is built from, in world space at the moment of death
+#[derive(Clone, Debug)]
+pub struct RagdollBone {
+    pub name: String,
+    pub position: Point3<f32>,
+    pub parent: Option<usize>,
+    /// Thickness of the limb running from this joint towards its children, used for collisions
+    pub radius: f32,
+}
+
+/// Component handing a skeleton over to physics. `PhysicsWorld` gives every bone a dynamic body
+/// with a capsule running from its joint towards its children, or a ball at the end of a chain,
+/// held to its parent by a ball joint that can only bend so far.
+pub struct Ragdoll {
+    bones: Vec<RagdollBone>,
+    /// Where each bone's capsule ends, `None` for a ball
+    ends: Vec<Option<Point3<f32>>>,
+    /// Velocity every body starts with, so the ragdoll carries on with the character's momentum
+    pub velocity: Vector3<f32>,
+    /// How far each bone's body has moved since the ragdoll was built, written after each physics
+    /// step
+    pub(crate) offsets: Vec<Matrix4<f32>>,
+    /// Changes in velocity waiting to be applied to bones in the next physics step
+    pub(crate) impulses: Vec<(usize, Vector3<f32>)>,
+}
+
+impl Ragdoll {
+    /// Builds a ragdoll from a posed skeleton. Parents must come before their children.
+    pub fn new(bones: Vec<RagdollBone>, velocity: Vector3<f32>) -> Self {
+        let ends = (0..bones.len())
+            .map(|index| {
+                let children = bones
+                    .iter()
+                    .filter(|bone| bone.parent == Some(index))
+                    .map(|bone| bone.position.to_vec())
+                    .collect_vec();
+                if children.is_empty() {
+                    return None;
+                }
+
+                let count = children.len() as f32;
+                let end = Point3::from_vec(children.into_iter().sum::<Vector3<f32>>() / count);
+                // Too short for a capsule of its thickness
+                (end.distance(bones[index].position) > 2.0 * bones[index].radius).then_some(end)
+            })
+            .collect();
+
+        Self {
+            offsets: vec![Matrix4::identity(); bones.len()],
+            impulses: vec![],
+            bones,
+            ends,
+            velocity,
+        }
+    }
+
+    pub fn bones(&self) -> &[RagdollBone] {
+        &self.bones
+    }
+
+    /// Where a bone's capsule ends when the ragdoll was built, `None` if it's a ball
+    pub fn end(&self, bone: usize) -> Option<Point3<f32>> {
+        self.ends[bone]
+    }
+
+    /// Knocks a bone, e.g. where a bullet hit, changing its velocity by `velocity`
+    pub fn apply_impulse(&mut self, bone: usize, velocity: Vector3<f32>) {
+        self.impulses.push((bone, velocity));
+    }
+
+    pub fn joint_positions(&self) -> Vec<Point3<f32>> {
+        self.bones
+            .iter()
+            .zip(self.offsets.iter())
+            .map(|(bone, offset)| offset.transform_point(bone.position))
+            .collect()
+    }
+
+    /// The bone whose joint is nearest to `point`
+    pub fn nearest_bone(&self, point: Point3<f32>) -> Option<usize> {
+        self.joint_positions()
+            .into_iter()
+            .position_min_by(|a, b| a.distance2(point).total_cmp(&b.distance2(point)))
+    }
+
+    /// Transform of each bone in world space relative to the pose the ragdoll was built in, for
+    /// skinning
+    pub fn bone_matrices(&self) -> &[Matrix4<f32>] {
+        &self.offsets
+    }
+}
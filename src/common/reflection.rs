@@ -0,0 +1,500 @@
+use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_2;
+
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix4, MetricSpace, Point3, Rotation, Transform as _, Vector3,
+    Vector4, Zero,
+};
+use color_eyre::Result;
+use glium::framebuffer::{DepthRenderBuffer, SimpleFrameBuffer};
+use glium::glutin::surface::WindowSurface;
+use glium::texture::{
+    CubeLayer, Cubemap, DepthFormat, MipmapsOption, RawImage2d, UncompressedFloatFormat,
+};
+use glium::uniforms::{
+    MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction, UniformBuffer,
+};
+use glium::{implement_uniform_block, Display, Surface, Texture2d};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+use crate::entity::World;
+use crate::maths;
+use crate::maths::Aabb;
+use crate::model::Transform;
+use crate::skybox;
+use crate::skybox::LAYERS;
+use crate::uuid::UUID;
+
+/// The most probes the PBR shader reflects at once, matching `MAX_REFLECTION_PROBES` in the shader
+pub const MAX_REFLECTION_PROBES: usize = 4;
+
+/// When a probe captures its surroundings
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProbeUpdate {
+    /// Once when placed or moved, and again when baked, for surroundings that stay put
+    #[default]
+    Baked,
+    /// A face every frame, so things moving around show up at the cost of drawing the scene again
+    Realtime,
+}
+
+/// Component capturing the scene around its entity's position into a cubemap, reflected by PBR
+/// materials inside its box. Reflections are projected onto the box, so one fitted to a room
+/// lines up with its walls wherever they're seen from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReflectionProbe {
+    /// Half the size of the box on each axis, centered on the entity and never rotated
+    pub extents: Vector3<f32>,
+    pub update: ProbeUpdate,
+}
+
+impl ReflectionProbe {
+    pub fn new(extents: Vector3<f32>) -> Self {
+        Self {
+            extents,
+            update: ProbeUpdate::default(),
+        }
+    }
+
+    /// World space box of a probe at `translation`
+    pub fn bounds(&self, translation: Vector3<f32>) -> Aabb {
+        Aabb::from_center(Point3::from_vec(translation), self.extents)
+    }
+}
+
+/// Component mirroring the scene in the plane through its entity's position, facing along its up
+/// direction, for PBR surfaces lying on the plane such as water or mirrors. Only the nearest the
+/// camera is in front of is drawn each frame.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlanarReflection {
+    /// Pixels drawn per pixel on screen, from 0.1 to 1, lower being cheaper and blurrier
+    pub resolution_scale: f32,
+}
+
+impl Default for PlanarReflection {
+    fn default() -> Self {
+        Self {
+            resolution_scale: 0.5,
+        }
+    }
+}
+
+impl PlanarReflection {
+    /// World space plane, as `(normal, distance)`, of a planar reflection with `transform`
+    pub fn plane(transform: &Transform) -> Vector4<f32> {
+        let normal = transform
+            .rotation
+            .rotate_vector(Vector3::unit_y())
+            .normalize();
+        normal.extend(-normal.dot(transform.translation))
+    }
+}
+
+/// The nearest planar reflection the camera is in front of, along with its plane
+pub(crate) fn nearest_planar_reflection(
+    world: &World,
+    camera: &Camera,
+) -> Option<(Vector4<f32>, PlanarReflection)> {
+    world
+        .query2::<PlanarReflection, Transform>()
+        .map(|(_, reflection, transform)| {
+            let plane = PlanarReflection::plane(transform);
+            (
+                plane.dot(camera.position.to_homogeneous()),
+                plane,
+                *reflection,
+            )
+        })
+        .filter(|(distance, ..)| *distance > 0.0)
+        .min_by(|(a, ..), (b, ..)| a.total_cmp(b))
+        .map(|(_, plane, reflection)| (plane, reflection))
+}
+
+/// `camera` mirrored in a plane given as `(normal, distance)`, seeing what's reflected in it.
+/// Its near plane is moved onto the plane, so nothing behind it shows up in the reflection.
+pub(crate) fn mirrored_camera(camera: &Camera, plane: Vector4<f32>) -> Camera {
+    let reflection = reflection_matrix(plane);
+
+    let mut mirrored = camera.clone();
+    mirrored.position = reflection.transform_point(camera.position);
+    mirrored.target = reflection.transform_point(camera.target);
+    mirrored.forward_direction = reflection.transform_vector(camera.forward_direction);
+    mirrored.up_direction = reflection.transform_vector(camera.up_direction);
+    mirrored.view = camera.view * reflection;
+
+    let view_plane = maths::transform_plane(mirrored.view, plane);
+    mirrored.view_projection =
+        maths::oblique_projection(camera.projection, view_plane) * mirrored.view;
+
+    mirrored
+}
+
+/// Mirrors points and directions in a plane given as `(normal, distance)` with a unit normal
+fn reflection_matrix(plane: Vector4<f32>) -> Matrix4<f32> {
+    let [a, b, c, d]: [f32; 4] = plane.into();
+
+    Matrix4::from_cols(
+        Vector4::new(1.0 - 2.0 * a * a, -2.0 * a * b, -2.0 * a * c, 0.0),
+        Vector4::new(-2.0 * a * b, 1.0 - 2.0 * b * b, -2.0 * b * c, 0.0),
+        Vector4::new(-2.0 * a * c, -2.0 * b * c, 1.0 - 2.0 * c * c, 0.0),
+        Vector4::new(-2.0 * a * d, -2.0 * b * d, -2.0 * c * d, 1.0),
+    )
+}
+
+/// `camera` moved to `position` and turned to see one face of a cubemap, with the field of view
+/// and aspect ratio covering exactly that face
+pub(crate) fn face_camera(camera: &Camera, position: Point3<f32>, layer: CubeLayer) -> Camera {
+    // Up is flipped for the sides, as cubemap faces are laid out top row first
+    let (forward, up) = match layer {
+        CubeLayer::PositiveX => (Vector3::unit_x(), -Vector3::unit_y()),
+        CubeLayer::NegativeX => (-Vector3::unit_x(), -Vector3::unit_y()),
+        CubeLayer::PositiveY => (Vector3::unit_y(), Vector3::unit_z()),
+        CubeLayer::NegativeY => (-Vector3::unit_y(), -Vector3::unit_z()),
+        CubeLayer::PositiveZ => (Vector3::unit_z(), -Vector3::unit_y()),
+        CubeLayer::NegativeZ => (-Vector3::unit_z(), -Vector3::unit_y()),
+    };
+
+    let mut face_camera = camera.clone();
+    face_camera.set_fov(FRAC_PI_2);
+    face_camera.set_aspect_ratio(1.0);
+    face_camera.position = position;
+    face_camera.target = position + forward;
+    face_camera.forward_direction = forward;
+    face_camera.up_direction = up;
+    face_camera.view = Matrix4::look_at_rh(position, position + forward, up);
+    face_camera.view_projection = face_camera.projection * face_camera.view;
+
+    face_camera
+}
+
+/// What a probe has captured so far
+pub(crate) struct ProbeCapture {
+    /// Each mipmap level is a blurrier version of the one before it, for rougher reflections
+    cubemap: Cubemap,
+    depth: DepthRenderBuffer,
+    resolution: u32,
+    /// Where the probe was when it was last captured, as a baked probe is captured again once
+    /// moved
+    position: Point3<f32>,
+    /// Face `ProbeUpdate::Realtime` captures next, as an index into `LAYERS`
+    next_face: usize,
+}
+
+impl ProbeCapture {
+    fn new(
+        display: &Display<WindowSurface>,
+        resolution: u32,
+        position: Point3<f32>,
+    ) -> Result<Self> {
+        Ok(Self {
+            cubemap: Cubemap::empty_with_format(
+                display,
+                UncompressedFloatFormat::F16F16F16F16,
+                MipmapsOption::EmptyMipmaps,
+                resolution,
+            )?,
+            depth: DepthRenderBuffer::new(display, DepthFormat::I24, resolution, resolution)?,
+            resolution,
+            position,
+            next_face: 0,
+        })
+    }
+
+    /// Draws into a face at full resolution
+    pub(crate) fn framebuffer<'a>(
+        &'a self,
+        display: &Display<WindowSurface>,
+        layer: CubeLayer,
+    ) -> Result<SimpleFrameBuffer<'a>> {
+        Ok(SimpleFrameBuffer::with_depth_buffer(
+            display,
+            self.cubemap.main_level().image(layer),
+            &self.depth,
+        )?)
+    }
+
+    /// Shrinks a face that's been drawn into each of its mipmap levels, blurring it enough for
+    /// rough reflections the way skyboxes are
+    pub(crate) fn update_mipmaps(
+        &self,
+        display: &Display<WindowSurface>,
+        layer: CubeLayer,
+    ) -> Result<()> {
+        let face = SimpleFrameBuffer::new(display, self.cubemap.main_level().image(layer))?;
+        for level in 1..self.cubemap.get_mipmap_levels() {
+            let framebuffer =
+                SimpleFrameBuffer::new(display, self.cubemap.mipmap(level).unwrap().image(layer))?;
+            face.fill(&framebuffer, MagnifySamplerFilter::Linear);
+        }
+
+        Ok(())
+    }
+
+    fn max_mipmap_level(&self) -> f32 {
+        (self.cubemap.get_mipmap_levels() - 1) as f32
+    }
+}
+
+/// Where the scene was drawn mirrored for the nearest planar reflection
+pub(crate) struct PlanarTarget {
+    color: Texture2d,
+    depth: DepthRenderBuffer,
+}
+
+impl PlanarTarget {
+    fn new(display: &Display<WindowSurface>, (width, height): (u32, u32)) -> Result<Self> {
+        Ok(Self {
+            color: Texture2d::empty_with_format(
+                display,
+                UncompressedFloatFormat::F16F16F16F16,
+                MipmapsOption::NoMipmap,
+                width,
+                height,
+            )?,
+            depth: DepthRenderBuffer::new(display, DepthFormat::I24, width, height)?,
+        })
+    }
+
+    pub(crate) fn framebuffer<'a>(
+        &'a self,
+        display: &Display<WindowSurface>,
+    ) -> Result<SimpleFrameBuffer<'a>> {
+        Ok(SimpleFrameBuffer::with_depth_buffer(
+            display,
+            &self.color,
+            &self.depth,
+        )?)
+    }
+
+    fn size(&self) -> (u32, u32) {
+        self.color.dimensions()
+    }
+}
+
+/// A probe as laid out in the `ReflectionProbes` uniform block
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct GpuProbe {
+    /// W is the highest mipmap level, which the shader picks between by roughness
+    position: [f32; 4],
+    box_min: [f32; 4],
+    box_max: [f32; 4],
+}
+implement_uniform_block!(GpuProbe, position, box_min, box_max);
+
+/// The probes bound for the view being drawn, in the order of their cubemaps
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct ProbesBlock {
+    probes: [GpuProbe; MAX_REFLECTION_PROBES],
+    probe_count: i32,
+}
+implement_uniform_block!(ProbesBlock, probes, probe_count);
+
+/// Keeps what reflection probes have captured and the scene as last drawn mirrored, binding them
+/// for the PBR shader. Captures are taken out while they're being drawn into, so nothing samples
+/// them at the same time.
+pub struct ReflectionRenderer {
+    /// By probe entity
+    captures: HashMap<UUID, ProbeCapture>,
+    /// Probes bound for the view being drawn, in the order of the `ReflectionProbes` block
+    bound: Vec<UUID>,
+    probes_buffer: UniformBuffer<ProbesBlock>,
+    planar: Option<PlanarTarget>,
+    /// Plane `planar` was drawn for, if it was drawn for the view being drawn, as it only lines up
+    /// with the view it was drawn from
+    planar_plane: Option<Vector4<f32>>,
+    /// Size of the view `planar` was drawn for, which it covers whatever its own size
+    planar_screen_size: (u32, u32),
+    /// Bound where there's nothing captured, as every sampler needs a texture
+    placeholder_cubemap: Cubemap,
+    placeholder_texture: Texture2d,
+}
+
+impl ReflectionRenderer {
+    pub fn new(display: &Display<WindowSurface>) -> Result<Self> {
+        Ok(Self {
+            captures: HashMap::new(),
+            bound: vec![],
+            probes_buffer: UniformBuffer::empty_dynamic(display)?,
+            planar: None,
+            planar_plane: None,
+            planar_screen_size: (1, 1),
+            placeholder_cubemap: skybox::black_cubemap(display)?,
+            placeholder_texture: Texture2d::new(
+                display,
+                RawImage2d::from_raw_rgba(vec![0_u8, 0, 0, 255], (1, 1)),
+            )?,
+        })
+    }
+
+    /// Forgets everything captured, so every probe captures its surroundings again
+    pub fn clear(&mut self) {
+        self.captures.clear();
+        self.bound.clear();
+    }
+
+    /// Forgets the captures of probes that are gone
+    pub(crate) fn retain(&mut self, world: &World) {
+        self.captures
+            .retain(|&entity, _| world.has::<ReflectionProbe>(entity));
+    }
+
+    /// Faces a probe at `position` should capture this frame: every face when it's new, changed
+    /// resolution or a baked probe has moved, one at a time while it's updated in real time,
+    /// otherwise none. Moving keeps the capture's textures, which are only made again for a new
+    /// resolution.
+    pub(crate) fn due_layers(
+        &mut self,
+        display: &Display<WindowSurface>,
+        entity: UUID,
+        probe: &ReflectionProbe,
+        position: Point3<f32>,
+        resolution: u32,
+    ) -> Result<Vec<CubeLayer>> {
+        let capture = match self.captures.get_mut(&entity) {
+            Some(capture) if capture.resolution == resolution => capture,
+            _ => {
+                let capture = ProbeCapture::new(display, resolution, position)?;
+                self.captures.insert(entity, capture);
+                return Ok(LAYERS.to_vec());
+            }
+        };
+
+        let moved = capture.position != position;
+        capture.position = position;
+
+        Ok(match probe.update {
+            ProbeUpdate::Baked if moved => LAYERS.to_vec(),
+            ProbeUpdate::Baked => vec![],
+            ProbeUpdate::Realtime => {
+                let layer = LAYERS[capture.next_face];
+                capture.next_face = (capture.next_face + 1) % LAYERS.len();
+                vec![layer]
+            }
+        })
+    }
+
+    /// Takes a probe's capture out to draw into, to be put back with `return_capture`
+    pub(crate) fn take_capture(&mut self, entity: UUID) -> Option<ProbeCapture> {
+        self.captures.remove(&entity)
+    }
+
+    pub(crate) fn return_capture(&mut self, entity: UUID, capture: ProbeCapture) {
+        self.captures.insert(entity, capture);
+    }
+
+    /// Binds the captured probes nearest the camera, or none if `enabled` is false
+    pub(crate) fn bind(&mut self, world: &World, camera: &Camera, enabled: bool) {
+        let mut block = ProbesBlock {
+            probes: [GpuProbe::default(); MAX_REFLECTION_PROBES],
+            probe_count: 0,
+        };
+        self.bound.clear();
+
+        if enabled {
+            let nearest = world
+                .query2::<ReflectionProbe, Transform>()
+                .filter_map(|(entity, probe, transform)| {
+                    let capture = self.captures.get(&entity)?;
+                    let bounds = probe.bounds(transform.translation);
+                    // Zero for every probe the camera is inside
+                    let closest = Point3::new(
+                        camera.position.x.clamp(bounds.min.x, bounds.max.x),
+                        camera.position.y.clamp(bounds.min.y, bounds.max.y),
+                        camera.position.z.clamp(bounds.min.z, bounds.max.z),
+                    );
+                    let distance = closest.distance2(camera.position);
+
+                    Some((distance, entity, bounds, capture))
+                })
+                .sorted_by(|(a, ..), (b, ..)| a.total_cmp(b))
+                .take(MAX_REFLECTION_PROBES);
+
+            for (gpu_probe, (_, entity, bounds, capture)) in block.probes.iter_mut().zip(nearest) {
+                *gpu_probe = GpuProbe {
+                    position: capture
+                        .position
+                        .to_vec()
+                        .extend(capture.max_mipmap_level())
+                        .into(),
+                    box_min: bounds.min.to_vec().extend(0.0).into(),
+                    box_max: bounds.max.to_vec().extend(0.0).into(),
+                };
+                self.bound.push(entity);
+                block.probe_count += 1;
+            }
+        }
+
+        self.probes_buffer.write(&block);
+    }
+
+    pub(crate) fn probes_buffer(&self) -> &UniformBuffer<ProbesBlock> {
+        &self.probes_buffer
+    }
+
+    /// Cubemap of the bound probe at `index`, or a black placeholder past the last
+    pub(crate) fn probe_map(&self, index: usize) -> Sampler<Cubemap> {
+        let cubemap = self
+            .bound
+            .get(index)
+            .and_then(|entity| self.captures.get(entity))
+            .map_or(&self.placeholder_cubemap, |capture| &capture.cubemap);
+
+        skybox::sampled_cubemap(cubemap)
+    }
+
+    /// Takes the planar reflection's target out to draw into, replacing it if it isn't `size`,
+    /// to be put back with `return_planar`
+    pub(crate) fn take_planar(
+        &mut self,
+        display: &Display<WindowSurface>,
+        size: (u32, u32),
+    ) -> Result<PlanarTarget> {
+        match self.planar.take() {
+            Some(planar) if planar.size() == size => Ok(planar),
+            _ => PlanarTarget::new(display, size),
+        }
+    }
+
+    pub(crate) fn return_planar(&mut self, planar: PlanarTarget) {
+        self.planar = Some(planar);
+    }
+
+    /// Shows the planar reflection on surfaces lying on `plane` in the view being drawn, which is
+    /// `screen_size`, or hides it if `None`
+    pub(crate) fn show_planar(&mut self, plane: Option<Vector4<f32>>, screen_size: (u32, u32)) {
+        self.planar_plane = plane.filter(|_| self.planar.is_some());
+        self.planar_screen_size = screen_size;
+    }
+
+    /// Plane the planar reflection is shown on as `(normal, distance)`, if it's shown
+    pub(crate) fn planar_plane(&self) -> Option<Vector4<f32>> {
+        self.planar_plane
+    }
+
+    /// Plane to pass the shader, zero when none is shown
+    pub(crate) fn planar_plane_uniform(&self) -> [f32; 4] {
+        self.planar_plane.unwrap_or_else(Vector4::zero).into()
+    }
+
+    pub(crate) fn planar_screen_size(&self) -> [f32; 2] {
+        let (width, height) = self.planar_screen_size;
+        [width as f32, height as f32]
+    }
+
+    pub(crate) fn planar_texture(&self) -> Sampler<Texture2d> {
+        let texture = match (&self.planar, self.planar_plane) {
+            (Some(planar), Some(_)) => &planar.color,
+            _ => &self.placeholder_texture,
+        };
+
+        texture
+            .sampled()
+            .minify_filter(MinifySamplerFilter::Linear)
+            .magnify_filter(MagnifySamplerFilter::Linear)
+            .wrap_function(SamplerWrapFunction::Clamp)
+    }
+}
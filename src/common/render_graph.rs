@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use glium::framebuffer::{MultiOutputFrameBuffer, SimpleFrameBuffer};
+use glium::glutin::surface::WindowSurface;
+use glium::texture::{DepthFormat, DepthTexture2d, MipmapsOption, UncompressedFloatFormat};
+use glium::{Display, Frame, Surface, Texture2d};
+
+/// Name passes use for the window, which is written to rather than created by the graph
+pub const TARGET: &str = "target";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureKind {
+    Color(UncompressedFloatFormat),
+    Depth,
+}
+
+/// A texture the graph creates for passes to draw into and read from
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextureDesc {
+    pub kind: TextureKind,
+    /// What the window's size is divided by, e.g. 2 for half resolution
+    pub divisor: u32,
+}
+
+impl TextureDesc {
+    pub fn color(format: UncompressedFloatFormat) -> Self {
+        Self {
+            kind: TextureKind::Color(format),
+            divisor: 1,
+        }
+    }
+
+    pub fn depth() -> Self {
+        Self {
+            kind: TextureKind::Depth,
+            divisor: 1,
+        }
+    }
+
+    pub fn divided(self, divisor: u32) -> Self {
+        Self {
+            divisor: divisor.max(1),
+            ..self
+        }
+    }
+
+    fn size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        (
+            (width / self.divisor).max(1),
+            (height / self.divisor).max(1),
+        )
+    }
+}
+
+enum Texture {
+    Color(Texture2d),
+    Depth(DepthTexture2d),
+}
+
+/// What a pass is given to draw with: the window, and the graph's textures it reads and writes
+pub struct PassContext<'a> {
+    pub display: &'a Display<WindowSurface>,
+    pub target: &'a mut Frame,
+    textures: &'a HashMap<String, Texture>,
+    outputs: &'a [String],
+}
+
+impl<'a> PassContext<'a> {
+    pub fn texture(&self, name: &str) -> Result<&'a Texture2d> {
+        match self.textures.get(name) {
+            Some(Texture::Color(texture)) => Ok(texture),
+            _ => Err(eyre!(
+                "No color texture called {:?} in the render graph",
+                name
+            )),
+        }
+    }
+
+    pub fn depth_texture(&self, name: &str) -> Result<&'a DepthTexture2d> {
+        match self.textures.get(name) {
+            Some(Texture::Depth(texture)) => Ok(texture),
+            _ => Err(eyre!(
+                "No depth texture called {:?} in the render graph",
+                name
+            )),
+        }
+    }
+
+    /// Cleared framebuffer drawing into the pass's color output, with its depth output if it has
+    /// one
+    pub fn framebuffer(&self) -> Result<SimpleFrameBuffer<'a>> {
+        let (colors, depth) = self.output_textures();
+        let [(_, color)] = colors.as_slice() else {
+            return Err(eyre!(
+                "Pass needs exactly one color output for a framebuffer"
+            ));
+        };
+
+        let mut framebuffer = match depth {
+            Some(depth) => SimpleFrameBuffer::with_depth_buffer(self.display, *color, depth)?,
+            None => SimpleFrameBuffer::new(self.display, *color)?,
+        };
+        framebuffer.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+
+        Ok(framebuffer)
+    }
+
+    /// Cleared framebuffer writing each shader output into the color output of the same name, for
+    /// passes with several
+    pub fn multi_output_framebuffer(&self) -> Result<MultiOutputFrameBuffer<'a>> {
+        let (colors, depth) = self.output_textures();
+        let depth = depth.ok_or_else(|| eyre!("Pass needs a depth output for a framebuffer"))?;
+
+        let mut framebuffer =
+            MultiOutputFrameBuffer::with_depth_buffer(self.display, colors.iter().copied(), depth)?;
+        framebuffer.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+
+        Ok(framebuffer)
+    }
+
+    fn output_textures(&self) -> (Vec<(&'a str, &'a Texture2d)>, Option<&'a DepthTexture2d>) {
+        let mut colors = vec![];
+        let mut depth = None;
+        let textures = self.textures;
+
+        for output in self.outputs.iter() {
+            match textures.get(output) {
+                Some(Texture::Color(texture)) => colors.push((output.as_str(), texture)),
+                Some(Texture::Depth(texture)) => depth = Some(texture),
+                None => {}
+            }
+        }
+
+        (colors, depth)
+    }
+}
+
+type PassFn<C> = Box<dyn FnMut(&mut PassContext, &mut C) -> Result<()>>;
+
+struct Pass<C> {
+    name: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    enabled: bool,
+    run: PassFn<C>,
+}
+
+/// Named passes drawing a frame, each declaring the textures it reads and writes. The graph
+/// creates those textures at the window's size, recreating them when it's resized, and runs each
+/// pass after every pass writing what it reads. Passes writing the same thing, such as overlays
+/// drawn onto the window, run in the order they were added. `C` is whatever the passes draw, e.g.
+/// the app.
+pub struct RenderGraph<C> {
+    textures: HashMap<String, TextureDesc>,
+    passes: Vec<Pass<C>>,
+    /// Indices of the enabled passes in the order they run, worked out again when they change
+    order: Option<Vec<usize>>,
+    allocated: HashMap<String, Texture>,
+    /// Window size the textures were created for
+    size: (u32, u32),
+}
+
+impl<C> Default for RenderGraph<C> {
+    fn default() -> Self {
+        Self {
+            textures: HashMap::new(),
+            passes: vec![],
+            order: None,
+            allocated: HashMap::new(),
+            size: (0, 0),
+        }
+    }
+}
+
+impl<C> RenderGraph<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_texture(&mut self, name: &str, desc: TextureDesc) {
+        self.textures.insert(name.to_owned(), desc);
+        self.allocated.remove(name);
+    }
+
+    /// Adds a pass reading the textures named in `inputs` and writing those in `outputs`, where
+    /// `TARGET` is the window
+    pub fn add_pass(
+        &mut self,
+        name: &str,
+        inputs: &[&str],
+        outputs: &[&str],
+        run: impl FnMut(&mut PassContext, &mut C) -> Result<()> + 'static,
+    ) {
+        let names = |names: &[&str]| names.iter().map(|&name| name.to_owned()).collect();
+
+        self.passes.push(Pass {
+            name: name.to_owned(),
+            inputs: names(inputs),
+            outputs: names(outputs),
+            enabled: true,
+            run: Box::new(run),
+        });
+        self.order = None;
+    }
+
+    /// Turns a pass on or off, e.g. from graphics settings, without rebuilding the graph
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        for pass in self.passes.iter_mut().filter(|pass| pass.name == name) {
+            if pass.enabled != enabled {
+                pass.enabled = enabled;
+                self.order = None;
+            }
+        }
+    }
+
+    /// Names of the enabled passes in the order they run
+    pub fn order(&mut self) -> Result<Vec<&str>> {
+        let order = self.sorted()?.clone();
+
+        Ok(order
+            .iter()
+            .map(|&pass| self.passes[pass].name.as_str())
+            .collect())
+    }
+
+    /// Runs every enabled pass on `context`, creating or resizing textures to match `target` first
+    pub fn execute(
+        &mut self,
+        display: &Display<WindowSurface>,
+        target: &mut Frame,
+        context: &mut C,
+    ) -> Result<()> {
+        let size = target.get_dimensions();
+        if size != self.size {
+            self.allocated.clear();
+            self.size = size;
+        }
+        self.allocate(display)?;
+
+        let order = self.sorted()?.clone();
+        for index in order {
+            let pass = &mut self.passes[index];
+            let mut pass_context = PassContext {
+                display,
+                target: &mut *target,
+                textures: &self.allocated,
+                outputs: &pass.outputs,
+            };
+
+            (pass.run)(&mut pass_context, context)
+                .map_err(|error| eyre!("{} pass failed: {}", pass.name, error))?;
+        }
+
+        Ok(())
+    }
+
+    fn allocate(&mut self, display: &Display<WindowSurface>) -> Result<()> {
+        for (name, desc) in self.textures.iter() {
+            if self.allocated.contains_key(name) {
+                continue;
+            }
+
+            let (width, height) = desc.size(self.size);
+            let texture = match desc.kind {
+                TextureKind::Color(format) => Texture::Color(Texture2d::empty_with_format(
+                    display,
+                    format,
+                    MipmapsOption::NoMipmap,
+                    width,
+                    height,
+                )?),
+                TextureKind::Depth => Texture::Depth(DepthTexture2d::empty_with_format(
+                    display,
+                    DepthFormat::F32,
+                    MipmapsOption::NoMipmap,
+                    width,
+                    height,
+                )?),
+            };
+            self.allocated.insert(name.clone(), texture);
+        }
+
+        Ok(())
+    }
+
+    /// Orders the enabled passes so each comes after those writing what it reads, and after
+    /// earlier passes writing the same things, keeping the order they were added where free to
+    fn sorted(&mut self) -> Result<&Vec<usize>> {
+        if self.order.is_none() {
+            let enabled = (0..self.passes.len())
+                .filter(|&pass| self.passes[pass].enabled)
+                .collect::<Vec<_>>();
+
+            let writes = |pass: usize, name: &String| self.passes[pass].outputs.contains(name);
+            let depends_on = |pass: usize, other: usize| {
+                let (pass_info, other_info) = (&self.passes[pass], &self.passes[other]);
+
+                pass != other
+                    && (pass_info.inputs.iter().any(|input| writes(other, input))
+                        || (other < pass
+                            && other_info.outputs.iter().any(|output| writes(pass, output))
+                            && !other_info.inputs.iter().any(|input| writes(pass, input))))
+            };
+
+            let mut order = Vec::with_capacity(enabled.len());
+            let mut remaining = enabled.clone();
+            while !remaining.is_empty() {
+                let next = remaining
+                    .iter()
+                    .position(|&pass| {
+                        !remaining
+                            .iter()
+                            .any(|&other| other != pass && depends_on(pass, other))
+                    })
+                    .ok_or_else(|| {
+                        eyre!(
+                            "Render graph passes depend on each other in a cycle: {}",
+                            remaining
+                                .iter()
+                                .map(|&pass| self.passes[pass].name.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    })?;
+                order.push(remaining.remove(next));
+            }
+
+            self.order = Some(order);
+        }
+
+        Ok(self.order.as_ref().unwrap())
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::{HashMap, VecDeque};
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3, VectorSpace};
+
+use crate::camera::Camera;
+use crate::model::Transform;
+use crate::scene::Scene;
+use crate::uuid::UUID;
+
+/// The state of every entity in a scene at a single point in time
+#[derive(Clone)]
+pub struct Snapshot {
+    pub time: f64,
+    pub camera: Camera,
+    pub transforms: HashMap<UUID, Transform>,
+}
+
+impl Snapshot {
+    pub fn capture(time: f64, scene: &Scene) -> Self {
+        Self {
+            time,
+            camera: scene.camera.clone(),
+            transforms: scene
+                .simulation
+                .world
+                .query::<Transform>()
+                .map(|(entity, transform)| (entity, transform.clone()))
+                .collect(),
+        }
+    }
+
+    /// Writes the snapshot back into the scene, skipping the camera and any entities that have
+    /// been despawned since
+    pub fn apply(&self, scene: &mut Scene) {
+        apply_transforms(&self.transforms, scene);
+    }
+
+    /// Blends towards another snapshot, only keeping entities that are in both
+    fn lerp(&self, other: &Snapshot, amount: f32) -> HashMap<UUID, Transform> {
+        self.transforms
+            .iter()
+            .filter_map(|(entity, from)| {
+                let to = other.transforms.get(entity)?;
+                Some((*entity, from.lerp(to, amount)))
+            })
+            .collect()
+    }
+}
+
+/// Keeps a rolling window of the most recent snapshots of a scene
+pub struct ReplayBuffer {
+    snapshots: VecDeque<Snapshot>,
+    duration: f64,
+}
+
+impl ReplayBuffer {
+    /// Creates a buffer holding `duration` seconds of history
+    pub fn new(duration: f64) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            duration,
+        }
+    }
+
+    pub fn record(&mut self, time: f64, scene: &Scene) {
+        self.snapshots.push_back(Snapshot::capture(time, scene));
+
+        while let Some(oldest) = self.snapshots.front() {
+            if time - oldest.time <= self.duration {
+                break;
+            }
+
+            self.snapshots.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.snapshots.back()
+    }
+
+    /// Clones every snapshot recorded within the last `length` seconds, oldest first
+    pub fn rewind(&self, length: f64) -> Vec<Snapshot> {
+        let Some(latest) = self.latest() else {
+            return vec![];
+        };
+
+        self.snapshots
+            .iter()
+            .filter(|snapshot| latest.time - snapshot.time <= length)
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum KillcamState {
+    Playing,
+    Finished,
+}
+
+/// Plays back the moments before a death from the point of view of the killer
+pub struct Killcam {
+    snapshots: Vec<Snapshot>,
+    elapsed: f64,
+    killer: UUID,
+    /// `None` for the local player, who is seen where the camera was
+    victim: Option<UUID>,
+    live_camera: Camera,
+}
+
+impl Killcam {
+    /// How far above the killer's origin the camera is placed
+    const EYE_HEIGHT: f32 = 1.7;
+
+    /// Starts a killcam from the last `length` seconds of the buffer, looking from the killer
+    /// entity at the victim, or at the camera when the victim is `None`
+    pub fn start(
+        replay_buffer: &ReplayBuffer,
+        length: f64,
+        killer: UUID,
+        victim: Option<UUID>,
+        scene: &Scene,
+    ) -> Option<Self> {
+        let snapshots = replay_buffer.rewind(length);
+
+        if snapshots.len() < 2 {
+            return None;
+        }
+
+        Some(Self {
+            snapshots,
+            elapsed: 0.0,
+            killer,
+            victim,
+            live_camera: scene.camera.clone(),
+        })
+    }
+
+    pub fn length(&self) -> f64 {
+        self.snapshots.last().unwrap().time - self.snapshots[0].time
+    }
+
+    /// Advances playback and writes the rewound state into the scene. When playback finishes the
+    /// most recent snapshot and the live camera are restored.
+    pub fn update(&mut self, deltatime: f64, scene: &mut Scene) -> KillcamState {
+        self.elapsed += deltatime;
+
+        if self.elapsed >= self.length() {
+            self.snapshots.last().unwrap().apply(scene);
+            scene.camera = self.live_camera.clone();
+
+            return KillcamState::Finished;
+        }
+
+        let time = self.snapshots[0].time + self.elapsed;
+        let next_index = self
+            .snapshots
+            .iter()
+            .position(|snapshot| snapshot.time > time)
+            .unwrap_or(self.snapshots.len() - 1)
+            .max(1);
+
+        let previous = &self.snapshots[next_index - 1];
+        let next = &self.snapshots[next_index];
+        // Snapshots taken at the same time would divide by zero
+        let span = next.time - previous.time;
+        let amount = if span > 0.0 {
+            ((time - previous.time) / span).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+
+        let transforms = previous.lerp(next, amount as f32);
+
+        apply_transforms(&transforms, scene);
+
+        let victim = match self.victim {
+            Some(victim) => transforms
+                .get(&victim)
+                .map(|transform| transform.translation),
+            None => Some(
+                previous
+                    .camera
+                    .position
+                    .to_vec()
+                    .lerp(next.camera.position.to_vec(), amount as f32),
+            ),
+        };
+        if let (Some(killer), Some(victim)) = (transforms.get(&self.killer), victim) {
+            self.look_from_killer(killer, victim, &mut scene.camera);
+        }
+
+        KillcamState::Playing
+    }
+
+    fn look_from_killer(&self, killer: &Transform, victim: Vector3<f32>, camera: &mut Camera) {
+        let eye = Point3::from_vec(killer.translation + Vector3::unit_y() * Self::EYE_HEIGHT);
+        let target = Point3::from_vec(victim);
+
+        let direction = target - eye;
+
+        if direction.magnitude2() > 0.0 {
+            camera.look_at(eye, direction.normalize());
+        }
+    }
+}
+
+fn apply_transforms(transforms: &HashMap<UUID, Transform>, scene: &mut Scene) {
+    for (entity, transform) in scene.simulation.world.query_mut::<Transform>() {
+        if let Some(recorded) = transforms.get(&entity) {
+            *transform = recorded.clone();
+        }
+    }
+}
@@ -0,0 +1,654 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cgmath::{Point3, Vector3};
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use itertools::Itertools;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::ai::{AiState, Enemy};
+use crate::billboards::Billboard;
+use crate::collision::{CollisionLayers, Trigger};
+use crate::entity::World;
+use crate::gameplay;
+use crate::gameplay::{Dead, Health, HealthBar, Pickup, Respawn};
+use crate::model::{Model, ModelInstance, Transform};
+use crate::physics::{Collider, RigidBody};
+use crate::simulation::Simulation;
+use crate::tween::Tweens;
+use crate::uuid::UUID;
+use crate::weapons::Weapon;
+
+/// Bumped whenever `SaveGame` changes, with a migration from the previous version added
+pub const SAVE_VERSION: u32 = 2;
+
+/// Where quick saves are written and loaded from
+pub const QUICK_SAVE_PATH: &str = "saves/quicksave.sav";
+
+/// Start of every save file, so anything else is rejected before it's decoded
+const MAGIC: [u8; 4] = *b"SGSV";
+
+/// Whoever is playing, who is the camera rather than an entity
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerState {
+    /// Where their eyes are
+    pub position: Point3<f32>,
+    pub forward_direction: Vector3<f32>,
+    pub health: f32,
+    /// Seconds until they respawn while they're dead
+    pub respawn: Option<f32>,
+    /// Weapons don't use ammunition, so which one is held is all there is to keep
+    pub weapon: Weapon,
+}
+
+/// An entity whose state changes while playing, e.g. an enemy or a pickup
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EntityState {
+    pub uuid: UUID,
+    pub transform: Transform,
+    pub health: Option<f32>,
+    /// Seconds until it respawns or goes while it's dead
+    pub dead: Option<f32>,
+    pub ai_state: Option<AiState>,
+    /// `None` in saves from before it was kept, whose entities all have to be in the scene
+    pub components: Option<EntityComponents>,
+}
+
+/// What an entity is made of, so those spawned while playing rather than loaded with the scene,
+/// such as enemies from the console, can be spawned again
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct EntityComponents {
+    /// Path of the model it's drawn with, even while it's dead and hidden
+    pub model: Option<PathBuf>,
+    pub health: Option<Health>,
+    pub enemy: Option<Enemy>,
+    pub respawn: Option<Respawn>,
+    pub pickup: Option<Pickup>,
+    pub trigger: Option<Trigger>,
+    pub layers: Option<CollisionLayers>,
+    /// At rest, as bodies already in the scene are when they're put back
+    pub rigid_body: Option<RigidBody>,
+    pub collider: Option<Collider>,
+    pub tweens: Option<Tweens>,
+    pub billboard: Option<Billboard>,
+    pub health_bar: bool,
+}
+
+impl EntityComponents {
+    fn capture(world: &World, entity: UUID) -> Self {
+        let model = world
+            .get::<ModelInstance>(entity)
+            .or_else(|| world.get::<Dead>(entity).and_then(Dead::model))
+            .map(|instance| instance.model.path.clone());
+
+        Self {
+            model,
+            health: world.get::<Health>(entity).copied(),
+            enemy: world.get::<Enemy>(entity).cloned(),
+            respawn: world.get::<Respawn>(entity).copied(),
+            pickup: world.get::<Pickup>(entity).copied(),
+            trigger: world.get::<Trigger>(entity).cloned(),
+            layers: world.get::<CollisionLayers>(entity).copied(),
+            rigid_body: world.get::<RigidBody>(entity).map(|body| RigidBody {
+                linear_velocity: Vector3::new(0.0, 0.0, 0.0),
+                ..body.clone()
+            }),
+            collider: world.get::<Collider>(entity).cloned(),
+            tweens: world.get::<Tweens>(entity).cloned(),
+            billboard: world.get::<Billboard>(entity).cloned(),
+            health_bar: world.has::<HealthBar>(entity),
+        }
+    }
+
+    /// Spawns the entity again as `uuid`, without its model if `models` can't give it
+    fn spawn(
+        &self,
+        simulation: &mut Simulation,
+        uuid: UUID,
+        transform: Transform,
+        models: &mut impl FnMut(&Path) -> Option<Arc<Model>>,
+    ) {
+        simulation.world.spawn_with_uuid(uuid);
+
+        match self.model.as_deref() {
+            Some(path) => match models(path) {
+                Some(model) => simulation.insert_model(uuid, model, transform),
+                None => {
+                    warn!(
+                        "Saved entity {} is spawned without its model {:?}",
+                        uuid, path
+                    );
+                    simulation.world.insert(uuid, transform);
+                }
+            },
+            None => {
+                simulation.world.insert(uuid, transform);
+            }
+        }
+
+        let world = &mut simulation.world;
+        insert(world, uuid, self.health);
+        insert(world, uuid, self.enemy.clone());
+        insert(world, uuid, self.respawn);
+        insert(world, uuid, self.pickup);
+        insert(world, uuid, self.trigger.clone());
+        insert(world, uuid, self.layers);
+        insert(world, uuid, self.rigid_body.clone());
+        insert(world, uuid, self.collider.clone());
+        insert(world, uuid, self.tweens.clone());
+        insert(world, uuid, self.billboard.clone());
+        if self.health_bar {
+            world.insert(uuid, HealthBar);
+        }
+    }
+}
+
+fn insert<T: 'static>(world: &mut World, entity: UUID, component: Option<T>) {
+    if let Some(component) = component {
+        world.insert(entity, component);
+    }
+}
+
+/// What a trigger had inside it, so walking out of it after loading is noticed but standing in
+/// it isn't seen as going in again
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TriggerState {
+    pub trigger: UUID,
+    /// `None` for the player
+    pub occupants: Vec<Option<UUID>>,
+}
+
+/// Snapshot of what changes while playing a scene, on top of the scene file it was played from.
+/// Entities that aren't in it when it's applied, such as pickups taken since, are despawned.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SaveGame {
+    /// The scene being played, which should be loaded before the save is applied
+    pub scene: Option<PathBuf>,
+    pub simulation_time: f64,
+    pub player: PlayerState,
+    pub entities: Vec<EntityState>,
+    pub triggers: Vec<TriggerState>,
+}
+
+impl SaveGame {
+    /// Snapshots the simulation of the scene at `scene_path` and the player given by whoever
+    /// runs it
+    pub fn capture(
+        simulation: &Simulation,
+        scene_path: Option<PathBuf>,
+        player: PlayerState,
+    ) -> Self {
+        let world = &simulation.world;
+
+        let entities = saved_entities(world)
+            .into_iter()
+            .filter_map(|uuid| {
+                Some(EntityState {
+                    uuid,
+                    transform: world.get::<Transform>(uuid)?.clone(),
+                    health: world.get::<Health>(uuid).map(|health| health.current),
+                    dead: world.get::<Dead>(uuid).map(|dead| dead.remaining),
+                    ai_state: world.get::<Enemy>(uuid).map(|enemy| enemy.state),
+                    components: Some(EntityComponents::capture(world, uuid)),
+                })
+            })
+            .collect();
+
+        let triggers = world
+            .query::<Trigger>()
+            .map(|(trigger, trigger_info)| TriggerState {
+                trigger,
+                occupants: trigger_info.occupants().collect(),
+            })
+            .collect();
+
+        Self {
+            scene: scene_path,
+            simulation_time: simulation.time,
+            player,
+            entities,
+            triggers,
+        }
+    }
+
+    /// Puts the simulation's entities back as they were, returning the player's state for
+    /// whoever runs the scene to take back. Saved entities that aren't in the scene, such as
+    /// those spawned while playing, are spawned again with their models from `models`.
+    pub fn apply(
+        &self,
+        simulation: &mut Simulation,
+        mut models: impl FnMut(&Path) -> Option<Arc<Model>>,
+    ) -> PlayerState {
+        let saved = self
+            .entities
+            .iter()
+            .map(|entity| entity.uuid)
+            .collect::<HashSet<_>>();
+        for entity in saved_entities(&simulation.world) {
+            if !saved.contains(&entity) {
+                simulation.despawn(entity);
+            }
+        }
+
+        for entity in self.entities.iter() {
+            let uuid = entity.uuid;
+            if !simulation.world.contains(uuid) {
+                let Some(components) = entity.components.as_ref() else {
+                    warn!("Saved entity {} is no longer in the scene", uuid);
+                    continue;
+                };
+                components.spawn(simulation, uuid, entity.transform.clone(), &mut models);
+            }
+
+            simulation.set_transform(uuid, entity.transform.clone());
+            match entity.dead {
+                Some(remaining) => gameplay::kill(simulation, uuid, remaining),
+                None => gameplay::revive(simulation, uuid),
+            }
+
+            let world = &mut simulation.world;
+            if let (Some(health), Some(current)) = (world.get_mut::<Health>(uuid), entity.health) {
+                health.current = current;
+            }
+            if let (Some(enemy), Some(state)) = (world.get_mut::<Enemy>(uuid), entity.ai_state) {
+                enemy.state = state;
+            }
+        }
+
+        for trigger in self.triggers.iter() {
+            if let Some(trigger_info) = simulation.world.get_mut::<Trigger>(trigger.trigger) {
+                trigger_info.set_occupants(trigger.occupants.iter().copied());
+            }
+        }
+
+        self.player.clone()
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let Some((magic, rest)) = bytes.split_first_chunk::<4>() else {
+            bail!("Save {:?} is empty", path);
+        };
+        if *magic != MAGIC {
+            bail!("{:?} is not a save", path);
+        }
+
+        let Some((version, body)) = rest.split_first_chunk::<4>() else {
+            bail!("Save {:?} has no version", path);
+        };
+        let version = u32::from_le_bytes(*version);
+
+        if version > SAVE_VERSION {
+            bail!(
+                "Save {:?} is version {} but only up to version {} is supported",
+                path,
+                version,
+                SAVE_VERSION
+            );
+        }
+
+        migrate(body, version)
+    }
+
+    /// Writes the save as the magic, the version and then the save encoded with bincode
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(SAVE_VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(self)?);
+        std::fs::write(path, bytes)?;
+
+        Ok(())
+    }
+}
+
+/// Entities whose state is saved: those that can be hurt, hunt the player or be picked up
+fn saved_entities(world: &World) -> Vec<UUID> {
+    world
+        .query::<Health>()
+        .map(|(entity, _)| entity)
+        .chain(world.query::<Enemy>().map(|(entity, _)| entity))
+        .chain(world.query::<Pickup>().map(|(entity, _)| entity))
+        .unique()
+        .collect()
+}
+
+/// Decodes a save written by an older version of the format, upgrading it one version at a time.
+/// bincode isn't self describing, so each older version needs its own copy of the types it was
+/// written with to decode it before converting it to the next.
+fn migrate(body: &[u8], version: u32) -> Result<SaveGame> {
+    match version {
+        SAVE_VERSION => Ok(bincode::deserialize(body)?),
+        1 => Ok(bincode::deserialize::<v1::SaveGame>(body)?.into()),
+        _ => bail!("No migration from save version {}", version),
+    }
+}
+
+/// Saves from before entities' components were kept
+mod v1 {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    pub struct EntityState {
+        pub uuid: UUID,
+        pub transform: Transform,
+        pub health: Option<f32>,
+        pub dead: Option<f32>,
+        pub ai_state: Option<AiState>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct SaveGame {
+        pub scene: Option<PathBuf>,
+        pub simulation_time: f64,
+        pub player: PlayerState,
+        pub entities: Vec<EntityState>,
+        pub triggers: Vec<TriggerState>,
+    }
+
+    impl From<SaveGame> for super::SaveGame {
+        fn from(save: SaveGame) -> Self {
+            let entities = save
+                .entities
+                .into_iter()
+                .map(|entity| super::EntityState {
+                    uuid: entity.uuid,
+                    transform: entity.transform,
+                    health: entity.health,
+                    dead: entity.dead,
+                    ai_state: entity.ai_state,
+                    components: None,
+                })
+                .collect();
+
+            Self {
+                scene: save.scene,
+                simulation_time: save.simulation_time,
+                player: save.player,
+                entities,
+                triggers: save.triggers,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maths::Aabb;
+    use crate::physics::{BodyKind, ColliderShape};
+    use crate::tween::Tween;
+
+    fn player() -> PlayerState {
+        PlayerState {
+            position: Point3::new(1.0, 2.0, 3.0),
+            forward_direction: Vector3::unit_z(),
+            health: 75.0,
+            respawn: None,
+            weapon: Weapon::rifle(),
+        }
+    }
+
+    fn at(x: f32) -> Transform {
+        Transform {
+            translation: Vector3::new(x, 0.0, 0.0),
+            ..Transform::default()
+        }
+    }
+
+    /// Somewhere of its own in the temporary directory for each test
+    fn save_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("savegame-test-{}-{}.sav", name, std::process::id()))
+    }
+
+    fn write(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = save_path(name);
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    /// Spawns what the editor's console does, but without models
+    fn spawn_runtime_entities(simulation: &mut Simulation) -> (UUID, UUID, UUID) {
+        let enemy = simulation.spawn();
+        simulation.world.insert(enemy, at(1.0));
+        simulation.world.insert(
+            enemy,
+            Enemy {
+                state: AiState::Chase,
+                ..Enemy::new(vec![Point3::new(0.0, 0.0, 0.0), Point3::new(4.0, 0.0, 0.0)])
+            },
+        );
+        simulation.world.insert(
+            enemy,
+            Health {
+                current: 20.0,
+                ..Health::new(50.0)
+            },
+        );
+        simulation.world.insert(
+            enemy,
+            Respawn {
+                delay: 5.0,
+                height: 0.9,
+            },
+        );
+        simulation.world.insert(enemy, Billboard::bar(1.2));
+        simulation.world.insert(enemy, HealthBar);
+        simulation.world.insert(enemy, CollisionLayers::ENEMY);
+
+        let pickup = simulation.spawn();
+        simulation.world.insert(pickup, at(2.0));
+        simulation.world.insert(
+            pickup,
+            Pickup {
+                kind: crate::levelgen::PickupKind::Health,
+                amount: 25.0,
+            },
+        );
+        simulation.world.insert(
+            pickup,
+            Trigger {
+                layers: CollisionLayers::PICKUP,
+                ..Trigger::new(Aabb::new(
+                    Point3::new(-0.5, -0.5, -0.5),
+                    Point3::new(0.5, 0.5, 0.5),
+                ))
+            },
+        );
+        simulation
+            .world
+            .insert(pickup, Tweens(vec![Tween::bob(0.2, 2.0)]));
+
+        let cube = simulation.spawn();
+        simulation.world.insert(cube, at(3.0));
+        simulation.world.insert(cube, Health::new(30.0));
+        simulation.world.insert(
+            cube,
+            RigidBody {
+                linear_velocity: Vector3::new(0.0, 0.0, 5.0),
+                ..RigidBody::new(BodyKind::Dynamic)
+            },
+        );
+        simulation.world.insert(
+            cube,
+            Collider::new(ColliderShape::Cuboid {
+                half_extents: Vector3::new(0.25, 0.25, 0.25),
+            }),
+        );
+
+        (enemy, pickup, cube)
+    }
+
+    #[test]
+    fn entities_spawned_while_playing_come_back_in_a_reloaded_scene() {
+        let mut simulation = Simulation::new();
+        let (enemy, pickup, cube) = spawn_runtime_entities(&mut simulation);
+        simulation.time = 12.5;
+
+        let path = save_path("round-trip");
+        SaveGame::capture(&simulation, None, player())
+            .save(&path)
+            .unwrap();
+        let save = SaveGame::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // As the scene file has none of them
+        let mut reloaded = Simulation::new();
+        let player = save.apply(&mut reloaded, |_| None);
+
+        assert_eq!(save.simulation_time, 12.5);
+        assert_eq!(player.health, 75.0);
+
+        let world = &reloaded.world;
+        assert_eq!(world.get::<Transform>(enemy).unwrap().translation.x, 1.0);
+        assert_eq!(world.get::<Health>(enemy).unwrap().current, 20.0);
+        assert_eq!(world.get::<Health>(enemy).unwrap().max, 50.0);
+        let restored_enemy = world.get::<Enemy>(enemy).unwrap();
+        assert_eq!(restored_enemy.state, AiState::Chase);
+        assert_eq!(restored_enemy.patrol.len(), 2);
+        assert_eq!(world.get::<Respawn>(enemy).unwrap().delay, 5.0);
+        assert_eq!(world.get::<Billboard>(enemy), Some(&Billboard::bar(1.2)));
+        assert!(world.has::<HealthBar>(enemy));
+        assert_eq!(
+            world.get::<CollisionLayers>(enemy),
+            Some(&CollisionLayers::ENEMY)
+        );
+
+        assert_eq!(world.get::<Pickup>(pickup).unwrap().amount, 25.0);
+        assert_eq!(
+            world.get::<Trigger>(pickup).unwrap().layers,
+            CollisionLayers::PICKUP
+        );
+        assert_eq!(world.get::<Tweens>(pickup).unwrap().0.len(), 1);
+
+        assert!(world.has::<Collider>(cube));
+        assert_eq!(
+            world.get::<RigidBody>(cube).unwrap().linear_velocity,
+            Vector3::new(0.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn applying_leaves_dead_entities_dead_and_despawns_those_taken_since() {
+        let mut simulation = Simulation::new();
+        let (enemy, pickup, _) = spawn_runtime_entities(&mut simulation);
+        gameplay::kill(&mut simulation, enemy, 3.0);
+
+        let save = SaveGame::capture(&simulation, None, player());
+
+        let mut reloaded = Simulation::new();
+        let extra = reloaded.spawn();
+        reloaded.world.insert(extra, Health::new(10.0));
+        save.apply(&mut reloaded, |_| None);
+
+        assert_eq!(reloaded.world.get::<Dead>(enemy).unwrap().remaining, 3.0);
+        assert!(reloaded.world.contains(pickup));
+        assert!(!reloaded.world.contains(extra));
+    }
+
+    #[test]
+    fn saves_without_components_skip_entities_missing_from_the_scene() {
+        let mut simulation = Simulation::new();
+        let (enemy, _, _) = spawn_runtime_entities(&mut simulation);
+        let mut save = SaveGame::capture(&simulation, None, player());
+        for entity in save.entities.iter_mut() {
+            entity.components = None;
+        }
+
+        let mut reloaded = Simulation::new();
+        save.apply(&mut reloaded, |_| None);
+
+        assert!(!reloaded.world.contains(enemy));
+    }
+
+    #[test]
+    fn version_1_saves_are_migrated() {
+        let save = v1::SaveGame {
+            scene: Some(PathBuf::from("scenes/test.json")),
+            simulation_time: 4.0,
+            player: player(),
+            entities: vec![v1::EntityState {
+                uuid: UUID::new(),
+                transform: at(1.0),
+                health: Some(10.0),
+                dead: None,
+                ai_state: Some(AiState::Patrol),
+            }],
+            triggers: vec![],
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(bincode::serialize(&save).unwrap());
+
+        let path = write("version-1", &bytes);
+        let migrated = SaveGame::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(migrated.scene, save.scene);
+        assert_eq!(migrated.entities.len(), 1);
+        assert_eq!(migrated.entities[0].health, Some(10.0));
+        assert!(migrated.entities[0].components.is_none());
+    }
+
+    #[test]
+    fn broken_headers_are_rejected() {
+        let mut newer = MAGIC.to_vec();
+        newer.extend((SAVE_VERSION + 1).to_le_bytes());
+        let mut unknown = MAGIC.to_vec();
+        unknown.extend(0u32.to_le_bytes());
+
+        let cases: [(&str, &[u8]); 6] = [
+            ("empty", b""),
+            ("short-magic", b"SG"),
+            ("wrong-magic", b"XXXX\x01\x00\x00\x00"),
+            ("no-version", &MAGIC[..]),
+            ("newer", &newer),
+            ("unknown", &unknown),
+        ];
+        for (name, bytes) in cases {
+            let path = write(name, bytes);
+            let result = SaveGame::load(&path);
+            std::fs::remove_file(&path).unwrap();
+
+            assert!(result.is_err(), "{} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn truncated_bodies_are_rejected() {
+        let mut simulation = Simulation::new();
+        spawn_runtime_entities(&mut simulation);
+
+        let path = save_path("truncated");
+        SaveGame::capture(&simulation, None, player())
+            .save(&path)
+            .unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        let result = SaveGame::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn overflowing_lengths_are_rejected() {
+        // A save whose first length prefix claims far more than the file holds
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(SAVE_VERSION.to_le_bytes());
+        bytes.push(1);
+        bytes.extend(u64::MAX.to_le_bytes());
+
+        let path = write("overflowing", &bytes);
+        let result = SaveGame::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+    }
+}
@@ -3,71 +3,371 @@ use std::fmt::Formatter;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use cgmath::{Matrix, Matrix4, Point3, SquareMatrix, Vector3, Zero};
+use bytemuck::{Pod, Zeroable};
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, SquareMatrix, Vector3, Vector4, Zero,
+};
+use color_eyre::eyre::eyre;
 use color_eyre::Result;
 use glium::glutin::surface::WindowSurface;
 use glium::index::{NoIndices, PrimitiveType};
+use glium::uniforms::{UniformBuffer, Uniforms};
 use glium::{
-    implement_vertex, uniform, Display, DrawParameters, Frame, IndexBuffer, Program, Surface,
-    VertexBuffer,
+    implement_vertex, uniform, Blend, BlendingFunction, Depth, DepthTest, Display, DrawParameters,
+    LinearBlendingFactor, PolygonMode, Program, Surface, VertexBuffer,
 };
 use itertools::Itertools;
+use log::warn;
+use palette::Srgb;
 use rfd::FileDialog;
 use serde::de::{MapAccess, Visitor};
 use serde::ser::{SerializeMap, SerializeStruct, SerializeTuple};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use winit::dpi::PhysicalSize;
 
+use crate::animation::BonesBlock;
+use crate::assets::{Assets, Handle};
+use crate::backend::{DrawScene, GlModel, ModelCache};
+use crate::billboards::BillboardRenderer;
 use crate::camera::Camera;
+use crate::collision::CollisionLayers;
+use crate::console::Console;
+use crate::context::OpenGLContext;
+use crate::debug_draw::DebugDraw;
+use crate::debug_view::DebugView;
+use crate::decals::DecalSystem;
+use crate::deferred::{DeferredRenderer, RenderMode};
+use crate::events::EventBus;
+use crate::gameplay::{Corpse, RagdollBlend};
+use crate::hierarchy;
+use crate::hierarchy::Parent;
+use crate::light::{Light, LightsBlock};
 use crate::line::{Line, LinePoint};
-use crate::model::{Model, ModelInstance, Transform};
-use crate::{context, maths};
+use crate::lod;
+use crate::map::{AssetSource, Brush, Map};
+use crate::material::{BlendMode, MaterialUniforms, ShadingModel};
+use crate::maths;
+use crate::maths::Frustum;
+use crate::model::{Model, ModelInstance, PreviousTransform, Transform};
+use crate::occlusion::OcclusionCuller;
+use crate::particles::ParticleSystem;
+use crate::physics::{Collider, RigidBody};
+use crate::profiling;
+use crate::profiling::GpuTimer;
+use crate::ragdoll::Ragdoll;
+use crate::reflection;
+use crate::reflection::{PlanarReflection, ReflectionProbe, ReflectionRenderer};
+use crate::settings::GraphicsSettings;
+use crate::simulation::Simulation;
+use crate::skybox::{Skybox, SkyboxRenderer, SkyboxSource};
+use crate::terrain::{Terrain, TerrainRenderer};
+use crate::tween::Tweens;
+use crate::uuid::UUID;
+use crate::viewport::{ViewTarget, Viewport};
+
+/// Global lighting shared by everything in a scene
+#[derive(Clone, Debug)]
+pub struct Environment {
+    /// Points towards the sun
+    pub sun_direction: Vector3<f32>,
+    pub sun_color: Srgb,
+    pub sun_intensity: f32,
+    pub ambient_color: Srgb,
+    pub ambient_intensity: f32,
+    pub sky_color: Srgb,
+    pub fog_color: Srgb,
+    pub fog_density: f32,
+    /// How soaked surfaces are from 0 to 1, making them darker and glossier
+    pub wetness: f32,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            sun_direction: Vector3::new(0.3, 1.0, 0.2).normalize(),
+            sun_color: Srgb::new(1.0, 1.0, 1.0),
+            sun_intensity: 1.0,
+            ambient_color: Srgb::new(1.0, 1.0, 1.0),
+            ambient_intensity: 0.1,
+            sky_color: Srgb::new(0.0, 0.0, 0.0),
+            fog_color: Srgb::new(0.0, 0.0, 0.0),
+            fog_density: 0.0,
+            wetness: 0.0,
+        }
+    }
+}
+
+/// How many model instances were drawn and culled, summed over the views players see this frame,
+/// leaving out reflections and portals
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CullingStatistics {
+    pub drawn: usize,
+    pub culled: usize,
+    /// Of those culled, how many were in view but hidden behind something else
+    pub occluded: usize,
+}
 
 pub struct Scene {
     pub camera: Camera,
     pub title: String,
+    pub environment: Environment,
+    /// Drawn instead of the sky color when set
+    pub skybox: Option<Skybox>,
+    /// Rhai scripts run alongside the scene by whoever runs it, saved with it
+    pub scripts: Vec<PathBuf>,
+    /// Ground drawn under everything else, set with `set_terrain`
+    pub terrain: Option<Terrain>,
 
-    pub model_instances: Vec<ModelInstance>,
+    /// Entities, physics and level data, everything that runs without a window
+    pub simulation: Simulation,
     pub lines: Vec<Line>,
+    /// Shapes drawn for one frame from anywhere, e.g. `scene.debug.draw_aabb(&aabb, colors::RED)`
+    pub debug: DebugDraw,
+    /// Events between gameplay systems, such as `Hit`s, updated each fixed step by whoever runs
+    /// the scene
+    pub events: EventBus,
+    /// Muzzle flashes, sparks and anything else with a `ParticleEmitter`, updated by whoever runs
+    /// the scene
+    pub particles: ParticleSystem,
+    /// Bullet holes and scorch marks left by weapons, updated by whoever runs the scene
+    pub decals: DecalSystem,
+
+    /// Draws every instance of a model in one draw call. Turning this off issues a draw call per
+    /// instance, which is only useful for comparison.
+    pub instanced_rendering: bool,
+    pub render_mode: RenderMode,
+    /// Only used by forward rendering
+    pub shading_model: ShadingModel,
+    /// How far rendering is between the previous and current simulation steps, from 0 to 1. Only
+    /// affects entities with a `PreviousTransform`.
+    pub interpolation: f32,
+    /// Skips instances outside the camera's view
+    pub frustum_culling: bool,
+    /// Skips instances in view but hidden behind others, as found by the previous frames from the
+    /// scene's camera
+    pub occlusion_culling: bool,
+    /// Its `visualize` draws the bounds of occluded instances
+    pub occlusion: OcclusionCuller,
+    /// Draws far away instances with their model's simpler meshes
+    pub level_of_detail: bool,
+    /// Draws the scene lit, or showing one thing about it such as its normals
+    pub debug_view: DebugView,
+    /// Reflects what each `ReflectionProbe` captures in PBR materials inside its box
+    pub reflection_probes: bool,
+    /// Draws the scene again mirrored in the nearest `PlanarReflection`, for PBR materials on its
+    /// plane to reflect
+    pub planar_reflections: bool,
+    /// Multiplies the distances levels of detail switch at, so higher keeps more detail
+    pub lod_bias: f32,
+    pub culling_statistics: CullingStatistics,
+    /// Draw calls issued by the last `render`, including those for portal views
+    pub draw_calls: usize,
+    /// Times the scene's render passes on the GPU, along with any others drawn between its
+    /// `begin` and `end`
+    pub gpu_timer: GpuTimer,
+    /// Quality options applied when drawing
+    pub graphics_settings: GraphicsSettings,
+    /// Everything the scene has loaded, handed on to the next scene to reuse
+    pub assets: Assets,
 
-    model_program: Program,
-    lines_program: Program,
+    /// Holds the terrain's collider
+    terrain_entity: Option<UUID>,
+    /// Level of detail each instance was last drawn at, which it only leaves once clearly past a
+    /// switch distance
+    lod_levels: HashMap<UUID, usize>,
+    /// Visible instances of skinned models, drawn one at a time with their own bones
+    skinned_instances: Vec<SkinnedInstance>,
+    /// Order the instance buffers are drawn in, nearest model first so that less is shaded only
+    /// to be hidden
+    draw_order: Vec<(Arc<Model>, usize)>,
+    /// Visible instances of models with blended materials, furthest first, drawn one at a time
+    /// after everything opaque
+    transparent_instances: Vec<TransparentInstance>,
+    /// Made by the first frame drawn with OpenGL, so scenes can be built and drawn without it
+    gl: Option<GlResources>,
+}
 
+/// Programs, renderers and buffers a scene draws with under OpenGL
+struct GlResources {
+    model_program: Handle<Program>,
+    pbr_program: Handle<Program>,
+    debug_view_program: Handle<Program>,
+    lines_program: Handle<Program>,
+    deferred_renderer: DeferredRenderer,
+    skybox_renderer: SkyboxRenderer,
+    billboard_renderer: BillboardRenderer,
+    terrain_renderer: TerrainRenderer,
+    /// Whether `terrain_renderer` has been built for the scene's current terrain
+    terrain_built: bool,
+    reflections: ReflectionRenderer,
+    /// Every light in the world, rewritten each frame
+    lights_buffer: UniformBuffer<LightsBlock>,
+    /// Skinning matrices of whichever skinned instance is being drawn
+    bones_buffer: UniformBuffer<BonesBlock>,
     line_vertex_buffers: Option<Vec<(u8, VertexBuffer<LinePoint>)>>,
-    loaded_models: HashMap<PathBuf, Arc<Model>>,
+    /// Per model and level of detail buffers of instance transforms, kept between frames
+    instance_buffers: HashMap<(Arc<Model>, usize), InstanceBuffer>,
+    skinned_instance_buffer: Option<InstanceBuffer>,
+    transparent_instance_buffer: Option<InstanceBuffer>,
+    /// One per view drawn by `render_views`, resized along with their viewports. `None` where one
+    /// couldn't be made, so that view is skipped.
+    view_targets: Vec<Option<ViewTarget>>,
+    models: ModelCache<GlModel>,
 }
 
-impl Scene {
-    pub fn new(title: &str, camera: Camera, display: &Display<WindowSurface>) -> Result<Self> {
-        let model_program = context::new_program(
+impl GlResources {
+    fn new(assets: &mut Assets, display: &Display<WindowSurface>) -> Result<Self> {
+        let model_program = assets.load_program(
             "assets/shaders/default/default.vert",
             "assets/shaders/default/default.frag",
-            None,
             display,
         )?;
 
-        let lines_program = context::new_program(
+        let pbr_program = assets.load_program(
+            "assets/shaders/default/default.vert",
+            "assets/shaders/pbr/pbr.frag",
+            display,
+        )?;
+
+        let debug_view_program = assets.load_program(
+            "assets/shaders/default/default.vert",
+            "assets/shaders/debug_view/debug_view.frag",
+            display,
+        )?;
+
+        let lines_program = assets.load_program(
             "assets/shaders/line/line.vert",
             "assets/shaders/line/line.frag",
-            None,
             display,
         )?;
 
         Ok(Self {
-            model_instances: vec![],
-            lines: vec![],
-            loaded_models: HashMap::new(),
             model_program,
+            pbr_program,
+            debug_view_program,
             lines_program,
-            title: title.to_owned(),
-            camera,
+            deferred_renderer: DeferredRenderer::new(assets, display)?,
+            skybox_renderer: SkyboxRenderer::new(assets, display)?,
+            billboard_renderer: BillboardRenderer::new(assets, display)?,
+            terrain_renderer: TerrainRenderer::new(assets, display)?,
+            terrain_built: false,
+            reflections: ReflectionRenderer::new(display)?,
+            lights_buffer: UniformBuffer::empty_dynamic(display)?,
+            bones_buffer: UniformBuffer::empty_dynamic(display)?,
             line_vertex_buffers: None,
+            instance_buffers: HashMap::new(),
+            skinned_instance_buffer: None,
+            transparent_instance_buffer: None,
+            view_targets: vec![],
+            models: ModelCache::new(),
         })
     }
+}
+
+/// Uniforms of forward shading, as the type `uniform!` makes can't be named to share it through a
+/// function
+macro_rules! forward_uniforms {
+    ($scene:expr, $camera:expr) => {{
+        let scene = $scene;
+        let gl = scene.gl();
+        let sun_color = scene.environment.sun_color * scene.environment.sun_intensity;
+        let ambient_color = scene.environment.ambient_color * scene.environment.ambient_intensity;
+        let fog_color = scene.environment.fog_color;
+
+        uniform! {
+            vp: maths::raw_matrix($camera.view_projection),
+            camera_position: <[f32; 3]>::from($camera.position),
+            sun_direction: <[f32; 3]>::from(scene.environment.sun_direction),
+            sun_color: [sun_color.red, sun_color.green, sun_color.blue],
+            ambient_color: [ambient_color.red, ambient_color.green, ambient_color.blue],
+            fog_color: [fog_color.red, fog_color.green, fog_color.blue],
+            fog_density: scene.environment.fog_density,
+            wetness: scene.environment.wetness,
+            lighting_only: scene.debug_view == DebugView::Lighting,
+            Lights: &gl.lights_buffer,
+            Bones: &gl.bones_buffer,
+            environment_map: gl.skybox_renderer.environment_map(scene.skybox.as_ref()),
+            environment_intensity: SkyboxRenderer::environment_intensity(scene.skybox.as_ref()),
+            environment_max_mipmap_level:
+                SkyboxRenderer::environment_max_mipmap_level(scene.skybox.as_ref()),
+            ReflectionProbes: gl.reflections.probes_buffer(),
+            reflection_probe_0: gl.reflections.probe_map(0),
+            reflection_probe_1: gl.reflections.probe_map(1),
+            reflection_probe_2: gl.reflections.probe_map(2),
+            reflection_probe_3: gl.reflections.probe_map(3),
+            planar_reflection: gl.reflections.planar_texture(),
+            planar_reflection_active: gl.reflections.planar_plane().is_some(),
+            planar_reflection_plane: gl.reflections.planar_plane_uniform(),
+            planar_reflection_screen_size: gl.reflections.planar_screen_size(),
+        }
+    }};
+}
+
+impl Scene {
+    /// Creates an empty scene, taking the contents of `assets`. Nothing is uploaded to the GPU
+    /// until the scene is first drawn.
+    pub fn new(title: &str, camera: Camera, assets: &mut Assets) -> Self {
+        Self {
+            simulation: Simulation::new(),
+            lines: vec![],
+            debug: DebugDraw::new(),
+            events: EventBus::new(),
+            particles: ParticleSystem::new(),
+            decals: DecalSystem::new(),
+            assets: std::mem::take(assets),
+            terrain_entity: None,
+            title: title.to_owned(),
+            camera,
+            environment: Environment::default(),
+            skybox: None,
+            scripts: vec![],
+            terrain: None,
+            lod_levels: HashMap::new(),
+            gpu_timer: GpuTimer::new(),
+            skinned_instances: vec![],
+            draw_order: vec![],
+            transparent_instances: vec![],
+            instanced_rendering: true,
+            render_mode: RenderMode::default(),
+            shading_model: ShadingModel::default(),
+            interpolation: 1.0,
+            frustum_culling: true,
+            occlusion_culling: false,
+            occlusion: OcclusionCuller::new(),
+            level_of_detail: true,
+            debug_view: DebugView::default(),
+            reflection_probes: true,
+            planar_reflections: true,
+            lod_bias: 1.0,
+            culling_statistics: CullingStatistics::default(),
+            draw_calls: 0,
+            graphics_settings: GraphicsSettings::default(),
+            gl: None,
+        }
+    }
+
+    /// Creates a scene as `new` does and fills it with `fill`, handing the contents of `assets`
+    /// back if that fails rather than dropping them with the scene
+    pub(crate) fn build(
+        title: &str,
+        camera: Camera,
+        assets: &mut Assets,
+        fill: impl FnOnce(&mut Scene) -> Result<()>,
+    ) -> Result<Self> {
+        let mut scene = Self::new(title, camera, assets);
+
+        match fill(&mut scene) {
+            Ok(()) => Ok(scene),
+            Err(error) => {
+                *assets = std::mem::take(&mut scene.assets);
+                Err(error)
+            }
+        }
+    }
 
     pub fn deserialize(
         serialised: &str,
-        display: &Display<WindowSurface>,
+        assets: &mut Assets,
         inner_size: PhysicalSize<u32>,
     ) -> Result<Self> {
         let mut unloaded_scene = serde_json::from_str::<UnloadedScene>(serialised)?;
@@ -76,19 +376,147 @@ impl Scene {
             .camera
             .set_aspect_ratio(inner_size.width as f32 / inner_size.height as f32);
 
-        let mut scene = Scene::new(&unloaded_scene.title, unloaded_scene.camera, display)?;
+        Self::build(
+            &unloaded_scene.title,
+            unloaded_scene.camera,
+            assets,
+            |scene| {
+                for (path, saved_instances) in unloaded_scene.model_paths_to_instances.iter() {
+                    let model = scene.load_model(path)?;
+                    for saved_instance in saved_instances {
+                        let entity = scene.simulation.world.spawn_with_uuid(saved_instance.uuid);
+                        scene.insert_model(entity, model.clone(), saved_instance.transform.clone());
+                    }
+                }
+
+                for saved_light in unloaded_scene.lights {
+                    let entity = scene.simulation.world.spawn_with_uuid(saved_light.uuid);
+                    scene.simulation.world.insert(entity, saved_light.light);
+                    scene.simulation.world.insert(entity, saved_light.transform);
+                }
+
+                for saved_probe in unloaded_scene.reflection_probes {
+                    let entity = scene.simulation.world.spawn_with_uuid(saved_probe.uuid);
+                    scene.simulation.world.insert(entity, saved_probe.probe);
+                    scene.simulation.world.insert(entity, saved_probe.transform);
+                }
+
+                for saved_reflection in unloaded_scene.planar_reflections {
+                    let entity = scene
+                        .simulation
+                        .world
+                        .spawn_with_uuid(saved_reflection.uuid);
+                    scene
+                        .simulation
+                        .world
+                        .insert(entity, saved_reflection.reflection);
+                    scene
+                        .simulation
+                        .world
+                        .insert(entity, saved_reflection.transform);
+                }
+
+                if let Some(saved_skybox) = unloaded_scene.skybox {
+                    let mut skybox = Skybox::load(saved_skybox.source)?;
+                    skybox.intensity = saved_skybox.intensity;
+                    skybox.image_based_lighting = saved_skybox.image_based_lighting;
+                    scene.skybox = Some(skybox);
+                }
+
+                // Only those on entities that were saved are kept
+                for saved_tweens in unloaded_scene.tweens {
+                    if scene.simulation.world.contains(saved_tweens.uuid) {
+                        scene
+                            .simulation
+                            .world
+                            .insert(saved_tweens.uuid, saved_tweens.tweens);
+                    }
+                }
 
-        for (path, transforms) in unloaded_scene.model_paths_to_transforms.iter() {
-            let model = scene.load_model(path, display)?;
-            for transform in transforms {
-                scene.model_instances.push(ModelInstance {
-                    model: model.clone(),
-                    transform: transform.clone(),
-                });
+                scene.scripts = unloaded_scene.scripts;
+                scene.simulation.spawn_points = unloaded_scene.spawn_points;
+
+                Ok(())
+            },
+        )
+    }
+
+    /// Reads a scene saved with `save`, loading each model it uses through `assets`, which it
+    /// takes the contents of unless it fails
+    pub fn load(path: &Path, assets: &mut Assets, inner_size: PhysicalSize<u32>) -> Result<Self> {
+        let serialized = std::fs::read_to_string(path)?;
+
+        Self::deserialize(&serialized, assets, inner_size)
+    }
+
+    /// Paths or logical names of the models a saved scene uses, so they can be loaded in the
+    /// background before the scene itself
+    pub fn model_paths(path: &Path) -> Result<Vec<PathBuf>> {
+        let serialized = std::fs::read_to_string(path)?;
+        let unloaded_scene = serde_json::from_str::<UnloadedScene>(&serialized)?;
+
+        Ok(unloaded_scene
+            .model_paths_to_instances
+            .into_keys()
+            .collect())
+    }
+
+    /// Writes the camera, title, lights, reflections, skybox, scripts, spawn points, tweens and the
+    /// ID and transform of each model instance grouped by model, referring to models by their
+    /// logical name if they have one
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// Builds a scene from a map, loading every asset it references or embeds
+    pub fn from_map(map: &Map, assets: &mut Assets, inner_size: PhysicalSize<u32>) -> Result<Self> {
+        let mut camera = map.camera.clone();
+        camera.set_aspect_ratio(inner_size.width as f32 / inner_size.height as f32);
+
+        Self::build(&map.title, camera, assets, |scene| {
+            scene.simulation = Simulation::from_map(map);
+
+            let models = map
+                .assets
+                .iter()
+                .map(|asset| match &asset.source {
+                    AssetSource::Referenced(path) => scene.load_model(path),
+                    AssetSource::Embedded(bytes) => scene.load_embedded_model(&asset.name, bytes),
+                })
+                .collect::<Result<Vec<Arc<Model>>>>()?;
+
+            for entity in map.entities.iter() {
+                let model = models
+                    .get(entity.asset)
+                    .ok_or_else(|| eyre!("Map entity refers to missing asset {}", entity.asset))?;
+
+                let uuid = scene.simulation.world.spawn_with_uuid(entity.uuid);
+                scene.insert_model(uuid, model.clone(), entity.transform.clone());
             }
-        }
 
-        Ok(scene)
+            if !map.brushes.is_empty() {
+                let cube = scene.load_model(Path::new("assets/models/cube.glb"))?;
+
+                // The cube model spans -1 to 1 on each axis
+                for brush in map.brushes.iter() {
+                    let entity = scene.spawn_model(
+                        cube.clone(),
+                        Transform {
+                            translation: brush.center().to_vec(),
+                            scale: brush.half_extents(),
+                            ..Transform::default()
+                        },
+                    );
+                    scene.simulation.world.insert(entity, Brush(*brush));
+                }
+            }
+
+            map.lighting.apply(&mut scene.environment);
+
+            Ok(())
+        })
     }
 
     pub fn save_as(&self) {
@@ -102,88 +530,1000 @@ impl Scene {
     }
 
     /// Load a model and create an instance of it in the scene
-    pub fn import_model(&mut self, path: &Path, display: &Display<WindowSurface>) -> Result<()> {
-        let model = self.load_model(path, display)?;
+    pub fn import_model(&mut self, path: &Path) -> Result<()> {
+        let model = self.load_model(path)?;
 
-        self.model_instances.push(ModelInstance::from(model));
+        self.spawn_model(model, Transform::default());
 
         Ok(())
     }
 
-    /// Load a model into the cache
-    pub fn load_model(
+    /// Load a model by path or logical name through the scene's assets
+    pub fn load_model(&mut self, path: &Path) -> Result<Arc<Model>> {
+        let handle = self.assets.load_model(path)?;
+
+        Ok(self.assets.model(handle).clone())
+    }
+
+    /// Drops a model from the scene's assets along with the instance buffers kept for it, unless
+    /// an entity or anything else still holds it. Returns whether it was unloaded.
+    pub fn unload_model(&mut self, name: &Path) -> bool {
+        let path = self.assets.resolve(name);
+        let Some(gl) = self.gl.as_mut() else {
+            return self.assets.unload_model(name);
+        };
+        let buffers = std::mem::take(&mut gl.instance_buffers);
+        let (unloading, kept) = buffers
+            .into_iter()
+            .partition::<HashMap<_, _>, _>(|((model, _), _)| model.path == path);
+        gl.instance_buffers = kept;
+
+        if self.assets.unload_model(name) {
+            return true;
+        }
+
+        // Still in use, so it's drawn again
+        gl.instance_buffers.extend(unloading);
+        false
+    }
+
+    /// Load a model from GLB data through the scene's assets, keyed by `name`
+    pub fn load_embedded_model(&mut self, name: &str, bytes: &[u8]) -> Result<Arc<Model>> {
+        let handle = self.assets.load_embedded_model(name, bytes)?;
+
+        Ok(self.assets.model(handle).clone())
+    }
+
+    /// Creates an entity that draws `model` at `transform`
+    pub fn spawn_model(&mut self, model: Arc<Model>, transform: Transform) -> UUID {
+        let entity = self.simulation.world.spawn();
+        self.insert_model(entity, model, transform);
+
+        entity
+    }
+
+    /// Gives an entity the components to draw `model`, as `Simulation::insert_model` does
+    pub(crate) fn insert_model(&mut self, entity: UUID, model: Arc<Model>, transform: Transform) {
+        self.simulation.insert_model(entity, model, transform);
+    }
+
+    /// Creates an entity that draws `model` and is moved by physics, colliding as its bounding box
+    pub fn spawn_physics_model(
         &mut self,
-        path: &Path,
-        display: &Display<WindowSurface>,
-    ) -> Result<Arc<Model>> {
-        Ok(self
-            .loaded_models
-            .entry(path.to_owned())
-            .or_insert(Model::load(path, display)?)
-            .clone())
+        model: Arc<Model>,
+        transform: Transform,
+        body: RigidBody,
+    ) -> UUID {
+        let collider = Collider::from_model_bounds(&model, transform.scale);
+
+        let entity = self.spawn_model(model, transform);
+        self.simulation.world.insert(entity, body);
+        self.simulation.world.insert(entity, collider);
+
+        entity
+    }
+
+    /// Sets what an entity is hit by and triggers, including its physics colliders if it has any
+    pub fn set_collision_layers(&mut self, entity: UUID, layers: CollisionLayers) {
+        self.simulation.world.insert(entity, layers);
+        self.simulation.physics.set_layers(entity, layers);
+    }
+
+    /// Replaces the scene's terrain along with its fixed collider, or removes it when `None`. Its
+    /// meshes are built when it's next drawn.
+    pub fn set_terrain(&mut self, terrain: Option<Terrain>) {
+        if let Some(entity) = self.terrain_entity.take() {
+            self.simulation.world.despawn(entity);
+        }
+        if let Some(gl) = self.gl.as_mut() {
+            gl.terrain_renderer.clear();
+            gl.terrain_built = false;
+        }
+
+        if let Some(terrain) = terrain.as_ref() {
+            let entity = self.simulation.world.spawn();
+            self.simulation.world.insert(entity, Transform::default());
+            self.simulation.world.insert(entity, terrain.collider());
+            self.terrain_entity = Some(entity);
+        }
+
+        self.terrain = terrain;
+    }
+
+    /// Adds console variables for the rendering switches, with `scene` finding the scene in
+    /// whatever the console runs on
+    pub fn register_cvars<C>(
+        console: &mut Console<C>,
+        scene: impl Fn(&mut C) -> &mut Scene + Copy + 'static,
+    ) {
+        console.register_cvar(
+            "r_debug_view",
+            "Draws the scene lit, or as wireframe, albedo, normals, depth, overdraw or lighting",
+            move |context| &mut scene(context).debug_view,
+        );
+        console.register_cvar(
+            "r_frustum_culling",
+            "Skips what's out of view",
+            move |context| &mut scene(context).frustum_culling,
+        );
+        console.register_cvar(
+            "r_occlusion_culling",
+            "Skips what's hidden behind other things",
+            move |context| &mut scene(context).occlusion_culling,
+        );
+        console.register_cvar(
+            "r_reflection_probes",
+            "Reflects what reflection probes capture",
+            move |context| &mut scene(context).reflection_probes,
+        );
+        console.register_cvar(
+            "r_planar_reflections",
+            "Mirrors the scene in the nearest planar reflection",
+            move |context| &mut scene(context).planar_reflections,
+        );
+        console.register_cvar(
+            "r_lod",
+            "Draws simpler models further away",
+            move |context| &mut scene(context).level_of_detail,
+        );
+        console.register_cvar(
+            "r_lod_bias",
+            "Multiplies the distances levels of detail switch at",
+            move |context| &mut scene(context).lod_bias,
+        );
+        console.register_cvar("fog_density", "How thick the fog is", move |context| {
+            &mut scene(context).environment.fog_density
+        });
+        console.register_cvar(
+            "wetness",
+            "How wet surfaces look, from 0 to 1",
+            move |context| &mut scene(context).environment.wetness,
+        );
     }
 
     pub fn model_is_loaded(&self, path: &Path) -> bool {
-        self.loaded_models.contains_key(&path.to_path_buf())
+        self.assets.model_is_loaded(path)
+    }
+
+    /// Captures every reflection probe again on the next render, e.g. once the level has been
+    /// changed around them
+    pub fn bake_reflection_probes(&mut self) {
+        if let Some(gl) = self.gl.as_mut() {
+            gl.reflections.clear();
+        }
+    }
+
+    /// Draws the scene from its camera through `backend` onto `target`
+    pub fn render<T: ?Sized, B: DrawScene<T>>(&mut self, backend: &mut B, target: &mut T) {
+        self.culling_statistics = CullingStatistics::default();
+        self.draw_calls = 0;
+        backend.draw_scene(self, target);
+    }
+
+    /// Renders the scene from several cameras, each into its viewport of `target`, e.g. for
+    /// split-screen or a preview from another camera. Each camera takes its viewport's aspect
+    /// ratio. The first view is treated as the scene's own camera, so only it keeps occlusion
+    /// results and shows what was debug drawn.
+    pub fn render_views<T: ?Sized, B: DrawScene<T>>(
+        &mut self,
+        backend: &mut B,
+        target: &mut T,
+        views: &[(Camera, Viewport)],
+    ) {
+        self.culling_statistics = CullingStatistics::default();
+        self.draw_calls = 0;
+        backend.draw_views(self, target, views);
+    }
+
+    /// What `render` does with OpenGL
+    pub(crate) fn render_gl<S: Surface>(&mut self, context: &OpenGLContext, target: &mut S) {
+        if !self.prepare_gl(context) {
+            return;
+        }
+        let display = &context.display;
+        self.update_reflection_probes(display);
+
+        let camera = self.camera.clone();
+        self.render_view(display, target, &camera, ViewKind::Main);
+
+        // Only from the scene's camera, as it's cleared once drawn
+        self.gpu_timer.begin("Debug draw");
+        self.draw_calls += self
+            .debug
+            .render(display, target, &mut self.assets, &camera)
+            .unwrap();
+        self.gpu_timer.end();
+    }
+
+    /// What `render_views` does with OpenGL
+    pub(crate) fn render_views_gl<S: Surface>(
+        &mut self,
+        context: &OpenGLContext,
+        target: &mut S,
+        views: &[(Camera, Viewport)],
+    ) {
+        if !self.prepare_gl(context) {
+            return;
+        }
+        let display = &context.display;
+        self.update_reflection_probes(display);
+
+        let size = target.get_dimensions();
+        let sky_color = self.environment.sky_color;
+        target.clear_color_and_depth((sky_color.red, sky_color.green, sky_color.blue, 1.0), 1.0);
+
+        // Taken out so views can be drawn into them while the scene is borrowed
+        let mut view_targets = std::mem::take(&mut self.gl.as_mut().unwrap().view_targets);
+        view_targets.resize_with(views.len(), || None);
+
+        for (index, (camera, viewport)) in views.iter().enumerate() {
+            let rect = viewport.rect(size);
+            let view_size = (rect.width, rect.height);
+            let view_target = &mut view_targets[index];
+            if view_target.as_ref().map(ViewTarget::size) != Some(view_size) {
+                *view_target = match ViewTarget::new(display, view_size) {
+                    Ok(view_target) => Some(view_target),
+                    Err(error) => {
+                        warn!("Skipping view {}: {}", index, error);
+                        None
+                    }
+                };
+            }
+            let Some(view_target) = view_target.as_ref() else {
+                continue;
+            };
+
+            let mut camera = camera.clone();
+            camera.set_aspect_ratio(viewport.aspect_ratio(size));
+
+            let mut framebuffer = match view_target.framebuffer(display) {
+                Ok(framebuffer) => framebuffer,
+                Err(error) => {
+                    warn!("Skipping view {}: {}", index, error);
+                    continue;
+                }
+            };
+            framebuffer
+                .clear_color_and_depth((sky_color.red, sky_color.green, sky_color.blue, 1.0), 1.0);
+            let view = if index == 0 {
+                ViewKind::Main
+            } else {
+                ViewKind::Player
+            };
+            self.render_view(display, &mut framebuffer, &camera, view);
+
+            if index == 0 {
+                self.gpu_timer.begin("Debug draw");
+                self.draw_calls += self
+                    .debug
+                    .render(display, &mut framebuffer, &mut self.assets, &camera)
+                    .unwrap();
+                self.gpu_timer.end();
+            }
+
+            view_target.blit(target, rect);
+        }
+
+        self.gl.as_mut().unwrap().view_targets = view_targets;
+    }
+
+    /// Renders the scene from a camera other than the scene's own with OpenGL, e.g. for portals
+    pub fn render_with_camera<S: Surface>(
+        &mut self,
+        context: &OpenGLContext,
+        target: &mut S,
+        camera: &Camera,
+    ) {
+        if self.prepare_gl(context) {
+            self.render_view(&context.display, target, camera, ViewKind::Auxiliary);
+        }
     }
 
-    pub fn render(&mut self, display: &Display<WindowSurface>, target: &mut Frame) {
-        self.render_models(display, target);
-        self.render_lines(display, target);
+    /// Makes the OpenGL resources the first time the scene is drawn with it, then uploads whatever
+    /// hasn't been yet: the skybox, the terrain's chunks and the models of instances. Returns
+    /// whether the scene can be drawn.
+    fn prepare_gl(&mut self, context: &OpenGLContext) -> bool {
+        let display = &context.display;
+        if self.gl.is_none() {
+            match GlResources::new(&mut self.assets, display) {
+                Ok(gl) => self.gl = Some(gl),
+                Err(error) => {
+                    warn!("Could not draw the scene with OpenGL: {}", error);
+                    return false;
+                }
+            }
+        }
+        let gl = self.gl.as_mut().unwrap();
+
+        if let Some(skybox) = self.skybox.as_mut() {
+            if let Err(error) = skybox.upload(display) {
+                warn!("Not drawing the skybox: {}", error);
+            }
+        }
+
+        if !gl.terrain_built {
+            gl.terrain_built = true;
+            if let Some(terrain) = self.terrain.as_ref() {
+                if let Err(error) = gl
+                    .terrain_renderer
+                    .build(terrain, &mut self.assets, display)
+                {
+                    gl.terrain_renderer.clear();
+                    warn!("Not drawing the terrain: {}", error);
+                }
+            }
+        }
+
+        gl.models.retain_used();
+        for (_, model_instance) in self.simulation.world.query::<ModelInstance>() {
+            if let Err(error) = gl.models.upload(context, &model_instance.model) {
+                warn!("Not drawing {:?}: {}", model_instance.model.path, error);
+            }
+        }
+
+        true
+    }
+
+    /// The OpenGL resources, which `prepare_gl` has made by the time anything is drawn with them
+    fn gl(&self) -> &GlResources {
+        self.gl.as_ref().unwrap()
     }
 
-    fn render_models(&self, display: &Display<WindowSurface>, target: &mut Frame) {
-        let instance_buffers = self.build_instance_buffers(display);
+    /// Captures the faces of reflection probes that are due, each drawn from the probe's position
+    /// with its own capture taken out so it isn't sampled while being drawn into
+    fn update_reflection_probes(&mut self, display: &Display<WindowSurface>) {
+        self.gl
+            .as_mut()
+            .unwrap()
+            .reflections
+            .retain(&self.simulation.world);
+        // Nothing else would show up in what's captured
+        if !self.reflection_probes || self.debug_view != DebugView::Lit {
+            return;
+        }
+
+        let _scope = profiling::scope("Reflection probes");
+        let probes = self
+            .simulation
+            .world
+            .query2::<ReflectionProbe, Transform>()
+            .map(|(entity, probe, transform)| {
+                (
+                    entity,
+                    probe.clone(),
+                    Point3::from_vec(transform.translation),
+                )
+            })
+            .collect_vec();
+        let resolution = self.graphics_settings.reflection_resolution;
+        let sky_color = self.environment.sky_color;
 
-        target.clear_color(0.0, 0.0, 0.0, 1.0);
+        for (entity, probe, position) in probes {
+            let layers = match self
+                .gl
+                .as_mut()
+                .unwrap()
+                .reflections
+                .due_layers(display, entity, &probe, position, resolution)
+            {
+                Ok(layers) => layers,
+                Err(error) => {
+                    warn!("Skipping reflection probe {:?}: {}", entity, error);
+                    continue;
+                }
+            };
+            let Some(capture) = self.gl.as_mut().unwrap().reflections.take_capture(entity) else {
+                continue;
+            };
+
+            for layer in layers {
+                let camera = reflection::face_camera(&self.camera, position, layer);
+                let mut framebuffer = match capture.framebuffer(display, layer) {
+                    Ok(framebuffer) => framebuffer,
+                    Err(error) => {
+                        warn!("Skipping reflection probe {:?}: {}", entity, error);
+                        break;
+                    }
+                };
+                framebuffer.clear_color_and_depth(
+                    (sky_color.red, sky_color.green, sky_color.blue, 1.0),
+                    1.0,
+                );
+                self.render_view(display, &mut framebuffer, &camera, ViewKind::Auxiliary);
+                if let Err(error) = capture.update_mipmaps(display, layer) {
+                    warn!("Could not blur reflection probe {:?}: {}", entity, error);
+                }
+            }
+            self.gl
+                .as_mut()
+                .unwrap()
+                .reflections
+                .return_capture(entity, capture);
+        }
+    }
+
+    /// Draws the scene mirrored in the nearest `PlanarReflection` the camera is in front of, for
+    /// surfaces lying on it to show. Returns the plane it was drawn for as `(normal, distance)`.
+    fn render_planar_reflection(
+        &mut self,
+        display: &Display<WindowSurface>,
+        (width, height): (u32, u32),
+        camera: &Camera,
+    ) -> Option<Vector4<f32>> {
+        if !self.planar_reflections || self.debug_view != DebugView::Lit {
+            return None;
+        }
+        let (plane, planar_reflection) =
+            reflection::nearest_planar_reflection(&self.simulation.world, camera)?;
+
+        let _scope = profiling::scope("Planar reflection");
+        let scale = planar_reflection.resolution_scale.clamp(0.1, 1.0);
+        let size = (
+            ((width as f32 * scale) as u32).max(1),
+            ((height as f32 * scale) as u32).max(1),
+        );
+        let planar = match self
+            .gl
+            .as_mut()
+            .unwrap()
+            .reflections
+            .take_planar(display, size)
+        {
+            Ok(planar) => planar,
+            Err(error) => {
+                warn!("Skipping the planar reflection: {}", error);
+                return None;
+            }
+        };
+
+        let sky_color = self.environment.sky_color;
+        let mirrored_camera = reflection::mirrored_camera(camera, plane);
+        match planar.framebuffer(display) {
+            Ok(mut framebuffer) => {
+                framebuffer.clear_color_and_depth(
+                    (sky_color.red, sky_color.green, sky_color.blue, 1.0),
+                    1.0,
+                );
+                self.render_view(
+                    display,
+                    &mut framebuffer,
+                    &mirrored_camera,
+                    ViewKind::Auxiliary,
+                );
+            }
+            Err(error) => {
+                // Dropping the target hides the reflection rather than showing a stale one
+                warn!("Skipping the planar reflection: {}", error);
+                return None;
+            }
+        }
+
+        self.gl.as_mut().unwrap().reflections.return_planar(planar);
+
+        Some(plane)
+    }
+
+    /// Occlusion results are only kept for the scene's own camera, as they don't hold for any
+    /// other. Only the scene's own camera draws planar reflections, before anything else as it
+    /// draws the scene from another camera.
+    fn render_view<S: Surface>(
+        &mut self,
+        display: &Display<WindowSurface>,
+        target: &mut S,
+        camera: &Camera,
+        view: ViewKind,
+    ) {
+        let main_view = view == ViewKind::Main;
+        let planar_plane = if main_view {
+            self.render_planar_reflection(display, target.get_dimensions(), camera)
+        } else {
+            None
+        };
+        self.gl
+            .as_mut()
+            .unwrap()
+            .reflections
+            .show_planar(planar_plane, target.get_dimensions());
+
+        let occlusion_culling = self.occlusion_culling && main_view;
+        if !self.occlusion_culling {
+            self.occlusion.clear();
+        }
+
+        {
+            let _scope = profiling::scope("Instance buffers");
+            if occlusion_culling {
+                self.occlusion.collect();
+            }
+            self.update_instance_buffers(display, camera, occlusion_culling, view);
+            let gl = self.gl.as_mut().unwrap();
+            gl.lights_buffer
+                .write(&LightsBlock::new(&self.simulation.world));
+            gl.reflections
+                .bind(&self.simulation.world, camera, self.reflection_probes);
+        }
+
+        let _scope = profiling::scope("Draw scene");
+
+        self.gpu_timer.begin("Models");
+        self.draw_calls += match (self.debug_view, self.render_mode) {
+            (view, _) if view.shader_view().is_some() => self.render_debug_view(target, camera),
+            // White surfaces only show up lit in the forward shaders
+            (DebugView::Lighting, _) | (_, RenderMode::Forward) => {
+                self.render_forward(target, camera)
+            }
+            (_, RenderMode::Deferred) => match self.render_deferred(display, target, camera) {
+                Ok(draw_calls) => draw_calls,
+                Err(error) => {
+                    warn!("Could not draw the scene deferred: {}", error);
+                    0
+                }
+            },
+        };
+        self.gpu_timer.end();
+
+        // Terrain has shaders of its own, so it's left out of views that replace the scene's
+        if self.terrain.is_some() && self.debug_view.shader_view().is_none() {
+            self.gpu_timer.begin("Terrain");
+            self.draw_calls += self.render_terrain(target, camera);
+            self.gpu_timer.end();
+        }
+
+        // Against everything opaque, for the next frames to skip what's hidden
+        if occlusion_culling {
+            self.gpu_timer.begin("Occlusion queries");
+            self.draw_calls += self
+                .occlusion
+                .test(display, target, &mut self.assets, camera, &mut self.debug)
+                .unwrap();
+            self.gpu_timer.end();
+        }
+
+        // On top of whatever they were left on, which is drawn by now
+        self.gpu_timer.begin("Decals");
+        self.draw_calls += self
+            .decals
+            .render(
+                display,
+                &self.simulation.world,
+                target,
+                &mut self.assets,
+                camera,
+            )
+            .unwrap();
+        self.gpu_timer.end();
+
+        if let Some(skybox) = self.skybox.as_ref() {
+            self.gpu_timer.begin("Skybox");
+            self.gl()
+                .skybox_renderer
+                .render(target, &self.assets, skybox, camera)
+                .unwrap();
+            self.gpu_timer.end();
+            self.draw_calls += 1;
+        }
+
+        // After the skybox, as it's drawn wherever nothing has written depth
+        self.gpu_timer.begin("Transparent");
+        self.draw_calls += self.render_transparent(target, camera);
+        self.gpu_timer.end();
+
+        self.gpu_timer.begin("Particles");
+        self.draw_calls += self
+            .particles
+            .render(display, target, &mut self.assets, camera)
+            .unwrap();
+        self.gpu_timer.end();
+
+        self.gpu_timer.begin("Billboards");
+        let gl = self.gl.as_mut().unwrap();
+        self.draw_calls += gl
+            .billboard_renderer
+            .render(
+                display,
+                &self.simulation.world,
+                target,
+                &self.assets,
+                camera,
+            )
+            .unwrap();
+        self.gpu_timer.end();
+
+        self.gpu_timer.begin("Lines");
+        self.render_lines(display, target, camera);
+        self.gpu_timer.end();
+    }
+
+    /// Returns the number of draw calls made
+    fn render_forward<S: Surface>(&self, target: &mut S, camera: &Camera) -> usize {
+        let sky_color = self.environment.sky_color;
+        target.clear_color_and_depth((sky_color.red, sky_color.green, sky_color.blue, 1.0), 1.0);
+
+        let uniforms = forward_uniforms!(self, camera);
+
+        self.draw_models(
+            target,
+            self.forward_program(),
+            &uniforms,
+            &DrawParameters {
+                depth: Depth {
+                    test: DepthTest::IfLess,
+                    write: true,
+                    ..Depth::default()
+                },
+                multisampling: self.graphics_settings.multisampling(),
+                ..DrawParameters::default()
+            },
+        )
+    }
+
+    /// Draws models with the debug view shader rather than shading them. Overdraw adds up every
+    /// fragment without depth testing. Returns the number of draw calls made.
+    fn render_debug_view<S: Surface>(&self, target: &mut S, camera: &Camera) -> usize {
+        target.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), 1.0);
+
+        let uniforms = uniform! {
+            vp: maths::raw_matrix(camera.view_projection),
+            camera_position: <[f32; 3]>::from(camera.position),
+            view: self.debug_view.shader_view().unwrap_or_default(),
+            near: Camera::NEAR,
+            far: Camera::FAR,
+            Bones: &self.gl().bones_buffer,
+        };
+
+        let draw_parameters = if self.debug_view == DebugView::Overdraw {
+            DrawParameters {
+                blend: Blend {
+                    color: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::One,
+                        destination: LinearBlendingFactor::One,
+                    },
+                    ..Blend::default()
+                },
+                ..DrawParameters::default()
+            }
+        } else {
+            DrawParameters {
+                depth: Depth {
+                    test: DepthTest::IfLess,
+                    write: true,
+                    ..Depth::default()
+                },
+                multisampling: self.graphics_settings.multisampling(),
+                ..DrawParameters::default()
+            }
+        };
+
+        self.draw_models(
+            target,
+            self.assets.program(self.gl().debug_view_program),
+            &uniforms,
+            &draw_parameters,
+        )
+    }
+
+    /// Forward shades the terrain's chunks in view, whichever render mode drew the models. Returns
+    /// the number of draw calls made.
+    fn render_terrain<S: Surface>(&self, target: &mut S, camera: &Camera) -> usize {
+        let uniforms = forward_uniforms!(self, camera);
+        let frustum = Frustum::from_matrix(camera.view_projection);
+
+        self.gl().terrain_renderer.render(
+            target,
+            &self.assets,
+            &uniforms,
+            self.frustum_culling.then_some(&frustum),
+            self.graphics_settings.multisampling(),
+        )
+    }
+
+    /// Forward shades the blended primitives of transparent instances over what's been drawn,
+    /// furthest first, whichever render mode drew the rest. Returns the number of draw calls made.
+    fn render_transparent<S: Surface>(&self, target: &mut S, camera: &Camera) -> usize {
+        let gl = self.gl();
+        let Some(instance_buffer) = gl.transparent_instance_buffer.as_ref() else {
+            return 0;
+        };
+
+        let uniforms = forward_uniforms!(self, camera);
+        let program = self.forward_program();
+        let draw_parameters = DrawParameters {
+            // Hidden by what's opaque without hiding each other
+            depth: Depth {
+                test: DepthTest::IfLess,
+                write: false,
+                ..Depth::default()
+            },
+            blend: Blend::alpha_blending(),
+            multisampling: self.graphics_settings.multisampling(),
+            ..DrawParameters::default()
+        };
+
+        let mut draw_calls = 0;
+
+        for (index, transparent_instance) in self.transparent_instances.iter().enumerate() {
+            let model = &transparent_instance.model;
+            let level = transparent_instance.level;
+            let Some(gl_model) = gl.models.get(model) else {
+                continue;
+            };
+
+            for (primitive, buffers) in model
+                .lod_meshes(level)
+                .iter()
+                .flat_map(|mesh| mesh.primitives.iter())
+                .zip(gl_model.level(level))
+            {
+                let material = model.materials[primitive.material].borrow();
+                if material.blend_mode != BlendMode::Blend {
+                    continue;
+                }
+
+                let uniforms = MaterialUniforms {
+                    uniforms: &uniforms,
+                    material: &material,
+                    textures: &gl_model.materials[primitive.material],
+                    max_anisotropy: self.graphics_settings.anisotropy,
+                };
+
+                target
+                    .draw(
+                        (
+                            &buffers.vertex_buffer,
+                            instance_buffer
+                                .buffer
+                                .slice(index..index + 1)
+                                .unwrap()
+                                .per_instance()
+                                .unwrap(),
+                        ),
+                        &buffers.index_buffer,
+                        program,
+                        &uniforms,
+                        &draw_parameters,
+                    )
+                    .unwrap();
+                draw_calls += 1;
+            }
+        }
+
+        draw_calls
+    }
+
+    fn forward_program(&self) -> &Program {
+        let gl = self.gl();
+        self.assets.program(match self.shading_model {
+            ShadingModel::BlinnPhong => gl.model_program,
+            ShadingModel::Pbr => gl.pbr_program,
+        })
+    }
+
+    fn render_deferred<S: Surface>(
+        &mut self,
+        display: &Display<WindowSurface>,
+        target: &mut S,
+        camera: &Camera,
+    ) -> Result<usize> {
+        self.gl
+            .as_mut()
+            .unwrap()
+            .deferred_renderer
+            .resize(display, target.get_dimensions())?;
+
+        let gl = self.gl();
+        let mut framebuffer = gl.deferred_renderer.geometry_framebuffer(display)?;
 
         let uniforms = uniform! {
-            vp: maths::raw_matrix(self.camera.view_projection),
-            camera_position: <[f32; 3]>::from(self.camera.position)
+            vp: maths::raw_matrix(camera.view_projection),
+            camera_position: <[f32; 3]>::from(camera.position),
+            wetness: self.environment.wetness,
+            Bones: &gl.bones_buffer,
         };
 
-        for (model, instance_buffer) in instance_buffers {
-            for mesh in model.meshes.iter() {
-                for primitive in mesh.primitives.iter() {
+        let draw_calls = self.draw_models(
+            &mut framebuffer,
+            self.assets.program(gl.deferred_renderer.geometry_program),
+            &uniforms,
+            &DrawParameters {
+                depth: Depth {
+                    test: DepthTest::IfLess,
+                    write: true,
+                    ..Depth::default()
+                },
+                multisampling: self.graphics_settings.multisampling(),
+                ..DrawParameters::default()
+            },
+        );
+
+        gl.deferred_renderer.render_lighting(
+            target,
+            &self.assets,
+            camera,
+            &self.environment,
+            &gl.lights_buffer,
+        )?;
+
+        // Geometry pass plus the lighting pass
+        Ok(draw_calls + 1)
+    }
+
+    /// Draws every model instance with `program`, adding each primitive's material to the uniforms.
+    /// Primitives with blended materials are left for `render_transparent`, apart from those of
+    /// skinned models. The uniforms must include `bones_buffer` as `Bones`. Returns the number of
+    /// draw calls made.
+    fn draw_models<S: Surface, U: Uniforms>(
+        &self,
+        target: &mut S,
+        program: &Program,
+        uniforms: &U,
+        draw_parameters: &DrawParameters,
+    ) -> usize {
+        let gl = self.gl();
+        let mut draw_calls = 0;
+        let draw_parameters = &DrawParameters {
+            polygon_mode: if self.debug_view == DebugView::Wireframe {
+                PolygonMode::Line
+            } else {
+                PolygonMode::Fill
+            },
+            ..draw_parameters.clone()
+        };
+
+        for ((model, level), instance_buffer) in self
+            .draw_order
+            .iter()
+            .filter_map(|key| gl.instance_buffers.get_key_value(key))
+        {
+            if instance_buffer.count == 0 {
+                continue;
+            }
+            let Some(gl_model) = gl.models.get(model) else {
+                continue;
+            };
+
+            let InstanceBuffer { buffer, count } = instance_buffer;
+
+            for (primitive, buffers) in model
+                .lod_meshes(*level)
+                .iter()
+                .flat_map(|mesh| mesh.primitives.iter())
+                .zip(gl_model.level(*level))
+            {
+                let material = model.materials[primitive.material].borrow();
+                if material.blend_mode == BlendMode::Blend {
+                    continue;
+                }
+
+                let uniforms = MaterialUniforms {
+                    uniforms,
+                    material: &material,
+                    textures: &gl_model.materials[primitive.material],
+                    max_anisotropy: self.graphics_settings.anisotropy,
+                };
+
+                if self.instanced_rendering {
+                    target
+                        .draw(
+                            (
+                                &buffers.vertex_buffer,
+                                buffer.slice(0..*count).unwrap().per_instance().unwrap(),
+                            ),
+                            &buffers.index_buffer,
+                            program,
+                            &uniforms,
+                            draw_parameters,
+                        )
+                        .unwrap();
+                    draw_calls += 1;
+
+                    continue;
+                }
+
+                for index in 0..*count {
                     target
                         .draw(
                             (
-                                &primitive.vertex_buffer,
-                                instance_buffer.per_instance().unwrap(),
+                                &buffers.vertex_buffer,
+                                buffer
+                                    .slice(index..index + 1)
+                                    .unwrap()
+                                    .per_instance()
+                                    .unwrap(),
                             ),
-                            &primitive.index_buffer,
-                            &self.model_program,
+                            &buffers.index_buffer,
+                            program,
                             &uniforms,
-                            &DrawParameters::default(),
+                            draw_parameters,
                         )
                         .unwrap();
+                    draw_calls += 1;
                 }
             }
         }
+
+        let Some(skinned_instance_buffer) = gl.skinned_instance_buffer.as_ref() else {
+            return draw_calls;
+        };
+
+        for (index, skinned_instance) in self.skinned_instances.iter().enumerate() {
+            let model = &skinned_instance.model;
+            let Some(gl_model) = gl.models.get(model) else {
+                continue;
+            };
+            gl.bones_buffer.write(&skinned_instance.bones);
+
+            for (primitive, buffers) in model
+                .meshes
+                .iter()
+                .flat_map(|mesh| mesh.primitives.iter())
+                .zip(gl_model.level(0))
+            {
+                let material = model.materials[primitive.material].borrow();
+                let uniforms = MaterialUniforms {
+                    uniforms,
+                    material: &material,
+                    textures: &gl_model.materials[primitive.material],
+                    max_anisotropy: self.graphics_settings.anisotropy,
+                };
+
+                target
+                    .draw(
+                        (
+                            &buffers.vertex_buffer,
+                            skinned_instance_buffer
+                                .buffer
+                                .slice(index..index + 1)
+                                .unwrap()
+                                .per_instance()
+                                .unwrap(),
+                        ),
+                        &buffers.index_buffer,
+                        program,
+                        &uniforms,
+                        draw_parameters,
+                    )
+                    .unwrap();
+                draw_calls += 1;
+            }
+        }
+
+        draw_calls
     }
 
-    fn render_lines(&mut self, display: &Display<WindowSurface>, target: &mut Frame) {
-        self.line_vertex_buffers
-            .get_or_insert(self.build_line_vertex_buffers(display));
+    fn render_lines<S: Surface>(
+        &mut self,
+        display: &Display<WindowSurface>,
+        target: &mut S,
+        camera: &Camera,
+    ) {
+        if self.gl().line_vertex_buffers.is_none() {
+            let line_vertex_buffers = self.build_line_vertex_buffers(display);
+            self.gl.as_mut().unwrap().line_vertex_buffers = Some(line_vertex_buffers);
+        }
 
         let uniforms = uniform! {
-            vp: maths::raw_matrix(self.camera.view_projection),
+            vp: maths::raw_matrix(camera.view_projection),
         };
 
-        for (width, line_points) in self.line_vertex_buffers.iter().flatten() {
+        let gl = self.gl();
+        let mut draw_calls = 0;
+        for (width, line_points) in gl.line_vertex_buffers.iter().flatten() {
             target
                 .draw(
                     line_points,
                     &NoIndices(PrimitiveType::LinesList),
-                    &self.lines_program,
+                    self.assets.program(gl.lines_program),
                     &uniforms,
                     &DrawParameters {
                         line_width: Some(*width as f32),
+                        multisampling: self.graphics_settings.multisampling(),
                         ..DrawParameters::default()
                     },
                 )
                 .unwrap();
+            draw_calls += 1;
         }
+        self.draw_calls += draw_calls;
     }
 
     fn build_line_vertex_buffers(
@@ -216,11 +1556,64 @@ impl Scene {
             .collect_vec()
     }
 
-    fn build_instance_map(&self) -> HashMap<Arc<Model>, Vec<Instance>> {
-        let mut instance_map = HashMap::<Arc<Model>, Vec<Instance>>::new();
+    /// Groups the transforms of each model's instances by level of detail, leaving out those
+    /// outside the frustum. With frustum culling only the instances the simulation's `bvh` finds
+    /// in view are visited, so its bounds should be up to date. Skinned instances are posed and
+    /// returned separately, as they can't share a draw call. Auxiliary views leave the culling
+    /// statistics and the levels instances were last drawn at alone.
+    pub(crate) fn build_instance_map(
+        &mut self,
+        camera: &Camera,
+        occlusion_culling: bool,
+        view: ViewKind,
+    ) -> VisibleInstances {
+        let frustum = Frustum::from_matrix(camera.view_projection);
+        let mut statistics = CullingStatistics::default();
+        let entities = if self.frustum_culling {
+            let visible = self.simulation.bvh.query_frustum(&frustum);
+            statistics.culled += self.simulation.bvh.len() - visible.len();
+            visible
+        } else {
+            self.simulation
+                .world
+                .query::<ModelInstance>()
+                .map(|(entity, _)| entity)
+                .collect_vec()
+        };
+        // Along with each instance's distance from the camera, for sorting
+        let mut instance_map = HashMap::<(Arc<Model>, usize), Vec<(f32, Instance)>>::new();
+        let mut lod_levels = HashMap::new();
+        let mut skinned_instances = vec![];
+        let mut transparent_instances = vec![];
+
+        for entity in entities {
+            let world = &self.simulation.world;
+            let (Some(model_instance), Some(transform)) = (
+                world.get::<ModelInstance>(entity),
+                world.get::<Transform>(entity),
+            ) else {
+                continue;
+            };
+            let model = &model_instance.model;
 
-        for model_instance in self.model_instances.iter() {
-            let transform_matrix = Matrix4::from(model_instance.transform.clone());
+            let transform_matrix = self.interpolated_matrix(entity, transform);
+            let bounds = model.bounds.transformed(transform_matrix);
+            if self.frustum_culling && !frustum.intersects_aabb(&bounds) {
+                statistics.culled += 1;
+                continue;
+            }
+
+            // Tested again each frame until it's seen, so it comes back when no longer hidden
+            if occlusion_culling {
+                self.occlusion.add_candidate(entity, bounds);
+                if self.occlusion.is_occluded(entity) {
+                    statistics.culled += 1;
+                    statistics.occluded += 1;
+                    continue;
+                }
+            }
+
+            statistics.drawn += 1;
 
             let instance = Instance {
                 transform: <[[f32; 4]; 4]>::from(transform_matrix),
@@ -229,25 +1622,188 @@ impl Scene {
                 ),
             };
 
-            instance_map
-                .entry(model_instance.model.clone())
-                .or_insert(vec![instance])
-                .push(instance);
+            let Some(skeleton) = &model.skeleton else {
+                let distance = (bounds.center() - camera.position).magnitude();
+                let level = if self.level_of_detail {
+                    lod::select_level(
+                        &model.lods,
+                        distance / self.lod_bias,
+                        self.lod_levels.get(&entity).copied().unwrap_or(0),
+                    )
+                } else {
+                    0
+                };
+                lod_levels.insert(entity, level);
+
+                if model.has_blended_materials() {
+                    transparent_instances.push(TransparentInstance {
+                        model: model.clone(),
+                        level,
+                        instance,
+                        distance,
+                    });
+                }
+
+                instance_map
+                    .entry((model.clone(), level))
+                    .or_default()
+                    .push((distance, instance));
+                continue;
+            };
+
+            let bone_matrices = match (world.get::<Corpse>(entity), world.get::<Ragdoll>(entity)) {
+                (Some(corpse), Some(ragdoll)) => corpse.bone_matrices(ragdoll),
+                _ => {
+                    let pose = self
+                        .simulation
+                        .pose(entity)
+                        .unwrap_or_else(|| skeleton.rest_pose());
+                    let animated = skeleton.bone_matrices(&pose);
+
+                    match world.get::<RagdollBlend>(entity) {
+                        Some(blend) => blend.bone_matrices(&animated),
+                        None => animated,
+                    }
+                }
+            };
+
+            skinned_instances.push(SkinnedInstance {
+                model: model_instance.model.clone(),
+                instance,
+                bones: BonesBlock::new(&bone_matrices),
+            });
+        }
+
+        if view != ViewKind::Auxiliary {
+            self.culling_statistics.culled += statistics.culled;
+            self.culling_statistics.occluded += statistics.occluded;
+            self.culling_statistics.drawn += statistics.drawn;
+
+            // Kept for instances that were culled, so they don't pop when they come back into view
+            self.lod_levels
+                .retain(|entity, _| self.simulation.world.contains(*entity));
+            self.lod_levels.extend(lod_levels);
+        }
+
+        for instances in instance_map.values_mut() {
+            instances.sort_by(|(a, _), (b, _)| a.total_cmp(b));
         }
+        let draw_order = instance_map
+            .iter()
+            .sorted_by(|(_, a), (_, b)| {
+                let nearest = |instances: &[(f32, Instance)]| {
+                    instances
+                        .first()
+                        .map_or(f32::INFINITY, |(distance, _)| *distance)
+                };
+                nearest(a).total_cmp(&nearest(b))
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
 
-        instance_map
+        transparent_instances.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+
+        VisibleInstances {
+            instances: instance_map
+                .into_iter()
+                .map(|(key, instances)| {
+                    (
+                        key,
+                        instances
+                            .into_iter()
+                            .map(|(_, instance)| instance)
+                            .collect(),
+                    )
+                })
+                .collect(),
+            draw_order,
+            skinned: skinned_instances,
+            transparent: transparent_instances,
+        }
     }
 
-    fn build_instance_buffers(
-        &self,
+    /// World matrix to draw an entity at, blending its transform and those of its parents between
+    /// the last two simulation steps
+    fn interpolated_matrix(&self, entity: UUID, transform: &Transform) -> Matrix4<f32> {
+        let world = &self.simulation.world;
+
+        let local = Matrix4::from(match world.get::<PreviousTransform>(entity) {
+            Some(PreviousTransform(previous)) => previous.lerp(transform, self.interpolation),
+            None => transform.clone(),
+        });
+
+        let Some(&parent) = world.get::<Parent>(entity) else {
+            return local;
+        };
+        let parent_matrix = world
+            .get::<Transform>(parent.entity)
+            .map_or(Matrix4::identity(), |parent_transform| {
+                self.interpolated_matrix(parent.entity, parent_transform)
+            });
+
+        parent_matrix * hierarchy::node_matrix(world, parent) * local
+    }
+
+    /// Writes the transforms of the instances visible to the camera into the instance buffers,
+    /// only reallocating a buffer when it grows too small
+    fn update_instance_buffers(
+        &mut self,
         display: &Display<WindowSurface>,
-    ) -> Vec<(Arc<Model>, VertexBuffer<Instance>)> {
-        let instance_map = self.build_instance_map();
+        camera: &Camera,
+        occlusion_culling: bool,
+        view: ViewKind,
+    ) {
+        let VisibleInstances {
+            instances: instance_map,
+            draw_order,
+            skinned: skinned_instances,
+            transparent: transparent_instances,
+        } = self.build_instance_map(camera, occlusion_culling, view);
 
-        instance_map
-            .into_iter()
-            .map(|(model, instances)| (model, VertexBuffer::new(display, &instances).unwrap()))
-            .collect_vec()
+        // Buffers of levels no instance is at this frame are kept while their model is loaded, as
+        // instances often move between levels and in and out of view
+        let assets = &self.assets;
+        let gl = self.gl.as_mut().unwrap();
+        gl.instance_buffers
+            .retain(|(model, _), _| assets.is_cached(model));
+        for instance_buffer in gl.instance_buffers.values_mut() {
+            instance_buffer.count = 0;
+        }
+
+        for (key, instances) in instance_map {
+            if let Some(instance_buffer) =
+                InstanceBuffer::update(gl.instance_buffers.get_mut(&key), display, &instances)
+            {
+                gl.instance_buffers.insert(key, instance_buffer);
+            }
+        }
+
+        let instances = skinned_instances
+            .iter()
+            .map(|skinned_instance| skinned_instance.instance)
+            .collect_vec();
+
+        if let Some(instance_buffer) =
+            InstanceBuffer::update(gl.skinned_instance_buffer.as_mut(), display, &instances)
+        {
+            gl.skinned_instance_buffer = Some(instance_buffer);
+        }
+
+        self.skinned_instances = skinned_instances;
+
+        let instances = transparent_instances
+            .iter()
+            .map(|transparent_instance| transparent_instance.instance)
+            .collect_vec();
+
+        if let Some(instance_buffer) =
+            InstanceBuffer::update(gl.transparent_instance_buffer.as_mut(), display, &instances)
+        {
+            gl.transparent_instance_buffer = Some(instance_buffer);
+        }
+
+        self.transparent_instances = transparent_instances;
+        self.draw_order = draw_order;
     }
 }
 
@@ -256,27 +1812,148 @@ impl Serialize for Scene {
     where
         S: Serializer,
     {
-        let mut instance_map = HashMap::<PathBuf, Vec<Transform>>::new();
+        let mut instance_map = HashMap::<PathBuf, Vec<SavedInstance>>::new();
+
+        for (uuid, model_instance, transform) in
+            self.simulation.world.query2::<ModelInstance, Transform>()
+        {
+            let path = &model_instance.model.path;
+            let name = self
+                .assets
+                .name_of(path)
+                .map_or_else(|| path.clone(), PathBuf::from);
 
-        for model_instance in self.model_instances.iter() {
-            instance_map
-                .entry(model_instance.model.path.clone())
-                .or_insert(vec![model_instance.transform.clone()])
-                .push(model_instance.transform.clone());
+            instance_map.entry(name).or_default().push(SavedInstance {
+                uuid,
+                transform: transform.clone(),
+            });
         }
 
-        let mut s = serializer.serialize_struct("Scene", 2)?;
+        let lights = self
+            .simulation
+            .world
+            .query2::<Light, Transform>()
+            .map(|(uuid, light, transform)| SavedLight {
+                uuid,
+                transform: transform.clone(),
+                light: light.clone(),
+            })
+            .collect_vec();
+
+        let reflection_probes = self
+            .simulation
+            .world
+            .query2::<ReflectionProbe, Transform>()
+            .map(|(uuid, probe, transform)| SavedReflectionProbe {
+                uuid,
+                transform: transform.clone(),
+                probe: probe.clone(),
+            })
+            .collect_vec();
+
+        let planar_reflections = self
+            .simulation
+            .world
+            .query2::<PlanarReflection, Transform>()
+            .map(|(uuid, reflection, transform)| SavedPlanarReflection {
+                uuid,
+                transform: transform.clone(),
+                reflection: *reflection,
+            })
+            .collect_vec();
+
+        let tweens = self
+            .simulation
+            .world
+            .query::<Tweens>()
+            .map(|(uuid, tweens)| SavedTweens {
+                uuid,
+                tweens: tweens.clone(),
+            })
+            .collect_vec();
+
+        let skybox = self.skybox.as_ref().map(|skybox| SavedSkybox {
+            source: skybox.source.clone(),
+            intensity: skybox.intensity,
+            image_based_lighting: skybox.image_based_lighting,
+        });
+
+        let mut s = serializer.serialize_struct("Scene", 10)?;
         s.serialize_field("model_instances", &instance_map)?;
+        s.serialize_field("lights", &lights)?;
+        s.serialize_field("reflection_probes", &reflection_probes)?;
+        s.serialize_field("planar_reflections", &planar_reflections)?;
+        s.serialize_field("skybox", &skybox)?;
         s.serialize_field("camera", &self.camera)?;
+        s.serialize_field("title", &self.title)?;
+        s.serialize_field("scripts", &self.scripts)?;
+        s.serialize_field("spawn_points", &self.simulation.spawn_points)?;
+        s.serialize_field("tweens", &tweens)?;
 
         s.end()
     }
 }
 
+/// A model instance as it is stored in a scene file. Scenes saved before instances had IDs are
+/// just the transforms, so those instances are given a new ID.
+#[derive(Serialize, Deserialize)]
+struct SavedInstance {
+    #[serde(default)]
+    uuid: UUID,
+    #[serde(flatten)]
+    transform: Transform,
+}
+
+/// A light as it is stored in a scene file
+#[derive(Serialize, Deserialize)]
+struct SavedLight {
+    uuid: UUID,
+    transform: Transform,
+    light: Light,
+}
+
+/// A reflection probe as it is stored in a scene file, captured again once loaded
+#[derive(Serialize, Deserialize)]
+struct SavedReflectionProbe {
+    uuid: UUID,
+    transform: Transform,
+    probe: ReflectionProbe,
+}
+
+/// A planar reflection as it is stored in a scene file
+#[derive(Serialize, Deserialize)]
+struct SavedPlanarReflection {
+    uuid: UUID,
+    transform: Transform,
+    reflection: PlanarReflection,
+}
+
+/// The tweens playing on an entity as they are stored in a scene file
+#[derive(Serialize, Deserialize)]
+struct SavedTweens {
+    uuid: UUID,
+    tweens: Tweens,
+}
+
+/// A skybox as it is stored in a scene file, loaded again from its source
+#[derive(Serialize, Deserialize)]
+struct SavedSkybox {
+    source: SkyboxSource,
+    intensity: f32,
+    image_based_lighting: bool,
+}
+
 struct UnloadedScene {
     pub camera: Camera,
     pub title: String,
-    pub model_paths_to_transforms: HashMap<PathBuf, Vec<Transform>>,
+    pub model_paths_to_instances: HashMap<PathBuf, Vec<SavedInstance>>,
+    pub lights: Vec<SavedLight>,
+    pub reflection_probes: Vec<SavedReflectionProbe>,
+    pub planar_reflections: Vec<SavedPlanarReflection>,
+    pub skybox: Option<SavedSkybox>,
+    pub scripts: Vec<PathBuf>,
+    pub spawn_points: Vec<Point3<f32>>,
+    pub tweens: Vec<SavedTweens>,
 }
 
 impl<'de> Deserialize<'de> for UnloadedScene {
@@ -286,7 +1963,18 @@ impl<'de> Deserialize<'de> for UnloadedScene {
     {
         deserializer.deserialize_struct(
             "UnloadedScene",
-            &["model_paths_to_transforms", "camera", "title"],
+            &[
+                "model_paths_to_transforms",
+                "lights",
+                "reflection_probes",
+                "planar_reflections",
+                "skybox",
+                "camera",
+                "title",
+                "scripts",
+                "spawn_points",
+                "tweens",
+            ],
             UnloadedSceneVisitor,
         )
     }
@@ -308,21 +1996,54 @@ impl<'de> Visitor<'de> for UnloadedSceneVisitor {
         let mut unloaded_scene = UnloadedScene {
             camera: Camera::default(),
             title: String::new(),
-            model_paths_to_transforms: HashMap::new(),
+            model_paths_to_instances: HashMap::new(),
+            lights: vec![],
+            reflection_probes: vec![],
+            planar_reflections: vec![],
+            skybox: None,
+            scripts: vec![],
+            spawn_points: vec![],
+            tweens: vec![],
         };
 
         while let Some(key) = map.next_key::<String>()? {
             match key.as_str() {
                 "model_instances" => {
-                    unloaded_scene.model_paths_to_transforms =
-                        map.next_value::<HashMap<PathBuf, Vec<Transform>>>()?
+                    unloaded_scene.model_paths_to_instances =
+                        map.next_value::<HashMap<PathBuf, Vec<SavedInstance>>>()?
+                }
+                "lights" => unloaded_scene.lights = map.next_value::<Vec<SavedLight>>()?,
+                "reflection_probes" => {
+                    unloaded_scene.reflection_probes =
+                        map.next_value::<Vec<SavedReflectionProbe>>()?
                 }
+                "planar_reflections" => {
+                    unloaded_scene.planar_reflections =
+                        map.next_value::<Vec<SavedPlanarReflection>>()?
+                }
+                "skybox" => unloaded_scene.skybox = map.next_value::<Option<SavedSkybox>>()?,
                 "camera" => unloaded_scene.camera = map.next_value::<Camera>()?,
                 "title" => unloaded_scene.title = map.next_value::<String>()?,
+                "scripts" => unloaded_scene.scripts = map.next_value::<Vec<PathBuf>>()?,
+                "spawn_points" => {
+                    unloaded_scene.spawn_points = map.next_value::<Vec<Point3<f32>>>()?
+                }
+                "tweens" => unloaded_scene.tweens = map.next_value::<Vec<SavedTweens>>()?,
                 _ => {
                     return Err(de::Error::unknown_field(
                         key.as_str(),
-                        &["model_paths_to_transforms", "camera", "title"],
+                        &[
+                            "model_paths_to_transforms",
+                            "lights",
+                            "reflection_probes",
+                            "planar_reflections",
+                            "skybox",
+                            "camera",
+                            "title",
+                            "scripts",
+                            "spawn_points",
+                            "tweens",
+                        ],
                     ))
                 }
             };
@@ -332,9 +2053,92 @@ impl<'de> Visitor<'de> for UnloadedSceneVisitor {
     }
 }
 
+/// Laid out in the order of its fields, as the wgpu backend describes it by offset
+#[repr(C)]
 #[derive(Copy, Clone)]
-struct Instance {
-    transform: [[f32; 4]; 4],
-    transform_normal: [[f32; 4]; 4],
+pub(crate) struct Instance {
+    pub transform: [[f32; 4]; 4],
+    pub transform_normal: [[f32; 4]; 4],
 }
 implement_vertex!(Instance, transform, transform_normal);
+
+// Only made of floats, so without padding and valid for any bytes
+unsafe impl Zeroable for Instance {}
+unsafe impl Pod for Instance {}
+
+/// Instance transforms for one model. Only the first `count` are drawn, so the buffer can be
+/// reused when fewer instances are visible.
+struct InstanceBuffer {
+    buffer: VertexBuffer<Instance>,
+    count: usize,
+}
+
+impl InstanceBuffer {
+    /// Writes `instances` into an existing buffer if they fit, otherwise returns a new buffer to
+    /// replace it with
+    fn update(
+        existing: Option<&mut Self>,
+        display: &Display<WindowSurface>,
+        instances: &[Instance],
+    ) -> Option<Self> {
+        match existing {
+            Some(instance_buffer) if instance_buffer.buffer.len() >= instances.len() => {
+                if !instances.is_empty() {
+                    instance_buffer
+                        .buffer
+                        .slice(0..instances.len())
+                        .unwrap()
+                        .write(instances);
+                }
+                instance_buffer.count = instances.len();
+
+                None
+            }
+            _ if instances.is_empty() => None,
+            _ => Some(Self {
+                buffer: VertexBuffer::dynamic(display, instances).unwrap(),
+                count: instances.len(),
+            }),
+        }
+    }
+}
+
+/// What a view is drawn for, which decides what it keeps of what it saw
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ViewKind {
+    /// The scene's own camera, or the first of `render_views`
+    Main,
+    /// The other cameras of `render_views`, such as other players in split-screen
+    Player,
+    /// Reflection probe faces, planar reflections and portals, which only read the levels of
+    /// detail the others chose
+    Auxiliary,
+}
+
+/// Instances in view this frame, grouped and ordered the way they're drawn
+pub(crate) struct VisibleInstances {
+    /// Nearest first within each model and level of detail
+    pub instances: HashMap<(Arc<Model>, usize), Vec<Instance>>,
+    /// Models and levels of detail by their nearest instance, nearest first
+    pub draw_order: Vec<(Arc<Model>, usize)>,
+    pub skinned: Vec<SkinnedInstance>,
+    /// Furthest first
+    pub transparent: Vec<TransparentInstance>,
+}
+
+/// An instance of a model with blended materials, also drawn with the model's other instances for
+/// its opaque primitives
+pub(crate) struct TransparentInstance {
+    pub model: Arc<Model>,
+    pub level: usize,
+    pub instance: Instance,
+    /// From the camera to the center of the instance's bounds
+    pub distance: f32,
+}
+
+/// An instance of a skinned model along with its pose
+pub(crate) struct SkinnedInstance {
+    pub model: Arc<Model>,
+    pub instance: Instance,
+    pub bones: BonesBlock,
+}
@@ -0,0 +1,448 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use cgmath::{Deg, InnerSpace, Quaternion, Rotation3, Vector3};
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use log::{info, warn};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope, AST};
+
+use crate::context::FileWatcher;
+use crate::events::ActionEvent;
+use crate::input::Input;
+use crate::model::{ModelInstance, Transform};
+use crate::scene::Scene;
+use crate::simulation::Simulation;
+use crate::uuid::UUID;
+
+/// Watched for changes so scripts can be edited while running
+pub const SCRIPT_DIRECTORY: &str = "assets/scripts";
+
+/// Shortest time between calls of a repeating timer, so one can't stall a step
+const MIN_TIMER_INTERVAL: f32 = 0.01;
+
+/// Most operations one call into a script can run before it's stopped, so an endless loop
+/// fails the call rather than hanging the game
+const MAX_SCRIPT_OPERATIONS: u64 = 1_000_000;
+
+/// Actions held, pressed and released since the last fixed step, as presses between steps would
+/// otherwise be missed
+#[derive(Default)]
+struct InputSnapshot {
+    down: HashSet<String>,
+    pressed: HashSet<String>,
+    released: HashSet<String>,
+}
+
+/// Calls a script's function once its time is up, and again every `interval` if it repeats
+struct Timer {
+    script: usize,
+    function: String,
+    remaining: f32,
+    interval: Option<f32>,
+}
+
+/// Everything scripts can reach, lent to them while they run
+#[derive(Default)]
+struct ScriptState {
+    /// The scene's simulation while scripts run, and an empty one to swap it with otherwise
+    simulation: Simulation,
+    input: InputSnapshot,
+    /// Entities scripts gave models to, which are loaded through the scene's assets once they've
+    /// run
+    spawned_models: Vec<(UUID, PathBuf)>,
+    timers: Vec<Timer>,
+    /// Script being run, which owns the timers it starts
+    current_script: usize,
+    /// Seconds simulated since the scripts were loaded
+    time: f64,
+}
+
+struct Script {
+    path: PathBuf,
+    ast: AST,
+    /// `this` in the script's functions, kept when it's reloaded so it can hold the script's
+    /// state
+    this: Dynamic,
+    started: bool,
+}
+
+/// Runs Rhai scripts against a scene, so gameplay can be written and changed without
+/// recompiling. A script defines any of `start()`, called once before its first update, and
+/// `update(deltatime)`, called every fixed step, keeping its state in `this`. Scripts can spawn,
+/// despawn and move entities, query actions and start timers, e.g.
+/// `every(2.0, "spawn_crate")`. Scripts in `SCRIPT_DIRECTORY` are reloaded when they're saved.
+pub struct ScriptHost {
+    engine: Engine,
+    state: Rc<RefCell<ScriptState>>,
+    scripts: Vec<Script>,
+    /// `None` if the script directory can't be watched
+    watcher: Option<FileWatcher>,
+}
+
+impl ScriptHost {
+    pub fn new() -> Self {
+        let state = Rc::new(RefCell::new(ScriptState::default()));
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_SCRIPT_OPERATIONS);
+        engine.on_print(|text| info!("Script: {}", text));
+        register_api(&mut engine, &state);
+
+        let watcher = FileWatcher::new(Path::new(SCRIPT_DIRECTORY))
+            .map_err(|error| warn!("Scripts won't be hot reloaded: {}", error))
+            .ok();
+
+        Self {
+            engine,
+            state,
+            scripts: vec![],
+            watcher,
+        }
+    }
+
+    /// Replaces the running scripts with those at `paths`, e.g. a newly loaded scene's. Scripts
+    /// that don't compile are skipped.
+    pub fn load(&mut self, paths: &[PathBuf]) {
+        self.scripts.clear();
+        {
+            let mut state = self.state.borrow_mut();
+            state.timers.clear();
+            state.time = 0.0;
+        }
+
+        for path in paths {
+            if let Err(error) = self.add(path) {
+                warn!("Could not load script {:?}: {}", path, error);
+            }
+        }
+    }
+
+    /// Starts running another script, whose `start` is called at the next update
+    pub fn add(&mut self, path: &Path) -> Result<()> {
+        let ast = self
+            .engine
+            .compile_file(path.to_owned())
+            .map_err(|error| eyre!("{}", error))?;
+
+        self.scripts.push(Script {
+            path: path.to_owned(),
+            ast,
+            this: Dynamic::from_map(Map::new()),
+            started: false,
+        });
+
+        Ok(())
+    }
+
+    /// Paths of the running scripts
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.scripts.iter().map(|script| script.path.as_path())
+    }
+
+    /// Remembers which actions are held, pressed and released, called every frame before the
+    /// input is reset
+    pub fn capture_input(&mut self, input: &Input) {
+        let snapshot = &mut self.state.borrow_mut().input;
+
+        snapshot.down = input
+            .action_map
+            .actions()
+            .filter(|action| input.action_down(action))
+            .map(str::to_owned)
+            .collect();
+
+        for event in input.action_events() {
+            match event {
+                ActionEvent::Pressed(action) => snapshot.pressed.insert(action),
+                ActionEvent::Released(action) => snapshot.released.insert(action),
+            };
+        }
+    }
+
+    /// Runs every script's `update` and the timers that are due by a fixed step. Scripts have the
+    /// scene's simulation for the length of the call.
+    pub fn update(&mut self, scene: &mut Scene, deltatime: f32) {
+        self.reload_changed();
+
+        {
+            let mut state = self.state.borrow_mut();
+            std::mem::swap(&mut state.simulation, &mut scene.simulation);
+            state.time += deltatime as f64;
+        }
+
+        for index in 0..self.scripts.len() {
+            if !self.scripts[index].started {
+                self.scripts[index].started = true;
+                self.call(index, "start", ());
+            }
+
+            self.call(index, "update", (deltatime,));
+        }
+
+        for (index, function) in self.due_timers(deltatime) {
+            self.call(index, &function, ());
+        }
+
+        let spawned_models = {
+            let mut state = self.state.borrow_mut();
+            std::mem::swap(&mut state.simulation, &mut scene.simulation);
+            state.input.pressed.clear();
+            state.input.released.clear();
+
+            std::mem::take(&mut state.spawned_models)
+        };
+
+        for (entity, path) in spawned_models {
+            match scene.load_model(&path) {
+                Ok(model) => {
+                    scene
+                        .simulation
+                        .world
+                        .insert(entity, ModelInstance::from(model));
+                }
+                Err(error) => warn!("Script could not load {:?}: {}", path, error),
+            }
+        }
+    }
+
+    /// Recompiles scripts whose files have changed, keeping the old version of any that no longer
+    /// compile. Their state in `this` is kept and `start` isn't called again.
+    fn reload_changed(&mut self) {
+        let Some(watcher) = &self.watcher else {
+            return;
+        };
+        let changed = watcher.changed_files();
+        if changed.is_empty() {
+            return;
+        }
+
+        for script in self.scripts.iter_mut() {
+            let Ok(path) = std::fs::canonicalize(&script.path) else {
+                continue;
+            };
+            if !changed.contains(&path) {
+                continue;
+            }
+
+            match self.engine.compile_file(script.path.clone()) {
+                Ok(ast) => {
+                    script.ast = ast;
+                    info!("Reloaded script {:?}", script.path);
+                }
+                Err(error) => warn!(
+                    "Keeping the old {:?}, as it failed to compile: {}",
+                    script.path, error
+                ),
+            }
+        }
+    }
+
+    /// Counts timers down, returning the script and function of each that's due
+    fn due_timers(&mut self, deltatime: f32) -> Vec<(usize, String)> {
+        let mut due = vec![];
+
+        self.state.borrow_mut().timers.retain_mut(|timer| {
+            timer.remaining -= deltatime;
+            if timer.remaining > 0.0 {
+                return true;
+            }
+
+            due.push((timer.script, timer.function.clone()));
+            match timer.interval {
+                Some(interval) => {
+                    timer.remaining += interval;
+                    true
+                }
+                None => false,
+            }
+        });
+
+        due
+    }
+
+    /// Calls a script's function if it defines one, logging any error it runs into
+    fn call(&mut self, index: usize, function: &str, args: impl FuncArgs) {
+        let script = &mut self.scripts[index];
+        if !script
+            .ast
+            .iter_functions()
+            .any(|definition| definition.name == function)
+        {
+            return;
+        }
+
+        self.state.borrow_mut().current_script = index;
+
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut script.this);
+        if let Err(error) = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut Scope::new(),
+            &script.ast,
+            function,
+            args,
+        ) {
+            warn!("Script {:?} failed in {}: {}", script.path, function, error);
+        }
+    }
+}
+
+impl Default for ScriptHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Entities as `Entity` and vectors as `Vec3`, along with the functions scripts can call
+fn register_api(engine: &mut Engine, state: &Rc<RefCell<ScriptState>>) {
+    engine
+        .register_type_with_name::<UUID>("Entity")
+        .register_fn("to_string", |entity: &mut UUID| entity.to_string())
+        .register_fn("==", |a: UUID, b: UUID| a == b);
+
+    engine
+        .register_type_with_name::<Vector3<f32>>("Vec3")
+        .register_fn("vec3", Vector3::<f32>::new)
+        .register_get_set(
+            "x",
+            |vector: &mut Vector3<f32>| vector.x,
+            |vector: &mut Vector3<f32>, x: f32| vector.x = x,
+        )
+        .register_get_set(
+            "y",
+            |vector: &mut Vector3<f32>| vector.y,
+            |vector: &mut Vector3<f32>, y: f32| vector.y = y,
+        )
+        .register_get_set(
+            "z",
+            |vector: &mut Vector3<f32>| vector.z,
+            |vector: &mut Vector3<f32>, z: f32| vector.z = z,
+        )
+        .register_fn("+", |a: Vector3<f32>, b: Vector3<f32>| a + b)
+        .register_fn("-", |a: Vector3<f32>, b: Vector3<f32>| a - b)
+        .register_fn("*", |vector: Vector3<f32>, scale: f32| vector * scale)
+        .register_fn("length", |vector: &mut Vector3<f32>| vector.magnitude())
+        .register_fn("to_string", |vector: &mut Vector3<f32>| {
+            format!("({}, {}, {})", vector.x, vector.y, vector.z)
+        });
+
+    // Registers a function borrowing the shared state for the length of the call
+    macro_rules! register {
+        ($name:expr, |$state:ident $(, $argument:ident: $type:ty)*| $body:expr) => {{
+            let shared = state.clone();
+            engine.register_fn($name, move |$($argument: $type),*| {
+                let mut $state = shared.borrow_mut();
+                $body
+            });
+        }};
+    }
+
+    register!("spawn", |state| {
+        let entity = state.simulation.spawn();
+        state.simulation.world.insert(entity, Transform::default());
+        entity
+    });
+    register!(
+        "spawn_model",
+        |state, path: &str, position: Vector3<f32>| {
+            let entity = state.simulation.spawn();
+            state.simulation.world.insert(
+                entity,
+                Transform {
+                    translation: position,
+                    ..Transform::default()
+                },
+            );
+            state.spawned_models.push((entity, PathBuf::from(path)));
+            entity
+        }
+    );
+    register!("despawn", |state, entity: UUID| state
+        .simulation
+        .despawn(entity));
+
+    register!("position", |state, entity: UUID| {
+        transform(&state.simulation, entity).map(|transform| transform.translation)
+    });
+    register!(
+        "set_position",
+        |state, entity: UUID, position: Vector3<f32>| {
+            let transform = transform(&state.simulation, entity)?;
+            state.simulation.set_transform(
+                entity,
+                Transform {
+                    translation: position,
+                    ..transform
+                },
+            );
+            Ok::<_, Box<EvalAltResult>>(())
+        }
+    );
+    register!("rotate_y", |state, entity: UUID, degrees: f32| {
+        let transform = transform(&state.simulation, entity)?;
+        state.simulation.set_transform(
+            entity,
+            Transform {
+                rotation: Quaternion::from_angle_y(Deg(degrees)) * transform.rotation,
+                ..transform
+            },
+        );
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    register!("scale", |state, entity: UUID| {
+        transform(&state.simulation, entity).map(|transform| transform.scale)
+    });
+    register!("set_scale", |state, entity: UUID, scale: Vector3<f32>| {
+        let transform = transform(&state.simulation, entity)?;
+        state
+            .simulation
+            .set_transform(entity, Transform { scale, ..transform });
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+
+    register!("action_down", |state, action: &str| state
+        .input
+        .down
+        .contains(action));
+    register!("action_pressed", |state, action: &str| state
+        .input
+        .pressed
+        .contains(action));
+    register!("action_released", |state, action: &str| state
+        .input
+        .released
+        .contains(action));
+
+    register!("after", |state, seconds: f32, function: &str| {
+        let script = state.current_script;
+        state.timers.push(Timer {
+            script,
+            function: function.to_owned(),
+            remaining: seconds,
+            interval: None,
+        });
+    });
+    register!("every", |state, seconds: f32, function: &str| {
+        let script = state.current_script;
+        let interval = seconds.max(MIN_TIMER_INTERVAL);
+        state.timers.push(Timer {
+            script,
+            function: function.to_owned(),
+            remaining: interval,
+            interval: Some(interval),
+        });
+    });
+    register!("time", |state| state.time as f32);
+}
+
+fn transform(simulation: &Simulation, entity: UUID) -> Result<Transform, Box<EvalAltResult>> {
+    simulation
+        .world
+        .get::<Transform>(entity)
+        .cloned()
+        .ok_or_else(|| format!("Entity {} has no transform", entity).into())
+}
@@ -0,0 +1,70 @@
+use std::path::Path;
+
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::postprocess::Tonemapping;
+
+/// Graphics settings, falling back to the defaults if missing
+pub const GRAPHICS_SETTINGS_PATH: &str = "assets/config/graphics.toml";
+
+/// Rendering quality options. The window's sample count and vsync are chosen when it is created, so
+/// apart from turning MSAA off, changing them only takes effect after a restart.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    /// Samples per pixel of the window's framebuffer, 0 to turn MSAA off
+    pub msaa_samples: u8,
+    pub vsync: bool,
+    /// Highest anisotropy used to sample material textures, 1 to turn anisotropic filtering off
+    pub anisotropy: u16,
+    /// Width and height of each face reflection probes capture
+    pub reflection_resolution: u32,
+    /// Glow around bright parts of the scene
+    pub bloom: bool,
+    pub tonemapping: Tonemapping,
+    /// Smooths jagged edges after the scene is drawn, as the off-screen target isn't multisampled
+    pub fxaa: bool,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        Self {
+            msaa_samples: 4,
+            vsync: true,
+            anisotropy: 8,
+            reflection_resolution: 256,
+            bloom: true,
+            tonemapping: Tonemapping::default(),
+            fxaa: true,
+        }
+    }
+}
+
+impl GraphicsSettings {
+    /// Sample counts offered in the editor
+    pub const MSAA_SAMPLES: [u8; 4] = [0, 2, 4, 8];
+    pub const ANISOTROPY_LEVELS: [u16; 5] = [1, 2, 4, 8, 16];
+    pub const REFLECTION_RESOLUTIONS: [u32; 4] = [64, 128, 256, 512];
+
+    /// Reads settings from a TOML file, keeping the defaults of any left out
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// Whether draws to the window should be multisampled
+    pub fn multisampling(&self) -> bool {
+        self.msaa_samples > 0
+    }
+
+    /// Whether the scene is drawn off-screen and post-processed before being shown
+    pub fn post_processing(&self) -> bool {
+        self.bloom || self.fxaa || self.tonemapping != Tonemapping::Off
+    }
+}
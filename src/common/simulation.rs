@@ -0,0 +1,631 @@
+use std::sync::Arc;
+
+use cgmath::{InnerSpace, Matrix, Matrix4, Point3, SquareMatrix, Vector3};
+use itertools::Itertools;
+
+use crate::animation::AnimationPlayer;
+use crate::animator::{AnimatorController, SPEED_PARAMETER};
+use crate::entity::World;
+use crate::hierarchy;
+use crate::hierarchy::{GlobalTransform, Hierarchy, Parent};
+use crate::light::Light;
+use crate::map::Map;
+use crate::maths::{Aabb, Ray};
+use crate::model::{Model, ModelInstance, PreviousTransform, Transform};
+use crate::navigation::NavGrid;
+use crate::physics::{Collider, PhysicsWorld};
+use crate::pool::{EntityPools, Lifetime};
+use crate::portal::PortalPair;
+use crate::profiling;
+use crate::spatial::Bvh;
+use crate::tween;
+use crate::uuid::UUID;
+
+/// Where a ray hit an entity
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RaycastHit {
+    pub entity: UUID,
+    /// Along the ray in multiples of its direction, so in world units if it was normalized
+    pub distance: f32,
+    /// World space normal of the surface that was hit
+    pub normal: Vector3<f32>,
+}
+
+/// Everything about a scene that runs without a window: its entities, physics and level data.
+/// Scenes draw one, while dedicated servers and tests run one on its own.
+#[derive(Default)]
+pub struct Simulation {
+    /// Every entity, such as model instances and lights, with its components
+    pub world: World,
+    /// Moves entities with a `RigidBody` and collides them with those with a `Collider`
+    pub physics: PhysicsWorld,
+    pub portals: Vec<PortalPair>,
+    /// Static level geometry that things collide with
+    pub colliders: Vec<Aabb>,
+    pub navigation: Option<NavGrid>,
+    pub spawn_points: Vec<Point3<f32>>,
+    /// Keeps the world matrices of parented entities up to date
+    pub hierarchy: Hierarchy,
+    /// World bounding boxes of model instances for raycasts, culling and overlap queries, as of
+    /// the last `update_bounds`
+    pub bvh: Bvh,
+    /// Seconds simulated by `step`
+    pub time: f64,
+    /// Entities parked by `recycle` for `acquire` to reuse
+    pub pools: EntityPools,
+    /// Entities despawned or recycled since the last `take_despawned`, in the order they went
+    despawned: Vec<UUID>,
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The parts of a map that don't need drawing, its collision, navigation, spawn points and
+    /// portals. Its models are left out, as they can only be loaded with a display.
+    pub fn from_map(map: &Map) -> Self {
+        let mut simulation = Self::new();
+
+        simulation.add_colliders(map.brushes.iter().chain(&map.colliders).copied());
+        simulation.navigation = map.navigation.clone();
+        simulation.spawn_points = map.spawn_points.clone();
+        simulation.portals = map.portals.clone();
+
+        simulation
+    }
+
+    /// Advances everything that moves on its own by a fixed step, keeping the previous transforms
+    /// to interpolate from
+    pub fn step(&mut self, deltatime: f64) {
+        self.store_previous_transforms();
+        self.update_lifetimes(deltatime as f32);
+        self.update_tweens(deltatime as f32);
+        {
+            let _scope = profiling::scope("Physics");
+            self.step_physics(deltatime as f32);
+        }
+        self.update_animations(deltatime as f32);
+        self.update_hierarchy();
+        self.update_bounds();
+
+        self.time += deltatime;
+    }
+
+    /// Creates an entity without any components
+    pub fn spawn(&mut self) -> UUID {
+        self.world.spawn()
+    }
+
+    /// Gives an entity the components to draw `model`. Models with idle and run clips are
+    /// animated as characters, others play their first animation if they have any.
+    pub fn insert_model(&mut self, entity: UUID, model: Arc<Model>, transform: Transform) {
+        if let Some(animator) = AnimatorController::character(&model) {
+            self.world.insert(entity, animator);
+        } else if !model.animations.is_empty() {
+            self.world.insert(entity, AnimationPlayer::new(0));
+        }
+
+        self.world.insert(entity, ModelInstance::from(model));
+        self.world.insert(entity, transform);
+    }
+
+    /// Removes an entity, its children and all of their components, returning whether it existed.
+    /// Their physics bodies go straight away.
+    pub fn despawn(&mut self, entity: UUID) -> bool {
+        let children = hierarchy::children(&self.world, entity).collect_vec();
+        for child in children {
+            self.despawn(child);
+        }
+
+        self.forget(entity);
+        self.world.despawn(entity)
+    }
+
+    /// An entity from `pool`, reusing one that was recycled if there is one. Reused entities keep
+    /// their components, so every one it needs should be inserted again.
+    pub fn acquire(&mut self, pool: &'static str) -> UUID {
+        let entity = self.pools.acquire(&mut self.world, pool);
+        // So it isn't drawn sliding from where it was last used
+        self.world.remove::<PreviousTransform>(entity);
+
+        entity
+    }
+
+    /// Parks an entity and its children in their pools for `acquire` to reuse, or despawns those
+    /// that aren't pooled. Returns whether the entity existed.
+    pub fn recycle(&mut self, entity: UUID) -> bool {
+        let children = hierarchy::children(&self.world, entity).collect_vec();
+        for child in children {
+            self.recycle(child);
+        }
+
+        if !self.world.contains(entity) {
+            return false;
+        }
+
+        self.forget(entity);
+        if !self.pools.release(&mut self.world, entity) {
+            self.world.despawn(entity);
+        }
+
+        true
+    }
+
+    /// Counts down every `Lifetime`, recycling the entities whose time is up
+    pub fn update_lifetimes(&mut self, deltatime: f32) {
+        let mut expired = vec![];
+        for (entity, lifetime) in self.world.query_mut::<Lifetime>() {
+            lifetime.remaining -= deltatime;
+            if lifetime.remaining <= 0.0 {
+                expired.push(entity);
+            }
+        }
+
+        for entity in expired {
+            self.recycle(entity);
+        }
+    }
+
+    /// Plays every entity's `Tweens`, before physics so kinematic bodies like elevators follow
+    pub fn update_tweens(&mut self, deltatime: f32) {
+        tween::update(&mut self.world, deltatime);
+    }
+
+    /// Entities despawned or recycled since the last call, in the order they went, for systems
+    /// outside the simulation to clean up after, e.g. sounds following them
+    pub fn take_despawned(&mut self) -> Vec<UUID> {
+        std::mem::take(&mut self.despawned)
+    }
+
+    /// Lets go of what's kept for an entity outside the world, as it's about to leave it
+    fn forget(&mut self, entity: UUID) {
+        if self.world.contains(entity) {
+            self.physics.remove_entity(entity);
+            self.despawned.push(entity);
+        }
+    }
+
+    /// Makes `child` follow `parent`, or one of the nodes of its model, staying where it is in the
+    /// world. Returns false without attaching if `child` is `parent` or one of its parents.
+    pub fn attach(&mut self, child: UUID, parent: UUID, node: Option<usize>) -> bool {
+        if hierarchy::is_ancestor(&self.world, child, parent) {
+            return false;
+        }
+
+        self.update_hierarchy();
+
+        let parent = Parent {
+            entity: parent,
+            node,
+        };
+        if let Some(global) = self.global_matrix(child) {
+            let parent_matrix = hierarchy::parent_matrix(&self.world, parent);
+            let local = parent_matrix.invert().unwrap_or(Matrix4::identity()) * global;
+
+            self.world.insert(child, Transform::from_matrix(local));
+        }
+        self.world.insert(child, parent);
+
+        self.update_hierarchy();
+
+        true
+    }
+
+    /// Stops `child` following its parent, staying where it is in the world. Returns whether it
+    /// had a parent.
+    pub fn detach(&mut self, child: UUID) -> bool {
+        self.update_hierarchy();
+
+        let global = self.global_matrix(child);
+        if self.world.remove::<Parent>(child).is_none() {
+            return false;
+        }
+        self.world.remove::<GlobalTransform>(child);
+
+        if let Some(global) = global {
+            self.world.insert(child, Transform::from_matrix(global));
+        }
+
+        true
+    }
+
+    /// Places an entity, moving its physics body along with it so physics doesn't put it back
+    pub fn set_transform(&mut self, entity: UUID, transform: Transform) {
+        self.physics.teleport(entity, &transform);
+        self.world.insert(entity, transform);
+    }
+
+    /// World matrix of an entity, taking its parents into account
+    pub fn global_matrix(&self, entity: UUID) -> Option<Matrix4<f32>> {
+        hierarchy::global_matrix(&self.world, entity)
+    }
+
+    /// Works out the world matrices of parented entities whose transforms have changed
+    pub fn update_hierarchy(&mut self) {
+        self.hierarchy.update(&mut self.world);
+    }
+
+    /// Refits the `bvh` around every model instance where it is now. Unparented instances' boxes
+    /// also cover where they were at the last step, so they hold wherever drawing interpolates to.
+    pub fn update_bounds(&mut self) {
+        let bounds = self
+            .world
+            .query::<ModelInstance>()
+            .filter_map(|(entity, model_instance)| {
+                let model_bounds = &model_instance.model.bounds;
+                let bounds = model_bounds.transformed(self.global_matrix(entity)?);
+
+                let previous = match self.world.get::<PreviousTransform>(entity) {
+                    Some(PreviousTransform(previous)) if !self.world.has::<Parent>(entity) => {
+                        Some(model_bounds.transformed(Matrix4::from(previous.clone())))
+                    }
+                    _ => None,
+                };
+
+                Some((
+                    entity,
+                    previous.map_or(bounds, |previous| bounds.union(&previous)),
+                ))
+            })
+            .collect_vec();
+
+        let world = &self.world;
+        self.bvh.retain(|entity| world.has::<ModelInstance>(entity));
+        for (entity, bounds) in bounds {
+            self.bvh.update(entity, bounds);
+        }
+    }
+
+    /// Model instances whose world bounding boxes overlap `aabb`
+    pub fn overlapping(&self, aabb: &Aabb) -> Vec<UUID> {
+        self.bvh.query_aabb(aabb)
+    }
+
+    /// Creates an entity that lights the scene from `transform`
+    pub fn spawn_light(&mut self, light: Light, transform: Transform) -> UUID {
+        let entity = self.world.spawn();
+        self.world.insert(entity, light);
+        self.world.insert(entity, transform);
+
+        entity
+    }
+
+    /// Adds static level collision, giving each box an entity with a `Collider` so physics bodies
+    /// collide with it too
+    pub fn add_colliders(&mut self, colliders: impl IntoIterator<Item = Aabb>) {
+        for collider in colliders {
+            let entity = self.world.spawn();
+            self.world.insert(
+                entity,
+                Collider::from_aabb(&collider, Vector3::new(1.0, 1.0, 1.0)),
+            );
+            self.world.insert(entity, Transform::default());
+
+            self.colliders.push(collider);
+        }
+    }
+
+    /// Advances physics, moving the `Transform` of every entity with a dynamic `RigidBody`
+    pub fn step_physics(&mut self, deltatime: f32) {
+        self.physics.step(&mut self.world, deltatime);
+    }
+
+    /// Advances every entity's `AnimationPlayer` and `AnimatorController`, setting each
+    /// controller's speed from how far its entity moved this step
+    pub fn update_animations(&mut self, deltatime: f32) {
+        self.world
+            .for_each2_mut::<AnimationPlayer, ModelInstance>(|_, player, model_instance| {
+                player.update(deltatime, &model_instance.model);
+            });
+
+        let speeds = self
+            .world
+            .query2::<Transform, PreviousTransform>()
+            .filter(|(entity, _, _)| self.world.has::<AnimatorController>(*entity))
+            .map(|(entity, transform, PreviousTransform(previous))| {
+                let moved = transform.translation - previous.translation;
+                let speed = Vector3::new(moved.x, 0.0, moved.z).magnitude() / deltatime;
+                (entity, speed)
+            })
+            .collect_vec();
+        for (entity, speed) in speeds {
+            if let Some(animator) = self.world.get_mut::<AnimatorController>(entity) {
+                animator.set_float(SPEED_PARAMETER, speed);
+            }
+        }
+
+        self.world
+            .for_each2_mut::<AnimatorController, ModelInstance>(|_, animator, model_instance| {
+                animator.update(deltatime, &model_instance.model);
+            });
+    }
+
+    /// Pose of an entity's skeleton from its `AnimatorController` or `AnimationPlayer`, falling
+    /// back to the rest pose. `None` if it has no skinned model.
+    pub fn pose(&self, entity: UUID) -> Option<Vec<Transform>> {
+        let model = &self.world.get::<ModelInstance>(entity)?.model;
+        let skeleton = model.skeleton.as_ref()?;
+
+        self.world
+            .get::<AnimatorController>(entity)
+            .and_then(|animator| animator.pose(model))
+            .or_else(|| {
+                self.world
+                    .get::<AnimationPlayer>(entity)
+                    .and_then(|player| player.pose(model))
+            })
+            .or_else(|| Some(skeleton.rest_pose()))
+    }
+
+    /// Remembers the current transform of every entity, to be called before each simulation step
+    /// so rendering can interpolate from it
+    pub fn store_previous_transforms(&mut self) {
+        let transforms = self
+            .world
+            .query::<Transform>()
+            .map(|(entity, transform)| (entity, transform.clone()))
+            .collect_vec();
+
+        for (entity, transform) in transforms {
+            self.world.insert(entity, PreviousTransform(transform));
+        }
+    }
+
+    /// The closest model instance a ray hits. Instances are tested as their bounding boxes, or as
+    /// their triangles with `triangles` if their box is hit. Skinned models are tested in their
+    /// rest pose, and only instances in the `bvh` are tested.
+    pub fn raycast(&self, ray: &Ray, triangles: bool) -> Option<RaycastHit> {
+        self.raycast_filtered(ray, triangles, |_| true)
+    }
+
+    /// `raycast` that only tests the entities `filter` returns true for
+    pub fn raycast_filtered(
+        &self,
+        ray: &Ray,
+        triangles: bool,
+        filter: impl Fn(UUID) -> bool,
+    ) -> Option<RaycastHit> {
+        let mut closest: Option<RaycastHit> = None;
+
+        for (entry, entity) in self.bvh.raycast(ray) {
+            // Boxes are nearest first, and nothing can be hit before the ray enters its box
+            if closest.is_some_and(|closest| closest.distance <= entry) {
+                break;
+            }
+            if !filter(entity) {
+                continue;
+            }
+            let Some(model_instance) = self.world.get::<ModelInstance>(entity) else {
+                continue;
+            };
+
+            let Some(inverse) = self
+                .global_matrix(entity)
+                .and_then(|matrix| matrix.invert())
+            else {
+                continue;
+            };
+
+            // Transforming the ray rather than every triangle keeps distances in world space
+            let local_ray = ray.transformed(inverse);
+            let model = &model_instance.model;
+
+            let Some(bounds_hit) = model.bounds.intersect_ray(&local_ray) else {
+                continue;
+            };
+
+            let hit = if triangles {
+                model
+                    .meshes
+                    .iter()
+                    .flat_map(|mesh| mesh.primitives.iter())
+                    .filter(|primitive| primitive.bounds.intersect_ray(&local_ray).is_some())
+                    .flat_map(|primitive| primitive.triangles())
+                    .filter_map(|triangle| local_ray.intersect_triangle(triangle))
+                    .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            } else {
+                Some(bounds_hit)
+            };
+
+            let Some((distance, normal)) = hit else {
+                continue;
+            };
+
+            if closest.is_some_and(|closest| closest.distance <= distance) {
+                continue;
+            }
+
+            closest = Some(RaycastHit {
+                entity,
+                distance,
+                normal: (inverse.transpose() * normal.extend(0.0))
+                    .truncate()
+                    .normalize(),
+            });
+        }
+
+        closest
+    }
+
+    /// Every entity with a `T`
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = (UUID, &T)> {
+        self.world.query::<T>()
+    }
+
+    pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = (UUID, &mut T)> {
+        self.world.query_mut::<T>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::{BodyKind, ColliderShape, RigidBody};
+    use crate::pool::Lifetime;
+    use crate::tween::{Tween, Tweens};
+
+    const STEP: f64 = 1.0 / 60.0;
+
+    fn spawn_ball(simulation: &mut Simulation, translation: Vector3<f32>) -> UUID {
+        let entity = simulation.spawn();
+        simulation.world.insert(
+            entity,
+            Transform {
+                translation,
+                ..Transform::default()
+            },
+        );
+        simulation
+            .world
+            .insert(entity, Collider::new(ColliderShape::Ball { radius: 0.5 }));
+        simulation
+            .world
+            .insert(entity, RigidBody::new(BodyKind::Dynamic));
+
+        entity
+    }
+
+    #[test]
+    fn step_counts_simulated_time() {
+        let mut simulation = Simulation::new();
+        for _ in 0..60 {
+            simulation.step(STEP);
+        }
+
+        assert!((simulation.time - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn step_keeps_previous_transforms() {
+        let mut simulation = Simulation::new();
+        let ball = spawn_ball(&mut simulation, Vector3::new(0.0, 10.0, 0.0));
+
+        simulation.step(STEP);
+        let after_first = simulation.world.get::<Transform>(ball).unwrap().clone();
+        simulation.step(STEP);
+
+        let PreviousTransform(previous) = simulation.world.get::<PreviousTransform>(ball).unwrap();
+        assert_eq!(previous.translation, after_first.translation);
+    }
+
+    #[test]
+    fn dynamic_bodies_fall_without_a_window() {
+        let mut simulation = Simulation::new();
+        let ball = spawn_ball(&mut simulation, Vector3::new(0.0, 10.0, 0.0));
+
+        for _ in 0..30 {
+            simulation.step(STEP);
+        }
+
+        let transform = simulation.world.get::<Transform>(ball).unwrap();
+        assert!(transform.translation.y < 10.0);
+    }
+
+    #[test]
+    fn dynamic_bodies_land_on_map_colliders() {
+        let mut simulation = Simulation::new();
+        simulation.add_colliders([Aabb::new(
+            Point3::new(-10.0, -1.0, -10.0),
+            Point3::new(10.0, 0.0, 10.0),
+        )]);
+        let ball = spawn_ball(&mut simulation, Vector3::new(0.0, 2.0, 0.0));
+
+        for _ in 0..240 {
+            simulation.step(STEP);
+        }
+
+        let transform = simulation.world.get::<Transform>(ball).unwrap();
+        assert!(transform.translation.y > 0.0 && transform.translation.y < 1.0);
+    }
+
+    #[test]
+    fn expired_lifetimes_are_despawned() {
+        let mut simulation = Simulation::new();
+        let entity = simulation.spawn();
+        simulation.world.insert(entity, Lifetime::new(0.1));
+
+        for _ in 0..3 {
+            simulation.step(STEP);
+        }
+        assert!(simulation.world.contains(entity));
+
+        for _ in 0..4 {
+            simulation.step(STEP);
+        }
+        assert!(!simulation.world.contains(entity));
+        assert_eq!(simulation.take_despawned(), vec![entity]);
+        assert!(simulation.take_despawned().is_empty());
+    }
+
+    #[test]
+    fn step_plays_tweens() {
+        let mut simulation = Simulation::new();
+        let entity = simulation.spawn();
+        simulation.world.insert(entity, Transform::default());
+        simulation
+            .world
+            .insert(entity, Tweens::from(Tween::bob(1.0, 2.0)));
+
+        for _ in 0..30 {
+            simulation.step(STEP);
+        }
+
+        let transform = simulation.world.get::<Transform>(entity).unwrap();
+        assert!(transform.translation.y > 0.0);
+    }
+
+    fn spawn_at(simulation: &mut Simulation, translation: Vector3<f32>) -> UUID {
+        let entity = simulation.spawn();
+        simulation.world.insert(
+            entity,
+            Transform {
+                translation,
+                ..Transform::default()
+            },
+        );
+
+        entity
+    }
+
+    #[test]
+    fn children_follow_a_moved_grandparent() {
+        let mut simulation = Simulation::new();
+        let grandparent = spawn_at(&mut simulation, Vector3::new(1.0, 0.0, 0.0));
+        let parent = spawn_at(&mut simulation, Vector3::new(1.0, 1.0, 0.0));
+        let child = spawn_at(&mut simulation, Vector3::new(1.0, 1.0, 1.0));
+        assert!(simulation.attach(parent, grandparent, None));
+        assert!(simulation.attach(child, parent, None));
+
+        simulation.set_transform(
+            grandparent,
+            Transform {
+                translation: Vector3::new(5.0, 0.0, 0.0),
+                ..Transform::default()
+            },
+        );
+        simulation.update_hierarchy();
+
+        let global = simulation.global_matrix(child).unwrap();
+        assert_eq!(global.w.truncate(), Vector3::new(5.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn attaching_to_a_collapsed_parent_stays_finite() {
+        let mut simulation = Simulation::new();
+        let parent = simulation.spawn();
+        simulation.world.insert(
+            parent,
+            Transform {
+                scale: Vector3::new(0.0, 1.0, 1.0),
+                ..Transform::default()
+            },
+        );
+        let child = spawn_at(&mut simulation, Vector3::new(0.0, 1.0, 0.0));
+        assert!(simulation.attach(child, parent, None));
+        assert!(simulation.detach(child));
+
+        let transform = simulation.world.get::<Transform>(child).unwrap();
+        assert!(transform.rotation.s.is_finite() && transform.rotation.v.x.is_finite());
+    }
+}
@@ -0,0 +1,341 @@
+use std::f32::consts::PI;
+use std::path::PathBuf;
+
+use cgmath::{InnerSpace, Matrix3, Matrix4, Vector3};
+use color_eyre::eyre::ensure;
+use color_eyre::Result;
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::glutin::surface::WindowSurface;
+use glium::index::PrimitiveType;
+use glium::texture::{
+    CubeLayer, Cubemap, MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat,
+};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler};
+use glium::{
+    implement_vertex, uniform, Depth, DepthTest, Display, DrawParameters, IndexBuffer, Program,
+    Surface, VertexBuffer,
+};
+use image::Rgb32FImage;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::{Assets, Handle};
+use crate::camera::Camera;
+use crate::maths;
+
+/// Cubemap faces in the order OpenGL numbers them
+pub(crate) const LAYERS: [CubeLayer; 6] = [
+    CubeLayer::PositiveX,
+    CubeLayer::NegativeX,
+    CubeLayer::PositiveY,
+    CubeLayer::NegativeY,
+    CubeLayer::PositiveZ,
+    CubeLayer::NegativeZ,
+];
+
+/// Faces made from equirectangular images are capped at this size
+const MAX_FACE_SIZE: u32 = 1024;
+
+/// Images a skybox can be loaded from
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SkyboxSource {
+    /// One image per face, ordered +X, -X, +Y, -Y, +Z, -Z
+    Faces([PathBuf; 6]),
+    /// A single panorama covering every direction, such as an HDR environment map
+    Equirectangular(PathBuf),
+}
+
+/// Surroundings drawn behind everything in a scene, which can also light PBR materials
+pub struct Skybox {
+    pub source: SkyboxSource,
+    /// Multiplies the colors of the images
+    pub intensity: f32,
+    /// Replaces the environment's flat ambient light in PBR shading with light from the skybox
+    pub image_based_lighting: bool,
+    /// Decoded faces, kept until the first frame drawn with OpenGL makes `cubemap` from them
+    faces: Vec<Rgb32FImage>,
+    /// Each mipmap level is a blurrier version of the one before it, for rougher reflections
+    cubemap: Option<Cubemap>,
+}
+
+impl Skybox {
+    pub fn load(source: SkyboxSource) -> Result<Self> {
+        debug!("Loading skybox {:?}...", source);
+
+        let faces = match &source {
+            SkyboxSource::Faces(paths) => paths
+                .iter()
+                .map(|path| Ok(image::open(path)?.to_rgb32f()))
+                .collect::<Result<Vec<Rgb32FImage>>>()?,
+            SkyboxSource::Equirectangular(path) => {
+                equirectangular_to_faces(&image::open(path)?.to_rgb32f())
+            }
+        };
+
+        let size = faces[0].width();
+        ensure!(
+            faces
+                .iter()
+                .all(|face| face.width() == size && face.height() == size),
+            "Skybox faces must be square and all the same size"
+        );
+
+        Ok(Self {
+            source,
+            intensity: 1.0,
+            image_based_lighting: true,
+            faces,
+            cubemap: None,
+        })
+    }
+
+    /// Makes the cubemap from the decoded faces, once. If that fails the skybox isn't drawn.
+    pub(crate) fn upload(&mut self, display: &Display<WindowSurface>) -> Result<()> {
+        if self.faces.is_empty() {
+            return Ok(());
+        }
+        let faces = std::mem::take(&mut self.faces);
+        let size = faces[0].width();
+
+        let cubemap = Cubemap::empty_with_format(
+            display,
+            UncompressedFloatFormat::F16F16F16,
+            MipmapsOption::EmptyMipmaps,
+            size,
+        )?;
+
+        for (face, layer) in faces.into_iter().zip(LAYERS) {
+            // Rows are uploaded top first, which is how cubemap faces are laid out
+            let face = Texture2d::with_format(
+                display,
+                RawImage2d::from_raw_rgb(face.into_raw(), (size, size)),
+                UncompressedFloatFormat::F16F16F16,
+                MipmapsOption::NoMipmap,
+            )?;
+
+            // Shrinking the whole face into each level blurs it enough for rough reflections
+            for level in 0..cubemap.get_mipmap_levels() {
+                let framebuffer =
+                    SimpleFrameBuffer::new(display, cubemap.mipmap(level).unwrap().image(layer))?;
+                face.as_surface()
+                    .fill(&framebuffer, MagnifySamplerFilter::Linear);
+            }
+        }
+
+        self.cubemap = Some(cubemap);
+
+        Ok(())
+    }
+
+    /// Number of mipmap levels past the first, which the shaders pick between by roughness
+    pub fn max_mipmap_level(&self) -> f32 {
+        self.cubemap
+            .as_ref()
+            .map_or(0.0, |cubemap| (cubemap.get_mipmap_levels() - 1) as f32)
+    }
+
+    fn sampled(&self) -> Option<Sampler<Cubemap>> {
+        self.cubemap.as_ref().map(sampled_cubemap)
+    }
+}
+
+/// Samples between mipmap levels, as the shaders pick them by roughness
+pub(crate) fn sampled_cubemap(cubemap: &Cubemap) -> Sampler<Cubemap> {
+    cubemap
+        .sampled()
+        .minify_filter(MinifySamplerFilter::LinearMipmapLinear)
+        .magnify_filter(MagnifySamplerFilter::Linear)
+}
+
+/// A single black texel on every face, to bind where a cubemap is expected but there isn't one
+pub(crate) fn black_cubemap(display: &Display<WindowSurface>) -> Result<Cubemap> {
+    let cubemap = Cubemap::empty_with_format(
+        display,
+        UncompressedFloatFormat::F16F16F16,
+        MipmapsOption::NoMipmap,
+        1,
+    )?;
+    for layer in LAYERS {
+        SimpleFrameBuffer::new(display, cubemap.main_level().image(layer))?
+            .clear_color(0.0, 0.0, 0.0, 1.0);
+    }
+
+    Ok(cubemap)
+}
+
+/// Resamples a panorama, with the top of the image straight up and its center facing -Z, into
+/// cubemap faces in the order of `LAYERS`
+fn equirectangular_to_faces(panorama: &Rgb32FImage) -> Vec<Rgb32FImage> {
+    let size = (panorama.width() / 4).clamp(1, MAX_FACE_SIZE);
+
+    LAYERS
+        .iter()
+        .map(|layer| {
+            Rgb32FImage::from_fn(size, size, |x, y| {
+                // Face coordinates from -1 to 1, right and down when looking at the face
+                let s = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+                let t = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+
+                let direction = match layer {
+                    CubeLayer::PositiveX => Vector3::new(1.0, -t, -s),
+                    CubeLayer::NegativeX => Vector3::new(-1.0, -t, s),
+                    CubeLayer::PositiveY => Vector3::new(s, 1.0, t),
+                    CubeLayer::NegativeY => Vector3::new(s, -1.0, -t),
+                    CubeLayer::PositiveZ => Vector3::new(s, -t, 1.0),
+                    CubeLayer::NegativeZ => Vector3::new(-s, -t, -1.0),
+                }
+                .normalize();
+
+                let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+                let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+
+                sample_bilinear(panorama, u, v)
+            })
+        })
+        .collect()
+}
+
+/// Samples an image at texture coordinates from 0 to 1, wrapping horizontally
+fn sample_bilinear(image: &Rgb32FImage, u: f32, v: f32) -> image::Rgb<f32> {
+    let (width, height) = image.dimensions();
+
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fraction_x, fraction_y) = (x - x0, y - y0);
+
+    let column = |x: f32| (x as i64).rem_euclid(width as i64) as u32;
+    let row = |y: f32| (y as u32).min(height - 1);
+
+    let texel = |x: f32, y: f32| Vector3::from(image.get_pixel(column(x), row(y)).0);
+
+    let top = texel(x0, y0) * (1.0 - fraction_x) + texel(x0 + 1.0, y0) * fraction_x;
+    let bottom = texel(x0, y0 + 1.0) * (1.0 - fraction_x) + texel(x0 + 1.0, y0 + 1.0) * fraction_x;
+
+    image::Rgb((top * (1.0 - fraction_y) + bottom * fraction_y).into())
+}
+
+#[derive(Copy, Clone)]
+struct SkyboxVertex {
+    position: [f32; 3],
+}
+implement_vertex!(SkyboxVertex, position);
+
+/// Draws a scene's skybox and provides the environment map uniforms for PBR shading
+pub struct SkyboxRenderer {
+    program: Handle<Program>,
+    cube: VertexBuffer<SkyboxVertex>,
+    indices: IndexBuffer<u16>,
+    /// Bound as the environment map when there's no skybox, as every sampler needs a texture
+    placeholder: Cubemap,
+}
+
+impl SkyboxRenderer {
+    pub fn new(assets: &mut Assets, display: &Display<WindowSurface>) -> Result<Self> {
+        let program = assets.load_program(
+            "assets/shaders/skybox/skybox.vert",
+            "assets/shaders/skybox/skybox.frag",
+            display,
+        )?;
+
+        let corners = [
+            [-1.0, -1.0, -1.0],
+            [1.0, -1.0, -1.0],
+            [1.0, 1.0, -1.0],
+            [-1.0, 1.0, -1.0],
+            [-1.0, -1.0, 1.0],
+            [1.0, -1.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [-1.0, 1.0, 1.0],
+        ];
+        let cube = VertexBuffer::new(display, &corners.map(|position| SkyboxVertex { position }))?;
+
+        // Seen from inside, so face culling is left off rather than winding these carefully
+        let indices = IndexBuffer::new(
+            display,
+            PrimitiveType::TrianglesList,
+            &[
+                0_u16, 1, 2, 0, 2, 3, // -Z
+                4, 6, 5, 4, 7, 6, // +Z
+                0, 3, 7, 0, 7, 4, // -X
+                1, 5, 6, 1, 6, 2, // +X
+                3, 2, 6, 3, 6, 7, // +Y
+                0, 4, 5, 0, 5, 1, // -Y
+            ],
+        )?;
+
+        Ok(Self {
+            program,
+            cube,
+            indices,
+            placeholder: black_cubemap(display)?,
+        })
+    }
+
+    /// Draws the skybox wherever nothing has been drawn yet, so it should come after the scene's
+    /// models with their depth still in the target
+    pub fn render<S: Surface>(
+        &self,
+        target: &mut S,
+        assets: &Assets,
+        skybox: &Skybox,
+        camera: &Camera,
+    ) -> Result<()> {
+        let Some(sampled) = skybox.sampled() else {
+            return Ok(());
+        };
+
+        // Only the camera's rotation, so the skybox always surrounds it
+        let view = camera.view;
+        let rotation = Matrix4::from(Matrix3::from_cols(
+            view.x.truncate(),
+            view.y.truncate(),
+            view.z.truncate(),
+        ));
+
+        let uniforms = uniform! {
+            vp: maths::raw_matrix(camera.projection * rotation),
+            skybox: sampled,
+            intensity: skybox.intensity,
+        };
+
+        target.draw(
+            &self.cube,
+            &self.indices,
+            assets.program(self.program),
+            &uniforms,
+            &DrawParameters {
+                depth: Depth {
+                    test: DepthTest::IfLessOrEqual,
+                    write: false,
+                    ..Depth::default()
+                },
+                ..DrawParameters::default()
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// The skybox's cubemap to light PBR materials with, or a black placeholder if image based
+    /// lighting is off
+    pub fn environment_map<'a>(&'a self, skybox: Option<&'a Skybox>) -> Sampler<'a, Cubemap> {
+        skybox
+            .filter(|skybox| skybox.image_based_lighting)
+            .and_then(Skybox::sampled)
+            .unwrap_or_else(|| sampled_cubemap(&self.placeholder))
+    }
+
+    /// How strongly `environment_map` lights PBR materials, where 0 uses the flat ambient light
+    pub fn environment_intensity(skybox: Option<&Skybox>) -> f32 {
+        match skybox {
+            Some(skybox) if skybox.image_based_lighting => skybox.intensity,
+            _ => 0.0,
+        }
+    }
+
+    pub fn environment_max_mipmap_level(skybox: Option<&Skybox>) -> f32 {
+        skybox.map_or(0.0, Skybox::max_mipmap_level)
+    }
+}
@@ -0,0 +1,582 @@
+use std::collections::HashMap;
+
+use cgmath::Vector3;
+
+use crate::maths::{Aabb, Frustum, Ray};
+use crate::uuid::UUID;
+
+/// World units each entity's box is grown by in the tree, so entities moving a little don't
+/// change its structure
+const MARGIN: f32 = 0.2;
+
+struct Node {
+    /// Around everything below the node. Leaves' boxes are grown by the margin.
+    bounds: Aabb,
+    parent: Option<usize>,
+    /// Longest path down to a leaf, zero for leaves
+    height: usize,
+    kind: NodeKind,
+}
+
+enum NodeKind {
+    Leaf {
+        entity: UUID,
+        /// The entity's box without the margin, which queries test against
+        tight: Aabb,
+    },
+    Branch {
+        children: [usize; 2],
+    },
+    /// Waiting to be reused
+    Free,
+}
+
+/// Bounding volume hierarchy over entities' world space boxes, making raycasts, frustum culling
+/// and overlap queries skip whole groups of entities at once. Boxes are refitted in place as
+/// entities move, with an entity only moved elsewhere in the tree once it leaves its grown box,
+/// and the tree is kept balanced with rotations as it changes.
+#[derive(Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// Indices of free nodes
+    free: Vec<usize>,
+    root: Option<usize>,
+    leaves: HashMap<UUID, usize>,
+}
+
+impl Bvh {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn contains(&self, entity: UUID) -> bool {
+        self.leaves.contains_key(&entity)
+    }
+
+    /// The box an entity was last given
+    pub fn bounds(&self, entity: UUID) -> Option<Aabb> {
+        match self.nodes[*self.leaves.get(&entity)?].kind {
+            NodeKind::Leaf { tight, .. } => Some(tight),
+            _ => None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Adds an entity or moves it to a new box. Returns whether the tree had to change around it,
+    /// which it doesn't while the box stays within the one the entity was grown to.
+    pub fn update(&mut self, entity: UUID, bounds: Aabb) -> bool {
+        let Some(&leaf) = self.leaves.get(&entity) else {
+            let leaf = self.allocate(Node {
+                bounds: grown(&bounds),
+                parent: None,
+                height: 0,
+                kind: NodeKind::Leaf {
+                    entity,
+                    tight: bounds,
+                },
+            });
+            self.leaves.insert(entity, leaf);
+            self.insert_leaf(leaf);
+
+            return true;
+        };
+
+        self.nodes[leaf].kind = NodeKind::Leaf {
+            entity,
+            tight: bounds,
+        };
+        if contains(&self.nodes[leaf].bounds, &bounds) {
+            return false;
+        }
+
+        self.remove_leaf(leaf);
+        self.nodes[leaf].bounds = grown(&bounds);
+        self.insert_leaf(leaf);
+
+        true
+    }
+
+    /// Returns whether the entity was in the tree
+    pub fn remove(&mut self, entity: UUID) -> bool {
+        let Some(leaf) = self.leaves.remove(&entity) else {
+            return false;
+        };
+
+        self.remove_leaf(leaf);
+        self.release(leaf);
+
+        true
+    }
+
+    /// Removes every entity `keep` returns false for
+    pub fn retain(&mut self, keep: impl Fn(UUID) -> bool) {
+        let removed = self
+            .leaves
+            .keys()
+            .copied()
+            .filter(|&entity| !keep(entity))
+            .collect::<Vec<_>>();
+
+        for entity in removed {
+            self.remove(entity);
+        }
+    }
+
+    /// Entities whose boxes overlap `aabb`
+    pub fn query_aabb(&self, aabb: &Aabb) -> Vec<UUID> {
+        let mut entities = vec![];
+        self.visit(
+            |bounds| bounds.intersects(aabb),
+            |entity, tight| {
+                if tight.intersects(aabb) {
+                    entities.push(entity);
+                }
+            },
+        );
+
+        entities
+    }
+
+    /// Entities whose boxes may be inside the frustum
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<UUID> {
+        let mut entities = vec![];
+        self.visit(
+            |bounds| frustum.intersects_aabb(bounds),
+            |entity, tight| {
+                if frustum.intersects_aabb(tight) {
+                    entities.push(entity);
+                }
+            },
+        );
+
+        entities
+    }
+
+    /// Entities whose boxes a ray hits, nearest first, along with how far along the ray it enters
+    /// each box. Nothing in a box can be hit before the ray enters it, so a raycast can stop at
+    /// the first box further than its closest hit so far.
+    pub fn raycast(&self, ray: &Ray) -> Vec<(f32, UUID)> {
+        let mut hits = vec![];
+        self.visit(
+            |bounds| bounds.intersect_ray(ray).is_some(),
+            |entity, tight| {
+                if let Some((distance, _)) = tight.intersect_ray(ray) {
+                    hits.push((distance, entity));
+                }
+            },
+        );
+
+        hits.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        hits
+    }
+
+    /// Calls `leaf` for every leaf under the nodes `descend` accepts the box of
+    fn visit(&self, descend: impl Fn(&Aabb) -> bool, mut leaf: impl FnMut(UUID, &Aabb)) {
+        let mut stack = self.root.into_iter().collect::<Vec<_>>();
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !descend(&node.bounds) {
+                continue;
+            }
+
+            match &node.kind {
+                NodeKind::Leaf { entity, tight } => leaf(*entity, tight),
+                NodeKind::Branch { children } => stack.extend(children),
+                NodeKind::Free => {}
+            }
+        }
+    }
+
+    fn allocate(&mut self, node: Node) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, index: usize) {
+        self.nodes[index].kind = NodeKind::Free;
+        self.nodes[index].parent = None;
+        self.free.push(index);
+    }
+
+    /// Hangs a leaf next to whichever node grows the tree's surface area least, see Catto,
+    /// "Dynamic Bounding Volume Hierarchies"
+    fn insert_leaf(&mut self, leaf: usize) {
+        let Some(root) = self.root else {
+            self.nodes[leaf].parent = None;
+            self.root = Some(leaf);
+            return;
+        };
+
+        let bounds = self.nodes[leaf].bounds;
+        let mut sibling = root;
+
+        while let NodeKind::Branch { children } = self.nodes[sibling].kind {
+            let area = surface_area(&self.nodes[sibling].bounds);
+            let combined = surface_area(&self.nodes[sibling].bounds.union(&bounds));
+
+            // Pairing the leaf with this node, against pushing it further down
+            let cost = 2.0 * combined;
+            let inherited = 2.0 * (combined - area);
+            let descend_cost = |child: usize| {
+                let child = &self.nodes[child];
+                let enlarged = surface_area(&child.bounds.union(&bounds));
+
+                match child.kind {
+                    NodeKind::Leaf { .. } => enlarged + inherited,
+                    _ => enlarged - surface_area(&child.bounds) + inherited,
+                }
+            };
+            let (first, second) = (descend_cost(children[0]), descend_cost(children[1]));
+
+            if cost < first && cost < second {
+                break;
+            }
+            sibling = if first < second {
+                children[0]
+            } else {
+                children[1]
+            };
+        }
+
+        let old_parent = self.nodes[sibling].parent;
+        let new_parent = self.allocate(Node {
+            bounds: self.nodes[sibling].bounds.union(&bounds),
+            parent: old_parent,
+            height: self.nodes[sibling].height + 1,
+            kind: NodeKind::Branch {
+                children: [sibling, leaf],
+            },
+        });
+        self.nodes[sibling].parent = Some(new_parent);
+        self.nodes[leaf].parent = Some(new_parent);
+
+        match old_parent {
+            Some(old_parent) => self.replace_child(old_parent, sibling, new_parent),
+            None => self.root = Some(new_parent),
+        }
+
+        self.refit(Some(new_parent));
+    }
+
+    /// Takes a leaf out of the tree, putting its sibling in place of their parent, but keeps the
+    /// node for it to be inserted again
+    fn remove_leaf(&mut self, leaf: usize) {
+        let Some(parent) = self.nodes[leaf].parent else {
+            self.root = None;
+            return;
+        };
+
+        let NodeKind::Branch { children } = self.nodes[parent].kind else {
+            unreachable!("Parents are always branches");
+        };
+        let sibling = if children[0] == leaf {
+            children[1]
+        } else {
+            children[0]
+        };
+        let grandparent = self.nodes[parent].parent;
+
+        self.nodes[sibling].parent = grandparent;
+        match grandparent {
+            Some(grandparent) => self.replace_child(grandparent, parent, sibling),
+            None => self.root = Some(sibling),
+        }
+
+        self.release(parent);
+        self.nodes[leaf].parent = None;
+        self.refit(grandparent);
+    }
+
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        if let NodeKind::Branch { children } = &mut self.nodes[parent].kind {
+            for child in children.iter_mut().filter(|child| **child == old) {
+                *child = new;
+            }
+        }
+    }
+
+    /// Balances and recomputes the boxes and heights of a branch and everything above it
+    fn refit(&mut self, mut index: Option<usize>) {
+        while let Some(branch) = index {
+            let branch = self.balance(branch);
+            self.fit(branch);
+            index = self.nodes[branch].parent;
+        }
+    }
+
+    /// Recomputes a branch's box and height from its children
+    fn fit(&mut self, branch: usize) {
+        let NodeKind::Branch { children: [a, b] } = self.nodes[branch].kind else {
+            return;
+        };
+
+        self.nodes[branch].bounds = self.nodes[a].bounds.union(&self.nodes[b].bounds);
+        self.nodes[branch].height = 1 + self.nodes[a].height.max(self.nodes[b].height);
+    }
+
+    /// Rotates the taller child of a branch up into its place if it's more than one level taller
+    /// than the other. Returns the node now in the branch's place.
+    fn balance(&mut self, branch: usize) -> usize {
+        let NodeKind::Branch { children: [a, b] } = self.nodes[branch].kind else {
+            return branch;
+        };
+
+        let (a_height, b_height) = (self.nodes[a].height, self.nodes[b].height);
+        if a_height > b_height + 1 {
+            self.rotate_up(branch, a, b)
+        } else if b_height > a_height + 1 {
+            self.rotate_up(branch, b, a)
+        } else {
+            branch
+        }
+    }
+
+    /// Puts `tall` in place of its parent `branch`, which takes `short` and the shorter of `tall`'s
+    /// children
+    fn rotate_up(&mut self, branch: usize, tall: usize, short: usize) -> usize {
+        let NodeKind::Branch { children: [f, g] } = self.nodes[tall].kind else {
+            return branch;
+        };
+
+        let parent = self.nodes[branch].parent;
+        self.nodes[tall].parent = parent;
+        self.nodes[branch].parent = Some(tall);
+        match parent {
+            Some(parent) => self.replace_child(parent, branch, tall),
+            None => self.root = Some(tall),
+        }
+
+        let (kept, moved) = if self.nodes[f].height > self.nodes[g].height {
+            (f, g)
+        } else {
+            (g, f)
+        };
+        self.nodes[tall].kind = NodeKind::Branch {
+            children: [branch, kept],
+        };
+        self.nodes[branch].kind = NodeKind::Branch {
+            children: [short, moved],
+        };
+        self.nodes[moved].parent = Some(branch);
+
+        self.fit(branch);
+        self.fit(tall);
+
+        tall
+    }
+}
+
+fn grown(aabb: &Aabb) -> Aabb {
+    let margin = Vector3::new(MARGIN, MARGIN, MARGIN);
+    Aabb::new(aabb.min - margin, aabb.max + margin)
+}
+
+fn contains(outer: &Aabb, inner: &Aabb) -> bool {
+    outer.contains(inner.min) && outer.contains(inner.max)
+}
+
+/// What the chance of a random ray hitting a box is proportional to
+fn surface_area(aabb: &Aabb) -> f32 {
+    let size = aabb.max - aabb.min;
+    2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use cgmath::{perspective, Deg, EuclideanSpace, Matrix4, Point3};
+
+    use super::*;
+
+    fn random_point(rng: &mut fastrand::Rng, extent: f32) -> Point3<f32> {
+        let mut coordinate = || (rng.f32() * 2.0 - 1.0) * extent;
+        Point3::new(coordinate(), coordinate(), coordinate())
+    }
+
+    fn random_box(rng: &mut fastrand::Rng) -> Aabb {
+        let size = Vector3::new(rng.f32(), rng.f32(), rng.f32()) * 3.0;
+        Aabb::from_center(random_point(rng, 50.0), size)
+    }
+
+    /// Every branch's box holds its children's, and parents, heights and leaves agree
+    fn assert_consistent(bvh: &Bvh) {
+        let mut leaves = 0;
+        let mut stack = bvh.root.into_iter().collect::<Vec<_>>();
+        if let Some(root) = bvh.root {
+            assert_eq!(bvh.nodes[root].parent, None);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &bvh.nodes[index];
+            match &node.kind {
+                NodeKind::Leaf { entity, tight } => {
+                    leaves += 1;
+                    assert_eq!(node.height, 0);
+                    assert_eq!(bvh.leaves.get(entity), Some(&index));
+                    assert!(contains(&node.bounds, tight));
+                }
+                NodeKind::Branch { children } => {
+                    for &child in children {
+                        let child_node = &bvh.nodes[child];
+                        assert_eq!(child_node.parent, Some(index));
+                        assert!(contains(&node.bounds, &child_node.bounds));
+                    }
+                    let heights = children.map(|child| bvh.nodes[child].height);
+                    assert_eq!(node.height, 1 + heights[0].max(heights[1]));
+                    stack.extend(children);
+                }
+                NodeKind::Free => panic!("Free node {} is in the tree", index),
+            }
+        }
+
+        assert_eq!(leaves, bvh.len());
+    }
+
+    /// Compares each query against testing every box in `boxes`
+    fn assert_matches_brute_force(bvh: &Bvh, boxes: &HashMap<UUID, Aabb>, rng: &mut fastrand::Rng) {
+        assert_eq!(bvh.len(), boxes.len());
+
+        for _ in 0..20 {
+            let query = random_box(rng);
+            let expected = boxes
+                .iter()
+                .filter(|(_, aabb)| aabb.intersects(&query))
+                .map(|(entity, _)| *entity)
+                .collect::<HashSet<_>>();
+            let found = bvh.query_aabb(&query);
+            assert_eq!(found.len(), expected.len());
+            assert_eq!(found.into_iter().collect::<HashSet<_>>(), expected);
+
+            let ray = Ray::new(random_point(rng, 60.0), random_point(rng, 1.0).to_vec());
+            let mut expected = boxes
+                .iter()
+                .filter_map(|(entity, aabb)| Some((aabb.intersect_ray(&ray)?.0, *entity)))
+                .collect::<Vec<_>>();
+            expected.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+            let distances = |hits: &[(f32, UUID)]| hits.iter().map(|(t, _)| *t).collect::<Vec<_>>();
+            let hits = bvh.raycast(&ray);
+            assert_eq!(distances(&hits), distances(&expected));
+
+            let eye = random_point(rng, 40.0);
+            let view = Matrix4::look_at_rh(eye, random_point(rng, 40.0), Vector3::unit_y());
+            let frustum = Frustum::from_matrix(perspective(Deg(60.0), 1.5, 0.1, 50.0) * view);
+            let expected = boxes
+                .iter()
+                .filter(|(_, aabb)| frustum.intersects_aabb(aabb))
+                .map(|(entity, _)| *entity)
+                .collect::<HashSet<_>>();
+            let found = bvh.query_frustum(&frustum);
+            assert_eq!(found.len(), expected.len());
+            assert_eq!(found.into_iter().collect::<HashSet<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn queries_match_brute_force_through_updates_and_removals() {
+        let mut rng = fastrand::Rng::with_seed(7);
+        let mut bvh = Bvh::new();
+        let mut boxes = HashMap::new();
+
+        for _ in 0..200 {
+            let (entity, aabb) = (UUID::new(), random_box(&mut rng));
+            assert!(bvh.update(entity, aabb));
+            boxes.insert(entity, aabb);
+        }
+        assert_consistent(&bvh);
+        assert_matches_brute_force(&bvh, &boxes, &mut rng);
+
+        for round in 0..10 {
+            let entities = boxes.keys().copied().collect::<Vec<_>>();
+            for entity in entities {
+                match rng.u32(0..4) {
+                    // Nudged a little, often staying within its margin, or moved somewhere else
+                    0 => {
+                        let nudge = Vector3::new(MARGIN, 0.0, 0.0) * 0.5;
+                        let aabb = boxes[&entity];
+                        let moved = Aabb::new(aabb.min + nudge, aabb.max + nudge);
+                        bvh.update(entity, moved);
+                        boxes.insert(entity, moved);
+                    }
+                    1 => {
+                        let moved = random_box(&mut rng);
+                        bvh.update(entity, moved);
+                        boxes.insert(entity, moved);
+                    }
+                    2 => {
+                        assert!(bvh.remove(entity));
+                        boxes.remove(&entity);
+                    }
+                    _ => {}
+                }
+            }
+            for _ in 0..40 {
+                let (entity, aabb) = (UUID::new(), random_box(&mut rng));
+                bvh.update(entity, aabb);
+                boxes.insert(entity, aabb);
+            }
+
+            assert_consistent(&bvh);
+            assert_matches_brute_force(&bvh, &boxes, &mut rng);
+            for (entity, aabb) in boxes.iter() {
+                assert_eq!(bvh.bounds(*entity), Some(*aabb), "round {}", round);
+            }
+        }
+    }
+
+    #[test]
+    fn moving_within_the_margin_keeps_the_tree() {
+        let mut bvh = Bvh::new();
+        let entity = UUID::new();
+        let aabb = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+        let moved = |by: f32| {
+            let offset = Vector3::new(by, 0.0, 0.0);
+            Aabb::new(aabb.min + offset, aabb.max + offset)
+        };
+
+        assert!(bvh.update(entity, aabb));
+        assert!(!bvh.update(entity, moved(MARGIN * 0.5)));
+        assert_eq!(bvh.bounds(entity), Some(moved(MARGIN * 0.5)));
+        assert!(bvh.update(entity, moved(MARGIN * 2.0)));
+    }
+
+    #[test]
+    fn removing_everything_empties_the_tree() {
+        let mut rng = fastrand::Rng::with_seed(3);
+        let mut bvh = Bvh::new();
+        let entities = (0..50).map(|_| UUID::new()).collect::<Vec<_>>();
+        for &entity in entities.iter() {
+            bvh.update(entity, random_box(&mut rng));
+        }
+
+        bvh.retain(|entity| entity == entities[0]);
+        assert_eq!(bvh.len(), 1);
+        assert_consistent(&bvh);
+
+        assert!(bvh.remove(entities[0]));
+        assert!(!bvh.remove(entities[0]));
+        assert!(bvh.is_empty());
+        assert_eq!(bvh.root, None);
+        assert!(bvh.query_aabb(&random_box(&mut rng)).is_empty());
+    }
+}
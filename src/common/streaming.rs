@@ -0,0 +1,367 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use cgmath::Point3;
+use color_eyre::Result;
+use itertools::Itertools;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::hierarchy::Parent;
+use crate::loading::{AssetLoader, LoadId, LoadState};
+use crate::model::{ModelInstance, Transform};
+use crate::scene::Scene;
+use crate::uuid::UUID;
+
+/// Name of the manifest written by `StreamingManifest::split`, beside the cell files
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Square of the ground a streamed world is split into, counted in cells from the origin along X
+/// and Z
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CellId {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl CellId {
+    pub fn containing(position: Point3<f32>, cell_size: f32) -> Self {
+        Self {
+            x: (position.x / cell_size).floor() as i32,
+            z: (position.z / cell_size).floor() as i32,
+        }
+    }
+
+    /// How many cells apart two cells are, counting diagonal steps as one
+    pub fn distance(self, other: CellId) -> i32 {
+        (self.x - other.x).abs().max((self.z - other.z).abs())
+    }
+}
+
+/// Component on entities spawned from a streamed cell, which are despawned when it's unloaded
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cell(pub CellId);
+
+/// An instance of a model in a cell
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CellEntity {
+    /// ID of the entity in the scene it was split from. Streamed entities are given new ones.
+    #[serde(default)]
+    pub uuid: UUID,
+    /// Path or logical name of the model
+    pub model: PathBuf,
+    pub transform: Transform,
+}
+
+/// What is in one cell of a streamed world, stored in a file of its own
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CellData {
+    pub entities: Vec<CellEntity>,
+}
+
+impl CellData {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    /// Each model the cell uses, once
+    fn models(&self) -> Vec<PathBuf> {
+        self.entities
+            .iter()
+            .map(|entity| entity.model.clone())
+            .unique()
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ManifestCell {
+    pub id: CellId,
+    /// Relative to the manifest
+    pub path: PathBuf,
+}
+
+/// Lists the cells of a world too big to keep loaded at once, see `SceneStreamer`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StreamingManifest {
+    /// Width and depth of each cell
+    pub cell_size: f32,
+    /// Cells up to this many cells away from the camera's are loaded. They're unloaded once a
+    /// cell further away, so walking back and forth over a border doesn't reload them.
+    pub load_radius: i32,
+    pub cells: Vec<ManifestCell>,
+    /// Where cell paths are relative to, set on load
+    #[serde(skip)]
+    pub directory: PathBuf,
+}
+
+impl StreamingManifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut manifest = serde_json::from_str::<Self>(&std::fs::read_to_string(path)?)?;
+        manifest.directory = path.parent().map(Path::to_owned).unwrap_or_default();
+
+        Ok(manifest)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// Splits the models in a scene into cells, writing a file for each with the manifest into
+    /// `directory`. Attached entities are left out, as they move with what they're attached to.
+    pub fn split(
+        scene: &Scene,
+        cell_size: f32,
+        load_radius: i32,
+        directory: &Path,
+    ) -> Result<Self> {
+        let world = &scene.simulation.world;
+        let mut cells = HashMap::<CellId, CellData>::new();
+
+        for (uuid, model_instance, transform) in world.query2::<ModelInstance, Transform>() {
+            if world.has::<Parent>(uuid) {
+                continue;
+            }
+
+            let position = Point3::new(transform.translation.x, 0.0, transform.translation.z);
+            let model = &model_instance.model.path;
+            cells
+                .entry(CellId::containing(position, cell_size))
+                .or_default()
+                .entities
+                .push(CellEntity {
+                    uuid,
+                    model: scene
+                        .assets
+                        .name_of(model)
+                        .map_or_else(|| model.clone(), PathBuf::from),
+                    transform: transform.clone(),
+                });
+        }
+
+        std::fs::create_dir_all(directory)?;
+
+        let mut manifest = Self {
+            cell_size,
+            load_radius,
+            cells: vec![],
+            directory: directory.to_owned(),
+        };
+        for (id, cell) in cells.iter().sorted_by_key(|(id, _)| (id.x, id.z)) {
+            let path = PathBuf::from(format!("cell_{}_{}.json", id.x, id.z));
+            cell.save(&directory.join(&path))?;
+            manifest.cells.push(ManifestCell { id: *id, path });
+        }
+        manifest.save(&directory.join(MANIFEST_FILE_NAME))?;
+
+        Ok(manifest)
+    }
+
+    fn cell_path(&self, id: CellId) -> Option<PathBuf> {
+        self.cells
+            .iter()
+            .find(|cell| cell.id == id)
+            .map(|cell| self.directory.join(&cell.path))
+    }
+}
+
+enum CellState {
+    /// Its file is being read on another thread
+    Reading,
+    /// Waiting on the asset loader for the models it uses
+    Loading(CellData, Vec<LoadId>),
+    Loaded {
+        entities: Vec<UUID>,
+        models: Vec<PathBuf>,
+    },
+    /// Couldn't be read, tried again once it's been out of range
+    Failed,
+}
+
+/// Keeps the cells of a streamed world around a point loaded and the rest unloaded, so memory
+/// stays bounded however big the world is. Cell files are read on background threads and their
+/// models loaded through an `AssetLoader`, so nothing stalls a frame. Entities spawned from a cell
+/// carry its `Cell`, and models no loaded cell uses any more are dropped from the scene's assets.
+pub struct SceneStreamer {
+    manifest: StreamingManifest,
+    cells: HashMap<CellId, CellState>,
+    read_sender: Sender<(CellId, Result<CellData>)>,
+    read_receiver: Receiver<(CellId, Result<CellData>)>,
+}
+
+impl SceneStreamer {
+    pub fn new(manifest: StreamingManifest) -> Self {
+        let (read_sender, read_receiver) = mpsc::channel();
+
+        Self {
+            manifest,
+            cells: HashMap::new(),
+            read_sender,
+            read_receiver,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::new(StreamingManifest::load(path)?))
+    }
+
+    pub fn manifest(&self) -> &StreamingManifest {
+        &self.manifest
+    }
+
+    /// Cells whose entities are in the scene
+    pub fn loaded_cells(&self) -> impl Iterator<Item = CellId> + '_ {
+        self.cells
+            .iter()
+            .filter(|(_, state)| matches!(state, CellState::Loaded { .. }))
+            .map(|(&id, _)| id)
+    }
+
+    /// Whether any cell in range is still being read or waiting for its models
+    pub fn is_loading(&self) -> bool {
+        self.cells
+            .values()
+            .any(|state| matches!(state, CellState::Reading | CellState::Loading(..)))
+    }
+
+    /// Starts loading the cells in range of `focus`, usually the camera, spawns those that have
+    /// finished loading and unloads those that have gone out of range. Called once per frame,
+    /// after `loader` has been updated.
+    pub fn update(&mut self, scene: &mut Scene, loader: &mut AssetLoader, focus: Point3<f32>) {
+        let center = CellId::containing(focus, self.manifest.cell_size);
+        let radius = self.manifest.load_radius;
+
+        let out_of_range = self
+            .cells
+            .keys()
+            .copied()
+            .filter(|id| id.distance(center) > radius + 1)
+            .collect_vec();
+        for id in out_of_range {
+            self.unload(scene, id);
+        }
+
+        for cell in self.manifest.cells.iter() {
+            if cell.id.distance(center) > radius || self.cells.contains_key(&cell.id) {
+                continue;
+            }
+
+            let (id, path) = (cell.id, self.manifest.directory.join(&cell.path));
+            let sender = self.read_sender.clone();
+            std::thread::spawn(move || sender.send((id, CellData::load(&path))));
+            self.cells.insert(cell.id, CellState::Reading);
+        }
+
+        while let Ok((id, data)) = self.read_receiver.try_recv() {
+            // Cells that went out of range while being read are dropped
+            if !matches!(self.cells.get(&id), Some(CellState::Reading)) {
+                continue;
+            }
+
+            let state = match data {
+                Ok(data) => {
+                    let loads = data
+                        .models()
+                        .iter()
+                        .map(|model| loader.load_model(&scene.assets, model))
+                        .collect();
+                    CellState::Loading(data, loads)
+                }
+                Err(error) => {
+                    warn!(
+                        "Could not read streamed cell {:?}: {}",
+                        self.manifest.cell_path(id),
+                        error
+                    );
+                    CellState::Failed
+                }
+            };
+            self.cells.insert(id, state);
+        }
+
+        let finished = self
+            .cells
+            .iter()
+            .filter(|(_, state)| match state {
+                CellState::Loading(_, loads) => loads
+                    .iter()
+                    .all(|&load| loader.state(load) != Some(&LoadState::Loading)),
+                _ => false,
+            })
+            .map(|(&id, _)| id)
+            .collect_vec();
+        for id in finished {
+            let Some(CellState::Loading(data, _)) = self.cells.remove(&id) else {
+                continue;
+            };
+            let state = Self::spawn(scene, id, data);
+            self.cells.insert(id, state);
+        }
+    }
+
+    /// Despawns every streamed entity and drops the models only they used
+    pub fn unload_all(&mut self, scene: &mut Scene) {
+        let ids = self.cells.keys().copied().collect_vec();
+        for id in ids {
+            self.unload(scene, id);
+        }
+    }
+
+    /// Adds a cell's entities to the scene, skipping those whose model failed to load
+    fn spawn(scene: &mut Scene, id: CellId, data: CellData) -> CellState {
+        let models = data.models();
+        let mut entities = vec![];
+
+        for entity in data.entities {
+            // Already loaded by the asset loader, so this only looks it up
+            let model = match scene.load_model(&entity.model) {
+                Ok(model) => model,
+                Err(error) => {
+                    warn!("Skipping streamed {:?}: {}", entity.model, error);
+                    continue;
+                }
+            };
+
+            // A new ID, as the scene it was split from may well be open too
+            let uuid = scene.simulation.spawn();
+            scene.insert_model(uuid, model, entity.transform);
+            scene.simulation.world.insert(uuid, Cell(id));
+            entities.push(uuid);
+        }
+
+        CellState::Loaded { entities, models }
+    }
+
+    fn unload(&mut self, scene: &mut Scene, id: CellId) {
+        let Some(CellState::Loaded { entities, models }) = self.cells.remove(&id) else {
+            return;
+        };
+
+        for entity in entities {
+            scene.simulation.despawn(entity);
+        }
+
+        let in_use = self
+            .cells
+            .values()
+            .flat_map(|state| match state {
+                CellState::Loading(data, _) => data.models(),
+                CellState::Loaded { models, .. } => models.clone(),
+                _ => vec![],
+            })
+            .collect::<HashSet<_>>();
+        for model in models.iter().filter(|model| !in_use.contains(*model)) {
+            // Kept if an entity outside the streamed cells uses it too
+            scene.unload_model(model);
+        }
+    }
+}
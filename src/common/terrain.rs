@@ -0,0 +1,503 @@
+use std::path::{Path, PathBuf};
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use color_eyre::eyre::ensure;
+use color_eyre::Result;
+use glium::glutin::surface::WindowSurface;
+use glium::index::PrimitiveType;
+use glium::uniforms::{
+    MinifySamplerFilter, SamplerBehavior, SamplerWrapFunction, UniformValue, Uniforms,
+};
+use glium::{
+    implement_vertex, Depth, DepthTest, Display, DrawParameters, IndexBuffer, Program, Surface,
+    VertexBuffer,
+};
+use itertools::Itertools;
+use palette::Srgb;
+
+use crate::assets::{Assets, Handle};
+use crate::maths;
+use crate::maths::{Aabb, Frustum};
+use crate::physics::{Collider, ColliderShape};
+use crate::texture::Texture;
+
+/// Quads along each side of a chunk, small enough for a chunk's vertices to be indexed with u16s
+const CHUNK_QUADS: usize = 32;
+
+/// Layers of value noise added together for generated heightmaps, each with half the strength and
+/// twice the detail of the last
+const NOISE_OCTAVES: usize = 5;
+/// Hills across a generated heightmap in its first octave
+const NOISE_FREQUENCY: f32 = 4.0;
+
+/// Layers are textured with this where they don't have a texture of their own
+const WHITE_TEXTURE_PATH: &str = "assets/textures/white.jpg";
+
+/// Heights sampled on a square grid, from 0 at the lowest to 1 at the highest
+#[derive(Clone, Debug)]
+pub struct Heightmap {
+    /// Samples along each side
+    pub resolution: usize,
+    /// Row by row along Z, each row along X
+    pub heights: Vec<f32>,
+}
+
+impl Heightmap {
+    /// Reads a square greyscale image, with white as the highest point
+    pub fn from_image(path: &Path) -> Result<Self> {
+        let image = image::open(path)?.to_luma16();
+        ensure!(
+            image.width() == image.height() && image.width() >= 2,
+            "Heightmap {:?} should be square and at least 2 pixels across",
+            path
+        );
+
+        Ok(Self {
+            resolution: image.width() as usize,
+            heights: image
+                .pixels()
+                .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32)
+                .collect(),
+        })
+    }
+
+    /// Rolling hills from fractal value noise, the same for the same `seed`
+    pub fn generate(resolution: usize, seed: u64) -> Self {
+        let resolution = resolution.max(2);
+
+        let mut heights = (0..resolution * resolution)
+            .map(|index| {
+                let x = (index % resolution) as f32 / (resolution - 1) as f32;
+                let z = (index / resolution) as f32 / (resolution - 1) as f32;
+
+                (0..NOISE_OCTAVES)
+                    .map(|octave| {
+                        let frequency = NOISE_FREQUENCY * 2.0_f32.powi(octave as i32);
+                        let noise = value_noise(seed + octave as u64, x * frequency, z * frequency);
+                        noise * 0.5_f32.powi(octave as i32)
+                    })
+                    .sum::<f32>()
+            })
+            .collect_vec();
+
+        // Stretched to fill 0 to 1, so the terrain's height is how high it really reaches
+        let (lowest, highest) = heights.iter().fold(
+            (f32::INFINITY, f32::NEG_INFINITY),
+            |(lowest, highest), &height| (lowest.min(height), highest.max(height)),
+        );
+        let range = (highest - lowest).max(f32::EPSILON);
+        for height in heights.iter_mut() {
+            *height = (*height - lowest) / range;
+        }
+
+        Self {
+            resolution,
+            heights,
+        }
+    }
+
+    fn get(&self, x: usize, z: usize) -> f32 {
+        self.heights[z * self.resolution + x]
+    }
+}
+
+/// One of the materials blended across the terrain
+#[derive(Clone, Debug)]
+pub struct TerrainLayer {
+    /// Multiplies the texture
+    pub color: Srgb,
+    pub texture: Option<PathBuf>,
+    /// Times the texture repeats per world unit
+    pub tiling: f32,
+}
+
+impl TerrainLayer {
+    pub fn new(color: Srgb) -> Self {
+        Self {
+            color,
+            texture: None,
+            tiling: 0.25,
+        }
+    }
+}
+
+/// Ground built from a heightmap, centered on the origin with its lowest point at zero. Drawn by
+/// `TerrainRenderer`, with sand, grass, rock and snow layers splatted by height and steepness.
+#[derive(Clone, Debug)]
+pub struct Terrain {
+    pub heightmap: Heightmap,
+    /// World units across each side
+    pub size: f32,
+    /// World units the highest point of the heightmap is raised to
+    pub height: f32,
+    /// Sand on low ground, grass, rock on steep slopes and snow on peaks, in that order
+    pub layers: [TerrainLayer; 4],
+}
+
+impl Terrain {
+    pub fn new(heightmap: Heightmap, size: f32, height: f32) -> Self {
+        Self {
+            heightmap,
+            size,
+            height,
+            layers: [
+                TerrainLayer::new(Srgb::new(0.76, 0.70, 0.50)),
+                TerrainLayer::new(Srgb::new(0.30, 0.50, 0.20)),
+                TerrainLayer::new(Srgb::new(0.45, 0.42, 0.40)),
+                TerrainLayer::new(Srgb::new(0.95, 0.95, 0.97)),
+            ],
+        }
+    }
+
+    /// World units between neighbouring samples
+    pub fn spacing(&self) -> f32 {
+        self.size / (self.heightmap.resolution - 1) as f32
+    }
+
+    /// World height of the ground at a point, blending between the nearest samples. `None` off the
+    /// edge of the terrain.
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let (column, row) = self.grid_position(x, z)?;
+        let last = self.heightmap.resolution - 1;
+
+        let (x0, z0) = (
+            (column.floor() as usize).min(last),
+            (row.floor() as usize).min(last),
+        );
+        let (x1, z1) = ((x0 + 1).min(last), (z0 + 1).min(last));
+        let (across, down) = (column - x0 as f32, row - z0 as f32);
+
+        let near = lerp(
+            self.heightmap.get(x0, z0),
+            self.heightmap.get(x1, z0),
+            across,
+        );
+        let far = lerp(
+            self.heightmap.get(x0, z1),
+            self.heightmap.get(x1, z1),
+            across,
+        );
+
+        Some(lerp(near, far, down) * self.height)
+    }
+
+    /// Direction pointing away from the ground at a point
+    pub fn normal_at(&self, x: f32, z: f32) -> Option<Vector3<f32>> {
+        let step = self.spacing();
+        let center = self.height_at(x, z)?;
+
+        // Samples past the edges fall back to the point itself
+        let height = |x: f32, z: f32| self.height_at(x, z).unwrap_or(center);
+        let (left, right) = (height(x - step, z), height(x + step, z));
+        let (back, front) = (height(x, z - step), height(x, z + step));
+
+        Some(Vector3::new(left - right, 2.0 * step, back - front).normalize())
+    }
+
+    /// Fixed heightfield matching the terrain, for an entity at the origin
+    pub fn collider(&self) -> Collider {
+        Collider::new(ColliderShape::HeightField {
+            rows: self.heightmap.resolution,
+            columns: self.heightmap.resolution,
+            heights: self.heightmap.heights.clone(),
+            scale: Vector3::new(self.size, self.height, self.size),
+        })
+    }
+
+    /// Fractional column and row of the heightmap under a point
+    fn grid_position(&self, x: f32, z: f32) -> Option<(f32, f32)> {
+        let half_size = self.size / 2.0;
+        if x < -half_size || x > half_size || z < -half_size || z > half_size {
+            return None;
+        }
+
+        let spacing = self.spacing();
+        Some(((x + half_size) / spacing, (z + half_size) / spacing))
+    }
+
+    fn sample_position(&self, column: usize, row: usize) -> Point3<f32> {
+        let spacing = self.spacing();
+        let half_size = self.size / 2.0;
+
+        Point3::new(
+            column as f32 * spacing - half_size,
+            self.heightmap.get(column, row) * self.height,
+            row as f32 * spacing - half_size,
+        )
+    }
+
+    /// How much of each layer covers a point, adding up to 1
+    fn splat_weights(&self, height: f32, normal: Vector3<f32>) -> [f32; 4] {
+        let height = height / self.height.max(f32::EPSILON);
+        let steepness = 1.0 - normal.y;
+
+        let rock = maths::smoothstep(0.15, 0.3, steepness);
+        let snow = maths::smoothstep(0.7, 0.8, height) * (1.0 - rock);
+        let sand = (1.0 - maths::smoothstep(0.05, 0.12, height)) * (1.0 - rock) * (1.0 - snow);
+        let grass = 1.0 - rock - snow - sand;
+
+        [sand, grass, rock, snow]
+    }
+}
+
+#[derive(Copy, Clone)]
+struct TerrainVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    /// Weight of each layer
+    splat: [f32; 4],
+}
+implement_vertex!(TerrainVertex, position, normal, splat);
+
+/// A square piece of the terrain, culled on its own
+struct TerrainChunk {
+    vertex_buffer: VertexBuffer<TerrainVertex>,
+    index_buffer: IndexBuffer<u16>,
+    bounds: Aabb,
+}
+
+/// Draws a terrain in chunks, skipping those outside the camera's view
+pub struct TerrainRenderer {
+    program: Handle<Program>,
+    chunks: Vec<TerrainChunk>,
+    layer_textures: Vec<Handle<Texture>>,
+    layer_colors: [[f32; 4]; 4],
+    layer_tiling: [f32; 4],
+}
+
+impl TerrainRenderer {
+    pub fn new(assets: &mut Assets, display: &Display<WindowSurface>) -> Result<Self> {
+        let program = assets.load_program(
+            "assets/shaders/terrain/terrain.vert",
+            "assets/shaders/terrain/terrain.frag",
+            display,
+        )?;
+
+        Ok(Self {
+            program,
+            chunks: vec![],
+            layer_textures: vec![],
+            layer_colors: [[1.0; 4]; 4],
+            layer_tiling: [1.0; 4],
+        })
+    }
+
+    /// Builds the chunks' meshes and loads the layers' textures, replacing any earlier terrain
+    pub fn build(
+        &mut self,
+        terrain: &Terrain,
+        assets: &mut Assets,
+        display: &Display<WindowSurface>,
+    ) -> Result<()> {
+        self.layer_textures = terrain
+            .layers
+            .iter()
+            .map(|layer| {
+                let path = layer
+                    .texture
+                    .clone()
+                    .unwrap_or_else(|| PathBuf::from(WHITE_TEXTURE_PATH));
+                assets.load_texture(&path, display)
+            })
+            .collect::<Result<_>>()?;
+        self.layer_colors = terrain
+            .layers
+            .clone()
+            .map(|layer| [layer.color.red, layer.color.green, layer.color.blue, 1.0]);
+        self.layer_tiling = terrain.layers.clone().map(|layer| layer.tiling);
+
+        let quads = terrain.heightmap.resolution - 1;
+        self.chunks = (0..quads)
+            .step_by(CHUNK_QUADS)
+            .cartesian_product((0..quads).step_by(CHUNK_QUADS))
+            .map(|(row, column)| Self::build_chunk(terrain, column, row, display))
+            .collect::<Result<_>>()?;
+
+        Ok(())
+    }
+
+    /// Removes the terrain
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    /// The chunk whose quads start at the sample in `first_column` and `first_row`
+    fn build_chunk(
+        terrain: &Terrain,
+        first_column: usize,
+        first_row: usize,
+        display: &Display<WindowSurface>,
+    ) -> Result<TerrainChunk> {
+        let last = terrain.heightmap.resolution - 1;
+        let columns = (first_column..=(first_column + CHUNK_QUADS).min(last)).collect_vec();
+        let rows = (first_row..=(first_row + CHUNK_QUADS).min(last)).collect_vec();
+
+        let vertices = rows
+            .iter()
+            .cartesian_product(columns.iter())
+            .map(|(&row, &column)| {
+                let position = terrain.sample_position(column, row);
+                let normal = terrain
+                    .normal_at(position.x, position.z)
+                    .unwrap_or(Vector3::unit_y());
+
+                TerrainVertex {
+                    position: position.into(),
+                    normal: normal.into(),
+                    splat: terrain.splat_weights(position.y, normal),
+                }
+            })
+            .collect_vec();
+
+        let width = columns.len() as u16;
+        let indices = (0..rows.len() as u16 - 1)
+            .cartesian_product(0..width - 1)
+            .flat_map(|(row, column)| {
+                let corner = row * width + column;
+                [
+                    corner,
+                    corner + width,
+                    corner + 1,
+                    corner + 1,
+                    corner + width,
+                    corner + width + 1,
+                ]
+            })
+            .collect_vec();
+
+        // Never empty, as every chunk has at least one quad
+        let bounds =
+            Aabb::from_points(vertices.iter().map(|vertex| Point3::from(vertex.position))).unwrap();
+
+        Ok(TerrainChunk {
+            vertex_buffer: VertexBuffer::new(display, &vertices)?,
+            index_buffer: IndexBuffer::new(display, PrimitiveType::TrianglesList, &indices)?,
+            bounds,
+        })
+    }
+
+    /// Draws the chunks in view with the scene's forward uniforms, which must include the camera
+    /// and environment. Returns the number of draw calls made.
+    pub fn render<S: Surface, U: Uniforms>(
+        &self,
+        target: &mut S,
+        assets: &Assets,
+        uniforms: &U,
+        frustum: Option<&Frustum>,
+        multisampling: bool,
+    ) -> usize {
+        let uniforms = TerrainUniforms {
+            uniforms,
+            renderer: self,
+            assets,
+        };
+        let draw_parameters = DrawParameters {
+            depth: Depth {
+                test: DepthTest::IfLess,
+                write: true,
+                ..Depth::default()
+            },
+            multisampling,
+            ..DrawParameters::default()
+        };
+
+        let mut draw_calls = 0;
+
+        for chunk in self.chunks.iter() {
+            if frustum.is_some_and(|frustum| !frustum.intersects_aabb(&chunk.bounds)) {
+                continue;
+            }
+
+            target
+                .draw(
+                    &chunk.vertex_buffer,
+                    &chunk.index_buffer,
+                    assets.program(self.program),
+                    &uniforms,
+                    &draw_parameters,
+                )
+                .unwrap();
+            draw_calls += 1;
+        }
+
+        draw_calls
+    }
+}
+
+/// The scene's uniforms with the terrain's layers added on
+struct TerrainUniforms<'a, U: Uniforms> {
+    uniforms: &'a U,
+    renderer: &'a TerrainRenderer,
+    assets: &'a Assets,
+}
+
+impl<U: Uniforms> Uniforms for TerrainUniforms<'_, U> {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut output: F) {
+        self.uniforms.visit_values(&mut output);
+
+        let sampler = Some(SamplerBehavior {
+            wrap_function: (
+                SamplerWrapFunction::Repeat,
+                SamplerWrapFunction::Repeat,
+                SamplerWrapFunction::Repeat,
+            ),
+            minify_filter: MinifySamplerFilter::LinearMipmapLinear,
+            ..SamplerBehavior::default()
+        });
+
+        const NAMES: [&str; 4] = [
+            "layer0_texture",
+            "layer1_texture",
+            "layer2_texture",
+            "layer3_texture",
+        ];
+        for (name, &texture) in NAMES.iter().zip(self.renderer.layer_textures.iter()) {
+            output(name, self.assets.texture(texture).uniform_value(sampler));
+        }
+
+        // Each column is one layer's colour
+        output(
+            "layer_colors",
+            UniformValue::Mat4(self.renderer.layer_colors),
+        );
+        output(
+            "layer_tiling",
+            UniformValue::Vec4(self.renderer.layer_tiling),
+        );
+    }
+}
+
+/// Smooth noise between random values at whole coordinates, from 0 to 1
+fn value_noise(seed: u64, x: f32, z: f32) -> f32 {
+    let (x0, z0) = (x.floor(), z.floor());
+    let (across, down) = (
+        maths::smoothstep(0.0, 1.0, x - x0),
+        maths::smoothstep(0.0, 1.0, z - z0),
+    );
+    let (x0, z0) = (x0 as i64, z0 as i64);
+
+    let near = lerp(lattice(seed, x0, z0), lattice(seed, x0 + 1, z0), across);
+    let far = lerp(
+        lattice(seed, x0, z0 + 1),
+        lattice(seed, x0 + 1, z0 + 1),
+        across,
+    );
+
+    lerp(near, far, down)
+}
+
+/// A random value from 0 to 1 for a whole coordinate, always the same for the same seed
+fn lattice(seed: u64, x: i64, z: i64) -> f32 {
+    let mut hash = seed
+        ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (z as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    hash ^= hash >> 33;
+
+    (hash >> 40) as f32 / (1_u64 << 24) as f32
+}
+
+fn lerp(a: f32, b: f32, amount: f32) -> f32 {
+    a + (b - a) * amount
+}
@@ -0,0 +1,606 @@
+use std::path::Path;
+
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::Result;
+use glium::glutin::surface::WindowSurface;
+use glium::texture::{
+    CompressedMipmapsOption, CompressedSrgbFormat, CompressedSrgbTexture2d, MipmapsOption,
+    RawImage2d, SrgbTexture2d,
+};
+use glium::uniforms::{AsUniformValue, SamplerBehavior, UniformValue};
+use glium::Display;
+use glium::Rect;
+use image::RgbaImage;
+use log::{debug, warn};
+
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// Block compressed formats that pre-compressed textures can be in. Textures are taken to be
+/// sRGB, as they're colors, whether the file says so or not.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlockFormat {
+    /// DXT1, with 1 bit alpha
+    Bc1,
+    /// DXT3, with explicit 4 bit alpha
+    Bc2,
+    /// DXT5, with interpolated alpha
+    Bc3,
+    /// BPTC
+    Bc7,
+}
+
+impl BlockFormat {
+    /// Bytes in each 4x4 block
+    fn block_size(self) -> usize {
+        match self {
+            Self::Bc1 => 8,
+            Self::Bc2 | Self::Bc3 | Self::Bc7 => 16,
+        }
+    }
+
+    fn srgb_format(self) -> CompressedSrgbFormat {
+        match self {
+            Self::Bc1 => CompressedSrgbFormat::S3tcDxt1Alpha,
+            Self::Bc2 => CompressedSrgbFormat::S3tcDxt3Alpha,
+            Self::Bc3 => CompressedSrgbFormat::S3tcDxt5Alpha,
+            Self::Bc7 => CompressedSrgbFormat::Bptc,
+        }
+    }
+
+    /// Bytes in a level of a block compressed image of this size
+    fn level_size(self, width: u32, height: u32) -> usize {
+        let blocks = |size: u32| size.div_ceil(4).max(1) as usize;
+        blocks(width) * blocks(height) * self.block_size()
+    }
+}
+
+/// A pre-compressed image read from a DDS or KTX2 file, with its mipmaps as they were stored.
+/// Blocks are uploaded as they are rather than flipped, so files should be exported with their
+/// first row at the bottom, as tools do for OpenGL.
+#[derive(Clone)]
+pub struct CompressedImage {
+    pub format: BlockFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Largest first, each half the size of the one before
+    pub levels: Vec<Vec<u8>>,
+}
+
+/// An image read into memory without touching the GPU, so it can be done on a loading thread
+#[derive(Clone)]
+pub enum DecodedTexture {
+    /// Any format `image` reads, mipmapped when uploaded
+    Raw(RgbaImage),
+    Compressed(CompressedImage),
+}
+
+impl DecodedTexture {
+    /// Reads DDS and KTX2 files as they are, and decodes anything else with `image`
+    pub fn read(path: &Path) -> Result<Self> {
+        debug!("Loading texture \"{:?}\"...", path);
+
+        let bytes = std::fs::read(path)?;
+        let compressed = if bytes.starts_with(&DDS_MAGIC) {
+            Some(read_dds(&bytes))
+        } else if bytes.starts_with(&KTX2_IDENTIFIER) {
+            Some(read_ktx2(&bytes))
+        } else {
+            None
+        };
+
+        match compressed {
+            Some(image) => {
+                Ok(Self::Compressed(image.map_err(|error| {
+                    eyre!("Could not read {:?}: {}", path, error)
+                })?))
+            }
+            None => Ok(Self::Raw(image::load_from_memory(&bytes)?.to_rgba8())),
+        }
+    }
+
+    /// The largest level as RGBA, decompressing it if it's compressed, for the wgpu backend which
+    /// uploads textures uncompressed
+    pub fn to_rgba(&self) -> Result<RgbaImage> {
+        match self {
+            Self::Raw(image) => Ok(image.clone()),
+            Self::Compressed(image) => decompress(image),
+        }
+    }
+}
+
+/// A texture loaded by `Assets`, either uploaded compressed or decoded and mipmapped
+pub enum Texture {
+    Srgb(SrgbTexture2d),
+    Compressed(CompressedSrgbTexture2d),
+}
+
+impl Texture {
+    /// Uploads a decoded texture. Uncompressed images have their mipmaps generated. Compressed
+    /// ones keep the mipmaps they were stored with, and are decompressed instead where the GPU
+    /// can't sample their format, if they're in one they can be decompressed from.
+    pub fn upload(decoded: DecodedTexture, display: &Display<WindowSurface>) -> Result<Self> {
+        let image = match decoded {
+            DecodedTexture::Raw(image) => image,
+            DecodedTexture::Compressed(image) => {
+                if image.format.srgb_format().is_supported(&**display) {
+                    return upload_compressed(&image, display).map(Self::Compressed);
+                }
+
+                warn!(
+                    "{:?} textures aren't supported, decompressing one",
+                    image.format
+                );
+                decompress(&image)?
+            }
+        };
+
+        let dimensions = image.dimensions();
+        Ok(Self::Srgb(SrgbTexture2d::with_mipmaps(
+            display,
+            RawImage2d::from_raw_rgba_reversed(&image.into_raw(), dimensions),
+            MipmapsOption::AutoGeneratedMipmaps,
+        )?))
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            Self::Srgb(texture) => texture.dimensions(),
+            Self::Compressed(texture) => texture.dimensions(),
+        }
+    }
+
+    /// For passing to a shader sampled with `sampler`, or the default if `None`
+    pub fn uniform_value(&self, sampler: Option<SamplerBehavior>) -> UniformValue<'_> {
+        match self {
+            Self::Srgb(texture) => UniformValue::SrgbTexture2d(texture, sampler),
+            Self::Compressed(texture) => UniformValue::CompressedSrgbTexture2d(texture, sampler),
+        }
+    }
+}
+
+impl AsUniformValue for Texture {
+    fn as_uniform_value(&self) -> UniformValue<'_> {
+        self.uniform_value(None)
+    }
+}
+
+fn upload_compressed(
+    image: &CompressedImage,
+    display: &Display<WindowSurface>,
+) -> Result<CompressedSrgbTexture2d> {
+    let format = image.format.srgb_format();
+    let texture = CompressedSrgbTexture2d::with_compressed_data(
+        display,
+        &image.levels[0],
+        image.width,
+        image.height,
+        format,
+        CompressedMipmapsOption::EmptyMipmapsMax(image.levels.len() as u32 - 1),
+    )?;
+
+    for (level, data) in image.levels.iter().enumerate().skip(1) {
+        let Some(mipmap) = texture.mipmap(level as u32) else {
+            break;
+        };
+        let (width, height) = level_dimensions(image.width, image.height, level as u32);
+        mipmap
+            .write_compressed_data(
+                Rect {
+                    left: 0,
+                    bottom: 0,
+                    width,
+                    height,
+                },
+                data,
+                width,
+                height,
+                format,
+            )
+            .map_err(|()| eyre!("Could not upload mipmap {}", level))?;
+    }
+
+    Ok(texture)
+}
+
+/// Levels in a full mipmap chain of an image, from its own size down to 1x1
+fn max_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Size of a mipmap level, which is at least 1x1
+fn level_dimensions(width: u32, height: u32, level: u32) -> (u32, u32) {
+    let halve = |size: u32| size.checked_shr(level).unwrap_or(0).max(1);
+    (halve(width), halve(height))
+}
+
+/// Splits the data after a header into the levels of an image, stopping at the last whole one.
+/// Files claiming more levels than a full mipmap chain has only get those.
+fn split_levels(
+    format: BlockFormat,
+    width: u32,
+    height: u32,
+    level_count: u32,
+    mut data: &[u8],
+) -> Result<Vec<Vec<u8>>> {
+    let mut levels = vec![];
+
+    for level in 0..level_count.clamp(1, max_level_count(width, height)) {
+        let (level_width, level_height) = level_dimensions(width, height, level);
+        let size = format.level_size(level_width, level_height);
+        if data.len() < size {
+            break;
+        }
+        levels.push(data[..size].to_vec());
+        data = &data[size..];
+    }
+
+    if levels.is_empty() {
+        bail!("Image data is shorter than its header says");
+    }
+    Ok(levels)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| eyre!("Header is cut short"))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Result<u64> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| eyre!("Header is cut short"))
+}
+
+/// Reads a DDS file of BC1, BC2, BC3 or BC7 data, going by its FourCC or DX10 header
+fn read_dds(bytes: &[u8]) -> Result<CompressedImage> {
+    let height = u32_at(bytes, 12)?;
+    let width = u32_at(bytes, 16)?;
+    let level_count = u32_at(bytes, 28)?;
+    let four_cc = bytes
+        .get(84..88)
+        .ok_or_else(|| eyre!("Header is cut short"))?;
+
+    let (format, data_offset) = match four_cc {
+        b"DXT1" => (BlockFormat::Bc1, 128),
+        b"DXT2" | b"DXT3" => (BlockFormat::Bc2, 128),
+        b"DXT4" | b"DXT5" => (BlockFormat::Bc3, 128),
+        b"DX10" => {
+            let format = match u32_at(bytes, 128)? {
+                70..=72 => BlockFormat::Bc1,
+                73..=75 => BlockFormat::Bc2,
+                76..=78 => BlockFormat::Bc3,
+                97..=99 => BlockFormat::Bc7,
+                dxgi_format => bail!("DXGI format {} isn't supported", dxgi_format),
+            };
+            (format, 148)
+        }
+        _ => bail!(
+            "Format {:?} isn't supported",
+            String::from_utf8_lossy(four_cc)
+        ),
+    };
+
+    let data = bytes
+        .get(data_offset..)
+        .ok_or_else(|| eyre!("Header is cut short"))?;
+    Ok(CompressedImage {
+        format,
+        width,
+        height,
+        levels: split_levels(format, width, height, level_count, data)?,
+    })
+}
+
+/// Reads a KTX2 file of BC1, BC2, BC3 or BC7 data without supercompression
+fn read_ktx2(bytes: &[u8]) -> Result<CompressedImage> {
+    let format = match u32_at(bytes, 12)? {
+        131..=134 => BlockFormat::Bc1,
+        135 | 136 => BlockFormat::Bc2,
+        137 | 138 => BlockFormat::Bc3,
+        145 | 146 => BlockFormat::Bc7,
+        vk_format => bail!("Vulkan format {} isn't supported", vk_format),
+    };
+    let width = u32_at(bytes, 20)?;
+    let height = u32_at(bytes, 24)?;
+    let level_count = u32_at(bytes, 40)?.clamp(1, max_level_count(width, height));
+    if u32_at(bytes, 44)? != 0 {
+        bail!("Supercompressed textures aren't supported");
+    }
+
+    // Each level's offset and length follow the header, largest level first
+    let levels = (0..level_count)
+        .map(|level| {
+            let entry = 80 + level as usize * 24;
+            let offset = u64_at(bytes, entry)? as usize;
+            let length = u64_at(bytes, entry + 8)? as usize;
+            let (level_width, level_height) = level_dimensions(width, height, level);
+            let expected = format.level_size(level_width, level_height);
+            if length != expected {
+                bail!(
+                    "Level {} is {} bytes rather than {}",
+                    level,
+                    length,
+                    expected
+                );
+            }
+
+            offset
+                .checked_add(length)
+                .and_then(|end| bytes.get(offset..end))
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| eyre!("Level {} is cut short", level))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(CompressedImage {
+        format,
+        width,
+        height,
+        levels,
+    })
+}
+
+/// Decodes the largest level of a BC1, BC2 or BC3 image, for GPUs without S3TC support
+pub(crate) fn decompress(image: &CompressedImage) -> Result<RgbaImage> {
+    if image.format == BlockFormat::Bc7 {
+        bail!("BC7 textures can't be decompressed");
+    }
+
+    let (width, height) = (image.width, image.height);
+    let mut decoded = RgbaImage::new(width, height);
+    let blocks_wide = width.div_ceil(4).max(1);
+    let block_size = image.format.block_size();
+
+    for (index, block) in image.levels[0].chunks_exact(block_size).enumerate() {
+        let (block_x, block_y) = (
+            index as u32 % blocks_wide * 4,
+            index as u32 / blocks_wide * 4,
+        );
+
+        let (alpha, color) = match image.format {
+            BlockFormat::Bc1 => ([255; 16], block),
+            BlockFormat::Bc2 => (explicit_alpha(&block[..8]), &block[8..]),
+            _ => (interpolated_alpha(&block[..8]), &block[8..]),
+        };
+        let colors = color_block(color, image.format == BlockFormat::Bc1);
+
+        for (texel, (color, alpha)) in colors.iter().zip(alpha).enumerate() {
+            let (x, y) = (block_x + texel as u32 % 4, block_y + texel as u32 / 4);
+            if x < width && y < height {
+                decoded.put_pixel(x, y, image::Rgba([color[0], color[1], color[2], alpha]));
+            }
+        }
+    }
+
+    Ok(decoded)
+}
+
+fn rgb565(color: u16) -> [u8; 4] {
+    let expand = |value: u16, bits: u32| ((value as u32 * 255) / ((1 << bits) - 1)) as u8;
+    [
+        expand(color >> 11, 5),
+        expand((color >> 5) & 0x3F, 6),
+        expand(color & 0x1F, 5),
+        255,
+    ]
+}
+
+/// The 16 texels of a BC1 style color block. Only BC1 uses the three color mode with a
+/// transparent black, which BC2 and BC3 are always decoded without.
+fn color_block(block: &[u8], bc1: bool) -> [[u8; 4]; 16] {
+    let (color0, color1) = (
+        u16::from_le_bytes([block[0], block[1]]),
+        u16::from_le_bytes([block[2], block[3]]),
+    );
+    let (a, b) = (rgb565(color0), rgb565(color1));
+    let mix = |weight_a: u32, weight_b: u32, total: u32| {
+        let channel = |i: usize| ((a[i] as u32 * weight_a + b[i] as u32 * weight_b) / total) as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+
+    let palette = if color0 > color1 || !bc1 {
+        [a, b, mix(2, 1, 3), mix(1, 2, 3)]
+    } else {
+        [a, b, mix(1, 1, 2), [0, 0, 0, 0]]
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|texel| palette[((indices >> (texel * 2)) & 0b11) as usize])
+}
+
+fn explicit_alpha(block: &[u8]) -> [u8; 16] {
+    std::array::from_fn(|texel| {
+        let nibble = (block[texel / 2] >> (texel % 2 * 4)) & 0xF;
+        nibble * 17
+    })
+}
+
+fn interpolated_alpha(block: &[u8]) -> [u8; 16] {
+    let (a, b) = (block[0] as u32, block[1] as u32);
+    let palette: [u8; 8] = if a > b {
+        std::array::from_fn(|index| match index {
+            0 => a as u8,
+            1 => b as u8,
+            _ => (((8 - index as u32) * a + (index as u32 - 1) * b) / 7) as u8,
+        })
+    } else {
+        std::array::from_fn(|index| match index {
+            0 => a as u8,
+            1 => b as u8,
+            6 => 0,
+            7 => 255,
+            _ => (((6 - index as u32) * a + (index as u32 - 1) * b) / 5) as u8,
+        })
+    };
+
+    let indices = block[2..8]
+        .iter()
+        .rev()
+        .fold(0u64, |bits, &byte| (bits << 8) | byte as u64);
+    std::array::from_fn(|texel| palette[((indices >> (texel * 3)) & 0b111) as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: u16 = 0xF800;
+    const BLUE: u16 = 0x001F;
+
+    /// A BC1 block of `color0` and `color1` whose first four texels use each palette entry in turn
+    fn bc1_block(color0: u16, color1: u16) -> Vec<u8> {
+        let mut block = vec![];
+        block.extend(color0.to_le_bytes());
+        block.extend(color1.to_le_bytes());
+        block.extend([0b11_10_01_00, 0, 0, 0]);
+        block
+    }
+
+    fn dds(width: u32, height: u32, level_count: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0; 128];
+        bytes[..4].copy_from_slice(&DDS_MAGIC);
+        bytes[12..16].copy_from_slice(&height.to_le_bytes());
+        bytes[16..20].copy_from_slice(&width.to_le_bytes());
+        bytes[28..32].copy_from_slice(&level_count.to_le_bytes());
+        bytes[84..88].copy_from_slice(b"DXT1");
+        bytes.extend(data);
+        bytes
+    }
+
+    /// A BC1 KTX2 file with `levels` after the level index, each listed as `lengths` long
+    fn ktx2(width: u32, height: u32, levels: &[Vec<u8>], lengths: &[u64]) -> Vec<u8> {
+        let mut bytes = vec![0; 80 + levels.len() * 24];
+        bytes[..12].copy_from_slice(&KTX2_IDENTIFIER);
+        bytes[12..16].copy_from_slice(&131u32.to_le_bytes());
+        bytes[20..24].copy_from_slice(&width.to_le_bytes());
+        bytes[24..28].copy_from_slice(&height.to_le_bytes());
+        bytes[40..44].copy_from_slice(&(levels.len() as u32).to_le_bytes());
+
+        for (level, (data, length)) in levels.iter().zip(lengths).enumerate() {
+            let entry = 80 + level * 24;
+            let offset = bytes.len() as u64;
+            bytes[entry..entry + 8].copy_from_slice(&offset.to_le_bytes());
+            bytes[entry + 8..entry + 16].copy_from_slice(&length.to_le_bytes());
+            bytes.extend(data);
+        }
+        bytes
+    }
+
+    fn bc1_image(block: Vec<u8>) -> CompressedImage {
+        CompressedImage {
+            format: BlockFormat::Bc1,
+            width: 4,
+            height: 4,
+            levels: vec![block],
+        }
+    }
+
+    fn texel(image: &RgbaImage, x: u32, y: u32) -> [u8; 4] {
+        image.get_pixel(x, y).0
+    }
+
+    #[test]
+    fn bc1_blocks_decode_to_their_four_color_palette() {
+        let decoded = decompress(&bc1_image(bc1_block(RED, BLUE))).unwrap();
+
+        assert_eq!(texel(&decoded, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(texel(&decoded, 1, 0), [0, 0, 255, 255]);
+        assert_eq!(texel(&decoded, 2, 0), [170, 0, 85, 255]);
+        assert_eq!(texel(&decoded, 3, 0), [85, 0, 170, 255]);
+        assert_eq!(texel(&decoded, 0, 3), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bc1_blocks_with_ordered_colors_have_transparent_black() {
+        let decoded = decompress(&bc1_image(bc1_block(BLUE, RED))).unwrap();
+
+        assert_eq!(texel(&decoded, 0, 0), [0, 0, 255, 255]);
+        assert_eq!(texel(&decoded, 1, 0), [255, 0, 0, 255]);
+        assert_eq!(texel(&decoded, 2, 0), [127, 0, 127, 255]);
+        assert_eq!(texel(&decoded, 3, 0), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn bc2_and_bc3_blocks_decode_their_alpha() {
+        // Every texel takes the first alpha of each
+        let mut bc2 = vec![0xFF; 8];
+        bc2[0] = 0x3F;
+        bc2.extend(bc1_block(RED, BLUE));
+        let mut bc3 = vec![200, 100, 0, 0, 0, 0, 0, 0];
+        bc3.extend(bc1_block(RED, BLUE));
+
+        for (format, block, alpha) in [(BlockFormat::Bc2, bc2, 255), (BlockFormat::Bc3, bc3, 200)] {
+            let image = CompressedImage {
+                format,
+                ..bc1_image(block)
+            };
+            let decoded = decompress(&image).unwrap();
+
+            assert_eq!(texel(&decoded, 0, 0), [255, 0, 0, alpha], "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn dds_levels_are_split_by_size() {
+        let image = read_dds(&dds(8, 8, 4, &[0; 56])).unwrap();
+
+        assert_eq!(image.format, BlockFormat::Bc1);
+        assert_eq!((image.width, image.height), (8, 8));
+        let sizes = image.levels.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes, [32, 8, 8, 8]);
+    }
+
+    #[test]
+    fn dds_level_counts_past_a_full_chain_are_capped() {
+        let image = read_dds(&dds(1, 1, u32::MAX, &[0; 8 * 40])).unwrap();
+
+        assert_eq!(image.levels.len(), 1);
+    }
+
+    #[test]
+    fn dds_files_cut_short_are_rejected() {
+        assert!(read_dds(&dds(8, 8, 1, &[0; 16])).is_err());
+        assert!(read_dds(&DDS_MAGIC).is_err());
+        assert!(read_dds(&dds(4, 4, 1, &[])[..100]).is_err());
+    }
+
+    #[test]
+    fn ktx2_levels_are_read_from_the_level_index() {
+        let levels = [vec![1; 32], vec![2; 8]];
+        let image = read_ktx2(&ktx2(8, 8, &levels, &[32, 8])).unwrap();
+
+        assert_eq!(image.format, BlockFormat::Bc1);
+        assert_eq!(image.levels, levels);
+    }
+
+    #[test]
+    fn ktx2_levels_of_the_wrong_size_are_rejected() {
+        let levels = [vec![0; 32], vec![0; 4]];
+
+        assert!(read_ktx2(&ktx2(8, 8, &levels, &[32, 4])).is_err());
+        assert!(read_ktx2(&ktx2(8, 8, &levels, &[32, u64::MAX])).is_err());
+    }
+
+    #[test]
+    fn ktx2_level_counts_past_a_full_chain_are_capped() {
+        let mut bytes = ktx2(1, 1, &[vec![0; 8]], &[8]);
+        bytes[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert_eq!(read_ktx2(&bytes).unwrap().levels.len(), 1);
+    }
+
+    #[test]
+    fn level_dimensions_stop_at_one_texel() {
+        assert_eq!(level_dimensions(256, 64, 3), (32, 8));
+        assert_eq!(level_dimensions(256, 64, 7), (2, 1));
+        assert_eq!(level_dimensions(u32::MAX, 1, 40), (1, 1));
+        assert_eq!(max_level_count(256, 64), 9);
+        assert_eq!(max_level_count(0, 0), 1);
+    }
+}
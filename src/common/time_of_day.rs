@@ -0,0 +1,100 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use cgmath::{InnerSpace, Vector3};
+use palette::{Mix, Srgb};
+
+use crate::colors;
+use crate::maths::smoothstep;
+use crate::scene::Environment;
+
+const NIGHT_SKY: Srgb = Srgb::new(0.01, 0.01, 0.03);
+const DUSK_SKY: Srgb = Srgb::new(0.8, 0.35, 0.15);
+const DAY_SKY: Srgb = Srgb::new(0.35, 0.6, 0.95);
+
+/// Animates the sun and sky of a scene over a day
+#[derive(Clone, Debug)]
+pub struct TimeOfDay {
+    /// Current time in hours, from 0 to 24
+    pub hours: f32,
+    /// How many real seconds a full day lasts
+    pub cycle_length: f32,
+    /// Tilts the sun's path away from straight overhead, in radians
+    pub tilt: f32,
+    pub paused: bool,
+    /// Colour temperature of the sun when it is at the horizon and overhead, in Kelvin
+    pub horizon_temperature: f32,
+    pub zenith_temperature: f32,
+    /// Ambient light that remains when the sun has set
+    pub night_ambient: f32,
+    pub day_ambient: f32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            hours: 10.0,
+            cycle_length: 120.0,
+            tilt: 0.3,
+            paused: false,
+            horizon_temperature: 2000.0,
+            zenith_temperature: 6500.0,
+            night_ambient: 0.03,
+            day_ambient: 0.3,
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn update(&mut self, deltatime: f32) {
+        if self.paused || self.cycle_length <= 0.0 {
+            return;
+        }
+
+        self.hours = (self.hours + deltatime * 24.0 / self.cycle_length) % 24.0;
+    }
+
+    /// Direction pointing towards the sun. The sun rises along +X at 6:00 and is highest at 12:00.
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let angle = self.hours / 24.0 * TAU - FRAC_PI_2;
+
+        Vector3::new(angle.cos(), angle.sin(), self.tilt.sin()).normalize()
+    }
+
+    /// How much sunlight reaches the ground, fading out as the sun crosses the horizon
+    pub fn daylight(&self) -> f32 {
+        smoothstep(-0.05, 0.2, self.sun_direction().y)
+    }
+
+    pub fn sun_color(&self) -> Srgb {
+        let elevation = self.sun_direction().y.clamp(0.0, 1.0);
+
+        colors::from_temperature(
+            self.horizon_temperature
+                + (self.zenith_temperature - self.horizon_temperature) * elevation,
+        )
+    }
+
+    pub fn sky_color(&self) -> Srgb {
+        let elevation = self.sun_direction().y;
+
+        if elevation < 0.0 {
+            DUSK_SKY.mix(NIGHT_SKY, smoothstep(0.0, 0.2, -elevation))
+        } else {
+            DUSK_SKY.mix(DAY_SKY, smoothstep(0.0, 0.3, elevation))
+        }
+    }
+
+    pub fn ambient_intensity(&self) -> f32 {
+        self.night_ambient + (self.day_ambient - self.night_ambient) * self.daylight()
+    }
+
+    /// Writes the current sun and sky into the scene's environment
+    pub fn apply(&self, environment: &mut Environment) {
+        environment.sun_direction = self.sun_direction();
+        environment.sun_color = self.sun_color();
+        environment.sun_intensity = self.daylight();
+        environment.sky_color = self.sky_color();
+        environment.ambient_color = self.sky_color();
+        environment.ambient_intensity = self.ambient_intensity();
+    }
+}
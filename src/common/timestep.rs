@@ -0,0 +1,140 @@
+use std::time::Instant;
+
+/// Runs the simulation at a fixed rate no matter how fast frames are rendered. Each frame call
+/// `advance` and run the returned number of steps, then blend the last two simulation states by
+/// `alpha` when rendering.
+pub struct FixedTimestep {
+    /// Length of a simulation step in seconds
+    pub step: f64,
+    accumulator: f64,
+    last_advance: Option<Instant>,
+}
+
+impl FixedTimestep {
+    /// Frame times are capped so a long stall, e.g. from loading, doesn't cause a burst of steps
+    /// that takes even longer to catch up on
+    const MAX_FRAME_TIME: f64 = 0.25;
+
+    pub fn new(rate: f64) -> Self {
+        Self {
+            step: 1.0 / rate,
+            accumulator: 0.0,
+            last_advance: None,
+        }
+    }
+
+    /// Adds the real time since the last call, returning how many steps are due
+    pub fn advance(&mut self) -> u32 {
+        let frame_time = self.elapsed();
+        self.advance_by(frame_time)
+    }
+
+    /// Real seconds since the last call or `advance`, for advancing by later with `advance_by`
+    pub fn elapsed(&mut self) -> f64 {
+        let now = Instant::now();
+        let frame_time = self
+            .last_advance
+            .map_or(0.0, |last_advance| (now - last_advance).as_secs_f64());
+        self.last_advance = Some(now);
+
+        frame_time
+    }
+
+    /// Adds a chosen frame time, e.g. one played back from a recording, returning how many steps
+    /// are due
+    pub fn advance_by(&mut self, frame_time: f64) -> u32 {
+        self.accumulator += frame_time.min(Self::MAX_FRAME_TIME);
+
+        let steps = (self.accumulator / self.step).floor();
+        self.accumulator -= steps * self.step;
+
+        steps as u32
+    }
+
+    /// Drops any time left over from earlier frames, so a recorded session takes the same steps
+    /// when played back
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+
+    /// How far between the previous and current simulation states the frame being rendered is,
+    /// from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step) as f32
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(60.0)
+    }
+}
+
+/// How fast the simulation runs compared to real time, for pausing, slow motion and stepping
+/// through it a step at a time while debugging. Only frame time given to `FixedTimestep` is
+/// affected, so the camera and editor carry on as normal.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimeControl {
+    /// Simulated seconds per real second, 1 for normal speed
+    pub time_scale: f64,
+    paused: bool,
+    /// Steps asked for while paused, run on the next frame
+    queued_steps: u32,
+}
+
+impl TimeControl {
+    pub fn new() -> Self {
+        Self {
+            time_scale: 1.0,
+            paused: false,
+            queued_steps: 0,
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.queued_steps = 0;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Runs a single step on the next frame, pausing first if running
+    pub fn step_once(&mut self) {
+        self.pause();
+        self.queued_steps += 1;
+    }
+
+    /// Simulated time passing over `frame_time` real seconds
+    pub fn scale(&self, frame_time: f64) -> f64 {
+        if self.paused {
+            0.0
+        } else {
+            frame_time * self.time_scale.max(0.0)
+        }
+    }
+
+    /// Takes the steps asked for by `step_once` since the last call
+    pub fn take_steps(&mut self) -> u32 {
+        std::mem::take(&mut self.queued_steps)
+    }
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
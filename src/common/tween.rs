@@ -0,0 +1,463 @@
+use std::f32::consts::{PI, TAU};
+
+use cgmath::{InnerSpace, Quaternion, Rad, Rotation3, Vector3, VectorSpace};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::entity::World;
+use crate::model::Transform;
+
+/// Steps a tween can finish in one update, so zero length steps can't loop forever
+const MAX_STEPS_PER_UPDATE: usize = 64;
+
+/// Curves shaping how a step moves from its start to its end
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Starts slowly and speeds up
+    QuadIn,
+    /// Starts quickly and slows down
+    QuadOut,
+    QuadInOut,
+    CubicInOut,
+    SineInOut,
+    /// Overshoots the end a little and settles back onto it
+    BackOut,
+}
+
+impl Easing {
+    /// How far along a step is, from 0 to 1, after `t` of its duration. Only `BackOut` leaves
+    /// that range on the way.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Self::Linear => t,
+            Self::QuadIn => t * t,
+            Self::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Self::QuadInOut if t < 0.5 => 2.0 * t * t,
+            Self::QuadInOut => 1.0 - (-2.0 * t + 2.0).powi(2) / 2.0,
+            Self::CubicInOut if t < 0.5 => 4.0 * t * t * t,
+            Self::CubicInOut => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+            Self::SineInOut => -((PI * t).cos() - 1.0) / 2.0,
+            Self::BackOut => {
+                let overshoot = 1.70158;
+                1.0 + (overshoot + 1.0) * (t - 1.0).powi(3) + overshoot * (t - 1.0).powi(2)
+            }
+        }
+    }
+}
+
+/// What a step does to a transform. Each changes one of its fields, leaving the others to
+/// whatever else moves the entity.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TweenChange {
+    MoveTo(Vector3<f32>),
+    MoveBy(Vector3<f32>),
+    RotateTo(Quaternion<f32>),
+    /// Turns around a world space axis, by as many turns as the angle makes
+    RotateBy {
+        axis: Vector3<f32>,
+        angle: Rad<f32>,
+    },
+    ScaleTo(Vector3<f32>),
+    /// Holds still, e.g. keeping a door open
+    Wait,
+}
+
+/// One change made over `duration` seconds, starting from wherever the transform is when the
+/// step begins
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TweenStep {
+    pub change: TweenChange,
+    pub duration: f32,
+    pub easing: Easing,
+}
+
+impl TweenStep {
+    pub fn new(change: TweenChange, duration: f32, easing: Easing) -> Self {
+        Self {
+            change,
+            duration,
+            easing,
+        }
+    }
+
+    pub fn wait(duration: f32) -> Self {
+        Self::new(TweenChange::Wait, duration, Easing::Linear)
+    }
+
+    /// Sets the field it changes to where it is `amount` of the way from `start`
+    fn apply(&self, start: &Transform, amount: f32, transform: &mut Transform) {
+        match self.change {
+            TweenChange::MoveTo(to) => {
+                transform.translation = start.translation.lerp(to, amount);
+            }
+            TweenChange::MoveBy(by) => transform.translation = start.translation + by * amount,
+            TweenChange::RotateTo(to) => {
+                let to = Transform::same_hemisphere(start.rotation, to);
+                transform.rotation = start.rotation.nlerp(to, amount);
+            }
+            TweenChange::RotateBy { axis, angle } => {
+                let turn = Quaternion::from_axis_angle(axis.normalize(), angle * amount);
+                transform.rotation = turn * start.rotation;
+            }
+            TweenChange::ScaleTo(to) => transform.scale = start.scale.lerp(to, amount),
+            TweenChange::Wait => (),
+        }
+    }
+}
+
+/// What a tween does once it's played its last step
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Repeat {
+    /// Stops, e.g. for a door that's opened
+    #[default]
+    Once,
+    /// Starts again from the first step where the last left off, e.g. to keep spinning
+    Loop,
+    /// Plays its steps back to the start and then forwards again, e.g. for an elevator
+    PingPong,
+}
+
+/// Steps played one after another on a transform. Steps can be played backwards, undoing each
+/// as it was done, whether by `Repeat::PingPong` or by `reverse`. Saved along with how far it's
+/// played, so it carries on from there once loaded.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tween {
+    pub steps: Vec<TweenStep>,
+    pub repeat: Repeat,
+    pub paused: bool,
+    /// Step being played
+    index: usize,
+    /// Through the step being played, from 0 to 1
+    progress: f32,
+    backwards: bool,
+    finished: bool,
+    /// Transform each step began from, kept so it can be played back
+    starts: Vec<Option<Transform>>,
+}
+
+impl Tween {
+    pub fn new(steps: Vec<TweenStep>, repeat: Repeat) -> Self {
+        Self {
+            starts: vec![None; steps.len()],
+            steps,
+            repeat,
+            paused: false,
+            index: 0,
+            progress: 0.0,
+            backwards: false,
+            finished: false,
+        }
+    }
+
+    /// Turns around `axis` forever, once every `period` seconds, e.g. for a pickup
+    pub fn spin(axis: Vector3<f32>, period: f32) -> Self {
+        let step = TweenStep::new(
+            TweenChange::RotateBy {
+                axis,
+                angle: Rad(TAU),
+            },
+            period,
+            Easing::Linear,
+        );
+
+        Self::new(vec![step], Repeat::Loop)
+    }
+
+    /// Floats up by `height` and back down forever, once every `period` seconds
+    pub fn bob(height: f32, period: f32) -> Self {
+        let step = TweenStep::new(
+            TweenChange::MoveBy(Vector3::unit_y() * height),
+            period / 2.0,
+            Easing::SineInOut,
+        );
+
+        Self::new(vec![step], Repeat::PingPong)
+    }
+
+    /// Moves by `offset` over `duration`, waits for `pause` and comes back, then waits again
+    /// before going round, e.g. for an elevator or a sliding platform
+    pub fn shuttle(offset: Vector3<f32>, duration: f32, pause: f32) -> Self {
+        let steps = vec![
+            TweenStep::new(TweenChange::MoveBy(offset), duration, Easing::QuadInOut),
+            TweenStep::wait(pause),
+            TweenStep::new(TweenChange::MoveBy(-offset), duration, Easing::QuadInOut),
+            TweenStep::wait(pause),
+        ];
+
+        Self::new(steps, Repeat::Loop)
+    }
+
+    /// Whether every step has been played, and it isn't going to repeat
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Plays the steps back the other way from where it is, e.g. to close a door while it's
+    /// opening or once it's open
+    pub fn reverse(&mut self) {
+        self.backwards = !self.backwards;
+        self.finished = false;
+    }
+
+    /// Moves `transform` along by `deltatime` seconds, carrying time left over at the end of a
+    /// step on into the next
+    pub fn update(&mut self, transform: &mut Transform, deltatime: f32) {
+        if self.paused || self.steps.is_empty() {
+            return;
+        }
+
+        let mut time = deltatime;
+        for _ in 0..MAX_STEPS_PER_UPDATE {
+            if self.finished {
+                return;
+            }
+
+            let step = self.steps[self.index];
+            if !self.backwards && self.progress == 0.0 {
+                self.starts[self.index] = Some(transform.clone());
+            }
+            let start = self.starts[self.index]
+                .get_or_insert_with(|| transform.clone())
+                .clone();
+
+            let change = if step.duration > 0.0 {
+                time / step.duration
+            } else {
+                f32::INFINITY
+            };
+            let unclamped = if self.backwards {
+                self.progress - change
+            } else {
+                self.progress + change
+            };
+            self.progress = unclamped.clamp(0.0, 1.0);
+            if step.duration > 0.0 {
+                time = (unclamped - self.progress).abs() * step.duration;
+            }
+
+            step.apply(&start, step.easing.apply(self.progress), transform);
+
+            let at_end = if self.backwards {
+                self.progress <= 0.0
+            } else {
+                self.progress >= 1.0
+            };
+            if !at_end {
+                return;
+            }
+            self.next_step();
+        }
+    }
+
+    /// Moves on to the step after the one just played in whichever direction it's playing, or
+    /// repeats or finishes after the last
+    fn next_step(&mut self) {
+        let last = self.steps.len() - 1;
+
+        match (self.backwards, self.repeat) {
+            (false, _) if self.index < last => {
+                self.index += 1;
+                self.progress = 0.0;
+            }
+            (true, _) if self.index > 0 => {
+                self.index -= 1;
+                self.progress = 1.0;
+            }
+            (false, Repeat::Loop) => {
+                self.index = 0;
+                self.progress = 0.0;
+            }
+            (false, Repeat::PingPong) | (true, Repeat::PingPong) => {
+                self.backwards = !self.backwards;
+            }
+            _ => self.finished = true,
+        }
+    }
+}
+
+/// Component playing tweens on its entity's `Transform` at the same time, e.g. spinning while
+/// bobbing up and down. Those that finish are kept, so they can be reversed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tweens(pub Vec<Tween>);
+
+impl From<Tween> for Tweens {
+    fn from(tween: Tween) -> Self {
+        Self(vec![tween])
+    }
+}
+
+/// Moves every entity with `Tweens` along by `deltatime`, called each fixed step before physics
+/// so kinematic bodies follow
+pub fn update(world: &mut World, deltatime: f32) {
+    let entities = world
+        .query::<Tweens>()
+        .map(|(entity, _)| entity)
+        .collect_vec();
+
+    for entity in entities {
+        let Some(mut transform) = world.get::<Transform>(entity).cloned() else {
+            continue;
+        };
+
+        for tween in world.get_mut::<Tweens>(entity).unwrap().0.iter_mut() {
+            tween.update(&mut transform, deltatime);
+        }
+        world.insert(entity, transform);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Zero;
+
+    use super::*;
+
+    const EASINGS: [Easing; 7] = [
+        Easing::Linear,
+        Easing::QuadIn,
+        Easing::QuadOut,
+        Easing::QuadInOut,
+        Easing::CubicInOut,
+        Easing::SineInOut,
+        Easing::BackOut,
+    ];
+
+    fn assert_near(actual: Vector3<f32>, expected: Vector3<f32>) {
+        assert!(
+            (actual - expected).magnitude() < 1e-4,
+            "{:?} isn't {:?}",
+            actual,
+            expected
+        );
+    }
+
+    fn move_by(x: f32, duration: f32) -> TweenStep {
+        TweenStep::new(
+            TweenChange::MoveBy(Vector3::unit_x() * x),
+            duration,
+            Easing::Linear,
+        )
+    }
+
+    #[test]
+    fn easings_start_and_end_in_place() {
+        for easing in EASINGS {
+            assert!(easing.apply(0.0).abs() < 1e-6, "{:?}", easing);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-6, "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn easings_clamp_time_outside_the_step() {
+        for easing in EASINGS {
+            assert_eq!(easing.apply(-1.0), easing.apply(0.0), "{:?}", easing);
+            assert_eq!(easing.apply(2.0), easing.apply(1.0), "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn easings_shape_the_middle() {
+        assert_eq!(Easing::Linear.apply(0.25), 0.25);
+        assert_eq!(Easing::QuadIn.apply(0.5), 0.25);
+        assert_eq!(Easing::QuadOut.apply(0.5), 0.75);
+        for easing in [Easing::QuadInOut, Easing::CubicInOut, Easing::SineInOut] {
+            assert!((easing.apply(0.5) - 0.5).abs() < 1e-6, "{:?}", easing);
+            assert!(easing.apply(0.25) < 0.25, "{:?}", easing);
+        }
+        assert!(Easing::BackOut.apply(0.8) > 1.0);
+    }
+
+    #[test]
+    fn update_carries_left_over_time_into_the_next_step() {
+        let mut tween = Tween::new(vec![move_by(1.0, 1.0), move_by(2.0, 1.0)], Repeat::Once);
+        let mut transform = Transform::default();
+
+        tween.update(&mut transform, 1.5);
+
+        assert_near(transform.translation, Vector3::new(2.0, 0.0, 0.0));
+        assert!(!tween.finished());
+    }
+
+    #[test]
+    fn update_finishes_after_the_last_step() {
+        let mut tween = Tween::new(vec![move_by(1.0, 1.0)], Repeat::Once);
+        let mut transform = Transform::default();
+
+        tween.update(&mut transform, 2.0);
+        tween.update(&mut transform, 1.0);
+
+        assert!(tween.finished());
+        assert_near(transform.translation, Vector3::unit_x());
+    }
+
+    #[test]
+    fn update_does_nothing_while_paused() {
+        let mut tween = Tween::new(vec![move_by(1.0, 1.0)], Repeat::Once);
+        tween.paused = true;
+        let mut transform = Transform::default();
+
+        tween.update(&mut transform, 0.5);
+
+        assert_near(transform.translation, Vector3::zero());
+    }
+
+    #[test]
+    fn ping_pong_comes_back_to_the_start() {
+        let mut tween = Tween::bob(1.0, 2.0);
+        let mut transform = Transform::default();
+
+        tween.update(&mut transform, 1.0);
+        assert_near(transform.translation, Vector3::unit_y());
+        tween.update(&mut transform, 1.0);
+        assert_near(transform.translation, Vector3::zero());
+        assert!(!tween.finished());
+    }
+
+    #[test]
+    fn reverse_undoes_what_was_played() {
+        let mut tween = Tween::new(vec![move_by(1.0, 1.0)], Repeat::Once);
+        let mut transform = Transform::default();
+
+        tween.update(&mut transform, 0.5);
+        tween.reverse();
+        tween.update(&mut transform, 0.5);
+
+        assert_near(transform.translation, Vector3::zero());
+    }
+
+    #[test]
+    fn shuttle_waits_at_both_ends() {
+        let mut tween = Tween::shuttle(Vector3::unit_x(), 1.0, 1.0);
+        let mut transform = Transform::default();
+
+        // Out, then waiting at the far end
+        tween.update(&mut transform, 1.5);
+        assert_near(transform.translation, Vector3::unit_x());
+        // Half way back
+        tween.update(&mut transform, 1.0);
+        assert_near(transform.translation, Vector3::unit_x() * 0.5);
+        // Back, then waiting at the start
+        tween.update(&mut transform, 1.0);
+        assert_near(transform.translation, Vector3::zero());
+        // Half way out again
+        tween.update(&mut transform, 1.0);
+        assert_near(transform.translation, Vector3::unit_x() * 0.5);
+    }
+
+    #[test]
+    fn zero_length_loops_stop_each_update() {
+        let mut tween = Tween::new(vec![move_by(1.0, 0.0)], Repeat::Loop);
+        let mut transform = Transform::default();
+
+        tween.update(&mut transform, 1.0);
+
+        assert_near(
+            transform.translation,
+            Vector3::unit_x() * MAX_STEPS_PER_UPDATE as f32,
+        );
+    }
+}
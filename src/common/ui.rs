@@ -0,0 +1,372 @@
+use std::f32::consts::FRAC_PI_4;
+
+use cgmath::{Vector2, VectorSpace};
+use color_eyre::Result;
+use glium::glutin::surface::WindowSurface;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::{
+    implement_vertex, uniform, Blend, Display, DrawParameters, Program, Surface, VertexBuffer,
+};
+use palette::{Srgb, Srgba, WithAlpha};
+use winit::dpi::PhysicalSize;
+
+use crate::assets::{Assets, Handle};
+use crate::maths;
+
+/// Seconds a hit marker stays on screen, fading out over that time
+const HIT_MARKER_DURATION: f32 = 0.25;
+
+/// Seconds the health of whatever was shot stays on screen after the last hit
+const TARGET_HEALTH_DURATION: f32 = 3.0;
+
+/// Point of the window a sprite is placed relative to, so it stays put as the window is resized
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Where the anchor is across and down the window, from 0 to 1
+    fn fraction(self) -> Vector2<f32> {
+        let (x, y) = match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        };
+
+        Vector2::new(x, y)
+    }
+}
+
+/// A flat coloured rectangle drawn over the scene, measured in pixels with y pointing down
+#[derive(Copy, Clone, Debug)]
+pub struct Sprite {
+    pub anchor: Anchor,
+    /// From the anchor to the sprite's center
+    pub offset: Vector2<f32>,
+    pub size: Vector2<f32>,
+    /// Radians clockwise around the sprite's center
+    pub rotation: f32,
+    pub color: Srgba,
+}
+
+impl Sprite {
+    pub fn new(anchor: Anchor, offset: Vector2<f32>, size: Vector2<f32>, color: Srgba) -> Self {
+        Self {
+            anchor,
+            offset,
+            size,
+            rotation: 0.0,
+            color,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Corner {
+    corner: [f32; 2],
+}
+implement_vertex!(Corner, corner);
+
+#[derive(Copy, Clone)]
+struct SpriteInstance {
+    center: [f32; 2],
+    size: [f32; 2],
+    rotation: f32,
+    color: [f32; 4],
+}
+implement_vertex!(SpriteInstance, center, size, rotation, color);
+
+/// Draws sprites in one instanced draw call, laid out for the window's current size
+pub struct SpriteRenderer {
+    program: Handle<Program>,
+    quad: VertexBuffer<Corner>,
+    /// Grows to fit the most sprites drawn at once
+    instances: Option<VertexBuffer<SpriteInstance>>,
+    window_size: Vector2<f32>,
+}
+
+impl SpriteRenderer {
+    pub fn new(
+        assets: &mut Assets,
+        display: &Display<WindowSurface>,
+        window_size: PhysicalSize<u32>,
+    ) -> Result<Self> {
+        let program = assets.load_program(
+            "assets/shaders/ui/sprite.vert",
+            "assets/shaders/ui/sprite.frag",
+            display,
+        )?;
+
+        let quad = VertexBuffer::new(
+            display,
+            &[[-0.5, -0.5], [0.5, -0.5], [-0.5, 0.5], [0.5, 0.5]].map(|corner| Corner { corner }),
+        )?;
+
+        let mut renderer = Self {
+            program,
+            quad,
+            instances: None,
+            window_size: Vector2::new(0.0, 0.0),
+        };
+        renderer.resize(window_size);
+
+        Ok(renderer)
+    }
+
+    /// Lays sprites out for a new window size, called whenever the window is resized
+    pub fn resize(&mut self, window_size: PhysicalSize<u32>) {
+        self.window_size = Vector2::new(window_size.width as f32, window_size.height as f32);
+    }
+
+    /// Draws the sprites over whatever is on the target, later ones on top
+    pub fn render<S: Surface>(
+        &mut self,
+        display: &Display<WindowSurface>,
+        target: &mut S,
+        assets: &Assets,
+        sprites: &[Sprite],
+    ) -> Result<()> {
+        let count = sprites.len();
+        if count == 0 {
+            return Ok(());
+        }
+
+        if self
+            .instances
+            .as_ref()
+            .map_or(true, |instances| instances.len() < count)
+        {
+            self.instances = Some(VertexBuffer::empty_dynamic(
+                display,
+                count.next_power_of_two(),
+            )?);
+        }
+
+        let instances = self.instances.as_ref().unwrap().slice(0..count).unwrap();
+        instances.write(
+            &sprites
+                .iter()
+                .map(|sprite| {
+                    let anchor = sprite.anchor.fraction();
+                    let center =
+                        Vector2::new(anchor.x * self.window_size.x, anchor.y * self.window_size.y)
+                            + sprite.offset;
+                    let color = sprite.color;
+
+                    SpriteInstance {
+                        center: center.into(),
+                        size: sprite.size.into(),
+                        rotation: sprite.rotation,
+                        color: [color.red, color.green, color.blue, color.alpha],
+                    }
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        // Pixels with y pointing down, so the top left of the window is at the origin
+        let projection = cgmath::ortho(0.0, self.window_size.x, self.window_size.y, 0.0, -1.0, 1.0);
+
+        target.draw(
+            (&self.quad, instances.per_instance().unwrap()),
+            &NoIndices(PrimitiveType::TriangleStrip),
+            assets.program(self.program),
+            &uniform! {
+                projection: maths::raw_matrix(projection),
+            },
+            &DrawParameters {
+                blend: Blend::alpha_blending(),
+                ..DrawParameters::default()
+            },
+        )?;
+
+        Ok(())
+    }
+}
+
+/// The in-game overlay: a crosshair, hit markers when shots land, the player's health bar and
+/// that of whatever they last shot
+pub struct Hud {
+    pub visible: bool,
+    /// Fraction of the player's health left, from 0 to 1, with no health bar when `None`
+    pub health: Option<f32>,
+    pub color: Srgb,
+    /// Seconds left of the current hit marker
+    hit_marker: f32,
+    /// Fraction of health the last thing shot has left, and for how many more seconds it's shown
+    target_health: Option<(f32, f32)>,
+    renderer: SpriteRenderer,
+}
+
+impl Hud {
+    /// Crosshair arms, from the center to the start of each and how long and thick they are
+    const CROSSHAIR_GAP: f32 = 6.0;
+    const CROSSHAIR_LENGTH: f32 = 10.0;
+    const CROSSHAIR_THICKNESS: f32 = 2.0;
+
+    const HEALTH_BAR_SIZE: Vector2<f32> = Vector2::new(240.0, 16.0);
+    /// Between the health bar and the window's bottom left corner
+    const HEALTH_BAR_MARGIN: f32 = 24.0;
+
+    const TARGET_BAR_SIZE: Vector2<f32> = Vector2::new(160.0, 10.0);
+    /// Between the target's health bar and the top of the window
+    const TARGET_BAR_MARGIN: f32 = 48.0;
+
+    pub fn new(
+        assets: &mut Assets,
+        display: &Display<WindowSurface>,
+        window_size: PhysicalSize<u32>,
+    ) -> Result<Self> {
+        Ok(Self {
+            visible: true,
+            health: None,
+            color: Srgb::new(1.0, 1.0, 1.0),
+            hit_marker: 0.0,
+            target_health: None,
+            renderer: SpriteRenderer::new(assets, display, window_size)?,
+        })
+    }
+
+    pub fn resize(&mut self, window_size: PhysicalSize<u32>) {
+        self.renderer.resize(window_size);
+    }
+
+    /// Flashes a hit marker around the crosshair, e.g. when a shot damages something
+    pub fn show_hit_marker(&mut self) {
+        self.hit_marker = HIT_MARKER_DURATION;
+    }
+
+    /// Shows how much health what was just shot has left, as a fraction from 0 to 1
+    pub fn show_target_health(&mut self, health: f32) {
+        self.target_health = Some((health, TARGET_HEALTH_DURATION));
+    }
+
+    /// Fades out the hit marker and the target's health, called once per frame
+    pub fn update(&mut self, deltatime: f32) {
+        self.hit_marker = (self.hit_marker - deltatime).max(0.0);
+
+        if let Some((_, remaining)) = &mut self.target_health {
+            *remaining -= deltatime;
+            if *remaining <= 0.0 {
+                self.target_health = None;
+            }
+        }
+    }
+
+    /// Everything the HUD is showing right now, back to front
+    pub fn sprites(&self) -> Vec<Sprite> {
+        let mut sprites = vec![];
+
+        let distance = Self::CROSSHAIR_GAP + Self::CROSSHAIR_LENGTH / 2.0;
+        let color = self.color.with_alpha(0.9);
+        for (direction, size) in [
+            (Vector2::unit_x(), Vector2::new(1.0, 0.0)),
+            (-Vector2::unit_x(), Vector2::new(1.0, 0.0)),
+            (Vector2::unit_y(), Vector2::new(0.0, 1.0)),
+            (-Vector2::unit_y(), Vector2::new(0.0, 1.0)),
+        ] {
+            let thickness = Vector2::new(1.0 - size.x, 1.0 - size.y) * Self::CROSSHAIR_THICKNESS;
+
+            sprites.push(Sprite::new(
+                Anchor::Center,
+                direction * distance,
+                size * Self::CROSSHAIR_LENGTH + thickness,
+                color,
+            ));
+        }
+
+        if self.hit_marker > 0.0 {
+            let alpha = self.hit_marker / HIT_MARKER_DURATION;
+
+            for (x, y) in [(1.0, 1.0), (-1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)] {
+                sprites.push(Sprite {
+                    rotation: FRAC_PI_4 * x * y,
+                    ..Sprite::new(
+                        Anchor::Center,
+                        Vector2::new(x, y) * distance,
+                        Vector2::new(Self::CROSSHAIR_LENGTH, Self::CROSSHAIR_THICKNESS),
+                        Srgb::new(1.0, 0.2, 0.2).with_alpha(alpha),
+                    )
+                });
+            }
+        }
+
+        if let Some(health) = self.health {
+            let size = Self::HEALTH_BAR_SIZE;
+            let corner = Vector2::new(Self::HEALTH_BAR_MARGIN, -Self::HEALTH_BAR_MARGIN);
+            let center = corner + Vector2::new(size.x, -size.y) / 2.0;
+
+            sprites.extend(Self::health_bar(Anchor::BottomLeft, center, size, health));
+        }
+
+        if let Some((health, _)) = self.target_health {
+            let size = Self::TARGET_BAR_SIZE;
+            let center = Vector2::new(0.0, Self::TARGET_BAR_MARGIN + size.y / 2.0);
+
+            sprites.extend(Self::health_bar(Anchor::Top, center, size, health));
+        }
+
+        sprites
+    }
+
+    /// A bar filled from the left with how much `health` is left, turning from green to red as it
+    /// empties, over a dark background
+    fn health_bar(
+        anchor: Anchor,
+        center: Vector2<f32>,
+        size: Vector2<f32>,
+        health: f32,
+    ) -> [Sprite; 2] {
+        let health = health.clamp(0.0, 1.0);
+        let border = 2.0;
+        let inner = size - Vector2::new(border, border) * 2.0;
+        let fill = Vector2::new(inner.x * health, inner.y);
+        let [red, green] = Vector2::new(0.9, 0.1)
+            .lerp(Vector2::new(0.2, 0.8), health)
+            .into();
+
+        [
+            Sprite::new(
+                anchor,
+                center,
+                size,
+                Srgb::new(0.0, 0.0, 0.0).with_alpha(0.5),
+            ),
+            Sprite::new(
+                anchor,
+                center + Vector2::new((fill.x - inner.x) / 2.0, 0.0),
+                fill,
+                Srgb::new(red, green, 0.1).with_alpha(0.9),
+            ),
+        ]
+    }
+
+    pub fn render<S: Surface>(
+        &mut self,
+        display: &Display<WindowSurface>,
+        target: &mut S,
+        assets: &Assets,
+    ) -> Result<()> {
+        if !self.visible {
+            return Ok(());
+        }
+
+        let sprites = self.sprites();
+        self.renderer.render(display, target, assets, &sprites)
+    }
+}
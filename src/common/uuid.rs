@@ -1,10 +1,10 @@
-use std::hash::{Hash, Hasher};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
-use once_cell::sync::OnceCell;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
-static CURRENT: OnceCell<u128> = OnceCell::new();
-
-#[derive(PartialEq, Eq)]
+/// A random 128 bit identifier, unique across runs so it can be saved and referred to later
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct UUID(u128);
 
 impl Default for UUID {
@@ -15,12 +15,54 @@ impl Default for UUID {
 
 impl UUID {
     pub fn new() -> Self {
-        Self(CURRENT.get_or_init(|| 0_u128) + 1)
+        Self(fastrand::u128(..))
+    }
+}
+
+// Lets entities be stored in other libraries' user data, such as the physics engine's
+impl From<UUID> for u128 {
+    fn from(uuid: UUID) -> Self {
+        uuid.0
+    }
+}
+
+impl From<u128> for UUID {
+    fn from(bits: u128) -> Self {
+        Self(bits)
+    }
+}
+
+impl Display for UUID {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for UUID {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(u128::from_str_radix(s, 16)?))
+    }
+}
+
+// Stored as a hex string, as 128 bit numbers aren't supported everywhere JSON is read
+impl Serialize for UUID {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
     }
 }
 
-impl Hash for UUID {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.0.hash(state)
+impl<'de> Deserialize<'de> for UUID {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
     }
 }
@@ -1,10 +1,20 @@
+use bytemuck::{Pod, Zeroable};
 use glium::implement_vertex;
 
+/// Laid out in the order of its fields, as the wgpu backend describes it by offset
+#[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct Vertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
     pub tex_coord: [f32; 2],
+    /// Direction of increasing U, with W giving the handedness of the bitangent. Zero when the
+    /// model has no tangents, which turns off normal mapping.
+    pub tangent: [f32; 4],
+    /// Indices of the joints the vertex follows, stored as floats for the vertex shader
+    pub joints: [f32; 4],
+    /// How much each joint moves the vertex. All zero for vertices that aren't skinned.
+    pub weights: [f32; 4],
 }
 
 impl Default for Vertex {
@@ -13,8 +23,15 @@ impl Default for Vertex {
             position: [0.0, 0.0, 0.0],
             normal: [0.0, 0.0, 0.0],
             tex_coord: [0.0, 0.0],
+            tangent: [0.0, 0.0, 0.0, 0.0],
+            joints: [0.0, 0.0, 0.0, 0.0],
+            weights: [0.0, 0.0, 0.0, 0.0],
         }
     }
 }
 
-implement_vertex!(Vertex, position, normal, tex_coord);
+implement_vertex!(Vertex, position, normal, tex_coord, tangent, joints, weights);
+
+// Only made of floats, so without padding and valid for any bytes
+unsafe impl Zeroable for Vertex {}
+unsafe impl Pod for Vertex {}
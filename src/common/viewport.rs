@@ -0,0 +1,132 @@
+use color_eyre::Result;
+use glium::framebuffer::{DepthRenderBuffer, SimpleFrameBuffer};
+use glium::glutin::surface::WindowSurface;
+use glium::texture::{DepthFormat, MipmapsOption, UncompressedFloatFormat};
+use glium::uniforms::MagnifySamplerFilter;
+use glium::{BlitTarget, Display, Rect, Surface, Texture2d};
+
+/// Rectangle of a target a view is drawn into, in fractions of its size from the bottom left so
+/// it holds across resizes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub const FULL: Self = Self {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    /// Where the `index`th of `count` local players is drawn: two players side by side, three or
+    /// four in quarters from the top left, leaving the last quarter empty for three
+    pub fn split(count: usize, index: usize) -> Self {
+        match count {
+            0 | 1 => Self::FULL,
+            2 => Self {
+                x: index as f32 * 0.5,
+                y: 0.0,
+                width: 0.5,
+                height: 1.0,
+            },
+            _ => Self {
+                x: (index % 2) as f32 * 0.5,
+                y: 0.5 - (index / 2) as f32 * 0.5,
+                width: 0.5,
+                height: 0.5,
+            },
+        }
+    }
+
+    /// In pixels of a target of `size`, at least one pixel across
+    pub fn rect(&self, size: (u32, u32)) -> Rect {
+        let (width, height) = (size.0 as f32, size.1 as f32);
+        let left = (self.x * width).round() as u32;
+        let bottom = (self.y * height).round() as u32;
+
+        Rect {
+            left,
+            bottom,
+            width: ((self.width * width).round() as u32).max(1),
+            height: ((self.height * height).round() as u32).max(1),
+        }
+    }
+
+    /// Width over height of the view in a target of `size`, for its camera's projection
+    pub fn aspect_ratio(&self, size: (u32, u32)) -> f32 {
+        let rect = self.rect(size);
+
+        rect.width as f32 / rect.height as f32
+    }
+
+    /// Whether a point given in pixels from the top left of a target of `size`, such as the
+    /// cursor, is inside the view
+    pub fn contains(&self, point: (f32, f32), size: (u32, u32)) -> bool {
+        let x = point.0 / size.0 as f32;
+        let y = 1.0 - point.1 / size.1 as f32;
+
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+/// Offscreen target a view is drawn into before it's copied into its viewport, as the scene is
+/// drawn as though it were the whole target. HDR, so post-processing still has the full range.
+pub struct ViewTarget {
+    color: Texture2d,
+    depth: DepthRenderBuffer,
+}
+
+impl ViewTarget {
+    pub fn new(display: &Display<WindowSurface>, size: (u32, u32)) -> Result<Self> {
+        Ok(Self {
+            color: Texture2d::empty_with_format(
+                display,
+                UncompressedFloatFormat::F16F16F16F16,
+                MipmapsOption::NoMipmap,
+                size.0,
+                size.1,
+            )?,
+            depth: DepthRenderBuffer::new(display, DepthFormat::I24, size.0, size.1)?,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.color.width(), self.color.height())
+    }
+
+    pub fn framebuffer(&self, display: &Display<WindowSurface>) -> Result<SimpleFrameBuffer> {
+        Ok(SimpleFrameBuffer::with_depth_buffer(
+            display,
+            &self.color,
+            &self.depth,
+        )?)
+    }
+
+    /// Copies what was drawn into `rect` of `target`
+    pub fn blit<S: Surface>(&self, target: &S, rect: Rect) {
+        let (width, height) = self.size();
+        let source = Rect {
+            left: 0,
+            bottom: 0,
+            width,
+            height,
+        };
+
+        self.color.as_surface().blit_color(
+            &source,
+            target,
+            &BlitTarget {
+                left: rect.left,
+                bottom: rect.bottom,
+                width: rect.width as i32,
+                height: rect.height as i32,
+            },
+            MagnifySamplerFilter::Nearest,
+        );
+    }
+}
@@ -0,0 +1,378 @@
+use std::sync::Arc;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use itertools::Itertools;
+use rapier3d::prelude::vector;
+use serde::{Deserialize, Serialize};
+
+use crate::collision::CollisionLayers;
+use crate::decals::DecalKind;
+use crate::gameplay::{Damage, Health};
+use crate::maths;
+use crate::maths::Ray;
+use crate::model::{Model, Transform};
+use crate::particles::ParticleEmitter;
+use crate::pool::Lifetime;
+use crate::scene::Scene;
+use crate::simulation::{RaycastHit, Simulation};
+use crate::uuid::UUID;
+
+/// How a weapon's shots travel
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FireMode {
+    /// Instantly hits the first thing along the aim, up to `range` away
+    Hitscan { range: f32 },
+    /// Spawns a `Projectile` moving at `speed` units per second for up to `lifetime` seconds
+    Projectile { speed: f32, lifetime: f32 },
+}
+
+/// What a weapon does each time it fires
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Weapon {
+    pub name: String,
+    /// Shots per second while the trigger is held
+    pub fire_rate: f32,
+    /// Degrees each pellet can stray from the aim
+    pub spread: f32,
+    /// Shots fired at once, each dealing `damage`
+    pub pellets: u32,
+    pub damage: f32,
+    /// How hard each hit pushes rigid bodies
+    pub impulse: f32,
+    pub mode: FireMode,
+    /// Left on surfaces that are hit
+    #[serde(default)]
+    pub decal: DecalKind,
+    /// Degrees the aim kicks up each time it fires
+    #[serde(default)]
+    pub recoil: f32,
+    /// Whether its shots explode where they hit, shaking the view of those nearby
+    #[serde(default)]
+    pub explosive: bool,
+}
+
+impl Weapon {
+    pub fn rifle() -> Self {
+        Self {
+            name: "Rifle".to_owned(),
+            fire_rate: 8.0,
+            spread: 1.0,
+            pellets: 1,
+            damage: 10.0,
+            impulse: 0.5,
+            mode: FireMode::Hitscan { range: 100.0 },
+            decal: DecalKind::BulletHole,
+            recoil: 0.6,
+            explosive: false,
+        }
+    }
+
+    pub fn shotgun() -> Self {
+        Self {
+            name: "Shotgun".to_owned(),
+            fire_rate: 1.2,
+            spread: 6.0,
+            pellets: 8,
+            damage: 6.0,
+            impulse: 0.3,
+            mode: FireMode::Hitscan { range: 30.0 },
+            decal: DecalKind::BulletHole,
+            recoil: 4.0,
+            explosive: false,
+        }
+    }
+
+    pub fn launcher() -> Self {
+        Self {
+            name: "Launcher".to_owned(),
+            fire_rate: 1.5,
+            spread: 0.0,
+            pellets: 1,
+            damage: 50.0,
+            impulse: 3.0,
+            mode: FireMode::Projectile {
+                speed: 20.0,
+                lifetime: 5.0,
+            },
+            decal: DecalKind::Scorch,
+            recoil: 2.5,
+            explosive: true,
+        }
+    }
+}
+
+/// Component moving its entity in a straight line until it hits something, fired with a
+/// `Lifetime` so it goes if it doesn't
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Projectile {
+    pub velocity: Vector3<f32>,
+    pub damage: f32,
+    pub impulse: f32,
+    /// Who fired it, which it passes through. `None` for the player.
+    pub owner: Option<UUID>,
+    pub decal: DecalKind,
+    pub explosive: bool,
+}
+
+/// A shot or projectile hitting an entity
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hit {
+    pub entity: UUID,
+    pub point: Point3<f32>,
+    /// Of the surface that was hit
+    pub normal: Vector3<f32>,
+    /// Direction the shot was travelling in
+    pub direction: Vector3<f32>,
+    pub damage: f32,
+    pub impulse: f32,
+    /// Who fired it, `None` for the player
+    pub source: Option<UUID>,
+    /// Left where it hit, unless what was hit has `Health`
+    pub decal: DecalKind,
+    /// Whether it exploded where it hit
+    pub explosive: bool,
+}
+
+/// Fires a weapon from the scene's camera, moves projectiles and sends the damage they deal
+pub struct WeaponSystem {
+    pub weapon: Weapon,
+    /// Drawn for each projectile, which is invisible without one
+    pub projectile_model: Option<Arc<Model>>,
+    /// Entity holding the weapon, which its shots pass through. `None` for the player, who has
+    /// no entity.
+    pub owner: Option<UUID>,
+    /// Seconds until the weapon can fire again
+    cooldown: f32,
+    fired: bool,
+}
+
+impl WeaponSystem {
+    /// Size projectile models are drawn at
+    const PROJECTILE_SCALE: f32 = 0.1;
+    /// Pool projectiles are recycled in, as every shot of a projectile weapon spawns one
+    pub const PROJECTILE_POOL: &'static str = "projectile";
+
+    pub fn new(weapon: Weapon) -> Self {
+        Self {
+            weapon,
+            projectile_model: None,
+            owner: None,
+            cooldown: 0.0,
+            fired: false,
+        }
+    }
+
+    /// Switches weapon, keeping the cooldown so switching can't fire faster
+    pub fn equip(&mut self, weapon: Weapon) {
+        self.weapon = weapon;
+    }
+
+    /// Whether the weapon fired during the last update, e.g. to play its sound
+    pub fn fired(&self) -> bool {
+        self.fired
+    }
+
+    /// Advances by a fixed step, firing while `trigger` is held. Sends every hit this step through
+    /// the scene's events, along with the `Damage` each one deals for `gameplay::update` to apply.
+    pub fn update(&mut self, scene: &mut Scene, deltatime: f32, trigger: bool) {
+        let mut hits = vec![];
+
+        self.cooldown = (self.cooldown - deltatime).max(0.0);
+        self.fired = trigger && self.cooldown == 0.0;
+        if self.fired {
+            self.fire(scene, &mut hits);
+            self.cooldown = 1.0 / self.weapon.fire_rate;
+        }
+
+        Self::update_projectiles(scene, deltatime, &mut hits);
+        Self::resolve_hits(scene, &hits);
+
+        for hit in hits {
+            scene.events.send(hit);
+        }
+    }
+
+    fn fire(&self, scene: &mut Scene, hits: &mut Vec<Hit>) {
+        let origin = scene.camera.position;
+        let aim = scene.camera.forward_direction.normalize();
+
+        for _ in 0..self.weapon.pellets {
+            let direction = maths::random_in_cone(aim, self.weapon.spread.to_radians());
+
+            match self.weapon.mode {
+                FireMode::Hitscan { range } => {
+                    if let Some(hit) = first_hit(
+                        &scene.simulation,
+                        &Ray::new(origin, direction),
+                        range,
+                        CollisionLayers::PROJECTILE,
+                        |other| Some(other) != self.owner,
+                    ) {
+                        hits.push(Hit {
+                            entity: hit.entity,
+                            point: origin + direction * hit.distance,
+                            normal: hit.normal,
+                            direction,
+                            damage: self.weapon.damage,
+                            impulse: self.weapon.impulse,
+                            source: self.owner,
+                            decal: self.weapon.decal,
+                            explosive: self.weapon.explosive,
+                        });
+                    }
+                }
+                FireMode::Projectile { speed, lifetime } => {
+                    let transform = Transform {
+                        translation: origin.to_vec(),
+                        scale: Vector3::new(1.0, 1.0, 1.0) * Self::PROJECTILE_SCALE,
+                        ..Transform::default()
+                    };
+
+                    let entity = scene.simulation.acquire(Self::PROJECTILE_POOL);
+                    match &self.projectile_model {
+                        Some(model) => scene.insert_model(entity, model.clone(), transform),
+                        None => {
+                            scene.simulation.world.insert(entity, transform);
+                        }
+                    }
+
+                    scene
+                        .simulation
+                        .world
+                        .insert(entity, ParticleEmitter::trail());
+                    scene
+                        .simulation
+                        .world
+                        .insert(entity, CollisionLayers::PROJECTILE);
+                    scene.simulation.world.insert(
+                        entity,
+                        Projectile {
+                            velocity: direction * speed,
+                            damage: self.weapon.damage,
+                            impulse: self.weapon.impulse,
+                            owner: self.owner,
+                            decal: self.weapon.decal,
+                            explosive: self.weapon.explosive,
+                        },
+                    );
+                    scene
+                        .simulation
+                        .world
+                        .insert(entity, Lifetime::new(lifetime));
+                }
+            }
+        }
+    }
+
+    /// Moves each projectile, stopping it at the first thing in its way this step
+    fn update_projectiles(scene: &mut Scene, deltatime: f32, hits: &mut Vec<Hit>) {
+        let projectiles = scene
+            .simulation
+            .query::<Projectile>()
+            .map(|(entity, projectile)| (entity, *projectile))
+            .collect_vec();
+        let mut finished = vec![];
+
+        for (entity, projectile) in projectiles.iter().copied() {
+            let Some(transform) = scene.simulation.world.get::<Transform>(entity) else {
+                finished.push(entity);
+                continue;
+            };
+
+            let origin = Point3::from_vec(transform.translation);
+            let step = projectile.velocity * deltatime;
+            let direction = projectile.velocity.normalize();
+
+            // Projectiles pass through each other and whoever fired them
+            let hit = first_hit(
+                &scene.simulation,
+                &Ray::new(origin, direction),
+                step.magnitude(),
+                CollisionLayers::of(&scene.simulation.world, entity),
+                |other| {
+                    Some(other) != projectile.owner
+                        && !projectiles
+                            .iter()
+                            .any(|&(projectile, _)| projectile == other)
+                },
+            );
+
+            if let Some(hit) = hit {
+                hits.push(Hit {
+                    entity: hit.entity,
+                    point: origin + direction * hit.distance,
+                    normal: hit.normal,
+                    direction,
+                    damage: projectile.damage,
+                    impulse: projectile.impulse,
+                    source: projectile.owner,
+                    decal: projectile.decal,
+                    explosive: projectile.explosive,
+                });
+                finished.push(entity);
+                continue;
+            }
+
+            scene
+                .simulation
+                .world
+                .get_mut::<Transform>(entity)
+                .unwrap()
+                .translation += step;
+        }
+
+        for entity in finished {
+            scene.simulation.recycle(entity);
+        }
+    }
+
+    /// Pushes any rigid bodies that were hit, marks the surfaces that were and sends the damage
+    /// dealt to each entity
+    fn resolve_hits(scene: &mut Scene, hits: &[Hit]) {
+        for hit in hits {
+            if let Some(body) = scene.simulation.physics.body_mut(hit.entity) {
+                let impulse = hit.direction * hit.impulse;
+                body.apply_impulse(vector![impulse.x, impulse.y, impulse.z], true);
+            }
+
+            // Characters don't keep holes in them
+            if !scene.simulation.world.has::<Health>(hit.entity) {
+                scene.decals.spawn(
+                    &scene.simulation.world,
+                    hit.decal,
+                    hit.point,
+                    hit.normal,
+                    Some(hit.entity),
+                );
+            }
+
+            scene.events.send(Damage::from(*hit));
+        }
+    }
+}
+
+/// The closest entity along a normalized ray within `max_distance`, out of model instances and
+/// physics colliders, such as level geometry, that interact with `layers` and that `filter`
+/// returns true for
+pub(crate) fn first_hit(
+    simulation: &Simulation,
+    ray: &Ray,
+    max_distance: f32,
+    layers: CollisionLayers,
+    filter: impl Fn(UUID) -> bool,
+) -> Option<RaycastHit> {
+    let model_hit = simulation
+        .raycast_filtered(ray, true, |entity| {
+            layers.interacts_with(&CollisionLayers::of(&simulation.world, entity)) && filter(entity)
+        })
+        .filter(|hit| hit.distance <= max_distance);
+    let collider_hit =
+        simulation
+            .physics
+            .cast_ray(ray.origin, ray.direction, max_distance, layers, &filter);
+
+    [model_hit, collider_hit]
+        .into_iter()
+        .flatten()
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
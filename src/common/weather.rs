@@ -0,0 +1,406 @@
+use std::path::Path;
+
+use cgmath::{Point3, Vector3};
+use color_eyre::Result;
+use glium::glutin::surface::WindowSurface;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::{uniform, Display, DrawParameters, Program, Surface, VertexBuffer};
+
+use crate::camera::Camera;
+use crate::line::LinePoint;
+use crate::scene::Environment;
+use crate::{context, maths};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WeatherState {
+    Clear,
+    Rain,
+    Snow,
+    Fog,
+    Storm,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Precipitation {
+    Rain,
+    Snow,
+}
+
+/// Looping ambient sounds a weather state asks for
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Ambience {
+    Rain,
+    Wind,
+    Thunder,
+}
+
+impl Ambience {
+    pub const ALL: [Ambience; 3] = [Ambience::Rain, Ambience::Wind, Ambience::Thunder];
+
+    /// The sound looped for this ambience
+    pub fn sound_path(self) -> &'static Path {
+        Path::new(match self {
+            Ambience::Rain => "assets/sounds/rain.wav",
+            Ambience::Wind => "assets/sounds/wind.wav",
+            Ambience::Thunder => "assets/sounds/thunder.wav",
+        })
+    }
+}
+
+/// Everything a weather state changes, blended when moving between states
+#[derive(Copy, Clone, Debug)]
+struct WeatherParameters {
+    /// Particles spawned per second
+    precipitation_rate: f32,
+    fog_density: f32,
+    /// How wet surfaces become if the weather lasts long enough
+    wetness: f32,
+    /// How much of the sun and sky the clouds block
+    overcast: f32,
+    wind: Vector3<f32>,
+    rain_volume: f32,
+    wind_volume: f32,
+}
+
+impl WeatherParameters {
+    fn of(state: WeatherState) -> Self {
+        let calm = Self {
+            precipitation_rate: 0.0,
+            fog_density: 0.0,
+            wetness: 0.0,
+            overcast: 0.0,
+            wind: Vector3::new(0.0, 0.0, 0.0),
+            rain_volume: 0.0,
+            wind_volume: 0.0,
+        };
+
+        match state {
+            WeatherState::Clear => calm,
+            WeatherState::Rain => Self {
+                precipitation_rate: 2000.0,
+                fog_density: 0.01,
+                wetness: 1.0,
+                overcast: 0.3,
+                wind: Vector3::new(0.5, 0.0, 0.2),
+                rain_volume: 0.6,
+                ..calm
+            },
+            WeatherState::Snow => Self {
+                precipitation_rate: 600.0,
+                fog_density: 0.02,
+                wetness: 0.2,
+                overcast: 0.2,
+                wind: Vector3::new(0.3, 0.0, 0.1),
+                wind_volume: 0.2,
+                ..calm
+            },
+            WeatherState::Fog => Self {
+                fog_density: 0.08,
+                wetness: 0.3,
+                overcast: 0.2,
+                ..calm
+            },
+            WeatherState::Storm => Self {
+                precipitation_rate: 5000.0,
+                fog_density: 0.02,
+                wetness: 1.0,
+                overcast: 0.6,
+                wind: Vector3::new(4.0, 0.0, 1.5),
+                rain_volume: 1.0,
+                wind_volume: 0.8,
+            },
+        }
+    }
+
+    fn lerp(&self, other: &Self, amount: f32) -> Self {
+        let mix = |from: f32, to: f32| from + (to - from) * amount;
+
+        Self {
+            precipitation_rate: mix(self.precipitation_rate, other.precipitation_rate),
+            fog_density: mix(self.fog_density, other.fog_density),
+            wetness: mix(self.wetness, other.wetness),
+            overcast: mix(self.overcast, other.overcast),
+            wind: self.wind + (other.wind - self.wind) * amount,
+            rain_volume: mix(self.rain_volume, other.rain_volume),
+            wind_volume: mix(self.wind_volume, other.wind_volume),
+        }
+    }
+}
+
+/// A weather state and how long it lasts before moving on to the next in the schedule
+#[derive(Copy, Clone, Debug)]
+pub struct ScheduledWeather {
+    pub state: WeatherState,
+    pub duration: f32,
+}
+
+struct Particle {
+    position: Point3<f32>,
+    velocity: Vector3<f32>,
+}
+
+pub struct Weather {
+    pub state: WeatherState,
+    /// Seconds taken to blend fully into a new state
+    pub transition_time: f32,
+    /// Cycled through in order when not empty
+    pub schedule: Vec<ScheduledWeather>,
+    /// Size of the box around the camera that particles are simulated in
+    pub particle_area: Vector3<f32>,
+    pub max_particles: usize,
+
+    current: WeatherParameters,
+    wetness: f32,
+    schedule_index: usize,
+    schedule_elapsed: f32,
+    particles: Vec<Particle>,
+    precipitation: Precipitation,
+    spawn_accumulator: f32,
+    lightning_timer: f32,
+    lightning_flash: f32,
+    rng: fastrand::Rng,
+    program: Program,
+}
+
+impl Weather {
+    /// How quickly surfaces soak up or dry off, per second
+    const WETTING_RATE: f32 = 0.1;
+    const DRYING_RATE: f32 = 0.02;
+    /// How long a raindrop streak is drawn, in seconds of travel
+    const STREAK_LENGTH: f32 = 0.03;
+
+    pub fn new(state: WeatherState, display: &Display<WindowSurface>) -> Result<Self> {
+        let program = context::new_program(
+            "assets/shaders/line/line.vert",
+            "assets/shaders/line/line.frag",
+            None,
+            display,
+        )?;
+
+        Ok(Self {
+            state,
+            transition_time: 10.0,
+            schedule: vec![],
+            particle_area: Vector3::new(30.0, 20.0, 30.0),
+            max_particles: 10000,
+            current: WeatherParameters::of(state),
+            wetness: WeatherParameters::of(state).wetness,
+            schedule_index: 0,
+            schedule_elapsed: 0.0,
+            particles: vec![],
+            precipitation: Self::precipitation_of(state),
+            spawn_accumulator: 0.0,
+            lightning_timer: 0.0,
+            lightning_flash: 0.0,
+            rng: fastrand::Rng::new(),
+            program,
+        })
+    }
+
+    /// Begins blending towards a new state
+    pub fn set(&mut self, state: WeatherState) {
+        self.state = state;
+
+        if state != WeatherState::Clear && state != WeatherState::Fog {
+            self.precipitation = Self::precipitation_of(state);
+        }
+    }
+
+    pub fn wetness(&self) -> f32 {
+        self.wetness
+    }
+
+    pub fn fog_density(&self) -> f32 {
+        self.current.fog_density
+    }
+
+    /// The ambient loops that should be playing and their volumes
+    pub fn ambience(&self) -> Vec<(Ambience, f32)> {
+        let mut ambience = vec![
+            (Ambience::Rain, self.current.rain_volume),
+            (Ambience::Wind, self.current.wind_volume),
+        ];
+
+        if self.lightning_flash > 0.0 {
+            ambience.push((Ambience::Thunder, self.lightning_flash));
+        }
+
+        ambience
+            .into_iter()
+            .filter(|(_, volume)| *volume > 0.01)
+            .collect()
+    }
+
+    pub fn update(&mut self, deltatime: f32, camera: &Camera) {
+        self.update_schedule(deltatime);
+
+        let target = WeatherParameters::of(self.state);
+        let amount = if self.transition_time > 0.0 {
+            (deltatime / self.transition_time).min(1.0)
+        } else {
+            1.0
+        };
+        self.current = self.current.lerp(&target, amount);
+
+        if self.wetness < self.current.wetness {
+            self.wetness =
+                (self.wetness + Self::WETTING_RATE * deltatime).min(self.current.wetness);
+        } else {
+            self.wetness = (self.wetness - Self::DRYING_RATE * deltatime).max(self.current.wetness);
+        }
+
+        self.update_lightning(deltatime);
+        self.update_particles(deltatime, camera.position);
+    }
+
+    /// Darkens the sky for cloud cover and sets fog and wetness. Should be applied after anything
+    /// else that sets the sun and sky, such as the time of day.
+    pub fn apply(&self, environment: &mut Environment) {
+        let clear_sky = 1.0 - self.current.overcast;
+
+        environment.sun_intensity *= clear_sky;
+        environment.sky_color = environment.sky_color * (1.0 - self.current.overcast * 0.7);
+        environment.ambient_intensity += self.lightning_flash;
+        environment.fog_density = self.current.fog_density;
+        environment.fog_color = environment.sky_color;
+        environment.wetness = self.wetness;
+    }
+
+    pub fn render<S: Surface>(
+        &self,
+        display: &Display<WindowSurface>,
+        target: &mut S,
+        camera: &Camera,
+    ) -> Result<()> {
+        if self.particles.is_empty() {
+            return Ok(());
+        }
+
+        let (color, streak_length) = match self.precipitation {
+            Precipitation::Rain => ([0.6, 0.65, 0.75], Self::STREAK_LENGTH),
+            // Snow is slow enough that its streaks read as flakes
+            Precipitation::Snow => ([0.95, 0.95, 1.0], Self::STREAK_LENGTH * 3.0),
+        };
+
+        let line_points = self
+            .particles
+            .iter()
+            .flat_map(|particle| {
+                [
+                    LinePoint {
+                        position: <[f32; 3]>::from(particle.position),
+                        color,
+                    },
+                    LinePoint {
+                        position: <[f32; 3]>::from(
+                            particle.position - particle.velocity * streak_length,
+                        ),
+                        color,
+                    },
+                ]
+            })
+            .collect::<Vec<LinePoint>>();
+
+        let vertex_buffer = VertexBuffer::new(display, &line_points)?;
+
+        let uniforms = uniform! {
+            vp: maths::raw_matrix(camera.view_projection),
+        };
+
+        target.draw(
+            &vertex_buffer,
+            &NoIndices(PrimitiveType::LinesList),
+            &self.program,
+            &uniforms,
+            &DrawParameters::default(),
+        )?;
+
+        Ok(())
+    }
+
+    fn precipitation_of(state: WeatherState) -> Precipitation {
+        match state {
+            WeatherState::Snow => Precipitation::Snow,
+            _ => Precipitation::Rain,
+        }
+    }
+
+    fn update_schedule(&mut self, deltatime: f32) {
+        if self.schedule.is_empty() {
+            return;
+        }
+
+        self.schedule_index %= self.schedule.len();
+        self.schedule_elapsed += deltatime;
+
+        let current = self.schedule[self.schedule_index];
+
+        if self.schedule_elapsed >= current.duration {
+            self.schedule_elapsed = 0.0;
+            self.schedule_index = (self.schedule_index + 1) % self.schedule.len();
+        }
+
+        let scheduled_state = self.schedule[self.schedule_index].state;
+
+        if scheduled_state != self.state {
+            self.set(scheduled_state);
+        }
+    }
+
+    fn update_lightning(&mut self, deltatime: f32) {
+        self.lightning_flash = (self.lightning_flash - deltatime * 4.0).max(0.0);
+
+        if self.state != WeatherState::Storm {
+            return;
+        }
+
+        self.lightning_timer -= deltatime;
+
+        if self.lightning_timer <= 0.0 {
+            self.lightning_flash = 1.5;
+            self.lightning_timer = 4.0 + self.rng.f32() * 12.0;
+        }
+    }
+
+    fn update_particles(&mut self, deltatime: f32, center: Point3<f32>) {
+        let half_area = self.particle_area / 2.0;
+
+        for particle in self.particles.iter_mut() {
+            particle.position += particle.velocity * deltatime;
+        }
+
+        self.particles.retain(|particle| {
+            let offset = particle.position - center;
+
+            particle.position.y > 0.0
+                && offset.x.abs() <= half_area.x
+                && offset.z.abs() <= half_area.z
+        });
+
+        self.spawn_accumulator += self.current.precipitation_rate * deltatime;
+
+        while self.spawn_accumulator >= 1.0 {
+            self.spawn_accumulator -= 1.0;
+
+            if self.particles.len() >= self.max_particles {
+                continue;
+            }
+
+            let fall_speed = match self.precipitation {
+                Precipitation::Rain => 20.0,
+                Precipitation::Snow => 1.5 + self.rng.f32(),
+            };
+
+            let spawn_offset = Vector3::new(
+                maths::linear_map(self.rng.f32(), 0.0, 1.0, -half_area.x, half_area.x),
+                half_area.y + self.rng.f32() * half_area.y,
+                maths::linear_map(self.rng.f32(), 0.0, 1.0, -half_area.z, half_area.z),
+            );
+
+            self.particles.push(Particle {
+                position: Point3::new(center.x, 0.0, center.z) + spawn_offset,
+                velocity: self.current.wind + Vector3::new(0.0, -fall_speed, 0.0),
+            });
+        }
+    }
+}
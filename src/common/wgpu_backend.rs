@@ -0,0 +1,894 @@
+use std::mem::size_of;
+use std::num::NonZeroU64;
+use std::ops::Range;
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Matrix4, Vector3};
+use color_eyre::eyre::{eyre, WrapErr};
+use color_eyre::Result;
+use image::RgbaImage;
+use itertools::Itertools;
+use log::warn;
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+use winit::event_loop::EventLoop;
+use winit::window::Window;
+
+use crate::animation::BonesBlock;
+use crate::backend::{Backend, BackendKind, DrawScene, ModelCache};
+use crate::camera::Camera;
+use crate::config::AppConfig;
+use crate::context;
+use crate::crash;
+use crate::debug_view::DebugView;
+use crate::light::LightsBlock;
+use crate::material::{BlendMode, Material};
+use crate::model::{Model, ModelInstance};
+use crate::scene::{Instance, Scene, ViewKind};
+use crate::settings::GraphicsSettings;
+use crate::vertex::Vertex;
+use crate::viewport::Viewport;
+
+const SHADER_PATH: &str = "assets/shaders/wgpu/forward.wgsl";
+
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// `LightsBlock` as the shader lays it out, rounded up to a multiple of 16 bytes
+const LIGHTS_SIZE: u64 = (size_of::<LightsBlock>() as u64).next_multiple_of(16);
+
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+    0 => Float32x3,
+    1 => Float32x3,
+    2 => Float32x2,
+    3 => Float32x4,
+    4 => Float32x4,
+    5 => Float32x4,
+];
+
+/// The columns of `Instance::transform` then those of `Instance::transform_normal`
+const INSTANCE_ATTRIBUTES: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
+    6 => Float32x4,
+    7 => Float32x4,
+    8 => Float32x4,
+    9 => Float32x4,
+    10 => Float32x4,
+    11 => Float32x4,
+    12 => Float32x4,
+    13 => Float32x4,
+];
+
+/// Draws through wgpu onto Metal, DX12 or Vulkan, whichever the platform has. Scenes are drawn
+/// forward and without multisampling, lit by the sun, the ambient light and the scene's lights
+/// and colored by each material's albedo. Deferred rendering, debug views other than lighting,
+/// PBR maps, reflections, the skybox, terrain, particles, decals, billboards, lines and debug
+/// drawing are only drawn with OpenGL, as are the editor's GUI, HUD and post-processing.
+pub struct WgpuContext {
+    pub window: Arc<Window>,
+    surface: wgpu::Surface<'static>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    adapter_info: wgpu::AdapterInfo,
+    depth: wgpu::TextureView,
+    frame_layout: wgpu::BindGroupLayout,
+    material_layout: wgpu::BindGroupLayout,
+    bones_layout: wgpu::BindGroupLayout,
+    opaque_pipeline: wgpu::RenderPipeline,
+    /// Blends with what's behind without writing depth
+    transparent_pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    /// `FrameUniforms` of each view, one slot apart
+    frame_buffer: GrowingBuffer,
+    lights_buffer: wgpu::Buffer,
+    /// A `BonesBlock` of identities, then one for each skinned instance drawn, one slot apart
+    bones_buffer: GrowingBuffer,
+    instance_buffer: GrowingBuffer,
+    models: ModelCache<WgpuModel>,
+}
+
+/// The window's surface texture a frame is drawn onto
+pub struct WgpuFrame {
+    texture: wgpu::SurfaceTexture,
+    view: wgpu::TextureView,
+}
+
+/// A model uploaded for wgpu
+pub struct WgpuModel {
+    /// Buffers of every primitive at each level of detail, as `GlModel` keeps them
+    levels: Vec<Vec<WgpuPrimitive>>,
+    /// One per material of the model
+    materials: Vec<WgpuMaterial>,
+}
+
+struct WgpuPrimitive {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+}
+
+struct WgpuMaterial {
+    /// The albedo factor, written each frame as it can be edited
+    uniforms: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl WgpuModel {
+    fn level(&self, level: usize) -> &[WgpuPrimitive] {
+        self.levels.get(level).unwrap_or(&self.levels[0])
+    }
+}
+
+/// What the shader reads of the camera and the scene's environment for one view
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct FrameUniforms {
+    view_projection: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+    sun_direction: [f32; 4],
+    sun_color: [f32; 4],
+    ambient_color: [f32; 4],
+    /// Density in `w`
+    fog: [f32; 4],
+    /// Wetness, then 1 for `DebugView::Lighting`
+    surface: [f32; 4],
+}
+
+// Only made of floats, so without padding and valid for any bytes
+unsafe impl Zeroable for FrameUniforms {}
+unsafe impl Pod for FrameUniforms {}
+
+impl FrameUniforms {
+    fn new(scene: &Scene, camera: &Camera) -> Self {
+        let environment = &scene.environment;
+        let sun_color = environment.sun_color * environment.sun_intensity;
+        let ambient_color = environment.ambient_color * environment.ambient_intensity;
+        let fog_color = environment.fog_color;
+        // Cameras project depth from -1 to 1 as OpenGL does, where wgpu's goes from 0 to 1
+        let depth_correction = Matrix4::from_translation(Vector3::new(0.0, 0.0, 0.5))
+            * Matrix4::from_nonuniform_scale(1.0, 1.0, 0.5);
+        let lighting_only = scene.debug_view == DebugView::Lighting;
+
+        Self {
+            view_projection: (depth_correction * camera.view_projection).into(),
+            camera_position: [camera.position.x, camera.position.y, camera.position.z, 1.0],
+            sun_direction: environment.sun_direction.extend(0.0).into(),
+            sun_color: [sun_color.red, sun_color.green, sun_color.blue, 1.0],
+            ambient_color: [
+                ambient_color.red,
+                ambient_color.green,
+                ambient_color.blue,
+                1.0,
+            ],
+            fog: [
+                fog_color.red,
+                fog_color.green,
+                fog_color.blue,
+                environment.fog_density,
+            ],
+            surface: [environment.wetness, lighting_only as u8 as f32, 0.0, 0.0],
+        }
+    }
+}
+
+/// Which of a model's primitives a draw is for, and how they're drawn
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum DrawKind {
+    /// Every primitive without a blended material
+    Opaque,
+    /// Every primitive, opaquely, as `Scene::render` draws skinned models
+    Skinned,
+    /// Only primitives with blended materials
+    Transparent,
+}
+
+struct Draw {
+    model: Arc<Model>,
+    level: usize,
+    /// Into the instance buffer
+    instances: Range<u32>,
+    /// Slot of the bones buffer
+    bones: usize,
+    kind: DrawKind,
+}
+
+/// A buffer written each frame, made again larger whenever what's written doesn't fit
+struct GrowingBuffer {
+    label: &'static str,
+    usage: wgpu::BufferUsages,
+    buffer: Option<wgpu::Buffer>,
+}
+
+impl GrowingBuffer {
+    fn new(label: &'static str, usage: wgpu::BufferUsages) -> Self {
+        Self {
+            label,
+            usage,
+            buffer: None,
+        }
+    }
+
+    fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8]) {
+        let size = bytes.len() as u64;
+        if self
+            .buffer
+            .as_ref()
+            .map_or(true, |buffer| buffer.size() < size)
+        {
+            self.buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(self.label),
+                // At least four bytes, as buffers are written in multiples of them
+                size: size.max(4).next_power_of_two(),
+                usage: self.usage | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+
+        if !bytes.is_empty() {
+            queue.write_buffer(self.buffer(), 0, bytes);
+        }
+    }
+
+    /// Only called once something has been written
+    fn buffer(&self) -> &wgpu::Buffer {
+        self.buffer.as_ref().unwrap()
+    }
+}
+
+impl WgpuContext {
+    pub fn new(
+        config: &AppConfig,
+        settings: &GraphicsSettings,
+        event_loop: &EventLoop<()>,
+    ) -> Result<Self> {
+        let window = Arc::new(context::window_builder(config, event_loop).build(event_loop)?);
+        crash::watch_window(&window);
+
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let surface = instance.create_surface(window.clone())?;
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: Some(&surface),
+            force_fallback_adapter: false,
+        }))
+        .ok_or_else(|| eyre!("No graphics adapter can draw to the window"))?;
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features: wgpu::Features::empty(),
+                required_limits: wgpu::Limits::default(),
+            },
+            None,
+        ))?;
+
+        // Shaders write linear colors, as they do into OpenGL's sRGB framebuffers
+        let capabilities = surface.get_capabilities(&adapter);
+        let format = capabilities
+            .formats
+            .iter()
+            .copied()
+            .find(wgpu::TextureFormat::is_srgb)
+            .or_else(|| capabilities.formats.first().copied())
+            .ok_or_else(|| eyre!("The window's surface has no formats"))?;
+        let size = window.inner_size();
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: if settings.vsync {
+                wgpu::PresentMode::AutoVsync
+            } else {
+                wgpu::PresentMode::AutoNoVsync
+            },
+            desired_maximum_frame_latency: 2,
+            alpha_mode: capabilities.alpha_modes[0],
+            view_formats: vec![],
+        };
+        surface.configure(&device, &surface_config);
+        let depth = depth_view(&device, &surface_config);
+
+        let frame_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Frame"),
+            entries: &[
+                uniform_entry(0, true, size_of::<FrameUniforms>() as u64),
+                uniform_entry(1, false, LIGHTS_SIZE),
+            ],
+        });
+        let material_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material"),
+            entries: &[
+                uniform_entry(0, false, size_of::<[f32; 4]>() as u64),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let bones_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bones"),
+            entries: &[uniform_entry(0, true, size_of::<BonesBlock>() as u64)],
+        });
+
+        let source = std::fs::read_to_string(SHADER_PATH)
+            .wrap_err_with(|| format!("Could not read {}", SHADER_PATH))?;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(SHADER_PATH),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&frame_layout, &material_layout, &bones_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |label, blend, depth_write_enabled| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[
+                        wgpu::VertexBufferLayout {
+                            array_stride: size_of::<Vertex>() as u64,
+                            step_mode: wgpu::VertexStepMode::Vertex,
+                            attributes: &VERTEX_ATTRIBUTES,
+                        },
+                        wgpu::VertexBufferLayout {
+                            array_stride: size_of::<Instance>() as u64,
+                            step_mode: wgpu::VertexStepMode::Instance,
+                            attributes: &INSTANCE_ATTRIBUTES,
+                        },
+                    ],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                // Both sides are drawn, as they are with OpenGL
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let opaque_pipeline = pipeline("Opaque", None, true);
+        let transparent_pipeline =
+            pipeline("Transparent", Some(wgpu::BlendState::ALPHA_BLENDING), false);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..wgpu::SamplerDescriptor::default()
+        });
+        let lights_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Lights"),
+            size: LIGHTS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let context = Self {
+            window,
+            surface,
+            device,
+            queue,
+            config: surface_config,
+            adapter_info: adapter.get_info(),
+            depth,
+            frame_layout,
+            material_layout,
+            bones_layout,
+            opaque_pipeline,
+            transparent_pipeline,
+            sampler,
+            frame_buffer: GrowingBuffer::new("Frames", wgpu::BufferUsages::UNIFORM),
+            lights_buffer,
+            bones_buffer: GrowingBuffer::new("Bones", wgpu::BufferUsages::UNIFORM),
+            instance_buffer: GrowingBuffer::new("Instances", wgpu::BufferUsages::VERTEX),
+            models: ModelCache::new(),
+        };
+        crash::set_gpu(context.description());
+
+        Ok(context)
+    }
+
+    /// Uploads the models of the scene's instances that haven't been yet, dropping those nothing
+    /// uses any more
+    fn upload_models(&mut self, scene: &Scene) {
+        // Taken out so the context can upload into it
+        let mut models = std::mem::take(&mut self.models);
+        models.retain_used();
+        for (_, model_instance) in scene.simulation.world.query::<ModelInstance>() {
+            if let Err(error) = models.upload(&*self, &model_instance.model) {
+                warn!("Not drawing {:?}: {}", model_instance.model.path, error);
+            }
+        }
+        self.models = models;
+    }
+
+    /// Bytes apart that slots of a buffer bound with a dynamic offset are
+    fn slot_size<T>(&self) -> usize {
+        let alignment = self.device.limits().min_uniform_buffer_offset_alignment as usize;
+
+        size_of::<T>().next_multiple_of(alignment)
+    }
+
+    fn upload_material(&self, material: &Material) -> Result<WgpuMaterial> {
+        let albedo = match material.albedo_texture.as_ref() {
+            Some(texture) => texture.to_rgba()?,
+            None => RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255])),
+        };
+        let texture = self.upload_texture(&albedo);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let uniforms = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Material"),
+                contents: bytemuck::bytes_of(&material.albedo_factor),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Material"),
+            layout: &self.material_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniforms.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        Ok(WgpuMaterial {
+            uniforms,
+            bind_group,
+        })
+    }
+
+    /// With its first row first, as glTF's texture coordinates start at the top
+    fn upload_texture(&self, image: &RgbaImage) -> wgpu::Texture {
+        let (width, height) = image.dimensions();
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            image.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+
+        texture
+    }
+}
+
+impl Backend for WgpuContext {
+    type Frame = WgpuFrame;
+    type Model = WgpuModel;
+
+    fn kind(&self) -> BackendKind {
+        BackendKind::Wgpu
+    }
+
+    fn description(&self) -> String {
+        let info = &self.adapter_info;
+
+        format!("{} with {}, {:?}", info.name, info.driver, info.backend)
+    }
+
+    fn begin_frame(&mut self) -> Result<WgpuFrame> {
+        let texture = match self.surface.get_current_texture() {
+            Ok(texture) => texture,
+            // The surface stops matching the window when it changes, so it's configured again
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.surface.configure(&self.device, &self.config);
+                self.surface.get_current_texture()?
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let view = texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        Ok(WgpuFrame { texture, view })
+    }
+
+    fn finish_frame(&mut self, frame: WgpuFrame) -> Result<()> {
+        frame.texture.present();
+        Ok(())
+    }
+
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        // Minimised windows have no size to draw at
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+
+        self.config.width = size.width;
+        self.config.height = size.height;
+        self.surface.configure(&self.device, &self.config);
+        self.depth = depth_view(&self.device, &self.config);
+    }
+
+    fn upload_model(&self, model: &Model) -> Result<WgpuModel> {
+        let levels = (0..=model.lods.len())
+            .map(|level| {
+                model
+                    .lod_meshes(level)
+                    .iter()
+                    .flat_map(|mesh| mesh.primitives.iter())
+                    .map(|primitive| WgpuPrimitive {
+                        vertex_buffer: self.device.create_buffer_init(
+                            &wgpu::util::BufferInitDescriptor {
+                                label: None,
+                                contents: bytemuck::cast_slice(&primitive.vertices),
+                                usage: wgpu::BufferUsages::VERTEX,
+                            },
+                        ),
+                        index_buffer: self.device.create_buffer_init(
+                            &wgpu::util::BufferInitDescriptor {
+                                label: None,
+                                contents: bytemuck::cast_slice(&primitive.indices),
+                                usage: wgpu::BufferUsages::INDEX,
+                            },
+                        ),
+                        index_count: primitive.indices.len() as u32,
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        let materials = model
+            .materials
+            .iter()
+            .map(|material| self.upload_material(&material.borrow()))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(WgpuModel { levels, materials })
+    }
+}
+
+impl DrawScene<WgpuFrame> for WgpuContext {
+    fn draw_scene(&mut self, scene: &mut Scene, target: &mut WgpuFrame) {
+        let camera = scene.camera.clone();
+        self.draw_views(scene, target, &[(camera, Viewport::FULL)]);
+    }
+
+    fn draw_views(
+        &mut self,
+        scene: &mut Scene,
+        target: &mut WgpuFrame,
+        views: &[(Camera, Viewport)],
+    ) {
+        self.upload_models(scene);
+
+        let size = (self.config.width, self.config.height);
+        let mut frames = vec![];
+        let mut instances: Vec<Instance> = vec![];
+        let mut bones = vec![BonesBlock::new(&[])];
+        let mut view_draws = vec![];
+
+        for (index, (camera, viewport)) in views.iter().enumerate() {
+            let mut camera = camera.clone();
+            camera.set_aspect_ratio(viewport.aspect_ratio(size));
+            let view = if index == 0 {
+                ViewKind::Main
+            } else {
+                ViewKind::Player
+            };
+            // Occlusion queries are only made with OpenGL
+            let visible = scene.build_instance_map(&camera, false, view);
+            frames.push(FrameUniforms::new(scene, &camera));
+
+            let mut draws = vec![];
+            let mut push = |model: &Arc<Model>, level, new_instances: &[Instance], slot, kind| {
+                let start = instances.len() as u32;
+                instances.extend_from_slice(new_instances);
+                draws.push(Draw {
+                    model: model.clone(),
+                    level,
+                    instances: start..instances.len() as u32,
+                    bones: slot,
+                    kind,
+                });
+            };
+
+            for key in visible.draw_order.iter() {
+                let (model, level) = key;
+                push(model, *level, &visible.instances[key], 0, DrawKind::Opaque);
+            }
+            for skinned in visible.skinned.iter() {
+                bones.push(skinned.bones);
+                let slot = bones.len() - 1;
+                push(
+                    &skinned.model,
+                    0,
+                    &[skinned.instance],
+                    slot,
+                    DrawKind::Skinned,
+                );
+            }
+            // After everything opaque, furthest first
+            for transparent in visible.transparent.iter() {
+                push(
+                    &transparent.model,
+                    transparent.level,
+                    &[transparent.instance],
+                    0,
+                    DrawKind::Transparent,
+                );
+            }
+
+            view_draws.push((viewport.rect(size), draws));
+        }
+
+        let frame_size = self.slot_size::<FrameUniforms>();
+        let bones_size = self.slot_size::<BonesBlock>();
+        self.frame_buffer
+            .write(&self.device, &self.queue, &slots(&frames, frame_size));
+        self.bones_buffer
+            .write(&self.device, &self.queue, &slots(&bones, bones_size));
+        self.instance_buffer
+            .write(&self.device, &self.queue, bytemuck::cast_slice(&instances));
+        self.queue.write_buffer(
+            &self.lights_buffer,
+            0,
+            bytemuck::bytes_of(&LightsBlock::new(&scene.simulation.world)),
+        );
+
+        let drawn_models = view_draws
+            .iter()
+            .flat_map(|(_, draws)| draws.iter().map(|draw| &draw.model))
+            .unique_by(|model| Arc::as_ptr(model));
+        for model in drawn_models {
+            let Some(wgpu_model) = self.models.get(model) else {
+                continue;
+            };
+            for (material, uploaded) in model.materials.iter().zip(&wgpu_model.materials) {
+                self.queue.write_buffer(
+                    &uploaded.uniforms,
+                    0,
+                    bytemuck::bytes_of(&material.borrow().albedo_factor),
+                );
+            }
+        }
+
+        let frame_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Frame"),
+            layout: &self.frame_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: slot_binding::<FrameUniforms>(self.frame_buffer.buffer()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.lights_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let bones_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bones"),
+            layout: &self.bones_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: slot_binding::<BonesBlock>(self.bones_buffer.buffer()),
+            }],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        let mut draw_calls = 0;
+        {
+            let sky_color = scene.environment.sky_color;
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Scene"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: sky_color.red as f64,
+                            g: sky_color.green as f64,
+                            b: sky_color.blue as f64,
+                            a: 1.0,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            if !instances.is_empty() {
+                pass.set_vertex_buffer(1, self.instance_buffer.buffer().slice(..));
+            }
+
+            for (index, (rect, draws)) in view_draws.iter().enumerate() {
+                // From the top left rather than the bottom left
+                pass.set_viewport(
+                    rect.left as f32,
+                    size.1.saturating_sub(rect.bottom + rect.height) as f32,
+                    rect.width as f32,
+                    rect.height as f32,
+                    0.0,
+                    1.0,
+                );
+                pass.set_bind_group(0, &frame_bind_group, &[(index * frame_size) as u32]);
+
+                for draw in draws {
+                    let Some(wgpu_model) = self.models.get(&draw.model) else {
+                        continue;
+                    };
+                    pass.set_pipeline(match draw.kind {
+                        DrawKind::Opaque | DrawKind::Skinned => &self.opaque_pipeline,
+                        DrawKind::Transparent => &self.transparent_pipeline,
+                    });
+                    pass.set_bind_group(2, &bones_bind_group, &[(draw.bones * bones_size) as u32]);
+
+                    for (primitive, buffers) in draw
+                        .model
+                        .lod_meshes(draw.level)
+                        .iter()
+                        .flat_map(|mesh| mesh.primitives.iter())
+                        .zip(wgpu_model.level(draw.level))
+                    {
+                        let blended = draw.model.materials[primitive.material].borrow().blend_mode
+                            == BlendMode::Blend;
+                        let drawn = match draw.kind {
+                            DrawKind::Opaque => !blended,
+                            DrawKind::Skinned => true,
+                            DrawKind::Transparent => blended,
+                        };
+                        if !drawn || buffers.index_count == 0 {
+                            continue;
+                        }
+
+                        pass.set_bind_group(
+                            1,
+                            &wgpu_model.materials[primitive.material].bind_group,
+                            &[],
+                        );
+                        pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+                        pass.set_index_buffer(
+                            buffers.index_buffer.slice(..),
+                            wgpu::IndexFormat::Uint16,
+                        );
+
+                        let indices = 0..buffers.index_count;
+                        if scene.instanced_rendering {
+                            pass.draw_indexed(indices, 0, draw.instances.clone());
+                            draw_calls += 1;
+                            continue;
+                        }
+
+                        for instance in draw.instances.clone() {
+                            pass.draw_indexed(indices.clone(), 0, instance..instance + 1);
+                            draw_calls += 1;
+                        }
+                    }
+                }
+            }
+        }
+        self.queue.submit([encoder.finish()]);
+
+        // Debug lines are only drawn with OpenGL
+        scene.debug.clear();
+        scene.draw_calls += draw_calls;
+    }
+}
+
+fn depth_view(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// Read by both shader stages, with `dynamic` ones bound at an offset for each draw
+fn uniform_entry(binding: u32, dynamic: bool, size: u64) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: dynamic,
+            min_binding_size: NonZeroU64::new(size),
+        },
+        count: None,
+    }
+}
+
+/// One `T` of a buffer of slots, picked by the dynamic offset it's bound at
+fn slot_binding<T>(buffer: &wgpu::Buffer) -> wgpu::BindingResource<'_> {
+    wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+        buffer,
+        offset: 0,
+        size: NonZeroU64::new(size_of::<T>() as u64),
+    })
+}
+
+/// Each item at the start of a slot of `slot_size` bytes
+fn slots<T: Pod>(items: &[T], slot_size: usize) -> Vec<u8> {
+    let mut bytes = vec![0; items.len() * slot_size];
+    for (slot, item) in bytes.chunks_exact_mut(slot_size).zip(items) {
+        slot[..size_of::<T>()].copy_from_slice(bytemuck::bytes_of(item));
+    }
+
+    bytes
+}
@@ -1,33 +1,179 @@
 use std::collections::VecDeque;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
 use std::thread::Thread;
 use std::time::Instant;
 
-use cgmath::{Deg, Point3, Quaternion, Rotation3, Vector3};
+use cgmath::{
+    Deg, EuclideanSpace, Euler, InnerSpace, MetricSpace, Point3, Quaternion, Rotation3,
+    Transform as _, Vector2, Vector3, Zero,
+};
+use color_eyre::eyre::bail;
 use egui_glium::egui_winit::egui;
 use egui_glium::egui_winit::egui::{Align, Button, ViewportId};
 use egui_glium::egui_winit::winit::event_loop::EventLoop;
 use egui_glium::EguiGlium;
-use glium::glutin::surface::WindowSurface;
-use glium::Display;
+use glium::texture::UncompressedFloatFormat;
+use glium::Surface;
 use image::open;
-use palette::Srgb;
+use itertools::Itertools;
+use log::{info, warn, LevelFilter};
+use palette::{Hsv, IntoColor, Srgb, Srgba};
 use rfd::FileDialog;
 use serde::Serialize;
-use winit::event::{Event, MouseButton, WindowEvent};
+use winit::dpi::PhysicalSize;
+use winit::event::{Event, WindowEvent};
 use winit::event_loop::ControlFlow;
 use winit::keyboard::KeyCode;
 
+use ai::{Enemy, EnemyAttack};
+use animator::{AnimatorController, SHOOT_TRIGGER};
 use app::Application;
-use common::camera::Camera;
+use assets::Assets;
+use audio::Audio;
+use backend::{Backend, BackendKind};
+use billboards::Billboard;
+use camera_effects::CameraEffects;
+use capture::Capture;
+use collision::{CollisionLayers, Trigger};
+use common::camera::{Camera, ViewMode};
 use common::*;
+use config::{AppConfig, DisplayMode};
+use console::Console;
 use context::OpenGLContext;
-use input::Input;
+use debug::LogLine;
+use debug_view::DebugView;
+use deferred::RenderMode;
+use entity::World;
+use events::{ActionEvent, WindowResized};
+use gameplay::{Damage, Dead, DeathBehavior, Health, HealthBar, PickedUp, Pickup, Respawn};
+use hierarchy::Parent;
+use input::{ActionMap, Input, InputRecorder, InputRecording, InputReplay, Stick};
+use levelgen::{Level, LevelGenerator, PickupKind, CUBE_MODEL_PATH};
+use light::{Light, LightKind};
 use line::Line;
+use loading::{AssetLoader, LoadId, LoadState};
+use map::{AssetSource, Map};
+use material::{BlendMode, Material, ShadingModel};
+use maths::Ray;
 use model::{Model, ModelInstance, Transform};
+use net::{Client, PlayerInput};
+use particles::ParticleEmitter;
+use physics::{BodyKind, Collider, ColliderShape, RigidBody};
+use portal::{PortalRenderer, PortalTeleporter};
+use postprocess::{PostProcessor, Tonemapping};
+use profiling::ScopeTiming;
+use reflection::{PlanarReflection, ProbeUpdate, ReflectionProbe};
+use render_graph::{RenderGraph, TextureDesc, TARGET};
+use replay::{Killcam, KillcamState, ReplayBuffer};
+use savegame::{PlayerState, SaveGame, QUICK_SAVE_PATH};
 use scene::Scene;
+use scripting::{ScriptHost, SCRIPT_DIRECTORY};
+use settings::GraphicsSettings;
+use simulation::RaycastHit;
+use skybox::{Skybox, SkyboxSource};
+use streaming::{SceneStreamer, StreamingManifest};
+use terrain::{Heightmap, Terrain};
+use time_of_day::TimeOfDay;
+use timestep::{FixedTimestep, TimeControl};
+use tween::{Tween, Tweens};
+use ui::Hud;
+use uuid::UUID;
+use viewport::Viewport;
+use weapons::{Hit, Weapon, WeaponSystem};
+use weather::{Ambience, Weather, WeatherState};
+
+/// Seconds for spawned models and pickups to spin all the way round
+const SPIN_PERIOD: f32 = 6.0;
+
+/// How high pickups float up and down, and the seconds they take to go up and back
+const PICKUP_BOB_HEIGHT: f32 = 0.15;
+const PICKUP_BOB_PERIOD: f32 = 2.0;
+
+/// Dropped into the scene to try out physics, and fired by projectile weapons
+const PHYSICS_CUBE_PATH: &str = "assets/models/cube.glb";
+
+const GUNSHOT_SOUND_PATH: &str = "assets/sounds/gunshot.wav";
+const FOOTSTEP_SOUND_PATH: &str = "assets/sounds/footstep.wav";
+
+/// Units walked between footstep sounds
+const FOOTSTEP_SPACING: f32 = 1.5;
+
+/// How far in front of the camera muzzle flashes appear
+const MUZZLE_DISTANCE: f32 = 0.5;
+const MUZZLE_FLASH_PARTICLES: usize = 12;
+/// Sparks thrown off wherever a shot lands
+const SPARK_PARTICLES: usize = 16;
+/// Fraction of the upward kick a shot can also kick to either side
+const RECOIL_SWAY: f32 = 0.3;
+/// Explosions further away than this don't shake the view
+const EXPLOSION_SHAKE_RADIUS: f32 = 12.0;
+/// Camera trauma per point of damage the player takes, up to the most one hit can add
+const DAMAGE_TRAUMA: f32 = 0.02;
+const MAX_DAMAGE_TRAUMA: f32 = 0.5;
+
+/// Health the player starts with and is given back after dying
+const PLAYER_HEALTH: f32 = 100.0;
+/// Seconds the player stays dead before respawning, counted once the killcam has played
+const PLAYER_RESPAWN_DELAY: f32 = 3.0;
+/// Seconds before the player's death the killcam plays back, and how often it's recorded
+const KILLCAM_LENGTH: f64 = 3.0;
+const KILLCAM_INTERVAL: f64 = 0.05;
+
+/// Size of pickups spawned from the editor, how far above the ground they float and the health
+/// they give
+const PICKUP_SIZE: f32 = 0.25;
+const PICKUP_HEIGHT: f32 = 0.5;
+const PICKUP_HEALTH: f32 = 25.0;
+/// How far above a pickup the marker showing where it is floats
+const PICKUP_MARKER_HEIGHT: f32 = 0.6;
+
+/// Half the width of the square enemies spawned from the editor patrol around
+const ENEMY_PATROL_SIZE: f32 = 4.0;
+/// Seconds before enemies spawned from the editor come back after being killed
+const ENEMY_RESPAWN_DELAY: f32 = 5.0;
+
+/// Radius of the spheres marking lights, and a quarter of the length of their direction
+const LIGHT_GIZMO_SIZE: f32 = 0.25;
+
+/// Half the size of the box of reflection probes added in the editor, along each axis
+const REFLECTION_PROBE_EXTENTS: f32 = 5.0;
+
+/// Where recorded traces are saved, in the Chrome trace format
+const TRACE_PATH: &str = "trace.json";
+
+/// Samples along each side of generated terrain
+const TERRAIN_RESOLUTION: usize = 257;
+/// World units across generated or loaded terrain and how high its peaks reach
+const TERRAIN_SIZE: f32 = 200.0;
+const TERRAIN_HEIGHT: f32 = 30.0;
+
+/// Width of the cells scenes are split into when exported as a streamed world, and how many
+/// cells around the camera's are kept loaded
+const STREAMING_CELL_SIZE: f32 = 50.0;
+const STREAMING_LOAD_RADIUS: i32 = 1;
+
+/// How far above the terrain the FPS camera is kept
+const EYE_HEIGHT: f32 = 1.7;
+
+/// Render graph passes turned on and off by whether post-processing is
+const WORLD_PASS: &str = "World";
+const POST_PROCESSING_PASS: &str = "Post-processing";
+const WORLD_TO_TARGET_PASS: &str = "World without post-processing";
+
+/// Time scale slow motion toggles to
+const SLOW_MOTION_SCALE: f64 = 0.25;
+
+/// Frames shown in the debug overlay's frame time graph
+const FRAME_TIME_HISTORY: usize = 240;
+
+/// Title of scenes made by the `generate_level` command
+const GENERATED_LEVEL_TITLE: &str = "Generated level";
+
+/// Most the console's output takes up before it scrolls
+const CONSOLE_HEIGHT: f32 = 240.0;
 
 struct FrameState {
     pub start: Instant,
@@ -35,6 +181,25 @@ struct FrameState {
     pub deltatime: f64,
     pub fps: f32,
     pub using_viewport: bool,
+    /// Keeps the cursor captured without holding the grab binding
+    pub cursor_locked: bool,
+    /// Milliseconds taken by each of the most recent frames, oldest first
+    pub frame_times: VecDeque<f32>,
+    pub show_debug_overlay: bool,
+    /// Whether the cursor was over a window or panel last frame, so clicks there don't pick
+    pub pointer_over_gui: bool,
+    /// Entity last clicked on in the viewport
+    pub picked: Option<RaycastHit>,
+    pub show_editor_panel: bool,
+    /// Entity shown in the editor panel's inspector, picked or chosen from its hierarchy
+    pub selected: Option<UUID>,
+    /// Draws lights, collision and the selection's bounds with the scene's debug lines
+    pub show_gizmos: bool,
+    pub show_log: bool,
+    /// Least severe messages shown in the log view
+    pub log_level: LevelFilter,
+    /// Only lines logged from modules containing this are shown
+    pub log_module: String,
 }
 
 impl FrameState {
@@ -43,33 +208,168 @@ impl FrameState {
 
         self.deltatime = self.start.elapsed().as_secs_f64();
         self.fps = (1.0 / self.deltatime) as f32;
+
+        if self.frame_times.len() == FRAME_TIME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back((self.deltatime * 1000.0) as f32);
     }
 }
 
 enum EngineEvent {
-    LoadScene(String),
+    LoadScene(PathBuf),
+    SaveScene(PathBuf),
+    LoadMap(PathBuf),
+    /// A streaming manifest, whose cells are loaded around the camera on top of the scene
+    OpenStreamedWorld(PathBuf),
+    /// A directory to split the scene into streamed cells in
+    ExportStreamedWorld(PathBuf),
     ImportModel(PathBuf),
+    /// An equirectangular image to surround the scene with
+    LoadSkybox(PathBuf),
+    /// A greyscale image to build terrain from
+    LoadHeightmap(PathBuf),
+    /// A Rhai script to run with the scene
+    AddScript(PathBuf),
+    PlayMusic(PathBuf),
+}
+
+/// A scene or map waiting for the models it uses to finish loading in the background
+enum PendingLoad {
+    Scene(PathBuf),
+    Map(Map),
+    /// Generated by the `generate_level` command, waiting on the cube model it's built from
+    Level(Level),
 }
 
 pub struct Editor {
+    config: AppConfig,
+    /// Where display changes made while running are saved
+    config_path: PathBuf,
+    /// Fullscreen mode Alt+Enter switches back to from windowed
+    fullscreen_mode: DisplayMode,
     input: Input,
     scene: Scene,
+    /// Where the scene was last loaded from or saved to
+    scene_path: Option<PathBuf>,
+    loader: AssetLoader,
+    /// Replaces the scene once its models have loaded, with a loading screen shown until then
+    pending_load: Option<(PendingLoad, Vec<LoadId>)>,
+    /// Models being imported, spawned once loaded
+    pending_imports: Vec<(LoadId, PathBuf)>,
+    /// Loads and unloads the cells of a streamed world as the camera moves
+    streamer: Option<SceneStreamer>,
+    /// Quick save applied once the scene it was saved in has loaded
+    pending_save: Option<SaveGame>,
     opengl_context: OpenGLContext,
     gui: EguiGlium,
     state: FrameState,
+    graphics_settings: GraphicsSettings,
+    time_of_day: TimeOfDay,
+    weather: Weather,
+    portal_renderer: PortalRenderer,
+    portal_teleporter: PortalTeleporter,
+    /// Runs the scene's scripts each fixed step
+    scripts: ScriptHost,
+    post_processor: PostProcessor,
+    /// Passes drawing each frame, taken out while they run as they draw the editor
+    render_graph: RenderGraph<Editor>,
+    capture: Capture,
+    hud: Hud,
+    weapons: WeaponSystem,
+    /// Whether fire was held this frame, for the fixed steps that follow
+    trigger_held: bool,
+    /// Taken away by enemies' attacks
+    player_health: Health,
+    /// Seconds until the player respawns while they're dead
+    player_respawn: Option<f32>,
+    /// The last few seconds of the scene, played back by the killcam
+    replay_buffer: ReplayBuffer,
+    /// Shows who killed the player, in place of the simulation until it finishes
+    killcam: Option<Killcam>,
+    /// `None` when there's no output device
+    audio: Option<Audio>,
+    /// Distance walked since the last footstep
+    footstep_distance: f32,
+    /// Connection to a multiplayer server, if joined
+    client: Option<Client>,
+    server_address: String,
+    timestep: FixedTimestep,
+    /// Pauses, slows down and steps through the simulation
+    time: TimeControl,
+    /// Input recorded with `--record`, saved to the path on exit
+    recorder: Option<(InputRecorder, PathBuf)>,
+    /// Input played back with `--replay` in place of the devices, until it runs out
+    replay: Option<InputReplay>,
+    /// Whether the recording or replay has begun, which waits for the scene to load so both
+    /// start from the same state
+    session_started: bool,
+    /// Cameras of the local players after the first, who play split-screen with gamepads
+    split_screen: Vec<Camera>,
+    console: Console<Editor>,
+    /// Cheat keeping the player from being hurt
+    god_mode: bool,
+    /// Zoom, shake and recoil on top of the scene's camera
+    camera_effects: CameraEffects,
     sender: Sender<EngineEvent>,
     receiver: Receiver<EngineEvent>,
 }
 
 impl Editor {
-    pub fn new(event_loop: &EventLoop<()>) -> Self {
-        color_eyre::install().unwrap();
-        debug::set_up_logging();
+    pub fn new(config: AppConfig, config_path: PathBuf, event_loop: &EventLoop<()>) -> Self {
+        // Both seed random numbers, so come before anything is spawned
+        let replay = config.replay.as_ref().and_then(|path| {
+            InputRecording::load(path)
+                .map(InputReplay::start)
+                .map_err(|error| warn!("Could not load input recording {:?}: {}", path, error))
+                .ok()
+        });
+        let recorder = config
+            .record
+            .as_ref()
+            .map(|path| (InputRecorder::start(config.scene.clone()), path.clone()));
+
+        let graphics_settings =
+            GraphicsSettings::load(&config.graphics_settings).unwrap_or_else(|error| {
+                warn!(
+                    "Using default graphics settings, could not load {:?}: {}",
+                    config.graphics_settings, error
+                );
+                GraphicsSettings::default()
+            });
+
+        // The GUI, HUD, post-processing, portals and weather are drawn with glium
+        if config.backend != BackendKind::OpenGl {
+            warn!(
+                "The editor only draws with OpenGL, so {:?} is left to the game",
+                config.backend
+            );
+        }
+        let opengl_context = OpenGLContext::new(&config, &graphics_settings, event_loop);
+        info!("Drawing with {}", opengl_context.description());
+
+        let mut assets = Assets::new();
+        if let Err(error) = assets.load_names(&config.asset_names) {
+            warn!(
+                "Could not load asset names from {:?}: {}",
+                config.asset_names, error
+            );
+        }
 
-        // TODO deferred rendering https://learnopengl.com/Advanced-Lighting/Deferred-Shading
-        let opengl_context = OpenGLContext::new("We glutin teapot now", false, event_loop);
+        let mut scene = Scene::new("Untitled", Camera::default(), &mut assets);
 
-        let mut scene = Scene::new("Untitled", Camera::default(), &opengl_context.display).unwrap();
+        // Invisible ground for physics bodies to land on, with its top at the origin
+        let ground = scene.simulation.spawn();
+        scene.simulation.world.insert(
+            ground,
+            Collider {
+                offset: Vector3::new(0.0, -0.5, 0.0),
+                ..Collider::new(ColliderShape::Cuboid {
+                    half_extents: Vector3::new(500.0, 0.5, 500.0),
+                })
+            },
+        );
+        scene.simulation.world.insert(ground, Transform::default());
 
         scene.lines = vec![
             Line::new(
@@ -92,7 +392,38 @@ impl Editor {
             ),
         ];
 
-        let input = Input::new();
+        let weather = Weather::new(WeatherState::Clear, &opengl_context.display).unwrap();
+        let hud = Hud::new(
+            &mut scene.assets,
+            &opengl_context.display,
+            opengl_context.window.inner_size(),
+        )
+        .unwrap();
+
+        let audio = match Audio::new() {
+            Ok(audio) => Some(audio),
+            Err(error) => {
+                warn!("Running without sound, could not open audio: {}", error);
+                None
+            }
+        };
+
+        let mut weapons = WeaponSystem::new(Weapon::rifle());
+        weapons.projectile_model = Some(scene.load_model(Path::new(PHYSICS_CUBE_PATH)).unwrap());
+
+        let portal_renderer = PortalRenderer::new(&opengl_context.display).unwrap();
+        let post_processor =
+            PostProcessor::new(&mut scene.assets, &opengl_context.display).unwrap();
+
+        let action_map = ActionMap::load(&config.bindings).unwrap_or_else(|error| {
+            warn!(
+                "Using default key bindings, could not load {:?}: {}",
+                config.bindings, error
+            );
+            ActionMap::default()
+        });
+        let mut input = Input::with_action_map(action_map);
+        input.cursor_sensitivity = config.mouse_sensitivity;
 
         let gui = EguiGlium::new(
             ViewportId::ROOT,
@@ -107,208 +438,2899 @@ impl Editor {
             deltatime: 0.0,
             fps: 0.0,
             using_viewport: false,
+            cursor_locked: false,
+            frame_times: VecDeque::with_capacity(FRAME_TIME_HISTORY),
+            show_debug_overlay: false,
+            show_log: false,
+            log_level: LevelFilter::Info,
+            log_module: String::new(),
+            pointer_over_gui: false,
+            picked: None,
+            show_editor_panel: false,
+            selected: None,
+            show_gizmos: false,
         };
 
         let (sender, receiver): (Sender<EngineEvent>, Receiver<EngineEvent>) = mpsc::channel();
+        let start_scene = match &replay {
+            Some(replay) => replay.recording().scene.clone(),
+            None => config.scene.clone(),
+        };
+        if let Some(scene_path) = start_scene {
+            sender.send(EngineEvent::LoadScene(scene_path)).unwrap();
+        }
+
+        let fullscreen_mode = match config.display_mode {
+            DisplayMode::Windowed => DisplayMode::Borderless,
+            mode => mode,
+        };
 
         Self {
+            config,
+            config_path,
+            fullscreen_mode,
             opengl_context,
             scene,
+            scene_path: None,
+            loader: AssetLoader::new(),
+            scripts: ScriptHost::new(),
+            pending_load: None,
+            pending_imports: vec![],
+            streamer: None,
+            pending_save: None,
             input,
             gui,
             state,
+            graphics_settings,
+            time_of_day: TimeOfDay::default(),
+            weather,
+            portal_renderer,
+            portal_teleporter: PortalTeleporter::default(),
+            post_processor,
+            render_graph: Self::render_graph(),
+            capture: Capture::new(),
+            hud,
+            weapons,
+            trigger_held: false,
+            player_health: Health::new(PLAYER_HEALTH),
+            player_respawn: None,
+            replay_buffer: ReplayBuffer::new(KILLCAM_LENGTH),
+            killcam: None,
+            audio,
+            footstep_distance: 0.0,
+            client: None,
+            server_address: format!("127.0.0.1:{}", net::DEFAULT_PORT),
+            timestep: FixedTimestep::default(),
+            time: TimeControl::new(),
+            recorder,
+            replay,
+            session_started: false,
+            split_screen: vec![],
+            console: Self::console(),
+            god_mode: false,
+            camera_effects: CameraEffects::new(),
             sender,
             receiver,
         }
     }
-}
 
-impl Application for Editor {
-    fn run(mut self, event_loop: EventLoop<()>) {
-        event_loop
-            .run(move |event, event_loop_window_target| {
-                event_loop_window_target.set_control_flow(ControlFlow::Poll);
-                self.input
-                    .process_event(self.opengl_context.window.id(), &event);
+    /// How far through loading a scene or map the editor is, in the middle of the window
+    fn render_loading_screen(ctx: &egui::Context, pending: &PendingLoad, progress: f32) {
+        let name = match pending {
+            PendingLoad::Scene(path) => path.display().to_string(),
+            PendingLoad::Map(map) => map.title.clone(),
+            PendingLoad::Level(_) => GENERATED_LEVEL_TITLE.to_owned(),
+        };
 
-                match event {
-                    Event::WindowEvent {
-                        event: window_event,
-                        window_id,
-                    } if window_id == self.opengl_context.window.id() => {
-                        match &window_event {
-                            WindowEvent::CloseRequested => event_loop_window_target.exit(),
-                            WindowEvent::Resized(new_size) => {
-                                self.opengl_context
-                                    .display
-                                    .resize((new_size.width, new_size.height));
-                                self.scene.camera.set_aspect_ratio(
-                                    new_size.width as f32 / new_size.height as f32,
-                                );
-                            }
-                            WindowEvent::RedrawRequested => {
-                                self.state.start = Instant::now();
+        egui::Window::new("Loading")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .title_bar(false)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!("Loading {}...", name));
+                ui.add(egui::ProgressBar::new(progress).show_percentage());
+            });
+    }
 
-                                if self.input.key_pressed(KeyCode::Escape) {
-                                    event_loop_window_target.exit();
-                                }
+    /// Drops down from the top of the window, taking the keyboard while it's open. Returns
+    /// whether a line was entered.
+    fn render_console(ctx: &egui::Context, console: &mut Console<Self>) -> bool {
+        let mut submitted = false;
 
-                                self.update();
-                                self.render();
+        egui::TopBottomPanel::top("console").show(ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(CONSOLE_HEIGHT)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for line in console.output() {
+                        ui.monospace(line);
+                    }
+                });
 
-                                self.state.update_statistics();
-                            }
-                            _ => (),
-                        };
+            // Taken before the text box sees them, which would move focus or the cursor
+            let (tab, up, down) = ctx.input_mut(|input| {
+                (
+                    input.consume_key(egui::Modifiers::NONE, egui::Key::Tab),
+                    input.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                    input.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                )
+            });
+            if tab {
+                console.complete();
+            }
+            if up {
+                console.history_previous();
+            }
+            if down {
+                console.history_next();
+            }
 
-                        let event_response = self
-                            .gui
-                            .on_event(&self.opengl_context.window, &window_event);
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut console.input)
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(f32::INFINITY),
+            );
+            // The key opening the console is typed into it as well
+            console.input.retain(|character| character != '`');
 
-                        if event_response.repaint {
-                            self.opengl_context.window.request_redraw();
+            if response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                submitted = true;
+            }
+            response.request_focus();
+        });
+
+        submitted
+    }
+
+    /// Frame timings and what the last frame drew, in the top right corner
+    fn render_debug_overlay(ctx: &egui::Context, state: &FrameState, scene: &Scene) {
+        egui::Window::new("Debug")
+            .anchor(egui::Align2::RIGHT_TOP, [-10.0, 10.0])
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let (average, worst) = match state.frame_times.len() {
+                    0 => (0.0, 0.0),
+                    frames => (
+                        state.frame_times.iter().sum::<f32>() / frames as f32,
+                        state.frame_times.iter().copied().fold(0.0, f32::max),
+                    ),
+                };
+
+                ui.label(format!("{:.1} FPS", state.fps));
+                ui.label(format!(
+                    "Frame time: {:.2} ms average, {:.2} ms worst",
+                    average, worst
+                ));
+
+                Self::frame_time_graph(ui, &state.frame_times, worst);
+
+                ui.label(format!("Draw calls: {}", scene.draw_calls));
+                ui.label(format!(
+                    "Instances drawn: {}, culled: {}, occluded: {}",
+                    scene.culling_statistics.drawn,
+                    scene.culling_statistics.culled,
+                    scene.culling_statistics.occluded
+                ));
+
+                let position = scene.camera.position;
+                ui.label(format!(
+                    "Camera: {:.2}, {:.2}, {:.2}",
+                    position.x, position.y, position.z
+                ));
+
+                Self::render_timings(ui, "CPU", &profiling::last_frame());
+                if scene.gpu_timer.enabled {
+                    Self::render_timings(ui, "GPU", &scene.gpu_timer.timings);
+                }
+
+                if profiling::is_tracing() {
+                    if ui.button("Save trace").clicked() {
+                        match profiling::save_trace(Path::new(TRACE_PATH)) {
+                            Ok(()) => info!("Saved trace to {}", TRACE_PATH),
+                            Err(error) => warn!("Failed to save trace: {}", error),
                         }
                     }
-                    Event::AboutToWait => self.opengl_context.window.request_redraw(),
-                    _ => (),
+                } else if ui.button("Record trace").clicked() {
+                    profiling::start_trace();
                 }
-            })
-            .unwrap();
+            });
     }
 
-    fn update(&mut self) {
-        for engine_event in self.receiver.try_iter() {
-            match engine_event {
-                EngineEvent::LoadScene(scene_string) => {
-                    self.scene = Scene::deserialize(
-                        &scene_string,
-                        &self.opengl_context.display,
-                        self.opengl_context.window.inner_size(),
-                    )
-                    .unwrap()
-                }
-                EngineEvent::ImportModel(model_path) => self
-                    .scene
-                    .import_model(model_path.as_path(), &self.opengl_context.display)
-                    .unwrap(),
-            }
-        }
-
-        self.state.using_viewport = self.input.mouse_button_down(MouseButton::Middle)
-            || self.input.key_down(KeyCode::Space);
+    /// Recent log lines, filtered by level and module
+    fn render_log(ctx: &egui::Context, state: &mut FrameState) {
+        egui::Window::new("Log")
+            .anchor(egui::Align2::LEFT_BOTTOM, [10.0, -10.0])
+            .default_size([640.0, 240.0])
+            .resizable(true)
+            .collapsible(true)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_label("Level")
+                        .selected_text(state.log_level.as_str())
+                        .show_ui(ui, |ui| {
+                            for level in LevelFilter::iter().skip(1) {
+                                ui.selectable_value(&mut state.log_level, level, level.as_str());
+                            }
+                        });
+                    ui.add(
+                        egui::TextEdit::singleline(&mut state.log_module)
+                            .hint_text("Module")
+                            .desired_width(160.0),
+                    );
+                    if ui.button("Clear").clicked() {
+                        debug::clear_log_lines();
+                    }
+                });
 
-        if self.state.using_viewport {
-            self.scene.camera.update(&self.input);
-            self.opengl_context.capture_cursor();
-            self.opengl_context.window.set_cursor_visible(false);
-            self.opengl_context.center_cursor();
-        } else {
-            self.opengl_context.release_cursor();
-            self.opengl_context.window.set_cursor_visible(true);
-        }
+                ui.separator();
 
-        self.input.reset_internal_state();
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        debug::for_each_log_line(|line| {
+                            if line.level <= state.log_level
+                                && line.target.contains(state.log_module.as_str())
+                            {
+                                ui.colored_label(Self::log_color(line), Self::log_text(line));
+                            }
+                        });
+                    });
+            });
+    }
 
-        if self.state.frame_count % 5 == 0 {
-            self.opengl_context.window.set_title(
-                format!("Editing {} at {:.1} FPS", self.scene.title, self.state.fps).as_str(),
-            );
+    fn log_color(line: &LogLine) -> egui::Color32 {
+        match line.level {
+            log::Level::Error => egui::Color32::RED,
+            log::Level::Warn => egui::Color32::YELLOW,
+            log::Level::Info => egui::Color32::LIGHT_GRAY,
+            log::Level::Debug => egui::Color32::GRAY,
+            log::Level::Trace => egui::Color32::DARK_GRAY,
         }
     }
 
-    fn render(&mut self) {
-        let window_size = self.opengl_context.window.inner_size();
-        if window_size.width == 0 || window_size.height == 0 {
-            return;
-        }
+    fn log_text(line: &LogLine) -> egui::RichText {
+        egui::RichText::new(format!(
+            "{} {:5} {}: {}",
+            line.time, line.level, line.target, line.message
+        ))
+        .monospace()
+    }
 
-        for model_instance in self.scene.model_instances.iter_mut() {
-            model_instance.transform.rotation =
-                Quaternion::from_angle_y(Deg((self.state.frame_count % 360) as f32));
-        }
+    /// The scene's entities, with buttons to spawn, delete and save them, and an inspector editing
+    /// the selected entity's components live
+    fn render_editor_panel(
+        ctx: &egui::Context,
+        state: &mut FrameState,
+        scene: &mut Scene,
+        scene_path: Option<&Path>,
+        sender: &Sender<EngineEvent>,
+    ) {
+        egui::SidePanel::right("editor_panel").show(ctx, |ui| {
+            ui.heading("Hierarchy");
 
-        let mut target = self.opengl_context.display.draw();
-        {
-            self.scene.render(&self.opengl_context.display, &mut target);
+            egui::ScrollArea::vertical()
+                .id_source("hierarchy")
+                .max_height(ui.available_height() / 2.0)
+                .show(ui, |ui| {
+                    let world = &scene.simulation.world;
+                    let roots = world
+                        .entities()
+                        .filter(|&entity| !world.has::<Parent>(entity))
+                        .sorted()
+                        .collect_vec();
 
-            self.render_gui();
+                    for entity in roots {
+                        Self::render_hierarchy_entry(ui, world, entity, &mut state.selected);
+                    }
+                });
 
-            self.gui.paint(&self.opengl_context.display, &mut target);
-        }
-        target.finish().unwrap();
-    }
+            state.selected = state
+                .selected
+                .filter(|&entity| scene.simulation.world.contains(entity));
+            let selected_model = state
+                .selected
+                .and_then(|entity| scene.simulation.world.get::<ModelInstance>(entity))
+                .map(|model_instance| model_instance.model.clone());
 
-    fn render_gui(&mut self) {
-        self.gui.run(&self.opengl_context.window, |ctx| {
-            egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
-                egui::menu::bar(ui, |ui| {
-                    ui.with_layout(egui::Layout::left_to_right(Align::Center), |ui| {
-                        ui.menu_button("File", |ui| {
-                            if ui.add(Button::new("Open scene")).clicked() {
-                                let sender = self.sender.clone();
+            ui.horizontal(|ui| {
+                // Another instance of the selected model, in front of the camera
+                if ui
+                    .add_enabled(selected_model.is_some(), Button::new("Spawn instance"))
+                    .clicked()
+                {
+                    let camera = &scene.camera;
+                    let translation = (camera.position + camera.forward_direction * 3.0).to_vec();
 
-                                std::thread::spawn(move || {
-                                    if let Some(file) = FileDialog::new()
-                                        .add_filter("json", &["json"])
-                                        .set_can_create_directories(true)
-                                        .set_directory("/")
-                                        .pick_file()
-                                    {
-                                        let scene_string = std::fs::read_to_string(file).unwrap();
+                    let entity = scene.spawn_model(
+                        selected_model.unwrap(),
+                        Transform {
+                            translation,
+                            ..Transform::default()
+                        },
+                    );
+                    scene.simulation.world.insert(
+                        entity,
+                        Tweens::from(Tween::spin(Vector3::unit_y(), SPIN_PERIOD)),
+                    );
+                    state.selected = Some(entity);
+                }
 
-                                        sender.send(EngineEvent::LoadScene(scene_string)).unwrap();
-                                    }
-                                });
+                if ui
+                    .add_enabled(state.selected.is_some(), Button::new("Delete"))
+                    .clicked()
+                {
+                    scene.simulation.despawn(state.selected.take().unwrap());
+                    state.picked = None;
+                }
 
-                                ui.close_menu();
-                            }
+                if ui.button("Save scene").clicked() {
+                    Self::save_scene(scene, scene_path, sender);
+                }
+            });
 
-                            if ui.add(Button::new("Save as")).clicked() {
-                                self.scene.save_as();
-                                ui.close_menu();
+            ui.separator();
+            ui.heading("Inspector");
+
+            let Some(entity) = state.selected else {
+                ui.label("Select an entity to edit it");
+                return;
+            };
+            ui.label(Self::entity_label(&scene.simulation.world, entity));
+
+            egui::ScrollArea::vertical()
+                .id_source("inspector")
+                .show(ui, |ui| {
+                    if let Some(transform) = scene.simulation.world.get::<Transform>(entity) {
+                        let mut transform = transform.clone();
+
+                        ui.collapsing("Transform", |ui| {
+                            if Self::render_transform_editor(ui, &mut transform) {
+                                scene.simulation.set_transform(entity, transform);
                             }
                         });
+                    }
 
-                        ui.menu_button("Scene", |ui| {
-                            if ui.add(Button::new("Import models")).clicked() {
-                                let sender = self.sender.clone();
+                    if let Some(light) = scene.simulation.world.get_mut::<Light>(entity) {
+                        ui.collapsing("Light", |ui| Self::render_light_editor(ui, light));
+                    }
 
-                                std::thread::spawn(move || {
-                                    if let Some(paths) = FileDialog::new()
-                                        .add_filter("gltf", &["gltf", "glb"])
-                                        .set_can_create_directories(true)
-                                        .set_directory("/")
-                                        .pick_files()
-                                    {
-                                        for path in paths {
-                                            sender.send(EngineEvent::ImportModel(path)).unwrap();
-                                        }
+                    if let Some(probe) = scene.simulation.world.get_mut::<ReflectionProbe>(entity) {
+                        ui.collapsing("Reflection probe", |ui| {
+                            Self::render_vector_editor(ui, "Extents", probe.extents.as_mut(), 0.1);
+                            egui::ComboBox::from_label("Update")
+                                .selected_text(format!("{:?}", probe.update))
+                                .show_ui(ui, |ui| {
+                                    for update in [ProbeUpdate::Baked, ProbeUpdate::Realtime] {
+                                        ui.selectable_value(
+                                            &mut probe.update,
+                                            update,
+                                            format!("{:?}", update),
+                                        );
                                     }
                                 });
+                        });
+                    }
 
-                                ui.close_menu();
-                            }
+                    if let Some(reflection) =
+                        scene.simulation.world.get_mut::<PlanarReflection>(entity)
+                    {
+                        ui.collapsing("Planar reflection", |ui| {
+                            ui.add(
+                                egui::Slider::new(&mut reflection.resolution_scale, 0.1..=1.0)
+                                    .text("Resolution scale"),
+                            );
                         });
+                    }
 
-                        ui.menu_button("Run", |ui| {
-                            if ui.add(Button::new("Run game")).clicked() {
-                                std::process::Command::new("cargo")
-                                    .arg("run")
-                                    .arg("--package")
-                                    .arg("shooter-game")
-                                    .arg("--bin")
-                                    .arg("game")
-                                    .spawn()
-                                    .unwrap();
+                    if let Some(model_instance) =
+                        scene.simulation.world.get::<ModelInstance>(entity)
+                    {
+                        ui.collapsing("Materials", |ui| {
+                            ui.label("Shared by every instance of the model, and not saved");
 
-                                ui.close_menu();
+                            for (index, material) in
+                                model_instance.model.materials.iter().enumerate()
+                            {
+                                Self::render_material_editor(ui, index, &mut material.borrow_mut());
                             }
                         });
-                    });
+                    }
                 });
-            });
+        });
+    }
+
+    /// An entity and, nested under it, its children
+    fn render_hierarchy_entry(
+        ui: &mut egui::Ui,
+        world: &World,
+        entity: UUID,
+        selected: &mut Option<UUID>,
+    ) {
+        let label = Self::entity_label(world, entity);
+        let children = hierarchy::children(world, entity).sorted().collect_vec();
+
+        if children.is_empty() {
+            if ui
+                .selectable_label(*selected == Some(entity), label)
+                .clicked()
+            {
+                *selected = Some(entity);
+            }
+            return;
+        }
+
+        egui::collapsing_header::CollapsingState::load_with_default_open(
+            ui.ctx(),
+            ui.make_persistent_id(entity),
+            false,
+        )
+        .show_header(ui, |ui| {
+            if ui
+                .selectable_label(*selected == Some(entity), label)
+                .clicked()
+            {
+                *selected = Some(entity);
+            }
+        })
+        .body(|ui| {
+            for child in children {
+                Self::render_hierarchy_entry(ui, world, child, selected);
+            }
+        });
+    }
+
+    /// What an entity is, going by its components, followed by its ID
+    fn entity_label(world: &World, entity: UUID) -> String {
+        let kind = if let Some(model_instance) = world.get::<ModelInstance>(entity) {
+            let path = &model_instance.model.path;
+            path.file_stem().map_or_else(
+                || path.display().to_string(),
+                |stem| stem.to_string_lossy().into(),
+            )
+        } else if world.has::<Light>(entity) {
+            "Light".to_owned()
+        } else if world.has::<ReflectionProbe>(entity) {
+            "Reflection probe".to_owned()
+        } else if world.has::<PlanarReflection>(entity) {
+            "Planar reflection".to_owned()
+        } else if world.has::<Collider>(entity) {
+            "Collider".to_owned()
+        } else {
+            "Entity".to_owned()
+        };
+
+        format!("{} {}", kind, entity)
+    }
+
+    /// Returns whether the transform was changed
+    fn render_transform_editor(ui: &mut egui::Ui, transform: &mut Transform) -> bool {
+        let euler = Euler::from(transform.rotation);
+        let mut rotation = [euler.x, euler.y, euler.z].map(|angle| Deg::from(angle).0);
+
+        let mut changed =
+            Self::render_vector_editor(ui, "Translation", transform.translation.as_mut(), 0.1);
+
+        if Self::render_vector_editor(ui, "Rotation", &mut rotation, 1.0) {
+            let [x, y, z] = rotation.map(Deg);
+            transform.rotation = Quaternion::from(Euler::new(x, y, z));
+            changed = true;
+        }
+
+        changed |= Self::render_vector_editor(ui, "Scale", transform.scale.as_mut(), 0.01);
+
+        changed
+    }
+
+    /// Returns whether any of the values were changed
+    fn render_vector_editor(
+        ui: &mut egui::Ui,
+        label: &str,
+        values: &mut [f32; 3],
+        speed: f32,
+    ) -> bool {
+        ui.horizontal(|ui| {
+            ui.label(label);
+
+            values.iter_mut().fold(false, |changed, value| {
+                ui.add(egui::DragValue::new(value).speed(speed)).changed() || changed
+            })
+        })
+        .inner
+    }
 
-            egui::SidePanel::left("my_side_panel").show(ctx, |ui| {});
+    fn render_light_editor(ui: &mut egui::Ui, light: &mut Light) {
+        ui.horizontal(|ui| {
+            let mut color: [u8; 3] = light.color.into_format::<u8>().into();
+
+            ui.label("Color");
+            if ui.color_edit_button_srgb(&mut color).changed() {
+                light.color = Srgb::<u8>::from(color).into_format();
+            }
         });
+
+        ui.add(
+            egui::DragValue::new(&mut light.intensity)
+                .speed(0.1)
+                .clamp_range(0.0..=f32::MAX)
+                .prefix("Intensity: "),
+        );
+
+        match &mut light.kind {
+            LightKind::Directional => {}
+            LightKind::Point { radius } => {
+                ui.add(
+                    egui::DragValue::new(radius)
+                        .speed(0.1)
+                        .clamp_range(0.0..=f32::MAX)
+                        .prefix("Radius: "),
+                );
+            }
+            LightKind::Spot {
+                radius,
+                inner_angle,
+                outer_angle,
+            } => {
+                ui.add(
+                    egui::DragValue::new(radius)
+                        .speed(0.1)
+                        .clamp_range(0.0..=f32::MAX)
+                        .prefix("Radius: "),
+                );
+                ui.add(egui::Slider::new(&mut outer_angle.0, 0.0..=90.0).text("Outer angle"));
+                ui.add(
+                    egui::Slider::new(&mut inner_angle.0, 0.0..=outer_angle.0).text("Inner angle"),
+                );
+            }
+        }
+    }
+
+    fn render_material_editor(ui: &mut egui::Ui, index: usize, material: &mut Material) {
+        let name = material
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Material {}", index));
+
+        egui::CollapsingHeader::new(name)
+            .id_source(index)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Albedo");
+                    ui.color_edit_button_rgba_unmultiplied(&mut material.albedo_factor);
+                });
+                ui.add(
+                    egui::Slider::new(&mut material.metallic_factor, 0.0..=1.0).text("Metallic"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut material.roughness_factor, 0.0..=1.0).text("Roughness"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut material.normal_scale, 0.0..=2.0).text("Normal scale"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut material.occlusion_strength, 0.0..=1.0)
+                        .text("Occlusion"),
+                );
+                ui.horizontal(|ui| {
+                    ui.label("Emissive");
+                    ui.color_edit_button_rgb(&mut material.emissive_factor);
+                });
+                egui::ComboBox::from_label("Blend mode")
+                    .selected_text(format!("{:?}", material.blend_mode))
+                    .show_ui(ui, |ui| {
+                        for blend_mode in [BlendMode::Opaque, BlendMode::Blend] {
+                            ui.selectable_value(
+                                &mut material.blend_mode,
+                                blend_mode,
+                                format!("{:?}", blend_mode),
+                            );
+                        }
+                    });
+            });
+    }
+
+    /// Each scope's time in the last frame, indented by how deeply it's nested
+    fn render_timings(ui: &mut egui::Ui, title: &str, timings: &[ScopeTiming]) {
+        egui::CollapsingHeader::new(title)
+            .default_open(true)
+            .show(ui, |ui| {
+                for timing in timings {
+                    ui.label(format!(
+                        "{}{}: {:.2} ms",
+                        "  ".repeat(timing.depth),
+                        timing.name,
+                        timing.duration.as_secs_f64() * 1000.0
+                    ));
+                }
+            });
+    }
+
+    /// Bars of each recent frame's time, scaled so the slowest reaches the top
+    fn frame_time_graph(ui: &mut egui::Ui, frame_times: &VecDeque<f32>, worst: f32) {
+        let (response, painter) = ui.allocate_painter(
+            egui::vec2(FRAME_TIME_HISTORY as f32, 60.0),
+            egui::Sense::hover(),
+        );
+        let rect = response.rect;
+
+        painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(160));
+
+        // Frames slower than 60 FPS are drawn in red
+        let scale = rect.height() / worst.max(1000.0 / 60.0);
+        for (index, &frame_time) in frame_times.iter().enumerate() {
+            let x = rect.left() + index as f32 + 0.5;
+            let color = if frame_time > 1000.0 / 60.0 {
+                egui::Color32::RED
+            } else {
+                egui::Color32::GREEN
+            };
+
+            painter.line_segment(
+                [
+                    egui::pos2(x, rect.bottom()),
+                    egui::pos2(x, rect.bottom() - frame_time * scale),
+                ],
+                egui::Stroke::new(1.0, color),
+            );
+        }
+    }
+
+    fn msaa_label(samples: u8) -> String {
+        match samples {
+            0 => "Off".to_owned(),
+            samples => format!("{}x", samples),
+        }
+    }
+
+    /// Marks every light and reflection, outlines the level's collision and the selected entity's
+    /// bounds
+    fn draw_gizmos(&mut self) {
+        let simulation = &self.scene.simulation;
+        let debug = &mut self.scene.debug;
+
+        for collider in simulation.colliders.iter() {
+            debug.draw_aabb(collider, colors::CYAN);
+        }
+
+        for (entity, light) in simulation.query::<Light>() {
+            let Some(matrix) = simulation.global_matrix(entity) else {
+                continue;
+            };
+            let position = Point3::from_vec(matrix.w.truncate());
+            let forward = matrix.transform_vector(-Vector3::unit_z()).normalize();
+
+            if !matches!(light.kind, LightKind::Directional) {
+                debug.draw_sphere(position, LIGHT_GIZMO_SIZE, light.color);
+            }
+            if !matches!(light.kind, LightKind::Point { .. }) {
+                debug.draw_ray(
+                    &Ray::new(position, forward),
+                    LIGHT_GIZMO_SIZE * 4.0,
+                    light.color,
+                );
+            }
+        }
+
+        let selected = self.state.selected.and_then(|entity| {
+            let model_instance = simulation.world.get::<ModelInstance>(entity)?;
+            Some((model_instance, simulation.global_matrix(entity)?))
+        });
+        if let Some((model_instance, matrix)) = selected {
+            debug.draw_box(&model_instance.model.bounds, matrix, colors::YELLOW);
+        }
+
+        for (entity, enemy) in simulation.query::<Enemy>() {
+            let Some(transform) = simulation.world.get::<Transform>(entity) else {
+                continue;
+            };
+
+            let mut from = Point3::from_vec(transform.translation);
+            for &to in enemy.path() {
+                debug.draw_line(from, to, colors::MAGENTA);
+                from = to;
+            }
+        }
+
+        for &spawn_point in simulation.spawn_points.iter() {
+            debug.draw_point(spawn_point, LIGHT_GIZMO_SIZE, colors::GREEN);
+        }
+
+        for (_, probe, transform) in simulation.world.query2::<ReflectionProbe, Transform>() {
+            let position = Point3::from_vec(transform.translation);
+            debug.draw_sphere(position, LIGHT_GIZMO_SIZE, colors::WHITE);
+            debug.draw_aabb(&probe.bounds(transform.translation), colors::WHITE);
+        }
+
+        // Along the normal of the plane it mirrors in
+        for (_, _, transform) in simulation.world.query2::<PlanarReflection, Transform>() {
+            let normal = PlanarReflection::plane(transform).truncate();
+            debug.draw_ray(
+                &Ray::new(Point3::from_vec(transform.translation), normal),
+                LIGHT_GIZMO_SIZE * 4.0,
+                colors::WHITE,
+            );
+        }
+    }
+
+    /// Selects the model under the cursor, or clears the selection if there is none
+    fn pick(&mut self) {
+        let Some(cursor_position) = self.input.cursor_position() else {
+            return;
+        };
+
+        let window_size = self.opengl_context.window.inner_size();
+        let ray = self.scene.camera.screen_ray(
+            cursor_position,
+            Vector2::new(window_size.width as f32, window_size.height as f32),
+        );
+
+        self.state.picked = self.scene.simulation.raycast(&ray, true);
+        self.state.selected = self.state.picked.map(|hit| hit.entity);
+    }
+
+    /// What to send a multiplayer server this frame
+    fn player_input(input: &Input, camera: &Camera) -> PlayerInput {
+        let mut movement = input.stick(Stick::Left);
+        for (action, direction) in [
+            ("move_forward", Vector2::unit_y()),
+            ("move_backward", -Vector2::unit_y()),
+            ("move_right", Vector2::unit_x()),
+            ("move_left", -Vector2::unit_x()),
+        ] {
+            if input.action_down(action) {
+                movement += direction;
+            }
+        }
+
+        PlayerInput {
+            movement,
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+            fire: input.action_down("fire"),
+            ..PlayerInput::default()
+        }
+    }
+
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.opengl_context.resize(new_size);
+        self.scene
+            .camera
+            .set_aspect_ratio(new_size.width as f32 / new_size.height as f32);
+        self.hud.resize(new_size);
+    }
+
+    /// Drops a physics cube in front of the camera, which shooting enough despawns
+    fn spawn_cube(&mut self) {
+        let cube = self.scene.load_model(Path::new(PHYSICS_CUBE_PATH)).unwrap();
+        let camera = &self.scene.camera;
+
+        let entity = self.scene.spawn_physics_model(
+            cube,
+            Transform {
+                translation: (camera.position + camera.forward_direction * 3.0).to_vec(),
+                scale: Vector3::new(0.25, 0.25, 0.25),
+                ..Transform::default()
+            },
+            RigidBody {
+                linear_velocity: camera.forward_direction * 5.0,
+                ..RigidBody::new(BodyKind::Dynamic)
+            },
+        );
+        // Shooting it enough despawns it
+        self.scene
+            .simulation
+            .world
+            .insert(entity, Health::new(30.0));
+    }
+
+    /// Puts an enemy in front of the camera, which respawns a while after it's killed
+    fn spawn_enemy(&mut self) {
+        let cube = self.scene.load_model(Path::new(PHYSICS_CUBE_PATH)).unwrap();
+        let camera = &self.scene.camera;
+
+        // On the ground in front of the camera, patrolling a square around there
+        let ahead = camera.position + camera.forward_direction * 6.0;
+        let center = Point3::new(ahead.x, 0.9, ahead.z);
+        let patrol = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .into_iter()
+            .map(|(x, z)| center + Vector3::new(x, 0.0, z) * ENEMY_PATROL_SIZE)
+            .collect();
+
+        let entity = self.scene.spawn_model(
+            cube,
+            Transform {
+                translation: center.to_vec(),
+                scale: Vector3::new(0.4, 0.9, 0.4),
+                ..Transform::default()
+            },
+        );
+        self.scene.simulation.world.insert(
+            entity,
+            Enemy {
+                eye_height: 0.7,
+                ..Enemy::new(patrol)
+            },
+        );
+        // Skinned enemies fall limp, others vanish until they respawn
+        self.scene.simulation.world.insert(
+            entity,
+            Health {
+                on_death: DeathBehavior::Ragdoll,
+                ..Health::new(50.0)
+            },
+        );
+        self.scene
+            .simulation
+            .world
+            .insert(entity, Billboard::bar(1.2));
+        self.scene.simulation.world.insert(entity, HealthBar);
+        self.scene.simulation.world.insert(
+            entity,
+            Respawn {
+                delay: ENEMY_RESPAWN_DELAY,
+                height: center.y,
+            },
+        );
+        self.scene
+            .set_collision_layers(entity, CollisionLayers::ENEMY);
+    }
+
+    /// Puts a health pickup on the ground in front of the camera
+    fn spawn_health_pickup(&mut self) {
+        let cube = self.scene.load_model(Path::new(PHYSICS_CUBE_PATH)).unwrap();
+        let camera = &self.scene.camera;
+        let ahead = camera.position + camera.forward_direction * 3.0;
+
+        let entity = self.scene.spawn_model(
+            cube.clone(),
+            Transform {
+                translation: Vector3::new(ahead.x, PICKUP_HEIGHT, ahead.z),
+                scale: Vector3::new(1.0, 1.0, 1.0) * PICKUP_SIZE,
+                ..Transform::default()
+            },
+        );
+        self.scene
+            .set_collision_layers(entity, CollisionLayers::PICKUP);
+        self.scene.simulation.world.insert(
+            entity,
+            Tweens(vec![
+                Tween::spin(Vector3::unit_y(), SPIN_PERIOD),
+                Tween::bob(PICKUP_BOB_HEIGHT, PICKUP_BOB_PERIOD),
+            ]),
+        );
+        // Reaching up to eye height, as the player is tested by where the camera is
+        let mut bounds = cube.bounds;
+        bounds.max.y += EYE_HEIGHT / PICKUP_SIZE;
+        self.scene.simulation.world.insert(
+            entity,
+            Trigger {
+                layers: CollisionLayers::PICKUP,
+                ..Trigger::new(bounds)
+            },
+        );
+        self.scene.simulation.world.insert(
+            entity,
+            Pickup {
+                kind: PickupKind::Health,
+                amount: PICKUP_HEALTH,
+            },
+        );
+        // Seen through walls, so pickups are easy to find
+        self.scene.simulation.world.insert(
+            entity,
+            Billboard {
+                size: Vector2::new(0.15, 0.15),
+                offset: Vector3::new(0.0, PICKUP_MARKER_HEIGHT, 0.0),
+                color: Srgba::new(0.2, 1.0, 0.4, 0.8),
+                always_on_top: true,
+                ..Billboard::default()
+            },
+        );
+    }
+
+    /// Commands and variables for testing, along with those of the modules the editor runs
+    fn console() -> Console<Self> {
+        let mut console = Console::new();
+
+        console.register_command(
+            "spawn",
+            "spawn <cube|enemy|pickup> [count]: Puts things in front of the camera",
+            |editor: &mut Self, arguments| {
+                let count = match arguments.get(1) {
+                    Some(_) => console::argument::<usize>(arguments, 1)?,
+                    None => 1,
+                };
+                let spawn: fn(&mut Self) = match arguments.first().copied() {
+                    Some("cube") => Self::spawn_cube,
+                    Some("enemy") => Self::spawn_enemy,
+                    Some("pickup") => Self::spawn_health_pickup,
+                    _ => bail!("Expected cube, enemy or pickup"),
+                };
+
+                for _ in 0..count {
+                    spawn(editor);
+                }
+                Ok(format!("Spawned {}", count))
+            },
+        );
+        console.register_command(
+            "generate_level",
+            "generate_level [seed]: Replaces the scene with generated rooms and corridors",
+            |editor: &mut Self, arguments| {
+                let seed = match arguments.first() {
+                    Some(_) => console::argument(arguments, 0)?,
+                    None => fastrand::u64(..),
+                };
+                let level = LevelGenerator {
+                    seed,
+                    ..LevelGenerator::default()
+                }
+                .generate();
+
+                let load = editor
+                    .loader
+                    .load_model(&editor.scene.assets, Path::new(CUBE_MODEL_PATH));
+                let rooms = level.rooms.len();
+                editor.pending_load = Some((PendingLoad::Level(level), vec![load]));
+
+                Ok(format!("Generating {} rooms from seed {}", rooms, seed))
+            },
+        );
+        console.register_command(
+            "teleport",
+            "teleport <x> <y> <z>: Moves the camera, keeping the way it faces",
+            |editor: &mut Self, arguments| {
+                let position = Point3::new(
+                    console::argument(arguments, 0)?,
+                    console::argument(arguments, 1)?,
+                    console::argument(arguments, 2)?,
+                );
+                let camera = &mut editor.scene.camera;
+                camera.look_at(position, camera.forward_direction);
+
+                Ok(format!("Teleported to {:?}", position))
+            },
+        );
+        console.register_command(
+            "set_time_scale",
+            "set_time_scale <scale>: Speeds up or slows down the simulation",
+            |editor: &mut Self, arguments| {
+                editor.time.time_scale = console::argument(arguments, 0)?;
+                Ok(format!("Time scale is {}", editor.time.time_scale))
+            },
+        );
+        console.register_command(
+            "toggle_wireframe",
+            "Draws models as lines or back again",
+            |editor: &mut Self, _| {
+                let wireframe = editor.scene.debug_view != DebugView::Wireframe;
+                editor.scene.debug_view = if wireframe {
+                    DebugView::Wireframe
+                } else {
+                    DebugView::Lit
+                };
+                Ok(format!("Wireframe is {}", on_off(wireframe)))
+            },
+        );
+        console.register_command(
+            "cycle_debug_view",
+            "Switches to the next debug view, back to lit after the last",
+            |editor: &mut Self, _| Ok(editor.cycle_debug_view()),
+        );
+        console.register_command(
+            "bake_reflections",
+            "Captures every reflection probe again",
+            |editor: &mut Self, _| {
+                editor.scene.bake_reflection_probes();
+                Ok(format!(
+                    "Baking {} reflection probes",
+                    editor.scene.simulation.query::<ReflectionProbe>().count()
+                ))
+            },
+        );
+        console.register_command(
+            "log_level",
+            "Sets the level logged for every module, or for the module given first",
+            |editor: &mut Self, arguments| {
+                let config = &mut editor.config.log;
+                let (module, level) = match arguments {
+                    [level] => (None, level),
+                    [module, level] => (Some(module), level),
+                    _ => bail!("Expected a level, or a module and a level"),
+                };
+                if LevelFilter::from_str(level).is_err() {
+                    bail!(
+                        "Expected off, error, warn, info, debug or trace, got {:?}",
+                        level
+                    );
+                }
+
+                match module {
+                    Some(module) => {
+                        config.modules.insert(module.to_string(), level.to_string());
+                    }
+                    None => config.level = level.to_string(),
+                }
+                debug::configure_logging(config);
+
+                Ok(format!(
+                    "Logging {} for {}",
+                    level,
+                    module.unwrap_or(&"every other module")
+                ))
+            },
+        );
+        console.register_command(
+            "god",
+            "Stops the player from being hurt or lets them be again",
+            |editor: &mut Self, _| {
+                editor.god_mode = !editor.god_mode;
+                Ok(format!("God mode is {}", on_off(editor.god_mode)))
+            },
+        );
+        console.register_command(
+            "kill_all",
+            "Deals every entity with health as much damage as it has left",
+            |editor: &mut Self, _| {
+                let damage = editor
+                    .scene
+                    .simulation
+                    .query::<Health>()
+                    .filter(|(entity, _)| !editor.scene.simulation.world.has::<Dead>(*entity))
+                    .map(|(entity, health)| Damage {
+                        entity,
+                        amount: health.current,
+                        point: editor.scene.camera.position,
+                        direction: Vector3::zero(),
+                        source: None,
+                    })
+                    .collect_vec();
+
+                let count = damage.len();
+                for damage in damage {
+                    editor.scene.events.send(damage);
+                }
+                Ok(format!("Killed {}", count))
+            },
+        );
+
+        console.register_cvar(
+            "time_scale",
+            "How fast the simulation runs",
+            |editor: &mut Self| &mut editor.time.time_scale,
+        );
+        console.register_cvar(
+            "cursor_sensitivity",
+            "Radians turned per pixel the mouse moves",
+            |editor: &mut Self| &mut editor.input.cursor_sensitivity,
+        );
+        console.register_cvar(
+            "gizmos",
+            "Draws the selected entity's gizmos",
+            |editor: &mut Self| &mut editor.state.show_gizmos,
+        );
+        console.register_cvar(
+            "fov",
+            "Field of view in degrees when not aiming",
+            |editor: &mut Self| &mut editor.camera_effects.fov,
+        );
+        console.register_cvar(
+            "camera_shake",
+            "Scales how much the camera shakes, 0 to turn it off",
+            |editor: &mut Self| &mut editor.camera_effects.shake,
+        );
+        Scene::register_cvars(&mut console, |editor: &mut Self| &mut editor.scene);
+
+        console
+    }
+
+    /// Returns what's shown now
+    fn cycle_debug_view(&mut self) -> String {
+        self.scene.debug_view = self.scene.debug_view.next();
+        format!("Showing {}", self.scene.debug_view.name())
+    }
+
+    /// Runs the line typed into the console on the editor
+    fn submit_console(&mut self) {
+        let mut console = std::mem::take(&mut self.console);
+        console.submit(self);
+        self.console = console;
+    }
+
+    /// Takes health off the player, starting the killcam and their respawn timer once it runs out
+    fn damage_player(&mut self, damage: f32, attacker: UUID) {
+        if self.player_respawn.is_some() || self.god_mode {
+            return;
+        }
+
+        self.player_health.current -= damage;
+        self.camera_effects
+            .add_trauma((damage * DAMAGE_TRAUMA).min(MAX_DAMAGE_TRAUMA));
+        if self.player_health.is_dead() {
+            info!(
+                "Killed by an enemy, respawning in {}s",
+                PLAYER_RESPAWN_DELAY
+            );
+            self.player_respawn = Some(PLAYER_RESPAWN_DELAY);
+            self.killcam = Killcam::start(
+                &self.replay_buffer,
+                KILLCAM_LENGTH,
+                attacker,
+                None,
+                &self.scene,
+            );
+        }
+    }
+
+    fn quick_save(&mut self) {
+        let camera = &self.scene.camera;
+        let player = PlayerState {
+            position: camera.position,
+            forward_direction: camera.forward_direction,
+            health: self.player_health.current,
+            respawn: self.player_respawn,
+            weapon: self.weapons.weapon.clone(),
+        };
+        let save = SaveGame::capture(&self.scene.simulation, self.scene_path.clone(), player);
+
+        match save.save(Path::new(QUICK_SAVE_PATH)) {
+            Ok(()) => info!("Quick saved to {}", QUICK_SAVE_PATH),
+            Err(error) => warn!("Could not quick save to {}: {}", QUICK_SAVE_PATH, error),
+        }
+    }
+
+    /// Loads the quick save, first loading the scene it was saved in again
+    fn quick_load(&mut self) {
+        let save = match SaveGame::load(Path::new(QUICK_SAVE_PATH)) {
+            Ok(save) => save,
+            Err(error) => {
+                warn!("Could not quick load {}: {}", QUICK_SAVE_PATH, error);
+                return;
+            }
+        };
+
+        // Reloaded even if it's open, so whatever was despawned since the save comes back
+        match save.scene.clone() {
+            Some(scene_path) => {
+                self.pending_save = Some(save);
+                self.sender
+                    .send(EngineEvent::LoadScene(scene_path))
+                    .unwrap();
+            }
+            None => {
+                warn!("The quick save's scene was never saved, so it's applied to the open one");
+                self.apply_save(save);
+            }
+        }
+    }
+
+    fn apply_save(&mut self, save: SaveGame) {
+        // Models of entities spawned while playing, which aren't in the scene file
+        let assets = &mut self.scene.assets;
+        let player = save.apply(&mut self.scene.simulation, |path| {
+            match assets.load_model(path) {
+                Ok(model) => Some(assets.model(model).clone()),
+                Err(error) => {
+                    warn!("Could not load {:?} for the quick save: {}", path, error);
+                    None
+                }
+            }
+        });
+
+        self.scene
+            .camera
+            .look_at(player.position, player.forward_direction);
+        self.player_health.current = player.health;
+        self.player_respawn = player.respawn;
+        self.weapons.equip(player.weapon);
+        self.scene.simulation.time = save.simulation_time;
+    }
+
+    /// Counts down the dead player's respawn timer, bringing them back at the spawn point furthest
+    /// from every living enemy once it's up
+    fn update_player_respawn(&mut self, deltatime: f32) {
+        let Some(remaining) = self.player_respawn.as_mut() else {
+            return;
+        };
+        *remaining -= deltatime;
+        if *remaining > 0.0 {
+            return;
+        }
+
+        self.player_respawn = None;
+        self.player_health = Health::new(PLAYER_HEALTH);
+
+        let enemies = self
+            .scene
+            .simulation
+            .query::<Enemy>()
+            .filter(|(entity, _)| !self.scene.simulation.world.has::<Dead>(*entity))
+            .filter_map(|(entity, _)| self.scene.simulation.world.get::<Transform>(entity))
+            .map(|transform| Point3::from_vec(transform.translation))
+            .collect::<Vec<_>>();
+        let spawn = gameplay::spawn_point(&self.scene.simulation, &enemies)
+            .unwrap_or(Point3::new(0.0, 0.0, 0.0))
+            + Vector3::unit_y() * EYE_HEIGHT;
+
+        let camera = &mut self.scene.camera;
+        camera.look_at(spawn, camera.forward_direction);
+    }
+
+    /// Writes the input recorded with `--record`, called on exit
+    fn save_recording(&self) {
+        let Some((recorder, path)) = &self.recorder else {
+            return;
+        };
+
+        let recording = recorder.recording();
+        match recording.save(path) {
+            Ok(()) => info!(
+                "Saved {} frames ({:.1}s) of input to {:?}",
+                recording.frames.len(),
+                recording.duration(),
+                path
+            ),
+            Err(error) => warn!("Could not save input recording to {:?}: {}", path, error),
+        }
+    }
+
+    /// Switches between windowed and the fullscreen mode last used
+    fn toggle_fullscreen(&mut self) {
+        self.config.display_mode = match self.config.display_mode {
+            DisplayMode::Windowed => self.fullscreen_mode,
+            _ => DisplayMode::Windowed,
+        };
+        self.apply_display_mode();
+    }
+
+    /// Switches the window to the config's display mode and saves it for next time
+    fn apply_display_mode(&mut self) {
+        if self.config.display_mode != DisplayMode::Windowed {
+            self.fullscreen_mode = self.config.display_mode;
+        }
+        self.opengl_context.set_display_mode(&self.config);
+
+        // Only the display options are saved, so command-line overrides aren't made permanent
+        let mut saved = match AppConfig::load(&self.config_path) {
+            Ok(saved) => saved,
+            Err(_) if !self.config_path.exists() => AppConfig::default(),
+            Err(error) => {
+                warn!(
+                    "Not saving display settings, as {:?} could not be read: {}",
+                    self.config_path, error
+                );
+                return;
+            }
+        };
+        saved.display_mode = self.config.display_mode;
+        saved.monitor = self.config.monitor.clone();
+        saved.resolution = self.config.resolution;
+        if let Err(error) = saved.save(&self.config_path) {
+            warn!(
+                "Could not save display settings to {:?}: {}",
+                self.config_path, error
+            );
+        }
+    }
+
+    /// Loops the ambient sounds the weather asks for at their volumes, stopping the rest
+    fn update_ambience(&mut self) {
+        let Some(audio) = &mut self.audio else {
+            return;
+        };
+
+        let playing = self.weather.ambience();
+        for ambience in Ambience::ALL {
+            let path = ambience.sound_path();
+
+            match playing.iter().find(|(playing, _)| *playing == ambience) {
+                Some(&(_, volume)) => {
+                    if let Err(error) = audio.set_loop(path, volume) {
+                        warn!("Could not play {:?}: {}", path, error);
+                    }
+                }
+                None => audio.stop_loop(path),
+            }
+        }
+    }
+
+    /// Plays an effect in both ears, if there is sound
+    fn play_sound(&mut self, path: &str, volume: f32) {
+        let Some(audio) = &mut self.audio else {
+            return;
+        };
+
+        if let Err(error) = audio
+            .load(Path::new(path))
+            .and_then(|sound| audio.play(&sound, volume))
+        {
+            warn!("Could not play {}: {}", path, error);
+        }
+    }
+
+    /// Saves to where the scene was loaded from or last saved to, asking where if it's new
+    fn save_scene(scene: &Scene, scene_path: Option<&Path>, sender: &Sender<EngineEvent>) {
+        match scene_path {
+            Some(path) => {
+                if let Err(error) = scene.save(path) {
+                    warn!("Could not save the scene to {:?}: {}", path, error);
+                }
+            }
+            None => Self::pick_save_path(sender.clone()),
+        }
+    }
+
+    fn pick_save_path(sender: Sender<EngineEvent>) {
+        std::thread::spawn(move || {
+            if let Some(save_path) = FileDialog::new().add_filter("json", &["json"]).save_file() {
+                sender.send(EngineEvent::SaveScene(save_path)).unwrap();
+            }
+        });
+    }
+}
+
+impl Application for Editor {
+    fn run(mut self, event_loop: EventLoop<()>) {
+        event_loop
+            .run(move |event, event_loop_window_target| {
+                event_loop_window_target.set_control_flow(ControlFlow::Poll);
+                if self.replay.is_none() {
+                    self.input
+                        .process_event(self.opengl_context.window.id(), &event);
+                }
+
+                match event {
+                    Event::WindowEvent {
+                        event: window_event,
+                        window_id,
+                    } if window_id == self.opengl_context.window.id() => {
+                        match &window_event {
+                            WindowEvent::CloseRequested => event_loop_window_target.exit(),
+                            WindowEvent::Resized(new_size) => {
+                                self.scene.events.send(WindowResized(*new_size));
+                            }
+                            WindowEvent::RedrawRequested => {
+                                self.state.start = Instant::now();
+
+                                if self.input.action_pressed("quit") {
+                                    event_loop_window_target.exit();
+                                }
+
+                                self.update();
+                                self.render();
+
+                                self.scene.gpu_timer.end_frame();
+                                profiling::end_frame(&self.scene.gpu_timer.timings);
+
+                                self.state.update_statistics();
+                            }
+                            _ => (),
+                        };
+
+                        let event_response = self
+                            .gui
+                            .on_event(&self.opengl_context.window, &window_event);
+
+                        if event_response.repaint {
+                            self.opengl_context.window.request_redraw();
+                        }
+                    }
+                    Event::AboutToWait => self.opengl_context.window.request_redraw(),
+                    Event::LoopExiting => self.save_recording(),
+                    _ => (),
+                }
+            })
+            .unwrap();
+    }
+
+    fn update(&mut self) {
+        let _scope = profiling::scope("Update");
+
+        for engine_event in self.receiver.try_iter() {
+            match engine_event {
+                EngineEvent::LoadScene(scene_path) => {
+                    let model_paths = match Scene::model_paths(&scene_path) {
+                        Ok(model_paths) => model_paths,
+                        Err(error) => {
+                            warn!("Could not load scene {:?}: {}", scene_path, error);
+                            // A save waiting on the scene would be applied to the next one opened
+                            self.pending_save = None;
+                            continue;
+                        }
+                    };
+                    let loads = model_paths
+                        .iter()
+                        .map(|path| self.loader.load_model(&self.scene.assets, path))
+                        .collect();
+                    self.pending_load = Some((PendingLoad::Scene(scene_path), loads));
+                }
+                EngineEvent::SaveScene(scene_path) => {
+                    // Left pointing at wherever it was saved before, if anywhere
+                    if let Err(error) = self.scene.save(&scene_path) {
+                        warn!("Could not save the scene to {:?}: {}", scene_path, error);
+                        continue;
+                    }
+                    self.scene_path = Some(scene_path);
+                }
+                EngineEvent::LoadMap(map_path) => {
+                    let map = match Map::load(&map_path) {
+                        Ok(map) => map,
+                        Err(error) => {
+                            warn!("Could not load map {:?}: {}", map_path, error);
+                            continue;
+                        }
+                    };
+
+                    let loads = map
+                        .assets
+                        .iter()
+                        .map(|asset| match &asset.source {
+                            AssetSource::Referenced(path) => {
+                                self.loader.load_model(&self.scene.assets, path)
+                            }
+                            AssetSource::Embedded(bytes) => self.loader.load_embedded_model(
+                                &self.scene.assets,
+                                &asset.name,
+                                bytes.clone(),
+                            ),
+                        })
+                        .collect();
+                    self.pending_load = Some((PendingLoad::Map(map), loads));
+                }
+                EngineEvent::OpenStreamedWorld(manifest_path) => {
+                    if let Some(mut streamer) = self.streamer.take() {
+                        streamer.unload_all(&mut self.scene);
+                    }
+                    match SceneStreamer::load(&manifest_path) {
+                        Ok(streamer) => self.streamer = Some(streamer),
+                        Err(error) => {
+                            warn!(
+                                "Could not open streamed world {:?}: {}",
+                                manifest_path, error
+                            )
+                        }
+                    }
+                }
+                EngineEvent::ExportStreamedWorld(directory) => {
+                    match StreamingManifest::split(
+                        &self.scene,
+                        STREAMING_CELL_SIZE,
+                        STREAMING_LOAD_RADIUS,
+                        &directory,
+                    ) {
+                        Ok(manifest) => info!(
+                            "Exported {} streamed cells to {:?}",
+                            manifest.cells.len(),
+                            directory
+                        ),
+                        Err(error) => {
+                            warn!(
+                                "Could not export streamed world to {:?}: {}",
+                                directory, error
+                            )
+                        }
+                    }
+                }
+                EngineEvent::ImportModel(model_path) => {
+                    let load = self.loader.load_model(&self.scene.assets, &model_path);
+                    self.pending_imports.push((load, model_path));
+                }
+                EngineEvent::LoadSkybox(skybox_path) => {
+                    match Skybox::load(SkyboxSource::Equirectangular(skybox_path.clone())) {
+                        Ok(skybox) => self.scene.skybox = Some(skybox),
+                        Err(error) => warn!("Could not load skybox {:?}: {}", skybox_path, error),
+                    }
+                }
+                EngineEvent::AddScript(script_path) => {
+                    let script_path = assets::relative_path(&script_path);
+                    if script_path.is_absolute() {
+                        warn!(
+                            "Script {:?} is outside the game's directory, so scenes saved with it \
+                             only open on this machine",
+                            script_path
+                        );
+                    }
+
+                    match self.scripts.add(&script_path) {
+                        Ok(()) => self.scene.scripts.push(script_path),
+                        Err(error) => warn!("Could not load script {:?}: {}", script_path, error),
+                    }
+                }
+                EngineEvent::LoadHeightmap(heightmap_path) => {
+                    let heightmap = match Heightmap::from_image(&heightmap_path) {
+                        Ok(heightmap) => heightmap,
+                        Err(error) => {
+                            warn!("Could not load heightmap {:?}: {}", heightmap_path, error);
+                            continue;
+                        }
+                    };
+
+                    let terrain = Terrain::new(heightmap, TERRAIN_SIZE, TERRAIN_HEIGHT);
+                    self.scene.set_terrain(Some(terrain));
+                }
+                EngineEvent::PlayMusic(music_path) => {
+                    if let Some(audio) = &mut self.audio {
+                        if let Err(error) = audio
+                            .load(&music_path)
+                            .and_then(|music| audio.play_music(&music, 1.0))
+                        {
+                            warn!("Could not play music {:?}: {}", music_path, error);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.loader
+            .update(&mut self.scene.assets, &self.opengl_context.display);
+        self.finish_loading();
+
+        if let Some(streamer) = &mut self.streamer {
+            let focus = self.scene.camera.position;
+            streamer.update(&mut self.scene, &mut self.loader, focus);
+        }
+
+        // Scenes are replaced when loading, so the settings are kept here
+        self.scene.graphics_settings = self.graphics_settings;
+
+        let changed_shaders = self.opengl_context.changed_shaders();
+        if !changed_shaders.is_empty() {
+            self.scene
+                .assets
+                .reload_programs(&changed_shaders, &self.opengl_context.display);
+        }
+
+        let in_session = self.recorder.is_some() || self.replay.is_some();
+        if in_session && !self.session_started && self.pending_load.is_none() {
+            self.session_started = true;
+            self.timestep.reset();
+
+            let recording = match (&self.recorder, &self.replay) {
+                (Some((recorder, _)), _) => recorder.recording(),
+                (None, Some(replay)) => replay.recording(),
+                (None, None) => unreachable!("A session needs a recorder or a replay"),
+            };
+            recording.seed_random();
+        }
+
+        let mut replayed_frame_time = None;
+        if self.replay.is_none() {
+            self.input.update_gamepads();
+        } else if self.session_started {
+            match self.replay.as_mut().unwrap().next_frame() {
+                Some(frame) => {
+                    self.input.restore(frame);
+                    self.state.deltatime = frame.deltatime;
+                    replayed_frame_time = Some(frame.frame_time);
+                }
+                None => {
+                    info!("Finished replaying input, handing back control");
+                    self.replay = None;
+                }
+            }
+        }
+
+        for WindowResized(new_size) in self.scene.events.drain::<WindowResized>() {
+            self.resize(new_size);
+        }
+
+        for event in self.input.action_events() {
+            self.scene.events.send(event);
+        }
+
+        let mut pick_pressed = false;
+        for event in self.scene.events.drain::<ActionEvent>() {
+            // Keys typed into the console don't do what they're bound to
+            let toggles_console =
+                matches!(&event, ActionEvent::Pressed(action) if action == "toggle_console");
+            if self.console.open && !toggles_console {
+                continue;
+            }
+
+            match event {
+                ActionEvent::Pressed(action) => match action.as_str() {
+                    "toggle_cursor_capture" => {
+                        self.state.cursor_locked = !self.state.cursor_locked;
+                    }
+                    "toggle_debug_overlay" => {
+                        self.state.show_debug_overlay = !self.state.show_debug_overlay;
+                    }
+                    "toggle_log" => self.state.show_log = !self.state.show_log,
+                    "toggle_console" => self.console.toggle(),
+                    "cycle_debug_view" => {
+                        let view = self.cycle_debug_view();
+                        info!("{}", view);
+                    }
+                    "toggle_editor_panel" => {
+                        self.state.show_editor_panel = !self.state.show_editor_panel;
+                    }
+                    "toggle_fullscreen"
+                        if self.input.key_down(KeyCode::AltLeft)
+                            || self.input.key_down(KeyCode::AltRight) =>
+                    {
+                        self.toggle_fullscreen();
+                    }
+                    "screenshot" => self.capture.take_screenshot(),
+                    "toggle_recording" => self.capture.toggle_recording(),
+                    "toggle_pause" => self.time.toggle_pause(),
+                    "step_frame" => self.time.step_once(),
+                    "quick_save" => self.quick_save(),
+                    "quick_load" => self.quick_load(),
+                    "toggle_slow_motion" => {
+                        self.time.time_scale = if self.time.time_scale == 1.0 {
+                            SLOW_MOTION_SCALE
+                        } else {
+                            1.0
+                        };
+                    }
+                    "pick" => pick_pressed = true,
+                    _ => (),
+                },
+                ActionEvent::Released(action) => {
+                    if action == "toggle_view_mode" {
+                        self.scene.camera.toggle_view_mode();
+                    }
+                }
+            }
+        }
+
+        self.state.using_viewport = !self.console.open
+            && (self.state.cursor_locked || self.input.action_down("grab_viewport"));
+
+        let camera_position = self.scene.camera.position;
+
+        // In split-screen, each player's camera only follows their own devices
+        let devices = self.input.assign_devices(self.split_screen.len() + 1);
+        for (camera, devices) in self.split_screen.iter_mut().zip(devices.iter().skip(1)) {
+            camera.update(&self.input.routed(devices), self.state.deltatime as f32);
+        }
+        let routed = (!self.split_screen.is_empty()).then(|| self.input.routed(&devices[0]));
+        let input = routed.as_ref().unwrap_or(&self.input);
+
+        // The killcam points the camera itself
+        let controlling_camera = self.killcam.is_none();
+        if self.state.using_viewport {
+            if controlling_camera {
+                self.scene.camera.update(input, self.state.deltatime as f32);
+            }
+            self.opengl_context.capture_cursor();
+            self.opengl_context.center_cursor();
+        } else {
+            // Orbit cameras can zoom and gamepads can move without grabbing the viewport
+            let zooming =
+                self.scene.camera.view_mode == ViewMode::Orbit && input.scroll_delta() != 0.0;
+            let using_gamepad = self.scene.camera.view_mode == ViewMode::FPS
+                && (!input.stick(Stick::Left).is_zero() || !input.stick(Stick::Right).is_zero());
+
+            if (zooming || using_gamepad) && controlling_camera {
+                self.scene.camera.update(input, self.state.deltatime as f32);
+            }
+
+            self.opengl_context.release_cursor();
+        }
+
+        self.input
+            .set_cursor_captured(self.opengl_context.cursor_captured());
+
+        if self.scene.camera.view_mode == ViewMode::FPS && controlling_camera {
+            // Walks over the terrain rather than through it
+            let camera = &mut self.scene.camera;
+            if let Some(ground) = self
+                .scene
+                .terrain
+                .as_ref()
+                .and_then(|terrain| terrain.height_at(camera.position.x, camera.position.z))
+            {
+                let mut position = camera.position;
+                position.y = ground + EYE_HEIGHT;
+                camera.look_at(position, camera.forward_direction);
+            }
+
+            let walked = self.scene.camera.position - camera_position;
+            self.footstep_distance += Vector2::new(walked.x, walked.z).magnitude();
+
+            if self.footstep_distance >= FOOTSTEP_SPACING {
+                self.footstep_distance = 0.0;
+                self.play_sound(FOOTSTEP_SOUND_PATH, 0.4);
+            }
+        }
+
+        if let Some(audio) = &mut self.audio {
+            audio.update(&self.scene.camera, &self.scene.simulation.world);
+        }
+
+        if let Some(client) = &mut self.client {
+            client.send_input(Self::player_input(&self.input, &self.scene.camera));
+            client.update(&mut self.scene);
+        }
+
+        if !self.state.using_viewport && !self.state.pointer_over_gui && pick_pressed {
+            self.pick();
+        }
+
+        // Only fire while looking around, so clicking the gui doesn't shoot
+        self.trigger_held = self.state.using_viewport && self.input.action_down("fire");
+        self.camera_effects.aim(
+            self.state.using_viewport
+                && self.player_respawn.is_none()
+                && self.input.action_down("aim"),
+        );
+        self.camera_effects
+            .update(&mut self.scene.camera, self.state.deltatime as f32);
+
+        let measured_frame_time = self.timestep.elapsed();
+        let frame_time = replayed_frame_time.unwrap_or(measured_frame_time);
+        if let Some((recorder, _)) = &mut self.recorder {
+            if self.session_started {
+                recorder.record(&self.input, self.state.deltatime, frame_time);
+            }
+        }
+
+        self.scripts.capture_input(&self.input);
+        self.input.reset_internal_state();
+
+        // Nothing is simulated until a recording or replay starts, so it starts the same each time
+        let steps = if in_session && !self.session_started {
+            0
+        } else {
+            self.timestep.advance_by(self.time.scale(frame_time)) + self.time.take_steps()
+        };
+        for _ in 0..steps {
+            self.fixed_update(self.timestep.step);
+        }
+        // Steps refit the bounds culling and picking use, so anything moved while paused is too
+        if steps == 0 {
+            self.scene.simulation.update_bounds();
+        }
+        self.scene.interpolation = self.timestep.alpha();
+
+        if self.state.show_gizmos {
+            self.draw_gizmos();
+        }
+
+        // Only while looking around, as that's when the editor plays like the game
+        self.hud.visible = self.state.using_viewport;
+        self.hud.health = Some(self.player_health.current / self.player_health.max);
+        self.hud.update(self.state.deltatime as f32);
+
+        // Particles and decals are part of the world, so stop along with it
+        let particle_deltatime = self.time.scale(self.state.deltatime);
+        self.scene
+            .particles
+            .update(&self.scene.simulation.world, particle_deltatime as f32);
+        self.scene
+            .decals
+            .update(&self.scene.simulation.world, particle_deltatime as f32);
+
+        if self.state.frame_count % 5 == 0 {
+            crash::set_scene(&self.scene.title);
+            self.opengl_context.window.set_title(
+                format!(
+                    "{} - editing {} at {:.1} FPS",
+                    self.config.title, self.scene.title, self.state.fps
+                )
+                .as_str(),
+            );
+        }
+    }
+
+    fn fixed_update(&mut self, deltatime: f64) {
+        let _scope = profiling::scope("Fixed update");
+
+        if let Some(killcam) = &mut self.killcam {
+            self.scene.simulation.store_previous_transforms();
+            if killcam.update(deltatime, &mut self.scene) == KillcamState::Finished {
+                self.killcam = None;
+            }
+            self.scene.simulation.update_hierarchy();
+            self.scene.simulation.update_bounds();
+            return;
+        }
+
+        // The dead can't shoot
+        let trigger = self.trigger_held && self.player_respawn.is_none();
+        self.weapons
+            .update(&mut self.scene, deltatime as f32, trigger);
+        gameplay::update(&mut self.scene, deltatime as f32);
+        let hits = self.scene.events.drain::<Hit>();
+        if self.weapons.fired() {
+            // Kicks up and a little to either side
+            let recoil = self.weapons.weapon.recoil.to_radians();
+            self.camera_effects.kick(
+                &mut self.scene.camera,
+                recoil,
+                recoil * RECOIL_SWAY * (fastrand::f32() * 2.0 - 1.0),
+            );
+
+            let camera = &self.scene.camera;
+            self.scene.particles.burst(
+                &ParticleEmitter::muzzle_flash(),
+                camera.position + camera.forward_direction * MUZZLE_DISTANCE,
+                camera.forward_direction,
+                MUZZLE_FLASH_PARTICLES,
+            );
+        }
+        for hit in hits.iter() {
+            self.scene.particles.burst(
+                &ParticleEmitter::sparks(),
+                hit.point,
+                -hit.direction,
+                SPARK_PARTICLES,
+            );
+
+            // Explosions shake the view the closer they are
+            if hit.explosive {
+                let distance = hit.point.distance(self.scene.camera.position);
+                self.camera_effects
+                    .add_trauma(1.0 - (distance / EXPLOSION_SHAKE_RADIUS).min(1.0));
+            }
+        }
+
+        // Only hits on something that can be hurt count, not on the level
+        let target_health = hits.iter().rev().find_map(|hit| {
+            let health = self.scene.simulation.world.get::<Health>(hit.entity)?;
+            Some(health.current / health.max)
+        });
+        if let Some(target_health) = target_health {
+            self.hud.show_hit_marker();
+            self.hud.show_target_health(target_health);
+        }
+        if self.weapons.fired() {
+            self.play_sound(GUNSHOT_SOUND_PATH, 0.6);
+        }
+
+        {
+            let _scope = profiling::scope("AI");
+            let player = self
+                .player_respawn
+                .is_none()
+                .then_some(self.scene.camera.position);
+            ai::update(&mut self.scene, player, deltatime as f32);
+        }
+        for attack in self.scene.events.drain::<EnemyAttack>() {
+            self.scene.particles.burst(
+                &ParticleEmitter::muzzle_flash(),
+                attack.origin,
+                (attack.target - attack.origin).normalize(),
+                MUZZLE_FLASH_PARTICLES,
+            );
+            self.play_sound(GUNSHOT_SOUND_PATH, 0.3);
+            if let Some(animator) = self
+                .scene
+                .simulation
+                .world
+                .get_mut::<AnimatorController>(attack.enemy)
+            {
+                animator.set_trigger(SHOOT_TRIGGER);
+            }
+            self.damage_player(attack.damage, attack.enemy);
+        }
+        self.update_player_respawn(deltatime as f32);
+
+        {
+            let _scope = profiling::scope("Simulation");
+            self.scene.simulation.step(deltatime);
+        }
+
+        let time = self.scene.simulation.time;
+        if self
+            .replay_buffer
+            .latest()
+            .map_or(true, |latest| time - latest.time >= KILLCAM_INTERVAL)
+        {
+            self.replay_buffer.record(time, &self.scene);
+        }
+
+        self.portal_teleporter.update(&mut self.scene);
+
+        let player = self
+            .player_respawn
+            .is_none()
+            .then_some(self.scene.camera.position);
+        collision::update_triggers(&mut self.scene, player);
+        for picked_up in self.scene.events.drain::<PickedUp>() {
+            if picked_up.entity.is_none() && picked_up.kind == PickupKind::Health {
+                let health = &mut self.player_health;
+                health.current = (health.current + picked_up.amount).min(health.max);
+            }
+        }
+
+        {
+            let _scope = profiling::scope("Scripts");
+            self.scripts.update(&mut self.scene, deltatime as f32);
+        }
+
+        self.time_of_day.update(deltatime as f32);
+        self.time_of_day.apply(&mut self.scene.environment);
+
+        self.weather.update(deltatime as f32, &self.scene.camera);
+        self.weather.apply(&mut self.scene.environment);
+        self.update_ambience();
+
+        for entity in self.scene.simulation.take_despawned() {
+            self.scene.decals.detach(entity);
+            if let Some(audio) = &mut self.audio {
+                audio.forget_emitter(entity);
+            }
+        }
+
+        self.scene.events.update();
+    }
+
+    /// Spawns imported models and replaces the scene once what they need has loaded
+    fn finish_loading(&mut self) {
+        self.pending_imports
+            .retain(|(load, path)| match self.loader.state(*load) {
+                Some(LoadState::Loading) => true,
+                Some(LoadState::Loaded) => {
+                    if let Err(error) = self.scene.import_model(path) {
+                        warn!("Could not import {:?}: {}", path, error);
+                    }
+                    false
+                }
+                _ => false,
+            });
+
+        let Some((_, loads)) = &self.pending_load else {
+            return;
+        };
+        if loads
+            .iter()
+            .any(|&load| self.loader.state(load) == Some(&LoadState::Loading))
+        {
+            return;
+        }
+
+        let (pending, loads) = self.pending_load.take().unwrap();
+        if loads
+            .iter()
+            .any(|&load| matches!(self.loader.state(load), Some(LoadState::Failed(_))))
+        {
+            warn!("Keeping the current scene, as not every model it needs could be loaded");
+            // A save waiting on the scene would be applied to the next one opened
+            if self.pending_save.take().is_some() {
+                warn!("Not loading the save, as its scene couldn't be loaded");
+            }
+            return;
+        }
+
+        let inner_size = self.opengl_context.window.inner_size();
+        let mut camera = self.scene.camera.clone();
+        camera.set_aspect_ratio(inner_size.width as f32 / inner_size.height as f32);
+
+        // Every model is in the scene's assets by now, so only the skybox is loaded here. They're
+        // only taken from the current scene if the new one is built.
+        let assets = &mut self.scene.assets;
+        let scene = match &pending {
+            PendingLoad::Scene(scene_path) => Scene::load(scene_path, assets, inner_size),
+            PendingLoad::Map(map) => Scene::from_map(map, assets, inner_size),
+            PendingLoad::Level(level) => level.build_scene(GENERATED_LEVEL_TITLE, camera, assets),
+        };
+        let scene = match scene {
+            Ok(scene) => scene,
+            Err(error) => {
+                warn!(
+                    "Keeping the current scene, as the new one couldn't be built: {}",
+                    error
+                );
+                if self.pending_save.take().is_some() {
+                    warn!("Not loading the save, as its scene couldn't be loaded");
+                }
+                return;
+            }
+        };
+
+        // Streamed entities go with the scene being replaced, as does its history
+        self.streamer = None;
+        self.replay_buffer.clear();
+        self.killcam = None;
+
+        self.scene = scene;
+        if let PendingLoad::Scene(scene_path) = pending {
+            self.scene_path = Some(scene_path);
+        }
+
+        self.scripts.load(&self.scene.scripts);
+
+        if let Some(save) = self.pending_save.take() {
+            self.apply_save(save);
+        }
+    }
+
+    fn render(&mut self) {
+        let window_size = self.opengl_context.window.inner_size();
+        if window_size.width == 0 || window_size.height == 0 {
+            return;
+        }
+
+        let _scope = profiling::scope("Render");
+
+        let post_processing = self.graphics_settings.post_processing();
+        let mut render_graph = std::mem::take(&mut self.render_graph);
+        render_graph.set_enabled(WORLD_PASS, post_processing);
+        render_graph.set_enabled(POST_PROCESSING_PASS, post_processing);
+        render_graph.set_enabled(WORLD_TO_TARGET_PASS, !post_processing);
+
+        let display = self.opengl_context.display.clone();
+        match self.opengl_context.begin_frame() {
+            Ok(mut frame) => {
+                if let Err(error) = render_graph.execute(&display, &mut frame, self) {
+                    warn!("Failed to render frame: {}", error);
+                }
+                if let Err(error) = self.opengl_context.finish_frame(frame) {
+                    warn!("Failed to show frame: {}", error);
+                }
+            }
+            Err(error) => warn!("Failed to start frame: {}", error),
+        }
+
+        self.render_graph = render_graph;
+    }
+
+    /// The world drawn into an HDR texture and post-processed onto the window, or straight onto
+    /// the window, with the HUD and GUI over it
+    fn render_graph() -> RenderGraph<Self> {
+        let mut graph = RenderGraph::new();
+        graph.add_texture(
+            "hdr",
+            TextureDesc::color(UncompressedFloatFormat::F16F16F16F16),
+        );
+        graph.add_texture("depth", TextureDesc::depth());
+
+        graph.add_pass(WORLD_PASS, &[], &["hdr", "depth"], |pass, editor| {
+            let mut framebuffer = pass.framebuffer()?;
+            editor.render_world(&mut framebuffer);
+            Ok(())
+        });
+
+        graph.add_pass(POST_PROCESSING_PASS, &["hdr"], &[TARGET], |pass, editor| {
+            editor.scene.gpu_timer.begin("Post-processing");
+            editor.scene.draw_calls += editor.post_processor.render(
+                pass.display,
+                pass.target,
+                pass.texture("hdr")?,
+                &editor.scene.assets,
+                &editor.graphics_settings,
+            )?;
+            editor.scene.gpu_timer.end();
+            Ok(())
+        });
+
+        graph.add_pass(WORLD_TO_TARGET_PASS, &[], &[TARGET], |pass, editor| {
+            editor.render_world(pass.target);
+            Ok(())
+        });
+
+        graph.add_pass("HUD", &[], &[TARGET], |pass, editor| {
+            editor.scene.gpu_timer.begin("HUD");
+            editor
+                .hud
+                .render(pass.display, pass.target, &editor.scene.assets)?;
+            editor.scene.gpu_timer.end();
+            Ok(())
+        });
+
+        graph.add_pass("GUI", &[], &[TARGET], |pass, editor| {
+            let _scope = profiling::scope("GUI");
+            editor.render_gui();
+
+            editor.scene.gpu_timer.begin("GUI");
+            editor.gui.paint(pass.display, pass.target);
+            editor.scene.gpu_timer.end();
+            Ok(())
+        });
+
+        // Reads the finished frame, so runs after everything drawn onto it
+        graph.add_pass("Capture", &[TARGET], &[], |pass, editor| {
+            if let Err(error) = editor.capture.capture(pass.display, pass.target) {
+                warn!("Failed to capture frame: {}", error);
+            }
+            Ok(())
+        });
+
+        graph
+    }
+
+    /// Draws the world from the camera shaken by camera effects, leaving the camera itself alone
+    fn render_world<S: Surface>(&mut self, target: &mut S) {
+        let shaken = self.camera_effects.shaken(&self.scene.camera);
+        let camera = std::mem::replace(&mut self.scene.camera, shaken);
+        self.draw_world(target);
+        self.scene.camera = camera;
+    }
+
+    /// Everything drawn in the world rather than over it, so it can be post-processed
+    fn draw_world<S: Surface>(&mut self, target: &mut S) {
+        let scene = &mut self.scene;
+        let context = &mut self.opengl_context;
+        if !self.split_screen.is_empty() {
+            let count = self.split_screen.len() + 1;
+            let views = std::iter::once(&scene.camera)
+                .chain(self.split_screen.iter())
+                .enumerate()
+                .map(|(index, camera)| (camera.clone(), Viewport::split(count, index)))
+                .collect_vec();
+            // Portals and weather are drawn over the whole window from the scene's camera, so
+            // they're left out rather than drawn wrongly in every view
+            scene.render_views(context, target, &views);
+            return;
+        }
+
+        scene.render(context, target);
+
+        scene.gpu_timer.begin("Portals");
+        self.portal_renderer.render(scene, context, target).unwrap();
+        scene.gpu_timer.end();
+
+        scene.gpu_timer.begin("Weather");
+        self.weather
+            .render(&context.display, target, &scene.camera)
+            .unwrap();
+        scene.gpu_timer.end();
+    }
+
+    fn render_gui(&mut self) {
+        let mut display_changed = false;
+        let mut console_submitted = false;
+
+        self.gui.run(&self.opengl_context.window, |ctx| {
+            if self.console.open {
+                console_submitted = Self::render_console(ctx, &mut self.console);
+            }
+
+            if let Some((pending, _)) = &self.pending_load {
+                Self::render_loading_screen(ctx, pending, self.loader.progress());
+            }
+
+            if self.state.show_debug_overlay {
+                Self::render_debug_overlay(ctx, &self.state, &self.scene);
+            }
+
+            if self.state.show_log {
+                Self::render_log(ctx, &mut self.state);
+            }
+
+            if self.state.show_editor_panel {
+                Self::render_editor_panel(
+                    ctx,
+                    &mut self.state,
+                    &mut self.scene,
+                    self.scene_path.as_deref(),
+                    &self.sender,
+                );
+            }
+
+            egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+                egui::menu::bar(ui, |ui| {
+                    ui.with_layout(egui::Layout::left_to_right(Align::Center), |ui| {
+                        ui.menu_button("File", |ui| {
+                            if ui.add(Button::new("Open scene")).clicked() {
+                                let sender = self.sender.clone();
+
+                                std::thread::spawn(move || {
+                                    if let Some(file) = FileDialog::new()
+                                        .add_filter("json", &["json"])
+                                        .set_can_create_directories(true)
+                                        .set_directory("/")
+                                        .pick_file()
+                                    {
+                                        sender.send(EngineEvent::LoadScene(file)).unwrap();
+                                    }
+                                });
+
+                                ui.close_menu();
+                            }
+
+                            if ui.add(Button::new("Save")).clicked() {
+                                Self::save_scene(
+                                    &self.scene,
+                                    self.scene_path.as_deref(),
+                                    &self.sender,
+                                );
+
+                                ui.close_menu();
+                            }
+
+                            if ui.add(Button::new("Save as")).clicked() {
+                                Self::pick_save_path(self.sender.clone());
+                                ui.close_menu();
+                            }
+
+                            if ui.add(Button::new("Open map")).clicked() {
+                                let sender = self.sender.clone();
+
+                                std::thread::spawn(move || {
+                                    if let Some(file) = FileDialog::new()
+                                        .add_filter("map", &["map"])
+                                        .set_directory("/")
+                                        .pick_file()
+                                    {
+                                        sender.send(EngineEvent::LoadMap(file)).unwrap();
+                                    }
+                                });
+
+                                ui.close_menu();
+                            }
+
+                            if ui.add(Button::new("Open streamed world")).clicked() {
+                                let sender = self.sender.clone();
+
+                                std::thread::spawn(move || {
+                                    if let Some(file) = FileDialog::new()
+                                        .add_filter("json", &["json"])
+                                        .set_directory("/")
+                                        .pick_file()
+                                    {
+                                        sender.send(EngineEvent::OpenStreamedWorld(file)).unwrap();
+                                    }
+                                });
+
+                                ui.close_menu();
+                            }
+
+                            if ui.add(Button::new("Export streamed world")).clicked() {
+                                let sender = self.sender.clone();
+
+                                std::thread::spawn(move || {
+                                    if let Some(directory) = FileDialog::new()
+                                        .set_can_create_directories(true)
+                                        .pick_folder()
+                                    {
+                                        sender
+                                            .send(EngineEvent::ExportStreamedWorld(directory))
+                                            .unwrap();
+                                    }
+                                });
+
+                                ui.close_menu();
+                            }
+
+                            if ui.add(Button::new("Export map")).clicked() {
+                                match Map::from_scene(&self.scene, true) {
+                                    Ok(map) => {
+                                        std::thread::spawn(move || {
+                                            if let Some(save_path) = FileDialog::new()
+                                                .add_filter("map", &["map"])
+                                                .save_file()
+                                            {
+                                                if let Err(error) = map.save(&save_path) {
+                                                    warn!(
+                                                        "Could not save map {:?}: {}",
+                                                        save_path, error
+                                                    );
+                                                }
+                                            }
+                                        });
+                                    }
+                                    Err(error) => warn!("Could not export the map: {}", error),
+                                }
+
+                                ui.close_menu();
+                            }
+                        });
+
+                        ui.menu_button("Scene", |ui| {
+                            if ui.add(Button::new("Import models")).clicked() {
+                                let sender = self.sender.clone();
+
+                                std::thread::spawn(move || {
+                                    if let Some(paths) = FileDialog::new()
+                                        .add_filter("model", &Model::extensions())
+                                        .set_can_create_directories(true)
+                                        .set_directory("/")
+                                        .pick_files()
+                                    {
+                                        for path in paths {
+                                            sender.send(EngineEvent::ImportModel(path)).unwrap();
+                                        }
+                                    }
+                                });
+
+                                ui.close_menu();
+                            }
+
+                            if ui.add(Button::new("Load skybox")).clicked() {
+                                let sender = self.sender.clone();
+
+                                std::thread::spawn(move || {
+                                    if let Some(file) = FileDialog::new()
+                                        .add_filter("image", &["hdr", "png", "jpg", "jpeg"])
+                                        .set_directory("/")
+                                        .pick_file()
+                                    {
+                                        sender.send(EngineEvent::LoadSkybox(file)).unwrap();
+                                    }
+                                });
+
+                                ui.close_menu();
+                            }
+
+                            if ui
+                                .add_enabled(
+                                    self.scene.skybox.is_some(),
+                                    Button::new("Clear skybox"),
+                                )
+                                .clicked()
+                            {
+                                self.scene.skybox = None;
+                                ui.close_menu();
+                            }
+
+                            if ui.add(Button::new("Add script")).clicked() {
+                                let sender = self.sender.clone();
+
+                                std::thread::spawn(move || {
+                                    if let Some(file) = FileDialog::new()
+                                        .add_filter("script", &["rhai"])
+                                        .set_directory(SCRIPT_DIRECTORY)
+                                        .pick_file()
+                                    {
+                                        sender.send(EngineEvent::AddScript(file)).unwrap();
+                                    }
+                                });
+
+                                ui.close_menu();
+                            }
+
+                            if ui.add(Button::new("Generate terrain")).clicked() {
+                                let terrain = Terrain::new(
+                                    Heightmap::generate(TERRAIN_RESOLUTION, fastrand::u64(..)),
+                                    TERRAIN_SIZE,
+                                    TERRAIN_HEIGHT,
+                                );
+                                self.scene.set_terrain(Some(terrain));
+                                ui.close_menu();
+                            }
+
+                            if ui.add(Button::new("Load heightmap")).clicked() {
+                                let sender = self.sender.clone();
+
+                                std::thread::spawn(move || {
+                                    if let Some(file) = FileDialog::new()
+                                        .add_filter("image", &["png", "jpg", "jpeg"])
+                                        .set_directory("/")
+                                        .pick_file()
+                                    {
+                                        sender.send(EngineEvent::LoadHeightmap(file)).unwrap();
+                                    }
+                                });
+
+                                ui.close_menu();
+                            }
+
+                            if ui
+                                .add_enabled(
+                                    self.scene.terrain.is_some(),
+                                    Button::new("Clear terrain"),
+                                )
+                                .clicked()
+                            {
+                                self.scene.set_terrain(None);
+                                ui.close_menu();
+                            }
+                        });
+
+                        ui.menu_button("Run", |ui| {
+                            if ui.add(Button::new("Run game")).clicked() {
+                                std::process::Command::new("cargo")
+                                    .arg("run")
+                                    .arg("--package")
+                                    .arg("shooter-game")
+                                    .arg("--bin")
+                                    .arg("game")
+                                    .spawn()
+                                    .unwrap();
+
+                                ui.close_menu();
+                            }
+                        });
+                    });
+                });
+            });
+
+            egui::SidePanel::left("my_side_panel").show(ctx, |ui| {
+                if ui
+                    .button(format!("Camera: {:?}", self.scene.camera.view_mode))
+                    .clicked()
+                {
+                    self.scene.camera.toggle_view_mode();
+                }
+
+                ui.add(
+                    egui::Slider::new(&mut self.time_of_day.hours, 0.0..=24.0).text("Time of day"),
+                );
+                ui.checkbox(&mut self.time_of_day.paused, "Pause day cycle");
+
+                ui.horizontal(|ui| {
+                    let label = if self.time.is_paused() {
+                        "Resume"
+                    } else {
+                        "Pause"
+                    };
+                    if ui.button(label).clicked() {
+                        self.time.toggle_pause();
+                    }
+                    if ui.button("Step").clicked() {
+                        self.time.step_once();
+                    }
+                });
+                ui.add(egui::Slider::new(&mut self.time.time_scale, 0.0..=2.0).text("Time scale"));
+
+                let mut weather_state = self.weather.state;
+                egui::ComboBox::from_label("Weather")
+                    .selected_text(format!("{:?}", weather_state))
+                    .show_ui(ui, |ui| {
+                        for state in [
+                            WeatherState::Clear,
+                            WeatherState::Rain,
+                            WeatherState::Snow,
+                            WeatherState::Fog,
+                            WeatherState::Storm,
+                        ] {
+                            ui.selectable_value(&mut weather_state, state, format!("{:?}", state));
+                        }
+                    });
+
+                if weather_state != self.weather.state {
+                    self.weather.set(weather_state);
+                }
+
+                if let Some(skybox) = self.scene.skybox.as_mut() {
+                    ui.add(
+                        egui::Slider::new(&mut skybox.intensity, 0.0..=4.0)
+                            .text("Skybox intensity"),
+                    );
+                    ui.checkbox(
+                        &mut skybox.image_based_lighting,
+                        "Light PBR materials with the skybox",
+                    );
+                }
+
+                ui.separator();
+
+                egui::ComboBox::from_label("Renderer")
+                    .selected_text(format!("{:?}", self.scene.render_mode))
+                    .show_ui(ui, |ui| {
+                        for render_mode in [RenderMode::Forward, RenderMode::Deferred] {
+                            ui.selectable_value(
+                                &mut self.scene.render_mode,
+                                render_mode,
+                                format!("{:?}", render_mode),
+                            );
+                        }
+                    });
+
+                egui::ComboBox::from_label("Debug view")
+                    .selected_text(format!("{:?}", self.scene.debug_view))
+                    .show_ui(ui, |ui| {
+                        for debug_view in DebugView::ALL {
+                            ui.selectable_value(
+                                &mut self.scene.debug_view,
+                                debug_view,
+                                format!("{:?}", debug_view),
+                            );
+                        }
+                    });
+
+                ui.add_enabled_ui(self.scene.render_mode == RenderMode::Forward, |ui| {
+                    egui::ComboBox::from_label("Shading")
+                        .selected_text(format!("{:?}", self.scene.shading_model))
+                        .show_ui(ui, |ui| {
+                            for shading_model in [ShadingModel::BlinnPhong, ShadingModel::Pbr] {
+                                ui.selectable_value(
+                                    &mut self.scene.shading_model,
+                                    shading_model,
+                                    format!("{:?}", shading_model),
+                                );
+                            }
+                        });
+                });
+
+                ui.collapsing("Graphics", |ui| {
+                    let settings = &mut self.graphics_settings;
+
+                    egui::ComboBox::from_label("MSAA")
+                        .selected_text(Self::msaa_label(settings.msaa_samples))
+                        .show_ui(ui, |ui| {
+                            for samples in GraphicsSettings::MSAA_SAMPLES {
+                                ui.selectable_value(
+                                    &mut settings.msaa_samples,
+                                    samples,
+                                    Self::msaa_label(samples),
+                                );
+                            }
+                        });
+                    egui::ComboBox::from_label("Anisotropic filtering")
+                        .selected_text(format!("{}x", settings.anisotropy))
+                        .show_ui(ui, |ui| {
+                            for anisotropy in GraphicsSettings::ANISOTROPY_LEVELS {
+                                ui.selectable_value(
+                                    &mut settings.anisotropy,
+                                    anisotropy,
+                                    format!("{}x", anisotropy),
+                                );
+                            }
+                        });
+                    egui::ComboBox::from_label("Reflection resolution")
+                        .selected_text(settings.reflection_resolution.to_string())
+                        .show_ui(ui, |ui| {
+                            for resolution in GraphicsSettings::REFLECTION_RESOLUTIONS {
+                                ui.selectable_value(
+                                    &mut settings.reflection_resolution,
+                                    resolution,
+                                    resolution.to_string(),
+                                );
+                            }
+                        });
+                    ui.checkbox(&mut settings.vsync, "Vsync");
+
+                    ui.checkbox(&mut settings.bloom, "Bloom");
+                    egui::ComboBox::from_label("Tonemapping")
+                        .selected_text(format!("{:?}", settings.tonemapping))
+                        .show_ui(ui, |ui| {
+                            for tonemapping in Tonemapping::ALL {
+                                ui.selectable_value(
+                                    &mut settings.tonemapping,
+                                    tonemapping,
+                                    format!("{:?}", tonemapping),
+                                );
+                            }
+                        });
+                    ui.checkbox(&mut settings.fxaa, "FXAA");
+                    ui.label("Vsync and raising MSAA apply after a restart");
+
+                    if ui.button("Save graphics settings").clicked() {
+                        if let Err(error) = settings.save(&self.config.graphics_settings) {
+                            warn!("Could not save graphics settings: {}", error);
+                        }
+                    }
+                });
+
+                ui.collapsing("Display", |ui| {
+                    let config = &mut self.config;
+                    let old = (
+                        config.display_mode,
+                        config.monitor.clone(),
+                        config.resolution,
+                    );
+                    let monitors = self.opengl_context.monitors();
+
+                    egui::ComboBox::from_label("Mode")
+                        .selected_text(format!("{:?}", config.display_mode))
+                        .show_ui(ui, |ui| {
+                            for mode in DisplayMode::ALL {
+                                ui.selectable_value(
+                                    &mut config.display_mode,
+                                    mode,
+                                    format!("{:?}", mode),
+                                );
+                            }
+                        });
+                    egui::ComboBox::from_label("Monitor")
+                        .selected_text(config.monitor.as_deref().unwrap_or("Primary"))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut config.monitor, None, "Primary");
+                            for name in monitors.iter().filter_map(|monitor| monitor.name()) {
+                                let label = name.clone();
+                                ui.selectable_value(&mut config.monitor, Some(name), label);
+                            }
+                        });
+
+                    let monitor = config
+                        .monitor
+                        .as_ref()
+                        .and_then(|name| {
+                            monitors
+                                .iter()
+                                .find(|monitor| monitor.name().as_ref() == Some(name))
+                        })
+                        .or(monitors.first());
+                    let resolution_label = |resolution: Option<(u32, u32)>| {
+                        resolution.map_or("Largest".to_owned(), |(width, height)| {
+                            format!("{}x{}", width, height)
+                        })
+                    };
+                    ui.add_enabled_ui(config.display_mode == DisplayMode::Exclusive, |ui| {
+                        egui::ComboBox::from_label("Resolution")
+                            .selected_text(resolution_label(config.resolution))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut config.resolution, None, "Largest");
+                                for resolution in
+                                    monitor.map_or_else(Vec::new, context::resolutions)
+                                {
+                                    ui.selectable_value(
+                                        &mut config.resolution,
+                                        Some(resolution),
+                                        resolution_label(Some(resolution)),
+                                    );
+                                }
+                            });
+                    });
+                    ui.label("Alt+Enter toggles fullscreen");
+
+                    if (
+                        config.display_mode,
+                        config.monitor.clone(),
+                        config.resolution,
+                    ) != old
+                    {
+                        display_changed = true;
+                    }
+                });
+
+                if let Some(audio) = &mut self.audio {
+                    ui.collapsing("Audio", |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut audio.master_volume, 0.0..=1.0).text("Master"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut audio.effects_volume, 0.0..=1.0).text("Effects"),
+                        );
+                        ui.add(egui::Slider::new(&mut audio.music_volume, 0.0..=1.0).text("Music"));
+
+                        ui.horizontal(|ui| {
+                            if ui.button("Play music").clicked() {
+                                let sender = self.sender.clone();
+
+                                std::thread::spawn(move || {
+                                    if let Some(file) = FileDialog::new()
+                                        .add_filter("audio", &["ogg", "mp3", "wav", "flac"])
+                                        .set_directory("/")
+                                        .pick_file()
+                                    {
+                                        sender.send(EngineEvent::PlayMusic(file)).unwrap();
+                                    }
+                                });
+                            }
+                            if ui.button("Stop music").clicked() {
+                                audio.stop_music();
+                            }
+                        });
+
+                        ui.label(format!("Sounds playing: {}", audio.playing_count()));
+                    });
+                }
+
+                ui.collapsing("Multiplayer", |ui| match &self.client {
+                    Some(client) => {
+                        ui.label(match client.player() {
+                            Some(player) => format!("Playing on {} as {}", client.server(), player),
+                            None => format!("Connecting to {}...", client.server()),
+                        });
+
+                        if ui.button("Disconnect").clicked() {
+                            self.client.take().unwrap().disconnect(&mut self.scene);
+                        }
+                    }
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.label("Server");
+                            ui.text_edit_singleline(&mut self.server_address);
+                        });
+
+                        if ui.button("Connect").clicked() {
+                            match Client::connect(self.server_address.as_str()) {
+                                Ok(client) => self.client = Some(client),
+                                Err(error) => {
+                                    warn!("Could not connect to {}: {}", self.server_address, error)
+                                }
+                            }
+                        }
+                    }
+                });
+
+                ui.checkbox(&mut self.state.show_gizmos, "Gizmos");
+
+                let mut players = self.split_screen.len() + 1;
+                if ui
+                    .add(egui::Slider::new(&mut players, 1..=4).text("Local players"))
+                    .changed()
+                {
+                    // New players start where the first one is
+                    self.split_screen
+                        .resize(players - 1, self.scene.camera.clone());
+                }
+                ui.checkbox(&mut self.scene.frustum_culling, "Frustum culling");
+                ui.checkbox(&mut self.scene.occlusion_culling, "Occlusion culling");
+                ui.add_enabled(
+                    self.scene.occlusion_culling,
+                    egui::Checkbox::new(&mut self.scene.occlusion.visualize, "Show occluded"),
+                );
+                ui.checkbox(&mut self.scene.level_of_detail, "Level of detail");
+                ui.checkbox(&mut self.scene.reflection_probes, "Reflection probes");
+                ui.checkbox(&mut self.scene.planar_reflections, "Planar reflections");
+                ui.add(egui::Slider::new(&mut self.scene.lod_bias, 0.25..=4.0).text("LOD bias"));
+                ui.label(format!(
+                    "Instances drawn: {}, culled: {}, occluded: {}",
+                    self.scene.culling_statistics.drawn,
+                    self.scene.culling_statistics.culled,
+                    self.scene.culling_statistics.occluded
+                ));
+
+                match self.state.picked {
+                    Some(hit) => ui.label(format!("Picked: {} at {:.2}", hit.entity, hit.distance)),
+                    None => ui.label("Click a model to pick it"),
+                };
+
+                let picked_model = self
+                    .state
+                    .picked
+                    .and_then(|hit| self.scene.simulation.world.get::<ModelInstance>(hit.entity));
+                if let Some(model_instance) = picked_model {
+                    let model = &model_instance.model;
+                    ui.collapsing(
+                        format!("{} nodes, {} meshes", model.nodes.len(), model.meshes.len()),
+                        |ui| {
+                            for node in model.nodes.iter() {
+                                ui.label(node.name.as_deref().unwrap_or("Unnamed node"));
+                            }
+                        },
+                    );
+                }
+
+                if let Some(hit) = self.state.picked {
+                    ui.horizontal(|ui| {
+                        // A small cube resting on top of the picked entity, which moves with it
+                        if ui.button("Attach cube").clicked() {
+                            let cube = self.scene.load_model(Path::new(PHYSICS_CUBE_PATH)).unwrap();
+                            let translation = self
+                                .scene
+                                .simulation
+                                .global_matrix(hit.entity)
+                                .map_or(Vector3::zero(), |matrix| matrix.w.truncate());
+                            let entity = self.scene.spawn_model(
+                                cube,
+                                Transform {
+                                    translation: translation + Vector3::unit_y(),
+                                    scale: Vector3::new(0.25, 0.25, 0.25),
+                                    ..Transform::default()
+                                },
+                            );
+                            self.scene.simulation.attach(entity, hit.entity, None);
+                        }
+
+                        let has_parent = self.scene.simulation.world.has::<Parent>(hit.entity);
+                        if ui.add_enabled(has_parent, Button::new("Detach")).clicked() {
+                            self.scene.simulation.detach(hit.entity);
+                        }
+                    });
+                }
+
+                ui.label(format!(
+                    "Lights: {}",
+                    self.scene.simulation.query::<Light>().count()
+                ));
+                ui.horizontal(|ui| {
+                    let color: Srgb = Hsv::new(fastrand::f32() * 360.0, 0.8, 1.0).into_color();
+
+                    // Placed at the camera, facing the same way
+                    let transform = Transform {
+                        translation: self.scene.camera.position.to_vec(),
+                        rotation: Quaternion::from_arc(
+                            -Vector3::unit_z(),
+                            self.scene.camera.forward_direction,
+                            None,
+                        ),
+                        ..Transform::default()
+                    };
+
+                    if ui.button("Add point light").clicked() {
+                        self.scene
+                            .simulation
+                            .spawn_light(Light::point(color, 20.0, 10.0), transform.clone());
+                    }
+                    if ui.button("Add spot light").clicked() {
+                        self.scene.simulation.spawn_light(
+                            Light::spot(color, 40.0, 20.0, Deg(15.0), Deg(25.0)),
+                            transform,
+                        );
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let position = self.scene.camera.position;
+
+                    if ui.button("Add reflection probe").clicked() {
+                        let entity = self.scene.simulation.spawn();
+                        let world = &mut self.scene.simulation.world;
+                        world.insert(
+                            entity,
+                            ReflectionProbe::new(
+                                Vector3::new(1.0, 1.0, 1.0) * REFLECTION_PROBE_EXTENTS,
+                            ),
+                        );
+                        world.insert(
+                            entity,
+                            Transform {
+                                translation: position.to_vec(),
+                                ..Transform::default()
+                            },
+                        );
+                    }
+                    // On the floor under the camera, facing up
+                    if ui.button("Add planar reflection").clicked() {
+                        let entity = self.scene.simulation.spawn();
+                        let world = &mut self.scene.simulation.world;
+                        world.insert(entity, PlanarReflection::default());
+                        world.insert(
+                            entity,
+                            Transform {
+                                translation: Vector3::new(
+                                    position.x,
+                                    position.y - EYE_HEIGHT,
+                                    position.z,
+                                ),
+                                ..Transform::default()
+                            },
+                        );
+                    }
+                    if ui.button("Bake reflections").clicked() {
+                        self.scene.bake_reflection_probes();
+                    }
+                });
+                egui::ComboBox::from_label("Weapon")
+                    .selected_text(self.weapons.weapon.name.clone())
+                    .show_ui(ui, |ui| {
+                        for weapon in [Weapon::rifle(), Weapon::shotgun(), Weapon::launcher()] {
+                            let selected = weapon.name == self.weapons.weapon.name;
+                            if ui.selectable_label(selected, &weapon.name).clicked() {
+                                self.weapons.equip(weapon);
+                            }
+                        }
+                    });
+
+                if ui.button("Drop cube").clicked() {
+                    self.spawn_cube();
+                }
+
+                if ui.button("Spawn enemy").clicked() {
+                    self.spawn_enemy();
+                }
+
+                if ui.button("Spawn health pickup").clicked() {
+                    self.spawn_health_pickup();
+                }
+
+                if ui.button("Add spawn point").clicked() {
+                    // On the floor under the camera, which is where spawn points are kept
+                    let position = self.scene.camera.position;
+                    self.scene.simulation.spawn_points.push(Point3::new(
+                        position.x,
+                        position.y - EYE_HEIGHT,
+                        position.z,
+                    ));
+                }
+
+                if ui.button("Clear lights").clicked() {
+                    let lights = self
+                        .scene
+                        .simulation
+                        .query::<Light>()
+                        .map(|(entity, _)| entity)
+                        .collect::<Vec<_>>();
+                    for entity in lights {
+                        self.scene.simulation.despawn(entity);
+                    }
+                }
+            });
+
+            self.state.pointer_over_gui = ctx.is_pointer_over_area();
+        });
+
+        if display_changed {
+            self.apply_display_mode();
+        }
+        if console_submitted {
+            self.submit_console();
+        }
+    }
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
     }
 }
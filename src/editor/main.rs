@@ -1,16 +1,94 @@
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use clap::Parser;
+use color_eyre::eyre::WrapErr;
+use color_eyre::Result;
+use log::error;
 use winit::event_loop::EventLoop;
 
 use common::app::Application;
+use common::config::{AppConfig, ConfigArgs};
+use common::crash;
+use common::debug;
+use common::map::Map;
+use common::profiling;
+use common::simulation::Simulation;
+use common::timestep::FixedTimestep;
 use editor::Editor;
 
 mod editor;
 
+/// Seconds simulated by `--headless` when `--seconds` isn't given
+const HEADLESS_SECONDS: f64 = 10.0;
+
+/// Level editor, also able to step maps without a window
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// Steps the simulation of a map as fast as possible without opening a window, e.g. to check
+    /// a map in CI
+    #[arg(long)]
+    headless: bool,
+    /// Map to simulate with `--headless`, an empty one if left out
+    #[arg(long, value_name = "PATH", requires = "headless")]
+    map: Option<PathBuf>,
+    /// Seconds of simulated time to run for with `--headless`
+    #[arg(long, default_value_t = HEADLESS_SECONDS, requires = "headless")]
+    seconds: f64,
+}
+
 fn main() {
+    let cli = Cli::parse();
+
+    color_eyre::install().unwrap();
+    crash::install();
+    debug::set_up_logging();
+
+    if cli.headless {
+        if let Err(error) = run_headless(cli.map.as_deref(), cli.seconds) {
+            error!("Headless run failed: {}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // Winit is dodgey on Wayland, prefer to use Xwayland
     std::env::set_var("WINIT_UNIX_BACKEND", "x11");
 
+    let config = AppConfig::from_args(&cli.config);
+    debug::configure_logging(&config.log);
     let event_loop = EventLoop::new().expect("Failed to create event loop");
 
-    let editor = Editor::new(&event_loop);
+    let editor = Editor::new(config, cli.config.path().to_owned(), &event_loop);
     editor.run(event_loop);
 }
+
+fn run_headless(map: Option<&Path>, seconds: f64) -> Result<()> {
+    let mut simulation = match map {
+        Some(path) => Simulation::from_map(
+            &Map::load(path).wrap_err_with(|| format!("Could not load {}", path.display()))?,
+        ),
+        None => Simulation::new(),
+    };
+
+    let step = FixedTimestep::default().step;
+    let start = Instant::now();
+
+    while simulation.time < seconds {
+        simulation.step(step);
+        // Nothing outside the simulation keeps anything for despawned entities here
+        simulation.take_despawned();
+        profiling::end_frame(&[]);
+    }
+
+    println!(
+        "Simulated {:.1}s with {} entities in {:.2?}",
+        simulation.time,
+        simulation.world.len(),
+        start.elapsed()
+    );
+
+    Ok(())
+}
@@ -1,3 +1,153 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use clap::Parser;
+use color_eyre::Result;
+use log::{error, info, warn};
+use winit::event::{Event, WindowEvent};
+use winit::event_loop::EventLoop;
+use winit::window::Window;
+
+use common::assets::Assets;
+use common::backend::{Backend, BackendKind, DrawScene};
+use common::camera::Camera;
+use common::config::{AppConfig, ConfigArgs};
+use common::context::OpenGLContext;
+use common::crash;
+use common::debug;
+use common::profiling;
+use common::scene::Scene;
+use common::settings::GraphicsSettings;
+use common::timestep::FixedTimestep;
+use common::wgpu_backend::WgpuContext;
+
+/// Plays a scene with the backend picked by `--backend`
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+}
+
 fn main() {
-    println!("Hello, world!");
+    let cli = Cli::parse();
+
+    color_eyre::install().unwrap();
+    crash::install();
+    debug::set_up_logging();
+
+    // Winit is dodgey on Wayland, prefer to use Xwayland
+    std::env::set_var("WINIT_UNIX_BACKEND", "x11");
+
+    let config = AppConfig::from_args(&cli.config);
+    debug::configure_logging(&config.log);
+    let settings = GraphicsSettings::load(&config.graphics_settings).unwrap_or_else(|error| {
+        warn!(
+            "Using default graphics settings, could not load {:?}: {}",
+            config.graphics_settings, error
+        );
+        GraphicsSettings::default()
+    });
+    let event_loop = EventLoop::new().expect("Failed to create event loop");
+
+    let result = match config.backend {
+        BackendKind::OpenGl => {
+            let context = OpenGLContext::new(&config, &settings, &event_loop);
+            let window = context.window.clone();
+            run(context, window, &config, settings, event_loop)
+        }
+        BackendKind::Wgpu => match WgpuContext::new(&config, &settings, &event_loop) {
+            Ok(context) => {
+                let window = context.window.clone();
+                run(context, window, &config, settings, event_loop)
+            }
+            Err(error) => {
+                error!("Could not draw with wgpu: {}", error);
+                std::process::exit(1);
+            }
+        },
+    };
+
+    if let Err(error) = result {
+        error!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Steps the scene's simulation at a fixed rate and draws it through `backend` until the window
+/// is closed
+fn run<B>(
+    mut backend: B,
+    window: Arc<Window>,
+    config: &AppConfig,
+    settings: GraphicsSettings,
+    event_loop: EventLoop<()>,
+) -> Result<()>
+where
+    B: Backend + DrawScene<<B as Backend>::Frame>,
+{
+    info!("Drawing with {}", backend.description());
+
+    let mut assets = Assets::new();
+    if let Err(error) = assets.load_names(&config.asset_names) {
+        warn!(
+            "Could not load asset names from {:?}: {}",
+            config.asset_names, error
+        );
+    }
+
+    let inner_size = window.inner_size();
+    let mut scene = match &config.scene {
+        Some(path) => Scene::load(path, &mut assets, inner_size)?,
+        None => {
+            let mut scene = Scene::new("Untitled", Camera::default(), &mut assets);
+            let teapot = scene.load_model(Path::new("assets/models/teapot.glb"))?;
+            scene.spawn_model(teapot, Default::default());
+            scene
+        }
+    };
+    scene
+        .camera
+        .set_aspect_ratio(inner_size.width as f32 / inner_size.height as f32);
+    scene.graphics_settings = settings;
+
+    let mut timestep = FixedTimestep::default();
+
+    event_loop.run(move |event, target| match event {
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::CloseRequested => target.exit(),
+            WindowEvent::Resized(size) => {
+                backend.resize(size);
+                if size.height > 0 {
+                    scene
+                        .camera
+                        .set_aspect_ratio(size.width as f32 / size.height as f32);
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                for _ in 0..timestep.advance() {
+                    scene.simulation.step(timestep.step);
+                    for entity in scene.simulation.take_despawned() {
+                        scene.decals.detach(entity);
+                    }
+                }
+                scene.interpolation = timestep.alpha();
+
+                match backend.begin_frame() {
+                    Ok(mut frame) => {
+                        scene.render(&mut backend, &mut frame);
+                        if let Err(error) = backend.finish_frame(frame) {
+                            warn!("Failed to show frame: {}", error);
+                        }
+                    }
+                    Err(error) => warn!("Failed to start frame: {}", error),
+                }
+                profiling::end_frame(&[]);
+            }
+            _ => {}
+        },
+        Event::AboutToWait => window.request_redraw(),
+        _ => {}
+    })?;
+
+    Ok(())
 }
@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use cgmath::Vector3;
+use common::crash;
+use common::debug;
+use common::map::Map;
+use common::model::Transform;
+use common::net::{Server, DEFAULT_PORT, TICK_RATE};
+use common::physics::{Collider, ColliderShape};
+use common::profiling;
+use common::simulation::Simulation;
+use common::timestep::FixedTimestep;
+use log::{error, info};
+
+/// Runs the simulation for networked games without opening a window. The first argument is the
+/// address to listen on, which defaults to every interface on `DEFAULT_PORT`. `--map <path>`
+/// plays on a map's collision instead of a flat ground.
+fn main() {
+    color_eyre::install().unwrap();
+    crash::install();
+    debug::set_up_logging();
+
+    let mut args = std::env::args().skip(1);
+    let mut address = None;
+    let mut map_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--map" => map_path = args.next(),
+            _ => address = Some(arg),
+        }
+    }
+    let address = address.unwrap_or_else(|| format!("0.0.0.0:{}", DEFAULT_PORT));
+
+    let mut server = Server::bind(address.as_str()).unwrap();
+    info!("Listening on {}", address);
+    server.player_model = Some(PathBuf::from("cube"));
+
+    let mut simulation = match map_path {
+        Some(path) => {
+            info!("Loading {}", path);
+            match Map::load(Path::new(&path)) {
+                Ok(map) => Simulation::from_map(&map),
+                Err(error) => {
+                    error!("Could not load {}: {}", path, error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            // The same ground as in the editor
+            let mut simulation = Simulation::new();
+            let ground = simulation.spawn();
+            simulation.world.insert(
+                ground,
+                Collider {
+                    offset: Vector3::new(0.0, -0.5, 0.0),
+                    ..Collider::new(ColliderShape::Cuboid {
+                        half_extents: Vector3::new(500.0, 0.5, 500.0),
+                    })
+                },
+            );
+            simulation.world.insert(ground, Transform::default());
+
+            simulation
+        }
+    };
+
+    let mut timestep = FixedTimestep::new(TICK_RATE);
+    let mut player_count = 0;
+
+    loop {
+        for _ in 0..timestep.advance() {
+            server.receive(&mut simulation.world);
+            server.update(&mut simulation.world, timestep.step as f32);
+            simulation.step(timestep.step);
+            server.send_snapshots(&simulation.world);
+            // Nothing outside the simulation keeps anything for despawned entities here
+            simulation.take_despawned();
+            profiling::end_frame(&[]);
+        }
+
+        if server.player_count() != player_count {
+            player_count = server.player_count();
+            info!("{} players connected", player_count);
+        }
+
+        // Nothing to do until the next tick is due
+        std::thread::sleep(Duration::from_secs_f64(timestep.step / 4.0));
+    }
+}